@@ -0,0 +1,265 @@
+use crate::codec::decoder::Decoder;
+use crate::codec::{CodecError, CodecMetadata};
+use crate::{AbsoluteT, DeltaT, Event, PlaneSize, TimeMode, D};
+use bitstream_io::{BigEndian, BitReader};
+use std::io::{Read, Seek};
+
+/// The number of bins of a [`log2_bin`] histogram
+pub const LOG2_BINS: usize = 33;
+
+/// The bin of a histogram with power-of-two bins that holds the given value. Bin 0 holds 0, and
+/// bin `i > 0` holds the values in `[2^(i-1), 2^i)`.
+pub fn log2_bin(value: u32) -> usize {
+    (u32::BITS - value.leading_zeros()) as usize
+}
+
+/// Collects the statistics of a stream in a single pass over its events
+#[derive(Debug, Clone)]
+pub struct StreamAnalyzer {
+    plane: PlaneSize,
+    time_mode: TimeMode,
+    tps: DeltaT,
+
+    /// The absolute timestamp of the last event of each pixel
+    last_t: Vec<AbsoluteT>,
+    pixel_event_counts: Vec<u64>,
+    d_histogram: [u64; 256],
+    dt_histogram: [u64; LOG2_BINS],
+    event_count: u64,
+    end_t: AbsoluteT,
+}
+
+/// The statistics of a stream, as collected by a [`StreamAnalyzer`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamStats {
+    /// The dimensions of the stream
+    pub plane: PlaneSize,
+
+    /// Ticks per second
+    pub tps: DeltaT,
+
+    /// The total number of events
+    pub event_count: u64,
+
+    /// The number of events of each pixel, indexed by `(y * width + x) * channels + c`
+    pub pixel_event_counts: Vec<u64>,
+
+    /// The number of events with each D value, including [`D_EMPTY`](crate::D_EMPTY) and
+    /// [`D_ZERO_INTEGRATION`](crate::D_ZERO_INTEGRATION)
+    pub d_histogram: [u64; 256],
+
+    /// The number of events with each Δt (ticks since the pixel's previous event, or since the
+    /// start of the stream), in the power-of-two bins of [`log2_bin`]
+    pub dt_histogram: [u64; LOG2_BINS],
+
+    /// The timestamp of the last event, in ticks. Streams start at t=0.
+    pub duration_ticks: AbsoluteT,
+}
+
+impl StreamAnalyzer {
+    /// Create an analyzer for a stream with the given metadata
+    pub fn new(meta: &CodecMetadata) -> Self {
+        Self {
+            plane: meta.plane,
+            time_mode: meta.time_mode,
+            tps: meta.tps,
+            last_t: vec![0; meta.plane.volume()],
+            pixel_event_counts: vec![0; meta.plane.volume()],
+            d_histogram: [0; 256],
+            dt_histogram: [0; LOG2_BINS],
+            event_count: 0,
+            end_t: 0,
+        }
+    }
+
+    /// Count an event, as returned by the decoder. The timestamps of [`TimeMode::DeltaT`] streams
+    /// are taken as Δt, and all others as absolute.
+    pub fn ingest_event(&mut self, event: &Event) {
+        let idx = (event.coord.y_usize() * self.plane.w_usize() + event.coord.x_usize())
+            * self.plane.c_usize()
+            + event.coord.c_usize();
+        let last_t = &mut self.last_t[idx];
+        let (t, dt) = match self.time_mode {
+            TimeMode::DeltaT => (last_t.saturating_add(event.t), event.t),
+            TimeMode::AbsoluteT | TimeMode::Mixed => (event.t, event.t.saturating_sub(*last_t)),
+        };
+        *last_t = t;
+
+        self.pixel_event_counts[idx] += 1;
+        self.d_histogram[usize::from(event.d)] += 1;
+        self.dt_histogram[log2_bin(dt)] += 1;
+        self.event_count += 1;
+        self.end_t = self.end_t.max(t);
+    }
+
+    /// Count a batch of events
+    pub fn ingest_events(&mut self, events: &[Event]) {
+        for event in events {
+            self.ingest_event(event);
+        }
+    }
+
+    /// The statistics of the events counted so far
+    pub fn stats(&self) -> StreamStats {
+        StreamStats {
+            plane: self.plane,
+            tps: self.tps,
+            event_count: self.event_count,
+            pixel_event_counts: self.pixel_event_counts.clone(),
+            d_histogram: self.d_histogram,
+            dt_histogram: self.dt_histogram,
+            duration_ticks: self.end_t,
+        }
+    }
+}
+
+impl StreamStats {
+    /// The duration of the stream, in seconds
+    pub fn duration_seconds(&self) -> f64 {
+        f64::from(self.duration_ticks) / f64::from(self.tps.max(1))
+    }
+
+    /// The event rate of each pixel, in events per second, indexed like
+    /// [`pixel_event_counts`](Self::pixel_event_counts)
+    pub fn pixel_event_rates(&self) -> Vec<f64> {
+        let seconds = self.duration_seconds();
+        self.pixel_event_counts
+            .iter()
+            .map(|&count| {
+                if seconds > 0.0 {
+                    count as f64 / seconds
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// The number of pixels with each event rate, in the power-of-two bins of [`log2_bin`] over
+    /// the whole number of events per second
+    pub fn event_rate_histogram(&self) -> [u64; LOG2_BINS] {
+        let mut histogram = [0; LOG2_BINS];
+        for rate in self.pixel_event_rates() {
+            histogram[log2_bin(rate.min(f64::from(u32::MAX)) as u32)] += 1;
+        }
+        histogram
+    }
+
+    /// The mean event rate of the pixels, in events per second
+    pub fn mean_event_rate(&self) -> f64 {
+        let seconds = self.duration_seconds();
+        if seconds > 0.0 && !self.pixel_event_counts.is_empty() {
+            self.event_count as f64 / seconds / self.pixel_event_counts.len() as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// The number of events with the given D value
+    pub fn d_count(&self, d: D) -> u64 {
+        self.d_histogram[usize::from(d)]
+    }
+}
+
+/// Read the rest of a stream and collect its statistics
+pub fn analyze_stream<R: Read + Seek>(
+    decoder: &mut Decoder<R>,
+    reader: &mut BitReader<R, BigEndian>,
+) -> Result<StreamStats, CodecError> {
+    let mut analyzer = StreamAnalyzer::new(decoder.meta());
+    loop {
+        match decoder.digest_event(reader) {
+            Ok(event) => analyzer.ingest_event(&event),
+            Err(CodecError::Eof) => break,
+            Err(CodecError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(analyzer.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze_stream, log2_bin, StreamAnalyzer};
+    use crate::codec::decoder::Decoder;
+    use crate::codec::encoder::Encoder;
+    use crate::codec::raw::stream::{RawInput, RawOutput};
+    use crate::codec::{CodecError, CodecMetadata, EncoderOptions};
+    use crate::{Coord, Event, PlaneSize, TimeMode, D_EMPTY};
+    use bitstream_io::{BigEndian, BitReader};
+    use std::io::{BufWriter, Cursor};
+
+    #[test]
+    fn test_log2_bin() {
+        assert_eq!(log2_bin(0), 0);
+        assert_eq!(log2_bin(1), 1);
+        assert_eq!(log2_bin(2), 2);
+        assert_eq!(log2_bin(3), 2);
+        assert_eq!(log2_bin(1024), 11);
+        assert_eq!(log2_bin(u32::MAX), 32);
+    }
+
+    #[test]
+    fn test_analyze_stream() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(2, 2, 1).unwrap();
+        let events: Vec<Event> = (0..4)
+            .flat_map(|x| {
+                (1..=x + 1).map(move |i| Event {
+                    coord: Coord {
+                        x: x % 2,
+                        y: x / 2,
+                        c: None,
+                    },
+                    d: if i == 1 { D_EMPTY } else { 5 },
+                    t: i * 1000,
+                })
+            })
+            .collect();
+
+        for time_mode in [TimeMode::AbsoluteT, TimeMode::DeltaT] {
+            let meta = CodecMetadata {
+                time_mode,
+                plane,
+                tps: 1000,
+                ..Default::default()
+            };
+            let mut encoder = Encoder::new_raw(
+                RawOutput::new(meta, BufWriter::new(Vec::new())),
+                EncoderOptions::default(plane),
+            );
+            for event in &events {
+                let mut event = *event;
+                if time_mode == TimeMode::DeltaT {
+                    event.t = 1000;
+                }
+                encoder.ingest_event(event)?;
+            }
+            let bytes = encoder.close_writer()?.unwrap().into_inner().unwrap();
+
+            let mut reader = BitReader::endian(Cursor::new(bytes), BigEndian);
+            let mut decoder = Decoder::new_raw(RawInput::new(), &mut reader)?;
+            let stats = analyze_stream(&mut decoder, &mut reader)?;
+
+            assert_eq!(stats.event_count, 10);
+            assert_eq!(stats.pixel_event_counts, vec![1, 2, 3, 4]);
+            assert_eq!(stats.d_count(D_EMPTY), 4);
+            assert_eq!(stats.d_count(5), 6);
+            assert_eq!(stats.dt_histogram[log2_bin(1000)], 10);
+            assert_eq!(stats.duration_ticks, 4000);
+            assert_eq!(stats.duration_seconds(), 4.0);
+            assert_eq!(stats.pixel_event_rates(), vec![0.25, 0.5, 0.75, 1.0]);
+            assert_eq!(stats.event_rate_histogram()[0], 3);
+            assert_eq!(stats.event_rate_histogram()[1], 1);
+            assert_eq!(stats.mean_event_rate(), 0.625);
+
+            // Collecting the events directly gives the same statistics
+            let mut analyzer = StreamAnalyzer::new(decoder.meta());
+            decoder.set_input_stream_position(&mut reader, decoder.meta().header_size as u64)?;
+            while let Ok(event) = decoder.digest_event(&mut reader) {
+                analyzer.ingest_event(&event);
+            }
+            assert_eq!(analyzer.stats(), stats);
+        }
+        Ok(())
+    }
+}
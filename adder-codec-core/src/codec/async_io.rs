@@ -0,0 +1,290 @@
+use crate::codec::decoder::Decoder;
+use crate::codec::encoder::Encoder;
+use crate::codec::raw::stream::{RawInput, RawOutput};
+use crate::codec::{CodecError, CodecMetadata, EncoderOptions, EncoderType};
+use crate::Event;
+use bitstream_io::{BigEndian, BitReader};
+use std::io::{Cursor, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[cfg(feature = "compression")]
+use crate::codec::compressed::stream::{CompressedInput, CompressedOutput};
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::{LzInput, LzOutput};
+
+/// How many bytes to request from the reader at a time
+const READ_CHUNK_SIZE: usize = 8192;
+
+/// Once this many bytes of the read buffer have been decoded, they are discarded
+const COMPACT_THRESHOLD: u64 = 1 << 20;
+
+/// An in-memory sink which the synchronous [`Encoder`] writes into, and which the
+/// [`AsyncEncoder`] drains into its asynchronous writer.
+///
+/// The compressed encoder writes from its own thread, so the buffer is shared.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Encode [`Event`]s to an asynchronous stream, such as a network socket.
+///
+/// Events are encoded synchronously by an [`Encoder`] into an in-memory buffer, which is written
+/// out to the stream whenever events are ingested.
+pub struct AsyncEncoder<W: AsyncWrite + Unpin> {
+    encoder: Encoder<SharedBuffer>,
+    buffer: SharedBuffer,
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEncoder<W> {
+    /// Create a new encoder writing the given type of stream. The header is written out
+    /// immediately.
+    pub async fn new(
+        encoder_type: EncoderType,
+        meta: CodecMetadata,
+        options: EncoderOptions,
+        writer: W,
+    ) -> Result<Self, CodecError> {
+        let buffer = SharedBuffer::default();
+        let encoder = match encoder_type {
+            EncoderType::Raw => Encoder::new_raw(RawOutput::new(meta, buffer.clone()), options),
+            #[cfg(feature = "compression")]
+            EncoderType::Compressed => {
+                Encoder::new_compressed(CompressedOutput::new(meta, buffer.clone()), options)
+            }
+            #[cfg(feature = "lz")]
+            EncoderType::Lz => Encoder::new_lz(LzOutput::new(meta, buffer.clone()), options),
+            _ => return Err(CodecError::MalformedEncoder),
+        };
+
+        let mut encoder = Self {
+            encoder,
+            buffer,
+            writer,
+        };
+        encoder.write_buffered().await?;
+        Ok(encoder)
+    }
+
+    /// Returns a reference to the metadata of the underlying compression scheme
+    pub fn meta(&self) -> &CodecMetadata {
+        self.encoder.meta()
+    }
+
+    /// Ingest an event, and write out whatever data is ready
+    pub async fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+        self.encoder.ingest_event(event)?;
+        self.write_buffered().await
+    }
+
+    /// Ingest a batch of events, and write out whatever data is ready
+    pub async fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        self.encoder.ingest_events(events)?;
+        self.write_buffered().await
+    }
+
+    /// Write out any remaining data and flush the stream, returning the writer
+    pub async fn close(self) -> Result<W, CodecError> {
+        let Self {
+            encoder,
+            buffer,
+            mut writer,
+        } = self;
+        encoder.close_writer()?;
+        writer.write_all(&buffer.take()).await?;
+        writer.flush().await?;
+        Ok(writer)
+    }
+
+    /// Move the encoded data from the buffer to the stream
+    async fn write_buffered(&mut self) -> Result<(), CodecError> {
+        let bytes = self.buffer.take();
+        if !bytes.is_empty() {
+            self.writer.write_all(&bytes).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode [`Event`]s from an asynchronous stream, such as a network socket.
+///
+/// Bytes are read from the stream as needed, and decoded synchronously by a [`Decoder`]. Since the
+/// stream can't be seeked, seeking operations aren't available.
+pub struct AsyncDecoder<R: AsyncRead + Unpin> {
+    decoder: Decoder<Cursor<Vec<u8>>>,
+    bitreader: BitReader<Cursor<Vec<u8>>, BigEndian>,
+    reader: R,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecoder<R> {
+    /// Create a new decoder, reading from the stream until its header has been decoded. The
+    /// stream may be raw or compressed.
+    pub async fn new(mut reader: R) -> Result<Self, CodecError> {
+        let mut bitreader = BitReader::endian(Cursor::new(Vec::new()), BigEndian);
+        loop {
+            bitreader.seek_bits(SeekFrom::Start(0))?;
+            match Self::decode_header(&mut bitreader) {
+                Ok(decoder) => {
+                    return Ok(Self {
+                        decoder,
+                        bitreader,
+                        reader,
+                    });
+                }
+                Err(e) if is_incomplete(&e) => {
+                    if fill(&mut reader, &mut bitreader).await? == 0 {
+                        return Err(CodecError::Eof);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Try decoding the header from the bytes read so far, first as a raw stream, then as a
+    /// compressed stream, and then as an Lz stream (without a dictionary)
+    fn decode_header(
+        bitreader: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) -> Result<Decoder<Cursor<Vec<u8>>>, CodecError> {
+        #[allow(unused_mut)]
+        let mut decoder = Decoder::new_raw(RawInput::new(), bitreader);
+        #[cfg(feature = "compression")]
+        if matches!(decoder, Err(CodecError::WrongMagic)) {
+            bitreader.seek_bits(SeekFrom::Start(0))?;
+            decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), bitreader);
+        }
+        #[cfg(feature = "lz")]
+        if matches!(decoder, Err(CodecError::WrongMagic)) {
+            bitreader.seek_bits(SeekFrom::Start(0))?;
+            decoder = Decoder::new_lz(LzInput::new(None), bitreader);
+        }
+        decoder
+    }
+
+    /// Returns a reference to the metadata of the underlying compression scheme
+    pub fn meta(&self) -> &CodecMetadata {
+        self.decoder.meta()
+    }
+
+    /// Read and decode the next event from the stream, waiting for more data as needed.
+    /// Returns [`CodecError::Eof`] at the end of the stream.
+    pub async fn digest_event(&mut self) -> Result<Event, CodecError> {
+        loop {
+            let pos = self.bitreader.position_in_bits()?;
+            match self.decoder.digest_event(&mut self.bitreader) {
+                Err(e) if is_incomplete(&e) => {
+                    // Rewind to the start of the incomplete data, and wait for the rest of it
+                    self.bitreader.seek_bits(SeekFrom::Start(pos))?;
+                    if fill(&mut self.reader, &mut self.bitreader).await? == 0 {
+                        return Err(CodecError::Eof);
+                    }
+                }
+                result => {
+                    self.compact()?;
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Discard the bytes which have already been decoded
+    fn compact(&mut self) -> Result<(), CodecError> {
+        let pos = self.bitreader.position_in_bits()? / 8;
+        if pos < COMPACT_THRESHOLD {
+            return Ok(());
+        }
+        let cursor = self.bitreader.reader().ok_or(CodecError::Seek)?;
+        cursor.get_mut().drain(..pos as usize);
+        cursor.set_position(0);
+        Ok(())
+    }
+}
+
+/// Did decoding fail only because not enough of the stream has arrived yet?
+fn is_incomplete(e: &CodecError) -> bool {
+    matches!(e, CodecError::IoError(e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
+/// Read the next chunk of the stream into the end of the buffer. Returns the number of bytes read,
+/// which is 0 at the end of the stream.
+async fn fill<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    bitreader: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+) -> Result<usize, CodecError> {
+    let mut chunk = vec![0; READ_CHUNK_SIZE];
+    let num_bytes = reader.read(&mut chunk).await?;
+    let cursor = bitreader.reader().ok_or(CodecError::Seek)?;
+    cursor.get_mut().extend_from_slice(&chunk[..num_bytes]);
+    Ok(num_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Coord, PlaneSize};
+
+    #[tokio::test]
+    async fn raw_round_trip() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let meta = CodecMetadata {
+            plane,
+            ..Default::default()
+        };
+        let events: Vec<Event> = (0..100)
+            .map(|t| Event {
+                coord: Coord {
+                    x: t % 4,
+                    y: t / 4 % 4,
+                    c: None,
+                },
+                d: 7,
+                t: t * 10,
+            })
+            .collect();
+
+        let mut encoder = AsyncEncoder::new(
+            EncoderType::Raw,
+            meta,
+            EncoderOptions::default(plane),
+            Vec::new(),
+        )
+        .await?;
+        encoder.ingest_events(&events).await?;
+        let bytes = encoder.close().await?;
+
+        // Feed the decoder a few bytes at a time, as if from a slow socket
+        let (mut tx, rx) = tokio::io::duplex(7);
+        let writer = tokio::spawn(async move { tx.write_all(&bytes).await });
+        let mut decoder = AsyncDecoder::new(rx).await?;
+        assert_eq!(decoder.meta().plane, plane);
+
+        let mut decoded = Vec::new();
+        loop {
+            match decoder.digest_event().await {
+                Ok(event) => decoded.push(event),
+                Err(CodecError::Eof) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        writer.await.unwrap()?;
+        assert_eq!(decoded, events);
+        Ok(())
+    }
+}
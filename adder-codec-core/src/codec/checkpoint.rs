@@ -0,0 +1,288 @@
+use crate::codec::CodecError;
+use crate::{AbsoluteT, DeltaT, Event};
+use bincode::{DefaultOptions, Options};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The version of the layout of a saved checkpoint. It's bumped whenever a field is added or
+/// changed, so that a checkpoint saved by another version is rejected rather than misread.
+pub(crate) const CHECKPOINT_VERSION: u8 = 2;
+
+/// The state of an [`Encoder`](crate::codec::encoder::Encoder) at a point in its stream, as taken
+/// by [`Encoder::checkpoint`](crate::codec::encoder::Encoder::checkpoint). Saved to disk, it lets a
+/// paused or crashed transcode resume appending to the same output with
+/// [`Encoder::resume`](crate::codec::encoder::Encoder::resume), rather than encoding everything
+/// again.
+///
+/// A compressed stream's Adus are coded independently, so only the events of its current Adu and
+/// the locations of the Adus written so far are kept. The first Adu after resuming isn't
+/// motion-compensated, and the bitrate controller (if any) starts afresh.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncoderCheckpoint {
+    /// The version of the checkpoint's layout. It comes first, so it can be checked before the
+    /// rest is read.
+    pub(crate) version: u8,
+
+    /// The length of the output when the checkpoint was taken, including the header. Anything
+    /// written after it (e.g., a partial Adu, or the end of the stream) is discarded on resuming.
+    pub(crate) bytes_written: u64,
+
+    /// The events ingested by the output which haven't been written out yet, e.g., those of the
+    /// current Adu of a compressed stream
+    pub(crate) pending_events: Vec<Event>,
+
+    /// The events held back by [`EventOrder::Interleaved`](crate::codec::EventOrder::Interleaved)
+    pub(crate) queued_events: Vec<Event>,
+
+    /// The events held back by [`EventValidation::Reorder`](crate::codec::EventValidation::Reorder)
+    pub(crate) validation_events: Vec<Event>,
+
+    /// The timestamp of the last validated event for each pixel
+    pub(crate) validation_last_t: Vec<Option<AbsoluteT>>,
+
+    /// The latest timestamp seen by [`EventValidation::Reorder`](crate::codec::EventValidation::Reorder)
+    pub(crate) validation_t_max: AbsoluteT,
+
+    /// The events of the current reference interval held back by
+    /// [`EventDrop::PerFrameBudget`](crate::codec::EventDrop::PerFrameBudget)
+    pub(crate) budget_events: Vec<Event>,
+
+    /// The index of the reference interval of the `budget_events`
+    pub(crate) budget_interval: Option<AbsoluteT>,
+
+    /// The delta time of the events dropped since each pixel's last kept event, in a
+    /// [`TimeMode::DeltaT`](crate::TimeMode::DeltaT) stream
+    pub(crate) budget_carry: Vec<DeltaT>,
+
+    /// The timestamp of the last event written for each pixel of a
+    /// [`TimeMode::Mixed`](crate::TimeMode::Mixed) raw or Lz stream
+    pub(crate) mixed_last_t: Vec<Option<AbsoluteT>>,
+
+    /// The start time of the current Adu of a compressed stream
+    pub(crate) adu_start_t: AbsoluteT,
+
+    /// The start time and byte offset of each Adu of a compressed stream written so far
+    pub(crate) time_index: Vec<(AbsoluteT, u64)>,
+
+    /// The clock corrections of a compressed stream so far, as pairs of stream time and UTC
+    /// nanoseconds
+    pub(crate) clock_corrections: Vec<(AbsoluteT, u64)>,
+
+    /// The reference interval changes of a compressed stream so far, as pairs of stream time and
+    /// interval
+    pub(crate) ref_interval_changes: Vec<(AbsoluteT, DeltaT)>,
+
+    /// The stabilization transforms of a compressed stream so far, as triples of stream time and
+    /// horizontal and vertical shift
+    pub(crate) stabilization_transforms: Vec<(AbsoluteT, i32, i32)>,
+}
+
+impl EncoderCheckpoint {
+    /// An empty checkpoint of the current version, to be filled in by the encoder
+    pub(crate) fn new() -> Self {
+        Self {
+            version: CHECKPOINT_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// Reject a checkpoint saved with another layout
+    pub(crate) fn check_version(&self) -> Result<(), CodecError> {
+        check_version(self.version)
+    }
+
+    /// The length of the output when the checkpoint was taken, including the header
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Write the checkpoint, e.g., to a file alongside the output
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), CodecError> {
+        Ok(bincode_options().serialize_into(writer, self)?)
+    }
+
+    /// Read a checkpoint written by [`EncoderCheckpoint::write_to`]. A checkpoint written by a
+    /// version of the codec with a different layout is rejected.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, CodecError> {
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        check_version(version[0])?;
+        Ok(bincode_options().deserialize_from((&version[..]).chain(reader))?)
+    }
+}
+
+fn check_version(version: u8) -> Result<(), CodecError> {
+    if version != CHECKPOINT_VERSION {
+        return Err(CodecError::UnsupportedCheckpointVersion {
+            expected: CHECKPOINT_VERSION,
+            found: version,
+        });
+    }
+    Ok(())
+}
+
+fn bincode_options() -> impl Options {
+    DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EncoderCheckpoint;
+    use crate::codec::encoder::Encoder;
+    use crate::codec::raw::stream::RawOutput;
+    use crate::codec::{CodecError, CodecMetadata, EncoderOptions, LATEST_CODEC_VERSION};
+    use crate::raw_event::ByteOrder;
+    use crate::{open_file_decoder, Coord, Event, PlaneSize, TimeMode};
+    use std::fs::File;
+    use std::io::{BufWriter, Cursor};
+    use std::path::Path;
+
+    fn test_events() -> Vec<Event> {
+        let mut events = Vec::new();
+        for k in 0..10 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    events.push(Event {
+                        coord: Coord::new_2d(x, y),
+                        t: 300 + k * 600 + (x * 7 + y * 13 + k * 31) % 500,
+                        d: 7,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+        events
+    }
+
+    fn meta(time_mode: TimeMode) -> CodecMetadata {
+        CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            time_mode,
+            plane: PlaneSize::new(16, 16, 1).unwrap(),
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        }
+    }
+
+    fn new_encoder(
+        path: &Path,
+        time_mode: TimeMode,
+        compressed: bool,
+        byte_order: ByteOrder,
+    ) -> Encoder<BufWriter<File>> {
+        let meta = meta(time_mode);
+        let writer = BufWriter::new(File::create(path).unwrap());
+        let options = EncoderOptions::default(meta.plane);
+        #[cfg(feature = "compression")]
+        if compressed {
+            use crate::codec::compressed::stream::CompressedOutput;
+            return Encoder::new_compressed(CompressedOutput::new(meta, writer), options);
+        }
+        assert!(!compressed);
+        Encoder::new_raw(
+            RawOutput::with_byte_order(meta, writer, byte_order),
+            options,
+        )
+    }
+
+    fn decode(path: &Path) -> Vec<Event> {
+        let (mut stream, mut bitreader) = open_file_decoder(path.to_str().unwrap()).unwrap();
+        let mut events = Vec::new();
+        loop {
+            match stream.digest_event(&mut bitreader) {
+                Ok(event) => events.push(event),
+                Err(CodecError::Eof | CodecError::IoError(_)) => break,
+                Err(e) => panic!("{e}"),
+            }
+        }
+        events
+    }
+
+    fn check_resume(
+        time_mode: TimeMode,
+        compressed: bool,
+        byte_order: ByteOrder,
+    ) -> Result<(), CodecError> {
+        let events = test_events();
+        let (before, after) = events.split_at(events.len() / 2 + 7);
+        let dir = std::env::temp_dir();
+        let id = rand::random::<u32>();
+        let whole_path = dir.join(format!("adder_checkpoint_whole_{id}.adder"));
+        let resumed_path = dir.join(format!("adder_checkpoint_resumed_{id}.adder"));
+
+        let mut encoder = new_encoder(&whole_path, time_mode, compressed, byte_order);
+        encoder.ingest_events(&events)?;
+        encoder.close_writer()?;
+
+        // Pause after the first events, by closing the stream. The end of the stream which is
+        // written then is discarded when resuming.
+        let mut encoder = new_encoder(&resumed_path, time_mode, compressed, byte_order);
+        for event in before {
+            encoder.ingest_event(*event)?;
+        }
+        let mut saved = Vec::new();
+        encoder.checkpoint()?.write_to(&mut saved)?;
+        encoder.close_writer()?;
+
+        let checkpoint = EncoderCheckpoint::read_from(Cursor::new(saved))?;
+        let options = EncoderOptions::default(meta(time_mode).plane);
+        let mut encoder = Encoder::resume(resumed_path.to_str().unwrap(), options, &checkpoint)?;
+        for event in after {
+            encoder.ingest_event(*event)?;
+        }
+        encoder.close_writer()?;
+
+        if compressed {
+            // The first Adu after resuming isn't motion-compensated, so only the events match
+            assert_eq!(decode(&whole_path), decode(&resumed_path));
+        } else {
+            assert_eq!(std::fs::read(&whole_path)?, std::fs::read(&resumed_path)?);
+        }
+        assert!(!decode(&resumed_path).is_empty());
+        std::fs::remove_file(&whole_path)?;
+        std::fs::remove_file(&resumed_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_version() -> Result<(), CodecError> {
+        let mut saved = Vec::new();
+        EncoderCheckpoint::new().write_to(&mut saved)?;
+        assert_eq!(
+            EncoderCheckpoint::read_from(Cursor::new(saved.clone()))?,
+            EncoderCheckpoint::new()
+        );
+
+        // A checkpoint with another layout isn't misread
+        saved[0] += 1;
+        assert!(matches!(
+            EncoderCheckpoint::read_from(Cursor::new(saved)),
+            Err(CodecError::UnsupportedCheckpointVersion { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_raw() -> Result<(), CodecError> {
+        check_resume(TimeMode::AbsoluteT, false, ByteOrder::Big)?;
+        check_resume(TimeMode::Mixed, false, ByteOrder::Big)
+    }
+
+    #[test]
+    fn test_resume_raw_little_endian() -> Result<(), CodecError> {
+        // The resumed events are appended in the stream's own byte order
+        check_resume(TimeMode::AbsoluteT, false, ByteOrder::Little)?;
+        check_resume(TimeMode::Mixed, false, ByteOrder::Little)
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_resume_compressed() -> Result<(), CodecError> {
+        check_resume(TimeMode::AbsoluteT, true, ByteOrder::Big)
+    }
+}
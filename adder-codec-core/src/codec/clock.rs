@@ -0,0 +1,263 @@
+use crate::{AbsoluteT, DeltaT};
+
+/// How strongly each new observation pulls the [`DriftEstimator`]'s estimate of the clock offset.
+/// Small values smooth out the jitter in when the host receives the sensor's data.
+const DRIFT_SMOOTHING: f64 = 0.1;
+
+/// Anchors a tick of the stream to the wall-clock time it actually occurred at. Between two
+/// corrections, the duration of a tick is rescaled so that the stream stays aligned with the
+/// wall clock even if the sensor's clock drifts relative to the host's.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClockCorrection {
+    /// The timestamp, in ticks
+    pub t: AbsoluteT,
+
+    /// The wall-clock time of `t`, in UTC nanoseconds since the Unix epoch
+    pub utc_ns: u64,
+}
+
+/// Marks the tick from which a source integrated its input over a different reference interval,
+/// e.g., as chosen by a motion-adaptive transcoder. Until the first change, the stream's
+/// `ref_interval` applies.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RefIntervalChange {
+    /// The timestamp from which the new interval applies, in ticks
+    pub t: AbsoluteT,
+
+    /// The number of ticks spanned by each integration of the input from `t` onward
+    pub ref_interval: DeltaT,
+}
+
+/// The reference interval in effect at `t`, given the changes (which must be in time order) and
+/// the stream's base `ref_interval`
+pub fn ref_interval_at(
+    changes: &[RefIntervalChange],
+    ref_interval: DeltaT,
+    t: AbsoluteT,
+) -> DeltaT {
+    match changes.partition_point(|change| change.t <= t) {
+        0 => ref_interval,
+        idx => changes[idx - 1].ref_interval,
+    }
+}
+
+/// Estimates the drift of a live sensor's clock relative to the host's, and periodically emits a
+/// [`ClockCorrection`] to record in the stream.
+#[derive(Debug, Clone)]
+pub struct DriftEstimator {
+    epoch_ns: u64,
+    tps: DeltaT,
+
+    /// The number of ticks between corrections
+    interval: AbsoluteT,
+
+    /// The earliest tick at which the next correction is emitted
+    next_t: AbsoluteT,
+
+    /// The smoothed offset of the host clock from the sensor's nominal clock, in nanoseconds
+    offset_ns: Option<f64>,
+
+    last_utc_ns: u64,
+}
+
+impl DriftEstimator {
+    /// Create a new estimator for a sensor with `tps` ticks per second, whose tick 0 nominally
+    /// occurred at `epoch_ns`. A correction is emitted at most once per `interval` ticks, e.g.,
+    /// once per Adu.
+    pub fn new(epoch_ns: u64, tps: DeltaT, interval: AbsoluteT) -> Self {
+        Self {
+            epoch_ns,
+            tps: tps.max(1),
+            interval: interval.max(1),
+            next_t: 0,
+            offset_ns: None,
+            last_utc_ns: 0,
+        }
+    }
+
+    /// The wall-clock time of `t` if the sensor's clock ran at exactly its nominal rate
+    fn nominal_utc_ns(&self, t: AbsoluteT) -> f64 {
+        self.epoch_ns as f64 + f64::from(t) * 1e9 / f64::from(self.tps)
+    }
+
+    /// Observe that the sensor reached tick `t` when the host clock read `host_utc_ns`. Returns a
+    /// correction to record if the sensor has entered a new interval since the last one.
+    pub fn observe(&mut self, t: AbsoluteT, host_utc_ns: u64) -> Option<ClockCorrection> {
+        let nominal = self.nominal_utc_ns(t);
+        let error = host_utc_ns as f64 - nominal;
+        let offset = match self.offset_ns {
+            None => error,
+            Some(offset) => offset + DRIFT_SMOOTHING * (error - offset),
+        };
+        self.offset_ns = Some(offset);
+
+        if t < self.next_t {
+            return None;
+        }
+        self.next_t = (t / self.interval)
+            .saturating_add(1)
+            .saturating_mul(self.interval);
+
+        // Wall-clock time never runs backwards
+        let utc_ns = ((nominal + offset).max(0.0) as u64).max(self.last_utc_ns);
+        self.last_utc_ns = utc_ns;
+        Some(ClockCorrection { t, utc_ns })
+    }
+}
+
+/// A pair of `(t, utc_ns)` points which define the local rate of the clock
+type Segment = ((i128, i128), (i128, i128));
+
+/// The pair of points which define the rate of the clock around the point matching `before`. With
+/// two or more corrections (which must be in time order), it's the consecutive pair bounding that
+/// point, or the nearest pair if it's outside their range. With a single correction, or none, the
+/// nominal `tps` applies from it, or from the `epoch`.
+fn segment(
+    corrections: &[ClockCorrection],
+    epoch: Option<u64>,
+    tps: DeltaT,
+    before: impl FnMut(&ClockCorrection) -> bool,
+) -> Option<Segment> {
+    let (t, utc_ns) = match corrections {
+        [] => (0, epoch?),
+        [only] => (only.t, only.utc_ns),
+        _ => {
+            let idx = corrections
+                .partition_point(before)
+                .clamp(1, corrections.len() - 1);
+            let (a, b) = (corrections[idx - 1], corrections[idx]);
+            return Some((
+                (i128::from(a.t), i128::from(a.utc_ns)),
+                (i128::from(b.t), i128::from(b.utc_ns)),
+            ));
+        }
+    };
+    let (t, utc_ns) = (i128::from(t), i128::from(utc_ns));
+    Some((
+        (t, utc_ns),
+        (t + i128::from(tps.max(1)), utc_ns + 1_000_000_000),
+    ))
+}
+
+/// Convert a timestamp in ticks to UTC nanoseconds since the Unix epoch, rounding down. See
+/// [`segment`] for how the corrections apply.
+pub(crate) fn t_to_utc_ns(
+    corrections: &[ClockCorrection],
+    epoch: Option<u64>,
+    tps: DeltaT,
+    t: AbsoluteT,
+) -> Option<u64> {
+    let ((t_0, utc_0), (t_1, utc_1)) = segment(corrections, epoch, tps, |c| c.t <= t)?;
+    let utc_ns = utc_0 + ((i128::from(t) - t_0) * (utc_1 - utc_0)).div_euclid(t_1 - t_0);
+    u64::try_from(utc_ns).ok()
+}
+
+/// Convert UTC nanoseconds since the Unix epoch to a timestamp in ticks, rounding down. The
+/// inverse of [`t_to_utc_ns`].
+pub(crate) fn utc_ns_to_t(
+    corrections: &[ClockCorrection],
+    epoch: Option<u64>,
+    tps: DeltaT,
+    utc_ns: u64,
+) -> Option<AbsoluteT> {
+    let ((t_0, utc_0), (t_1, utc_1)) = segment(corrections, epoch, tps, |c| c.utc_ns <= utc_ns)?;
+    if utc_1 == utc_0 {
+        return None;
+    }
+    let t = t_0 + ((i128::from(utc_ns) - utc_0) * (t_1 - t_0)).div_euclid(utc_1 - utc_0);
+    AbsoluteT::try_from(t).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nominal_conversion() {
+        assert_eq!(t_to_utc_ns(&[], None, 1000, 5), None);
+        assert_eq!(t_to_utc_ns(&[], Some(1_000), 1000, 5), Some(5_001_000));
+        assert_eq!(utc_ns_to_t(&[], Some(1_000), 1000, 5_001_999), Some(5));
+        assert_eq!(utc_ns_to_t(&[], Some(1_000), 1000, 999), None);
+    }
+
+    #[test]
+    fn test_corrected_conversion() {
+        // The sensor's clock runs 1% fast: 1000 of its ticks take only 990 ms
+        let corrections = [
+            ClockCorrection {
+                t: 0,
+                utc_ns: 1_000_000_000,
+            },
+            ClockCorrection {
+                t: 1000,
+                utc_ns: 1_990_000_000,
+            },
+            ClockCorrection {
+                t: 2000,
+                utc_ns: 2_980_000_000,
+            },
+        ];
+        let tps = 1000;
+        assert_eq!(
+            t_to_utc_ns(&corrections, None, tps, 1500),
+            Some(2_485_000_000)
+        );
+        assert_eq!(
+            utc_ns_to_t(&corrections, None, tps, 2_485_000_000),
+            Some(1500)
+        );
+
+        // Past the last correction, the drift is extrapolated
+        assert_eq!(
+            t_to_utc_ns(&corrections, None, tps, 3000),
+            Some(3_970_000_000)
+        );
+    }
+
+    #[test]
+    fn test_ref_interval_at() {
+        let changes = [
+            RefIntervalChange {
+                t: 2550,
+                ref_interval: 1020,
+            },
+            RefIntervalChange {
+                t: 6630,
+                ref_interval: 255,
+            },
+        ];
+        assert_eq!(ref_interval_at(&changes, 255, 0), 255);
+        assert_eq!(ref_interval_at(&changes, 255, 2550), 1020);
+        assert_eq!(ref_interval_at(&changes, 255, 6629), 1020);
+        assert_eq!(ref_interval_at(&changes, 255, 9000), 255);
+        assert_eq!(ref_interval_at(&[], 255, 9000), 255);
+    }
+
+    #[test]
+    fn test_drift_estimator() {
+        // 1000 ticks per second, but the sensor's clock runs 1% slow, so the host sees each tick
+        // 1.01 ms apart
+        let epoch_ns = 1_000_000_000;
+        let mut estimator = DriftEstimator::new(epoch_ns, 1000, 500);
+        let mut corrections = Vec::new();
+        for t in (0..=20_000).step_by(10) {
+            let host_utc_ns = epoch_ns + u64::from(t) * 1_010_000;
+            corrections.extend(estimator.observe(t, host_utc_ns));
+        }
+
+        // A single correction per interval
+        assert_eq!(corrections.len(), 41);
+        assert!(corrections
+            .windows(2)
+            .all(|pair| pair[1].t - pair[0].t == 500));
+
+        // Once the estimate settles, the corrected time tracks the host clock closely, while the
+        // nominal time falls 200 ms behind
+        let t = 20_000;
+        let host_utc_ns = epoch_ns + u64::from(t) * 1_010_000;
+        let corrected = t_to_utc_ns(&corrections, Some(epoch_ns), 1000, t).unwrap();
+        let nominal = t_to_utc_ns(&[], Some(epoch_ns), 1000, t).unwrap();
+        assert!(host_utc_ns.abs_diff(corrected) < 2_000_000);
+        assert_eq!(host_utc_ns - nominal, 200_000_000);
+    }
+}
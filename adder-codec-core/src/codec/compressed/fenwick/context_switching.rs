@@ -49,6 +49,11 @@ impl FenwickModel {
         self.current_context = context;
     }
 
+    /// The weights of the given context
+    pub(crate) fn weights(&self, context: usize) -> &Weights {
+        &self.contexts[context]
+    }
+
     fn context(&self) -> &Weights {
         &self.contexts[self.current_context]
     }
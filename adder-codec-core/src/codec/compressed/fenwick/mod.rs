@@ -77,6 +77,16 @@ impl Weights {
         self.fenwick_counts.len() - 1
     }
 
+    /// The weight of each symbol, excluding the EOF
+    pub(crate) fn counts(&self) -> Vec<u64> {
+        (0..self.len())
+            .map(|i| {
+                let range = self.range(Some(i));
+                range.end - range.start
+            })
+            .collect()
+    }
+
     /// Used for decoding. Find the symbol index for the given `prefix_sum`
     fn symbol(&self, prefix_sum: u64) -> Option<usize> {
         if prefix_sum < self.prefix_sum(None) {
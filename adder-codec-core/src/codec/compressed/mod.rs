@@ -2,6 +2,8 @@ pub mod fenwick;
 mod source_model;
 /// Compressed codec
 pub mod stream;
+/// Integer wavelet transforms for coding the Δt values of each pixel's events
+mod wavelet;
 
 pub const BLOCK_SIZE_BIG: usize = 64;
 
@@ -12,6 +14,84 @@ pub const DRESIDUAL_NO_EVENT: DResidual = 256;
 pub const DRESIDUAL_SKIP_CUBE: DResidual = 257;
 pub type TResidual = i16;
 
+/// The number of bits of timestamp precision which each enhancement layer of a layered stream
+/// adds to the one before it
+pub const LAYER_T_BITS: u8 = 2;
+
+/// The most enhancement layers that a layered stream can have, so that the base layer's timestamp
+/// residuals can still be bitshifted without being coded in full
+pub const MAX_ENHANCEMENT_LAYERS: u8 = 7;
+
+/// The first codec version whose Adus code each cube with its own arithmetic coder, behind a
+/// length prefix, rather than coding all the cubes with a single one. The cubes can then be
+/// compressed and decompressed in parallel, at the cost of a few bytes per cube.
+pub const INDEPENDENT_CUBES_VERSION: u8 = 9;
+
+/// The first codec version whose Adus signal how their Δt values are coded, allowing the wavelet
+/// modes of [`DeltaTCoding`](crate::codec::DeltaTCoding) besides predictive coding
+pub const DELTA_T_CODING_VERSION: u8 = 9;
+
+/// The first codec version that partitions each cube adaptively for intra-coding. A cube's blocks
+/// are split into quadrants (down to [`MIN_BLOCK_SIZE`](source_model::event_structure::MIN_BLOCK_SIZE))
+/// wherever some of their pixels have no events, so that empty regions are coded with a single flag.
+pub const ADAPTIVE_BLOCKS_VERSION: u8 = 10;
+
+/// The first codec version whose cubes may be predicted from the previous Adu. The header declares
+/// whether they are. If so, each cube codes a motion vector, and the first event of each of its
+/// pixels is predicted from the pixel that far away in the previous Adu (if it had an event
+/// there). See
+/// [`CodecMetadata::motion_compensation`](crate::codec::CodecMetadata::motion_compensation).
+pub const MOTION_COMPENSATION_VERSION: u8 = 11;
+
+/// The first codec version whose header declares the width and height of its cubes, rather than
+/// always using [`DEFAULT_BLOCK_SIZE`](crate::codec::DEFAULT_BLOCK_SIZE). See
+/// [`EncoderOptions::block_size`](crate::codec::EncoderOptions::block_size).
+pub const CONFIGURABLE_BLOCK_SIZE_VERSION: u8 = 12;
+
+/// The first codec version whose header declares the largest [`D`](crate::D) value of its events,
+/// rather than always allowing up to [`D_MAX`](crate::D_MAX). The maximum can only be lowered.
+/// The D residuals of a compressed stream with a smaller maximum have fewer possible values, so
+/// they take fewer bits to code. See
+/// [`CodecMetadata::d_max`](crate::codec::CodecMetadata::d_max).
+pub const CONFIGURABLE_D_MAX_VERSION: u8 = 13;
+
+/// The first codec version whose header declares how the color channels of its Adus are laid
+/// out. With the planar layout, each channel is coded as its own plane of single-channel cubes,
+/// which are only predicted from the same channel of the previous Adu, so a decoder can skip the
+/// other channels' cubes entirely. See
+/// [`CodecMetadata::channel_layout`](crate::codec::CodecMetadata::channel_layout).
+pub const PLANAR_CHANNELS_VERSION: u8 = 14;
+
+/// The first codec version whose header declares the priors that the residual contexts of each
+/// Adu's base layer start from, which may be statistics gathered on earlier streams rather than
+/// flat priors. See [`ContextPriors`](crate::codec::priors::ContextPriors) and
+/// [`CodecMetadata::context_priors_id`](crate::codec::CodecMetadata::context_priors_id).
+pub const CONTEXT_PRIORS_VERSION: u8 = 15;
+
+/// The first codec version whose header declares a quantization table for the wavelet
+/// coefficients of the Δt values of each pixel's events, when they're coded with a
+/// [wavelet mode](crate::codec::DeltaTCoding::Haar). Each frequency band is quantized by its own
+/// bitshift, rather than all the coefficients being coded losslessly. See
+/// [`EncoderOptions::quantization_table`](crate::codec::EncoderOptions::quantization_table).
+pub const QUANTIZATION_TABLE_VERSION: u8 = 16;
+
+/// The first codec version which may append the changes of the source's reference interval to
+/// the end of the stream, after the time index and clock corrections. Decoders of earlier
+/// versions wouldn't recognize the table, so they'd lose track of the ones in front of it.
+pub const REF_INTERVAL_CHANGES_VERSION: u8 = 19;
+
+/// The first codec version which may append the shifts of a stabilizing transcoder to the end of
+/// the stream, after the changes of the reference interval. Earlier versions don't record them,
+/// so their events are left in the stabilized frame's coordinates.
+pub const STABILIZATION_VERSION: u8 = 20;
+
+/// The bitshift of the timestamp residuals coded in the given layer of a stream with
+/// `enhancement_layers` enhancement layers. Layer 0 is the base layer, and the last enhancement
+/// layer restores the exact timestamps.
+pub(crate) fn layer_bitshift(layer: u8, enhancement_layers: u8) -> u8 {
+    LAYER_T_BITS * enhancement_layers.saturating_sub(layer)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::codec::encoder::Encoder;
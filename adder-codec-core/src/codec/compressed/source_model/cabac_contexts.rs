@@ -1,8 +1,13 @@
 use crate::codec::compressed::fenwick::context_switching::FenwickModel;
 use crate::codec::compressed::fenwick::Weights;
-use crate::{AbsoluteT, DeltaT, EventCoordless, Intensity, D, D_SHIFT};
+use crate::codec::compressed::source_model::event_structure::motion::MOTION_SYMBOLS;
+use crate::codec::priors::{primed_counts, ContextPriors};
+use crate::{
+    AbsoluteT, DeltaT, EventCoordless, Intensity, D, D_EMPTY, D_SHIFT, D_ZERO_INTEGRATION,
+};
 use arithmetic_coding_adder_dep::Encoder;
 use bitstream_io::{BigEndian, BitWrite, BitWriter};
+use std::sync::Mutex;
 
 pub struct Contexts {
     /// Decimation factor residuals context
@@ -17,6 +22,19 @@ pub struct Contexts {
     pub(crate) eof_context: usize,
 
     pub(crate) bitshift_context: usize,
+
+    /// Whether a block of an adaptively partitioned cube is split into quadrants
+    pub(crate) split_context: usize,
+
+    /// Whether a block (or a cube's channel) of an adaptively partitioned cube has any events
+    pub(crate) occupancy_context: usize,
+
+    /// The motion vector of a cube predicted from the previous Adu
+    pub(crate) motion_context: usize,
+
+    /// The smallest bitshift of the timestamp residuals, for coding the coarse base layer of a
+    /// layered stream. Residuals coded in full are unaffected.
+    pub(crate) min_bitshift: u8,
 }
 
 pub const D_RESIDUAL_OFFSET: i16 = 255;
@@ -25,16 +43,50 @@ pub const BITSHIFT_ENCODE_FULL: u8 = 15;
 
 impl Contexts {
     pub fn new(source_model: &mut FenwickModel, dt_ref: DeltaT) -> Contexts {
-        let d_context = source_model.push_context_with_weights(d_residual_default_weights());
+        Self::with_d_max(source_model, dt_ref, None)
+    }
+
+    /// Create the contexts for a stream whose events have D values of at most `d_max` (besides
+    /// the special symbols), if given. The D residuals which can't occur are given no probability.
+    pub fn with_d_max(
+        source_model: &mut FenwickModel,
+        dt_ref: DeltaT,
+        d_max: Option<D>,
+    ) -> Contexts {
+        Self::with_priors(source_model, dt_ref, d_max, None)
+    }
 
+    /// Create the contexts as with [`Contexts::with_d_max`], with the residual contexts primed
+    /// with the given priors, if any, rather than starting from their default weights
+    pub fn with_priors(
+        source_model: &mut FenwickModel,
+        dt_ref: DeltaT,
+        d_max: Option<D>,
+        priors: Option<&ContextPriors>,
+    ) -> Contexts {
+        let mut d_weights = d_residual_default_weights(d_max);
         // TODO: Configure this based on the delta_t_max parameter!!
-        let t_weights = t_residual_default_weights(dt_ref);
+        let mut t_weights = t_residual_default_weights(dt_ref);
+        if let Some(priors) = priors {
+            d_weights = primed_weights(&d_weights, &priors.d_counts);
+            t_weights = primed_weights(&t_weights, &priors.t_counts);
+        }
+        let d_context = source_model.push_context_with_weights(d_weights);
+
         let t_residual_max = (t_weights.len() as i64 - 2) / 2;
         let t_context = source_model.push_context_with_weights(t_weights);
 
         let eof_context = source_model.push_context_with_weights(Weights::new_with_counts(1, &[1]));
         let bitshift_context =
             source_model.push_context_with_weights(Weights::new_with_counts(16, &[1; 16]));
+        let split_context =
+            source_model.push_context_with_weights(Weights::new_with_counts(2, &[1, 1]));
+        let occupancy_context =
+            source_model.push_context_with_weights(Weights::new_with_counts(2, &[1, 1]));
+        let motion_context = source_model.push_context_with_weights(Weights::new_with_counts(
+            MOTION_SYMBOLS,
+            &[1; MOTION_SYMBOLS],
+        ));
 
         Contexts {
             d_context,
@@ -42,13 +94,61 @@ impl Contexts {
             t_residual_max,
             eof_context,
             bitshift_context,
+            split_context,
+            occupancy_context,
+            motion_context,
+            min_bitshift: 0,
+        }
+    }
+
+    /// The current weights of the residual contexts
+    pub(crate) fn residual_counts(&self, source_model: &FenwickModel) -> ContextPriors {
+        ContextPriors {
+            d_counts: source_model.weights(self.d_context).counts(),
+            t_counts: source_model.weights(self.t_context).counts(),
+        }
+    }
+
+    /// Add the symbols coded in the residual contexts since their weights were `initial` to
+    /// `statistics`, for gathering [`ContextPriors`]
+    pub(crate) fn record_statistics(
+        &self,
+        statistics: &Mutex<ContextPriors>,
+        source_model: &FenwickModel,
+        initial: &ContextPriors,
+    ) {
+        let coded = |counts: Vec<u64>, initial: &[u64]| -> Vec<u64> {
+            counts
+                .iter()
+                .zip(initial)
+                .map(|(count, initial)| count - initial)
+                .collect()
+        };
+        let current = self.residual_counts(source_model);
+        statistics.lock().unwrap().add(
+            &coded(current.d_counts, &initial.d_counts),
+            &coded(current.t_counts, &initial.t_counts),
+        );
+    }
+
+    /// Quantize a residual (already bitshifted by `bitshift`) further, if needed to reach the
+    /// minimum bitshift
+    fn coarsen(&self, bitshift: u8, t_residual: i64) -> (u8, i64) {
+        if bitshift == BITSHIFT_ENCODE_FULL || bitshift >= self.min_bitshift {
+            return (bitshift, t_residual);
+        }
+        let t_residual_abs = t_residual.abs() >> (self.min_bitshift - bitshift);
+        if t_residual < 0 {
+            (self.min_bitshift, -t_residual_abs)
+        } else {
+            (self.min_bitshift, t_residual_abs)
         }
     }
 
     /// Find out how much we need to bitshift the t_residual to fit within the range of the model
     pub(crate) fn residual_to_bitshift(&self, t_residual_i64: i64) -> (u8, i64) {
         if t_residual_i64.abs() < self.t_residual_max {
-            (0, t_residual_i64)
+            self.coarsen(0, t_residual_i64)
             // } else if t_residual_i64.abs() > self.dt_max {
         } else {
             // JUST LOSSLESS FOR NOW
@@ -90,7 +190,7 @@ impl Contexts {
         c_thresh_max: f64,
     ) -> (u8, i64) {
         if t_residual_i64.abs() < self.t_residual_max {
-            (0, t_residual_i64)
+            self.coarsen(0, t_residual_i64)
         } else {
             if event.t < prev_event.t {
                 // dbg!(event.clone(), prev_event.clone());
@@ -123,9 +223,9 @@ impl Contexts {
 
             if t_residual.abs() < self.t_residual_max {
                 if t_residual_i64 < 0 {
-                    (bitshift, -t_residual)
+                    self.coarsen(bitshift, -t_residual)
                 } else {
-                    (bitshift, t_residual)
+                    self.coarsen(bitshift, t_residual)
                 }
             } else {
                 // JUST LOSSLESS
@@ -135,6 +235,11 @@ impl Contexts {
     }
 }
 
+fn primed_weights(defaults: &Weights, counts: &[u64]) -> Weights {
+    let counts = primed_counts(&defaults.counts(), counts);
+    Weights::new_with_counts(counts.len(), &counts)
+}
+
 pub fn t_residual_default_weights(_dt_ref: DeltaT) -> Weights {
     // t residuals can fit within i16
 
@@ -176,7 +281,7 @@ pub fn t_residual_default_weights(_dt_ref: DeltaT) -> Weights {
 //     Weights::new_with_counts(counts.len(), &counts)
 // }
 
-pub fn d_residual_default_weights() -> Weights {
+pub fn d_residual_default_weights(d_max: Option<D>) -> Weights {
     // d residuals can fit within i16
 
     // DResidual_NO_EVENT =  256
@@ -221,6 +326,23 @@ pub fn d_residual_default_weights() -> Weights {
         idx += 1;
     }
 
+    // A positive residual is coded as a single symbol above those of the bytes of an inter-coded
+    // residual, so the ones which no two possible D values differ by can be left out
+    if let Some(d_max) = d_max {
+        let ds: Vec<D> = (0..=d_max).chain([D_ZERO_INTEGRATION, D_EMPTY]).collect();
+        let mut possible = [false; 256];
+        for &d in &ds {
+            for &prev_d in ds.iter().filter(|&&prev_d| prev_d < d) {
+                possible[usize::from(d - prev_d)] = true;
+            }
+        }
+        for (residual, possible) in possible.iter().enumerate().skip(1) {
+            if !possible {
+                counts[residual + D_RESIDUAL_OFFSET as usize] = 0;
+            }
+        }
+    }
+
     Weights::new_with_counts(counts.len(), &Vec::from(counts))
 }
 
@@ -1,16 +1,23 @@
 use crate::codec::compressed::fenwick::context_switching::FenwickModel;
+use crate::codec::compressed::layer_bitshift;
 use crate::codec::compressed::source_model::cabac_contexts::{eof_context, Contexts};
 use crate::codec::compressed::source_model::event_structure::event_cube::EventCube;
-use crate::codec::compressed::source_model::event_structure::BLOCK_SIZE;
-use crate::codec::compressed::source_model::{ComponentCompression, HandleEvent};
-use crate::codec::CodecError;
-use crate::{AbsoluteT, DeltaT, Event, PlaneSize};
+use crate::codec::compressed::source_model::event_structure::motion::MotionReference;
+use crate::codec::compressed::source_model::{decode_symbol, ComponentCompression, HandleEvent};
+use crate::codec::priors::ContextPriors;
+use crate::codec::rate_controller::QualityMap;
+use crate::codec::{CodecError, DeltaTCoding, QUANTIZATION_BANDS};
+use crate::{AbsoluteT, DeltaT, Event, PixelAddress, PlaneSize, Roi, D};
 use arithmetic_coding_adder_dep::{Decoder, Encoder};
-use bitstream_io::{BigEndian, BitReader, BitWriter};
+use bitstream_io::{BigEndian, BitRead, BitReader, BitWrite, BitWriter};
 use ndarray::Array2;
 use nestify::nest;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator,
+};
 use std::io::Cursor;
 use std::mem::size_of;
+use std::sync::{Arc, Mutex};
 
 nest! {
     #[derive(Clone, Debug, Default)]
@@ -28,9 +35,43 @@ nest! {
         /// How many dt_ref intervals the whole adu spans
         pub(crate) num_intervals: usize,
 
+        /// The width and height of each cube
+        block_size: usize,
+
+        /// The number of planes of cubes, one after another. A planar Adu has one for each
+        /// channel. See [`EventAdu::set_planar_channels`].
+        planes: usize,
+
+        /// In a planar Adu, only the cubes of this channel's plane are decoded, if it's set
+        pub(crate) channel: Option<usize>,
+
+        /// The largest D value of the events, which rules out some D residuals. See
+        /// [`CONFIGURABLE_D_MAX_VERSION`](crate::codec::compressed::CONFIGURABLE_D_MAX_VERSION).
+        pub(crate) d_max: Option<D>,
+
         pub(crate) skip_adu: bool,
 
-        cube_to_write_count: u16,
+        /// Code each cube with its own arithmetic coder, so that the cubes can be compressed and
+        /// decompressed in parallel. See
+        /// [`INDEPENDENT_CUBES_VERSION`](crate::codec::compressed::INDEPENDENT_CUBES_VERSION).
+        pub(crate) independent_cubes: bool,
+
+        /// Keep the first events of each Adu (after its base layer is coded) as a reference for
+        /// predicting the cubes of the next one. See
+        /// [`MOTION_COMPENSATION_VERSION`](crate::codec::compressed::MOTION_COMPENSATION_VERSION).
+        pub(crate) keep_motion_reference: bool,
+
+        /// The first events of the previous Adu, which this Adu's cubes may be predicted from
+        pub(crate) motion_reference: Option<Arc<MotionReference>>,
+
+        /// The priors which the residual contexts of the base layer start from, if not flat. See
+        /// [`CONTEXT_PRIORS_VERSION`](crate::codec::compressed::CONTEXT_PRIORS_VERSION).
+        pub(crate) context_priors: Option<Arc<ContextPriors>>,
+
+        /// Gathers the residual symbols decoded in the base layer, if set
+        pub(crate) statistics: Option<Arc<Mutex<ContextPriors>>>,
+
+        cube_to_write_count: u32,
 
         pub(crate) state:
             #[derive(Clone, Debug, Default, PartialEq)]
@@ -53,16 +94,18 @@ impl EventAdu {
         start_t: AbsoluteT,
         dt_ref: DeltaT,
         num_intervals: usize,
+        block_size: usize,
     ) -> Self {
-        let blocks_y = (plane.h_usize() + BLOCK_SIZE - 1) / BLOCK_SIZE;
-        let blocks_x = (plane.w_usize() + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        let blocks_y = (plane.h_usize() + block_size - 1) / block_size;
+        let blocks_x = (plane.w_usize() + block_size - 1) / block_size;
 
         Self {
             event_cubes: Array2::from_shape_fn((blocks_y, blocks_x), |(y, x)| {
                 EventCube::new(
-                    y as u16 * BLOCK_SIZE as u16,
-                    x as u16 * BLOCK_SIZE as u16,
+                    (y * block_size) as PixelAddress,
+                    (x * block_size) as PixelAddress,
                     plane.c_usize(),
+                    block_size,
                     start_t,
                     dt_ref,
                     num_intervals,
@@ -71,7 +114,16 @@ impl EventAdu {
             start_t,
             dt_ref,
             num_intervals,
+            block_size,
+            planes: 1,
+            channel: None,
+            d_max: None,
             skip_adu: true,
+            independent_cubes: false,
+            keep_motion_reference: false,
+            motion_reference: None,
+            context_priors: None,
+            statistics: None,
             cube_to_write_count: 0,
             // decompressed_event_queue: VecDeque::with_capacity(plane.volume() * 4),
             state: Default::default(),
@@ -80,94 +132,515 @@ impl EventAdu {
         }
     }
 
+    /// Lay out the Adu's channels as separate planes of single-channel cubes, one plane after
+    /// another, rather than coding all the channels in each cube. See
+    /// [`PLANAR_CHANNELS_VERSION`](crate::codec::compressed::PLANAR_CHANNELS_VERSION). This
+    /// replaces the Adu's cubes, so it must be set before any of their other settings.
+    pub(crate) fn set_planar_channels(&mut self) {
+        let num_channels = self
+            .event_cubes
+            .iter()
+            .next()
+            .map_or(1, |cube| cube.num_channels);
+        if num_channels == 1 || self.planes > 1 {
+            return;
+        }
+        let (blocks_y, blocks_x) = self.event_cubes.dim();
+        self.event_cubes = Array2::from_shape_fn((num_channels * blocks_y, blocks_x), |(y, x)| {
+            let mut cube = EventCube::new(
+                ((y % blocks_y) * self.block_size) as PixelAddress,
+                (x * self.block_size) as PixelAddress,
+                1,
+                self.block_size,
+                self.start_t,
+                self.dt_ref,
+                self.num_intervals,
+            );
+            cube.plane = Some(y / blocks_y);
+            cube
+        });
+        self.planes = num_channels;
+    }
+
+    /// The index of the cube which the event belongs to, and the event as that cube ingests it.
+    /// The cubes of a planar Adu only have a single channel.
+    fn locate(&self, mut event: Event) -> ([usize; 2], Event) {
+        let mut idx_y = event.coord.y_usize() / self.block_size;
+        if self.planes > 1 {
+            idx_y += event.coord.c_usize() * (self.event_cubes.nrows() / self.planes);
+            event.coord.c = None;
+        }
+        ([idx_y, event.coord.x_usize() / self.block_size], event)
+    }
+
+    /// Set how the Δt values of each pixel's events are coded, for all the cubes of this Adu
+    /// and of the Adus which follow it
+    pub(crate) fn set_delta_t_coding(&mut self, coding: DeltaTCoding) {
+        for cube in self.event_cubes.iter_mut() {
+            cube.delta_t_coding = coding;
+        }
+    }
+
+    /// Set the bitshift which each band of the wavelet coefficients of the Δt values is quantized
+    /// by, for all the cubes of this Adu and of the Adus which follow it
+    pub(crate) fn set_quantization_table(&mut self, table: [u8; QUANTIZATION_BANDS]) {
+        for cube in self.event_cubes.iter_mut() {
+            cube.quantization_table = table;
+        }
+    }
+
+    /// Cap the maximum contrast threshold of each channel, for all the cubes of this Adu and of
+    /// the Adus which follow it
+    pub(crate) fn set_channel_c_thresh_max(&mut self, channel_c_thresh_max: [u8; 3]) {
+        for cube in self.event_cubes.iter_mut() {
+            cube.channel_c_thresh_max = channel_c_thresh_max;
+        }
+    }
+
+    /// Set whether the cubes of this Adu (and of the Adus which follow it) are intra-coded with
+    /// adaptive block partitioning
+    pub(crate) fn set_adaptive_blocks(&mut self, adaptive_blocks: bool) {
+        for cube in self.event_cubes.iter_mut() {
+            cube.adaptive_blocks = adaptive_blocks;
+        }
+    }
+
+    /// Set whether the cubes of this Adu (and of the Adus which follow it) code a motion vector,
+    /// for predicting them from the previous Adu
+    pub(crate) fn set_motion_compensation(&mut self, motion_compensation: bool) {
+        for cube in self.event_cubes.iter_mut() {
+            cube.motion_compensation = motion_compensation;
+        }
+    }
+
+    /// Hand the motion reference to each cube, to be predicted from
+    fn share_motion_reference(&mut self) {
+        for cube in self.event_cubes.iter_mut() {
+            cube.motion_reference = self.motion_reference.clone();
+        }
+    }
+
+    /// Replace the motion reference with the first events of this Adu, if it's kept
+    fn update_motion_reference(&mut self) {
+        if self.keep_motion_reference {
+            self.motion_reference = Some(Arc::new(MotionReference::new(&self.event_cubes)));
+        }
+    }
+
     pub fn compress(
         &mut self,
         stream: &mut BitWriter<Vec<u8>, BigEndian>,
         c_thresh_max: u8,
     ) -> Result<(), CodecError> {
-        // Create a new source model instance
-        let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
-        let contexts = Contexts::new(&mut source_model, self.dt_ref);
+        self.compress_layered(stream, c_thresh_max, None, 0)
+            .map(|_| ())
+    }
 
-        let mut encoder = Encoder::new(source_model);
+    /// Compress the Adu as a base layer with coarse timestamps, written to `stream`, and
+    /// `enhancement_layers` enhancement layers which each refine the timestamps further. Each
+    /// layer is coded on its own, so a decoder can stop after any of them. The last layer restores
+    /// the exact timestamps. Returns the compressed enhancement layers, in order.
+    ///
+    /// If a quality map is given, it overrides `c_thresh_max` for each cube.
+    pub fn compress_layered(
+        &mut self,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+        c_thresh_max: u8,
+        quality_map: Option<&QualityMap>,
+        enhancement_layers: u8,
+    ) -> Result<Vec<Vec<u8>>, CodecError> {
+        // The base layer's timestamps are lossy, so keep the exact ones for the enhancement layers
+        let target_ts: Vec<Vec<AbsoluteT>> = if enhancement_layers > 0 {
+            self.event_cubes.iter().map(EventCube::event_ts).collect()
+        } else {
+            Vec::new()
+        };
 
-        // Write out the starting timestamp of the Adu
-        encoder.model.set_context(contexts.t_context);
-        for byte in self.start_t.to_be_bytes().iter() {
-            encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-        }
+        self.share_motion_reference();
+        if self.independent_cubes {
+            self.compress_cubes(
+                stream,
+                c_thresh_max,
+                quality_map,
+                layer_bitshift(0, enhancement_layers),
+            )?;
+        } else {
+            // Create a new source model instance
+            let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+            let mut contexts = Contexts::with_priors(
+                &mut source_model,
+                self.dt_ref,
+                self.d_max,
+                self.context_priors.as_deref(),
+            );
+            contexts.min_bitshift = layer_bitshift(0, enhancement_layers);
+
+            let mut encoder = Encoder::new(source_model);
+
+            // Write out the starting timestamp of the Adu
+            encoder.model.set_context(contexts.t_context);
+            for byte in self.start_t.to_be_bytes().iter() {
+                encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+            }
 
-        for cube in self.event_cubes.iter_mut() {
-            debug_assert_eq!(cube.start_t, self.start_t);
-            cube.compress_intra(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
-        }
+            for cube in self.event_cubes.iter_mut() {
+                debug_assert_eq!(cube.start_t, self.start_t);
+                cube.compress_intra(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
+            }
 
-        for cube in self.event_cubes.iter_mut() {
-            debug_assert_eq!(cube.start_t, self.start_t);
-            cube.compress_inter(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
+            for cube in self.event_cubes.iter_mut() {
+                debug_assert_eq!(cube.start_t, self.start_t);
+                let c_thresh_max = quality_map.map_or(c_thresh_max, |quality_map| {
+                    quality_map.c_thresh_max(cube.start_x, cube.start_y)
+                });
+                cube.compress_inter(&mut encoder, &contexts, stream, Some(c_thresh_max))?;
+            }
+
+            // Flush the encoder
+            eof_context(&contexts, &mut encoder, stream);
         }
 
-        // Flush the encoder
-        eof_context(&contexts, &mut encoder, stream);
+        // The next Adu is predicted from this one's base layer, which is all that a decoder is
+        // guaranteed to decode
+        self.update_motion_reference();
+
+        let mut layers = Vec::with_capacity(enhancement_layers as usize);
+        for layer in 1..=enhancement_layers {
+            let mut layer_stream = BitWriter::endian(Vec::new(), BigEndian);
+            let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+            let contexts = Contexts::new(&mut source_model, self.dt_ref);
+            let mut encoder = Encoder::new(source_model);
+            for (cube, target_ts) in self.event_cubes.iter_mut().zip(target_ts.iter()) {
+                cube.compress_refinement(
+                    &mut encoder,
+                    &contexts,
+                    &mut layer_stream,
+                    target_ts,
+                    layer_bitshift(layer, enhancement_layers),
+                )?;
+            }
+            eof_context(&contexts, &mut encoder, &mut layer_stream);
+            layers.push(layer_stream.into_writer());
+        }
 
         self.clear_compression();
 
+        Ok(layers)
+    }
+
+    /// Compress each cube with its own arithmetic coder, in parallel. The Adu's starting timestamp
+    /// is written out first, followed by each cube's coded bytes prefixed by their length.
+    fn compress_cubes(
+        &mut self,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+        c_thresh_max: u8,
+        quality_map: Option<&QualityMap>,
+        min_bitshift: u8,
+    ) -> Result<(), CodecError> {
+        let dt_ref = self.dt_ref;
+        let d_max = self.d_max;
+        let priors = self.context_priors.as_deref();
+        let cubes = self
+            .event_cubes
+            .as_slice_mut()
+            .expect("event cubes are in standard layout");
+        let cube_records: Vec<Vec<u8>> = cubes
+            .par_iter_mut()
+            .map(|cube| {
+                let mut cube_stream = BitWriter::endian(Vec::new(), BigEndian);
+                let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+                let mut contexts = Contexts::with_priors(&mut source_model, dt_ref, d_max, priors);
+                contexts.min_bitshift = min_bitshift;
+                let mut encoder = Encoder::new(source_model);
+
+                cube.compress_intra(
+                    &mut encoder,
+                    &contexts,
+                    &mut cube_stream,
+                    Some(c_thresh_max),
+                )?;
+                let c_thresh_max = quality_map.map_or(c_thresh_max, |quality_map| {
+                    quality_map.c_thresh_max(cube.start_x, cube.start_y)
+                });
+                cube.compress_inter(
+                    &mut encoder,
+                    &contexts,
+                    &mut cube_stream,
+                    Some(c_thresh_max),
+                )?;
+                eof_context(&contexts, &mut encoder, &mut cube_stream);
+                Ok(cube_stream.into_writer())
+            })
+            .collect::<Result<_, CodecError>>()?;
+
+        stream.write_bytes(&self.start_t.to_be_bytes())?;
+        for record in cube_records {
+            stream.write_bytes(&(record.len() as u32).to_be_bytes())?;
+            stream.write_bytes(&record)?;
+        }
         Ok(())
     }
 
-    pub fn decompress(&mut self, stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>) {
+    /// Decompress an Adu. If a region of interest is given, only the events of the cubes which
+    /// intersect it are returned by [`HandleEvent::digest_event`]. All the cubes share a single
+    /// arithmetic-coded stream, so every cube's intra-coded events must still be decoded, but
+    /// decoding stops after the last cube in the region.
+    pub fn decompress(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+        roi: Option<&Roi>,
+    ) -> Result<(), CodecError> {
+        self.decompress_layered(stream, &mut [], 0, roi)
+    }
+
+    /// Decompress the base layer of an Adu from `stream`, and then refine its timestamps with
+    /// the given leading enhancement layers, out of the `enhancement_layers` that it was coded
+    /// with. See [`EventAdu::decompress`] for how the region of interest is handled.
+    pub fn decompress_layered(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+        layers: &mut [BitReader<Cursor<Vec<u8>>, BigEndian>],
+        enhancement_layers: u8,
+        roi: Option<&Roi>,
+    ) -> Result<(), CodecError> {
         self.clear_decompression();
 
         // let mut adu = Self::new(plane, start_t, dt_ref, num_intervals);
 
-        // Create a new source model instance
-        let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
-        let contexts = Contexts::new(&mut source_model, self.dt_ref);
-        let mut decoder = Decoder::new(source_model);
-
-        // Read the starting timestamp of the Adu
-        decoder.model.set_context(contexts.t_context);
-        let mut start_t = [0u8; size_of::<AbsoluteT>()];
+        // The cubes outside the region of interest are skipped when digesting events, and the
+        // inter-coded events after the last cube in the region don't need to be decoded at all.
+        // In a planar Adu, the cubes of the other channels aren't needed either.
+        let in_channel: Vec<bool> = self
+            .event_cubes
+            .iter()
+            .map(|cube| match (self.channel, cube.plane) {
+                (Some(channel), Some(plane)) => channel == plane,
+                _ => true,
+            })
+            .collect();
+        let in_roi: Vec<bool> = self
+            .event_cubes
+            .iter()
+            .zip(in_channel.iter())
+            .map(|(cube, &in_channel)| in_channel && roi.map_or(true, |roi| cube.intersects(roi)))
+            .collect();
+        let last_in_roi = in_roi.iter().rposition(|&in_roi| in_roi);
+
+        self.share_motion_reference();
+        if self.independent_cubes {
+            // Every cube of the channel is needed to predict the next Adu. A planar Adu's cubes are
+            // only predicted from the same channel.
+            let decoded = if self.keep_motion_reference {
+                in_channel
+            } else {
+                in_roi.clone()
+            };
+            self.decompress_cubes(stream, &decoded)?;
+        } else {
+            // Create a new source model instance
+            let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+            let contexts = Contexts::with_priors(
+                &mut source_model,
+                self.dt_ref,
+                self.d_max,
+                self.context_priors.as_deref(),
+            );
+            let initial = self
+                .statistics
+                .as_ref()
+                .map(|_| contexts.residual_counts(&source_model));
+            let mut decoder = Decoder::new(source_model);
+
+            // Read the starting timestamp of the Adu
+            decoder.model.set_context(contexts.t_context);
+            let mut start_t = [0u8; size_of::<AbsoluteT>()];
+
+            for byte in start_t.iter_mut() {
+                *byte = decode_symbol(&mut decoder, stream)? as u8;
+            }
+            // Adus with no events aren't written out, so the next Adu may not directly follow the
+            // previous one
+            self.set_start_t(AbsoluteT::from_be_bytes(start_t));
+
+            for block_idx_y in 0..self.event_cubes.nrows() {
+                for block_idx_x in 0..self.event_cubes.ncols() {
+                    self.event_cubes[[block_idx_y, block_idx_x]].decompress_intra(
+                        &mut decoder,
+                        &contexts,
+                        stream,
+                        self.start_t,
+                    )?;
+                    debug_assert_eq!(
+                        self.event_cubes[[block_idx_y, block_idx_x]].start_t,
+                        self.start_t
+                    );
+                }
+            }
 
-        for byte in start_t.iter_mut() {
-            *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+            for (idx, cube) in self.event_cubes.iter_mut().enumerate() {
+                if last_in_roi.map_or(true, |last| idx > last) {
+                    break;
+                }
+                cube.decompress_inter(&mut decoder, &contexts, stream)?;
+                debug_assert_eq!(cube.start_t, self.start_t);
+            }
+            if let (Some(statistics), Some(initial)) = (&self.statistics, &initial) {
+                contexts.record_statistics(statistics, &decoder.model, initial);
+            }
         }
-
-        for block_idx_y in 0..self.event_cubes.nrows() {
-            for block_idx_x in 0..self.event_cubes.ncols() {
-                self.event_cubes[[block_idx_y, block_idx_x]].decompress_intra(
+        self.update_motion_reference();
+        for (layer, layer_stream) in (1..=enhancement_layers).zip(layers.iter_mut()) {
+            let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+            let contexts = Contexts::new(&mut source_model, self.dt_ref);
+            let mut decoder = Decoder::new(source_model);
+            for (idx, cube) in self.event_cubes.iter_mut().enumerate() {
+                if last_in_roi.map_or(true, |last| idx > last) {
+                    break;
+                }
+                cube.decompress_refinement(
                     &mut decoder,
                     &contexts,
-                    stream,
-                    self.start_t,
-                );
-                debug_assert_eq!(
-                    self.event_cubes[[block_idx_y, block_idx_x]].start_t,
-                    self.start_t
-                );
+                    layer_stream,
+                    layer_bitshift(layer, enhancement_layers),
+                )?;
             }
         }
-
-        for block_idx_y in 0..self.event_cubes.nrows() {
-            for block_idx_x in 0..self.event_cubes.ncols() {
-                self.event_cubes[[block_idx_y, block_idx_x]].decompress_inter(
-                    &mut decoder,
-                    &contexts,
-                    stream,
-                );
-                debug_assert_eq!(
-                    self.event_cubes[[block_idx_y, block_idx_x]].start_t,
-                    self.start_t
-                );
+        for (cube, in_roi) in self.event_cubes.iter_mut().zip(in_roi.iter()) {
+            if !in_roi {
+                cube.skip_digest();
             }
         }
         self.state = AduState::Decompressed;
         self.first_run = false;
+        Ok(())
+    }
+
+    /// Decompress the cubes of an Adu written by [`EventAdu::compress_cubes`], in parallel. Since
+    /// each cube is coded on its own, only the cubes flagged in `decoded` are decoded at all.
+    fn decompress_cubes(
+        &mut self,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+        decoded: &[bool],
+    ) -> Result<(), CodecError> {
+        let mut start_t = [0u8; size_of::<AbsoluteT>()];
+        stream.read_bytes(&mut start_t)?;
+        // Adus with no events aren't written out, so the next Adu may not directly follow the
+        // previous one
+        self.set_start_t(AbsoluteT::from_be_bytes(start_t));
+
+        let mut cube_records = Vec::with_capacity(self.event_cubes.len());
+        for _ in 0..self.event_cubes.len() {
+            let mut len = [0u8; 4];
+            stream.read_bytes(&mut len)?;
+            cube_records.push(stream.read_to_vec(u32::from_be_bytes(len) as usize)?);
+        }
+
+        let start_t = self.start_t;
+        let dt_ref = self.dt_ref;
+        let d_max = self.d_max;
+        let priors = self.context_priors.as_deref();
+        let statistics = self.statistics.as_deref();
+        let cubes = self
+            .event_cubes
+            .as_slice_mut()
+            .expect("event cubes are in standard layout");
+        cubes
+            .par_iter_mut()
+            .zip(cube_records.into_par_iter())
+            .enumerate()
+            .try_for_each(|(idx, (cube, record))| {
+                if !decoded[idx] {
+                    return Ok(());
+                }
+                let mut cube_stream = BitReader::endian(Cursor::new(record), BigEndian);
+                let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+                let contexts = Contexts::with_priors(&mut source_model, dt_ref, d_max, priors);
+                let initial = statistics.map(|_| contexts.residual_counts(&source_model));
+                let mut decoder = Decoder::new(source_model);
+                cube.decompress_intra(&mut decoder, &contexts, &mut cube_stream, start_t)?;
+                cube.decompress_inter(&mut decoder, &contexts, &mut cube_stream)?;
+                if let (Some(statistics), Some(initial)) = (statistics, &initial) {
+                    contexts.record_statistics(statistics, &decoder.model, initial);
+                }
+                Ok(())
+            })
+    }
+
+    /// The events ingested by the Adu which haven't been compressed yet
+    pub(crate) fn pending_events(&self) -> Vec<Event> {
+        let mut events = Vec::new();
+        for cube in self.event_cubes.iter() {
+            cube.pending_events(&mut events);
+        }
+        events
+    }
+
+    /// Move the Adu (and each of its cubes) to the time range beginning at `start_t`
+    pub(crate) fn set_start_t(&mut self, start_t: AbsoluteT) {
+        self.start_t = start_t;
+        for cube in self.event_cubes.iter_mut() {
+            cube.start_t = start_t;
+        }
+    }
+
+    /// Skip over an Adu which can't be decompressed (e.g., because it's corrupted), so that the
+    /// next Adu is decompressed with the correct start time
+    pub fn skip_decompression(&mut self) {
+        self.clear_decompression();
+        // The next Adu can't be predicted from one that wasn't decoded
+        self.motion_reference = None;
+        self.state = AduState::Empty;
+        self.first_run = false;
     }
 
     pub fn decoder_is_empty(&self) -> bool {
         self.state == AduState::Empty
     }
+
+    /// Take in a batch of raw events and place them at the appropriate locations.
+    ///
+    /// The events are first partitioned by the cube they belong to (preserving their relative
+    /// order), and then each cube ingests its own partition in parallel.
+    ///
+    /// Assume that all the events fit within the adu's time frame. This is checked at the caller.
+    ///
+    /// Returns true if these are the first events that the Adu has ingested
+    pub(crate) fn ingest_events(&mut self, events: Vec<Event>) -> bool {
+        if events.is_empty() {
+            return false;
+        }
+
+        let num_cols = self.event_cubes.ncols();
+        let mut partitions: Vec<Vec<Event>> = vec![Vec::new(); self.event_cubes.len()];
+        for event in events {
+            let ([idx_y, idx_x], event) = self.locate(event);
+            partitions[idx_y * num_cols + idx_x].push(event);
+        }
+
+        let cubes = self
+            .event_cubes
+            .as_slice_mut()
+            .expect("event cubes are in standard layout");
+        let new_cube_count: u32 = cubes
+            .par_iter_mut()
+            .zip(partitions.into_par_iter())
+            .map(|(cube, partition)| {
+                let mut first = false;
+                for event in partition {
+                    first |= cube.ingest_event(event);
+                }
+                u32::from(first)
+            })
+            .sum();
+        self.cube_to_write_count += new_cube_count;
+
+        if self.skip_adu {
+            self.skip_adu = false;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl HandleEvent for EventAdu {
@@ -177,10 +650,9 @@ impl HandleEvent for EventAdu {
     ///
     /// Returns true if this is the first event that the Adu has ingested
     fn ingest_event(&mut self, event: Event) -> bool {
-        let idx_y = event.coord.y_usize() / BLOCK_SIZE;
-        let idx_x = event.coord.x_usize() / BLOCK_SIZE;
+        let (idx, event) = self.locate(event);
 
-        if self.event_cubes[[idx_y, idx_x]].ingest_event(event) {
+        if self.event_cubes[idx].ingest_event(event) {
             self.cube_to_write_count += 1;
         };
 
@@ -257,7 +729,7 @@ mod tests {
         let dt_ref = 255;
         let num_intervals = 10;
 
-        let adu = EventAdu::new(plane, start_t, dt_ref, num_intervals);
+        let adu = EventAdu::new(plane, start_t, dt_ref, num_intervals, 16);
 
         assert_eq!(adu.event_cubes.shape(), &[7, 7]);
 
@@ -272,7 +744,7 @@ mod tests {
         let dt_ref = 255;
         let num_intervals = 10;
 
-        let adu = EventAdu::new(plane, start_t, dt_ref, num_intervals);
+        let adu = EventAdu::new(plane, start_t, dt_ref, num_intervals, 16);
 
         assert_eq!(adu.event_cubes.shape(), &[2, 1]);
 
@@ -286,7 +758,7 @@ mod tests {
         let dt_ref = 255;
         let num_intervals = 10;
 
-        let mut adu = EventAdu::new(plane, start_t, dt_ref, num_intervals);
+        let mut adu = EventAdu::new(plane, start_t, dt_ref, num_intervals, 16);
 
         assert_eq!(adu.event_cubes.shape(), &[2, 1]);
 
@@ -312,8 +784,8 @@ mod tests {
         compress_test(&mut adu, &mut stream, 0)?;
 
         let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
-        let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals);
-        adu2.decompress(&mut stream);
+        let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals, 16);
+        adu2.decompress(&mut stream, None)?;
 
         assert_eq!(adu.event_cubes.shape(), adu2.event_cubes.shape());
         for (cube1, cube2) in adu.event_cubes.iter().zip(adu2.event_cubes.iter()) {
@@ -380,7 +852,7 @@ mod tests {
         let dt_ref = 255;
         let num_intervals = 10;
 
-        let mut adu = EventAdu::new(plane, start_t, dt_ref, num_intervals);
+        let mut adu = EventAdu::new(plane, start_t, dt_ref, num_intervals, 16);
 
         assert_eq!(adu.event_cubes.shape(), &[2, 1]);
 
@@ -413,8 +885,8 @@ mod tests {
 
         let encoded_data = stream.into_writer();
         let mut stream = BitReader::endian(Cursor::new(encoded_data.clone()), BigEndian);
-        let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals);
-        adu2.decompress(&mut stream);
+        let mut adu2 = EventAdu::new(plane, start_t, dt_ref, num_intervals, 16);
+        adu2.decompress(&mut stream, None)?;
 
         assert_eq!(adu.event_cubes.shape(), adu2.event_cubes.shape());
         let mut pixel_count = 0;
@@ -2,21 +2,30 @@ use crate::codec::compressed::fenwick::context_switching::FenwickModel;
 use crate::codec::compressed::source_model::cabac_contexts::{
     Contexts, BITSHIFT_ENCODE_FULL, D_RESIDUAL_OFFSET,
 };
-use crate::codec::compressed::source_model::event_structure::BLOCK_SIZE;
-use crate::codec::compressed::source_model::{ComponentCompression, HandleEvent};
+use crate::codec::compressed::source_model::event_structure::motion::{
+    motion_to_symbol, residual_cost, symbol_to_motion, MotionReference, MotionVector,
+    MAX_MOTION_VECTOR,
+};
+use crate::codec::compressed::source_model::event_structure::MIN_BLOCK_SIZE;
+use crate::codec::compressed::source_model::{decode_symbol, ComponentCompression, HandleEvent};
+use crate::codec::compressed::wavelet;
 use crate::codec::compressed::{DResidual, TResidual, DRESIDUAL_NO_EVENT, DRESIDUAL_SKIP_CUBE};
-use crate::codec::CodecError;
-use crate::{AbsoluteT, Coord, DeltaT, Event, EventCoordless, PixelAddress, D, D_EMPTY};
+use crate::codec::{CodecError, DeltaTCoding, QUANTIZATION_BANDS};
+use crate::{AbsoluteT, Coord, DeltaT, Event, EventCoordless, PixelAddress, Roi, D_EMPTY};
 use arithmetic_coding_adder_dep::{Decoder, Encoder};
 use bitstream_io::{BigEndian, BitReader, BitWriter};
+use ndarray::Array3;
 use std::cmp::{max, min};
 use std::collections::VecDeque;
 use std::io::Cursor;
 use std::mem::size_of;
+use std::sync::Arc;
 
 type Pixel = Vec<EventCoordless>;
 
-type EventLists = [[[Pixel; BLOCK_SIZE]; BLOCK_SIZE]; 3];
+type Square = Vec<Vec<Pixel>>;
+
+type EventLists = [Square; 3];
 
 #[derive(PartialEq, Debug, Clone, Default)]
 pub struct EventCube {
@@ -26,7 +35,10 @@ pub struct EventCube {
     /// The absolute x-coordinate of the top-left pixel in the cube
     pub(crate) start_x: PixelAddress,
 
-    num_channels: usize,
+    pub(crate) num_channels: usize,
+
+    /// The width and height of the cube
+    pub(crate) block_size: usize,
 
     /// Contains the sparse events in the cube. The index is the relative interval of dt_ref from the start
     pub(crate) raw_event_lists: EventLists,
@@ -41,41 +53,753 @@ pub struct EventCube {
     /// How many dt_ref intervals the whole cube spans
     num_intervals: usize,
 
-    raw_event_memory: [[[EventCoordless; BLOCK_SIZE]; BLOCK_SIZE]; 3],
+    raw_event_memory: [Vec<Vec<EventCoordless>>; 3],
 
     skip_cube: bool,
 
     decompressed_event_queue: VecDeque<Event>,
+
+    /// How the timestamps of each pixel's events after its first are inter-coded
+    pub(crate) delta_t_coding: DeltaTCoding,
+
+    /// The bitshift which each band of the wavelet coefficients is quantized by, with the wavelet
+    /// modes of [`DeltaTCoding`]
+    pub(crate) quantization_table: [u8; QUANTIZATION_BANDS],
+
+    /// The cap on the maximum contrast threshold of each channel's inter-coded events
+    pub(crate) channel_c_thresh_max: [u8; 3],
+
+    /// Whether the first events of the cube's pixels are intra-coded in a quadtree of blocks,
+    /// rather than in row-major order
+    pub(crate) adaptive_blocks: bool,
+
+    /// Whether the cube codes a motion vector, for predicting it from the previous Adu
+    pub(crate) motion_compensation: bool,
+
+    /// The first events of the previous Adu, which the cube may be predicted from
+    pub(crate) motion_reference: Option<Arc<MotionReference>>,
+
+    /// The channel of the plane that the cube belongs to, in an Adu with the
+    /// [planar](crate::codec::ChannelLayout::Planar) channel layout. Such a cube has a single
+    /// channel.
+    pub(crate) plane: Option<usize>,
 }
 
 impl EventCube {
+    /// Returns true if any of the cube's pixels fall inside the region of interest
+    pub(crate) fn intersects(&self, roi: &Roi) -> bool {
+        roi.intersects(
+            self.start_x,
+            self.start_y,
+            self.block_size as PixelAddress,
+            self.block_size as PixelAddress,
+        )
+    }
+
+    /// The channel of the stream which the cube's channel `c` holds
+    pub(crate) fn stream_channel(&self, c: usize) -> usize {
+        self.plane.unwrap_or(c)
+    }
+
+    /// The channel of the events from the cube's channel `c`. Events of a single-channel stream
+    /// have no channel.
+    fn event_channel(&self, c: usize) -> Option<u8> {
+        match self.plane {
+            Some(plane) => Some(plane as u8),
+            None => (self.num_channels > 1).then_some(c as u8),
+        }
+    }
+
+    /// Don't return any of the cube's decompressed events
+    pub(crate) fn skip_digest(&mut self) {
+        self.skip_cube = true;
+    }
+
+    /// The timestamps of the cube's events, in the order they're coded
+    pub(crate) fn event_ts(&self) -> Vec<AbsoluteT> {
+        self.raw_event_lists[..self.num_channels]
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .map(|event| event.t)
+            .collect()
+    }
+
+    /// Append the cube's events which haven't been compressed yet to `events`
+    pub(crate) fn pending_events(&self, events: &mut Vec<Event>) {
+        for (c, square) in self.raw_event_lists[..self.num_channels].iter().enumerate() {
+            for (y, row) in square.iter().enumerate() {
+                for (x, pixel) in row.iter().enumerate() {
+                    events.extend(pixel.iter().map(|event| Event {
+                        coord: Coord {
+                            x: x as PixelAddress + self.start_x,
+                            y: y as PixelAddress + self.start_y,
+                            c: self.event_channel(c),
+                        },
+                        d: event.d,
+                        t: event.t,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Code an enhancement layer, which refines the timestamps of the cube's events (as they've
+    /// been reconstructed so far) toward `target_ts`, quantized by `bitshift`
+    pub(crate) fn compress_refinement(
+        &mut self,
+        encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+        contexts: &Contexts,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+        target_ts: &[AbsoluteT],
+        bitshift: u8,
+    ) -> Result<(), CodecError> {
+        if self.skip_cube {
+            return Ok(());
+        }
+        let events = self.raw_event_lists[..self.num_channels]
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .flatten();
+        for (event, target_t) in events.zip(target_ts) {
+            let t_residual_i64 = *target_t as i64 - event.t as i64;
+            let t_residual_abs = t_residual_i64.abs() >> bitshift;
+            let t_residual = if t_residual_i64 < 0 {
+                -t_residual_abs
+            } else {
+                t_residual_abs
+            };
+
+            // Flag whether the refinement is coded in full, just like a residual's bitshift
+            let (full, _) = contexts.residual_to_bitshift(t_residual);
+            encoder.model.set_context(contexts.bitshift_context);
+            encoder.encode(Some(&(full as usize)), stream).unwrap();
+
+            encoder.model.set_context(contexts.t_context);
+            if full == BITSHIFT_ENCODE_FULL {
+                for byte in t_residual.to_be_bytes().iter() {
+                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+                }
+            } else {
+                for byte in (t_residual as TResidual).to_be_bytes().iter() {
+                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+                }
+            }
+            event.t = (event.t as i64 + (t_residual << bitshift)) as AbsoluteT;
+        }
+        Ok(())
+    }
+
+    /// Decode an enhancement layer coded by [`EventCube::compress_refinement`], refining the
+    /// timestamps of the cube's decoded events
+    pub(crate) fn decompress_refinement(
+        &mut self,
+        decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+        contexts: &Contexts,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+        bitshift: u8,
+    ) -> Result<(), CodecError> {
+        if self.skip_cube {
+            return Ok(());
+        }
+        let mut t_residual_buffer = [0u8; size_of::<TResidual>()];
+        let mut t_residual_full_buffer = [0u8; size_of::<i64>()];
+        let events = self.raw_event_lists[..self.num_channels]
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .flatten();
+        for event in events {
+            decoder.model.set_context(contexts.bitshift_context);
+            let full = decode_symbol(decoder, stream)? as u8;
+
+            decoder.model.set_context(contexts.t_context);
+            let t_residual = if full == BITSHIFT_ENCODE_FULL {
+                for byte in t_residual_full_buffer.iter_mut() {
+                    *byte = decode_symbol(decoder, stream)? as u8;
+                }
+                i64::from_be_bytes(t_residual_full_buffer)
+            } else {
+                for byte in t_residual_buffer.iter_mut() {
+                    *byte = decode_symbol(decoder, stream)? as u8;
+                }
+                TResidual::from_be_bytes(t_residual_buffer) as i64
+            };
+
+            let t = t_residual
+                .checked_shl(u32::from(bitshift))
+                .and_then(|t_residual| (event.t as i64).checked_add(t_residual))
+                .ok_or(CodecError::CorruptAdu)?;
+            event.t = AbsoluteT::try_from(t).map_err(|_| CodecError::CorruptAdu)?;
+        }
+        Ok(())
+    }
+
     pub fn new(
         start_y: PixelAddress,
         start_x: PixelAddress,
         num_channels: usize,
+        block_size: usize,
         start_t: AbsoluteT,
         dt_ref: DeltaT,
         num_intervals: usize,
     ) -> Self {
-        let row: [Pixel; BLOCK_SIZE] = vec![Vec::with_capacity(num_intervals); BLOCK_SIZE]
-            .try_into()
-            .unwrap();
-        let square: [[Pixel; BLOCK_SIZE]; BLOCK_SIZE] = vec![row; BLOCK_SIZE].try_into().unwrap();
+        let square: Square = vec![vec![Vec::with_capacity(num_intervals); block_size]; block_size];
         let lists = [square.clone(), square.clone(), square.clone()];
+        let memory = vec![vec![EventCoordless::default(); block_size]; block_size];
 
         Self {
             start_y,
             start_x,
             num_channels,
+            block_size,
             raw_event_lists: lists,
             start_t,
             dt_ref,
             num_intervals,
-            raw_event_memory: [[[EventCoordless::default(); BLOCK_SIZE]; BLOCK_SIZE]; 3],
+            raw_event_memory: [memory.clone(), memory.clone(), memory],
             skip_cube: true,
             decompressed_event_queue: Default::default(),
+            delta_t_coding: DeltaTCoding::Predictive,
+            quantization_table: [0; QUANTIZATION_BANDS],
+            channel_c_thresh_max: [u8::MAX; 3],
+            adaptive_blocks: false,
+            motion_compensation: false,
+            motion_reference: None,
+            plane: None,
         }
     }
+
+    /// Record the first event of each of the cube's pixels in `first_events` (indexed by channel
+    /// and absolute coordinates), with its timestamp relative to the start of the cube
+    pub(crate) fn record_first_events(&self, first_events: &mut Array3<Option<EventCoordless>>) {
+        for (c, square) in self.raw_event_lists[..self.num_channels].iter().enumerate() {
+            for (y, row) in square.iter().enumerate() {
+                for (x, pixel) in row.iter().enumerate() {
+                    first_events[(
+                        self.stream_channel(c),
+                        self.start_y as usize + y,
+                        self.start_x as usize + x,
+                    )] = pixel.first().map(|event| EventCoordless {
+                        d: event.d,
+                        t: event.t.saturating_sub(self.start_t),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Find the motion vector which best predicts the first events of the cube's pixels from the
+    /// previous Adu, by the estimated cost of coding their residuals. Returns `None` if no motion
+    /// vector predicts them better than the events coded before them in the cube do.
+    fn search_motion(&self, reference: &MotionReference) -> Option<MotionVector> {
+        let first_events: Vec<((usize, usize, usize), EventCoordless)> = self.raw_event_lists
+            [..self.num_channels]
+            .iter()
+            .enumerate()
+            .flat_map(|(c, square)| {
+                square.iter().enumerate().flat_map(move |(y, row)| {
+                    row.iter().enumerate().filter_map(move |(x, pixel)| {
+                        pixel.first().map(|event| ((c, y, x), *event))
+                    })
+                })
+            })
+            .collect();
+        let cost = |prediction: EventCoordless, event: EventCoordless| {
+            let (d_residual, t_residual) = event.residual(&prediction);
+            residual_cost(d_residual) + residual_cost(t_residual)
+        };
+
+        // The cost of predicting each event from the one coded before it, as without motion
+        let mut previous = EventCoordless {
+            d: 0,
+            t: self.start_t,
+        };
+        let fallback_costs: Vec<u32> = first_events
+            .iter()
+            .map(|(_, event)| {
+                let fallback_cost = cost(previous, *event);
+                previous = *event;
+                fallback_cost
+            })
+            .collect();
+        let mut best: (u32, Option<MotionVector>) = (fallback_costs.iter().sum(), None);
+
+        for dy in -MAX_MOTION_VECTOR..=MAX_MOTION_VECTOR {
+            for dx in -MAX_MOTION_VECTOR..=MAX_MOTION_VECTOR {
+                let motion_cost: u32 = first_events
+                    .iter()
+                    .zip(fallback_costs.iter())
+                    .map(|(((c, y, x), event), fallback_cost)| {
+                        reference
+                            .get(
+                                self.stream_channel(*c),
+                                self.start_y as i64 + *y as i64 + dy,
+                                self.start_x as i64 + *x as i64 + dx,
+                                self.start_t,
+                            )
+                            .map_or(*fallback_cost, |prediction| cost(prediction, *event))
+                    })
+                    .sum();
+                if motion_cost < best.0 {
+                    best = (motion_cost, Some((dy, dx)));
+                }
+            }
+        }
+        best.1
+    }
+
+    /// Intra-code the cube's channels as quadtrees. Each channel is flagged as occupied or not,
+    /// and an occupied channel is coded as a block of the whole cube with [`compress_block`].
+    fn compress_intra_adaptive(
+        &mut self,
+        predictor: &mut IntraPredictor,
+        encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+        contexts: &Contexts,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    ) {
+        let cube = Block::cube(self.block_size);
+        for (c, square) in self.raw_event_lists[..self.num_channels]
+            .iter_mut()
+            .enumerate()
+        {
+            let occupied = cube.is_occupied(square);
+            encode_flag(encoder, contexts.occupancy_context, stream, occupied);
+            if occupied {
+                compress_block(square, c, cube, predictor, encoder, contexts, stream);
+            }
+        }
+    }
+
+    /// Decode the quadtrees coded by [`EventCube::compress_intra_adaptive`]
+    fn decompress_intra_adaptive(
+        &mut self,
+        predictor: &mut IntraPredictor,
+        decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+        contexts: &Contexts,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) -> Result<(), CodecError> {
+        let cube = Block::cube(self.block_size);
+        for (c, square) in self.raw_event_lists[..self.num_channels]
+            .iter_mut()
+            .enumerate()
+        {
+            // Only the occupied blocks are coded, so the rest must be left empty
+            square.iter_mut().flatten().for_each(Vec::clear);
+            if decode_flag(decoder, contexts.occupancy_context, stream)? {
+                decompress_block(square, c, cube, predictor, decoder, contexts, stream)?;
+            }
+        }
+        self.skip_cube = predictor.init_event.is_none();
+        Ok(())
+    }
+
+    /// Inter-code each pixel's events after its first with a wavelet transform of their Δt
+    /// values, rather than predictively. All the pixel's D residuals come first, terminated by a
+    /// NO_EVENT symbol, so the decoder knows how many wavelet coefficients follow.
+    fn compress_inter_wavelet(
+        &mut self,
+        encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+        contexts: &Contexts,
+        stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    ) -> Result<(), CodecError> {
+        let coding = self.delta_t_coding;
+        let table = self.quantization_table;
+        for c in 0..self.num_channels {
+            for pixel in self.raw_event_lists[c].iter_mut().flatten() {
+                if pixel.is_empty() {
+                    continue;
+                }
+
+                encoder.model.set_context(contexts.d_context);
+                for pair in pixel.windows(2) {
+                    let d_residual = pair[1].residual(&pair[0]).0 as DResidual;
+                    for byte in d_residual.to_be_bytes().iter() {
+                        encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+                    }
+                }
+                for byte in (DRESIDUAL_NO_EVENT).to_be_bytes().iter() {
+                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+                }
+
+                let mut coefficients: Vec<i64> = pixel
+                    .windows(2)
+                    .map(|pair| pair[1].residual(&pair[0]).1)
+                    .collect();
+                wavelet::forward(coding, &mut coefficients);
+                let len = coefficients.len();
+                for (i, coefficient) in coefficients.iter_mut().enumerate() {
+                    let quantized = wavelet::quantize(&table, len, i, *coefficient);
+                    let quantized = encode_t_residual(encoder, contexts, stream, quantized);
+                    *coefficient = wavelet::dequantize(&table, len, i, quantized);
+                }
+
+                // Reconstruct the timestamps just as the decoder will, in case the coefficients
+                // were quantized. Rounding may take a Δt down to 0 or below, but a pixel's events
+                // must stay in strictly increasing time order.
+                wavelet::inverse(coding, &mut coefficients);
+                for (idx, delta_t) in coefficients.into_iter().enumerate() {
+                    pixel[idx + 1].t = pixel[idx].t.saturating_add(delta_t.max(1) as AbsoluteT);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode the pixels' events coded by [`EventCube::compress_inter_wavelet`]
+    fn decompress_inter_wavelet(
+        &mut self,
+        decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+        contexts: &Contexts,
+        stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) -> Result<(), CodecError> {
+        let coding = self.delta_t_coding;
+        let table = self.quantization_table;
+        let mut d_residual_buffer = [0u8; size_of::<DResidual>()];
+
+        // As for predictive coding, more events than there are ticks in the cube means the data is
+        // corrupt
+        let max_pixel_events = self.num_intervals * self.dt_ref as usize + 1;
+
+        for c in 0..self.num_channels {
+            for pixel in self.raw_event_lists[c].iter_mut().flatten() {
+                if pixel.is_empty() {
+                    continue;
+                }
+
+                decoder.model.set_context(contexts.d_context);
+                loop {
+                    for byte in d_residual_buffer.iter_mut() {
+                        *byte = decode_symbol(decoder, stream)? as u8;
+                    }
+                    let d_residual = DResidual::from_be_bytes(d_residual_buffer);
+                    if d_residual == DRESIDUAL_NO_EVENT {
+                        break;
+                    }
+                    if pixel.len() > max_pixel_events {
+                        return Err(CodecError::CorruptAdu);
+                    }
+                    let event = pixel[pixel.len() - 1]
+                        .checked_add_residual(i64::from(d_residual), 0)
+                        .ok_or(CodecError::CorruptAdu)?;
+                    pixel.push(event);
+                }
+
+                let len = pixel.len() - 1;
+                let mut coefficients = Vec::with_capacity(len);
+                for i in 0..len {
+                    let quantized = decode_t_residual(decoder, contexts, stream)?;
+                    coefficients.push(wavelet::dequantize(&table, len, i, quantized));
+                }
+                wavelet::inverse(coding, &mut coefficients);
+                for (idx, delta_t) in coefficients.into_iter().enumerate() {
+                    // Clamped as by the encoder
+                    let delta_t =
+                        AbsoluteT::try_from(delta_t.max(1)).map_err(|_| CodecError::CorruptAdu)?;
+                    pixel[idx + 1].t = pixel[idx]
+                        .t
+                        .checked_add(delta_t)
+                        .ok_or(CodecError::CorruptAdu)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A square block of a cube's pixels, for adaptive partitioning
+#[derive(Clone, Copy)]
+struct Block {
+    y: usize,
+    x: usize,
+    size: usize,
+}
+
+impl Block {
+    /// The block spanning the whole of a cube with the given width and height
+    fn cube(size: usize) -> Block {
+        Block { y: 0, x: 0, size }
+    }
+
+    /// The block's quadrants, in row-major order
+    fn quadrants(self) -> [Block; 4] {
+        let size = self.size / 2;
+        [(0, 0), (0, size), (size, 0), (size, size)].map(|(dy, dx)| Block {
+            y: self.y + dy,
+            x: self.x + dx,
+            size,
+        })
+    }
+
+    /// Returns true if any of the block's pixels have an event
+    fn is_occupied(self, square: &Square) -> bool {
+        square[self.y..self.y + self.size].iter().any(|row| {
+            row[self.x..self.x + self.size]
+                .iter()
+                .any(|p| !p.is_empty())
+        })
+    }
+}
+
+fn encode_flag(
+    encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+    context: usize,
+    stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    flag: bool,
+) {
+    encoder.model.set_context(context);
+    encoder.encode(Some(&(flag as usize)), stream).unwrap();
+}
+
+fn decode_flag(
+    decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+    context: usize,
+    stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+) -> Result<bool, CodecError> {
+    decoder.model.set_context(context);
+    Ok(decode_symbol(decoder, stream)? != 0)
+}
+
+/// Intra-code an occupied block of the pixels of channel `c`. Larger than [`MIN_BLOCK_SIZE`], a
+/// block with any empty quadrant is split, and only its occupied quadrants are coded
+/// (recursively). Otherwise, the block's pixels are coded in row-major order, as in a cube that
+/// isn't partitioned.
+fn compress_block(
+    square: &mut Square,
+    c: usize,
+    block: Block,
+    predictor: &mut IntraPredictor,
+    encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+    contexts: &Contexts,
+    stream: &mut BitWriter<Vec<u8>, BigEndian>,
+) {
+    if block.size > MIN_BLOCK_SIZE {
+        let quadrants = block.quadrants();
+        let split = quadrants
+            .iter()
+            .any(|quadrant| !quadrant.is_occupied(square));
+        encode_flag(encoder, contexts.split_context, stream, split);
+        if split {
+            for quadrant in quadrants {
+                let occupied = quadrant.is_occupied(square);
+                encode_flag(encoder, contexts.occupancy_context, stream, occupied);
+                if occupied {
+                    compress_block(square, c, quadrant, predictor, encoder, contexts, stream);
+                }
+            }
+            return;
+        }
+    }
+
+    for (y, row) in square.iter_mut().enumerate().skip(block.y).take(block.size) {
+        for (x, pixel) in row.iter_mut().enumerate().skip(block.x).take(block.size) {
+            compress_intra_pixel(pixel, (c, y, x), predictor, encoder, contexts, stream);
+        }
+    }
+}
+
+/// Decode a block coded by [`compress_block`]
+fn decompress_block(
+    square: &mut Square,
+    c: usize,
+    block: Block,
+    predictor: &mut IntraPredictor,
+    decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+    contexts: &Contexts,
+    stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+) -> Result<(), CodecError> {
+    if block.size > MIN_BLOCK_SIZE && decode_flag(decoder, contexts.split_context, stream)? {
+        for quadrant in block.quadrants() {
+            if decode_flag(decoder, contexts.occupancy_context, stream)? {
+                decompress_block(square, c, quadrant, predictor, decoder, contexts, stream)?;
+            }
+        }
+        return Ok(());
+    }
+
+    for (y, row) in square.iter_mut().enumerate().skip(block.y).take(block.size) {
+        for (x, pixel) in row.iter_mut().enumerate().skip(block.x).take(block.size) {
+            let d_residual =
+                decompress_intra_pixel(pixel, (c, y, x), predictor, decoder, contexts, stream)?;
+            if d_residual == DRESIDUAL_SKIP_CUBE {
+                // An empty cube is coded with occupancy flags, never with a SKIP_CUBE symbol
+                return Err(CodecError::CorruptAdu);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Predicts the first event of each pixel of a cube, as they're intra-coded
+struct IntraPredictor<'a> {
+    start_t: AbsoluteT,
+
+    /// The last event intra-coded in the cube
+    init_event: Option<EventCoordless>,
+
+    /// The first events of the previous Adu, and the absolute coordinates (y, x) of the top-left
+    /// pixel of the block which the cube is predicted from
+    motion: Option<(&'a MotionReference, i64, i64)>,
+
+    /// The plane of a cube of a planar Adu, whose events are only predicted from that channel
+    plane: Option<usize>,
+}
+
+impl IntraPredictor<'_> {
+    fn new(start_t: AbsoluteT, plane: Option<usize>) -> Self {
+        Self {
+            start_t,
+            init_event: None,
+            motion: None,
+            plane,
+        }
+    }
+
+    /// Predict the first event of the pixel at (c, y, x) in the cube, from the pixel of the
+    /// previous Adu that the cube's motion vector points to, if it had an event. Otherwise,
+    /// predict it from the last event coded in the cube. The cube's first coded event is
+    /// predicted to have a D of 0, at the start of the cube.
+    fn predict(&self, (c, y, x): (usize, usize, usize)) -> EventCoordless {
+        self.motion
+            .and_then(|(reference, origin_y, origin_x)| {
+                reference.get(
+                    self.plane.unwrap_or(c),
+                    origin_y + y as i64,
+                    origin_x + x as i64,
+                    self.start_t,
+                )
+            })
+            .or(self.init_event)
+            .unwrap_or(EventCoordless {
+                d: 0,
+                t: self.start_t,
+            })
+    }
+}
+
+/// Intra-code the first event of the pixel at `position` (or a NO_EVENT symbol, if it has none),
+/// as residuals from the predicted event
+fn compress_intra_pixel(
+    pixel: &mut Pixel,
+    position: (usize, usize, usize),
+    predictor: &mut IntraPredictor,
+    encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+    contexts: &Contexts,
+    stream: &mut BitWriter<Vec<u8>, BigEndian>,
+) {
+    encoder.model.set_context(contexts.d_context);
+
+    let Some(event) = pixel.first_mut() else {
+        // There's no event for this pixel. Encode a NO_EVENT symbol.
+        let tmp = (DRESIDUAL_NO_EVENT + D_RESIDUAL_OFFSET) as usize;
+        encoder.encode(Some(&tmp), stream).unwrap();
+        return;
+    };
+
+    let prediction = predictor.predict(position);
+    let (d_residual, t_residual) = event.residual(&prediction);
+    let tmp = (d_residual as DResidual + D_RESIDUAL_OFFSET) as usize;
+    encoder.encode(Some(&tmp), stream).unwrap();
+
+    let t_residual = encode_t_residual(encoder, contexts, stream, t_residual);
+
+    // Use the reconstructed value, so we base our next prediction on what the decoder will see
+    event.t = (prediction.t as i64 + t_residual) as AbsoluteT;
+    debug_assert!(event.t < 2_u32.pow(31));
+
+    predictor.init_event = Some(*event);
+}
+
+/// Decode the first event of a pixel coded by [`compress_intra_pixel`], if it has one. Returns
+/// the decoded D residual, which is [`DRESIDUAL_NO_EVENT`] or [`DRESIDUAL_SKIP_CUBE`] if the
+/// pixel has no event.
+fn decompress_intra_pixel(
+    pixel: &mut Pixel,
+    position: (usize, usize, usize),
+    predictor: &mut IntraPredictor,
+    decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+    contexts: &Contexts,
+    stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+) -> Result<DResidual, CodecError> {
+    decoder.model.set_context(contexts.d_context);
+
+    let tmp = decode_symbol(decoder, stream)?;
+    let d_residual = (tmp as i16)
+        .checked_sub(D_RESIDUAL_OFFSET)
+        .ok_or(CodecError::CorruptAdu)?;
+
+    if d_residual == DRESIDUAL_SKIP_CUBE || d_residual == DRESIDUAL_NO_EVENT {
+        pixel.clear(); // So we can skip it for inter-coding
+        return Ok(d_residual);
+    }
+
+    let t_residual = decode_t_residual(decoder, contexts, stream)?;
+    let event = predictor
+        .predict(position)
+        .checked_add_residual(i64::from(d_residual), t_residual)
+        .ok_or(CodecError::CorruptAdu)?;
+    pixel.push(event);
+    predictor.init_event = Some(event);
+    Ok(d_residual)
+}
+
+/// Code a t residual (or wavelet coefficient) as a bitshift and the shifted residual, like the
+/// residuals of intra-coded events. Returns the residual as the decoder will reconstruct it.
+fn encode_t_residual(
+    encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
+    contexts: &Contexts,
+    stream: &mut BitWriter<Vec<u8>, BigEndian>,
+    t_residual_i64: i64,
+) -> i64 {
+    let (bitshift_amt, t_residual) = contexts.residual_to_bitshift(t_residual_i64);
+
+    encoder.model.set_context(contexts.bitshift_context);
+    for byte in bitshift_amt.to_be_bytes().iter() {
+        encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+    }
+
+    encoder.model.set_context(contexts.t_context);
+    if bitshift_amt == BITSHIFT_ENCODE_FULL {
+        for byte in t_residual.to_be_bytes().iter() {
+            encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+        }
+        t_residual
+    } else {
+        let t_residual = t_residual as TResidual;
+        for byte in t_residual.to_be_bytes().iter() {
+            encoder.encode(Some(&(*byte as usize)), stream).unwrap();
+        }
+        (t_residual as i64) << bitshift_amt
+    }
+}
+
+/// Decode a t residual coded by [`encode_t_residual`]
+fn decode_t_residual(
+    decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+    contexts: &Contexts,
+    stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+) -> Result<i64, CodecError> {
+    decoder.model.set_context(contexts.bitshift_context);
+    let bitshift_amt = decode_symbol(decoder, stream)? as u8;
+
+    decoder.model.set_context(contexts.t_context);
+    if bitshift_amt == BITSHIFT_ENCODE_FULL {
+        let mut t_residual_full_buffer = [0u8; size_of::<i64>()];
+        for byte in t_residual_full_buffer.iter_mut() {
+            *byte = decode_symbol(decoder, stream)? as u8;
+        }
+        Ok(i64::from_be_bytes(t_residual_full_buffer))
+    } else {
+        let mut t_residual_buffer = [0u8; size_of::<TResidual>()];
+        for byte in t_residual_buffer.iter_mut() {
+            *byte = decode_symbol(decoder, stream)? as u8;
+        }
+        (TResidual::from_be_bytes(t_residual_buffer) as i64)
+            .checked_shl(u32::from(bitshift_amt))
+            .ok_or(CodecError::CorruptAdu)
+    }
 }
 
 fn generate_t_prediction(
@@ -106,8 +830,11 @@ fn generate_t_prediction(
         };
         max(
             prev_event.t,
-            prev_event.t
-                + min(delta_t_prediction, (num_intervals as u8) as u32 * dt_ref) as AbsoluteT,
+            prev_event
+                .t
+                .saturating_add(
+                    min(delta_t_prediction, (num_intervals as u8) as u32 * dt_ref) as AbsoluteT,
+                ),
         )
     }
 }
@@ -163,19 +890,16 @@ impl HandleEvent for EventCube {
         } else if self.decompressed_event_queue.is_empty() {
             // Then we need to convert all the cube events back into actual events and queue them up
             for c in 0..self.num_channels {
-                for y in 0..BLOCK_SIZE {
-                    for x in 0..BLOCK_SIZE {
+                let channel = self.event_channel(c);
+                for y in 0..self.block_size {
+                    for x in 0..self.block_size {
                         if !self.raw_event_lists[c][y][x].is_empty() {
                             for event in self.raw_event_lists[c][y][x].iter() {
                                 let event = Event {
                                     coord: Coord {
                                         x: x as PixelAddress + self.start_x,
                                         y: y as PixelAddress + self.start_y,
-                                        c: if self.num_channels == 1 {
-                                            None
-                                        } else {
-                                            Some(c as u8)
-                                        },
+                                        c: channel,
                                     },
                                     d: event.d,
                                     t: event.t,
@@ -201,8 +925,8 @@ impl HandleEvent for EventCube {
     /// Clear out the cube's events and increment the start time by the cube's duration
     fn clear_compression(&mut self) {
         for c in 0..3 {
-            for y in 0..BLOCK_SIZE {
-                for x in 0..BLOCK_SIZE {
+            for y in 0..self.block_size {
+                for x in 0..self.block_size {
                     self.raw_event_lists[c][y][x].clear();
                 }
             }
@@ -212,8 +936,8 @@ impl HandleEvent for EventCube {
     }
     fn clear_decompression(&mut self) {
         for c in 0..3 {
-            for y in 0..BLOCK_SIZE {
-                for x in 0..BLOCK_SIZE {
+            for y in 0..self.block_size {
+                for x in 0..self.block_size {
                     self.raw_event_lists[c][y][x].clear();
                 }
             }
@@ -232,7 +956,7 @@ mod build_tests {
     /// Create an empty cube
     #[test]
     fn create_cube() -> Result<(), Box<dyn std::error::Error>> {
-        let cube = EventCube::new(16, 16, 1, 255, 255, 2550);
+        let cube = EventCube::new(16, 16, 1, 16, 255, 255, 2550);
         assert_eq!(cube.start_y, 16);
         assert_eq!(cube.start_x, 16);
 
@@ -241,7 +965,7 @@ mod build_tests {
 
     /// Create a cube and add several sparse events to it
     fn fill_cube() -> Result<EventCube, Box<dyn std::error::Error>> {
-        let mut cube = EventCube::new(16, 16, 1, 255, 255, 2550);
+        let mut cube = EventCube::new(16, 16, 1, 16, 255, 255, 2550);
         assert_eq!(cube.start_y, 16);
         assert_eq!(cube.start_x, 16);
 
@@ -313,105 +1037,57 @@ impl ComponentCompression for EventCube {
         stream: &mut BitWriter<Vec<u8>, BigEndian>,
         _: Option<u8>,
     ) -> Result<(), CodecError> {
+        let reference = self.motion_reference.clone();
+        let mut predictor = IntraPredictor::new(self.start_t, self.plane);
+        if self.motion_compensation {
+            let motion = reference
+                .as_deref()
+                .and_then(|reference| self.search_motion(reference));
+            encoder.model.set_context(contexts.motion_context);
+            encoder
+                .encode(Some(&motion_to_symbol(motion)), stream)
+                .unwrap();
+            predictor.motion = motion
+                .zip(reference.as_deref())
+                .map(|((dy, dx), reference)| {
+                    (
+                        reference,
+                        self.start_y as i64 + dy,
+                        self.start_x as i64 + dx,
+                    )
+                });
+        }
+
+        if self.adaptive_blocks {
+            self.compress_intra_adaptive(&mut predictor, encoder, contexts, stream);
+            return Ok(());
+        }
+
         encoder.model.set_context(contexts.d_context);
         if self.skip_cube {
             // If we're skipping this cube, just encode a NO_EVENT symbol
             let tmp = (DRESIDUAL_SKIP_CUBE + D_RESIDUAL_OFFSET) as usize;
             encoder.encode(Some(&tmp), stream).unwrap();
-            // for byte in (DRESIDUAL_SKIP_CUBE).to_be_bytes().iter() {
-            //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-            // }
             return Ok(()); // We're done
         }
 
-        let mut init_event: Option<EventCoordless> = None;
-        let mut d_residual = 0;
-
         // Intra-code the first event (if present) for each pixel in row-major order
-        for c in 0..self.num_channels {
-            self.raw_event_lists[c].iter_mut().for_each(|row| {
-                row.iter_mut().for_each(|pixel| {
-                    encoder.model.set_context(contexts.d_context);
-
-                    if !pixel.is_empty() {
-                        let event = pixel.first_mut().unwrap();
-
-                        if let Some(init) = &mut init_event {
-                            d_residual = event.d as DResidual - init.d as DResidual;
-                            // Write the D residual (relative to the start_d for the first event)
-
-                            let tmp = (d_residual + D_RESIDUAL_OFFSET) as usize;
-                            encoder.encode(Some(&tmp), stream).unwrap();
-                            //     for byte in d_residual.to_be_bytes().iter() {
-                            //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                            // }
-                        } else {
-                            // Write the first event's D directly
-                            let tmp = (event.d as DResidual + D_RESIDUAL_OFFSET) as usize;
-                            encoder.encode(Some(&tmp), stream).unwrap();
-                            // for byte in (event.d as DResidual).to_be_bytes().iter() {
-                            //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                            // }
-
-                            // Create the init event with t being the start_t of the cube
-                            init_event = Some(EventCoordless {
-                                d: event.d,
-                                t: self.start_t,
-                            })
-                        }
-
-                        if let Some(init) = &mut init_event {
-                            // Don't do any special prediction here (yet). Just predict the same t as previously found.
-                            let t_residual_i64 = event.t as i64 - init.t as i64;
-                            let (bitshift_amt, t_residual) =
-                                contexts.residual_to_bitshift(t_residual_i64);
-                            // contexts.residual_to_bitshift2(
-                            //     init.t as i64,
-                            //     t_residual_i64,
-                            //     event,
-                            //     init,
-                            //     self.dt_ref
-                            // );
-
-                            encoder.model.set_context(contexts.bitshift_context);
-                            for byte in bitshift_amt.to_be_bytes().iter() {
-                                encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                            }
-
-                            encoder.model.set_context(contexts.t_context);
-
-                            if bitshift_amt == BITSHIFT_ENCODE_FULL {
-                                for byte in t_residual.to_be_bytes().iter() {
-                                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                                }
-                                event.t = (init.t as i64 + t_residual) as AbsoluteT;
-                            } else {
-                                let t_residual = t_residual as TResidual;
-                                for byte in t_residual.to_be_bytes().iter() {
-                                    encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                                }
-                                // Shift it back for the event, so we base our next prediction on the reconstructed value!
-                                // if bitshift_amt != 0 {
-                                event.t = (init.t as i64
-                                    + ((t_residual as i64) << bitshift_amt as i64))
-                                    as AbsoluteT;
-                            }
-                            debug_assert!(event.t < 2_u32.pow(31));
-
-                            *init = *event;
-                        } else {
-                            panic!("No init event");
-                        }
-                    } else {
-                        // Else there's no event for this pixel. Encode a NO_EVENT symbol.
-                        let tmp = (DRESIDUAL_NO_EVENT + D_RESIDUAL_OFFSET) as usize;
-                        encoder.encode(Some(&tmp), stream).unwrap();
-                        // for byte in (DRESIDUAL_NO_EVENT).to_be_bytes().iter() {
-                        //     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
-                        // }
-                    }
-                })
-            })
+        for (c, square) in self.raw_event_lists[..self.num_channels]
+            .iter_mut()
+            .enumerate()
+        {
+            for (y, row) in square.iter_mut().enumerate() {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    compress_intra_pixel(
+                        pixel,
+                        (c, y, x),
+                        &mut predictor,
+                        encoder,
+                        contexts,
+                        stream,
+                    );
+                }
+            }
         }
         Ok(())
     }
@@ -426,8 +1102,12 @@ impl ComponentCompression for EventCube {
         if self.skip_cube {
             return Ok(());
         }
+        if self.delta_t_coding != DeltaTCoding::Predictive {
+            return self.compress_inter_wavelet(encoder, contexts, stream);
+        }
         let c_thresh_max = c_thresh_max.unwrap_or(7);
         for c in 0..self.num_channels {
+            let c_thresh_max = c_thresh_max.min(self.channel_c_thresh_max[self.stream_channel(c)]);
             self.raw_event_lists[c].iter_mut().for_each(|row| {
                 row.iter_mut().for_each(|pixel| {
                     if !pixel.is_empty() {
@@ -442,7 +1122,7 @@ impl ComponentCompression for EventCube {
                                 let event = &mut pixel[idx];
 
                                 // Get the D residual
-                                let d_residual = event.d as DResidual - prev_event.d as DResidual;
+                                let d_residual = event.residual(&prev_event).0 as DResidual;
                                 // Write the D residual (relative to the start_d for the first event)
                                 for byte in d_residual.to_be_bytes().iter() {
                                     encoder.encode(Some(&(*byte as usize)), stream).unwrap();
@@ -522,80 +1202,53 @@ impl ComponentCompression for EventCube {
         contexts: &Contexts,
         stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
         start_t: AbsoluteT,
-    ) {
-        let mut bitshift_buffer = [0u8; 1];
-        let mut t_residual_buffer = [0u8; size_of::<TResidual>()];
-        let mut t_residual_full_buffer = [0u8; size_of::<i64>()];
-        let mut init_event: Option<EventCoordless> = None;
-
-        for c in 0..self.num_channels {
-            for y in 0..BLOCK_SIZE {
-                for x in 0..BLOCK_SIZE {
-                    let pixel = &mut self.raw_event_lists[c][y][x];
-
-                    decoder.model.set_context(contexts.d_context);
+    ) -> Result<(), CodecError> {
+        let reference = self.motion_reference.clone();
+        let mut predictor = IntraPredictor::new(start_t, self.plane);
+        if self.motion_compensation {
+            decoder.model.set_context(contexts.motion_context);
+            let motion =
+                symbol_to_motion(decode_symbol(decoder, stream)?).ok_or(CodecError::CorruptAdu)?;
+            if let Some((dy, dx)) = motion {
+                // The cube can't be decoded without the previous Adu
+                let reference = reference.as_deref().ok_or(CodecError::CorruptAdu)?;
+                predictor.motion = Some((
+                    reference,
+                    self.start_y as i64 + dy,
+                    self.start_x as i64 + dx,
+                ));
+            }
+        }
 
-                    let tmp = decoder.decode(stream).unwrap().unwrap();
-                    let d_residual = tmp as i16 - D_RESIDUAL_OFFSET;
+        if self.adaptive_blocks {
+            return self.decompress_intra_adaptive(&mut predictor, decoder, contexts, stream);
+        }
 
+        for (c, square) in self.raw_event_lists[..self.num_channels]
+            .iter_mut()
+            .enumerate()
+        {
+            for (y, row) in square.iter_mut().enumerate() {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let d_residual = decompress_intra_pixel(
+                        pixel,
+                        (c, y, x),
+                        &mut predictor,
+                        decoder,
+                        contexts,
+                        stream,
+                    )?;
                     if d_residual == DRESIDUAL_SKIP_CUBE {
-                        pixel.clear(); // So we can skip it for intra-coding
                         self.skip_cube = true;
-                        return;
-                    } else if d_residual == DRESIDUAL_NO_EVENT {
-                        pixel.clear(); // So we can skip it for intra-coding
-                    } else {
-                        let d = if let Some(init) = &mut init_event {
-                            (init.d as DResidual + d_residual) as D
-                        } else {
-                            // There is no init event
-                            init_event = Some(EventCoordless { d: 0, t: start_t });
-                            self.skip_cube = false;
-                            d_residual as D
-                        };
-
-                        if let Some(init) = &mut init_event {
-                            // decoder.model.set_context(contexts.dtref_context);
-                            // for byte in dtref_residual_buffer.iter_mut() {
-                            //     *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                            // }
-                            // let dtref_residual = DResidual::from_be_bytes(dtref_residual_buffer);
-
-                            decoder.model.set_context(contexts.bitshift_context);
-                            for byte in bitshift_buffer.iter_mut() {
-                                *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                            }
-                            let bitshift_amt = bitshift_buffer[0];
-
-                            let t_residual = if bitshift_amt == BITSHIFT_ENCODE_FULL {
-                                decoder.model.set_context(contexts.t_context);
-                                for byte in t_residual_full_buffer.iter_mut() {
-                                    *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                                }
-                                i64::from_be_bytes(t_residual_full_buffer)
-                            } else {
-                                decoder.model.set_context(contexts.t_context);
-                                for byte in t_residual_buffer.iter_mut() {
-                                    *byte = decoder.decode(stream).unwrap().unwrap() as u8;
-                                }
-                                let t_residual = TResidual::from_be_bytes(t_residual_buffer) as i64;
-                                (t_residual) << bitshift_amt as i64
-                            };
-
-                            init.d = (init.d as DResidual + d_residual) as D;
-
-                            debug_assert!(init.t as i64 + t_residual >= 0);
-                            init.t = (init.t as i64 + t_residual) as AbsoluteT;
-
-                            // debug_assert!(init.t < start_t + num_intervals as AbsoluteT * dt_ref);
-                            pixel.push(EventCoordless { d, t: init.t });
-                        } else {
-                            panic!("No init event");
-                        }
+                        return Ok(());
                     }
                 }
             }
         }
+        if predictor.init_event.is_some() {
+            self.skip_cube = false;
+        }
+        Ok(())
     }
 
     fn decompress_inter(
@@ -603,18 +1256,25 @@ impl ComponentCompression for EventCube {
         decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
         contexts: &Contexts,
         stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
-    ) {
+    ) -> Result<(), CodecError> {
         if self.skip_cube {
-            return;
+            return Ok(());
+        }
+        if self.delta_t_coding != DeltaTCoding::Predictive {
+            return self.decompress_inter_wavelet(decoder, contexts, stream);
         }
         let mut d_residual_buffer = [0u8; size_of::<DResidual>()];
         let mut t_residual_buffer = [0u8; size_of::<TResidual>()];
         let mut t_residual_full_buffer = [0u8; size_of::<i64>()];
         let mut bitshift_buffer = [0u8; 1];
 
+        // A pixel's events have increasing timestamps, so it can't have more events than there
+        // are ticks in the cube. More than that means the data is corrupt.
+        let max_pixel_events = self.num_intervals * self.dt_ref as usize + 1;
+
         for c in 0..self.num_channels {
-            self.raw_event_lists[c].iter_mut().for_each(|row| {
-                row.iter_mut().for_each(|pixel| {
+            for row in self.raw_event_lists[c].iter_mut() {
+                for pixel in row.iter_mut() {
                     if !pixel.is_empty() {
                         // Then look for the next events for this pixel
                         let mut idx = 1;
@@ -623,17 +1283,23 @@ impl ComponentCompression for EventCube {
                             decoder.model.set_context(contexts.d_context);
 
                             for byte in d_residual_buffer.iter_mut() {
-                                *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                                *byte = decode_symbol(decoder, stream)? as u8;
                             }
                             let d_residual = DResidual::from_be_bytes(d_residual_buffer);
 
                             if d_residual == DRESIDUAL_NO_EVENT {
                                 break; // We have all the events for this pixel now
                             }
+                            if pixel.len() > max_pixel_events {
+                                return Err(CodecError::CorruptAdu);
+                            }
                             debug_assert!(idx - 1 < pixel.len());
                             let prev_event = pixel[idx - 1];
 
-                            let d = (prev_event.d as DResidual + d_residual) as D;
+                            let d = prev_event
+                                .checked_add_residual(i64::from(d_residual), 0)
+                                .ok_or(CodecError::CorruptAdu)?
+                                .d;
 
                             let t_prediction = generate_t_prediction(
                                 idx,
@@ -647,27 +1313,32 @@ impl ComponentCompression for EventCube {
 
                             decoder.model.set_context(contexts.bitshift_context);
                             for byte in bitshift_buffer.iter_mut() {
-                                *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                                *byte = decode_symbol(decoder, stream)? as u8;
                             }
                             let bitshift_amt = bitshift_buffer[0];
 
                             let t_residual = if bitshift_amt == BITSHIFT_ENCODE_FULL {
                                 decoder.model.set_context(contexts.t_context);
                                 for byte in t_residual_full_buffer.iter_mut() {
-                                    *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                                    *byte = decode_symbol(decoder, stream)? as u8;
                                 }
                                 i64::from_be_bytes(t_residual_full_buffer)
                             } else {
                                 decoder.model.set_context(contexts.t_context);
                                 for byte in t_residual_buffer.iter_mut() {
-                                    *byte = decoder.decode(stream).unwrap().unwrap() as u8;
+                                    *byte = decode_symbol(decoder, stream)? as u8;
                                 }
                                 let t_residual = TResidual::from_be_bytes(t_residual_buffer) as i64;
-                                (t_residual) << bitshift_amt as i64
+                                t_residual
+                                    .checked_shl(u32::from(bitshift_amt))
+                                    .ok_or(CodecError::CorruptAdu)?
                             };
 
                             let t = max(
-                                (t_prediction as i64 + t_residual) as AbsoluteT,
+                                (t_prediction as i64)
+                                    .checked_add(t_residual)
+                                    .ok_or(CodecError::CorruptAdu)?
+                                    as AbsoluteT,
                                 prev_event.t,
                             );
                             debug_assert!(t >= prev_event.t);
@@ -680,9 +1351,10 @@ impl ComponentCompression for EventCube {
                             idx += 1;
                         }
                     }
-                });
-            });
+                }
+            }
         }
+        Ok(())
     }
 }
 
@@ -703,7 +1375,7 @@ mod compression_tests {
 
     #[test]
     fn compress_and_decompress_intra() -> Result<(), Box<dyn Error>> {
-        let mut cube = EventCube::new(0, 0, 1, 255, 255, 10);
+        let mut cube = EventCube::new(0, 0, 1, 16, 255, 255, 10);
         let mut counter = 0;
         for _ in 0..3 {
             for y in 0..16 {
@@ -742,7 +1414,7 @@ mod compression_tests {
 
         let mut cube2 = cube.clone();
 
-        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255);
+        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255)?;
 
         for c in 0..3 {
             for y in 0..16 {
@@ -770,7 +1442,7 @@ mod compression_tests {
 
     #[test]
     fn compress_and_decompress_inter() -> Result<(), Box<dyn Error>> {
-        let mut cube = EventCube::new(0, 0, 1, 255, 255, 2);
+        let mut cube = EventCube::new(0, 0, 1, 16, 255, 255, 2);
         let mut counter = 0;
         for _ in 0..3 {
             for y in 0..16 {
@@ -831,8 +1503,8 @@ mod compression_tests {
         let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
 
         let mut cube2 = cube.clone();
-        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255);
-        cube2.decompress_inter(&mut decoder, &contexts, &mut stream);
+        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255)?;
+        cube2.decompress_inter(&mut decoder, &contexts, &mut stream)?;
 
         for c in 0..3 {
             for y in 0..16 {
@@ -861,7 +1533,7 @@ mod compression_tests {
 
     #[test]
     fn compress_and_decompress_empty() -> Result<(), Box<dyn Error>> {
-        let mut cube = EventCube::new(0, 0, 1, 255, 255, 10);
+        let mut cube = EventCube::new(0, 0, 1, 16, 255, 255, 10);
 
         let bufwriter = Vec::new();
         let mut stream = BitWriter::endian(bufwriter, BigEndian);
@@ -888,8 +1560,8 @@ mod compression_tests {
         let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
 
         let mut cube2 = cube.clone();
-        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255);
-        cube2.decompress_inter(&mut decoder, &contexts, &mut stream);
+        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255)?;
+        cube2.decompress_inter(&mut decoder, &contexts, &mut stream)?;
 
         for c in 0..3 {
             for y in 0..16 {
@@ -917,7 +1589,7 @@ mod compression_tests {
     #[test]
     fn compress_and_decompress_intra_huge_tresidual() -> Result<(), Box<dyn Error>> {
         let num_intervals = 2;
-        let mut cube = EventCube::new(0, 0, 1, 255000, 255, num_intervals);
+        let mut cube = EventCube::new(0, 0, 1, 16, 255000, 255, num_intervals);
 
         cube.ingest_event(Event {
             coord: Coord {
@@ -963,8 +1635,8 @@ mod compression_tests {
         let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
 
         let mut cube2 = cube.clone();
-        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255000);
-        cube2.decompress_inter(&mut decoder, &contexts, &mut stream);
+        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255000)?;
+        cube2.decompress_inter(&mut decoder, &contexts, &mut stream)?;
 
         // Note that these may NOT be the original values we ingested, due to the bit shifting!
         assert_eq!(
@@ -982,7 +1654,7 @@ mod compression_tests {
     #[test]
     fn compress_and_decompress_inter_huge_tresidual() -> Result<(), Box<dyn Error>> {
         let num_intervals = 2;
-        let mut cube = EventCube::new(0, 0, 1, 255000, 255, num_intervals);
+        let mut cube = EventCube::new(0, 0, 1, 16, 255000, 255, num_intervals);
 
         cube.ingest_event(Event {
             coord: Coord {
@@ -1037,9 +1709,9 @@ mod compression_tests {
         let mut stream = BitReader::endian(Cursor::new(stream.into_writer()), BigEndian);
 
         let mut cube2 = cube.clone();
-        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255000);
+        cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255000)?;
 
-        cube2.decompress_inter(&mut decoder, &contexts, &mut stream);
+        cube2.decompress_inter(&mut decoder, &contexts, &mut stream)?;
 
         // Note that these may NOT be the original values we ingested, due to the bit shifting!
         assert_eq!(
@@ -1057,4 +1729,62 @@ mod compression_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn compress_and_decompress_adaptive_blocks() -> Result<(), Box<dyn Error>> {
+        // Events in one corner of the cube, and at a single pixel elsewhere
+        let mut pixels: Vec<(u32, u32)> =
+            (0..4).flat_map(|y| (0..4).map(move |x| (y, x))).collect();
+        pixels.push((12, 9));
+
+        let roundtrip = |adaptive_blocks: bool| -> Result<usize, Box<dyn Error>> {
+            let mut cube = EventCube::new(0, 0, 1, 16, 255, 255, 10);
+            cube.adaptive_blocks = adaptive_blocks;
+            for k in 0..3 {
+                for &(y, x) in &pixels {
+                    cube.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 500 + y * 16 + x,
+                        d: 7 + k as u8,
+                    });
+                }
+            }
+
+            let mut stream = BitWriter::endian(Vec::new(), BigEndian);
+            let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+            let contexts = crate::codec::compressed::source_model::cabac_contexts::Contexts::new(
+                &mut source_model,
+                255,
+            );
+            let mut encoder = Encoder::new(source_model);
+            cube.compress_intra(&mut encoder, &contexts, &mut stream, None)?;
+            cube.compress_inter(&mut encoder, &contexts, &mut stream, None)?;
+            eof_context(&contexts, &mut encoder, &mut stream);
+            let bytes = stream.into_writer();
+            let len = bytes.len();
+
+            let mut source_model = FenwickModel::with_symbols(u16::MAX as usize, 1 << 30);
+            let contexts = crate::codec::compressed::source_model::cabac_contexts::Contexts::new(
+                &mut source_model,
+                255,
+            );
+            let mut decoder = arithmetic_coding_adder_dep::Decoder::new(source_model);
+            let mut stream = BitReader::endian(Cursor::new(bytes), BigEndian);
+
+            let mut cube2 = EventCube::new(0, 0, 1, 16, 255, 255, 10);
+            cube2.adaptive_blocks = adaptive_blocks;
+            cube2.decompress_intra(&mut decoder, &contexts, &mut stream, 255)?;
+            cube2.decompress_inter(&mut decoder, &contexts, &mut stream)?;
+
+            // The encoder reconstructs the timestamps as the decoder will see them
+            assert_eq!(cube.raw_event_lists, cube2.raw_event_lists);
+            assert_eq!(cube2.raw_event_lists[0][12][9].len(), 3);
+            Ok(len)
+        };
+
+        let row_major_len = roundtrip(false)?;
+        let adaptive_len = roundtrip(true)?;
+        assert!(adaptive_len < row_major_len);
+        Ok(())
+    }
 }
@@ -4,5 +4,8 @@ pub mod event_adu;
 /// An `EventCube` has many compressed events
 mod event_cube;
 
-/// Width and height (same number) of a block
-pub const BLOCK_SIZE: usize = 16;
+/// Predicting an `EventCube` from the previous `EventAdu`
+pub(crate) mod motion;
+
+/// Width and height of the smallest block that an adaptively partitioned cube is split into
+pub const MIN_BLOCK_SIZE: usize = 4;
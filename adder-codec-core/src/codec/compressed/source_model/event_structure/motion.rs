@@ -0,0 +1,115 @@
+use crate::codec::compressed::source_model::event_structure::event_cube::EventCube;
+use crate::{AbsoluteT, EventCoordless};
+use ndarray::{Array2, Array3};
+
+/// The largest offset (in pixels, in each dimension) that a cube's motion vector can have
+pub const MAX_MOTION_VECTOR: i64 = 4;
+
+/// The number of motion symbols: one for a cube which isn't predicted from the previous Adu, and
+/// one for each motion vector
+pub const MOTION_SYMBOLS: usize =
+    ((2 * MAX_MOTION_VECTOR + 1) * (2 * MAX_MOTION_VECTOR + 1) + 1) as usize;
+
+/// The offset, as (y, x), from each pixel of a cube to the pixel of the previous Adu which it's
+/// predicted from
+pub(crate) type MotionVector = (i64, i64);
+
+/// The symbol coding a cube's motion vector, or `None` if it isn't predicted from the previous Adu
+pub(crate) fn motion_to_symbol(motion: Option<MotionVector>) -> usize {
+    match motion {
+        None => 0,
+        Some((dy, dx)) => {
+            ((dy + MAX_MOTION_VECTOR) * (2 * MAX_MOTION_VECTOR + 1) + dx + MAX_MOTION_VECTOR + 1)
+                as usize
+        }
+    }
+}
+
+/// Invert [`motion_to_symbol`]. Returns `None` for a symbol which isn't a valid motion vector.
+pub(crate) fn symbol_to_motion(symbol: usize) -> Option<Option<MotionVector>> {
+    if symbol == 0 {
+        return Some(None);
+    }
+    if symbol >= MOTION_SYMBOLS {
+        return None;
+    }
+    let idx = symbol as i64 - 1;
+    let width = 2 * MAX_MOTION_VECTOR + 1;
+    Some(Some((
+        idx / width - MAX_MOTION_VECTOR,
+        idx % width - MAX_MOTION_VECTOR,
+    )))
+}
+
+/// The first event of each pixel in an Adu (as the decoder reconstructs it), with its timestamp
+/// relative to the start of the Adu. The cubes of the next Adu are predicted from it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MotionReference {
+    first_events: Array3<Option<EventCoordless>>,
+}
+
+impl MotionReference {
+    /// Gather the first events of the Adu's cubes
+    pub(crate) fn new(cubes: &Array2<EventCube>) -> Self {
+        // The cubes of a planar Adu each hold one channel of one plane
+        let (mut num_channels, mut height, mut width) = (1, 0, 0);
+        for cube in cubes.iter() {
+            num_channels = num_channels.max(cube.stream_channel(cube.num_channels - 1) + 1);
+            height = height.max(cube.start_y as usize + cube.block_size);
+            width = width.max(cube.start_x as usize + cube.block_size);
+        }
+        let mut first_events = Array3::from_elem((num_channels, height, width), None);
+        for cube in cubes.iter() {
+            cube.record_first_events(&mut first_events);
+        }
+        Self { first_events }
+    }
+
+    /// The first event of the given pixel (in absolute coordinates), if it's in the frame and had
+    /// one, with its timestamp shifted to the Adu beginning at `start_t`
+    pub(crate) fn get(
+        &self,
+        c: usize,
+        y: i64,
+        x: i64,
+        start_t: AbsoluteT,
+    ) -> Option<EventCoordless> {
+        if y < 0 || x < 0 {
+            return None;
+        }
+        self.first_events
+            .get((c, y as usize, x as usize))
+            .copied()
+            .flatten()
+            .map(|event| EventCoordless {
+                d: event.d,
+                t: start_t + event.t,
+            })
+    }
+}
+
+/// Roughly how many bits it takes to code a residual
+pub(crate) fn residual_cost(residual: i64) -> u32 {
+    u64::BITS - residual.unsigned_abs().leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{motion_to_symbol, symbol_to_motion, MAX_MOTION_VECTOR, MOTION_SYMBOLS};
+
+    #[test]
+    fn test_motion_symbols() {
+        assert_eq!(symbol_to_motion(motion_to_symbol(None)), Some(None));
+        let mut symbols = vec![motion_to_symbol(None)];
+        for dy in -MAX_MOTION_VECTOR..=MAX_MOTION_VECTOR {
+            for dx in -MAX_MOTION_VECTOR..=MAX_MOTION_VECTOR {
+                let symbol = motion_to_symbol(Some((dy, dx)));
+                assert_eq!(symbol_to_motion(symbol), Some(Some((dy, dx))));
+                symbols.push(symbol);
+            }
+        }
+        symbols.sort_unstable();
+        assert_eq!(symbols, (0..MOTION_SYMBOLS).collect::<Vec<_>>());
+        assert_eq!(symbol_to_motion(MOTION_SYMBOLS), None);
+    }
+}
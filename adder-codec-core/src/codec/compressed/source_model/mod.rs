@@ -29,13 +29,13 @@ trait ComponentCompression {
         contexts: &Contexts,
         stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
         start_t: AbsoluteT,
-    );
+    ) -> Result<(), CodecError>;
     fn decompress_inter(
         &mut self,
         decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
         contexts: &Contexts,
         stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
-    );
+    ) -> Result<(), CodecError>;
     fn compress_inter(
         &mut self,
         encoder: &mut Encoder<FenwickModel, BitWriter<Vec<u8>, BigEndian>>,
@@ -44,6 +44,15 @@ trait ComponentCompression {
         c_thresh_max: Option<u8>,
     ) -> Result<(), CodecError>;
 }
+/// Decode the next symbol. Reaching the end-of-stream symbol before the Adu is complete means that
+/// the data is corrupt.
+fn decode_symbol(
+    decoder: &mut Decoder<FenwickModel, BitReader<Cursor<Vec<u8>>, BigEndian>>,
+    stream: &mut BitReader<Cursor<Vec<u8>>, BigEndian>,
+) -> Result<usize, CodecError> {
+    decoder.decode(stream)?.ok_or(CodecError::CorruptAdu)
+}
+
 pub mod cabac_contexts;
 pub mod event_structure;
 
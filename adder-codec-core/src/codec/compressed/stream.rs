@@ -1,25 +1,336 @@
-use crate::codec::{CodecError, CodecMetadata, EncoderOptions, ReadCompression, WriteCompression};
+use crate::codec::checkpoint::EncoderCheckpoint;
+use crate::codec::clock::{ClockCorrection, RefIntervalChange};
+use crate::codec::stabilization::StabilizationTransform;
+use crate::codec::{
+    ChannelLayout, CodecError, CodecMetadata, DeltaTCoding, EncoderOptions, EventValidation,
+    IntraRefresh, ReadCompression, RoundTripCheck, WriteCompression, BLOCK_SIZES,
+    DEFAULT_BLOCK_SIZE,
+};
 use bitstream_io::{BigEndian, BitRead, BitReader, BitWrite, BitWriter};
 use priority_queue::PriorityQueue;
 use std::cmp::Reverse;
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::ops::{Add, AddAssign};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
 use crate::codec::compressed::source_model::event_structure::event_adu::EventAdu;
+use crate::codec::compressed::source_model::event_structure::motion::MotionReference;
 use crate::codec::compressed::source_model::HandleEvent;
+use crate::codec::compressed::{
+    ADAPTIVE_BLOCKS_VERSION, CONFIGURABLE_BLOCK_SIZE_VERSION, CONFIGURABLE_D_MAX_VERSION,
+    CONTEXT_PRIORS_VERSION, DELTA_T_CODING_VERSION, INDEPENDENT_CUBES_VERSION,
+    MAX_ENHANCEMENT_LAYERS, MOTION_COMPENSATION_VERSION, PLANAR_CHANNELS_VERSION,
+    QUANTIZATION_TABLE_VERSION, REF_INTERVAL_CHANGES_VERSION, STABILIZATION_VERSION,
+};
 use crate::codec::header::{Magic, MAGIC_COMPRESSED};
-use crate::codec::rate_controller::CrfParameters;
-use crate::{DeltaT, Event};
+use crate::codec::priors::ContextPriors;
+use crate::codec::rate_controller::{BitrateController, CrfParameters, QualityMap};
+use crate::{AbsoluteT, DeltaT, Event, Roi, D, D_EMPTY, D_MAX, D_ZERO_INTEGRATION};
 
 /// A message to send to the writer thread (that is, the main thread) to write out the compressed
 /// ADΔER data to the stream
 pub(crate) struct BytesMessage {
     message_id: u32,
+    start_t: AbsoluteT,
     bytes: Vec<u8>,
+    checksum: Option<u32>,
+    sync_marker: bool,
+    delta_t_coding: DeltaTCoding,
+}
+
+/// An Adu waiting in the writer thread's queue: its start time, bytes, checksum, whether to
+/// precede it with a sync marker, and how its Δt values are coded
+type QueuedAdu = (AbsoluteT, Vec<u8>, Option<u32>, bool, DeltaTCoding);
+
+/// Set in an Adu's length prefix when the length is followed by a CRC32 checksum of the Adu
+const ADU_CHECKSUM_FLAG: u32 = 1 << 31;
+
+/// The position of the two bits of an Adu's length prefix which signal how its Δt values are
+/// coded, from [`DELTA_T_CODING_VERSION`] on
+const ADU_DELTA_T_CODING_SHIFT: u32 = 29;
+
+/// The bits of an Adu's length prefix which hold its length in bytes, from
+/// [`DELTA_T_CODING_VERSION`] on
+const ADU_LENGTH_MASK: u32 = (1 << ADU_DELTA_T_CODING_SHIFT) - 1;
+
+/// Precedes each Adu (and its start time) in a stream written with sync markers. As a length
+/// prefix, it would declare an Adu of nearly 2 GiB (or one with an invalid Δt coding), so it can't
+/// be mistaken for one.
+const ADU_SYNC_MARKER: [u8; 4] = [0xFF, 0xAD, 0xE5, 0x5C];
+
+/// The bits of an Adu's length prefix which signal the given Δt coding
+fn delta_t_coding_bits(coding: DeltaTCoding) -> u32 {
+    let bits = match coding {
+        DeltaTCoding::Predictive => 0,
+        DeltaTCoding::Haar => 1,
+        DeltaTCoding::Cdf53 => 2,
+    };
+    bits << ADU_DELTA_T_CODING_SHIFT
+}
+
+/// The Δt coding signaled in an Adu's length prefix, or `None` if the bits are invalid
+fn delta_t_coding_from_bits(header_len: u32) -> Option<DeltaTCoding> {
+    match (header_len & !ADU_CHECKSUM_FLAG) >> ADU_DELTA_T_CODING_SHIFT {
+        0 => Some(DeltaTCoding::Predictive),
+        1 => Some(DeltaTCoding::Haar),
+        2 => Some(DeltaTCoding::Cdf53),
+        _ => None,
+    }
+}
+
+/// Lookup table for [`crc32`], with the reflected IEEE polynomial
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Compute the CRC32 (IEEE) checksum of the given bytes
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Whether the cubes of a stream with the given metadata code a motion vector, and so whether each
+/// Adu is kept to predict the next one
+fn motion_compensated(meta: &CodecMetadata) -> bool {
+    meta.codec_version >= MOTION_COMPENSATION_VERSION && meta.motion_compensation
+}
+
+/// Create an empty Adu, starting at `start_t`, laid out for a stream with the given metadata
+fn new_adu(meta: &CodecMetadata, start_t: AbsoluteT) -> EventAdu {
+    let mut adu = EventAdu::new(
+        meta.plane,
+        start_t,
+        meta.ref_interval,
+        meta.adu_interval,
+        meta.block_size,
+    );
+    // The cubes are replaced, so this comes before their other settings
+    if meta.codec_version >= PLANAR_CHANNELS_VERSION && meta.channel_layout == ChannelLayout::Planar
+    {
+        adu.set_planar_channels();
+    }
+    adu.independent_cubes = meta.codec_version >= INDEPENDENT_CUBES_VERSION;
+    adu.set_adaptive_blocks(meta.codec_version >= ADAPTIVE_BLOCKS_VERSION);
+    adu.set_motion_compensation(motion_compensated(meta));
+    adu.keep_motion_reference = motion_compensated(meta);
+    adu.d_max = (meta.codec_version >= CONFIGURABLE_D_MAX_VERSION).then_some(meta.d_max);
+    if meta.codec_version >= QUANTIZATION_TABLE_VERSION {
+        adu.set_quantization_table(meta.quantization_table);
+    }
+    adu
+}
+
+/// Decode an Adu's record into its events, as a decoder of the stream would. The Adu must be
+/// predicted from the same motion reference, and primed with the same priors, as when it was
+/// compressed.
+fn decode_adu_record(
+    meta: &CodecMetadata,
+    record: Vec<u8>,
+    delta_t_coding: DeltaTCoding,
+    motion_reference: Option<Arc<MotionReference>>,
+    context_priors: Option<Arc<ContextPriors>>,
+) -> Result<Vec<Event>, CodecError> {
+    let mut adu = new_adu(meta, 0);
+    adu.set_delta_t_coding(delta_t_coding);
+    adu.motion_reference = motion_reference;
+    adu.context_priors = context_priors;
+    let mut layer_streams: Vec<_> = split_adu_record(record, meta.enhancement_layers, None)
+        .ok_or(CodecError::CorruptAdu)?
+        .into_iter()
+        .map(|layer| BitReader::endian(Cursor::new(layer), BigEndian))
+        .collect();
+    let (adu_stream, layer_streams) = layer_streams.split_first_mut().unwrap();
+    adu.decompress_layered(adu_stream, layer_streams, meta.enhancement_layers, None)?;
+
+    let mut events = Vec::new();
+    loop {
+        match adu.digest_event() {
+            Ok(event) => events.push(event),
+            Err(CodecError::NoMoreEvents) => return Ok(events),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Compare the events decoded from an Adu with the ones it was compressed from. Timestamps are
+/// only compared if the Adu was coded losslessly. Returns a description of the first difference.
+fn round_trip_mismatch(
+    mut source: Vec<Event>,
+    mut decoded: Vec<Event>,
+    lossless: bool,
+) -> Option<String> {
+    // Each pixel's events are in the same order either way
+    let key = |event: &Event| {
+        (
+            event.coord.y,
+            event.coord.x,
+            event.coord.c,
+            event.t,
+            event.d,
+        )
+    };
+    source.sort_by_key(key);
+    decoded.sort_by_key(key);
+
+    let mismatch = source
+        .iter()
+        .zip(decoded.iter())
+        .find(|(a, b)| a.coord != b.coord || a.d != b.d || (lossless && a.t != b.t));
+    match mismatch {
+        Some((expected, found)) => Some(format!("expected {expected:?}, found {found:?}")),
+        None if source.len() != decoded.len() => Some(format!(
+            "expected {} events, found {}",
+            source.len(),
+            decoded.len()
+        )),
+        None => None,
+    }
+}
+
+/// Report a round-trip mismatch of the Adu starting at `start_t` as `check` says: print it for
+/// [`RoundTripCheck::Log`], or keep it in `failure` for [`RoundTripCheck::Fail`], if it's the
+/// first failure
+fn report_round_trip_mismatch(
+    check: RoundTripCheck,
+    failure: &RwLock<Option<(AbsoluteT, String)>>,
+    start_t: AbsoluteT,
+    detail: String,
+) {
+    match check {
+        RoundTripCheck::None => {}
+        RoundTripCheck::Log => {
+            eprintln!("Round-trip check failed for the Adu at t={start_t}: {detail}");
+        }
+        RoundTripCheck::Fail => {
+            failure.write().unwrap().get_or_insert((start_t, detail));
+        }
+    }
+}
+
+/// Check that the event's D value can be coded in a stream with the given maximum. Only streams
+/// which declare their maximum restrict the D values, since they rule out some D residuals.
+fn check_d(event: &Event, d_max: Option<D>) -> Result<(), CodecError> {
+    match d_max {
+        Some(d_max) if event.d > d_max && event.d != D_ZERO_INTEGRATION && event.d != D_EMPTY => {
+            Err(CodecError::DOutOfRange { d: event.d, d_max })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Compress an Adu into the bytes of its record in the stream. A layered Adu's record holds each of
+/// its layers in turn, starting with the base layer, and each prefixed by its length.
+fn compress_adu_record(
+    adu: &mut EventAdu,
+    c_thresh_max: u8,
+    quality_map: Option<&QualityMap>,
+    enhancement_layers: u8,
+) -> Vec<u8> {
+    let mut temp_stream = BitWriter::endian(Vec::new(), BigEndian);
+    let layers = adu
+        .compress_layered(
+            &mut temp_stream,
+            c_thresh_max,
+            quality_map,
+            enhancement_layers,
+        )
+        .unwrap_or_default();
+    let base = temp_stream.into_writer();
+    if enhancement_layers == 0 {
+        return base;
+    }
+
+    let mut record = Vec::new();
+    for layer in std::iter::once(base).chain(layers) {
+        record.extend_from_slice(&(layer.len() as u32).to_be_bytes());
+        record.extend_from_slice(&layer);
+    }
+    record
+}
+
+/// Split the record of a layered Adu into its base layer and at most `max_layers` of its
+/// enhancement layers. Returns `None` if the record is malformed.
+fn split_adu_record(
+    record: Vec<u8>,
+    enhancement_layers: u8,
+    max_layers: Option<u8>,
+) -> Option<Vec<Vec<u8>>> {
+    if enhancement_layers == 0 {
+        return Some(vec![record]);
+    }
+
+    let num_layers = 1 + enhancement_layers.min(max_layers.unwrap_or(enhancement_layers)) as usize;
+    let mut layers = Vec::with_capacity(num_layers);
+    let mut rest = &record[..];
+    for _ in 0..num_layers {
+        let (len, tail) = rest.split_first_chunk::<4>()?;
+        let len = u32::from_be_bytes(*len) as usize;
+        if len > tail.len() {
+            return None;
+        }
+        let (layer, tail) = tail.split_at(len);
+        layers.push(layer.to_vec());
+        rest = tail;
+    }
+    Some(layers)
+}
+
+/// Marks the end of a time index appended to a compressed stream
+const TIME_INDEX_MAGIC: [u8; 4] = *b"aidx";
+
+/// Marks the end of the clock corrections appended to a compressed stream, after the time index
+/// if there is one
+const CLOCK_CORRECTIONS_MAGIC: [u8; 4] = *b"aclk";
+
+/// Marks the end of the reference interval changes appended to a compressed stream, after the
+/// time index and clock corrections if there are any
+const REF_INTERVALS_MAGIC: [u8; 4] = *b"aref";
+
+/// Marks the end of the stabilization transforms appended to a compressed stream, after any of
+/// the other tables
+const STABILIZATION_MAGIC: [u8; 4] = *b"astb";
+
+/// The most Adus which may be compressing, or waiting to be written out, at once. Once this many
+/// are in flight, compressing another blocks until the writer thread catches up, so that a source
+/// which outpaces the compressor (e.g., with a small
+/// [`max_adu_events`](EncoderOptions::max_adu_events)) doesn't spawn threads and buffer Adus
+/// without bound.
+const MAX_ADUS_IN_FLIGHT: usize = 16;
+
+/// An entry in the time index of a compressed stream, locating a single Adu
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AduIndexEntry {
+    /// The start time of the Adu
+    pub start_t: AbsoluteT,
+
+    /// The byte offset of the Adu's sync marker (or its length prefix, if it has no marker),
+    /// relative to the end of the stream header
+    pub offset: u64,
 }
 
 /// Write compressed ADΔER data to a stream.
+///
+/// Each Adu stores its start time, and its events as delta times within it, so it natively has the
+/// layout of [`TimeMode::Mixed`](crate::TimeMode::Mixed). Events are ingested with absolute
+/// timestamps in both that mode and [`TimeMode::AbsoluteT`](crate::TimeMode::AbsoluteT).
 pub struct CompressedOutput<W: Write> {
     pub(crate) meta: CodecMetadata,
     pub(crate) adu: EventAdu,
@@ -27,6 +338,9 @@ pub struct CompressedOutput<W: Write> {
     pub(crate) options: EncoderOptions,
     // pub(crate) written_bytes_rx: std::sync::mpsc::Receiver<BytesMessage>,
     pub(crate) written_bytes_tx: Option<std::sync::mpsc::Sender<BytesMessage>>,
+    /// Holds a permit for each Adu in flight. The writer thread takes one back for each Adu it
+    /// writes out. See [`MAX_ADUS_IN_FLIGHT`].
+    adu_permits_tx: std::sync::mpsc::SyncSender<()>,
     // pub(crate) bytes_writer_queue: PriorityQueue<Vec<u8>, Reverse<u32>>,
     /// The ID of the last message sent from a spawned compressor thread
     pub(crate) last_message_sent: u32,
@@ -34,15 +348,79 @@ pub struct CompressedOutput<W: Write> {
     /// The ID of the last message received in the writer thread and actually written out the stream
     pub(crate) last_message_written: Arc<RwLock<u32>>,
 
+    /// The location of each Adu written out so far
+    pub(crate) time_index: Arc<RwLock<Vec<AduIndexEntry>>>,
+
+    /// The number of bytes of the Adus written out so far
+    pub(crate) adu_bytes_written: Arc<AtomicU64>,
+
+    /// The corrections of the stream's clock against the wall clock, at most one per Adu
+    pub(crate) clock_corrections: Vec<ClockCorrection>,
+
+    /// The changes of the source's reference interval, in time order
+    pub(crate) ref_interval_changes: Vec<RefIntervalChange>,
+
+    /// The changes of the shift applied to the input frames by a stabilizing transcoder, in time
+    /// order
+    pub(crate) stabilization_transforms: Vec<StabilizationTransform>,
+
+    /// Adjusts the maximum contrast threshold of each Adu to meet the target bitrate, if there is
+    /// one. Each compressor thread reports the size of its Adu back to it.
+    pub(crate) bitrate_controller: Option<Arc<RwLock<BitrateController>>>,
+
+    /// Receives the motion reference from the compressor thread of the last Adu, for the next one
+    /// to be predicted from, if motion compensation is enabled
+    pub(crate) motion_reference_rx: Option<MotionReferenceReceiver>,
+
+    /// The start time and description of the first Adu which failed its round-trip check, with
+    /// [`RoundTripCheck::Fail`]
+    pub(crate) round_trip_failure: Arc<RwLock<Option<(AbsoluteT, String)>>>,
+
+    /// The number of events ingested by the current Adu
+    adu_events: usize,
+
+    /// The start time of the last Adu which wasn't predicted from the one before it, and the
+    /// number of events compressed since then. See [`EncoderOptions::intra_refresh`].
+    intra_refresh_state: (AbsoluteT, u64),
+
+    /// Whether the next Adu must be coded without prediction, as requested by
+    /// [`WriteCompression::force_intra_adu`]
+    force_intra: bool,
+
     pub(crate) _phantom: std::marker::PhantomData<W>,
 }
 
+pub(crate) type MotionReferenceReceiver = std::sync::mpsc::Receiver<Option<Arc<MotionReference>>>;
+
 /// Read compressed ADΔER data from a stream.
 pub struct CompressedInput<R: Read> {
     pub(crate) meta: CodecMetadata,
 
     adu: Option<EventAdu>,
 
+    time_index: Option<Vec<AduIndexEntry>>,
+
+    /// Whether the stream has sync markers between its Adus
+    sync_markers: bool,
+
+    /// The end of the time range of the last Adu which was decoded intact
+    decoded_end_t: AbsoluteT,
+
+    /// Only the cubes intersecting this region are fully decoded, if it's set
+    roi: Option<Roi>,
+
+    /// Only the cubes of this channel are decoded, if it's set and the Adus are planar
+    channel: Option<u8>,
+
+    /// Only this many enhancement layers of each Adu are decoded, if it's set
+    max_layers: Option<u8>,
+
+    /// The priors which the stream was primed with, if any, and their id
+    context_priors: Option<(Arc<ContextPriors>, u32)>,
+
+    /// Gathers the residual symbols decoded from each Adu, if it's set
+    pub(crate) statistics: Option<Arc<Mutex<ContextPriors>>>,
+
     _phantom: std::marker::PhantomData<R>,
 }
 
@@ -74,27 +452,72 @@ fn flush_bytes_queue_worker<W: Write>(
     mut stream: Arc<RwLock<BitWriter<W, BigEndian>>>,
     written_bytes_rx: std::sync::mpsc::Receiver<BytesMessage>,
     last_message_written: Arc<RwLock<u32>>,
-    mut bytes_writer_queue: PriorityQueue<Vec<u8>, Reverse<u32>>,
+    time_index: Arc<RwLock<Vec<AduIndexEntry>>>,
+    adu_bytes_written: Arc<AtomicU64>,
+    adu_permits_rx: std::sync::mpsc::Receiver<()>,
+    mut bytes_writer_queue: PriorityQueue<QueuedAdu, Reverse<u32>>,
 ) {
     while let Ok(bytes_message) = written_bytes_rx.recv() {
         // Blocking recv
         // eprintln!("received message");
 
-        bytes_writer_queue.push(bytes_message.bytes, Reverse(bytes_message.message_id));
+        bytes_writer_queue.push(
+            (
+                bytes_message.start_t,
+                bytes_message.bytes,
+                bytes_message.checksum,
+                bytes_message.sync_marker,
+                bytes_message.delta_t_coding,
+            ),
+            Reverse(bytes_message.message_id),
+        );
 
         let mut last_message_written = last_message_written.write().unwrap();
-        while let Some((bytes, message_id)) = bytes_writer_queue.pop() {
+        while let Some(((start_t, bytes, checksum, sync_marker, delta_t_coding), message_id)) =
+            bytes_writer_queue.pop()
+        {
             if message_id == Reverse(*last_message_written + 1) {
                 let mut stream_write = stream.write().unwrap();
 
-                // Write the number of bytes in the compressed Adu as the 32-bit header for this Adu
-                stream_write
-                    .write_bytes(&(bytes.len() as u32).to_be_bytes())
-                    .unwrap();
+                // Write the sync marker and the Adu's start time, so that a decoder can find the
+                // Adu even if the data before it is damaged
+                if sync_marker {
+                    stream_write.write_bytes(&ADU_SYNC_MARKER).unwrap();
+                    stream_write.write_bytes(&start_t.to_be_bytes()).unwrap();
+                }
+
+                // Write the number of bytes in the compressed Adu, and how its Δt values are
+                // coded, as the 32-bit header for this Adu, followed by its checksum if there is
+                // one
+                let mut header_len = bytes.len() as u32 | delta_t_coding_bits(delta_t_coding);
+                if checksum.is_some() {
+                    header_len |= ADU_CHECKSUM_FLAG;
+                }
+                stream_write.write_bytes(&header_len.to_be_bytes()).unwrap();
+                if let Some(checksum) = checksum {
+                    stream_write.write_bytes(&checksum.to_be_bytes()).unwrap();
+                }
                 stream_write.write_bytes(&bytes).unwrap();
+
+                // Byte offset of the Adu, relative to the end of the header. A resumed stream
+                // starts from the offset of its checkpoint.
+                let offset = adu_bytes_written.load(Ordering::Relaxed);
+                time_index
+                    .write()
+                    .unwrap()
+                    .push(AduIndexEntry { start_t, offset });
+                let adu_len = 4
+                    + checksum.map_or(0, |_| 4)
+                    + if sync_marker { 8 } else { 0 }
+                    + bytes.len() as u64;
+                adu_bytes_written.store(offset + adu_len, Ordering::Relaxed);
                 *last_message_written += 1;
+                let _ = adu_permits_rx.try_recv();
             } else {
-                bytes_writer_queue.push(bytes, message_id); // message_id here is already Reversed
+                bytes_writer_queue.push(
+                    (start_t, bytes, checksum, sync_marker, delta_t_coding),
+                    message_id,
+                ); // message_id here is already Reversed
                 break;
             }
         }
@@ -104,9 +527,11 @@ fn flush_bytes_queue_worker<W: Write>(
 
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutput<W> {
     /// Create a new compressed output stream.
-    pub fn new(meta: CodecMetadata, writer: W) -> Self {
-        let adu = EventAdu::new(meta.plane, 0, meta.ref_interval, meta.adu_interval as usize);
+    pub fn new(mut meta: CodecMetadata, writer: W) -> Self {
+        meta.negotiate_coordinates();
+        let adu = new_adu(&meta, 0);
         let (written_bytes_tx, written_bytes_rx) = std::sync::mpsc::channel();
+        let (adu_permits_tx, adu_permits_rx) = std::sync::mpsc::sync_channel(MAX_ADUS_IN_FLIGHT);
 
         let stream_lock = RwLock::new(BitWriter::endian(writer, BigEndian));
         let stream_lock_arc = Arc::new(stream_lock);
@@ -115,11 +540,20 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutpu
         let last_message_written = Arc::new(RwLock::new(0));
         let last_message_written_clone = last_message_written.clone();
 
+        let time_index = Arc::new(RwLock::new(Vec::new()));
+        let time_index_clone = time_index.clone();
+
+        let adu_bytes_written = Arc::new(AtomicU64::new(0));
+        let adu_bytes_written_clone = adu_bytes_written.clone();
+
         std::thread::spawn(move || {
             flush_bytes_queue_worker(
                 stream_lock_arc_clone,
                 written_bytes_rx,
                 last_message_written_clone,
+                time_index_clone,
+                adu_bytes_written_clone,
+                adu_permits_rx,
                 PriorityQueue::new(),
             );
             eprintln!("Exiting writer thread...");
@@ -133,23 +567,338 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> CompressedOutpu
             options: EncoderOptions::default(meta.plane),
             // written_bytes_rx,
             written_bytes_tx: Some(written_bytes_tx),
+            adu_permits_tx,
             // bytes_writer_queue: PriorityQueue::new(),
             last_message_sent: 0,
             last_message_written,
+            time_index,
+            adu_bytes_written,
+            clock_corrections: Vec::new(),
+            ref_interval_changes: Vec::new(),
+            stabilization_transforms: Vec::new(),
+            bitrate_controller: None,
+            motion_reference_rx: None,
+            round_trip_failure: Default::default(),
+            adu_events: 0,
+            intra_refresh_state: (0, 0),
+            force_intra: false,
             _phantom: Default::default(),
         }
     }
 
     /// Keep the compressed encoder's option state synchronized with the high-level encoder container
     pub(crate) fn with_options(&mut self, options: EncoderOptions) {
+        // Older headers can't declare the layers
+        self.meta.enhancement_layers = if self.meta.codec_version >= 6 {
+            options.enhancement_layers.min(MAX_ENHANCEMENT_LAYERS)
+        } else {
+            0
+        };
+
+        // The Adus can only be resized before any events are ingested
+        if let Some(adu_interval) = options.adu_interval {
+            self.meta.adu_interval = adu_interval.max(1);
+            self.adu = new_adu(&self.meta, 0);
+        }
+
+        // Older headers can't declare the block size
+        if let Some(block_size) = options.block_size {
+            if self.meta.codec_version >= CONFIGURABLE_BLOCK_SIZE_VERSION
+                && BLOCK_SIZES.contains(&block_size)
+            {
+                self.meta.block_size = block_size;
+                self.adu = new_adu(&self.meta, 0);
+            }
+        }
+
+        // Older headers can't declare the quantization table
+        if let Some(table) = options.quantization_table {
+            if self.meta.codec_version >= QUANTIZATION_TABLE_VERSION {
+                self.meta.quantization_table = table;
+                self.adu.set_quantization_table(table);
+            }
+        }
+
+        // Older headers can't declare motion compensation
+        self.meta.motion_compensation = options.motion_compensation;
+        let motion_compensated = motion_compensated(&self.meta);
+        self.adu.set_motion_compensation(motion_compensated);
+        self.adu.keep_motion_reference = motion_compensated;
+
+        // Older headers can't declare the priors
+        self.meta.context_priors_id = match &options.context_priors {
+            Some(priors) if self.meta.codec_version >= CONTEXT_PRIORS_VERSION => priors.id(),
+            _ => 0,
+        };
+
+        self.bitrate_controller = match options.target_kbps {
+            Some(target_kbps) if self.meta.tps > 0 => {
+                let adu_seconds = f64::from(self.meta.ref_interval) * self.meta.adu_interval as f64
+                    / f64::from(self.meta.tps);
+                Some(Arc::new(RwLock::new(BitrateController::new(
+                    target_kbps,
+                    adu_seconds,
+                    options.crf.get_parameters().c_thresh_max,
+                ))))
+            }
+            _ => None,
+        };
+
         self.options = options;
     }
 
+    /// The maximum contrast threshold which the next Adu will be compressed with, if the bitrate
+    /// is controlled
+    pub fn bitrate_c_thresh_max(&self) -> Option<u8> {
+        self.bitrate_controller
+            .as_ref()
+            .map(|controller| controller.read().unwrap().c_thresh_max())
+    }
+
     /// Convenience function to get a mutable reference to the underlying stream.
     #[inline(always)]
     pub(crate) fn stream(&mut self) -> &mut Arc<RwLock<BitWriter<W, BigEndian>>> {
         self.stream.as_mut().unwrap()
     }
+
+    /// The last timestamp which fits within the current Adu's time range
+    #[inline(always)]
+    fn adu_end_t(&self) -> AbsoluteT {
+        self.adu.start_t + (self.adu.dt_ref * self.adu.num_intervals as DeltaT)
+    }
+
+    /// Check whether an event arrived after its Adu was already compressed. Such an event is
+    /// dropped under [`EventValidation::Reorder`], as one which arrived too late to be corrected,
+    /// and is otherwise an error. Returns whether the event should be ingested.
+    fn check_late_event(&self, event: &Event) -> Result<bool, CodecError> {
+        if event.t >= self.adu.start_t {
+            return Ok(true);
+        }
+        match self.options.event_validation {
+            EventValidation::Reorder { .. } => Ok(false),
+            EventValidation::None | EventValidation::Reject => Err(CodecError::LateEvent {
+                t: event.t,
+                adu_start_t: self.adu.start_t,
+            }),
+        }
+    }
+
+    /// Compress the current Adu, and move on to the Adu whose time range contains `t`. Sparse
+    /// streams (e.g., from a DVS camera) may have no events for several Adus, which are skipped
+    /// rather than written out empty.
+    fn advance_adu(&mut self, t: AbsoluteT) {
+        if !self.adu.skip_adu {
+            self.compress_adu();
+        }
+        if t > self.adu_end_t() {
+            let adu_len = (self.adu.dt_ref * self.adu.num_intervals as DeltaT).max(1);
+            self.adu.set_start_t((t - 1) / adu_len * adu_len);
+        }
+    }
+
+    /// Compress the current Adu early if it holds as many events as the options allow, and carry
+    /// on with another Adu over the same time range
+    fn limit_adu_events(&mut self) {
+        if self
+            .options
+            .max_adu_events
+            .is_some_and(|max_adu_events| self.adu_events >= max_adu_events)
+        {
+            let start_t = self.adu.start_t;
+            self.compress_adu();
+            self.adu.set_start_t(start_t);
+        }
+    }
+
+    /// Drop the motion reference of the last Adu if the refresh policy (or a forced intra Adu)
+    /// calls for the Adu starting at `start_t` to be coded without prediction, and count the
+    /// Adu's events towards the next refresh
+    fn intra_refresh(&mut self, start_t: AbsoluteT, adu_events: u64) {
+        let forced = std::mem::take(&mut self.force_intra);
+        let (refresh_t, refresh_events) = &mut self.intra_refresh_state;
+        let refresh = match self.options.intra_refresh {
+            IntraRefresh::Never => false,
+            IntraRefresh::Time(ticks) => start_t >= refresh_t.saturating_add(ticks),
+            IntraRefresh::Events(events) => *refresh_events >= events,
+        };
+        // The first Adu has no reference to be predicted from
+        if refresh || forced || self.motion_reference_rx.is_none() {
+            self.motion_reference_rx = None;
+            *refresh_t = start_t;
+            *refresh_events = 0;
+        }
+        *refresh_events += adu_events;
+    }
+
+    /// Compress the current Adu on a spawned thread, send its bytes to the writer thread, and
+    /// reset the Adu for the next time range.
+    fn compress_adu(&mut self) {
+        let adu_events = std::mem::take(&mut self.adu_events) as u64;
+        // self.flush_bytes_queue();
+        if self.stream.is_some() {
+            let c_thresh_max = self
+                .bitrate_c_thresh_max()
+                .unwrap_or(self.options.crf.get_parameters().c_thresh_max);
+
+            // Compress the Adu. This also writes the EOF symbol and flushes the encoder
+            // First, clone the ADU
+            let mut adu = self.adu.clone();
+            let tx = self.written_bytes_tx.as_ref().unwrap().clone();
+            // Spawn a thread to compress the ADU and write out the data
+
+            // Wait for a permit, once too many Adus are in flight. The writer thread only exits
+            // once the stream is closed, so this can't fail while it's open.
+            let _ = self.adu_permits_tx.send(());
+            let message_id_to_send = self.last_message_sent + 1;
+            self.last_message_sent += 1;
+            let start_t = self.adu.start_t;
+            let adu_checksum = self.options.adu_checksum;
+            let adu_sync_marker = self.options.adu_sync_markers;
+            let enhancement_layers = self.meta.enhancement_layers;
+            let quality_map = self.options.quality_map.clone();
+            let bitrate_controller = self.bitrate_controller.clone();
+            let round_trip_check = self.options.round_trip_check;
+            let round_trip_failure = self.round_trip_failure.clone();
+            let meta = self.meta;
+
+            // Earlier versions can't signal any coding but the predictive one
+            let delta_t_coding = if self.meta.codec_version >= DELTA_T_CODING_VERSION {
+                self.options.delta_t_coding
+            } else {
+                DeltaTCoding::Predictive
+            };
+            adu.set_delta_t_coding(delta_t_coding);
+            adu.set_channel_c_thresh_max(self.options.channel_c_thresh_max.unwrap_or([u8::MAX; 3]));
+            if self.meta.context_priors_id != 0 {
+                adu.context_priors = self.options.context_priors.clone();
+            }
+
+            // With motion compensation, each Adu is predicted from the one compressed before it,
+            // so its thread waits for that one's reference, and passes its own on to the next
+            adu.keep_motion_reference = motion_compensated(&self.meta);
+            if adu.keep_motion_reference {
+                self.intra_refresh(start_t, adu_events);
+            }
+            let motion_reference = adu.keep_motion_reference.then(|| {
+                let (tx, rx) = std::sync::mpsc::channel();
+                (self.motion_reference_rx.replace(rx), tx)
+            });
+
+            std::thread::spawn(move || {
+                let motion_reference_tx = motion_reference.map(|(rx, tx)| {
+                    adu.motion_reference = rx.and_then(|rx| rx.recv().ok()).flatten();
+                    tx
+                });
+
+                // Keep what's needed to decode the Adu again, since compressing it consumes its
+                // events and replaces its motion reference
+                let round_trip_source = (round_trip_check != RoundTripCheck::None).then(|| {
+                    (
+                        adu.pending_events(),
+                        adu.motion_reference.clone(),
+                        adu.context_priors.clone(),
+                    )
+                });
+
+                let written_data = compress_adu_record(
+                    &mut adu,
+                    c_thresh_max,
+                    quality_map.as_deref(),
+                    enhancement_layers,
+                );
+                if let Some(bitrate_controller) = bitrate_controller {
+                    bitrate_controller
+                        .write()
+                        .unwrap()
+                        .observe(written_data.len());
+                }
+                if let Some(tx) = motion_reference_tx {
+                    // The next Adu's thread may not exist, if this is the last Adu
+                    let _ = tx.send(adu.motion_reference.clone());
+                }
+
+                if let Some((source_events, motion_reference, context_priors)) = round_trip_source {
+                    let lossless = c_thresh_max == 0 && quality_map.is_none();
+                    let mismatch = match decode_adu_record(
+                        &meta,
+                        written_data.clone(),
+                        delta_t_coding,
+                        motion_reference,
+                        context_priors,
+                    ) {
+                        Ok(decoded) => round_trip_mismatch(source_events, decoded, lossless),
+                        Err(e) => Some(format!("decoding failed ({e})")),
+                    };
+                    if let Some(detail) = mismatch {
+                        report_round_trip_mismatch(
+                            round_trip_check,
+                            &round_trip_failure,
+                            start_t,
+                            detail,
+                        );
+                    }
+                }
+
+                tx.send(BytesMessage {
+                    message_id: message_id_to_send,
+                    start_t,
+                    checksum: adu_checksum.then(|| crc32(&written_data)),
+                    bytes: written_data,
+                    sync_marker: adu_sync_marker,
+                    delta_t_coding,
+                })
+                .unwrap();
+            });
+
+            self.adu.clear_compression();
+        }
+    }
+
+    /// Append the time index to the end of the stream: each index entry, the number of entries,
+    /// and a magic number.
+    fn write_time_index(&self, stream: &mut BitWriter<W, BigEndian>) -> std::io::Result<()> {
+        let time_index = self.time_index.read().unwrap();
+        for entry in time_index.iter() {
+            stream.write_bytes(&entry.start_t.to_be_bytes())?;
+            stream.write_bytes(&entry.offset.to_be_bytes())?;
+        }
+        stream.write_bytes(&(time_index.len() as u32).to_be_bytes())?;
+        stream.write_bytes(&TIME_INDEX_MAGIC)
+    }
+
+    /// Append the clock corrections to the end of the stream: each correction, the number of
+    /// corrections, and a magic number.
+    fn write_clock_corrections(&self, stream: &mut BitWriter<W, BigEndian>) -> std::io::Result<()> {
+        for correction in &self.clock_corrections {
+            stream.write_bytes(&correction.t.to_be_bytes())?;
+            stream.write_bytes(&correction.utc_ns.to_be_bytes())?;
+        }
+        stream.write_bytes(&(self.clock_corrections.len() as u32).to_be_bytes())?;
+        stream.write_bytes(&CLOCK_CORRECTIONS_MAGIC)
+    }
+
+    /// Append the reference interval changes to the end of the stream: each change, the number of
+    /// changes, and a magic number.
+    fn write_ref_intervals(&self, stream: &mut BitWriter<W, BigEndian>) -> std::io::Result<()> {
+        for change in &self.ref_interval_changes {
+            stream.write_bytes(&change.t.to_be_bytes())?;
+            stream.write_bytes(&change.ref_interval.to_be_bytes())?;
+        }
+        stream.write_bytes(&(self.ref_interval_changes.len() as u32).to_be_bytes())?;
+        stream.write_bytes(&REF_INTERVALS_MAGIC)
+    }
+
+    /// Append the stabilization transforms to the end of the stream: each transform, the number
+    /// of transforms, and a magic number.
+    fn write_stabilization(&self, stream: &mut BitWriter<W, BigEndian>) -> std::io::Result<()> {
+        for transform in &self.stabilization_transforms {
+            stream.write_bytes(&transform.t.to_be_bytes())?;
+            stream.write_bytes(&transform.dx.to_be_bytes())?;
+            stream.write_bytes(&transform.dy.to_be_bytes())?;
+        }
+        stream.write_bytes(&(self.stabilization_transforms.len() as u32).to_be_bytes())?;
+        stream.write_bytes(&STABILIZATION_MAGIC)
+    }
 }
 
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'static>
@@ -175,7 +924,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
         self.stream().write().unwrap().byte_align()
     }
 
-    fn into_writer(&mut self) -> Option<W> {
+    fn into_writer(&mut self) -> Result<Option<W>, CodecError> {
         if !self.adu.skip_adu {
             // while self.last_message_sent
             //     != self.last_message_written + self.bytes_writer_queue.len() as u32
@@ -201,26 +950,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
             //     }
 
             dbg!("compressing partial last adu");
-            let mut temp_stream = BitWriter::endian(Vec::new(), BigEndian);
-
-            let parameters = self.options.crf.get_parameters().clone();
-            let mut adu = self.adu.clone();
-            let tx = self.written_bytes_tx.as_ref().unwrap().clone();
-            // Spawn a thread to compress the ADU and write out the data
-
-            let message_id_to_send = self.last_message_sent + 1;
-            self.last_message_sent += 1;
-
-            std::thread::spawn(move || {
-                adu.compress(&mut temp_stream, parameters.c_thresh_max).ok();
-                let written_data = temp_stream.into_writer();
-
-                tx.send(BytesMessage {
-                    message_id: message_id_to_send,
-                    bytes: written_data,
-                })
-                .unwrap();
-            });
+            self.compress_adu();
             // }
         }
 
@@ -241,14 +971,37 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
                                       // error out from the receiver, because the communication channel is severed
 
         std::thread::sleep(std::time::Duration::from_secs(1)); // Wait for the thread to exit. TODO: Make this deterministic, wait on the thread handle
-        let arc = self.stream.take()?;
+        let Some(arc) = self.stream.take() else {
+            return Ok(None);
+        };
 
         let lock = Arc::into_inner(arc).unwrap();
         // let mut guard = tmp.write().unwrap();
-        let consumed_data = lock.into_inner().unwrap();
+        let mut consumed_data = lock.into_inner().unwrap();
+
+        // A zero-length Adu marks the end of the compressed data, if anything follows it
+        if self.options.time_index
+            || !self.clock_corrections.is_empty()
+            || !self.ref_interval_changes.is_empty()
+            || !self.stabilization_transforms.is_empty()
+        {
+            consumed_data.write_bytes(&0_u32.to_be_bytes())?;
+        }
+        if self.options.time_index {
+            self.write_time_index(&mut consumed_data)?;
+        }
+        if !self.clock_corrections.is_empty() {
+            self.write_clock_corrections(&mut consumed_data)?;
+        }
+        if !self.ref_interval_changes.is_empty() {
+            self.write_ref_intervals(&mut consumed_data)?;
+        }
+        if !self.stabilization_transforms.is_empty() {
+            self.write_stabilization(&mut consumed_data)?;
+        }
         // let new_writer = BitWriter::endian(Default::default(), BigEndian);
         // let old_writer = std::mem::replace(&mut *guard, new_writer);
-        Some(consumed_data.into_writer())
+        Ok(Some(consumed_data.into_writer()))
         //     let temp_writer = BitWriter::endian(W, BigEndian);
         //     let aa = std::mem::replace(&mut tmpp, temp_writer);
         //     tmpp.into_writer()
@@ -263,105 +1016,537 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static + 'static + 'sta
         self.stream().write().unwrap().flush()
     }
 
+    fn check_round_trip(&self) -> Result<(), CodecError> {
+        match &*self.round_trip_failure.read().unwrap() {
+            Some((start_t, detail)) => Err(CodecError::RoundTripMismatch {
+                start_t: *start_t,
+                detail: detail.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+        self.check_round_trip()?;
+        check_d(&event, self.adu.d_max)?;
+
         // Check that the event fits within the Adu's time range
-        if event.t > self.adu.start_t + (self.adu.dt_ref * self.adu.num_intervals as DeltaT) {
+        if event.t > self.adu_end_t() {
             // dbg!("compressing adu");
             // If it doesn't, compress the events and reset the Adu
+            self.advance_adu(event.t);
+        }
 
-            // self.flush_bytes_queue();
-            if self.stream.is_some() {
-                // if let Some((bytes, message_id)) = self.bytes_writer_queue.pop() {
-                //     if message_id == Reverse(self.last_message_written + 1) {
-                //         // Write the number of bytes in the compressed Adu as the 32-bit header for this Adu
-                //         stream
-                //             .write_bytes(&(bytes.len() as u32).to_be_bytes())
-                //             .unwrap();
-                //         stream.write_bytes(&bytes).unwrap();
-                //         self.last_message_written += 1;
-                //     } else {
-                //         self.bytes_writer_queue.push(bytes, message_id); // message_id here is already Reversed
-                //                                                          // break;
-                //     }
-                // }
-
-                // Create a temporary u8 stream to write the arithmetic-coded data to
-                let mut temp_stream = BitWriter::endian(Vec::new(), BigEndian);
-
-                let parameters = self.options.crf.get_parameters().clone();
-
-                // Compress the Adu. This also writes the EOF symbol and flushes the encoder
-                // First, clone the ADU
-                let mut adu = self.adu.clone();
-                let tx = self.written_bytes_tx.as_ref().unwrap().clone();
-                // Spawn a thread to compress the ADU and write out the data
-
-                let message_id_to_send = self.last_message_sent + 1;
-                self.last_message_sent += 1;
-
-                std::thread::spawn(move || {
-                    adu.compress(&mut temp_stream, parameters.c_thresh_max).ok();
-                    let written_data = temp_stream.into_writer();
-
-                    tx.send(BytesMessage {
-                        message_id: message_id_to_send,
-                        bytes: written_data,
-                    })
-                    .unwrap();
-                });
-
-                self.adu.clear_compression();
-            }
+        if !self.check_late_event(&event)? {
+            return Ok(());
         }
 
         // Ingest the event in the Adu
         let _ = self.adu.ingest_event(event);
+        self.adu_events += 1;
+        self.limit_adu_events();
 
         Ok(())
     }
-    // fn ingest_event_debug(&mut self, event: Event) -> Result<Option<Adu>, CodecError> {
-    //     if let (true, _) = self.frame.add_event(event, self.meta.delta_t_max)? {
-    //         let adu = self.compress_events()?;
-    //         self.frame.add_event(event, self.meta.delta_t_max)?;
-    //         return Ok(Some(adu));
-    //     };
-    //     Ok(None)
-    // }
-}
 
-impl<R: Read> CompressedInput<R> {
-    /// Create a new compressed input stream.
-    pub fn new(delta_t_max: DeltaT, ref_interval: DeltaT, adu_interval: usize) -> Self
-    where
-        Self: Sized,
-    {
-        Self {
-            meta: CodecMetadata {
-                codec_version: 0,
-                header_size: 0,
-                time_mode: Default::default(),
-                plane: Default::default(),
-                tps: 0,
-                ref_interval,
-                delta_t_max,
-                event_size: 0,
-                source_camera: Default::default(),
-                adu_interval,
-            },
-            adu: None,
-            _phantom: std::marker::PhantomData,
+    /// The current Adu is compressed as it is, and another one is started over the same time
+    /// range, as when an Adu reaches [`EncoderOptions::max_adu_events`]
+    fn force_intra_adu(&mut self) -> Result<(), CodecError> {
+        if !self.adu.skip_adu {
+            let start_t = self.adu.start_t;
+            self.compress_adu();
+            self.adu.set_start_t(start_t);
         }
+        self.force_intra = true;
+        Ok(())
     }
-}
 
-impl<R: Read + Seek> ReadCompression<R> for CompressedInput<R> {
-    fn magic(&self) -> Magic {
-        MAGIC_COMPRESSED
+    fn record_ref_interval(&mut self, change: RefIntervalChange) -> Result<(), CodecError> {
+        // Older decoders wouldn't recognize the table of changes
+        if self.meta.codec_version < REF_INTERVAL_CHANGES_VERSION {
+            return Err(CodecError::RefIntervalChangesUnsupported);
+        }
+        if let Some(last) = self.ref_interval_changes.last() {
+            // Changes must move forward in time
+            if change.t < last.t {
+                return Ok(());
+            }
+            // A later change at the same time replaces the earlier one
+            if change.t == last.t {
+                self.ref_interval_changes.pop();
+            }
+        }
+        let current = self
+            .ref_interval_changes
+            .last()
+            .map_or(self.meta.ref_interval, |last| last.ref_interval);
+        if change.ref_interval != current {
+            self.ref_interval_changes.push(change);
+        }
+        Ok(())
     }
 
-    fn meta(&self) -> &CodecMetadata {
-        &self.meta
-    }
+    fn record_stabilization(
+        &mut self,
+        transform: StabilizationTransform,
+    ) -> Result<(), CodecError> {
+        // Older decoders wouldn't recognize the table of transforms, so they're left out, as by
+        // the formats which can't carry them
+        if self.meta.codec_version < STABILIZATION_VERSION {
+            return Ok(());
+        }
+        if let Some(last) = self.stabilization_transforms.last() {
+            // Transforms must move forward in time
+            if transform.t < last.t {
+                return Ok(());
+            }
+            // A later transform at the same time replaces the earlier one
+            if transform.t == last.t {
+                self.stabilization_transforms.pop();
+            }
+        }
+        let current = self
+            .stabilization_transforms
+            .last()
+            .map_or((0, 0), |last| (last.dx, last.dy));
+        if (transform.dx, transform.dy) != current {
+            self.stabilization_transforms.push(transform);
+        }
+        Ok(())
+    }
+
+    /// The Adus are written out by a separate thread, so this lags behind the events ingested
+    fn bytes_written(&self) -> u64 {
+        self.meta.header_size as u64 + self.adu_bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// The current Adu is saved as its events, to be compressed once the stream is resumed
+    fn checkpoint(&mut self, checkpoint: &mut EncoderCheckpoint) -> Result<(), CodecError> {
+        // Wait for the Adus already compressed to be written out
+        while self.last_message_sent != *self.last_message_written.read().unwrap() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        self.flush_writer()?;
+
+        checkpoint.bytes_written = self.bytes_written();
+        checkpoint.pending_events = self.adu.pending_events();
+        checkpoint.adu_start_t = self.adu.start_t;
+        checkpoint.time_index = self
+            .time_index
+            .read()
+            .unwrap()
+            .iter()
+            .map(|entry| (entry.start_t, entry.offset))
+            .collect();
+        checkpoint.clock_corrections = self
+            .clock_corrections
+            .iter()
+            .map(|correction| (correction.t, correction.utc_ns))
+            .collect();
+        checkpoint.ref_interval_changes = self
+            .ref_interval_changes
+            .iter()
+            .map(|change| (change.t, change.ref_interval))
+            .collect();
+        checkpoint.stabilization_transforms = self
+            .stabilization_transforms
+            .iter()
+            .map(|transform| (transform.t, transform.dx, transform.dy))
+            .collect();
+        Ok(())
+    }
+
+    fn resume(&mut self, checkpoint: &EncoderCheckpoint) -> Result<(), CodecError> {
+        let adu_bytes_written = checkpoint
+            .bytes_written
+            .checked_sub(self.meta.header_size as u64)
+            .ok_or(CodecError::BadFile)?;
+        self.adu_bytes_written
+            .store(adu_bytes_written, Ordering::Relaxed);
+        *self.time_index.write().unwrap() = checkpoint
+            .time_index
+            .iter()
+            .map(|&(start_t, offset)| AduIndexEntry { start_t, offset })
+            .collect();
+        self.clock_corrections = checkpoint
+            .clock_corrections
+            .iter()
+            .map(|&(t, utc_ns)| ClockCorrection { t, utc_ns })
+            .collect();
+        self.ref_interval_changes = checkpoint
+            .ref_interval_changes
+            .iter()
+            .map(|&(t, ref_interval)| RefIntervalChange { t, ref_interval })
+            .collect();
+        self.stabilization_transforms = checkpoint
+            .stabilization_transforms
+            .iter()
+            .map(|&(t, dx, dy)| StabilizationTransform { t, dx, dy })
+            .collect();
+
+        self.adu.set_start_t(checkpoint.adu_start_t);
+        self.ingest_events(checkpoint.pending_events.clone())
+    }
+
+    /// Ingest a batch of events. Rather than filling the cubes one event at a time, the events
+    /// belonging to the current Adu are gathered up and then distributed to their cubes in
+    /// parallel. Whenever an event falls beyond the Adu's time range, the gathered events are
+    /// flushed to the cubes and the Adu is compressed, just like in `ingest_event`.
+    ///
+    /// The batch is first sorted by time, since a batch from a transcoder interleaves the events of
+    /// different pixels, and may straddle an Adu boundary.
+    fn ingest_events(&mut self, mut events: Vec<Event>) -> Result<(), CodecError> {
+        self.check_round_trip()?;
+        for event in &events {
+            check_d(event, self.adu.d_max)?;
+        }
+        events.sort_by_key(|event| event.t);
+        let mut batch = Vec::with_capacity(events.len());
+        for event in events {
+            if event.t > self.adu_end_t() {
+                self.adu.ingest_events(std::mem::take(&mut batch));
+                self.advance_adu(event.t);
+            }
+            if !self.check_late_event(&event)? {
+                continue;
+            }
+            batch.push(event);
+            if self
+                .options
+                .max_adu_events
+                .is_some_and(|max_adu_events| self.adu_events + batch.len() >= max_adu_events)
+            {
+                self.adu_events += batch.len();
+                self.adu.ingest_events(std::mem::take(&mut batch));
+                self.limit_adu_events();
+            }
+        }
+        self.adu_events += batch.len();
+        self.adu.ingest_events(batch);
+
+        Ok(())
+    }
+
+    fn record_clock(&mut self, correction: ClockCorrection) -> Result<(), CodecError> {
+        match self.clock_corrections.last_mut() {
+            // Corrections must move forward in time
+            Some(last) if correction.t <= last.t => {}
+            // Keep only the latest correction within each Adu
+            Some(last) if last.t >= self.adu.start_t => *last = correction,
+            _ => self.clock_corrections.push(correction),
+        }
+        Ok(())
+    }
+    // fn ingest_event_debug(&mut self, event: Event) -> Result<Option<Adu>, CodecError> {
+    //     if let (true, _) = self.frame.add_event(event, self.meta.delta_t_max)? {
+    //         let adu = self.compress_events()?;
+    //         self.frame.add_event(event, self.meta.delta_t_max)?;
+    //         return Ok(Some(adu));
+    //     };
+    //     Ok(None)
+    // }
+}
+
+impl<R: Read> CompressedInput<R> {
+    /// Create a new compressed input stream.
+    pub fn new(delta_t_max: DeltaT, ref_interval: DeltaT, adu_interval: usize) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            meta: CodecMetadata {
+                codec_version: 0,
+                header_size: 0,
+                time_mode: Default::default(),
+                plane: Default::default(),
+                tps: 0,
+                ref_interval,
+                delta_t_max,
+                event_size: 0,
+                source_camera: Default::default(),
+                adu_interval,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: DEFAULT_BLOCK_SIZE,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            adu: None,
+            time_index: None,
+            sync_markers: false,
+            decoded_end_t: 0,
+            roi: None,
+            channel: None,
+            max_layers: None,
+            context_priors: None,
+            statistics: None,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Decode a stream primed with the given priors. See
+    /// [`EncoderOptions::context_priors`](crate::codec::EncoderOptions::context_priors).
+    pub fn with_context_priors(mut self, priors: Arc<ContextPriors>) -> Self {
+        let id = priors.id();
+        self.context_priors = Some((priors, id));
+        self
+    }
+
+    /// Read the number of entries and the magic number at the end of a table appended to the
+    /// stream, which ends `end` bytes before the end of the stream
+    fn read_table_footer(
+        reader: &mut BitReader<R, BigEndian>,
+        end: i64,
+    ) -> Result<(u32, [u8; 4]), CodecError>
+    where
+        R: Seek,
+    {
+        // Note that `seek_bits` takes a positive offset back from the end of the stream
+        reader.seek_bits(SeekFrom::End((end + 8) * 8))?;
+        let mut buffer = [0u8; 4];
+        reader.read_bytes(&mut buffer)?;
+        let num_entries = u32::from_be_bytes(buffer);
+        reader.read_bytes(&mut buffer)?;
+        Ok((num_entries, buffer))
+    }
+
+    /// Find the table with the given magic number among those appended to the stream, which are
+    /// (in order, each optional) the time index, the clock corrections, the reference interval
+    /// changes, and the stabilization transforms. Returns the number of bytes the table starts
+    /// before the end of the stream, and its number of entries.
+    ///
+    /// The tables' sizes are read from the stream, so a table which doesn't fit in the stream
+    /// is rejected as corrupt.
+    fn find_table(
+        reader: &mut BitReader<R, BigEndian>,
+        table_magic: [u8; 4],
+    ) -> Result<Option<(i64, u32)>, CodecError>
+    where
+        R: Seek,
+    {
+        reader.seek_bits(SeekFrom::End(0))?;
+        let stream_len = (reader.position_in_bits()? / 8) as i64;
+        let mut end = 0;
+        loop {
+            // Too short to hold another table
+            if end + 8 > stream_len {
+                return Ok(None);
+            }
+            let (num_entries, magic) = Self::read_table_footer(reader, end)?;
+            let entry_size = match magic {
+                REF_INTERVALS_MAGIC => 8,
+                TIME_INDEX_MAGIC | CLOCK_CORRECTIONS_MAGIC | STABILIZATION_MAGIC => 12,
+                _ => return Ok(None),
+            };
+            let start = end + 8 + entry_size * i64::from(num_entries);
+            if start > stream_len {
+                return Err(CodecError::Deserialize);
+            }
+            if magic == table_magic {
+                return Ok(Some((start, num_entries)));
+            }
+
+            // The time index is the first table, so no other table comes before it
+            if magic == TIME_INDEX_MAGIC {
+                return Ok(None);
+            }
+            end = start;
+        }
+    }
+
+    /// Read the time index from the end of the stream, if it has one
+    pub(crate) fn read_time_index(
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<AduIndexEntry>, CodecError>
+    where
+        R: Seek,
+    {
+        let Some((start, num_entries)) = Self::find_table(reader, TIME_INDEX_MAGIC)? else {
+            return Err(CodecError::NoTimeIndex);
+        };
+
+        reader.seek_bits(SeekFrom::End(start * 8))?;
+        let mut time_index = Vec::with_capacity(num_entries as usize);
+        for _ in 0..num_entries {
+            let mut start_t = [0u8; 4];
+            let mut offset = [0u8; 8];
+            reader.read_bytes(&mut start_t)?;
+            reader.read_bytes(&mut offset)?;
+            time_index.push(AduIndexEntry {
+                start_t: AbsoluteT::from_be_bytes(start_t),
+                offset: u64::from_be_bytes(offset),
+            });
+        }
+        Ok(time_index)
+    }
+
+    /// Set the input stream position to the start of the Adu located by a time index entry, and
+    /// start decoding afresh from there
+    fn seek_to_entry(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        entry: AduIndexEntry,
+    ) -> Result<(), CodecError>
+    where
+        R: Seek,
+    {
+        let pos = self.meta.header_size as u64 + entry.offset;
+        if reader.seek_bits(SeekFrom::Start(pos * 8)).is_err() {
+            return Err(CodecError::Seek);
+        }
+
+        self.decoded_end_t = entry.start_t;
+        self.adu = Some(new_adu(&self.meta, entry.start_t));
+        Ok(())
+    }
+
+    /// Read the clock corrections from the end of the stream, if it has any
+    fn read_clock_corrections(
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<ClockCorrection>, CodecError>
+    where
+        R: Seek,
+    {
+        let Some((start, num_corrections)) = Self::find_table(reader, CLOCK_CORRECTIONS_MAGIC)?
+        else {
+            return Ok(Vec::new());
+        };
+
+        reader.seek_bits(SeekFrom::End(start * 8))?;
+        let mut corrections = Vec::with_capacity(num_corrections as usize);
+        for _ in 0..num_corrections {
+            let mut t = [0u8; 4];
+            let mut utc_ns = [0u8; 8];
+            reader.read_bytes(&mut t)?;
+            reader.read_bytes(&mut utc_ns)?;
+            corrections.push(ClockCorrection {
+                t: AbsoluteT::from_be_bytes(t),
+                utc_ns: u64::from_be_bytes(utc_ns),
+            });
+        }
+        Ok(corrections)
+    }
+
+    /// Read the reference interval changes from the end of the stream, if it has any
+    fn read_ref_intervals(
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<RefIntervalChange>, CodecError>
+    where
+        R: Seek,
+    {
+        let Some((start, num_changes)) = Self::find_table(reader, REF_INTERVALS_MAGIC)? else {
+            return Ok(Vec::new());
+        };
+
+        reader.seek_bits(SeekFrom::End(start * 8))?;
+        let mut changes = Vec::with_capacity(num_changes as usize);
+        for _ in 0..num_changes {
+            let mut t = [0u8; 4];
+            let mut ref_interval = [0u8; 4];
+            reader.read_bytes(&mut t)?;
+            reader.read_bytes(&mut ref_interval)?;
+            changes.push(RefIntervalChange {
+                t: AbsoluteT::from_be_bytes(t),
+                ref_interval: DeltaT::from_be_bytes(ref_interval),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Read the stabilization transforms from the end of the stream, if it has any
+    fn read_stabilization(
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<StabilizationTransform>, CodecError>
+    where
+        R: Seek,
+    {
+        let Some((start, num_transforms)) = Self::find_table(reader, STABILIZATION_MAGIC)? else {
+            return Ok(Vec::new());
+        };
+
+        reader.seek_bits(SeekFrom::End(start * 8))?;
+        let mut transforms = Vec::with_capacity(num_transforms as usize);
+        for _ in 0..num_transforms {
+            let mut t = [0u8; 4];
+            let mut dx = [0u8; 4];
+            let mut dy = [0u8; 4];
+            reader.read_bytes(&mut t)?;
+            reader.read_bytes(&mut dx)?;
+            reader.read_bytes(&mut dy)?;
+            transforms.push(StabilizationTransform {
+                t: AbsoluteT::from_be_bytes(t),
+                dx: i32::from_be_bytes(dx),
+                dy: i32::from_be_bytes(dy),
+            });
+        }
+        Ok(transforms)
+    }
+
+    /// Read the start time from the sync marker at the reader's position, if there is one. The
+    /// reader is left where it was.
+    fn peek_sync_marker(reader: &mut BitReader<R, BigEndian>) -> Option<AbsoluteT>
+    where
+        R: Seek,
+    {
+        let pos = reader.position_in_bits().ok()?;
+        let mut buffer = [0u8; 8];
+        let res = reader.read_bytes(&mut buffer);
+        reader.seek_bits(SeekFrom::Start(pos)).ok()?;
+        res.ok()?;
+        (buffer[..4] == ADU_SYNC_MARKER)
+            .then(|| AbsoluteT::from_be_bytes(buffer[4..].try_into().unwrap()))
+    }
+
+    /// Scan forward to the next sync marker, leaving the reader at it. Returns the start time of
+    /// the Adu it marks, or `None` if the stream ends first.
+    fn find_sync_marker(reader: &mut BitReader<R, BigEndian>) -> Option<AbsoluteT>
+    where
+        R: Seek,
+    {
+        let mut window = [0u8; 4];
+        loop {
+            window.rotate_left(1);
+            window[3] = reader.read::<u8>(8).ok()?;
+            if window == ADU_SYNC_MARKER {
+                let pos = reader.position_in_bits().ok()?;
+                reader.seek_bits(SeekFrom::Start(pos - 32)).ok()?;
+                return Self::peek_sync_marker(reader);
+            }
+        }
+    }
+
+    /// Check that the reader is at the start of an Adu, the end of the compressed data, or the end
+    /// of the stream. If it's not, the length of the Adu just read must have been corrupted.
+    fn at_adu_boundary(reader: &mut BitReader<R, BigEndian>) -> Result<bool, CodecError>
+    where
+        R: Seek,
+    {
+        let pos = reader.position_in_bits()?;
+        let mut buffer = [0u8; 4];
+        let at_boundary = match reader.read_bytes(&mut buffer) {
+            Ok(()) => buffer == ADU_SYNC_MARKER || buffer == [0; 4],
+            Err(_) => true,
+        };
+        reader.seek_bits(SeekFrom::Start(pos))?;
+        Ok(at_boundary)
+    }
+}
+
+impl<R: Read + Seek> ReadCompression<R> for CompressedInput<R> {
+    fn magic(&self) -> Magic {
+        MAGIC_COMPRESSED
+    }
+
+    fn meta(&self) -> &CodecMetadata {
+        &self.meta
+    }
 
     fn meta_mut(&mut self) -> &mut CodecMetadata {
         &mut self.meta
@@ -382,30 +1567,155 @@ impl<R: Read + Seek> ReadCompression<R> for CompressedInput<R> {
     #[allow(unused_variables)]
     fn digest_event(&mut self, reader: &mut BitReader<R, BigEndian>) -> Result<Event, CodecError> {
         if self.adu.is_none() {
-            self.adu = Some(EventAdu::new(
-                self.meta.plane,
-                0,
-                self.meta.ref_interval,
-                self.meta.adu_interval,
-            ));
+            self.adu = Some(new_adu(&self.meta, 0));
         }
 
         if let Some(adu) = &mut self.adu {
             if adu.decoder_is_empty() {
+                // The stream can only be decoded with the priors it was primed with
+                let priors_id = self.context_priors.as_ref().map_or(0, |(_, id)| *id);
+                if priors_id != self.meta.context_priors_id {
+                    return Err(CodecError::ContextPriorsMismatch {
+                        expected: self.meta.context_priors_id,
+                        found: priors_id,
+                    });
+                }
+
                 let start = std::time::Instant::now();
-                // Read the size of the Adu in bytes
+                // Read the Adu's sync marker, if the stream has them, and the size of the Adu in
+                // bytes
                 let mut buffer = [0u8; 4];
                 reader.read_bytes(&mut buffer)?;
-                let num_bytes = u32::from_be_bytes(buffer);
+                let marker_t = if buffer == ADU_SYNC_MARKER {
+                    self.sync_markers = true;
+                    reader.read_bytes(&mut buffer)?;
+                    let marker_t = AbsoluteT::from_be_bytes(buffer);
+                    if reader.read_bytes(&mut buffer).is_err() {
+                        return Err(CodecError::AduLost {
+                            start_t: marker_t,
+                            end_t: None,
+                        });
+                    }
+                    Some(marker_t)
+                } else if self.sync_markers && buffer != [0; 4] {
+                    // We're not at the start of an Adu, so the last Adu's length must have been
+                    // wrong. Skip ahead to the next one.
+                    return Err(CodecError::AduLost {
+                        start_t: self.decoded_end_t,
+                        end_t: Self::find_sync_marker(reader),
+                    });
+                } else {
+                    None
+                };
+                let header_len = u32::from_be_bytes(buffer);
+                let (num_bytes, delta_t_coding) =
+                    if self.meta.codec_version >= DELTA_T_CODING_VERSION {
+                        (
+                            header_len & ADU_LENGTH_MASK,
+                            delta_t_coding_from_bits(header_len),
+                        )
+                    } else {
+                        (
+                            header_len & !ADU_CHECKSUM_FLAG,
+                            Some(DeltaTCoding::Predictive),
+                        )
+                    };
+                if num_bytes == 0 {
+                    if let Some(start_t) = marker_t {
+                        // A marked Adu can't be empty, so its length was damaged
+                        return Err(CodecError::AduLost {
+                            start_t,
+                            end_t: Self::find_sync_marker(reader),
+                        });
+                    }
+                    // We've reached the time index at the end of the stream
+                    return Err(CodecError::Eof);
+                }
 
-                // Read the compressed Adu from the stream
-                let adu_bytes = reader.read_to_vec(num_bytes as usize)?;
+                // Read the Adu's checksum, if it has one, and the compressed Adu itself
+                let data_pos = reader.position_in_bits()?;
+                let read_adu = |reader: &mut BitReader<R, BigEndian>| {
+                    let checksum = if header_len & ADU_CHECKSUM_FLAG != 0 {
+                        let mut buffer = [0u8; 4];
+                        reader.read_bytes(&mut buffer)?;
+                        Some(u32::from_be_bytes(buffer))
+                    } else {
+                        None
+                    };
+                    Ok::<_, std::io::Error>((checksum, reader.read_to_vec(num_bytes as usize)?))
+                };
+                let (checksum, adu_bytes) = match (read_adu(reader), marker_t) {
+                    (Ok(record), None) => record,
+                    (Ok(record), Some(start_t)) => {
+                        // If the Adu's length was damaged, resume from the marker after it
+                        if !Self::at_adu_boundary(reader)? {
+                            reader.seek_bits(SeekFrom::Start(data_pos))?;
+                            return Err(CodecError::AduLost {
+                                start_t,
+                                end_t: Self::find_sync_marker(reader),
+                            });
+                        }
+                        record
+                    }
+                    (Err(e), None) => return Err(e.into()),
+                    (Err(_), Some(start_t)) => {
+                        // The stream is truncated
+                        return Err(CodecError::AduLost {
+                            start_t,
+                            end_t: None,
+                        });
+                    }
+                };
+
+                // A corrupted Adu is skipped. The next call will read the following Adu.
+                if let Some(expected) = checksum {
+                    let found = crc32(&adu_bytes);
+                    if found != expected {
+                        adu.skip_decompression();
+                        return Err(CodecError::ChecksumMismatch { expected, found });
+                    }
+                }
 
-                // Create a temporary u8 stream to read the arithmetic-coded data from
-                let mut adu_stream = BitReader::endian(Cursor::new(adu_bytes), BigEndian);
+                // Decompress the Adu, refined by as many of its enhancement layers as requested.
+                // If it's damaged, skip it, and report the time range which was lost.
+                let enhancement_layers = self.meta.enhancement_layers;
+                let res = delta_t_coding
+                    .zip(split_adu_record(
+                        adu_bytes,
+                        enhancement_layers,
+                        self.max_layers,
+                    ))
+                    .ok_or(CodecError::CorruptAdu)
+                    .and_then(|(delta_t_coding, layers)| {
+                        adu.set_delta_t_coding(delta_t_coding);
+                        adu.channel = self.channel.map(usize::from);
+                        adu.context_priors = self
+                            .context_priors
+                            .as_ref()
+                            .map(|(priors, _)| priors.clone());
+                        adu.statistics = self.statistics.clone();
 
-                // Decompress the Adu
-                adu.decompress(&mut adu_stream);
+                        // Create temporary u8 streams to read the arithmetic-coded data from
+                        let mut layer_streams: Vec<_> = layers
+                            .into_iter()
+                            .map(|layer| BitReader::endian(Cursor::new(layer), BigEndian))
+                            .collect();
+                        let (adu_stream, layer_streams) = layer_streams.split_first_mut().unwrap();
+                        adu.decompress_layered(
+                            adu_stream,
+                            layer_streams,
+                            enhancement_layers,
+                            self.roi.as_ref(),
+                        )
+                    });
+                if res.is_err() || marker_t.is_some_and(|start_t| start_t != adu.start_t) {
+                    adu.skip_decompression();
+                    return Err(CodecError::AduLost {
+                        start_t: marker_t.unwrap_or(self.decoded_end_t),
+                        end_t: Self::peek_sync_marker(reader),
+                    });
+                }
+                self.decoded_end_t = adu.start_t + adu.dt_ref * adu.num_intervals as DeltaT;
 
                 let duration = start.elapsed();
                 println!("Decompressed Adu in {:?} ns", duration.as_nanos());
@@ -440,13 +1750,135 @@ impl<R: Read + Seek> ReadCompression<R> for CompressedInput<R> {
         }
         Ok(())
     }
+
+    fn seek_to_time(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        t: AbsoluteT,
+    ) -> Result<AbsoluteT, CodecError> {
+        if self.time_index.is_none() {
+            self.time_index = Some(Self::read_time_index(reader)?);
+        }
+        let time_index = self.time_index.as_ref().unwrap();
+
+        // Find the last Adu which starts at or before the target time. If its time range was
+        // split across several Adus, start from the first of them.
+        let idx = time_index.partition_point(|entry| entry.start_t <= t);
+        let start_t = time_index[idx.saturating_sub(1)..]
+            .first()
+            .ok_or(CodecError::NoTimeIndex)?
+            .start_t;
+        let entry = time_index[time_index.partition_point(|entry| entry.start_t < start_t)];
+
+        self.seek_to_entry(reader, entry)?;
+        Ok(entry.start_t)
+    }
+
+    fn time_end_position(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        t: AbsoluteT,
+    ) -> Result<Option<u64>, CodecError> {
+        if self.time_index.is_none() {
+            let pos = reader.position_in_bits()?;
+            let time_index = Self::read_time_index(reader);
+            reader.seek_bits(SeekFrom::Start(pos))?;
+            match time_index {
+                Ok(time_index) => self.time_index = Some(time_index),
+                Err(CodecError::NoTimeIndex) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        let time_index = self.time_index.as_ref().unwrap();
+
+        // An Adu only holds events at or after its start time
+        let idx = time_index.partition_point(|entry| entry.start_t < t);
+        Ok(time_index
+            .get(idx)
+            .map(|entry| self.meta.header_size as u64 + entry.offset))
+    }
+
+    fn chunk_positions(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<u64>, CodecError> {
+        if self.time_index.is_none() {
+            self.time_index = Some(Self::read_time_index(reader)?);
+        }
+        let header_size = self.meta.header_size as u64;
+        Ok(self
+            .time_index
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|entry| header_size + entry.offset)
+            .collect())
+    }
+
+    fn set_roi(&mut self, roi: Option<Roi>) {
+        self.roi = roi;
+    }
+
+    fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel;
+    }
+
+    fn set_max_layers(&mut self, max_layers: Option<u8>) {
+        self.max_layers = max_layers;
+    }
+
+    fn clock_corrections(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<ClockCorrection>, CodecError> {
+        let pos = reader.position_in_bits()?;
+        let corrections = Self::read_clock_corrections(reader);
+        reader.seek_bits(SeekFrom::Start(pos))?;
+        corrections
+    }
+
+    fn ref_interval_changes(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<RefIntervalChange>, CodecError> {
+        let pos = reader.position_in_bits()?;
+        let changes = Self::read_ref_intervals(reader);
+        reader.seek_bits(SeekFrom::Start(pos))?;
+        changes
+    }
+
+    fn stabilization_transforms(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<StabilizationTransform>, CodecError> {
+        let pos = reader.position_in_bits()?;
+        let transforms = Self::read_stabilization(reader);
+        reader.seek_bits(SeekFrom::Start(pos))?;
+        transforms
+    }
+
+    fn seek_to_chunk(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        position: u64,
+    ) -> Result<(), CodecError> {
+        let offset = position
+            .checked_sub(self.meta.header_size as u64)
+            .ok_or(CodecError::Seek)?;
+        let entry = *self
+            .time_index
+            .as_ref()
+            .and_then(|time_index| time_index.iter().find(|entry| entry.offset == offset))
+            .ok_or(CodecError::Seek)?;
+        self.seek_to_entry(reader, entry)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::codec::compressed::stream::CompressedInput;
     use crate::codec::{CodecError, ReadCompression};
-    use crate::PlaneSize;
+    use crate::{PlaneSize, D_MAX};
     use bitstream_io::{BigEndian, BitReader};
     use std::cmp::min;
     use std::error::Error;
@@ -482,6 +1914,18 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             Cursor::new(Vec::new()),
         );
@@ -502,205 +1946,2169 @@ mod tests {
                     counter += 1;
                 }
             }
-        }
+        }
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+        assert!(!output.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_decompress_barely_full() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let start_t = 0;
+        let dt_ref = 255;
+        let num_intervals = 10;
+
+        // A random candidate pixel to check that its events match
+        let candidate_px_idx = (7, 12);
+        let mut input_px_events = Vec::new();
+        let mut output_px_events = Vec::new();
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 0,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane: PlaneSize {
+                    width: 16,
+                    height: 32,
+                    channels: 1,
+                },
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+
+        let mut counter = 0;
+        for y in 0..30 {
+            for x in 0..16 {
+                let event = Event {
+                    coord: Coord { x, y, c: None },
+                    t: min(280 + counter, start_t + dt_ref * num_intervals as u32),
+                    d: 7,
+                };
+                if y == candidate_px_idx.0 && x == candidate_px_idx.1 {
+                    input_px_events.push(event);
+                }
+                compressed_output.ingest_event(event).unwrap();
+                if 280 + counter > start_t + dt_ref * num_intervals as u32 {
+                    break;
+                } else {
+                    counter += 1;
+                }
+            }
+        }
+
+        // Ingest one more event which is in the next Adu time span
+        compressed_output
+            .ingest_event(Event {
+                coord: Coord {
+                    x: 0,
+                    y: 0,
+                    c: None,
+                },
+                t: start_t + dt_ref * num_intervals as u32 + 1,
+                d: 7,
+            })
+            .unwrap();
+        counter += 1;
+
+        // Sleep for 3 seconds to give the writer thread time to catch up
+        std::thread::sleep(std::time::Duration::from_secs(3));
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+        assert!(!output.is_empty());
+        dbg!(counter);
+        dbg!(output.len());
+        // Check that the size is less than the raw events
+        assert!((output.len() as u32) < counter * 9);
+
+        let mut compressed_input = CompressedInput::new(
+            dt_ref * num_intervals as u32,
+            dt_ref,
+            num_intervals as usize,
+        );
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+        for i in 0..counter - 1 {
+            let event = compressed_input.digest_event(&mut stream);
+            if event.is_err() {
+                dbg!(i);
+            }
+            let event = event.unwrap();
+            if event.coord.y == candidate_px_idx.0 && event.coord.x == candidate_px_idx.1 {
+                output_px_events.push(event);
+            }
+        }
+
+        assert_eq!(input_px_events, output_px_events);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_decompress_several() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        // A random candidate pixel to check that its events match
+        let candidate_px_idx = (7, 12);
+        let mut input_px_events = Vec::new();
+        let mut output_px_events = Vec::new();
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 0,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane: PlaneSize {
+                    width: 16,
+                    height: 32,
+                    channels: 1,
+                },
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+
+        let mut counter = 0;
+        for _ in 0..10 {
+            for y in 0..30 {
+                for x in 0..16 {
+                    let event = Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + counter,
+                        d: 7,
+                    };
+                    if y == candidate_px_idx.0 && x == candidate_px_idx.1 {
+                        input_px_events.push(event);
+                    }
+                    compressed_output.ingest_event(event)?;
+
+                    counter += 1;
+                }
+            }
+        }
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+        assert!(!output.is_empty());
+        // Check that the size is less than the raw events
+        assert!((output.len() as u32) < counter * 9);
+
+        let mut compressed_input = CompressedInput::new(
+            dt_ref * num_intervals as u32,
+            dt_ref,
+            num_intervals as usize,
+        );
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+        for _ in 0..counter - 1 {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(event) => {
+                    if event.coord.y == candidate_px_idx.0 && event.coord.x == candidate_px_idx.1 {
+                        output_px_events.push(event);
+                    }
+                }
+                Err(CodecError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+
+        assert!(input_px_events.len() >= output_px_events.len());
+        for i in 0..output_px_events.len() {
+            // Have some slack in the comparison of the T component, since there could be some slight loss here
+            let a = input_px_events[i].t - 5..input_px_events[i].t + 5;
+            let comp_t = output_px_events[i].t;
+            assert!(a.contains(&comp_t));
+            assert_eq!(input_px_events[i].d, output_px_events[i].d);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_to_time() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 0,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+        compressed_output.options.time_index = true;
+
+        let mut counter = 0;
+        for _ in 0..10 {
+            for y in 0..30 {
+                for x in 0..16 {
+                    compressed_output.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + counter,
+                        d: 7,
+                    })?;
+                    counter += 1;
+                }
+            }
+        }
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(
+            dt_ref * num_intervals as u32,
+            dt_ref,
+            num_intervals as usize,
+        );
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output.clone()), BigEndian);
+
+        // Decode the whole stream. The time index must not be mistaken for an Adu.
+        let mut all_events = Vec::new();
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(event) => all_events.push(event),
+                Err(CodecError::Eof) => break,
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+        assert!(!all_events.is_empty());
+
+        // Jump back into the middle of the stream
+        let target_t = 280 + counter / 2;
+        let resume_t = compressed_input.seek_to_time(&mut stream, target_t)?;
+        assert!(resume_t <= target_t);
+        assert!(resume_t > 0);
+
+        let mut seeked_events = Vec::new();
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(event) => seeked_events.push(event),
+                Err(CodecError::Eof) => break,
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+        assert!(!seeked_events.is_empty());
+        assert!(seeked_events.len() < all_events.len());
+        assert!(seeked_events.iter().all(|e| e.t >= resume_t));
+        assert!(all_events.ends_with(&seeked_events));
+
+        // A time index which claims more entries than fit in the stream is corrupt, and a stream
+        // too short to hold a table has no time index
+        let mut corrupt = output.clone();
+        let footer = corrupt.len() - 8;
+        corrupt[footer..footer + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let mut stream = BitReader::endian(Cursor::new(corrupt), BigEndian);
+        assert!(matches!(
+            CompressedInput::read_time_index(&mut stream),
+            Err(CodecError::Deserialize)
+        ));
+        let mut stream = BitReader::endian(Cursor::new(output[..4].to_vec()), BigEndian);
+        assert!(matches!(
+            CompressedInput::read_time_index(&mut stream),
+            Err(CodecError::NoTimeIndex)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clock_corrections() -> Result<(), Box<dyn Error>> {
+        use crate::codec::clock::ClockCorrection;
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+        let adu_len = dt_ref * num_intervals;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 0,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: adu_len,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+        compressed_output.options.time_index = true;
+
+        let mut expected = Vec::new();
+        for i in 0..6 {
+            for y in 0..30 {
+                for x in 0..16 {
+                    compressed_output.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 1 + adu_len * i + x,
+                        d: 7,
+                    })?;
+                }
+            }
+
+            // Two corrections per Adu, of which only the later is kept
+            for t in [adu_len * i + 10, adu_len * i + 20] {
+                let correction = ClockCorrection {
+                    t,
+                    utc_ns: 1_000_000 * u64::from(t),
+                };
+                compressed_output.record_clock(correction)?;
+            }
+            expected.push(ClockCorrection {
+                t: adu_len * i + 20,
+                utc_ns: 1_000_000 * u64::from(adu_len * i + 20),
+            });
+        }
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(adu_len, dt_ref, num_intervals as usize);
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+
+        assert_eq!(compressed_input.clock_corrections(&mut stream)?, expected);
+        assert_eq!(stream.position_in_bits()?, 0);
+
+        // The time index is still found in front of the corrections, and neither is mistaken
+        // for an Adu
+        let mut num_events = 0;
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(_) => num_events += 1,
+                Err(CodecError::Eof) => break,
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+        assert_eq!(num_events, 6 * 30 * 16);
+        assert_eq!(
+            compressed_input.seek_to_time(&mut stream, adu_len * 3 + 5)?,
+            adu_len * 3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ref_interval_changes() -> Result<(), Box<dyn Error>> {
+        use crate::codec::clock::{ClockCorrection, RefIntervalChange};
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::compressed::REF_INTERVAL_CHANGES_VERSION;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+        let adu_len = dt_ref * num_intervals;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: REF_INTERVAL_CHANGES_VERSION,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: adu_len,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+        compressed_output.options.time_index = true;
+        compressed_output.options.crf.override_c_thresh_max(0);
+
+        let change = |t, ref_interval| RefIntervalChange { t, ref_interval };
+        for i in 0..6 {
+            for y in 0..30 {
+                for x in 0..16 {
+                    compressed_output.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 1 + adu_len * i + x,
+                        d: 7,
+                    })?;
+                }
+            }
+            match i {
+                // The interval in effect is the stream's own, so it's not a change
+                0 => compressed_output.record_ref_interval(change(0, dt_ref))?,
+
+                // A later change at the same time replaces the earlier one
+                1 => {
+                    compressed_output.record_ref_interval(change(adu_len, dt_ref * 4))?;
+                    compressed_output.record_ref_interval(change(adu_len, dt_ref * 2))?;
+                }
+
+                // Repeats and changes back in time are dropped
+                2 => {
+                    compressed_output.record_ref_interval(change(adu_len * 2, dt_ref * 2))?;
+                    compressed_output.record_ref_interval(change(adu_len - 1, dt_ref * 3))?;
+                }
+                4 => compressed_output.record_ref_interval(change(adu_len * 4, dt_ref))?,
+                _ => {}
+            }
+        }
+        let correction = ClockCorrection {
+            t: adu_len,
+            utc_ns: 1_000_000_000,
+        };
+        compressed_output.record_clock(correction)?;
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(adu_len, dt_ref, num_intervals as usize);
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+
+        assert_eq!(
+            compressed_input.ref_interval_changes(&mut stream)?,
+            vec![change(adu_len, dt_ref * 2), change(adu_len * 4, dt_ref)]
+        );
+        assert_eq!(stream.position_in_bits()?, 0);
+
+        // The time index and the clock corrections are still found in front of the changes
+        assert_eq!(
+            compressed_input.clock_corrections(&mut stream)?,
+            vec![correction]
+        );
+        let mut num_events = 0;
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(_) => num_events += 1,
+                Err(CodecError::Eof) => break,
+                Err(e) => return Err(Box::new(e)),
+            }
+        }
+        assert_eq!(num_events, 6 * 30 * 16);
+        assert_eq!(
+            compressed_input.seek_to_time(&mut stream, adu_len * 3 + 5)?,
+            adu_len * 3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stabilization_transforms() -> Result<(), Box<dyn Error>> {
+        use crate::codec::clock::RefIntervalChange;
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::compressed::STABILIZATION_VERSION;
+        use crate::codec::stabilization::StabilizationTransform;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+        let adu_len = dt_ref * num_intervals;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: STABILIZATION_VERSION,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: adu_len,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+        compressed_output.options.time_index = true;
+
+        let transform = |t, dx, dy| StabilizationTransform { t, dx, dy };
+        for i in 0..4 {
+            for y in 0..30 {
+                for x in 0..16 {
+                    compressed_output.ingest_event(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 1 + adu_len * i + x,
+                        d: 7,
+                    })?;
+                }
+            }
+            match i {
+                // The input isn't shifted to begin with, so it's not a change
+                0 => compressed_output.record_stabilization(transform(0, 0, 0))?,
+
+                // A later transform at the same time replaces the earlier one
+                1 => {
+                    compressed_output.record_stabilization(transform(adu_len, 5, 5))?;
+                    compressed_output.record_stabilization(transform(adu_len, -3, 2))?;
+                }
+
+                // Repeats and transforms back in time are dropped
+                2 => {
+                    compressed_output.record_stabilization(transform(adu_len * 2, -3, 2))?;
+                    compressed_output.record_stabilization(transform(adu_len - 1, 1, 1))?;
+                    compressed_output.record_stabilization(transform(adu_len * 2 + 5, 0, 0))?;
+                }
+                _ => {}
+            }
+        }
+        let change = RefIntervalChange {
+            t: adu_len,
+            ref_interval: dt_ref * 2,
+        };
+        compressed_output.record_ref_interval(change)?;
+
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(adu_len, dt_ref, num_intervals as usize);
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+
+        assert_eq!(
+            compressed_input.stabilization_transforms(&mut stream)?,
+            vec![transform(adu_len, -3, 2), transform(adu_len * 2 + 5, 0, 0)]
+        );
+        assert_eq!(stream.position_in_bits()?, 0);
+
+        // The other tables are still found in front of the transforms
+        assert_eq!(
+            compressed_input.ref_interval_changes(&mut stream)?,
+            vec![change]
+        );
+        assert_eq!(
+            compressed_input.seek_to_time(&mut stream, adu_len * 2 + 5)?,
+            adu_len * 2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_crc32() {
+        use crate::codec::compressed::stream::crc32;
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_adu_checksum() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let encode = |adu_checksum: bool| -> Result<Vec<u8>, Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version: 0,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: dt_ref * num_intervals as u32,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
+                },
+                Cursor::new(Vec::new()),
+            );
+            // The time index marks the end of the compressed data
+            compressed_output.options.time_index = true;
+            compressed_output.options.adu_checksum = adu_checksum;
+
+            let mut counter = 0;
+            for _ in 0..10 {
+                for y in 0..30 {
+                    for x in 0..16 {
+                        compressed_output.ingest_event(Event {
+                            coord: Coord { x, y, c: None },
+                            t: 280 + counter,
+                            d: 7,
+                        })?;
+                        counter += 1;
+                    }
+                }
+            }
+            Ok(compressed_output.into_writer()?.unwrap().into_inner())
+        };
+
+        let decode = |output: Vec<u8>| {
+            let mut compressed_input = CompressedInput::new(
+                dt_ref * num_intervals as u32,
+                dt_ref,
+                num_intervals as usize,
+            );
+            compressed_input.meta.plane = plane;
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut events = Vec::new();
+            let mut mismatches = 0;
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => events.push(event),
+                    Err(CodecError::Eof) => break,
+                    Err(CodecError::ChecksumMismatch { .. }) => mismatches += 1,
+                    Err(e) => panic!("{e}"),
+                }
+            }
+            (events, mismatches)
+        };
+
+        // The checksums add 4 bytes to each Adu, and don't change the decoded events
+        let plain = encode(false)?;
+        let checked = encode(true)?;
+        assert!(checked.len() > plain.len());
+        assert_eq!((checked.len() - plain.len()) % 4, 0);
+        let (plain_events, _) = decode(plain);
+        let (checked_events, mismatches) = decode(checked.clone());
+        assert_eq!(mismatches, 0);
+        assert_eq!(checked_events, plain_events);
+
+        // Flip a bit in the first Adu's data, after its length and checksum. The corrupted Adu is
+        // reported and skipped, and the rest of the stream still decodes.
+        let mut corrupted = checked;
+        corrupted[8] ^= 0x10;
+        let (corrupted_events, mismatches) = decode(corrupted);
+        assert_eq!(mismatches, 1);
+        assert!(corrupted_events.len() < checked_events.len());
+        assert!(checked_events.ends_with(&corrupted_events));
+        Ok(())
+    }
+
+    #[test]
+    fn test_adu_sync_markers() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let encode = |adu_sync_markers: bool| -> Result<Vec<u8>, Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version: 0,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: dt_ref * num_intervals as u32,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
+                },
+                Cursor::new(Vec::new()),
+            );
+            compressed_output.options.adu_sync_markers = adu_sync_markers;
+
+            let mut counter = 0;
+            for _ in 0..10 {
+                for y in 0..30 {
+                    for x in 0..16 {
+                        compressed_output.ingest_event(Event {
+                            coord: Coord { x, y, c: None },
+                            t: 280 + counter,
+                            d: 7,
+                        })?;
+                        counter += 1;
+                    }
+                }
+            }
+            Ok(compressed_output.into_writer()?.unwrap().into_inner())
+        };
+
+        // Decode until the end of the stream, collecting the events and the lost time ranges
+        let decode = |output: Vec<u8>| {
+            let mut compressed_input = CompressedInput::new(
+                dt_ref * num_intervals as u32,
+                dt_ref,
+                num_intervals as usize,
+            );
+            compressed_input.meta.plane = plane;
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut events = Vec::new();
+            let mut lost = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => events.push(event),
+                    Err(CodecError::AduLost { start_t, end_t }) => lost.push((start_t, end_t)),
+                    Err(CodecError::IoError(_)) => break,
+                    Err(e) => panic!("{e}"),
+                }
+            }
+            (events, lost)
+        };
+
+        // The markers add 8 bytes to each Adu, and don't change the decoded events
+        let plain = encode(false)?;
+        let marked = encode(true)?;
+        assert_eq!(&marked[..4], &super::ADU_SYNC_MARKER);
+        assert!(marked.len() > plain.len());
+        assert_eq!((marked.len() - plain.len()) % 8, 0);
+        let (plain_events, _) = decode(plain);
+        let (marked_events, lost) = decode(marked.clone());
+        assert!(lost.is_empty());
+        assert_eq!(marked_events, plain_events);
+
+        // Damage the first Adu's length prefix. The decoder resynchronizes at the next marker, and
+        // reports the first Adu's time range as lost.
+        let mut damaged = marked.clone();
+        damaged[11] ^= 0x01;
+        let (damaged_events, lost) = decode(damaged);
+        assert_eq!(lost.len(), 1);
+        let (start_t, end_t) = lost[0];
+        assert_eq!(start_t, 0);
+        assert_eq!(end_t, Some(dt_ref * num_intervals as u32));
+        assert!(damaged_events.len() < marked_events.len());
+        assert!(marked_events.ends_with(&damaged_events));
+
+        // Truncate the stream partway through the last Adu. Everything before it still decodes.
+        let mut truncated = marked;
+        truncated.truncate(truncated.len() - 20);
+        let (truncated_events, lost) = decode(truncated);
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].1, None);
+        assert!(truncated_events.len() < marked_events.len());
+        assert!(marked_events.starts_with(&truncated_events));
+        Ok(())
+    }
+
+    #[test]
+    fn test_layered_adus() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::{EncoderOptions, WriteCompression, LATEST_CODEC_VERSION};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 30, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+        let enhancement_layers = 2;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: LATEST_CODEC_VERSION,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+        compressed_output.with_options(EncoderOptions {
+            enhancement_layers,
+            ..EncoderOptions::default(plane)
+        });
+        assert_eq!(
+            compressed_output.meta.enhancement_layers,
+            enhancement_layers
+        );
+
+        let mut events = Vec::new();
+        let mut counter = 0;
+        for _ in 0..10 {
+            for y in 0..30 {
+                for x in 0..16 {
+                    let event = Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + counter,
+                        d: 7,
+                    };
+                    compressed_output.ingest_event(event)?;
+                    events.push(event);
+                    counter += 1;
+                }
+            }
+        }
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        // Decode until the end of the stream, with at most the given number of enhancement layers
+        let decode = |max_layers: Option<u8>| {
+            let mut compressed_input = CompressedInput::new(
+                dt_ref * num_intervals as u32,
+                dt_ref,
+                num_intervals as usize,
+            );
+            compressed_input.meta.plane = plane;
+            compressed_input.meta.enhancement_layers = enhancement_layers;
+            compressed_input.set_max_layers(max_layers);
+            let mut stream = BitReader::endian(Cursor::new(output.clone()), BigEndian);
+            let mut decoded = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => decoded.push(event),
+                    Err(CodecError::IoError(_)) => break,
+                    Err(e) => panic!("{e}"),
+                }
+            }
+            decoded
+        };
+
+        // Decoding every layer restores the exact events
+        let full = decode(None);
+        let mut sorted = full.clone();
+        sorted.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+        assert_eq!(sorted, events);
+
+        // Each layer brings the timestamps closer to the exact ones, without changing the events
+        let error = |decoded: &[Event]| -> u64 {
+            assert_eq!(decoded.len(), full.len());
+            decoded
+                .iter()
+                .zip(full.iter())
+                .map(|(event, exact)| {
+                    assert_eq!((event.coord, event.d), (exact.coord, exact.d));
+                    u64::from(event.t.abs_diff(exact.t))
+                })
+                .sum()
+        };
+        let base_error = error(&decode(Some(0)));
+        let layer_1_error = error(&decode(Some(1)));
+        assert!(base_error > 0);
+        assert!(layer_1_error <= base_error);
+        assert_eq!(error(&decode(Some(2))), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quality_map() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::rate_controller::QualityMap;
+        use crate::codec::{EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, Roi, SourceCamera, TimeMode};
+        use std::io::Cursor;
+        use std::sync::Arc;
+
+        let plane = PlaneSize::new(32, 32, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 3,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+
+        // Encode the top-left cube losslessly, and everything else as lossily as possible
+        let roi = Roi::new(0, 0, 16, 16);
+        let mut quality_map = QualityMap::new(plane, u8::MAX);
+        quality_map.set_region(&roi, 0);
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(u8::MAX);
+        options.quality_map = Some(Arc::new(quality_map));
+        compressed_output.with_options(options);
+
+        // Give each pixel irregular intervals between its events
+        let mut events = Vec::new();
+        for k in 0..10 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 600 + (x * 7 + y * 13 + k * 31) % 500,
+                        d: 7,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+        for event in &events {
+            compressed_output.ingest_event(*event)?;
+        }
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(
+            dt_ref * num_intervals as u32,
+            dt_ref,
+            num_intervals as usize,
+        );
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut decoded = Vec::new();
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(event) => decoded.push(event),
+                Err(CodecError::IoError(_)) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        assert_eq!(decoded.len(), events.len());
+
+        // The events in the region are decoded exactly
+        let in_roi = |events: &[Event]| {
+            let mut in_roi: Vec<Event> = events
+                .iter()
+                .filter(|event| roi.contains(event.coord))
+                .copied()
+                .collect();
+            in_roi.sort_by_key(|event| (event.coord.y, event.coord.x, event.t));
+            in_roi
+        };
+        assert_eq!(in_roi(&decoded), in_roi(&events));
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_c_thresh_max() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::{EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(16, 16, 3)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 3,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+
+        // Encode the green channel losslessly, and the others as lossily as possible
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(u8::MAX);
+        options.channel_c_thresh_max = Some([u8::MAX, 0, u8::MAX]);
+        compressed_output.with_options(options);
+
+        // Give each pixel irregular intervals between its events
+        let mut events = Vec::new();
+        for k in 0..10 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    for c in 0..3 {
+                        events.push(Event {
+                            coord: Coord { x, y, c: Some(c) },
+                            t: 300 + k * 600 + (x * 7 + y * 13 + k * 31 + c as u32 * 17) % 500,
+                            d: 7,
+                        });
+                    }
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+        for event in &events {
+            compressed_output.ingest_event(*event)?;
+        }
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(
+            dt_ref * num_intervals as u32,
+            dt_ref,
+            num_intervals as usize,
+        );
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut decoded = Vec::new();
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(event) => decoded.push(event),
+                Err(CodecError::IoError(_)) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        assert_eq!(decoded.len(), events.len());
+
+        // The green channel's events are decoded exactly
+        let green = |events: &[Event]| {
+            let mut green: Vec<Event> = events
+                .iter()
+                .filter(|event| event.coord.c == Some(1))
+                .copied()
+                .collect();
+            green.sort_by_key(|event| (event.coord.y, event.coord.x, event.t));
+            green
+        };
+        assert_eq!(green(&decoded), green(&events));
+        Ok(())
+    }
+
+    #[test]
+    fn test_target_bitrate() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::{EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(32, 32, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 3,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+
+        // Each Adu spans 1/6 second, so 1 kbps leaves only about 20 bytes for each
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        options.target_kbps = Some(1);
+        compressed_output.with_options(options);
+        assert_eq!(compressed_output.bitrate_c_thresh_max(), Some(0));
+
+        let mut events = Vec::new();
+        for k in 0..10 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 600 + (x * 7 + y * 13 + k * 31) % 500,
+                        d: 7,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+        for event in &events {
+            compressed_output.ingest_event(*event)?;
+        }
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        // Every Adu overshot the target, so the encoder became lossier
+        assert!(compressed_output.bitrate_c_thresh_max().unwrap() > 0);
+
+        let mut compressed_input = CompressedInput::new(
+            dt_ref * num_intervals as u32,
+            dt_ref,
+            num_intervals as usize,
+        );
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut decoded = 0;
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(_) => decoded += 1,
+                Err(CodecError::IoError(_)) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        assert_eq!(decoded, events.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_adu_interval_option() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::{EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(32, 32, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut compressed_output = CompressedOutput::new(
+            crate::codec::CodecMetadata {
+                codec_version: 3,
+                header_size: 0,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: dt_ref,
+                delta_t_max: dt_ref * num_intervals as u32,
+                event_size: 0,
+                source_camera: SourceCamera::FramedU8,
+                adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            Cursor::new(Vec::new()),
+        );
+
+        // Shorten the Adus to 2 reference intervals
+        let mut options = EncoderOptions::default(plane);
+        options.adu_interval = Some(2);
+        compressed_output.with_options(options);
+        assert_eq!(compressed_output.meta.adu_interval, 2);
+        assert_eq!(compressed_output.adu.num_intervals, 2);
+
+        let mut events = Vec::new();
+        for k in 0..10 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 600 + (x * 7 + y * 13 + k * 31) % 500,
+                        d: 7,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+        for event in &events {
+            compressed_output.ingest_event(*event)?;
+        }
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+        let mut compressed_input = CompressedInput::new(dt_ref * num_intervals as u32, dt_ref, 2);
+        compressed_input.meta.plane = plane;
+        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut decoded = 0;
+        loop {
+            match compressed_input.digest_event(&mut stream) {
+                Ok(_) => decoded += 1,
+                Err(CodecError::IoError(_)) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        assert_eq!(decoded, events.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_adu_events() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::encoder::Encoder;
+        use crate::codec::testing::{decode_from_vec, decoder_from_vec, sorted_by_pixel};
+        use crate::codec::{CodecMetadata, EncoderOptions, LATEST_CODEC_VERSION};
+        use crate::{Coord, Event, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(32, 32, 1)?;
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        for k in 0..4 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 600 + (x * 7 + y * 13 + k * 31) % 500,
+                        d: 7,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+
+        let encode = |max_adu_events: Option<usize>, batched: bool| {
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.time_index = true;
+            options.max_adu_events = max_adu_events;
+            let mut encoder = Encoder::new_compressed(
+                CompressedOutput::new(meta, Cursor::new(Vec::new())),
+                options,
+            );
+            if batched {
+                encoder.ingest_events(&events)?;
+            } else {
+                for event in &events {
+                    encoder.ingest_event(*event)?;
+                }
+            }
+            Ok::<_, CodecError>(encoder.close_writer()?.unwrap().into_inner())
+        };
+
+        // Each Adu spans about two rounds of events, so capping it at 1000 events splits it
+        for batched in [false, true] {
+            let whole = encode(None, batched)?;
+            let split = encode(Some(1000), batched)?;
+            assert_ne!(split, whole);
+
+            // Seeking into a split time range starts from its first part
+            let (mut decoder, mut reader) = decoder_from_vec(split.clone())?;
+            let start_t = decoder.seek_to_time(&mut reader, 1000)?;
+            assert_eq!(start_t, 0);
+            let (mut decoder, mut reader) = decoder_from_vec(whole)?;
+            assert_eq!(decoder.seek_to_time(&mut reader, 1000)?, start_t);
+
+            let (_, decoded) = decode_from_vec(split)?;
+            assert_eq!(sorted_by_pixel(decoded), sorted_by_pixel(events.clone()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_late_event() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::encoder::Encoder;
+        use crate::codec::testing::decode_from_vec;
+        use crate::codec::{CodecMetadata, EncoderOptions, EventValidation, LATEST_CODEC_VERSION};
+        use crate::{Coord, Event, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(8, 8, 1)?;
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        };
+        let event = |x, t| Event {
+            coord: Coord { x, y: 0, c: None },
+            t,
+            d: 7,
+        };
+        // The last event belongs to the first Adu, which is compressed by the time it arrives
+        let events = [event(0, 100), event(1, 2000), event(2, 2100), event(3, 200)];
+
+        let new_encoder = |event_validation| {
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.event_validation = event_validation;
+            Encoder::new_compressed(
+                CompressedOutput::new(meta, Cursor::new(Vec::new())),
+                options,
+            )
+        };
+
+        for event_validation in [EventValidation::None, EventValidation::Reject] {
+            let mut encoder = new_encoder(event_validation);
+            for event in &events[..3] {
+                encoder.ingest_event(*event)?;
+            }
+            assert!(matches!(
+                encoder.ingest_event(events[3]),
+                Err(CodecError::LateEvent { t: 200, .. })
+            ));
+            let mut encoder = new_encoder(event_validation);
+            assert!(matches!(
+                encoder
+                    .ingest_events(&events[..3])
+                    .and(encoder.ingest_events(&events[3..])),
+                Err(CodecError::LateEvent { t: 200, .. })
+            ));
+        }
+
+        // Reordering drops it, as it's later than the reordering window can correct
+        let mut encoder = new_encoder(EventValidation::Reorder { window: 10 });
+        for event in events {
+            encoder.ingest_event(event)?;
+        }
+        let (_, decoded) = decode_from_vec(encoder.close_writer()?.unwrap().into_inner())?;
+        let mut decoded: Vec<_> = decoded.into_iter().map(|event| event.t).collect();
+        decoded.sort_unstable();
+        assert_eq!(decoded, [100, 2000, 2100]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_planar_channels() -> Result<(), Box<dyn Error>> {
+        use crate::codec::testing::{
+            decode_from_vec, decoder_from_vec, encode_to_vec, sorted_by_pixel,
+        };
+        use crate::codec::{
+            ChannelLayout, CodecMetadata, EncoderOptions, EncoderType, LATEST_CODEC_VERSION,
+        };
+        use crate::{Coord, Event, TimeMode};
+
+        let plane = PlaneSize::new(32, 32, 3)?;
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        for k in 0..4 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    for c in 0..3 {
+                        events.push(Event {
+                            coord: Coord { x, y, c: Some(c) },
+                            t: 300 + k * 600 + (x * 7 + y * 13 + u32::from(c) * 5 + k * 31) % 500,
+                            d: 5 + c,
+                        });
+                    }
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        options.motion_compensation = true;
+        let encode = |channel_layout: ChannelLayout| {
+            let meta = CodecMetadata {
+                channel_layout,
+                ..meta
+            };
+            encode_to_vec(EncoderType::Compressed, meta, options.clone(), &events)
+        };
+        let interleaved = encode(ChannelLayout::Interleaved)?;
+        let planar = encode(ChannelLayout::Planar)?;
+        assert_ne!(planar, interleaved);
+
+        let (decoded_meta, decoded) = decode_from_vec(planar.clone())?;
+        assert_eq!(decoded_meta.channel_layout, ChannelLayout::Planar);
+        assert_eq!(sorted_by_pixel(decoded), sorted_by_pixel(events.clone()));
+
+        // Only the green plane is decoded
+        let (mut decoder, reader) = decoder_from_vec(planar)?;
+        decoder.set_channel(Some(1));
+        let green = decoder.into_events(reader).collect::<Result<Vec<_>, _>>()?;
+        let expected: Vec<Event> = events
+            .iter()
+            .filter(|event| event.coord.c == Some(1))
+            .copied()
+            .collect();
+        assert_eq!(sorted_by_pixel(green), sorted_by_pixel(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_independent_cubes() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::compressed::INDEPENDENT_CUBES_VERSION;
+        use crate::codec::{EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(64, 48, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        let mut events = Vec::new();
+        for k in 0..10 {
+            for y in 0..48 {
+                for x in 0..64 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 600 + (x * 7 + y * 13 + k * 31) % 500,
+                        d: 7,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+
+        let roundtrip = |codec_version: u8| -> Result<Vec<Event>, Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: dt_ref * num_intervals as u32,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
+                },
+                Cursor::new(Vec::new()),
+            );
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            compressed_output.with_options(options);
+            for event in &events {
+                compressed_output.ingest_event(*event)?;
+            }
+            let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+            let mut compressed_input = CompressedInput::new(
+                dt_ref * num_intervals as u32,
+                dt_ref,
+                num_intervals as usize,
+            );
+            compressed_input.meta.plane = plane;
+            compressed_input.meta.codec_version = codec_version;
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut decoded = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => decoded.push(event),
+                    Err(CodecError::IoError(_)) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(decoded)
+        };
+
+        // Coding the cubes independently doesn't change which events are decoded
+        let shared = roundtrip(INDEPENDENT_CUBES_VERSION - 1)?;
+        let independent = roundtrip(INDEPENDENT_CUBES_VERSION)?;
+        assert_eq!(independent.len(), events.len());
+        assert_eq!(independent, shared);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delta_t_coding() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::compressed::DELTA_T_CODING_VERSION;
+        use crate::codec::{DeltaTCoding, EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(32, 32, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+
+        // Several events per pixel in each Adu, so that each pixel has a Δt sequence to transform
+        let mut events = Vec::new();
+        for k in 0..40 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 150 + (x * 7 + y * 13 + k * 31) % 100,
+                        d: 7 + (k % 3) as u8,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+
+        let roundtrip = |delta_t_coding: DeltaTCoding| -> Result<Vec<Event>, Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version: DELTA_T_CODING_VERSION,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: dt_ref * num_intervals as u32,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
+                },
+                Cursor::new(Vec::new()),
+            );
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.delta_t_coding = delta_t_coding;
+            compressed_output.with_options(options);
+            for event in &events {
+                compressed_output.ingest_event(*event)?;
+            }
+            let output = compressed_output.into_writer()?.unwrap().into_inner();
+
+            let mut compressed_input = CompressedInput::new(
+                dt_ref * num_intervals as u32,
+                dt_ref,
+                num_intervals as usize,
+            );
+            compressed_input.meta.plane = plane;
+            compressed_input.meta.codec_version = DELTA_T_CODING_VERSION;
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut decoded = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => decoded.push(event),
+                    Err(CodecError::IoError(_)) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            decoded.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+            Ok(decoded)
+        };
 
-        let output = compressed_output.into_writer().unwrap().into_inner();
-        assert!(!output.is_empty());
+        // Without quantization, the wavelet modes restore the timestamps exactly
+        let mut expected = events.clone();
+        expected.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+        for delta_t_coding in [DeltaTCoding::Haar, DeltaTCoding::Cdf53] {
+            assert_eq!(roundtrip(delta_t_coding)?, expected, "{delta_t_coding:?}");
+        }
         Ok(())
     }
 
     #[test]
-    fn test_compress_decompress_barely_full() -> Result<(), Box<dyn Error>> {
+    fn test_quantization_table() -> Result<(), Box<dyn Error>> {
         use crate::codec::compressed::stream::CompressedOutput;
-        use crate::codec::WriteCompression;
+        use crate::codec::compressed::QUANTIZATION_TABLE_VERSION;
+        use crate::codec::{DeltaTCoding, EncoderOptions, WriteCompression, QUANTIZATION_BANDS};
         use crate::Coord;
         use crate::{Event, SourceCamera, TimeMode};
         use std::io::Cursor;
 
-        let plane = PlaneSize::new(16, 30, 1)?;
-        let start_t = 0;
+        let plane = PlaneSize::new(32, 32, 1)?;
         let dt_ref = 255;
-        let num_intervals = 10;
+        let num_intervals = 5;
 
-        // A random candidate pixel to check that its events match
-        let candidate_px_idx = (7, 12);
-        let mut input_px_events = Vec::new();
-        let mut output_px_events = Vec::new();
+        // Several events per pixel in each Adu, so that each pixel has a Δt sequence to transform
+        let mut events = Vec::new();
+        for k in 0..40 {
+            for y in 0..32 {
+                for x in 0..32 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 300 + k * 150 + (x * 7 + y * 13 + k * 31) % 100,
+                        d: 7 + (k % 3) as u8,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
 
-        let mut compressed_output = CompressedOutput::new(
-            crate::codec::CodecMetadata {
-                codec_version: 0,
-                header_size: 0,
-                time_mode: TimeMode::AbsoluteT,
-                plane: PlaneSize {
-                    width: 16,
-                    height: 32,
-                    channels: 1,
+        type Table = [u8; QUANTIZATION_BANDS];
+        let roundtrip = |table: Option<Table>| -> Result<(Vec<Event>, usize), Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version: QUANTIZATION_TABLE_VERSION,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: dt_ref * num_intervals as u32,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
                 },
-                tps: 7650,
-                ref_interval: dt_ref,
-                delta_t_max: dt_ref * num_intervals,
-                event_size: 0,
-                source_camera: SourceCamera::FramedU8,
-                adu_interval: num_intervals as usize,
-            },
-            Cursor::new(Vec::new()),
-        );
+                Cursor::new(Vec::new()),
+            );
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.delta_t_coding = DeltaTCoding::Cdf53;
+            options.quantization_table = table;
+            compressed_output.with_options(options);
+            for event in &events {
+                compressed_output.ingest_event(*event)?;
+            }
+            let output = compressed_output.into_writer()?.unwrap().into_inner();
+            let len = output.len();
 
-        let mut counter = 0;
-        for y in 0..30 {
-            for x in 0..16 {
-                let event = Event {
-                    coord: Coord { x, y, c: None },
-                    t: min(280 + counter, start_t + dt_ref * num_intervals as u32),
-                    d: 7,
-                };
-                if y == candidate_px_idx.0 && x == candidate_px_idx.1 {
-                    input_px_events.push(event);
-                }
-                compressed_output.ingest_event(event).unwrap();
-                if 280 + counter > start_t + dt_ref * num_intervals as u32 {
-                    break;
-                } else {
-                    counter += 1;
+            // The decoder takes the table from the header, as it would from a real stream
+            let mut compressed_input = CompressedInput::new(
+                dt_ref * num_intervals as u32,
+                dt_ref,
+                num_intervals as usize,
+            );
+            compressed_input.meta.plane = plane;
+            compressed_input.meta.codec_version = QUANTIZATION_TABLE_VERSION;
+            compressed_input.meta.quantization_table = table.unwrap_or_default();
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut decoded = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => decoded.push(event),
+                    Err(CodecError::IoError(_)) => break,
+                    Err(e) => return Err(e.into()),
                 }
             }
+            decoded.sort_by_key(|event| (event.coord.y, event.coord.x, event.t));
+            Ok((decoded, len))
+        };
+
+        let mut expected = events.clone();
+        expected.sort_by_key(|event| (event.coord.y, event.coord.x, event.t));
+        let (lossless, lossless_len) = roundtrip(None)?;
+        assert_eq!(lossless, expected);
+
+        // Quantizing the finer bands costs some timing precision, but every event survives
+        let (quantized, quantized_len) = roundtrip(Some([0, 0, 1, 2, 3, 3, 3, 3]))?;
+        assert!(quantized_len < lossless_len);
+        assert_eq!(quantized.len(), expected.len());
+        assert_ne!(quantized, expected);
+        for (quantized, expected) in quantized.iter().zip(expected.iter()) {
+            assert_eq!(quantized.coord, expected.coord);
+            assert_eq!(quantized.d, expected.d);
+            assert!(quantized.t.abs_diff(expected.t) < 128);
         }
+        Ok(())
+    }
 
-        // Ingest one more event which is in the next Adu time span
-        compressed_output
-            .ingest_event(Event {
-                coord: Coord {
-                    x: 0,
-                    y: 0,
-                    c: None,
-                },
-                t: start_t + dt_ref * num_intervals as u32 + 1,
-                d: 7,
-            })
-            .unwrap();
-        counter += 1;
+    #[test]
+    fn test_motion_compensation() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::compressed::MOTION_COMPENSATION_VERSION;
+        use crate::codec::{EncoderOptions, WriteCompression};
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
 
-        // Sleep for 3 seconds to give the writer thread time to catch up
-        std::thread::sleep(std::time::Duration::from_secs(3));
+        let plane = PlaneSize::new(64, 32, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+        let adu_len = dt_ref * num_intervals;
 
-        let output = compressed_output.into_writer().unwrap().into_inner();
-        assert!(!output.is_empty());
-        dbg!(counter);
-        dbg!(output.len());
-        // Check that the size is less than the raw events
-        assert!((output.len() as u32) < counter * 9);
+        // A textured scene panning left by 2 pixels per Adu
+        let mut events = Vec::new();
+        for k in 0..20 {
+            for y in 0..32 {
+                for x in 0..64 {
+                    let u = x + 2 * k;
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: k * adu_len + 300 + (u * u * 7 + y * 13 + u * y * 5) % 100,
+                        d: 7 + ((u * 3 + y) % 4) as u8,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
 
-        let mut compressed_input = CompressedInput::new(
-            dt_ref * num_intervals as u32,
-            dt_ref,
-            num_intervals as usize,
-        );
-        compressed_input.meta.plane = plane;
-        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
-        for i in 0..counter - 1 {
-            let event = compressed_input.digest_event(&mut stream);
-            if event.is_err() {
-                dbg!(i);
+        let roundtrip = |motion_compensation: bool| -> Result<(Vec<Event>, usize), Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version: MOTION_COMPENSATION_VERSION,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: adu_len,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
+                },
+                Cursor::new(Vec::new()),
+            );
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.motion_compensation = motion_compensation;
+            compressed_output.with_options(options);
+            for event in &events {
+                compressed_output.ingest_event(*event)?;
             }
-            let event = event.unwrap();
-            if event.coord.y == candidate_px_idx.0 && event.coord.x == candidate_px_idx.1 {
-                output_px_events.push(event);
+            let output = compressed_output.into_writer()?.unwrap().into_inner();
+            let len = output.len();
+
+            let mut compressed_input =
+                CompressedInput::new(adu_len, dt_ref, num_intervals as usize);
+            compressed_input.meta.plane = plane;
+            compressed_input.meta.codec_version = MOTION_COMPENSATION_VERSION;
+            compressed_input.meta.motion_compensation = motion_compensation;
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut decoded = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => decoded.push(event),
+                    Err(CodecError::IoError(_)) => break,
+                    Err(e) => return Err(e.into()),
+                }
             }
-        }
+            decoded.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+            Ok((decoded, len))
+        };
 
-        assert_eq!(input_px_events, output_px_events);
+        let mut expected = events.clone();
+        expected.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+        let (static_decoded, static_len) = roundtrip(false)?;
+        let (motion_decoded, motion_len) = roundtrip(true)?;
+        assert_eq!(static_decoded, expected);
+        assert_eq!(motion_decoded, expected);
+        assert!(motion_len < static_len);
         Ok(())
     }
 
     #[test]
-    fn test_compress_decompress_several() -> Result<(), Box<dyn Error>> {
+    fn test_intra_refresh() -> Result<(), Box<dyn Error>> {
         use crate::codec::compressed::stream::CompressedOutput;
-        use crate::codec::WriteCompression;
+        use crate::codec::compressed::MOTION_COMPENSATION_VERSION;
+        use crate::codec::{EncoderOptions, IntraRefresh, WriteCompression};
         use crate::Coord;
-        use crate::{Event, SourceCamera, TimeMode};
+        use crate::{AbsoluteT, Event, SourceCamera, TimeMode};
         use std::io::Cursor;
 
-        let plane = PlaneSize::new(16, 30, 1)?;
+        let plane = PlaneSize::new(64, 32, 1)?;
         let dt_ref = 255;
         let num_intervals = 5;
+        let adu_len = dt_ref * num_intervals;
 
-        // A random candidate pixel to check that its events match
-        let candidate_px_idx = (7, 12);
-        let mut input_px_events = Vec::new();
-        let mut output_px_events = Vec::new();
+        // A textured scene panning left by 2 pixels per Adu
+        let mut events = Vec::new();
+        for k in 0..20 {
+            for y in 0..32 {
+                for x in 0..64 {
+                    let u = x + 2 * k;
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: k * adu_len + 300 + (u * u * 7 + y * 13 + u * y * 5) % 100,
+                        d: 7 + ((u * 3 + y) % 4) as u8,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
 
-        let mut compressed_output = CompressedOutput::new(
-            crate::codec::CodecMetadata {
-                codec_version: 0,
-                header_size: 0,
-                time_mode: TimeMode::AbsoluteT,
-                plane: PlaneSize {
-                    width: 16,
-                    height: 32,
-                    channels: 1,
+        // Optionally force an intra Adu at the first event at or after `cut_t`, as at a scene cut
+        let encode = |intra_refresh: IntraRefresh,
+                      cut_t: Option<AbsoluteT>|
+         -> Result<Vec<u8>, Box<dyn Error>> {
+            let mut compressed_output = CompressedOutput::new(
+                crate::codec::CodecMetadata {
+                    codec_version: MOTION_COMPENSATION_VERSION,
+                    header_size: 0,
+                    time_mode: TimeMode::AbsoluteT,
+                    plane,
+                    tps: 7650,
+                    ref_interval: dt_ref,
+                    delta_t_max: adu_len,
+                    event_size: 0,
+                    source_camera: SourceCamera::FramedU8,
+                    adu_interval: num_intervals as usize,
+                    chroma_subsampling: Default::default(),
+                    epoch: None,
+                    enhancement_layers: 0,
+                    wide_coordinates: false,
+                    block_size: 16,
+                    d_max: D_MAX,
+                    channel_layout: Default::default(),
+                    context_priors_id: 0,
+                    quantization_table: Default::default(),
+                    intensity_peak: 1.0,
+                    color_space: Default::default(),
+                    motion_compensation: false,
                 },
-                tps: 7650,
-                ref_interval: dt_ref,
-                delta_t_max: dt_ref * num_intervals as u32,
-                event_size: 0,
-                source_camera: SourceCamera::FramedU8,
-                adu_interval: num_intervals as usize,
-            },
-            Cursor::new(Vec::new()),
-        );
-
-        let mut counter = 0;
-        for _ in 0..10 {
-            for y in 0..30 {
-                for x in 0..16 {
-                    let event = Event {
-                        coord: Coord { x, y, c: None },
-                        t: 280 + counter,
-                        d: 7,
-                    };
-                    if y == candidate_px_idx.0 && x == candidate_px_idx.1 {
-                        input_px_events.push(event);
-                    }
-                    compressed_output.ingest_event(event)?;
+                Cursor::new(Vec::new()),
+            );
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.motion_compensation = true;
+            options.time_index = true;
+            options.intra_refresh = intra_refresh;
+            compressed_output.with_options(options);
+            let mut cut_t = cut_t;
+            for event in &events {
+                if cut_t.is_some_and(|cut_t| event.t >= cut_t) {
+                    compressed_output.force_intra_adu()?;
+                    cut_t = None;
+                }
+                compressed_output.ingest_event(*event)?;
+            }
+            Ok(compressed_output.into_writer()?.unwrap().into_inner())
+        };
 
-                    counter += 1;
+        // Join the stream at the 11th Adu, as a late viewer of a live stream would
+        let join = |output: Vec<u8>| -> Result<Vec<Event>, CodecError> {
+            let mut compressed_input =
+                CompressedInput::new(adu_len, dt_ref, num_intervals as usize);
+            compressed_input.meta.plane = plane;
+            compressed_input.meta.codec_version = MOTION_COMPENSATION_VERSION;
+            compressed_input.meta.motion_compensation = true;
+            let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
+            compressed_input.seek_to_time(&mut stream, 10 * adu_len)?;
+            let mut decoded = Vec::new();
+            loop {
+                match compressed_input.digest_event(&mut stream) {
+                    Ok(event) => decoded.push(event),
+                    Err(CodecError::Eof | CodecError::IoError(_)) => break,
+                    Err(e) => return Err(e),
                 }
             }
+            decoded.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+            Ok(decoded)
+        };
+
+        let mut expected: Vec<Event> = events
+            .iter()
+            .filter(|event| event.t >= 10 * adu_len)
+            .copied()
+            .collect();
+        expected.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+
+        // Without refreshes, the 11th Adu is predicted from the one before it
+        let never = encode(IntraRefresh::Never, None)?;
+        assert!(matches!(
+            join(never.clone()),
+            Err(CodecError::AduLost { .. })
+        ));
+
+        // Both policies refresh every 5 Adus
+        for intra_refresh in [
+            IntraRefresh::Time(5 * adu_len),
+            IntraRefresh::Events(5 * 64 * 32),
+        ] {
+            let refreshed = encode(intra_refresh, None)?;
+            assert!(refreshed.len() > never.len());
+            assert_eq!(join(refreshed)?, expected);
         }
 
-        let output = compressed_output.into_writer().unwrap().into_inner();
-        assert!(!output.is_empty());
-        // Check that the size is less than the raw events
-        assert!((output.len() as u32) < counter * 9);
+        // A forced intra Adu at the 11th one can be joined, without any refresh policy
+        let forced = encode(IntraRefresh::Never, Some(10 * adu_len))?;
+        assert_eq!(join(forced)?, expected);
+        Ok(())
+    }
 
-        let mut compressed_input = CompressedInput::new(
-            dt_ref * num_intervals as u32,
-            dt_ref,
-            num_intervals as usize,
+    #[test]
+    fn test_motion_compensation_flag() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::MOTION_COMPENSATION_VERSION;
+        use crate::codec::testing::{decoder_from_vec, encode_to_vec, sorted_by_pixel};
+        use crate::codec::{CodecMetadata, EncoderOptions, EncoderType, LATEST_CODEC_VERSION};
+        use crate::{Coord, Event, TimeMode};
+
+        let plane = PlaneSize::new(64, 32, 1)?;
+        let adu_len = 255 * 5;
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: adu_len,
+            adu_interval: 5,
+            ..Default::default()
+        };
+
+        // The same panning scene as above
+        let mut events = Vec::new();
+        for k in 0..20 {
+            for y in 0..32 {
+                for x in 0..64 {
+                    let u = x + 2 * k;
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: k * adu_len + 300 + (u * u * 7 + y * 13 + u * y * 5) % 100,
+                        d: 7 + ((u * 3 + y) % 4) as u8,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+        let expected = sorted_by_pixel(
+            events
+                .iter()
+                .filter(|event| event.t >= 10 * adu_len)
+                .copied()
+                .collect(),
         );
-        compressed_input.meta.plane = plane;
-        let mut stream = BitReader::endian(Cursor::new(output), BigEndian);
-        for _ in 0..counter - 1 {
-            match compressed_input.digest_event(&mut stream) {
-                Ok(event) => {
-                    if event.coord.y == candidate_px_idx.0 && event.coord.x == candidate_px_idx.1 {
-                        output_px_events.push(event);
+
+        // Join the stream at the 11th Adu, with no intra refreshes
+        let join =
+            |codec_version: u8, motion_compensation: bool| -> Result<Vec<Event>, CodecError> {
+                let meta = CodecMetadata {
+                    codec_version,
+                    ..meta
+                };
+                let mut options = EncoderOptions::default(plane);
+                options.crf.override_c_thresh_max(0);
+                options.motion_compensation = motion_compensation;
+                options.time_index = true;
+                let output = encode_to_vec(EncoderType::Compressed, meta, options, &events)?;
+
+                let (mut decoder, mut reader) = decoder_from_vec(output)?;
+                assert_eq!(decoder.meta().motion_compensation, motion_compensation);
+                decoder.seek_to_time(&mut reader, 10 * adu_len)?;
+                let mut decoded = Vec::new();
+                loop {
+                    match decoder.digest_event(&mut reader) {
+                        Ok(event) => decoded.push(event),
+                        Err(CodecError::Eof | CodecError::IoError(_)) => break,
+                        Err(e) => return Err(e),
                     }
                 }
-                Err(CodecError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Ok(sorted_by_pixel(decoded))
+            };
 
-                Err(e) => return Err(Box::new(e)),
-            }
-        }
+        // The flag is in the header of every version with motion compensation
+        for codec_version in [MOTION_COMPENSATION_VERSION, LATEST_CODEC_VERSION] {
+            // Without motion compensation, no Adu depends on the one before it
+            assert_eq!(join(codec_version, false)?, expected);
 
-        assert!(input_px_events.len() >= output_px_events.len());
-        for i in 0..output_px_events.len() {
-            // Have some slack in the comparison of the T component, since there could be some slight loss here
-            let a = input_px_events[i].t - 5..input_px_events[i].t + 5;
-            let comp_t = output_px_events[i].t;
-            assert!(a.contains(&comp_t));
-            assert_eq!(input_px_events[i].d, output_px_events[i].d);
+            // With it, the 11th Adu is predicted from the one before it
+            assert!(matches!(
+                join(codec_version, true),
+                Err(CodecError::AduLost { .. })
+            ));
         }
         Ok(())
     }
@@ -734,6 +4142,18 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             Cursor::new(Vec::new()),
         );
@@ -790,7 +4210,7 @@ mod tests {
         // Sleep for 3 seconds to give the writer thread time to catch up
         std::thread::sleep(std::time::Duration::from_secs(3));
 
-        let output = compressed_output.into_writer().unwrap().into_inner();
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
         assert!(!output.is_empty());
         // Check that the size is less than the raw events
 
@@ -852,6 +4272,18 @@ mod tests {
                 event_size: 0,
                 source_camera: SourceCamera::FramedU8,
                 adu_interval: num_intervals as usize,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             Cursor::new(Vec::new()),
         );
@@ -914,7 +4346,7 @@ mod tests {
         // Sleep for 3 seconds to give the writer thread time to catch up
         std::thread::sleep(std::time::Duration::from_secs(10));
 
-        let output = compressed_output.into_writer().unwrap().into_inner();
+        let output = compressed_output.into_writer()?.unwrap().into_inner();
         assert!(!output.is_empty());
         // Check that the size is less than the raw events
         assert!((output.len() as u32) < counter * 9);
@@ -949,4 +4381,149 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_compress_bulk_matches_serial() -> Result<(), Box<dyn Error>> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::WriteCompression;
+        use crate::Coord;
+        use crate::{Event, SourceCamera, TimeMode};
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(40, 35, 1)?;
+        let dt_ref = 255;
+        let num_intervals = 5;
+        let meta = crate::codec::CodecMetadata {
+            codec_version: 0,
+            header_size: 0,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: dt_ref,
+            delta_t_max: dt_ref * num_intervals as u32,
+            event_size: 0,
+            source_camera: SourceCamera::FramedU8,
+            adu_interval: num_intervals as usize,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: 16,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
+        };
+
+        let mut events = Vec::new();
+        let mut counter = 0;
+        for _ in 0..6 {
+            for y in 0..35 {
+                for x in 0..40 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + counter,
+                        d: 7,
+                    });
+                    counter += 1;
+                }
+            }
+        }
+
+        let mut serial_output = CompressedOutput::new(meta, Cursor::new(Vec::new()));
+        for event in events.iter() {
+            serial_output.ingest_event(*event)?;
+        }
+        let serial_output = serial_output.into_writer()?.unwrap().into_inner();
+
+        let mut bulk_output = CompressedOutput::new(meta, Cursor::new(Vec::new()));
+        bulk_output.ingest_events(events)?;
+        let bulk_output = bulk_output.into_writer()?.unwrap().into_inner();
+
+        assert!(!bulk_output.is_empty());
+        assert_eq!(serial_output, bulk_output);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_check() -> Result<(), Box<dyn Error>> {
+        use super::{report_round_trip_mismatch, round_trip_mismatch};
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::encoder::Encoder;
+        use crate::codec::{CodecMetadata, EncoderOptions, RoundTripCheck, LATEST_CODEC_VERSION};
+        use crate::{Coord, Event, TimeMode};
+        use std::io::Cursor;
+        use std::sync::RwLock;
+
+        let plane = PlaneSize::new(40, 35, 1)?;
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        };
+        let mut events = Vec::new();
+        for k in 0..6 {
+            for y in 0..35 {
+                for x in 0..40 {
+                    events.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 280 + k * 700 + (x * 7 + y * 13) % 300,
+                        d: 7 + (x % 3) as u8,
+                    });
+                }
+            }
+        }
+        events.sort_by_key(|event| event.t);
+
+        // Every Adu decodes to its events, whether it's coded losslessly or not
+        for (c_thresh_max, check) in [
+            (0, RoundTripCheck::Fail),
+            (20, RoundTripCheck::Fail),
+            (0, RoundTripCheck::Log),
+        ] {
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(c_thresh_max);
+            options.motion_compensation = true;
+            options.round_trip_check = check;
+            let mut encoder = Encoder::new_compressed(
+                CompressedOutput::new(meta, Cursor::new(Vec::new())),
+                options,
+            );
+            encoder.ingest_events(&events)?;
+            assert!(!encoder.close_writer()?.unwrap().into_inner().is_empty());
+        }
+
+        // A mismatch is only kept to fail the encoder under Fail, and only the first one
+        let failure = RwLock::new(None);
+        report_round_trip_mismatch(RoundTripCheck::None, &failure, 0, "none".to_string());
+        report_round_trip_mismatch(RoundTripCheck::Log, &failure, 255, "log".to_string());
+        assert_eq!(*failure.read().unwrap(), None);
+        report_round_trip_mismatch(RoundTripCheck::Fail, &failure, 510, "first".to_string());
+        report_round_trip_mismatch(RoundTripCheck::Fail, &failure, 765, "second".to_string());
+        assert_eq!(*failure.read().unwrap(), Some((510, "first".to_string())));
+
+        let mut decoded = events.clone();
+        decoded.reverse();
+        assert_eq!(
+            round_trip_mismatch(events.clone(), decoded.clone(), true),
+            None
+        );
+        decoded[10].t += 1;
+        assert!(round_trip_mismatch(events.clone(), decoded.clone(), true).is_some());
+        assert_eq!(
+            round_trip_mismatch(events.clone(), decoded.clone(), false),
+            None
+        );
+        decoded.pop();
+        assert!(round_trip_mismatch(events, decoded, false).is_some());
+        Ok(())
+    }
 }
@@ -0,0 +1,205 @@
+use crate::codec::{DeltaTCoding, MAX_QUANTIZATION_SHIFT, QUANTIZATION_BANDS};
+
+/// Transform `signal` in place into its multi-level wavelet coefficients. Each level splits the
+/// low band of the level before it into a low band (at the front) and a high band (behind it),
+/// until the low band is a single coefficient. The transforms are integer-to-integer, so
+/// [`inverse`] restores the signal exactly.
+pub(crate) fn forward(coding: DeltaTCoding, signal: &mut [i64]) {
+    let mut len = signal.len();
+    while len >= 2 {
+        let band = &mut signal[..len];
+        match coding {
+            DeltaTCoding::Predictive => return,
+            DeltaTCoding::Haar => haar_forward(band),
+            DeltaTCoding::Cdf53 => cdf53_forward(band),
+        }
+        deinterleave(band);
+        len = (len + 1) / 2;
+    }
+}
+
+/// Invert [`forward`], transforming the wavelet coefficients back into the signal in place
+pub(crate) fn inverse(coding: DeltaTCoding, coefficients: &mut [i64]) {
+    let mut band_lens = Vec::new();
+    let mut len = coefficients.len();
+    while len >= 2 {
+        band_lens.push(len);
+        len = (len + 1) / 2;
+    }
+    for len in band_lens.into_iter().rev() {
+        let band = &mut coefficients[..len];
+        interleave(band);
+        match coding {
+            DeltaTCoding::Predictive => return,
+            DeltaTCoding::Haar => haar_inverse(band),
+            DeltaTCoding::Cdf53 => cdf53_inverse(band),
+        }
+    }
+}
+
+/// The frequency band of the coefficient at `i`, out of the `len` coefficients from [`forward`]:
+/// 0 for the low band, 1 for the coarsest high band, and so on
+pub(crate) fn band(len: usize, i: usize) -> usize {
+    // The high band of each level lies behind the low band which the next level splits. This is
+    // called for every coefficient, so the levels are walked without collecting them.
+    let mut levels = 0;
+    let mut high_level = None;
+    let mut band_len = len;
+    while band_len >= 2 {
+        let low_len = (band_len + 1) / 2;
+        if high_level.is_none() && (low_len..band_len).contains(&i) {
+            high_level = Some(levels);
+        }
+        levels += 1;
+        band_len = low_len;
+    }
+    high_level.map_or(0, |level| levels - level)
+}
+
+/// The bitshift which the coefficient at `i` of `len` is quantized by, according to `table`
+fn quantization_shift(table: &[u8; QUANTIZATION_BANDS], len: usize, i: usize) -> u32 {
+    let shift = table[band(len, i).min(QUANTIZATION_BANDS - 1)];
+    u32::from(shift.min(MAX_QUANTIZATION_SHIFT))
+}
+
+/// Quantize the coefficient at `i` of `len` according to `table`, rounding to the nearest step
+pub(crate) fn quantize(table: &[u8; QUANTIZATION_BANDS], len: usize, i: usize, c: i64) -> i64 {
+    match quantization_shift(table, len, i) {
+        0 => c,
+        shift => c.saturating_add(1 << (shift - 1)) >> shift,
+    }
+}
+
+/// Invert [`quantize`], up to its rounding
+pub(crate) fn dequantize(table: &[u8; QUANTIZATION_BANDS], len: usize, i: usize, q: i64) -> i64 {
+    q.wrapping_shl(quantization_shift(table, len, i))
+}
+
+/// Move the even-indexed samples (the low band) to the front, and the odd-indexed samples (the
+/// high band) behind them
+fn deinterleave(band: &mut [i64]) {
+    let lows: Vec<i64> = band.iter().step_by(2).copied().collect();
+    let highs: Vec<i64> = band.iter().skip(1).step_by(2).copied().collect();
+    band[..lows.len()].copy_from_slice(&lows);
+    band[lows.len()..].copy_from_slice(&highs);
+}
+
+/// Invert [`deinterleave`]
+fn interleave(band: &mut [i64]) {
+    let (lows, highs) = band.split_at((band.len() + 1) / 2);
+    let (lows, highs) = (lows.to_vec(), highs.to_vec());
+    for (i, low) in lows.into_iter().enumerate() {
+        band[2 * i] = low;
+    }
+    for (i, high) in highs.into_iter().enumerate() {
+        band[2 * i + 1] = high;
+    }
+}
+
+/// The integer Haar (S) transform of each pair of samples. A trailing unpaired sample is passed
+/// through to the low band.
+fn haar_forward(band: &mut [i64]) {
+    for pair in band.chunks_exact_mut(2) {
+        let high = pair[1].wrapping_sub(pair[0]);
+        pair[0] = pair[0].wrapping_add(high >> 1);
+        pair[1] = high;
+    }
+}
+
+fn haar_inverse(band: &mut [i64]) {
+    for pair in band.chunks_exact_mut(2) {
+        let low = pair[0].wrapping_sub(pair[1] >> 1);
+        pair[1] = pair[1].wrapping_add(low);
+        pair[0] = low;
+    }
+}
+
+/// The neighbors of the sample at `i`, with the signal mirrored at its ends
+fn neighbors(band: &[i64], i: usize) -> (i64, i64) {
+    let left = if i > 0 { band[i - 1] } else { band[i + 1] };
+    let right = if i + 1 < band.len() {
+        band[i + 1]
+    } else {
+        band[i - 1]
+    };
+    (left, right)
+}
+
+/// The reversible CDF 5/3 transform, by lifting: predict each odd sample from its even
+/// neighbors, and then update each even sample from its (predicted) odd neighbors
+fn cdf53_forward(band: &mut [i64]) {
+    for i in (1..band.len()).step_by(2) {
+        let (left, right) = neighbors(band, i);
+        band[i] = band[i].wrapping_sub(left.wrapping_add(right) >> 1);
+    }
+    for i in (0..band.len()).step_by(2) {
+        let (left, right) = neighbors(band, i);
+        band[i] = band[i].wrapping_add(left.wrapping_add(right).wrapping_add(2) >> 2);
+    }
+}
+
+fn cdf53_inverse(band: &mut [i64]) {
+    for i in (0..band.len()).step_by(2) {
+        let (left, right) = neighbors(band, i);
+        band[i] = band[i].wrapping_sub(left.wrapping_add(right).wrapping_add(2) >> 2);
+    }
+    for i in (1..band.len()).step_by(2) {
+        let (left, right) = neighbors(band, i);
+        band[i] = band[i].wrapping_add(left.wrapping_add(right) >> 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{band, dequantize, forward, inverse, quantize};
+    use crate::codec::{DeltaTCoding, QUANTIZATION_BANDS};
+
+    #[test]
+    fn test_wavelet_round_trip() {
+        for coding in [DeltaTCoding::Haar, DeltaTCoding::Cdf53] {
+            for len in 0..40 {
+                let signal: Vec<i64> = (0..len as i64)
+                    .map(|i| (i * 7919 + 13) % 1021 - 300)
+                    .collect();
+                let mut coefficients = signal.clone();
+                forward(coding, &mut coefficients);
+                inverse(coding, &mut coefficients);
+                assert_eq!(coefficients, signal, "{coding:?}, length {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_wavelet_compacts_smooth_signal() {
+        // A linear ramp of Δt values, as from a steadily brightening pixel
+        let signal: Vec<i64> = (0..16).map(|i| 100 + 3 * i).collect();
+
+        let mut coefficients = signal.clone();
+        forward(DeltaTCoding::Cdf53, &mut coefficients);
+
+        // Away from the mirrored end, a ramp is predicted exactly, so its details vanish
+        assert_eq!(&coefficients[8..15], &[0; 7]);
+        let energy = |values: &[i64]| values.iter().map(|v| v.abs()).sum::<i64>();
+        assert!(energy(&coefficients[1..]) < energy(&signal[1..]) / 10);
+    }
+
+    #[test]
+    fn test_quantization_bands() {
+        // 11 coefficients: the low band, then high bands of 1, 1, 3, and 5 coefficients
+        let bands: Vec<usize> = (0..11).map(|i| band(11, i)).collect();
+        assert_eq!(bands, [0, 1, 2, 3, 3, 3, 4, 4, 4, 4, 4]);
+        assert_eq!(band(1, 0), 0);
+
+        // Only the finest band is quantized, to the nearest multiple of 4
+        let mut table = [0; QUANTIZATION_BANDS];
+        table[4] = 2;
+        assert_eq!(quantize(&table, 11, 3, 7), 7);
+        assert_eq!(quantize(&table, 11, 8, 7), 2);
+        assert_eq!(quantize(&table, 11, 8, -7), -2);
+        assert_eq!(dequantize(&table, 11, 8, -2), -8);
+
+        // Finer bands than the table holds use its last band
+        table[QUANTIZATION_BANDS - 1] = 1;
+        assert_eq!(quantize(&table, 1 << 10, 1 << 9, 5), 3);
+    }
+}
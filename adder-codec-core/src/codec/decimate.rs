@@ -0,0 +1,140 @@
+use crate::{AbsoluteT, DeltaT, Event, PlaneSize, D, D_MAX, D_SHIFT_F64};
+
+/// Enforces a minimum Δt between the events of each pixel, so that consumers which can't keep up
+/// with a stream's full event rate get a lighter stream with coarser timing.
+///
+/// An event which comes less than `min_dt` after its pixel's last event is merged into the pixel's
+/// next event instead: its integration (2^D) is added to the pixel's, and the pixel fires with the
+/// largest D that its integration covers once `min_dt` has passed. The remainder carries over to
+/// its next event. Events without any integration (e.g., [`D_EMPTY`](crate::D_EMPTY) and
+/// [`D_ZERO_INTEGRATION`](crate::D_ZERO_INTEGRATION)) are dropped if they come too soon, or if the
+/// pixel has integration pending.
+///
+/// The events must have absolute timestamps.
+#[derive(Debug, Clone)]
+pub struct Decimator {
+    min_dt: DeltaT,
+    plane: PlaneSize,
+
+    /// The integration of each pixel since its last event, and the time of that event
+    pixels: Vec<(f64, AbsoluteT)>,
+}
+
+impl Decimator {
+    /// Create a decimator for events on `plane`. A `min_dt` of 0 leaves the events unchanged.
+    pub fn new(plane: PlaneSize, min_dt: DeltaT) -> Self {
+        Self {
+            min_dt,
+            plane,
+            pixels: vec![(0.0, 0); plane.volume()],
+        }
+    }
+
+    /// The minimum number of ticks between the events of a pixel
+    pub fn min_dt(&self) -> DeltaT {
+        self.min_dt
+    }
+
+    /// The dimensions of the events
+    pub fn plane(&self) -> PlaneSize {
+        self.plane
+    }
+
+    /// Merge an event into its pixel. Returns the pixel's event, if enough time has passed since
+    /// its last one.
+    pub fn ingest_event(&mut self, event: Event) -> Option<Event> {
+        if event.coord.x >= self.plane.w()
+            || event.coord.y >= self.plane.h()
+            || event.coord.c_usize() >= self.plane.c_usize()
+        {
+            return None;
+        }
+        let idx = (event.coord.y_usize() * self.plane.w_usize() + event.coord.x_usize())
+            * self.plane.c_usize()
+            + event.coord.c_usize();
+        let (integration, last_t) = &mut self.pixels[idx];
+        let too_soon = event.t.saturating_sub(*last_t) < self.min_dt;
+
+        if event.d > D_MAX {
+            if too_soon || *integration > 0.0 {
+                return None;
+            }
+            *last_t = event.t;
+            return Some(event);
+        }
+
+        *integration += D_SHIFT_F64[usize::from(event.d)];
+        if too_soon {
+            return None;
+        }
+
+        let d = (integration.log2().floor() as D).min(D_MAX);
+        *integration -= D_SHIFT_F64[usize::from(d)];
+        *last_t = event.t;
+        Some(Event { d, ..event })
+    }
+
+    /// Forget what each pixel has integrated, e.g., after seeking to another part of the stream
+    pub fn reset(&mut self) {
+        self.pixels.fill((0.0, 0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimator;
+    use crate::{Coord, Event, PlaneSize, D_EMPTY};
+
+    fn event(x: u32, y: u32, d: u8, t: u32) -> Event {
+        Event {
+            coord: Coord { x, y, c: None },
+            d,
+            t,
+        }
+    }
+
+    #[test]
+    fn test_decimate() {
+        let mut decimator = Decimator::new(PlaneSize::new(2, 2, 1).unwrap(), 100);
+
+        // Events far enough apart are unchanged
+        assert_eq!(
+            decimator.ingest_event(event(0, 0, 5, 100)),
+            Some(event(0, 0, 5, 100))
+        );
+        assert_eq!(
+            decimator.ingest_event(event(0, 0, 5, 250)),
+            Some(event(0, 0, 5, 250))
+        );
+
+        // Events which come too soon are merged into the next one
+        assert_eq!(decimator.ingest_event(event(0, 0, 5, 280)), None);
+        assert_eq!(decimator.ingest_event(event(0, 0, 5, 310)), None);
+        assert_eq!(
+            decimator.ingest_event(event(0, 0, 6, 350)),
+            Some(event(0, 0, 7, 350))
+        );
+
+        // The remainder carries over
+        assert_eq!(decimator.ingest_event(event(0, 0, 6, 400)), None);
+        assert_eq!(
+            decimator.ingest_event(event(0, 0, 4, 450)),
+            Some(event(0, 0, 6, 450))
+        );
+
+        // Pixels are independent
+        assert_eq!(
+            decimator.ingest_event(event(1, 0, 3, 120)),
+            Some(event(1, 0, 3, 120))
+        );
+
+        // Events without integration are dropped if they come too soon, or if integration is
+        // pending
+        assert_eq!(decimator.ingest_event(event(1, 1, D_EMPTY, 50)), None);
+        assert_eq!(
+            decimator.ingest_event(event(1, 1, D_EMPTY, 100)),
+            Some(event(1, 1, D_EMPTY, 100))
+        );
+        assert_eq!(decimator.ingest_event(event(0, 0, D_EMPTY, 600)), None);
+    }
+}
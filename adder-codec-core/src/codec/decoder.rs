@@ -1,30 +1,91 @@
-use crate::codec::{CodecError, CodecMetadata, EncoderType, ReadCompression, ReadCompressionEnum};
-use crate::SourceType::*;
-use crate::{Event, PlaneSize, SourceCamera, SourceType};
+use crate::codec::clock::{self, ClockCorrection, RefIntervalChange};
+use crate::codec::decimate::Decimator;
+use crate::codec::downsample::Downsampler;
+use crate::codec::reconstruct::FrameReconstructor;
+use crate::codec::stabilization::{self, StabilizationTransform};
+use crate::codec::validate::OrderValidator;
+use crate::codec::{
+    ChannelLayout, CodecError, CodecMetadata, EncoderType, ProgressHook, ProgressInfo,
+    ProgressTracker, ReadCompression, ReadCompressionEnum, UserMetadata, BLOCK_SIZES,
+    DEFAULT_BLOCK_SIZE, MAX_USER_METADATA_SIZE,
+};
+use crate::raw_event::ByteOrder;
+use crate::{AbsoluteT, DeltaT, Event, PixelAddress, PlaneSize, Roi, SourceType, TimeMode, D_MAX};
 
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
 #[cfg(feature = "compression")]
 use crate::codec::compressed::stream::CompressedInput;
+#[cfg(feature = "lz")]
+use crate::codec::header::MAGIC_LZ;
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::LzInput;
 
 use crate::codec::header::{
-    EventStreamHeader, EventStreamHeaderExtensionV1, EventStreamHeaderExtensionV2,
-    EventStreamHeaderExtensionV3, MAGIC_COMPRESSED,
+    EventStreamHeader, EventStreamHeaderExtensionV1, EventStreamHeaderExtensionV10,
+    EventStreamHeaderExtensionV11, EventStreamHeaderExtensionV12, EventStreamHeaderExtensionV13,
+    EventStreamHeaderExtensionV14, EventStreamHeaderExtensionV15, EventStreamHeaderExtensionV16,
+    EventStreamHeaderExtensionV17, EventStreamHeaderExtensionV18, EventStreamHeaderExtensionV19,
+    EventStreamHeaderExtensionV2, EventStreamHeaderExtensionV20, EventStreamHeaderExtensionV3,
+    EventStreamHeaderExtensionV4, EventStreamHeaderExtensionV5, EventStreamHeaderExtensionV6,
+    EventStreamHeaderExtensionV7, EventStreamHeaderExtensionV8, EventStreamHeaderExtensionV9,
+    Magic, MAGIC_COMPRESSED,
 };
 use crate::codec::raw::stream::RawInput;
 use crate::codec::CodecError::Deserialize;
 use bincode::config::{FixintEncoding, WithOtherEndian, WithOtherIntEncoding};
 use bincode::{DefaultOptions, Options};
 use bitstream_io::{BigEndian, BitRead, BitReader};
+use ndarray::Array3;
 use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+/// A window of the stream's timestamps to decode, set by [`Decoder::set_time_window`]
+#[derive(Debug, Copy, Clone)]
+struct TimeWindow {
+    start_t: AbsoluteT,
+    end_t: Option<AbsoluteT>,
+
+    /// The position of the first chunk past the window, if the stream's time index gives one
+    end_pos: Option<u64>,
+}
 
 /// Struct for decoding [`Event`]s from a stream
 pub struct Decoder<R: Read + Seek> {
     input: ReadCompressionEnum<R>,
-    bincode: WithOtherEndian<
-        WithOtherIntEncoding<DefaultOptions, FixintEncoding>,
-        bincode::config::BigEndian,
-    >,
+
+    /// The corrections of the stream's clock against the wall clock, once they've been read
+    clock_corrections: Vec<ClockCorrection>,
+
+    /// The changes of the reference interval, once they've been read
+    ref_interval_changes: Vec<RefIntervalChange>,
+
+    /// The shifts applied to the input frames by a stabilizing transcoder, once they've been read
+    stabilization_transforms: Vec<StabilizationTransform>,
+
+    /// The key-value metadata about the acquisition, decoded from the header
+    user_metadata: UserMetadata,
+
+    /// Only the events inside this region are returned, if it's set
+    roi: Option<Roi>,
+
+    /// Only the events of this channel are returned, if it's set
+    channel: Option<u8>,
+
+    /// Only the events inside this window are returned, if it's set
+    time_window: Option<TimeWindow>,
+
+    /// Merges the events of neighborhoods of pixels, if it's set
+    downsampler: Option<Downsampler>,
+
+    /// Merges the events of each pixel which come too close together, if a minimum Δt is set
+    decimator: Option<Decimator>,
+
+    /// Checks that each pixel's events are in time order, if it's set
+    validator: Option<OrderValidator>,
+
+    /// Reports the decoder's progress, if a hook is set
+    progress: Option<ProgressTracker>,
     _phantom: std::marker::PhantomData<R>,
 }
 
@@ -41,9 +102,17 @@ impl<R: Read + Seek> Decoder<R> {
     {
         let mut decoder = Self {
             input: ReadCompressionEnum::CompressedInput(compression),
-            bincode: DefaultOptions::new()
-                .with_fixint_encoding()
-                .with_big_endian(),
+            clock_corrections: Vec::new(),
+            ref_interval_changes: Vec::new(),
+            stabilization_transforms: Vec::new(),
+            user_metadata: UserMetadata::new(),
+            roi: None,
+            channel: None,
+            time_window: None,
+            downsampler: None,
+            decimator: None,
+            validator: None,
+            progress: None,
             _phantom: std::marker::PhantomData,
         };
         decoder.decode_header(reader)?;
@@ -60,9 +129,45 @@ impl<R: Read + Seek> Decoder<R> {
     {
         let mut decoder = Self {
             input: ReadCompressionEnum::RawInput(compression),
-            bincode: DefaultOptions::new()
-                .with_fixint_encoding()
-                .with_big_endian(),
+            clock_corrections: Vec::new(),
+            ref_interval_changes: Vec::new(),
+            stabilization_transforms: Vec::new(),
+            user_metadata: UserMetadata::new(),
+            roi: None,
+            channel: None,
+            time_window: None,
+            downsampler: None,
+            decimator: None,
+            validator: None,
+            progress: None,
+            _phantom: std::marker::PhantomData,
+        };
+        decoder.decode_header(reader)?;
+        Ok(decoder)
+    }
+
+    /// Create a new decoder with the given Zstandard compression scheme
+    #[cfg(feature = "lz")]
+    pub fn new_lz(
+        compression: LzInput<R>,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Self, CodecError>
+    where
+        Self: Sized,
+    {
+        let mut decoder = Self {
+            input: ReadCompressionEnum::LzInput(compression),
+            clock_corrections: Vec::new(),
+            ref_interval_changes: Vec::new(),
+            stabilization_transforms: Vec::new(),
+            user_metadata: UserMetadata::new(),
+            roi: None,
+            channel: None,
+            time_window: None,
+            downsampler: None,
+            decimator: None,
+            validator: None,
+            progress: None,
             _phantom: std::marker::PhantomData,
         };
         decoder.decode_header(reader)?;
@@ -75,6 +180,12 @@ impl<R: Read + Seek> Decoder<R> {
         self.input.meta()
     }
 
+    /// The byte order of the events in the stream, as negotiated from its magic number
+    #[inline]
+    pub fn byte_order(&self) -> ByteOrder {
+        self.input.byte_order()
+    }
+
     /// Returns a mutable reference to the metadata of the underlying compression scheme
     #[inline]
     pub fn meta_mut(&mut self) -> &mut CodecMetadata {
@@ -82,124 +193,284 @@ impl<R: Read + Seek> Decoder<R> {
     }
 
     /// Get the source data representation, based on the source camera
-    #[allow(clippy::match_same_arms)]
     pub fn get_source_type(&self) -> SourceType {
-        match self.input.meta().source_camera {
-            SourceCamera::FramedU8 => U8,
-            SourceCamera::FramedU16 => U16,
-            SourceCamera::FramedU32 => U32,
-            SourceCamera::FramedU64 => U64,
-            SourceCamera::FramedF32 => F32,
-            SourceCamera::FramedF64 => F64,
-            SourceCamera::Dvs => U8,
-            SourceCamera::DavisU8 => U8,
-            SourceCamera::Atis => U8,
-            SourceCamera::Asint => F64,
-        }
+        self.input.meta().source_camera.source_type()
     }
 
     /// Decode the header and its extensions
     fn decode_header(&mut self, reader: &mut BitReader<R, BigEndian>) -> Result<usize, CodecError> {
-        let header_size = bincode::serialized_size(&EventStreamHeader::default())?;
-        let mut buffer: Vec<u8> = vec![0; header_size as usize];
-        reader.read_bytes(&mut buffer)?;
+        let (magic, meta) = read_header(reader)?;
+        if !self.input.negotiate_magic(magic) {
+            return Err(CodecError::WrongMagic);
+        }
+        *self.input.meta_mut() = meta;
+        read_header_extension(reader, self.input.meta_mut(), &mut self.user_metadata)?;
+        Ok(self.input.meta().header_size)
+    }
 
-        let header = match self
-            .bincode
-            .deserialize_from::<_, EventStreamHeader>(&*buffer)
-        {
-            Ok(header) => header,
-            Err(_) => return Err(Deserialize),
-        };
+    /// The wall-clock time of tick 0, in UTC nanoseconds since the Unix epoch, if the stream
+    /// declares one
+    pub fn epoch(&self) -> Option<u64> {
+        self.input.meta().epoch
+    }
 
-        {
-            if header.magic != self.input.magic() {
-                return Err(CodecError::WrongMagic);
-            }
-            let meta = self.input.meta_mut();
-            *meta = CodecMetadata {
-                codec_version: header.version,
-                header_size: header_size as usize,
-                time_mode: Default::default(),
-                plane: PlaneSize::new(header.width, header.height, header.channels)?,
-                tps: header.tps,
-                ref_interval: header.ref_interval,
-                delta_t_max: header.delta_t_max,
-                event_size: header.event_size,
-                source_camera: Default::default(), // Gets filled by decoding the V2 header extension
-                adu_interval: Default::default(), // Gets filled by decoding the V3 header extension
-            };
+    /// Read the corrections of the stream's clock against the wall clock, which a live source may
+    /// have recorded to compensate for the drift of the sensor's clock. Once read, they're
+    /// applied by [`Decoder::t_to_utc_ns`] and [`Decoder::utc_ns_to_t`]. The reader is left
+    /// where it was.
+    pub fn read_clock_corrections(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<&[ClockCorrection], CodecError> {
+        self.clock_corrections = self.input.clock_corrections(reader)?;
+        Ok(&self.clock_corrections)
+    }
 
-            // Manual fix for malformed files with old software
-            if meta.event_size == 10 {
-                meta.event_size = 11;
-            }
+    /// Read the changes of the reference interval over which the source integrated its input,
+    /// which a motion-adaptive transcoder may have recorded. Once read, they're applied by
+    /// [`Decoder::ref_interval_at`]. The reader is left where it was.
+    pub fn read_ref_interval_changes(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<&[RefIntervalChange], CodecError> {
+        self.ref_interval_changes = self.input.ref_interval_changes(reader)?;
+        Ok(&self.ref_interval_changes)
+    }
+
+    /// The reference interval in effect at an absolute timestamp: the stream's `ref_interval`,
+    /// unless a change read by [`Decoder::read_ref_interval_changes`] applies
+    pub fn ref_interval_at(&self, t: AbsoluteT) -> DeltaT {
+        clock::ref_interval_at(
+            &self.ref_interval_changes,
+            self.input.meta().ref_interval,
+            t,
+        )
+    }
+
+    /// Read the shifts which a stabilizing transcoder applied to the input frames to cancel out
+    /// the camera's global motion. Once read, they're applied by [`Decoder::stabilization_at`].
+    /// The reader is left where it was.
+    pub fn read_stabilization_transforms(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<&[StabilizationTransform], CodecError> {
+        self.stabilization_transforms = self.input.stabilization_transforms(reader)?;
+        Ok(&self.stabilization_transforms)
+    }
+
+    /// The shift applied to the input frames at an absolute timestamp, as (`dx`, `dy`) pixels.
+    /// Shifting a reconstructed frame by the opposite amount un-warps it. It's (0, 0) unless a
+    /// transform read by [`Decoder::read_stabilization_transforms`] applies.
+    pub fn stabilization_at(&self, t: AbsoluteT) -> (i32, i32) {
+        stabilization::shift_at(&self.stabilization_transforms, t)
+    }
+
+    /// The key-value metadata about the acquisition, which the header may declare (e.g., the
+    /// camera serial number, exposure settings, or GPS position). Empty if there is none.
+    pub fn user_metadata(&self) -> &UserMetadata {
+        &self.user_metadata
+    }
+
+    /// Convert an absolute timestamp in ticks to UTC nanoseconds since the Unix epoch. Returns
+    /// `None` if the stream doesn't declare an epoch and has no clock corrections.
+    pub fn t_to_utc_ns(&self, t: AbsoluteT) -> Option<u64> {
+        let meta = self.input.meta();
+        if meta.tps == 0 {
+            return None;
         }
-        self.decode_header_extension(reader)?;
-        Ok(self.input.meta().header_size)
+        clock::t_to_utc_ns(&self.clock_corrections, meta.epoch, meta.tps, t)
+    }
+
+    /// Convert UTC nanoseconds since the Unix epoch to an absolute timestamp in ticks, rounding
+    /// down. Returns `None` if the stream doesn't declare an epoch and has no clock corrections,
+    /// or if the time falls outside the range of the stream's timestamps.
+    pub fn utc_ns_to_t(&self, utc_ns: u64) -> Option<AbsoluteT> {
+        let meta = self.input.meta();
+        clock::utc_ns_to_t(&self.clock_corrections, meta.epoch, meta.tps, utc_ns)
+    }
+
+    /// Only return the events whose coordinates fall inside the given region of interest, or
+    /// all events if `None`. For compressed input, the Adus' cubes outside the region are only
+    /// partially decoded, which makes decoding a small patch of a large plane cheaper.
+    pub fn set_roi(&mut self, roi: Option<Roi>) {
+        self.roi = roi;
+        self.input.set_roi(roi);
+    }
+
+    /// Returns the region of interest, if one is set
+    pub fn roi(&self) -> Option<Roi> {
+        self.roi
+    }
+
+    /// Only return the events of the given channel, or of all channels if `None`. For compressed
+    /// input with the [planar](ChannelLayout::Planar) channel layout, only the cubes of that
+    /// channel are decoded, which makes decoding a single channel of a color stream cheaper.
+    ///
+    /// With [motion compensation](crate::codec::EncoderOptions::motion_compensation), each cube of
+    /// a planar stream is predicted from the same channel of the previous Adu, so the channel
+    /// should be set before decoding starts. A channel which wasn't decoded in the previous Adu
+    /// can't be predicted from it.
+    pub fn set_channel(&mut self, channel: Option<u8>) {
+        self.channel = channel;
+        self.input.set_channel(channel);
+    }
+
+    /// Returns the channel being decoded, if one is set
+    pub fn channel(&self) -> Option<u8> {
+        self.channel
     }
 
-    fn decode_header_extension(
+    /// The number of enhancement layers refining each Adu of a layered compressed stream, beyond
+    /// its base layer. 0 if the stream isn't layered.
+    pub fn enhancement_layers(&self) -> u8 {
+        self.input.meta().enhancement_layers
+    }
+
+    /// Only decode the base layer and at most `max_layers` enhancement layers of each Adu of a
+    /// layered compressed stream, for a quicker, lower-fidelity preview. The events are the same,
+    /// but their timestamps are coarser with fewer layers. `None` decodes every layer.
+    pub fn set_max_layers(&mut self, max_layers: Option<u8>) {
+        self.input.set_max_layers(max_layers);
+    }
+
+    /// Only return the events from `start_time` into the stream, up to `duration` later (or to
+    /// the end of the stream), for partial playback. Their timestamps are given relative to
+    /// `start_time`, just as if only that part of the source had been transcoded. With a time
+    /// index, decoding skips ahead to `start_time` and stops early, as in
+    /// [`Decoder::decode_between`]. Otherwise, the rest of the stream is decoded from the current
+    /// position and filtered.
+    ///
+    /// The window applies to the decoded timestamps, so it's only meaningful for streams with
+    /// absolute timestamps. A zero `start_time` and no `duration` decodes the whole stream.
+    pub fn set_time_window(
         &mut self,
         reader: &mut BitReader<R, BigEndian>,
+        start_time: Duration,
+        duration: Option<Duration>,
     ) -> Result<(), CodecError> {
-        let codec_version = self.input.meta().codec_version;
-        if codec_version == 0 {
+        if start_time.is_zero() && duration.is_none() {
+            self.time_window = None;
             return Ok(());
         }
-        let mut extension_size =
-            bincode::serialized_size(&EventStreamHeaderExtensionV1::default())?;
-        let mut buffer: Vec<u8> = vec![0; extension_size as usize];
-        reader.read_bytes(&mut buffer)?;
-        let extension_v1 = match self
-            .bincode
-            .deserialize_from::<_, EventStreamHeaderExtensionV1>(&*buffer)
-        {
-            Ok(header) => header,
-            Err(_) => return Err(Deserialize),
+
+        let start_t = self.duration_to_t(start_time);
+        let end_t = duration.map(|duration| start_t.saturating_add(self.duration_to_t(duration)));
+
+        // An Adu holds the events up to and including the start time of the next one
+        match self.input.seek_to_time(reader, start_t.saturating_sub(1)) {
+            Ok(_) | Err(CodecError::NoTimeIndex) => {}
+            Err(e) => return Err(e),
+        }
+        let end_pos = match end_t {
+            Some(end_t) => self.input.time_end_position(reader, end_t)?,
+            None => None,
         };
-        self.input.meta_mut().source_camera = extension_v1.source;
-        self.input.meta_mut().header_size += extension_size as usize;
+        self.time_window = Some(TimeWindow {
+            start_t,
+            end_t,
+            end_pos,
+        });
+        Ok(())
+    }
 
-        if codec_version == 1 {
+    /// Spatially downsample the events by merging each `factor`×`factor` neighborhood of pixels,
+    /// as described for [`Downsampler`], for cheap thumbnails and previews of large streams. The
+    /// events are given on [`Decoder::output_plane`]. A factor of 0 or 1 returns the events at full
+    /// resolution. The region of interest still applies to the full-resolution coordinates.
+    ///
+    /// Streams with Δt timestamps can't be downsampled, since merging a neighborhood's events
+    /// needs their absolute times.
+    pub fn set_downsample(&mut self, factor: PixelAddress) -> Result<(), CodecError> {
+        if factor <= 1 {
+            self.downsampler = None;
+            self.rebuild_decimator();
             return Ok(());
         }
+        let meta = self.input.meta();
+        if meta.time_mode == TimeMode::DeltaT {
+            return Err(CodecError::UnsupportedTimeMode(meta.time_mode));
+        }
+        self.downsampler = Some(Downsampler::new(meta.plane, factor)?);
+        self.rebuild_decimator();
+        Ok(())
+    }
 
-        extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV2::default())?;
-        buffer = vec![0; extension_size as usize];
-        reader.read_bytes(&mut buffer)?;
-        let extension_v2 = match self
-            .bincode
-            .deserialize_from::<_, EventStreamHeaderExtensionV2>(&*buffer)
-        {
-            Ok(header) => header,
-            Err(_) => return Err(Deserialize),
-        };
-        self.input.meta_mut().time_mode = extension_v2.time_mode;
-        self.input.meta_mut().header_size += extension_size as usize;
+    /// The dimensions of the decoded events, which are smaller than the stream's if they're
+    /// downsampled
+    pub fn output_plane(&self) -> PlaneSize {
+        self.downsampler
+            .as_ref()
+            .map_or(self.input.meta().plane, Downsampler::plane)
+    }
 
-        if codec_version == 2 {
+    /// Enforce a minimum Δt of `min_dt` between the events of each pixel, as described for
+    /// [`Decimator`], for consumers which can't keep up with the stream's full event rate. Events
+    /// which come too soon are merged into the pixel's next event. A zero `min_dt` returns every
+    /// event. The minimum applies after downsampling, if it's set.
+    ///
+    /// Streams with Δt timestamps can't be decimated, since merging a pixel's events needs their
+    /// absolute times.
+    pub fn set_min_dt(&mut self, min_dt: Duration) -> Result<(), CodecError> {
+        if min_dt.is_zero() {
+            self.decimator = None;
             return Ok(());
         }
+        let meta = self.input.meta();
+        if meta.time_mode == TimeMode::DeltaT {
+            return Err(CodecError::UnsupportedTimeMode(meta.time_mode));
+        }
+        let min_dt = self.duration_to_t(min_dt).max(1);
+        self.decimator = Some(Decimator::new(self.output_plane(), min_dt));
+        Ok(())
+    }
 
-        extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV3::default())?;
-        buffer = vec![0; extension_size as usize];
-        reader.read_bytes(&mut buffer)?;
-        let extension_v3 = match self
-            .bincode
-            .deserialize_from::<_, EventStreamHeaderExtensionV3>(&*buffer)
-        {
-            Ok(header) => header,
-            Err(_) => return Err(Deserialize),
-        };
-        self.input.meta_mut().adu_interval = extension_v3.adu_interval as usize;
-        self.input.meta_mut().header_size += extension_size as usize;
-
-        if codec_version == 3 {
+    /// Check that each pixel's decoded events have strictly increasing timestamps, as described
+    /// for [`OrderValidator`], to catch corrupt streams or buggy encoders. A duplicate or
+    /// out-of-order event is returned as [`CodecError::DuplicateEvent`] or
+    /// [`CodecError::OutOfOrderEvent`], and decoding can carry on past it. The events are checked
+    /// as they're decoded, before any other filters.
+    ///
+    /// Streams with Δt timestamps can't be validated, since their events don't carry the times
+    /// to compare.
+    pub fn set_validate_order(&mut self, validate: bool) -> Result<(), CodecError> {
+        if !validate {
+            self.validator = None;
             return Ok(());
         }
+        let meta = self.input.meta();
+        if meta.time_mode == TimeMode::DeltaT {
+            return Err(CodecError::UnsupportedTimeMode(meta.time_mode));
+        }
+        self.validator = Some(OrderValidator::new(meta.plane));
+        Ok(())
+    }
+
+    /// Start the decimator over on the current output plane, after it changes
+    fn rebuild_decimator(&mut self) {
+        if let Some(decimator) = &self.decimator {
+            self.decimator = Some(Decimator::new(self.output_plane(), decimator.min_dt()));
+        }
+    }
+
+    /// Convert a length of time to the stream's ticks, saturating at the largest timestamp
+    fn duration_to_t(&self, duration: Duration) -> AbsoluteT {
+        let ticks = duration.as_nanos() * u128::from(self.input.meta().tps) / 1_000_000_000;
+        AbsoluteT::try_from(ticks).unwrap_or(AbsoluteT::MAX)
+    }
+
+    /// Report the decoder's progress to `hook` every `interval` decoded events, and once more
+    /// when the end of the stream is reached. This replaces any previous hook.
+    pub fn set_progress_hook<F>(&mut self, interval: u64, hook: F)
+    where
+        F: FnMut(ProgressInfo) + Send + Sync + 'static,
+    {
+        let hook: ProgressHook = Box::new(hook);
+        self.progress = Some(ProgressTracker::new(interval, hook));
+    }
 
-        Err(CodecError::UnsupportedVersion(codec_version))
+    /// Remove the progress hook, if there is one
+    pub fn clear_progress_hook(&mut self) {
+        self.progress = None;
     }
 
     /// Read and decode the next event from the input stream
@@ -208,7 +479,214 @@ impl<R: Read + Seek> Decoder<R> {
         &mut self,
         reader: &mut BitReader<R, BigEndian>,
     ) -> Result<Event, CodecError> {
-        self.input.digest_event(reader)
+        let result = self.digest_filtered_event(reader);
+        if let Some(progress) = &mut self.progress {
+            let due = match &result {
+                Ok(event) => {
+                    let t = (self.input.meta().time_mode != TimeMode::DeltaT).then_some(event.t);
+                    progress.count(1, t)
+                }
+                Err(CodecError::Eof) => true,
+                Err(_) => false,
+            };
+            if due {
+                if let Ok(bits) = reader.position_in_bits() {
+                    progress.report(bits / 8);
+                }
+            }
+        }
+        result
+    }
+
+    /// Read and decode the next event which passes the region of interest and time window, merged
+    /// with its neighbors if the events are downsampled, and with the pixel's other events if
+    /// they're decimated
+    #[inline]
+    fn digest_filtered_event(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Event, CodecError> {
+        if self.roi.is_none()
+            && self.channel.is_none()
+            && self.time_window.is_none()
+            && self.downsampler.is_none()
+            && self.decimator.is_none()
+            && self.validator.is_none()
+        {
+            return self.input.digest_event(reader);
+        }
+        loop {
+            let mut event = self.input.digest_event(reader)?;
+            if let Some(validator) = &mut self.validator {
+                validator.validate(&event)?;
+            }
+            if let Some(window) = self.time_window {
+                // As in `Decoder::decode_chunk`, the event is from past the window once the
+                // reader has passed its end
+                if let Some(end_pos) = window.end_pos {
+                    if self.get_input_stream_position(reader)? > end_pos {
+                        return Err(CodecError::Eof);
+                    }
+                }
+                if event.t < window.start_t || window.end_t.is_some_and(|end_t| event.t >= end_t) {
+                    continue;
+                }
+                event.t -= window.start_t;
+            }
+            if !self.roi.map_or(true, |roi| roi.contains(event.coord)) {
+                continue;
+            }
+            if self
+                .channel
+                .is_some_and(|channel| event.coord.c.unwrap_or(0) != channel)
+            {
+                continue;
+            }
+            let Some(event) = (match &mut self.downsampler {
+                Some(downsampler) => downsampler.ingest_event(event),
+                None => Some(event),
+            }) else {
+                continue;
+            };
+            match &mut self.decimator {
+                Some(decimator) => {
+                    if let Some(event) = decimator.ingest_event(event) {
+                        return Ok(event);
+                    }
+                }
+                None => return Ok(event),
+            }
+        }
+    }
+
+    /// Iterate over the remaining events of the input stream. The iterator ends when the end of
+    /// the stream is reached, or after yielding the first error.
+    pub fn events<'a>(&'a mut self, reader: &'a mut BitReader<R, BigEndian>) -> Events<'a, R> {
+        Events {
+            decoder: self,
+            reader,
+            done: false,
+        }
+    }
+
+    /// Iterate over frames of intensities at `fps` frames per second, reconstructed from the
+    /// remaining events as described for [`FrameReconstructor`], for rendering previews without
+    /// the framer of `adder-codec-rs`. The frames are indexed by (y, x, c), on
+    /// [`Decoder::output_plane`]. The iterator ends after the frame of the stream's last event, or
+    /// after yielding the first error.
+    ///
+    /// Streams with Δt timestamps aren't supported.
+    pub fn reconstruct_frames<'a>(
+        &'a mut self,
+        reader: &'a mut BitReader<R, BigEndian>,
+        fps: f64,
+    ) -> Result<Frames<'a, R>, CodecError> {
+        let meta = CodecMetadata {
+            plane: self.output_plane(),
+            ..*self.input.meta()
+        };
+        Ok(Frames {
+            reconstructor: FrameReconstructor::new(&meta, fps)?,
+            decoder: self,
+            reader,
+            done: false,
+        })
+    }
+
+    /// Consume the decoder and its reader, returning an iterator over the remaining events of the
+    /// input stream. See [`Decoder::events`].
+    pub fn into_events(self, reader: BitReader<R, BigEndian>) -> IntoEvents<R> {
+        IntoEvents {
+            decoder: self,
+            reader,
+            done: false,
+        }
+    }
+
+    /// Iterate over the events of the whole input stream in reverse temporal order, for scrubbing
+    /// backwards. The stream is walked one chunk at a time from its end, using the time index of
+    /// a compressed stream or the fixed event size of a raw stream. The events within each chunk
+    /// are sorted by timestamp, except in [`TimeMode::DeltaT`](crate::TimeMode::DeltaT), where
+    /// they're given in reverse stream order.
+    ///
+    /// The reader is left at an arbitrary position afterwards. Returns
+    /// [`CodecError::NoTimeIndex`] if the stream is compressed without a time index.
+    pub fn events_rev<'a>(
+        &'a mut self,
+        reader: &'a mut BitReader<R, BigEndian>,
+    ) -> Result<EventsRev<'a, R>, CodecError> {
+        let chunks = self.input.chunk_positions(reader)?;
+        Ok(EventsRev {
+            decoder: self,
+            reader,
+            chunks,
+            chunk_end: None,
+            buffer: Vec::new(),
+            done: false,
+        })
+    }
+
+    /// Iterate over the events with timestamps in `t_start..t_end`, for extracting a clip without
+    /// decoding the whole stream. With a time index, decoding skips ahead to the Adu covering
+    /// `t_start` and stops before the first Adu starting at or after `t_end`. Otherwise, the rest
+    /// of the stream is decoded from the current position and filtered.
+    ///
+    /// The window applies to the decoded timestamps, so it's only meaningful for streams with
+    /// absolute timestamps.
+    pub fn decode_between<'a>(
+        &'a mut self,
+        reader: &'a mut BitReader<R, BigEndian>,
+        t_start: AbsoluteT,
+        t_end: AbsoluteT,
+    ) -> Result<EventsBetween<'a, R>, CodecError> {
+        // An Adu holds the events up to and including the start time of the next one
+        match self.input.seek_to_time(reader, t_start.saturating_sub(1)) {
+            Ok(_) | Err(CodecError::NoTimeIndex) => {}
+            Err(e) => return Err(e),
+        }
+        let end = self.input.time_end_position(reader, t_end)?;
+        Ok(EventsBetween {
+            decoder: self,
+            reader,
+            t_start,
+            t_end,
+            end,
+            done: false,
+        })
+    }
+
+    /// Decode the chunk of the stream from `start` up to `end` (or the end of the stream)
+    fn decode_chunk(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Vec<Event>, CodecError> {
+        self.input.seek_to_chunk(reader, start)?;
+        let mut events = Vec::new();
+        loop {
+            match self.digest_event(reader) {
+                Ok(event) => {
+                    // A compressed Adu is read whole before its first event is returned, so an
+                    // event belongs to the next chunk once the reader is past this chunk's end
+                    let pos = self.get_input_stream_position(reader)?;
+                    if end.is_some_and(|end| pos > end) {
+                        break;
+                    }
+                    events.push(event);
+                }
+                Err(CodecError::Eof) => break,
+                Err(CodecError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.input.meta().time_mode != TimeMode::DeltaT {
+            events.sort_by_key(|event| event.t);
+        }
+        Ok(events)
     }
 
     // Read and decode the next event from the input stream
@@ -227,9 +705,38 @@ impl<R: Read + Seek> Decoder<R> {
         reader: &mut BitReader<R, BigEndian>,
         position: u64,
     ) -> Result<(), CodecError> {
+        if let Some(downsampler) = &mut self.downsampler {
+            downsampler.reset();
+        }
+        if let Some(decimator) = &mut self.decimator {
+            decimator.reset();
+        }
+        if let Some(validator) = &mut self.validator {
+            validator.reset();
+        }
         self.input.set_input_stream_position(reader, position)
     }
 
+    /// Seek to the start of the compressed data covering time `t`, using the time index at the
+    /// end of the stream. Returns the timestamp that decoding resumes from, which is the start
+    /// time of the Adu containing `t`.
+    pub fn seek_to_time(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        t: AbsoluteT,
+    ) -> Result<AbsoluteT, CodecError> {
+        if let Some(downsampler) = &mut self.downsampler {
+            downsampler.reset();
+        }
+        if let Some(decimator) = &mut self.decimator {
+            decimator.reset();
+        }
+        if let Some(validator) = &mut self.validator {
+            validator.reset();
+        }
+        self.input.seek_to_time(reader, t)
+    }
+
     /// Returns the current position of the input stream in bytes
     pub fn get_input_stream_position(
         &self,
@@ -249,7 +756,7 @@ impl<R: Read + Seek> Decoder<R> {
             reader.seek_bits(SeekFrom::End(
                 i * self.input.meta().plane.volume() as i64 * 8,
             ))?;
-            if let Err(CodecError::Eof) = self.digest_event(reader) {
+            if let Err(CodecError::Eof) = self.input.digest_event(reader) {
                 break;
             }
         }
@@ -262,10 +769,600 @@ impl<R: Read + Seek> Decoder<R> {
         if self.input.magic() == MAGIC_COMPRESSED {
             return EncoderType::Compressed;
         }
+        #[cfg(feature = "lz")]
+        if self.input.magic() == MAGIC_LZ {
+            return EncoderType::Lz;
+        }
         EncoderType::Raw
     }
 }
 
+/// Map the result of decoding an event to an iterator item. The end of the stream (however the
+/// underlying compression scheme signals it) ends the iteration, and an error is yielded only once.
+fn next_event(
+    result: Result<Event, CodecError>,
+    done: &mut bool,
+) -> Option<Result<Event, CodecError>> {
+    match result {
+        Ok(event) => Some(Ok(event)),
+        Err(CodecError::Eof) => {
+            *done = true;
+            None
+        }
+        Err(CodecError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            *done = true;
+            None
+        }
+        Err(e) => {
+            *done = true;
+            Some(Err(e))
+        }
+    }
+}
+
+/// An iterator over the events of a [`Decoder`], created by [`Decoder::events`]
+pub struct Events<'a, R: Read + Seek> {
+    decoder: &'a mut Decoder<R>,
+    reader: &'a mut BitReader<R, BigEndian>,
+    done: bool,
+}
+
+impl<'a, R: Read + Seek> Iterator for Events<'a, R> {
+    type Item = Result<Event, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        next_event(self.decoder.digest_event(self.reader), &mut self.done)
+    }
+}
+
+impl<'a, R: Read + Seek> std::iter::FusedIterator for Events<'a, R> {}
+
+/// An iterator over frames reconstructed from the events of a [`Decoder`], created by
+/// [`Decoder::reconstruct_frames`]
+pub struct Frames<'a, R: Read + Seek> {
+    decoder: &'a mut Decoder<R>,
+    reader: &'a mut BitReader<R, BigEndian>,
+    reconstructor: FrameReconstructor,
+    done: bool,
+}
+
+impl<'a, R: Read + Seek> Iterator for Frames<'a, R> {
+    type Item = Result<Array3<f32>, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.reconstructor.pop_frame() {
+                return Some(Ok(frame));
+            }
+            if self.done {
+                return None;
+            }
+            match next_event(self.decoder.digest_event(self.reader), &mut self.done) {
+                Some(Ok(event)) => self.reconstructor.ingest_event(event),
+                Some(Err(e)) => return Some(Err(e)),
+                None => self.reconstructor.finish(),
+            }
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> std::iter::FusedIterator for Frames<'a, R> {}
+
+/// An iterator over the events of a [`Decoder`] in reverse temporal order, created by
+/// [`Decoder::events_rev`]
+pub struct EventsRev<'a, R: Read + Seek> {
+    decoder: &'a mut Decoder<R>,
+    reader: &'a mut BitReader<R, BigEndian>,
+
+    /// The start positions of the chunks which haven't been decoded yet
+    chunks: Vec<u64>,
+
+    /// The start position of the chunk which was decoded last, where the next one ends
+    chunk_end: Option<u64>,
+
+    /// The remaining events of the current chunk, in temporal order
+    buffer: Vec<Event>,
+    done: bool,
+}
+
+impl<'a, R: Read + Seek> Iterator for EventsRev<'a, R> {
+    type Item = Result<Event, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if let Some(event) = self.buffer.pop() {
+                return Some(Ok(event));
+            }
+
+            let Some(start) = self.chunks.pop() else {
+                self.done = true;
+                break;
+            };
+            match self
+                .decoder
+                .decode_chunk(self.reader, start, self.chunk_end)
+            {
+                Ok(events) => self.buffer = events,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            self.chunk_end = Some(start);
+        }
+        None
+    }
+}
+
+impl<'a, R: Read + Seek> std::iter::FusedIterator for EventsRev<'a, R> {}
+
+/// An iterator over the events of a [`Decoder`] within a time window, created by
+/// [`Decoder::decode_between`]
+pub struct EventsBetween<'a, R: Read + Seek> {
+    decoder: &'a mut Decoder<R>,
+    reader: &'a mut BitReader<R, BigEndian>,
+    t_start: AbsoluteT,
+    t_end: AbsoluteT,
+
+    /// The position of the first chunk past the window, if the stream's time index gives one
+    end: Option<u64>,
+    done: bool,
+}
+
+impl<'a, R: Read + Seek> Iterator for EventsBetween<'a, R> {
+    type Item = Result<Event, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let event = match next_event(self.decoder.digest_event(self.reader), &mut self.done)? {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e)),
+            };
+
+            // As in `Decoder::decode_chunk`, the event is from past the window once the reader
+            // has passed its end
+            if let Some(end) = self.end {
+                match self.decoder.get_input_stream_position(self.reader) {
+                    Ok(pos) if pos > end => {
+                        self.done = true;
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+            }
+            if (self.t_start..self.t_end).contains(&event.t) {
+                return Some(Ok(event));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, R: Read + Seek> std::iter::FusedIterator for EventsBetween<'a, R> {}
+
+/// An iterator which owns a [`Decoder`] and its reader, created by [`Decoder::into_events`]
+pub struct IntoEvents<R: Read + Seek> {
+    decoder: Decoder<R>,
+    reader: BitReader<R, BigEndian>,
+    done: bool,
+}
+
+impl<R: Read + Seek> IntoEvents<R> {
+    /// Returns a reference to the decoder
+    pub fn decoder(&self) -> &Decoder<R> {
+        &self.decoder
+    }
+
+    /// Give back the decoder and its reader
+    pub fn into_inner(self) -> (Decoder<R>, BitReader<R, BigEndian>) {
+        (self.decoder, self.reader)
+    }
+}
+
+impl<R: Read + Seek> Iterator for IntoEvents<R> {
+    type Item = Result<Event, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        next_event(self.decoder.digest_event(&mut self.reader), &mut self.done)
+    }
+}
+
+impl<R: Read + Seek> std::iter::FusedIterator for IntoEvents<R> {}
+
+/// Read the fixed part of a stream's header, returning its magic number and the metadata it
+/// declares. The rest of the metadata is filled in by [`read_header_extension`].
+pub(crate) fn read_header<R: Read>(
+    reader: &mut BitReader<R, BigEndian>,
+) -> Result<(Magic, CodecMetadata), CodecError> {
+    let header_size = bincode::serialized_size(&EventStreamHeader::default())?;
+    let mut buffer: Vec<u8> = vec![0; header_size as usize];
+    reader.read_bytes(&mut buffer)?;
+
+    let header = match bincode_options().deserialize_from::<_, EventStreamHeader>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+
+    let mut meta = CodecMetadata {
+        codec_version: header.version,
+        header_size: header_size as usize,
+        time_mode: Default::default(),
+        plane: PlaneSize::new(header.width.into(), header.height.into(), header.channels)?,
+        tps: header.tps,
+        ref_interval: header.ref_interval,
+        delta_t_max: header.delta_t_max,
+        event_size: header.event_size,
+        source_camera: Default::default(), // Gets filled by decoding the V2 header extension
+        adu_interval: Default::default(),  // Gets filled by decoding the V3 header extension
+        chroma_subsampling: Default::default(), // Gets filled by decoding the V4 header extension
+        epoch: None,                       // Gets filled by decoding the V5 header extension
+        enhancement_layers: 0,             // Gets filled by decoding the V6 header extension
+        wide_coordinates: false,           // Gets filled by decoding the V7 header extension
+        block_size: 16,
+        d_max: D_MAX,
+        channel_layout: Default::default(),
+        context_priors_id: 0,
+        quantization_table: Default::default(),
+        intensity_peak: 1.0,
+        color_space: Default::default(),
+        motion_compensation: false,
+    };
+
+    // Manual fix for malformed files with old software
+    if meta.event_size == 10 {
+        meta.event_size = 11;
+    }
+    Ok((header.magic, meta))
+}
+
+/// Read the extensions of a stream's header which its codec version has, filling in the metadata
+/// read by [`read_header`] and the user metadata. Afterwards, the reader is at the stream's first
+/// event.
+pub(crate) fn read_header_extension<R: Read>(
+    reader: &mut BitReader<R, BigEndian>,
+    meta: &mut CodecMetadata,
+    user_metadata: &mut UserMetadata,
+) -> Result<(), CodecError> {
+    let bincode = bincode_options();
+    let codec_version = meta.codec_version;
+    if codec_version == 0 {
+        return Ok(());
+    }
+    let mut extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV1::default())?;
+    let mut buffer: Vec<u8> = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v1 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV1>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.source_camera = extension_v1.source;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 1 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV2::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v2 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV2>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.time_mode = extension_v2.time_mode;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 2 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV3::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v3 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV3>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.adu_interval = extension_v3.adu_interval as usize;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 3 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV4::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v4 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV4>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.chroma_subsampling = extension_v4.chroma_subsampling;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 4 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV5::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v5 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV5>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.epoch = (extension_v5.epoch_ns != 0).then_some(extension_v5.epoch_ns);
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 5 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV6::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v6 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV6>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.enhancement_layers = extension_v6.enhancement_layers;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 6 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV7::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v7 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV7>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.wide_coordinates = extension_v7.wide_coordinates;
+    meta.plane = PlaneSize::new(extension_v7.width, extension_v7.height, meta.plane.c())?;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 7 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV8::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v8 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV8>(&*buffer) {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.header_size += extension_size as usize;
+    if extension_v8.user_metadata_size > MAX_USER_METADATA_SIZE {
+        return Err(Deserialize);
+    }
+    if extension_v8.user_metadata_size > 0 {
+        buffer = vec![0; extension_v8.user_metadata_size as usize];
+        reader.read_bytes(&mut buffer)?;
+        *user_metadata = match bincode.deserialize_from(&*buffer) {
+            Ok(user_metadata) => user_metadata,
+            Err(_) => return Err(Deserialize),
+        };
+        meta.header_size += buffer.len();
+    }
+
+    if codec_version == 8 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV9::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    if bincode
+        .deserialize_from::<_, EventStreamHeaderExtensionV9>(&*buffer)
+        .is_err()
+    {
+        return Err(Deserialize);
+    }
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 9 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV10::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    if bincode
+        .deserialize_from::<_, EventStreamHeaderExtensionV10>(&*buffer)
+        .is_err()
+    {
+        return Err(Deserialize);
+    }
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 10 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV11::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v11 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV11>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.motion_compensation = extension_v11.motion_compensation;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 11 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV12::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v12 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV12>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    if !BLOCK_SIZES.contains(&usize::from(extension_v12.block_size)) {
+        return Err(Deserialize);
+    }
+    meta.block_size = usize::from(extension_v12.block_size);
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 12 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV13::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v13 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV13>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    if extension_v13.d_max > D_MAX {
+        return Err(Deserialize);
+    }
+    meta.d_max = extension_v13.d_max;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 13 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV14::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v14 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV14>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.channel_layout = if extension_v14.planar_channels {
+        ChannelLayout::Planar
+    } else {
+        ChannelLayout::Interleaved
+    };
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 14 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV15::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v15 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV15>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.context_priors_id = extension_v15.context_priors_id;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 15 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV16::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v16 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV16>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.quantization_table = extension_v16.quantization_table;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 16 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV17::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v17 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV17>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.intensity_peak = extension_v17.intensity_peak;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 17 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV18::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    let extension_v18 = match bincode.deserialize_from::<_, EventStreamHeaderExtensionV18>(&*buffer)
+    {
+        Ok(header) => header,
+        Err(_) => return Err(Deserialize),
+    };
+    meta.color_space = extension_v18.color_space;
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 18 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV19::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    if bincode
+        .deserialize_from::<_, EventStreamHeaderExtensionV19>(&*buffer)
+        .is_err()
+    {
+        return Err(Deserialize);
+    }
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 19 {
+        return Ok(());
+    }
+
+    extension_size = bincode::serialized_size(&EventStreamHeaderExtensionV20::default())?;
+    buffer = vec![0; extension_size as usize];
+    reader.read_bytes(&mut buffer)?;
+    if bincode
+        .deserialize_from::<_, EventStreamHeaderExtensionV20>(&*buffer)
+        .is_err()
+    {
+        return Err(Deserialize);
+    }
+    meta.header_size += extension_size as usize;
+
+    if codec_version == 20 {
+        return Ok(());
+    }
+
+    Err(CodecError::UnsupportedVersion(codec_version))
+}
+
+fn bincode_options(
+) -> WithOtherEndian<WithOtherIntEncoding<DefaultOptions, FixintEncoding>, bincode::config::BigEndian>
+{
+    DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,9 +1371,10 @@ mod tests {
     use crate::codec::raw::stream::{RawInput, RawOutput};
 
     use crate::codec::rate_controller::Crf;
-    use crate::codec::{EncoderOptions, EventOrder};
-    use crate::Coord;
+    use crate::codec::{EncoderOptions, EventOrder, LATEST_CODEC_VERSION};
+    use crate::{Coord, TimeMode, D, D_EMPTY};
     use std::io::{BufReader, BufWriter, Cursor, Write};
+    use std::sync::Arc;
 
     fn stock_event() -> Event {
         Event {
@@ -306,6 +1404,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -343,6 +1453,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -351,6 +1473,11 @@ mod tests {
             EncoderOptions {
                 event_drop: Default::default(),
                 event_order: EventOrder::Interleaved,
+                event_validation: Default::default(),
+                time_index: false,
+                adu_checksum: false,
+                adu_sync_markers: false,
+                enhancement_layers: 0,
                 crf: Crf::new(
                     None,
                     PlaneSize {
@@ -359,6 +1486,21 @@ mod tests {
                         channels: 1,
                     },
                 ),
+                quality_map: None,
+                channel_c_thresh_max: None,
+                target_kbps: None,
+                user_metadata: None,
+                raw_flush_events: None,
+                adu_interval: None,
+                delta_t_coding: Default::default(),
+                lz_dictionary: None,
+                motion_compensation: false,
+                block_size: None,
+                round_trip_check: Default::default(),
+                max_adu_events: None,
+                context_priors: None,
+                intra_refresh: Default::default(),
+                quantization_table: None,
             },
         );
 
@@ -390,6 +1532,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -449,6 +1603,42 @@ mod tests {
         assert_eq!(reader.input.meta().header_size, 33);
     }
 
+    #[test]
+    fn header_v5_epoch_raw() {
+        let epoch = 1_700_000_000_000_000_000;
+        let meta = CodecMetadata {
+            tps: 1000,
+            epoch: Some(epoch),
+            enhancement_layers: 0,
+            ..Default::default()
+        };
+        let encoder = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(meta.plane),
+        );
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(BufReader::new(Cursor::new(&*output)), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert_eq!(reader.input.meta().header_size, 88);
+        assert_eq!(reader.epoch(), Some(epoch));
+        assert_eq!(reader.t_to_utc_ns(1500), Some(epoch + 1_500_000_000));
+        assert_eq!(reader.utc_ns_to_t(epoch + 1_500_999_999), Some(1500));
+        assert_eq!(reader.utc_ns_to_t(epoch - 1), None);
+
+        // Streams without an epoch can't be correlated with wall-clock time
+        let output = setup_encoded_raw(5);
+        let mut bitreader = BitReader::endian(BufReader::new(Cursor::new(&*output)), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert_eq!(reader.epoch(), None);
+        assert_eq!(reader.t_to_utc_ns(0), None);
+    }
+
     #[test]
     #[cfg(feature = "compression")]
     fn header_v0_compressed() {
@@ -500,4 +1690,623 @@ mod tests {
         let event = reader.digest_event(&mut bitreader).unwrap();
         assert_eq!(event, stock_event());
     }
+
+    #[test]
+    fn digest_event_raw_mixed() {
+        let meta = CodecMetadata {
+            time_mode: TimeMode::Mixed,
+            plane: PlaneSize::new(2, 1, 1).unwrap(),
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(meta.plane),
+        );
+
+        // Events spanning several ADUs of 255 ticks, including ADU heads that are smaller than
+        // the time remaining in the pixel's previous ADU
+        let events: Vec<Event> = [(0, 10), (0, 100), (1, 254), (0, 300), (1, 255), (0, 310)]
+            .into_iter()
+            .chain([(0, 1000), (1, 1020), (1, 1021)])
+            .map(|(x, t)| Event {
+                coord: Coord { x, y: 0, c: None },
+                d: 7,
+                t,
+            })
+            .collect();
+        for event in &events {
+            encoder.ingest_event(*event).unwrap();
+        }
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(BufReader::new(Cursor::new(&*output)), BigEndian);
+        let mut reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert_eq!(reader.meta().time_mode, TimeMode::Mixed);
+        let decoded: Vec<Event> = reader
+            .events(&mut bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, events);
+    }
+
+    #[test]
+    fn digest_event_raw_mixed_color() {
+        // Each channel of a pixel keeps its own previous timestamp, and a pixel which is quiet
+        // for several ADUs starts again from a head
+        let meta = CodecMetadata {
+            time_mode: TimeMode::Mixed,
+            plane: PlaneSize::new(2, 2, 3).unwrap(),
+            adu_interval: 2,
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(meta.plane),
+        );
+        let events: Vec<Event> = [
+            (0, 1, 0, 5),
+            (0, 1, 1, 6),
+            (0, 1, 0, 40),
+            (1, 1, 2, 200),
+            (0, 1, 1, 509),
+            (0, 1, 0, 510),
+            (1, 1, 2, 4000),
+            (0, 1, 1, 4001),
+        ]
+        .into_iter()
+        .map(|(x, y, c, t)| Event {
+            coord: Coord { x, y, c: Some(c) },
+            d: 7,
+            t,
+        })
+        .collect();
+        encoder.ingest_events(&events).unwrap();
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(BufReader::new(Cursor::new(&*output)), BigEndian);
+        let mut reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let decoded: Vec<Event> = reader
+            .events(&mut bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, events);
+    }
+
+    #[test]
+    fn events_iter_raw() {
+        let output = setup_encoded_raw(2);
+        let tmp = Cursor::new(&*output);
+        let bufreader = BufReader::new(tmp);
+        let compression = RawInput::new();
+
+        let mut bitreader = BitReader::endian(bufreader, BigEndian);
+        let mut reader = Decoder::new_raw(compression, &mut bitreader).unwrap();
+        let events: Vec<Event> = reader
+            .events(&mut bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(events, vec![stock_event()]);
+
+        let tmp = Cursor::new(&*output);
+        let mut bitreader = BitReader::endian(BufReader::new(tmp), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let mut count = 0;
+        for event in reader.into_events(bitreader) {
+            assert_eq!(event.unwrap(), stock_event());
+            count += 1;
+        }
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn events_rev_raw() {
+        let plane = PlaneSize {
+            width: 100,
+            height: 100,
+            channels: 1,
+        };
+        let meta = CodecMetadata {
+            codec_version: LATEST_CODEC_VERSION,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            ref_interval: 255,
+            delta_t_max: 255,
+            adu_interval: 1,
+            ..Default::default()
+        };
+        let compression = RawOutput::new(meta, BufWriter::new(Vec::new()));
+        let mut encoder: Encoder<BufWriter<Vec<u8>>> =
+            Encoder::new_raw(compression, EncoderOptions::default(plane));
+
+        // Enough events to span several chunks of the raw stream
+        let events: Vec<Event> = (0..10_000)
+            .map(|i| Event {
+                coord: Coord {
+                    x: i % 100,
+                    y: i / 100 % 100,
+                    c: None,
+                },
+                d: 0,
+                t: i,
+            })
+            .collect();
+        for event in &events {
+            encoder.ingest_event(*event).unwrap();
+        }
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let reversed: Vec<Event> = reader
+            .events_rev(&mut bitreader)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(reversed, events.into_iter().rev().collect::<Vec<_>>());
+    }
+
+    /// Events beyond the reach of a narrow pixel address, on a plane 70000 pixels wide
+    fn wide_events() -> Vec<Event> {
+        [0, 65_535, 69_999]
+            .into_iter()
+            .zip(1..)
+            .map(|(x, i)| Event {
+                coord: Coord { x, y: 1, c: None },
+                d: 7,
+                t: 100 * i,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wide_coordinates_raw() {
+        let plane = PlaneSize::new(70_000, 2, 1).unwrap();
+        let meta = CodecMetadata {
+            codec_version: 5,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        for event in wide_events() {
+            encoder.ingest_event(event).unwrap();
+        }
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+
+        // The stream is written with the first version which can declare wide coordinates
+        assert_eq!(reader.meta().codec_version, 7);
+        assert!(reader.meta().wide_coordinates);
+        assert_eq!(reader.meta().plane, plane);
+        assert_eq!(reader.meta().event_size, 13);
+        let events: Vec<Event> = reader
+            .into_events(bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(events, wide_events());
+    }
+
+    #[test]
+    fn narrow_coordinates_raw() {
+        // Streams which don't need wide coordinates keep the narrow event format
+        let output = setup_encoded_raw(LATEST_CODEC_VERSION);
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert!(!reader.meta().wide_coordinates);
+        assert_eq!(reader.meta().event_size, 9);
+        let events: Vec<Event> = reader
+            .into_events(bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(events, vec![stock_event()]);
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn wide_coordinates_compressed() {
+        use crate::codec::CompressedOutput;
+
+        let plane = PlaneSize::new(70_000, 2, 1).unwrap();
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..Default::default()
+        };
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        let mut encoder = Encoder::new_compressed(
+            CompressedOutput::new(meta, BufWriter::new(Vec::new())),
+            options,
+        );
+        for event in wide_events() {
+            encoder.ingest_event(event).unwrap();
+        }
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut reader =
+            Decoder::new_compressed(CompressedInput::new(255 * 5, 255, 5), &mut bitreader).unwrap();
+        assert!(reader.meta().wide_coordinates);
+        assert_eq!(reader.meta().plane, plane);
+
+        let mut events = Vec::new();
+        loop {
+            match reader.digest_event(&mut bitreader) {
+                Ok(event) => events.push(event),
+                Err(CodecError::IoError(_) | CodecError::Eof) => break,
+                Err(e) => panic!("{e}"),
+            }
+        }
+        assert_eq!(events, wide_events());
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn block_size_compressed() {
+        use crate::codec::CompressedOutput;
+
+        // The plane isn't a whole number of cubes of any size
+        let plane = PlaneSize::new(40, 24, 1).unwrap();
+        let mut expected = Vec::new();
+        for k in 0..4 {
+            for y in 0..24 {
+                for x in 0..40 {
+                    expected.push(Event {
+                        coord: Coord { x, y, c: None },
+                        d: 7 + ((x + y) % 3) as u8,
+                        t: 300 + k * 1275 + (x * 7 + y * 13) % 200,
+                    });
+                }
+            }
+        }
+        expected.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+
+        for block_size in BLOCK_SIZES {
+            let meta = CodecMetadata {
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: 255,
+                delta_t_max: 255 * 5,
+                adu_interval: 5,
+                ..Default::default()
+            };
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            options.block_size = Some(block_size);
+            let mut encoder = Encoder::new_compressed(
+                CompressedOutput::new(meta, BufWriter::new(Vec::new())),
+                options,
+            );
+            for event in &expected {
+                encoder.ingest_event(*event).unwrap();
+            }
+            let output = encoder
+                .close_writer()
+                .unwrap()
+                .unwrap()
+                .into_inner()
+                .unwrap();
+
+            let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+            let mut reader =
+                Decoder::new_compressed(CompressedInput::new(255 * 5, 255, 5), &mut bitreader)
+                    .unwrap();
+            assert_eq!(reader.meta().block_size, block_size);
+
+            let mut events = Vec::new();
+            loop {
+                match reader.digest_event(&mut bitreader) {
+                    Ok(event) => events.push(event),
+                    Err(CodecError::IoError(_) | CodecError::Eof) => break,
+                    Err(e) => panic!("{e}"),
+                }
+            }
+            events.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+            assert_eq!(events, expected, "block size {block_size}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn d_max_compressed() {
+        use crate::codec::CompressedOutput;
+
+        let plane = PlaneSize::new(32, 16, 1).unwrap();
+        let mut expected = Vec::new();
+        for k in 0..4 {
+            for y in 0..16 {
+                for x in 0..32 {
+                    expected.push(Event {
+                        coord: Coord { x, y, c: None },
+                        d: ((x * 3 + y + k) % 8) as u8,
+                        t: 300 + k * 1275 + (x * 7 + y * 13) % 200,
+                    });
+                }
+            }
+        }
+        expected.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+
+        let encode = |d_max: D, events: &[Event]| -> Result<Vec<u8>, CodecError> {
+            let meta = CodecMetadata {
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 7650,
+                ref_interval: 255,
+                delta_t_max: 255 * 5,
+                adu_interval: 5,
+                d_max,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+                ..Default::default()
+            };
+            let mut options = EncoderOptions::default(plane);
+            options.crf.override_c_thresh_max(0);
+            let mut encoder = Encoder::new_compressed(
+                CompressedOutput::new(meta, BufWriter::new(Vec::new())),
+                options,
+            );
+            for event in events {
+                encoder.ingest_event(*event)?;
+            }
+            Ok(encoder.close_writer()?.unwrap().into_inner().unwrap())
+        };
+
+        let output = encode(7, &expected).unwrap();
+        let mut bitreader = BitReader::endian(Cursor::new(output.clone()), BigEndian);
+        let mut reader =
+            Decoder::new_compressed(CompressedInput::new(255 * 5, 255, 5), &mut bitreader).unwrap();
+        assert_eq!(reader.meta().d_max, 7);
+
+        let mut events = Vec::new();
+        loop {
+            match reader.digest_event(&mut bitreader) {
+                Ok(event) => events.push(event),
+                Err(CodecError::IoError(_) | CodecError::Eof) => break,
+                Err(e) => panic!("{e}"),
+            }
+        }
+        events.sort_by_key(|event| (event.t, event.coord.y, event.coord.x));
+        assert_eq!(events, expected);
+
+        // The D residuals which the smaller maximum rules out aren't given any probability
+        assert!(output.len() < encode(D_MAX, &expected).unwrap().len());
+
+        // Events beyond the maximum can't be coded
+        let mut event = expected[0];
+        event.d = 8;
+        assert!(matches!(
+            encode(7, &[event]),
+            Err(CodecError::DOutOfRange { d: 8, d_max: 7 })
+        ));
+        event.d = D_EMPTY;
+        assert!(encode(7, &[event]).is_ok());
+    }
+
+    fn encode_user_metadata(codec_version: u8, user_metadata: &UserMetadata) -> Vec<u8> {
+        let meta = CodecMetadata {
+            codec_version,
+            time_mode: TimeMode::AbsoluteT,
+            ..Default::default()
+        };
+        let mut options = EncoderOptions::default(meta.plane);
+        options.user_metadata = Some(Arc::new(user_metadata.clone()));
+        let mut encoder = Encoder::new_raw(RawOutput::new(meta, Cursor::new(Vec::new())), options);
+        encoder.ingest_event(stock_event()).unwrap();
+        encoder.close_writer().unwrap().unwrap().into_inner()
+    }
+
+    #[test]
+    fn user_metadata_raw() {
+        let user_metadata = UserMetadata::from([
+            ("serial".to_string(), "00051234".to_string()),
+            (
+                "exposure".to_string(),
+                r#"{"shutter_us": 500, "gain_db": 6.0}"#.to_string(),
+            ),
+        ]);
+        let output = encode_user_metadata(LATEST_CODEC_VERSION, &user_metadata);
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert_eq!(reader.user_metadata(), &user_metadata);
+
+        // The events follow the variable-size metadata
+        let events: Vec<Event> = reader
+            .into_events(bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(events, vec![stock_event()]);
+
+        // Older headers can't hold the metadata
+        let output = encode_user_metadata(7, &user_metadata);
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert!(reader.user_metadata().is_empty());
+        assert_eq!(reader.meta().header_size, 59);
+
+        // A corrupt size is rejected before the metadata is allocated. It follows the version 7
+        // header
+        let mut output = encode_user_metadata(LATEST_CODEC_VERSION, &user_metadata);
+        output[59..63].copy_from_slice(&(MAX_USER_METADATA_SIZE + 1).to_be_bytes());
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        assert!(matches!(
+            Decoder::new_raw(RawInput::new(), &mut bitreader),
+            Err(Deserialize)
+        ));
+    }
+
+    #[test]
+    fn progress_hook_raw() {
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane: PlaneSize::new(4, 4, 3).unwrap(),
+            ..Default::default()
+        };
+        let events: Vec<Event> = (1..=25)
+            .map(|t| Event {
+                coord: Coord::new(t % 4, t / 4 % 4, Some(0)),
+                d: 5,
+                t: t * 10,
+            })
+            .collect();
+
+        let encoder_reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports = encoder_reports.clone();
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, Cursor::new(Vec::new())),
+            EncoderOptions::default(meta.plane),
+        );
+        encoder.set_progress_hook(10, move |info| reports.lock().unwrap().push(info));
+        for event in &events[..5] {
+            encoder.ingest_event(*event).unwrap();
+        }
+        encoder.ingest_events(&events[5..]).unwrap();
+        let output = encoder.close_writer().unwrap().unwrap().into_inner();
+
+        // Reported when the 10th event falls within a batch, and once the stream is closed
+        let encoder_reports = encoder_reports.lock().unwrap();
+        assert_eq!(encoder_reports.len(), 2);
+        assert_eq!(encoder_reports[0].events, 25);
+        assert_eq!(
+            *encoder_reports.last().unwrap(),
+            ProgressInfo {
+                bytes: output.len() as u64,
+                events: 25,
+                t: 250,
+            }
+        );
+
+        let decoder_reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reports = decoder_reports.clone();
+        let mut bitreader = BitReader::endian(Cursor::new(output.clone()), BigEndian);
+        let mut decoder = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        decoder.set_progress_hook(10, move |info| reports.lock().unwrap().push(info));
+        let header_size = decoder.meta().header_size as u64;
+        let event_size = u64::from(decoder.meta().event_size);
+        let decoded: Vec<Event> = decoder
+            .events(&mut bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, events);
+
+        let decoder_reports = decoder_reports.lock().unwrap();
+        assert_eq!(
+            *decoder_reports,
+            vec![
+                ProgressInfo {
+                    bytes: header_size + 10 * event_size,
+                    events: 10,
+                    t: 100,
+                },
+                ProgressInfo {
+                    bytes: header_size + 20 * event_size,
+                    events: 20,
+                    t: 200,
+                },
+                ProgressInfo {
+                    bytes: output.len() as u64,
+                    events: 25,
+                    t: 250,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_order_raw() {
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane: PlaneSize::new(4, 4, 1).unwrap(),
+            ..Default::default()
+        };
+        let event = |x, t| Event {
+            coord: Coord::new_2d(x, 0),
+            d: 5,
+            t,
+        };
+        let events = vec![
+            event(0, 10),
+            event(1, 5),
+            event(0, 8),
+            event(0, 20),
+            event(0, 20),
+        ];
+
+        // The encoder doesn't validate the events by default
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, Cursor::new(Vec::new())),
+            EncoderOptions::default(meta.plane),
+        );
+        encoder.ingest_events(&events).unwrap();
+        let output = encoder.close_writer().unwrap().unwrap().into_inner();
+
+        let mut bitreader = BitReader::endian(Cursor::new(output), BigEndian);
+        let mut decoder = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        decoder.set_validate_order(true).unwrap();
+        let header_size = decoder.meta().header_size as u64;
+        let mut decode = || decoder.digest_event(&mut bitreader);
+        assert_eq!(decode().unwrap(), events[0]);
+        assert_eq!(decode().unwrap(), events[1]);
+        assert!(matches!(
+            decode(),
+            Err(CodecError::OutOfOrderEvent {
+                x: 0,
+                t: 8,
+                last_t: 10,
+                ..
+            })
+        ));
+
+        // Decoding carries on past an invalid event
+        assert_eq!(decode().unwrap(), events[3]);
+        assert!(matches!(
+            decode(),
+            Err(CodecError::DuplicateEvent { x: 0, t: 20, .. })
+        ));
+        assert!(matches!(decode(), Err(CodecError::Eof)));
+
+        // Seeking back starts the validation over
+        decoder
+            .set_input_stream_position(&mut bitreader, header_size)
+            .unwrap();
+        assert_eq!(decoder.digest_event(&mut bitreader).unwrap(), events[0]);
+    }
 }
@@ -0,0 +1,160 @@
+use crate::codec::CodecError;
+use crate::{AbsoluteT, Coord, Event, PixelAddress, PlaneSize, D, D_MAX, D_SHIFT_F64};
+
+/// Merges the events of each `factor`×`factor` neighborhood of pixels into the events of a single
+/// pixel of a smaller plane, so that thumbnails and previews of a large stream can be made without
+/// reconstructing it at full resolution.
+///
+/// Each event adds its pixel's integration (2^D) to its merged pixel, averaged over the pixels of
+/// the neighborhood. The merged pixel fires once its integration reaches that of the incoming
+/// event, with the largest D that its integration covers, and the remainder carries over to its
+/// next event. So, a neighborhood whose pixels all fire with the same D at the same time yields a
+/// single event with that D and time. Events without any integration (e.g.,
+/// [`D_EMPTY`](crate::D_EMPTY) and [`D_ZERO_INTEGRATION`](crate::D_ZERO_INTEGRATION)) are only
+/// passed on if the merged pixel has nothing integrated, and at most once per timestamp.
+///
+/// The events must have absolute timestamps. Those of a merged pixel never go back in time, even
+/// if its neighborhood's events arrive out of order.
+#[derive(Debug, Clone)]
+pub struct Downsampler {
+    factor: PixelAddress,
+    source_plane: PlaneSize,
+    plane: PlaneSize,
+
+    /// The integration of each merged pixel since its last event, and the time of that event
+    pixels: Vec<(f64, Option<AbsoluteT>)>,
+}
+
+impl Downsampler {
+    /// Create a downsampler for events on `source_plane`. A factor of 1 leaves the events
+    /// unchanged.
+    pub fn new(source_plane: PlaneSize, factor: PixelAddress) -> Result<Self, CodecError> {
+        let factor = factor.max(1);
+        let plane = PlaneSize::new(
+            source_plane.w().div_ceil(factor),
+            source_plane.h().div_ceil(factor),
+            source_plane.c(),
+        )?;
+        Ok(Self {
+            factor,
+            source_plane,
+            plane,
+            pixels: vec![(0.0, None); plane.volume()],
+        })
+    }
+
+    /// The width and height of the neighborhoods which are merged
+    pub fn factor(&self) -> PixelAddress {
+        self.factor
+    }
+
+    /// The dimensions of the merged events
+    pub fn plane(&self) -> PlaneSize {
+        self.plane
+    }
+
+    /// Merge an event into its neighborhood's pixel. Returns the merged pixel's event, if it fires.
+    pub fn ingest_event(&mut self, event: Event) -> Option<Event> {
+        if event.coord.x >= self.source_plane.w() || event.coord.y >= self.source_plane.h() {
+            return None;
+        }
+        let coord = Coord {
+            x: event.coord.x / self.factor,
+            y: event.coord.y / self.factor,
+            c: event.coord.c,
+        };
+        let idx = (coord.y_usize() * self.plane.w_usize() + coord.x_usize()) * self.plane.c_usize()
+            + coord.c_usize();
+        let (integration, last_t) = &mut self.pixels[idx];
+        let t = last_t.map_or(event.t, |last_t| event.t.max(last_t));
+
+        if event.d > D_MAX {
+            if *integration > 0.0 || *last_t == Some(t) {
+                return None;
+            }
+            *last_t = Some(t);
+            return Some(Event {
+                coord,
+                d: event.d,
+                t,
+            });
+        }
+
+        // The neighborhoods at the right and bottom edges may be cut short by the plane
+        let width = self
+            .factor
+            .min(self.source_plane.w() - coord.x * self.factor);
+        let height = self
+            .factor
+            .min(self.source_plane.h() - coord.y * self.factor);
+        *integration += D_SHIFT_F64[usize::from(event.d)] / f64::from(width * height);
+        if *integration < D_SHIFT_F64[usize::from(event.d)] {
+            return None;
+        }
+
+        let d = (integration.log2().floor() as D).min(D_MAX);
+        *integration -= D_SHIFT_F64[usize::from(d)];
+        *last_t = Some(t);
+        Some(Event { coord, d, t })
+    }
+
+    /// Forget what each merged pixel has integrated, e.g., after seeking to another part of the
+    /// stream
+    pub fn reset(&mut self) {
+        self.pixels.fill((0.0, None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Downsampler;
+    use crate::{Coord, Event, PlaneSize, D_EMPTY};
+
+    fn event(x: u32, y: u32, d: u8, t: u32) -> Event {
+        Event {
+            coord: Coord { x, y, c: None },
+            d,
+            t,
+        }
+    }
+
+    #[test]
+    fn test_downsample() {
+        let mut downsampler = Downsampler::new(PlaneSize::new(5, 4, 1).unwrap(), 2).unwrap();
+        assert_eq!(downsampler.plane(), PlaneSize::new(3, 2, 1).unwrap());
+
+        // A uniform neighborhood fires once, with the same D
+        assert_eq!(downsampler.ingest_event(event(0, 0, 7, 100)), None);
+        assert_eq!(downsampler.ingest_event(event(1, 0, 7, 100)), None);
+        assert_eq!(downsampler.ingest_event(event(0, 1, 7, 100)), None);
+        assert_eq!(
+            downsampler.ingest_event(event(1, 1, 7, 100)),
+            Some(event(0, 0, 7, 100))
+        );
+
+        // Brighter pixels make the merged pixel fire sooner, and its time never goes back
+        assert_eq!(downsampler.ingest_event(event(2, 3, 8, 300)), None);
+        assert_eq!(
+            downsampler.ingest_event(event(3, 2, 6, 200)),
+            Some(event(1, 1, 6, 200))
+        );
+        assert_eq!(
+            downsampler.ingest_event(event(3, 3, 4, 150)),
+            Some(event(1, 1, 4, 200))
+        );
+
+        // The neighborhoods at the edge are smaller
+        assert_eq!(downsampler.ingest_event(event(4, 0, 5, 100)), None);
+        assert_eq!(
+            downsampler.ingest_event(event(4, 1, 5, 150)),
+            Some(event(2, 0, 5, 150))
+        );
+
+        // Events without integration are passed on once
+        assert_eq!(
+            downsampler.ingest_event(event(0, 2, D_EMPTY, 500)),
+            Some(event(0, 1, D_EMPTY, 500))
+        );
+        assert_eq!(downsampler.ingest_event(event(1, 2, D_EMPTY, 500)), None);
+    }
+}
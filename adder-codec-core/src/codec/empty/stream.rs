@@ -1,5 +1,6 @@
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
+use crate::codec::clock::RefIntervalChange;
 use crate::codec::header::{Magic, MAGIC_RAW};
 use crate::codec::{CodecError, CodecMetadata, WriteCompression};
 use crate::Event;
@@ -44,8 +45,8 @@ impl<W: std::io::Write + std::marker::Send + std::marker::Sync + 'static> WriteC
         Ok(())
     }
 
-    fn into_writer(&mut self) -> Option<W> {
-        None
+    fn into_writer(&mut self) -> Result<Option<W>, CodecError> {
+        Ok(None)
     }
 
     fn flush_writer(&mut self) -> std::io::Result<()> {
@@ -56,6 +57,10 @@ impl<W: std::io::Write + std::marker::Send + std::marker::Sync + 'static> WriteC
         Ok(())
     }
 
+    fn record_ref_interval(&mut self, _change: RefIntervalChange) -> Result<(), CodecError> {
+        Ok(())
+    }
+
     // #[cfg(feature = "compression")]
     // fn ingest_event_debug(&mut self, event: Event) -> Result<Option<Adu>, CodecError> {
     //     todo!()
@@ -1,27 +1,43 @@
+use crate::codec::checkpoint::EncoderCheckpoint;
+use crate::codec::clock::{ClockCorrection, RefIntervalChange};
+use crate::codec::stabilization::StabilizationTransform;
 use crate::codec::{
-    CodecError, CodecMetadata, EncoderOptions, EventDrop, EventOrder, WriteCompression,
-    WriteCompressionEnum,
+    ChannelLayout, CodecError, CodecMetadata, EncoderOptions, EncoderType, EventDrop, EventOrder,
+    EventValidation, ProgressHook, ProgressInfo, ProgressTracker, WriteCompression,
+    WriteCompressionEnum, MAX_USER_METADATA_SIZE,
+};
+use crate::{
+    open_file_decoder, AbsoluteT, ColorSpace, DeltaT, Event, EventSingle, SourceType, TimeMode,
+    D_EMPTY, D_MAX, EOF_EVENT,
 };
-use crate::SourceType::*;
-use crate::{Event, EventSingle, SourceCamera, SourceType, EOF_EVENT};
 use std::collections::BinaryHeap;
 
+use std::fs::{File, OpenOptions};
 use std::io;
-use std::io::{Sink, Write};
+use std::io::{BufWriter, Seek, SeekFrom, Sink, Write};
 use std::time::Instant;
 
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
 #[cfg(feature = "compression")]
 use crate::codec::compressed::stream::CompressedOutput;
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::LzOutput;
 
 use crate::codec::empty::stream::EmptyOutput;
 use crate::codec::header::{
     EventStreamHeader, EventStreamHeaderExtensionV0, EventStreamHeaderExtensionV1,
-    EventStreamHeaderExtensionV2, EventStreamHeaderExtensionV3,
+    EventStreamHeaderExtensionV10, EventStreamHeaderExtensionV11, EventStreamHeaderExtensionV12,
+    EventStreamHeaderExtensionV13, EventStreamHeaderExtensionV14, EventStreamHeaderExtensionV15,
+    EventStreamHeaderExtensionV16, EventStreamHeaderExtensionV17, EventStreamHeaderExtensionV18,
+    EventStreamHeaderExtensionV19, EventStreamHeaderExtensionV2, EventStreamHeaderExtensionV20,
+    EventStreamHeaderExtensionV3, EventStreamHeaderExtensionV4, EventStreamHeaderExtensionV5,
+    EventStreamHeaderExtensionV6, EventStreamHeaderExtensionV7, EventStreamHeaderExtensionV8,
+    EventStreamHeaderExtensionV9,
 };
 
 use crate::codec::raw::stream::RawOutput;
+use crate::codec::validate::check_order;
 use bincode::config::{FixintEncoding, WithOtherEndian, WithOtherIntEncoding};
 use bincode::{DefaultOptions, Options};
 
@@ -40,6 +56,28 @@ struct EncoderState {
     current_event_rate: f64,
     last_event_ts: Instant,
     queue: BinaryHeap<Event>,
+
+    /// The timestamp of the last validated event for each pixel
+    last_t: Vec<Option<AbsoluteT>>,
+
+    /// Events held back by [`EventValidation::Reorder`]
+    validation_queue: BinaryHeap<Event>,
+
+    /// The latest timestamp seen by [`EventValidation::Reorder`]
+    validation_t_max: AbsoluteT,
+
+    /// The events of the current reference interval held back by [`EventDrop::PerFrameBudget`]
+    budget_queue: Vec<Event>,
+
+    /// The index of the reference interval of the events in the `budget_queue`
+    budget_interval: Option<AbsoluteT>,
+
+    /// The delta time of the events dropped by [`EventDrop::PerFrameBudget`] since each pixel's
+    /// last kept event, in a [`TimeMode::DeltaT`] stream
+    budget_carry: Vec<DeltaT>,
+
+    /// Reports the encoder's progress, if a hook is set
+    progress: Option<ProgressTracker>,
 }
 
 impl Default for EncoderState {
@@ -48,6 +86,13 @@ impl Default for EncoderState {
             current_event_rate: 0.0,
             last_event_ts: Instant::now(),
             queue: BinaryHeap::new(),
+            last_t: Vec::new(),
+            validation_queue: BinaryHeap::new(),
+            validation_t_max: 0,
+            budget_queue: Vec::new(),
+            budget_interval: None,
+            budget_carry: Vec::new(),
+            progress: None,
         }
     }
 }
@@ -77,7 +122,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
     where
         Self: Sized,
     {
-        compression.with_options(options);
+        compression.with_options(options.clone());
         let mut encoder = Self {
             output: WriteCompressionEnum::CompressedOutput(compression),
             bincode: DefaultOptions::new()
@@ -91,10 +136,11 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
     }
 
     /// Create a new [`Encoder`] with the given raw compression scheme
-    pub fn new_raw(compression: RawOutput<W>, options: EncoderOptions) -> Self
+    pub fn new_raw(mut compression: RawOutput<W>, options: EncoderOptions) -> Self
     where
         Self: Sized,
     {
+        compression.with_options(options.clone());
         let mut encoder = Self {
             output: WriteCompressionEnum::RawOutput(compression),
             bincode: DefaultOptions::new()
@@ -107,26 +153,33 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         encoder
     }
 
+    /// Create a new [`Encoder`] with the given Zstandard compression scheme
+    #[cfg(feature = "lz")]
+    pub fn new_lz(mut compression: LzOutput<W>, options: EncoderOptions) -> Self
+    where
+        Self: Sized,
+    {
+        compression.with_options(options.clone());
+        let mut encoder = Self {
+            output: WriteCompressionEnum::LzOutput(compression),
+            bincode: DefaultOptions::new()
+                .with_fixint_encoding()
+                .with_big_endian(),
+            options,
+            state: Default::default(),
+        };
+        encoder.encode_header().unwrap();
+        encoder
+    }
+
     /// Returns a reference to the metadata of the underlying compression scheme
     #[inline]
     pub fn meta(&self) -> &CodecMetadata {
         self.output.meta()
     }
 
-    #[allow(clippy::match_same_arms)]
     fn get_source_type(&self) -> SourceType {
-        match self.output.meta().source_camera {
-            SourceCamera::FramedU8 => U8,
-            SourceCamera::FramedU16 => U16,
-            SourceCamera::FramedU32 => U32,
-            SourceCamera::FramedU64 => U64,
-            SourceCamera::FramedF32 => F32,
-            SourceCamera::FramedF64 => F64,
-            SourceCamera::Dvs => U8,
-            SourceCamera::DavisU8 => U8,
-            SourceCamera::Atis => U8,
-            SourceCamera::Asint => F64,
-        }
+        self.output.meta().source_camera.source_type()
     }
 
     /// Signify the end of the file in a unified way
@@ -148,12 +201,45 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         self.output.flush_writer()
     }
 
+    /// Report the encoder's progress to `hook` every `interval` ingested events, and once more
+    /// when the writer is closed. This replaces any previous hook.
+    pub fn set_progress_hook<F>(&mut self, interval: u64, hook: F)
+    where
+        F: FnMut(ProgressInfo) + Send + Sync + 'static,
+    {
+        let hook: ProgressHook = Box::new(hook);
+        self.state.progress = Some(ProgressTracker::new(interval, hook));
+    }
+
+    /// Remove the progress hook, if there is one
+    pub fn clear_progress_hook(&mut self) {
+        self.state.progress = None;
+    }
+
+    /// Count ingested events towards the encoder's progress, and report it if it's due
+    #[inline]
+    fn track_progress(&mut self, events: u64, t: AbsoluteT) {
+        if let Some(progress) = &mut self.state.progress {
+            let t = (self.output.meta().time_mode != TimeMode::DeltaT).then_some(t);
+            if progress.count(events, t) {
+                progress.report(self.output.bytes_written());
+            }
+        }
+    }
+
     /// Close the encoder's writer and return it, consuming the encoder in the process.
     pub fn close_writer(mut self) -> Result<Option<W>, CodecError> {
+        self.flush_validation_queue()?;
+        self.flush_budget_queue()?;
         // self.output.byte_align()?;
         // self.write_eof()?;
         // self.flush_writer()?;
-        Ok(self.output.into_writer())
+        let writer = self.output.into_writer()?;
+        if let Some(progress) = &mut self.state.progress {
+            progress.report(self.output.bytes_written());
+        }
+        self.output.check_round_trip()?;
+        Ok(writer)
         // let compressed_output = self.compressed_output.take();
         // let raw_output = self.raw_output.take();
         //
@@ -177,6 +263,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
             meta.ref_interval,
             meta.delta_t_max,
             meta.codec_version,
+            meta.wide_coordinates,
         );
         self.bincode.serialize_into(&mut buffer, &header)?;
 
@@ -225,12 +312,249 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         if meta.codec_version == 3 {
             return Ok(buffer);
         }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV4 {
+                chroma_subsampling: meta.chroma_subsampling,
+            },
+        )?;
+        if meta.codec_version == 4 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV5 {
+                epoch_ns: meta.epoch.unwrap_or(0),
+            },
+        )?;
+        if meta.codec_version == 5 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV6 {
+                enhancement_layers: meta.enhancement_layers,
+            },
+        )?;
+        if meta.codec_version == 6 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV7 {
+                wide_coordinates: meta.wide_coordinates,
+                width: meta.plane.w(),
+                height: meta.plane.h(),
+            },
+        )?;
+        if meta.codec_version == 7 {
+            return Ok(buffer);
+        }
+
+        // The user metadata has a variable size, so it follows the fixed-size extension which
+        // declares its size
+        let user_metadata = match &self.options.user_metadata {
+            Some(user_metadata) if !user_metadata.is_empty() => {
+                self.bincode.serialize(&**user_metadata)?
+            }
+            _ => Vec::new(),
+        };
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV8 {
+                user_metadata_size: u32::try_from(user_metadata.len())
+                    .ok()
+                    .filter(|size| *size <= MAX_USER_METADATA_SIZE)
+                    .ok_or(CodecError::MalformedEncoder)?,
+            },
+        )?;
+        buffer.extend_from_slice(&user_metadata);
+        if meta.codec_version == 8 {
+            return Ok(buffer);
+        }
+
+        self.bincode
+            .serialize_into(&mut buffer, &EventStreamHeaderExtensionV9 {})?;
+        if meta.codec_version == 9 {
+            return Ok(buffer);
+        }
+
+        self.bincode
+            .serialize_into(&mut buffer, &EventStreamHeaderExtensionV10 {})?;
+        if meta.codec_version == 10 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV11 {
+                motion_compensation: meta.motion_compensation,
+            },
+        )?;
+        if meta.codec_version == 11 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV12 {
+                block_size: u16::try_from(meta.block_size)
+                    .map_err(|_| CodecError::MalformedEncoder)?,
+            },
+        )?;
+        if meta.codec_version == 12 {
+            return Ok(buffer);
+        }
+
+        if meta.d_max > D_MAX {
+            return Err(CodecError::MalformedEncoder);
+        }
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV13 { d_max: meta.d_max },
+        )?;
+        if meta.codec_version == 13 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV14 {
+                planar_channels: meta.channel_layout == ChannelLayout::Planar,
+            },
+        )?;
+        if meta.codec_version == 14 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV15 {
+                context_priors_id: meta.context_priors_id,
+            },
+        )?;
+        if meta.codec_version == 15 {
+            return Ok(buffer);
+        }
+
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV16 {
+                quantization_table: meta.quantization_table,
+            },
+        )?;
+        if meta.codec_version == 16 {
+            return Ok(buffer);
+        }
+
+        if !(meta.intensity_peak.is_finite() && meta.intensity_peak > 0.0) {
+            return Err(CodecError::MalformedEncoder);
+        }
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV17 {
+                intensity_peak: meta.intensity_peak,
+            },
+        )?;
+        if meta.codec_version == 17 {
+            return Ok(buffer);
+        }
+
+        if meta.color_space != ColorSpace::Bgr && meta.plane.c() != 3 {
+            return Err(CodecError::MalformedEncoder);
+        }
+        self.bincode.serialize_into(
+            &mut buffer,
+            &EventStreamHeaderExtensionV18 {
+                color_space: meta.color_space,
+            },
+        )?;
+        if meta.codec_version == 18 {
+            return Ok(buffer);
+        }
+
+        self.bincode
+            .serialize_into(&mut buffer, &EventStreamHeaderExtensionV19 {})?;
+        if meta.codec_version == 19 {
+            return Ok(buffer);
+        }
+
+        self.bincode
+            .serialize_into(&mut buffer, &EventStreamHeaderExtensionV20 {})?;
+        if meta.codec_version == 20 {
+            return Ok(buffer);
+        }
         Err(CodecError::BadFile)
     }
 
     /// Ingest an event
     #[inline(always)]
     pub fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+        self.track_progress(1, event.t);
+
+        // Validation is only meaningful when the events carry absolute timestamps
+        if self.meta().time_mode == TimeMode::DeltaT {
+            return self.ingest_validated_event(event);
+        }
+
+        match self.options.event_validation {
+            EventValidation::None => self.ingest_validated_event(event),
+            EventValidation::Reject => {
+                self.validate_event(&event)?;
+                self.ingest_validated_event(event)
+            }
+            EventValidation::Reorder { window } => {
+                self.state.validation_t_max = self.state.validation_t_max.max(event.t);
+                self.state.validation_queue.push(event);
+
+                // Release the events which have fallen out of the reordering window
+                while let Some(&first) = self.state.validation_queue.peek() {
+                    if first.t.saturating_add(window) >= self.state.validation_t_max {
+                        break;
+                    }
+                    self.state.validation_queue.pop();
+
+                    // Drop the event if it's a duplicate or arrived too late to be corrected
+                    if self.validate_event(&first).is_ok() {
+                        self.ingest_validated_event(first)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Check that the event's timestamp is later than that of the last validated event for its
+    /// pixel. If it is, record it as that pixel's latest timestamp.
+    fn validate_event(&mut self, event: &Event) -> Result<(), CodecError> {
+        let plane = self.meta().plane;
+        if self.state.last_t.len() != plane.volume() {
+            self.state.last_t = vec![None; plane.volume()];
+        }
+
+        let idx = (event.coord.y_usize() * plane.w_usize() + event.coord.x_usize())
+            * plane.c_usize()
+            + event.coord.c_usize();
+        check_order(&mut self.state.last_t[idx], event)
+    }
+
+    /// Release all the events still held back by [`EventValidation::Reorder`]
+    fn flush_validation_queue(&mut self) -> Result<(), CodecError> {
+        while let Some(event) = self.state.validation_queue.pop() {
+            if self.validate_event(&event).is_ok() {
+                self.ingest_validated_event(event)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ingest an event which has already passed validation
+    #[inline(always)]
+    fn ingest_validated_event(&mut self, event: Event) -> Result<(), CodecError> {
         match self.options.event_drop {
             EventDrop::None => {}
             EventDrop::Manual {
@@ -250,8 +574,70 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
             EventDrop::Auto => {
                 todo!()
             }
+            EventDrop::PerFrameBudget { .. } => {
+                if self.meta().time_mode != TimeMode::DeltaT {
+                    // An event fired at the end of an interval belongs to it
+                    let ref_interval = self.meta().ref_interval.max(1);
+                    let interval = event.t.saturating_sub(1) / ref_interval;
+                    match self.state.budget_interval {
+                        Some(current) if interval <= current => {}
+                        _ => {
+                            self.flush_budget_queue()?;
+                            self.state.budget_interval = Some(interval);
+                        }
+                    }
+                }
+                self.state.budget_queue.push(event);
+                return Ok(());
+            }
         }
+        self.ingest_kept_event(event)
+    }
 
+    /// Drop the least significant events of the current reference interval to fit the
+    /// [`EventDrop::PerFrameBudget`], and pass on the rest
+    fn flush_budget_queue(&mut self) -> Result<(), CodecError> {
+        let mut events = std::mem::take(&mut self.state.budget_queue);
+        if let EventDrop::PerFrameBudget { max_events } = self.options.event_drop {
+            let keep = select_budget_events(&events, max_events);
+            if self.meta().time_mode == TimeMode::DeltaT {
+                self.fold_dropped_delta_t(&mut events, &keep);
+            }
+            let mut keep = keep.into_iter();
+            events.retain(|_| keep.next().unwrap_or(false));
+        }
+        for event in events.drain(..) {
+            self.ingest_kept_event(event)?;
+        }
+
+        // Reuse the allocation for the next interval
+        self.state.budget_queue = events;
+        Ok(())
+    }
+
+    /// Add the delta time of each pixel's dropped events to its next kept event, so that the
+    /// timestamps of its later events don't drift when they're decoded
+    fn fold_dropped_delta_t(&mut self, events: &mut [Event], keep: &[bool]) {
+        let plane = self.meta().plane;
+        if self.state.budget_carry.len() != plane.volume() {
+            self.state.budget_carry = vec![0; plane.volume()];
+        }
+        for (event, &keep) in events.iter_mut().zip(keep) {
+            let idx = (event.coord.y_usize() * plane.w_usize() + event.coord.x_usize())
+                * plane.c_usize()
+                + event.coord.c_usize();
+            let carry = &mut self.state.budget_carry[idx];
+            if keep {
+                event.t = event.t.saturating_add(std::mem::take(carry));
+            } else {
+                *carry = carry.saturating_add(event.t);
+            }
+        }
+    }
+
+    /// Pass on an event which wasn't dropped, in the [`EventOrder`] of the options
+    #[inline(always)]
+    fn ingest_kept_event(&mut self, event: Event) -> Result<(), CodecError> {
         match self.options.event_order {
             EventOrder::Unchanged => self.output.ingest_event(event),
             EventOrder::Interleaved => {
@@ -279,8 +665,20 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
 
     /// Ingest an array of events
     ///
+    /// If no events are to be dropped or reordered, the whole array is handed to the underlying
+    /// compression scheme at once, which may process it in bulk.
+    ///
     /// TODO: Make this move events, not by reference
     pub fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        if self.options.event_drop == EventDrop::None
+            && self.options.event_order == EventOrder::Unchanged
+            && self.options.event_validation == EventValidation::None
+        {
+            let result = self.output.ingest_events(events.to_vec());
+            let t_max = events.iter().map(|event| event.t).max().unwrap_or(0);
+            self.track_progress(events.len() as u64, t_max);
+            return result;
+        }
         for event in events {
             self.ingest_event(*event)?;
         }
@@ -292,36 +690,198 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Encoder<W> {
         for v in events {
             self.ingest_events(v)?;
         }
+        if matches!(self.options.event_drop, EventDrop::PerFrameBudget { .. })
+            && self.meta().time_mode == TimeMode::DeltaT
+        {
+            self.flush_budget_queue()?;
+        }
         Ok(())
     }
 
+    /// Record a correction of the stream's clock against the wall clock, e.g., from a
+    /// [`DriftEstimator`](crate::codec::clock::DriftEstimator) for a live source. Only compressed
+    /// streams carry corrections. They keep at most one per Adu.
+    pub fn record_clock(&mut self, correction: ClockCorrection) -> Result<(), CodecError> {
+        self.output.record_clock(correction)
+    }
+
+    /// Record a change of the reference interval over which the source integrates its input, e.g.,
+    /// from a motion-adaptive transcoder. Only compressed streams carry changes, so the other
+    /// formats reject them.
+    pub fn record_ref_interval(&mut self, change: RefIntervalChange) -> Result<(), CodecError> {
+        self.output.record_ref_interval(change)
+    }
+
+    /// Record a change of the shift applied to the input frames by a stabilizing transcoder, so
+    /// that a decoder can un-warp them. Only compressed streams carry transforms.
+    pub fn record_stabilization(
+        &mut self,
+        transform: StabilizationTransform,
+    ) -> Result<(), CodecError> {
+        self.output.record_stabilization(transform)
+    }
+
+    /// End the current Adu early, so that the events after this point go in an Adu which isn't
+    /// predicted from the ones before it, e.g., after a scene cut. Only compressed streams have
+    /// Adus. Events still held back for [`EventOrder::Interleaved`] go in the new Adu.
+    pub fn force_intra_adu(&mut self) -> Result<(), CodecError> {
+        self.output.force_intra_adu()
+    }
+
+    /// Save the encoder's state, so that the stream can be resumed from this point with
+    /// [`Encoder::resume`], e.g., after a crash or an intentional pause. Everything which can be
+    /// written out is, and the writer is flushed. The events which can't be written out yet are
+    /// kept in the checkpoint. Only raw, Lz, and compressed outputs can be checkpointed.
+    ///
+    /// The encoder can go on ingesting events afterwards.
+    pub fn checkpoint(&mut self) -> Result<EncoderCheckpoint, CodecError> {
+        let mut checkpoint = EncoderCheckpoint::new();
+        self.output.checkpoint(&mut checkpoint)?;
+        checkpoint.queued_events = self.state.queue.iter().copied().collect();
+        checkpoint.validation_events = self.state.validation_queue.iter().copied().collect();
+        checkpoint.validation_last_t = self.state.last_t.clone();
+        checkpoint.validation_t_max = self.state.validation_t_max;
+        checkpoint.budget_events = self.state.budget_queue.clone();
+        checkpoint.budget_interval = self.state.budget_interval;
+        checkpoint.budget_carry = self.state.budget_carry.clone();
+        Ok(checkpoint)
+    }
+
     pub fn get_options(&self) -> EncoderOptions {
-        self.options
+        self.options.clone()
     }
 
-    /// Keeps the compressed output options in sync with the encoder options. This prevents us
+    /// Keeps the compressed or raw output options in sync with the encoder options. This prevents us
     /// from constantly having to look up a reference-counted variable, which is costly at this scale.
     pub fn sync_crf(&mut self) {
         match &mut self.output {
             #[cfg(feature = "compression")]
             WriteCompressionEnum::CompressedOutput(compressed_output) => {
-                compressed_output.options = self.options;
+                compressed_output.options = self.options.clone();
+            }
+            WriteCompressionEnum::RawOutput(raw_output) => {
+                raw_output.with_options(self.options.clone());
+            }
+            #[cfg(feature = "lz")]
+            WriteCompressionEnum::LzOutput(lz_output) => {
+                lz_output.with_options(self.options.clone());
             }
-            WriteCompressionEnum::RawOutput(_) => {}
             WriteCompressionEnum::EmptyOutput(_) => {}
         }
     }
 }
 
+impl Encoder<BufWriter<File>> {
+    /// Reopen the stream at `file_path` to go on appending to it from a checkpoint taken by
+    /// [`Encoder::checkpoint`]. Anything written to the file after the checkpoint is discarded.
+    ///
+    /// The stream's header is kept as it is, so the options which the header declares (the Adu
+    /// interval, block size, and number of enhancement layers) are ignored.
+    pub fn resume(
+        file_path: &str,
+        mut options: EncoderOptions,
+        checkpoint: &EncoderCheckpoint,
+    ) -> Result<Self, CodecError> {
+        checkpoint.check_version()?;
+        let (stream, _) = open_file_decoder(file_path)?;
+        let meta = *stream.meta();
+        let encoder_type = stream.get_compression_type();
+
+        let file = OpenOptions::new().write(true).open(file_path)?;
+        if checkpoint.bytes_written < meta.header_size as u64
+            || checkpoint.bytes_written > file.metadata()?.len()
+        {
+            return Err(CodecError::BadFile);
+        }
+        file.set_len(checkpoint.bytes_written)?;
+        let mut writer = BufWriter::new(file);
+        writer.seek(SeekFrom::End(0))?;
+
+        options.adu_interval = None;
+        options.block_size = None;
+        options.enhancement_layers = meta.enhancement_layers;
+
+        let mut output = match encoder_type {
+            #[cfg(feature = "compression")]
+            EncoderType::Compressed => {
+                let mut compression = CompressedOutput::new(meta, writer);
+                compression.with_options(options.clone());
+                WriteCompressionEnum::CompressedOutput(compression)
+            }
+            #[cfg(feature = "lz")]
+            EncoderType::Lz => {
+                let mut compression = LzOutput::new(meta, writer);
+                compression.with_options(options.clone());
+                WriteCompressionEnum::LzOutput(compression)
+            }
+            _ => {
+                let mut compression = RawOutput::with_byte_order(meta, writer, stream.byte_order());
+                compression.with_options(options.clone());
+                WriteCompressionEnum::RawOutput(compression)
+            }
+        };
+        output.resume(checkpoint)?;
+
+        Ok(Self {
+            output,
+            bincode: DefaultOptions::new()
+                .with_fixint_encoding()
+                .with_big_endian(),
+            options,
+            state: EncoderState {
+                queue: checkpoint.queued_events.iter().copied().collect(),
+                last_t: checkpoint.validation_last_t.clone(),
+                validation_queue: checkpoint.validation_events.iter().copied().collect(),
+                validation_t_max: checkpoint.validation_t_max,
+                budget_queue: checkpoint.budget_events.clone(),
+                budget_interval: checkpoint.budget_interval,
+                budget_carry: checkpoint.budget_carry.clone(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// Choose the `max_events` most significant of the events of a reference interval, for
+/// [`EventDrop::PerFrameBudget`]. Empty events are the least significant, then those with the
+/// smallest D. Ties go to the earlier event, then the one with the lower coordinates. Returns
+/// whether each event is kept.
+fn select_budget_events(events: &[Event], max_events: usize) -> Vec<bool> {
+    if events.len() <= max_events {
+        return vec![true; events.len()];
+    }
+    let mut ranked: Vec<usize> = (0..events.len()).collect();
+    ranked.sort_unstable_by_key(|&i| {
+        let event = &events[i];
+        let significance = if event.d == D_EMPTY {
+            0
+        } else {
+            u16::from(event.d) + 1
+        };
+        (
+            std::cmp::Reverse(significance),
+            event.t,
+            event.coord.y,
+            event.coord.x,
+            event.coord.c,
+        )
+    });
+    let mut keep = vec![false; events.len()];
+    for &i in &ranked[..max_events] {
+        keep[i] = true;
+    }
+    keep
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::codec::raw::stream::RawOutput;
+    use crate::codec::decoder::Decoder;
+    use crate::codec::raw::stream::{RawInput, RawOutput};
     use crate::codec::{CodecMetadata, LATEST_CODEC_VERSION};
-    use crate::{Coord, PlaneSize};
-    use bitstream_io::{BigEndian, BitWriter};
-    use std::io::BufWriter;
-    use std::sync::{Arc, RwLock};
+    use crate::{Coord, PixelAddress, PlaneSize, D};
+    use bitstream_io::{BigEndian, BitReader};
+    use std::io::{BufReader, BufWriter, Cursor};
 
     #[test]
     fn raw() {
@@ -339,11 +899,27 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bincode: DefaultOptions::new()
                 .with_fixint_encoding()
                 .with_big_endian(),
             stream: Some(bufwriter),
+            mixed_time: Default::default(),
+            flush_events: None,
+            unflushed_events: 0,
+            bytes_written: 0,
         };
         let encoder = Encoder {
             output: WriteCompressionEnum::RawOutput(compression),
@@ -378,6 +954,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -418,6 +1006,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -444,42 +1044,336 @@ mod tests {
         let mut writer = encoder.close_writer().unwrap().unwrap();
         writer.flush().unwrap();
         let output = writer.into_inner().unwrap();
-        assert_eq!(output.len(), 37 + 22); // 37 bytes for the header, 22 bytes for the 2 events
+        assert_eq!(output.len(), 88 + 22); // 88 bytes for the header, 22 bytes for the 2 events
+    }
+
+    /// A writer which counts how many times it's been flushed
+    #[derive(Default)]
+    struct FlushCounter {
+        data: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
     }
 
     #[test]
-    #[cfg(feature = "compression")]
-    fn compressed() {
-        let output = Vec::new();
-        let bufwriter = BufWriter::new(output);
-        let (written_bytes_tx, written_bytes_rx) = std::sync::mpsc::channel();
+    fn raw_flush_events() {
+        let plane = PlaneSize {
+            width: 8,
+            height: 1,
+            channels: 1,
+        };
+        let compression = RawOutput::new(
+            CodecMetadata {
+                codec_version: LATEST_CODEC_VERSION,
+                header_size: 0,
+                time_mode: Default::default(),
+                plane,
+                tps: 0,
+                ref_interval: 255,
+                delta_t_max: 255,
+                event_size: 0,
+                source_camera: Default::default(),
+                adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
+            },
+            FlushCounter::default(),
+        );
+        let mut options = EncoderOptions::default(plane);
+        options.raw_flush_events = Some(3);
+        let mut encoder = Encoder::new_raw(compression, options);
+        let flushes = |encoder: &Encoder<FlushCounter>| match &encoder.output {
+            WriteCompressionEnum::RawOutput(raw) => raw.stream.as_ref().unwrap().flushes,
+            _ => unreachable!(),
+        };
+        let header_flushes = flushes(&encoder);
 
-        let compression = CompressedOutput {
-            meta: CodecMetadata {
-                codec_version: 0,
+        for x in 0..7 {
+            encoder
+                .ingest_event(Event {
+                    coord: Coord { x, y: 0, c: None },
+                    d: 5,
+                    t: 10,
+                })
+                .unwrap();
+        }
+
+        // The writer was flushed after the 3rd and 6th events
+        assert_eq!(flushes(&encoder) - header_flushes, 2);
+    }
+
+    fn validation_encoder(event_validation: EventValidation) -> Encoder<BufWriter<Vec<u8>>> {
+        let plane = PlaneSize {
+            width: 2,
+            height: 2,
+            channels: 3,
+        };
+        let compression = RawOutput::new(
+            CodecMetadata {
+                codec_version: LATEST_CODEC_VERSION,
                 header_size: 0,
                 time_mode: Default::default(),
-                plane: Default::default(),
+                plane,
                 tps: 0,
-                ref_interval: 0,
-                delta_t_max: 0,
+                ref_interval: 255,
+                delta_t_max: 255,
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
-            // frame: Default::default(),
-            // adu: Adu::new(),
-            // contexts: None,
-            adu: Default::default(),
-            stream: Some(Arc::new(RwLock::new(BitWriter::endian(
-                bufwriter, BigEndian,
-            )))),
-            options: EncoderOptions::default(PlaneSize::default()),
-            written_bytes_tx: Some(written_bytes_tx),
-            last_message_sent: 0,
-            last_message_written: Arc::new(RwLock::new(0)),
-            _phantom: Default::default(),
+            BufWriter::new(Vec::new()),
+        );
+        let mut options = EncoderOptions::default(plane);
+        options.event_validation = event_validation;
+        Encoder::new_raw(compression, options)
+    }
+
+    #[test]
+    fn validation_reject() {
+        let mut encoder = validation_encoder(EventValidation::Reject);
+        let mut event = Event {
+            coord: Coord {
+                x: 1,
+                y: 0,
+                c: Some(2),
+            },
+            d: 0,
+            t: 10,
+        };
+        encoder.ingest_event(event).unwrap();
+
+        // A duplicate of the same event
+        assert!(matches!(
+            encoder.ingest_event(event),
+            Err(CodecError::DuplicateEvent { t: 10, .. })
+        ));
+
+        // An event which goes back in time
+        event.t = 5;
+        assert!(matches!(
+            encoder.ingest_event(event),
+            Err(CodecError::OutOfOrderEvent {
+                t: 5,
+                last_t: 10,
+                ..
+            })
+        ));
+
+        // The same timestamp at a different pixel is fine
+        event.t = 10;
+        event.coord.c = Some(1);
+        encoder.ingest_event(event).unwrap();
+    }
+
+    #[test]
+    fn validation_reorder() {
+        let mut encoder = validation_encoder(EventValidation::Reorder { window: 100 });
+        let event = |x, t| Event {
+            coord: Coord {
+                x,
+                y: 1,
+                c: Some(0),
+            },
+            d: 0,
+            t,
+        };
+        encoder.ingest_event(event(0, 10)).unwrap();
+        encoder.ingest_event(event(0, 5)).unwrap(); // Corrected
+        encoder.ingest_event(event(0, 10)).unwrap(); // Dropped
+        encoder.ingest_event(event(1, 20)).unwrap();
+
+        // Far enough ahead to release the events above, so this one arrives too late
+        encoder.ingest_event(event(1, 200)).unwrap();
+        encoder.ingest_event(event(0, 8)).unwrap(); // Dropped
+
+        let mut writer = encoder.close_writer().unwrap().unwrap();
+        writer.flush().unwrap();
+        let output = writer.into_inner().unwrap();
+        assert_eq!(output.len(), 88 + 11 * 5); // 88 bytes for the header, 4 events + EOF
+    }
+
+    #[test]
+    fn select_budget() {
+        let event = |x, d, t| Event {
+            coord: Coord {
+                x,
+                y: 0,
+                c: Some(0),
+            },
+            d,
+            t,
+        };
+        let events = vec![
+            event(0, 5, 10),
+            event(1, D_EMPTY, 12),
+            event(0, 7, 20),
+            event(1, 5, 8),
+            event(1, 5, 10),
+        ];
+
+        // The empty event goes first, then the latest of the ties
+        assert_eq!(
+            select_budget_events(&events, 3),
+            vec![true, false, true, true, false]
+        );
+    }
+
+    /// Encode `events` with the given time mode and a budget of two events per reference
+    /// interval, passing each batch as one interval, and decode them again
+    fn budget_round_trip(time_mode: TimeMode, batches: &[Vec<Event>]) -> Vec<Event> {
+        let meta = CodecMetadata {
+            time_mode,
+            plane: PlaneSize::new(2, 2, 3).unwrap(),
+            ref_interval: 255,
+            delta_t_max: 255,
+            ..Default::default()
+        };
+        let mut options = EncoderOptions::default(meta.plane);
+        options.event_drop = EventDrop::PerFrameBudget { max_events: 2 };
+        let mut encoder =
+            Encoder::new_raw(RawOutput::new(meta, BufWriter::new(Vec::new())), options);
+        encoder.ingest_events_events(batches).unwrap();
+        let output = encoder
+            .close_writer()
+            .unwrap()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+
+        let mut bitreader = BitReader::endian(BufReader::new(Cursor::new(&*output)), BigEndian);
+        let mut reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        assert_eq!(reader.meta().time_mode, time_mode);
+        reader
+            .events(&mut bitreader)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn budget_event(x: PixelAddress, d: D, t: AbsoluteT) -> Event {
+        Event {
+            coord: Coord {
+                x,
+                y: 1,
+                c: Some(0),
+            },
+            d,
+            t,
+        }
+    }
+
+    #[test]
+    fn per_frame_budget() {
+        // The first reference interval has one event too many, and the empty event is dropped
+        let events = vec![
+            budget_event(0, 5, 10),
+            budget_event(1, D_EMPTY, 100),
+            budget_event(1, 7, 255),
+            budget_event(0, 6, 300),
+            budget_event(1, 6, 400),
+        ];
+        assert_eq!(
+            budget_round_trip(TimeMode::AbsoluteT, &[events]),
+            vec![
+                budget_event(0, 5, 10),
+                budget_event(1, 7, 255),
+                budget_event(0, 6, 300),
+                budget_event(1, 6, 400),
+            ]
+        );
+    }
+
+    #[test]
+    fn per_frame_budget_delta_t() {
+        // Each batch is an interval. The dropped events' delta times are carried to their pixels'
+        // next kept events, within the interval and across intervals.
+        let batches = vec![
+            vec![
+                budget_event(0, 5, 10),
+                budget_event(1, D_EMPTY, 100),
+                budget_event(1, 3, 50),
+                budget_event(1, 7, 105),
+            ],
+            vec![
+                budget_event(0, 2, 40),
+                budget_event(0, 6, 200),
+                budget_event(1, 6, 255),
+            ],
+            vec![budget_event(0, 6, 255)],
+        ];
+        assert_eq!(
+            budget_round_trip(TimeMode::DeltaT, &batches),
+            vec![
+                budget_event(0, 5, 10),
+                budget_event(1, 7, 255),
+                budget_event(0, 6, 240),
+                budget_event(1, 6, 255),
+                budget_event(0, 6, 255),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn compressed() {
+        let output = Vec::new();
+        let bufwriter = BufWriter::new(output);
+        let meta = CodecMetadata {
+            codec_version: 0,
+            header_size: 0,
+            time_mode: Default::default(),
+            plane: Default::default(),
+            tps: 0,
+            ref_interval: 0,
+            delta_t_max: 0,
+            event_size: 0,
+            source_camera: Default::default(),
+            adu_interval: 1,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: 16,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
         };
+        let compression = CompressedOutput::new(meta, bufwriter);
         let _encoder = Encoder {
             output: WriteCompressionEnum::CompressedOutput(compression),
             bincode: DefaultOptions::new()
@@ -507,6 +1401,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: Default::default(),
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -537,6 +1443,18 @@ mod tests {
                 event_size: 0,
                 source_camera: Default::default(),
                 adu_interval: Default::default(),
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
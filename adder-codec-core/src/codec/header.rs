@@ -1,9 +1,15 @@
-use crate::{PlaneSize, SourceCamera, TimeMode};
+use crate::codec::QUANTIZATION_BANDS;
+use crate::{
+    ChromaSubsampling, ColorSpace, NarrowPixelAddress, PixelAddress, PlaneSize, SourceCamera,
+    TimeMode,
+};
 use serde::{Deserialize, Serialize};
 
 pub(crate) type Magic = [u8; 5];
 pub(crate) const MAGIC_RAW: Magic = [97, 100, 100, 101, 114]; // 'adder' in ASCII
+pub(crate) const MAGIC_RAW_LE: Magic = [97, 100, 100, 101, 108]; // 'addel' in ASCII
 pub(crate) const MAGIC_COMPRESSED: Magic = [97, 100, 100, 101, 99]; // 'addec' in ASCII
+pub(crate) const MAGIC_LZ: Magic = [97, 100, 100, 101, 122]; // 'addez' in ASCII
 
 /// ADΔER event stream header
 ///
@@ -14,9 +20,9 @@ pub(crate) const MAGIC_COMPRESSED: Magic = [97, 100, 100, 101, 99]; // 'addec' i
 pub(crate) struct EventStreamHeader {
     pub(crate) magic: Magic,
     pub(crate) version: u8,
-    pub(crate) endianness: u8, // 'b' = big endian
-    pub(crate) width: u16,
-    pub(crate) height: u16,
+    pub(crate) endianness: u8, // 'b' = big endian, 'l' = little-endian raw events
+    pub(crate) width: NarrowPixelAddress,
+    pub(crate) height: NarrowPixelAddress,
     pub(crate) tps: u32,
     pub(crate) ref_interval: u32,
     pub(crate) delta_t_max: u32,
@@ -45,8 +51,137 @@ pub(crate) struct EventStreamHeaderExtensionV3 {
     pub(crate) adu_interval: u32,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV4 {
+    pub(crate) chroma_subsampling: ChromaSubsampling,
+}
+
+/// The wall-clock time of tick 0, in nanoseconds since the Unix epoch (UTC). 0 if unknown.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV5 {
+    pub(crate) epoch_ns: u64,
+}
+
+/// The number of enhancement layers which refine each Adu of a compressed stream. 0 if the stream
+/// isn't layered.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV6 {
+    pub(crate) enhancement_layers: u8,
+}
+
+/// Whether the events have wide (32-bit) pixel addresses, and the full plane dimensions. The
+/// dimensions in the base header are saturated if they don't fit in a [`NarrowPixelAddress`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV7 {
+    pub(crate) wide_coordinates: bool,
+    pub(crate) width: PixelAddress,
+    pub(crate) height: PixelAddress,
+}
+
+/// The size in bytes of the [`UserMetadata`](crate::codec::UserMetadata) which immediately
+/// follows this extension. 0 if there is none.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV8 {
+    pub(crate) user_metadata_size: u32,
+}
+
+/// Marks a stream whose compressed Adus code each cube independently. See
+/// [`INDEPENDENT_CUBES_VERSION`](crate::codec::compressed::INDEPENDENT_CUBES_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV9 {}
+
+/// Marks a stream whose compressed Adus partition their cubes adaptively. See
+/// [`ADAPTIVE_BLOCKS_VERSION`](crate::codec::compressed::ADAPTIVE_BLOCKS_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV10 {}
+
+/// Whether the compressed Adus are predicted from the previous Adu. See
+/// [`MOTION_COMPENSATION_VERSION`](crate::codec::compressed::MOTION_COMPENSATION_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV11 {
+    pub(crate) motion_compensation: bool,
+}
+
+/// The width and height of the cubes of a compressed stream. See
+/// [`CONFIGURABLE_BLOCK_SIZE_VERSION`](crate::codec::compressed::CONFIGURABLE_BLOCK_SIZE_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV12 {
+    pub(crate) block_size: u16,
+}
+
+/// The largest [`D`](crate::D) value of the stream's events. See
+/// [`CONFIGURABLE_D_MAX_VERSION`](crate::codec::compressed::CONFIGURABLE_D_MAX_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV13 {
+    pub(crate) d_max: u8,
+}
+
+/// Whether the color channels of the stream's compressed Adus are laid out as separate planes.
+/// See [`PLANAR_CHANNELS_VERSION`](crate::codec::compressed::PLANAR_CHANNELS_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV14 {
+    pub(crate) planar_channels: bool,
+}
+
+/// The id of the context priors that the arithmetic coder of the stream's compressed Adus was
+/// primed with, or 0 if it starts from flat priors. See
+/// [`CONTEXT_PRIORS_VERSION`](crate::codec::compressed::CONTEXT_PRIORS_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV15 {
+    pub(crate) context_priors_id: u32,
+}
+
+/// The bitshift which each wavelet band of the Δt values of a compressed stream's pixels is
+/// quantized by. See
+/// [`QUANTIZATION_TABLE_VERSION`](crate::codec::compressed::QUANTIZATION_TABLE_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV16 {
+    pub(crate) quantization_table: [u8; QUANTIZATION_BANDS],
+}
+
+/// The source intensity which a full-scale sample stands for. See
+/// [`INTENSITY_PEAK_VERSION`](crate::codec::INTENSITY_PEAK_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV17 {
+    pub(crate) intensity_peak: f32,
+}
+
+/// The color space of the channels. See
+/// [`COLOR_SPACE_VERSION`](crate::codec::COLOR_SPACE_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV18 {
+    pub(crate) color_space: ColorSpace,
+}
+
+/// Marks a stream which may append the changes of its reference interval. See
+/// [`REF_INTERVAL_CHANGES_VERSION`](crate::codec::compressed::REF_INTERVAL_CHANGES_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV19 {}
+
+/// Marks a stream which may append the shifts of its stabilizing transcoder. See
+/// [`STABILIZATION_VERSION`](crate::codec::compressed::STABILIZATION_VERSION).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct EventStreamHeaderExtensionV20 {}
+
 impl HeaderExtension for EventStreamHeaderExtensionV2 {}
 impl HeaderExtension for EventStreamHeaderExtensionV3 {}
+impl HeaderExtension for EventStreamHeaderExtensionV4 {}
+impl HeaderExtension for EventStreamHeaderExtensionV5 {}
+impl HeaderExtension for EventStreamHeaderExtensionV6 {}
+impl HeaderExtension for EventStreamHeaderExtensionV7 {}
+impl HeaderExtension for EventStreamHeaderExtensionV8 {}
+impl HeaderExtension for EventStreamHeaderExtensionV9 {}
+impl HeaderExtension for EventStreamHeaderExtensionV10 {}
+impl HeaderExtension for EventStreamHeaderExtensionV11 {}
+impl HeaderExtension for EventStreamHeaderExtensionV12 {}
+impl HeaderExtension for EventStreamHeaderExtensionV13 {}
+impl HeaderExtension for EventStreamHeaderExtensionV14 {}
+impl HeaderExtension for EventStreamHeaderExtensionV15 {}
+impl HeaderExtension for EventStreamHeaderExtensionV16 {}
+impl HeaderExtension for EventStreamHeaderExtensionV17 {}
+impl HeaderExtension for EventStreamHeaderExtensionV18 {}
+impl HeaderExtension for EventStreamHeaderExtensionV19 {}
+impl HeaderExtension for EventStreamHeaderExtensionV20 {}
 
 impl EventStreamHeader {
     pub(crate) fn new(
@@ -56,28 +191,42 @@ impl EventStreamHeader {
         ref_interval: u32,
         delta_t_max: u32,
         codec_version: u8,
+        wide_coordinates: bool,
     ) -> EventStreamHeader {
         assert!(plane_size.channels > 0);
         assert!(delta_t_max > 0);
         assert!(plane_size.width > 0);
         assert!(plane_size.height > 0);
-        assert!(magic == MAGIC_RAW || magic == MAGIC_COMPRESSED);
+        assert!(
+            magic == MAGIC_RAW
+                || magic == MAGIC_RAW_LE
+                || magic == MAGIC_COMPRESSED
+                || magic == MAGIC_LZ
+        );
 
         EventStreamHeader {
             magic,
             version: codec_version,
-            endianness: 98, // 'b' in ASCII, for big-endian
-            width: plane_size.width,
-            height: plane_size.height,
+            endianness: if magic == MAGIC_RAW_LE {
+                108 // 'l' in ASCII, for little-endian
+            } else {
+                98 // 'b' in ASCII, for big-endian
+            },
+            width: NarrowPixelAddress::try_from(plane_size.width)
+                .unwrap_or(NarrowPixelAddress::MAX),
+            height: NarrowPixelAddress::try_from(plane_size.height)
+                .unwrap_or(NarrowPixelAddress::MAX),
             tps,
             ref_interval,
             delta_t_max,
 
             // Number of bytes each event occupies
-            event_size: match plane_size.channels {
-                1 => 9, // If single-channel, don't need to waste 2 bytes on the c portion
+            event_size: match (plane_size.channels, wide_coordinates) {
+                (1, false) => 9, // If single-channel, don't need to waste 2 bytes on the c portion
                 // for every event
-                _ => 11,
+                (_, false) => 11,
+                (1, true) => 13, // Each pixel address takes 2 more bytes
+                (_, true) => 15,
             },
             channels: plane_size.channels,
         }
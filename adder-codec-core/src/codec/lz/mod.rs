@@ -0,0 +1,2 @@
+/// Zstandard codec
+pub mod stream;
@@ -1,11 +1,21 @@
 #![warn(missing_docs)]
 
+use crate::codec::checkpoint::EncoderCheckpoint;
+use crate::codec::clock::{ClockCorrection, RefIntervalChange};
 use crate::codec::header::Magic;
-use crate::{DeltaT, Event, PlaneSize, SourceCamera, TimeMode};
+use crate::codec::priors::ContextPriors;
+use crate::codec::stabilization::StabilizationTransform;
+use crate::raw_event::ByteOrder;
+use crate::{
+    AbsoluteT, ChromaSubsampling, ColorSpace, DeltaT, Event, PixelAddress, PlaneSize, Roi,
+    SourceCamera, TimeMode, D, D_MAX,
+};
 use bitstream_io::{BigEndian, BitReader};
 use enum_dispatch::enum_dispatch;
+use std::collections::BTreeMap;
 use std::io;
 use std::io::{Read, Seek, Sink, Write};
+use std::sync::Arc;
 
 /// Different options for what to with the events we're given
 #[enum_dispatch(WriteCompression<W>)]
@@ -17,6 +27,10 @@ pub enum WriteCompressionEnum<W: Write + std::marker::Send + std::marker::Sync +
     /// Write the ADΔER stream as raw events
     RawOutput(RawOutput<W>),
 
+    /// Write the ADΔER stream as blocks of raw events, compressed with Zstandard
+    #[cfg(feature = "lz")]
+    LzOutput(LzOutput<W>),
+
     /// An empty output stream. Send all the data into the void.
     EmptyOutput(EmptyOutput<Sink>),
 }
@@ -30,6 +44,10 @@ pub enum EncoderType {
     /// Write the ADΔER stream as raw events
     Raw,
 
+    /// Write the ADΔER stream as blocks of raw events, compressed with Zstandard. This is much
+    /// faster than arithmetic coding, but gives larger streams.
+    Lz,
+
     // /// Write the ADΔER stream as raw events, but make sure that they are ordered perfectly according
     // /// to their firing times
     // RawInterleaved,
@@ -47,31 +65,108 @@ enum ReadCompressionEnum<R: Read + Seek> {
     #[cfg(feature = "compression")]
     CompressedInput(CompressedInput<R>),
     RawInput(RawInput<R>),
+    #[cfg(feature = "lz")]
+    LzInput(LzInput<R>),
 }
 
+/// Asynchronous encoding and decoding over non-blocking streams
+#[cfg(feature = "async")]
+pub mod async_io;
+
+/// Save the state of an encoder, to resume its stream later
+pub mod checkpoint;
+
+/// Align stream timestamps with the wall clock
+pub mod clock;
+
 /// Compressed codec utilities
 #[cfg(feature = "compression")]
 pub mod compressed;
 
+/// Merge the events of each pixel which come too close together, for decoding a lighter stream
+pub mod decimate;
+
 /// ADΔER stream decoder
 pub mod decoder;
 
+/// Merge neighborhoods of pixels, for decoding a smaller preview of a stream
+pub mod downsample;
+
 /// Filler for when generated ADΔER events need not be captured
 pub mod empty;
 
 /// ADΔER stream encoder
 pub mod encoder;
-mod header;
+pub(crate) mod header;
+
+/// Watch a live ADΔER stream for anomalies
+pub mod monitor;
 
 /// Control the quality of ADDER transcoding and compression in a predictable manner
 pub mod rate_controller;
 /// Raw codec utilities
 pub mod raw;
 
+/// Reconstruct frames of intensities from events, for lightweight previews
+pub mod reconstruct;
+
+/// Prime the arithmetic coder of compressed streams with the statistics of earlier streams
+pub mod priors;
+
+/// Salvage the intact events of a damaged stream
+pub mod repair;
+
+/// Feed ADΔER events to several destinations at once
+pub mod sink;
+
+/// The global motion transforms applied by a stabilizing transcoder
+pub mod stabilization;
+
+/// Pipe the events of any decoder into any encoder
+pub mod transcode;
+
+/// Check that each pixel's events are in time order
+pub mod validate;
+
+/// Zstandard codec utilities
+#[cfg(feature = "lz")]
+pub mod lz;
+
+/// Encode and decode in-memory streams, for testing code built on the codec
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// Current latest version of the codec.
 ///
 /// This is the version which will be written to the header.
-pub const LATEST_CODEC_VERSION: u8 = 3;
+pub const LATEST_CODEC_VERSION: u8 = 20;
+
+/// Width and height (same number) of the cubes of a compressed stream, unless its header declares
+/// otherwise. Streams before version 12 always use it.
+pub const DEFAULT_BLOCK_SIZE: usize = 16;
+
+/// The cube sizes which a compressed stream can declare. Smaller cubes adapt to local motion and
+/// detail, while larger ones spend fewer bits on per-cube overhead.
+pub const BLOCK_SIZES: [usize; 3] = [8, 16, 32];
+
+/// The number of frequency bands in a quantization table. Band 0 is the lowest-frequency wavelet
+/// coefficient of a pixel's Δt values, band 1 is the coarsest high band, and so on. Any finer
+/// bands use the shift of the last band. See [`EncoderOptions::quantization_table`].
+pub const QUANTIZATION_BANDS: usize = 8;
+
+/// The largest shift which a quantization table applies to a wavelet coefficient. Larger shifts
+/// are clamped to it.
+pub const MAX_QUANTIZATION_SHIFT: u8 = 16;
+
+/// The first codec version whose header declares the intensity peak of a floating-point (HDR)
+/// source, so that its frames can be reconstructed in the source's own units. See
+/// [`CodecMetadata::intensity_peak`].
+pub const INTENSITY_PEAK_VERSION: u8 = 17;
+
+/// The first codec version whose header declares the color space of the channels, so that
+/// streams transcoded directly from YCbCr planes can be converted back to BGR when they're
+/// reconstructed. See [`CodecMetadata::color_space`].
+pub const COLOR_SPACE_VERSION: u8 = 18;
 
 /// The metadata which stays the same over the course of an ADΔER stream
 #[allow(missing_docs)]
@@ -87,6 +182,18 @@ pub struct CodecMetadata {
     pub event_size: u8,
     pub source_camera: SourceCamera,
     pub adu_interval: usize, // TODO: Allow the adu_interval to be non-constant. Each ADU will encode its own size at its beginning
+    pub chroma_subsampling: ChromaSubsampling,
+    pub epoch: Option<u64>, // Wall-clock time of tick 0, in UTC nanoseconds since the Unix epoch
+    pub enhancement_layers: u8, // Layers refining each compressed Adu, beyond its base layer
+    pub wide_coordinates: bool, // Events have 32-bit pixel addresses. Forced for large planes
+    pub block_size: usize,  // Width and height of the cubes of a compressed stream
+    pub d_max: D,           // Largest D of the events, besides the special symbols. At most D_MAX
+    pub channel_layout: ChannelLayout, // How a compressed stream lays out its color channels
+    pub context_priors_id: u32, // Id of the priors the compressed coder starts from. 0 if flat
+    pub quantization_table: [u8; QUANTIZATION_BANDS], // Shifts of the Δt wavelet bands
+    pub intensity_peak: f32, // Source intensity of a full-scale sample. Above 1.0 for HDR sources
+    pub color_space: ColorSpace, // Color space of the channels of a 3-channel stream
+    pub motion_compensation: bool, // Compressed Adus are predicted from the previous Adu
 }
 
 impl Default for CodecMetadata {
@@ -102,10 +209,96 @@ impl Default for CodecMetadata {
             event_size: 9,
             source_camera: Default::default(),
             adu_interval: 1,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: DEFAULT_BLOCK_SIZE,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
+        }
+    }
+}
+
+impl CodecMetadata {
+    /// Use wide coordinates if the plane is too large for narrow ones. Only the version 7 header
+    /// onward can declare wide coordinates, so a stream which uses them is written with at least
+    /// that version.
+    pub(crate) fn negotiate_coordinates(&mut self) {
+        self.wide_coordinates |= self.plane.needs_wide_coordinates();
+        if self.wide_coordinates {
+            self.codec_version = self.codec_version.max(7);
         }
     }
 }
 
+/// Arbitrary key-value metadata about the acquisition of a stream, such as the camera serial
+/// number, exposure settings, or GPS position. A value may be any text, e.g., a JSON document.
+pub type UserMetadata = BTreeMap<String, String>;
+
+/// The largest size, in bytes, of the serialized [`UserMetadata`] of a stream header. A decoder
+/// rejects a header declaring more, rather than allocating whatever a corrupt header asks for.
+pub const MAX_USER_METADATA_SIZE: u32 = 1 << 20;
+
+/// The progress of an [`Encoder`](encoder::Encoder) or [`Decoder`](decoder::Decoder), as reported
+/// to its progress hook
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ProgressInfo {
+    /// The number of bytes written (when encoding) or read (when decoding) so far, including the
+    /// header. A compressed encoder only counts the Adus which have been written out.
+    pub bytes: u64,
+
+    /// The number of events ingested (when encoding) or decoded (when decoding) so far
+    pub events: u64,
+
+    /// The latest timestamp of the events so far. Always 0 for [`TimeMode::DeltaT`] streams.
+    pub t: AbsoluteT,
+}
+
+/// A callback which receives the progress of an encoder or decoder
+pub type ProgressHook = Box<dyn FnMut(ProgressInfo) + Send + Sync>;
+
+/// Counts the progress of an encoder or decoder, and reports it to a hook every `interval`
+/// events
+pub(crate) struct ProgressTracker {
+    hook: ProgressHook,
+    interval: u64,
+    info: ProgressInfo,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(interval: u64, hook: ProgressHook) -> Self {
+        Self {
+            hook,
+            interval: interval.max(1),
+            info: ProgressInfo::default(),
+        }
+    }
+
+    /// Count some events, with the latest timestamp among them if it's absolute. Returns true if
+    /// a report is due.
+    #[inline]
+    pub(crate) fn count(&mut self, events: u64, t: Option<AbsoluteT>) -> bool {
+        let before = self.info.events / self.interval;
+        self.info.events += events;
+        if let Some(t) = t {
+            self.info.t = self.info.t.max(t);
+        }
+        self.info.events / self.interval > before
+    }
+
+    /// Report the progress so far to the hook
+    pub(crate) fn report(&mut self, bytes: u64) {
+        self.info.bytes = bytes;
+        (self.hook)(self.info);
+    }
+}
+
 /// A trait for writing ADΔER data to a stream.
 #[enum_dispatch]
 pub trait WriteCompression<W: Write + std::marker::Send + std::marker::Sync + 'static> {
@@ -131,8 +324,9 @@ pub trait WriteCompression<W: Write + std::marker::Send + std::marker::Sync + 's
     /// Align the bitstream to the next byte boundary
     fn byte_align(&mut self) -> io::Result<()>;
 
-    /// Consumes the compression stream and returns the underlying writer.
-    fn into_writer(&mut self) -> Option<W>;
+    /// Consumes the compression stream and returns the underlying writer, after writing out
+    /// anything the stream still holds.
+    fn into_writer(&mut self) -> Result<Option<W>, CodecError>;
 
     /// Flush the `BitWriter`. Does not flush the internal `BufWriter`.
     fn flush_writer(&mut self) -> io::Result<()>;
@@ -141,6 +335,73 @@ pub trait WriteCompression<W: Write + std::marker::Send + std::marker::Sync + 's
     /// of the stream (Is it ready to write events? Is it accumulating/reorganizing events? etc.)
     fn ingest_event(&mut self, event: Event) -> Result<(), CodecError>;
 
+    /// Take in a batch of events and process them, in order. By default, this just ingests each
+    /// event individually.
+    fn ingest_events(&mut self, events: Vec<Event>) -> Result<(), CodecError> {
+        for event in events {
+            self.ingest_event(event)?;
+        }
+        Ok(())
+    }
+
+    /// Record a correction of the stream's clock against the wall clock. Formats which can't
+    /// carry corrections ignore them.
+    #[allow(unused_variables)]
+    fn record_clock(&mut self, correction: ClockCorrection) -> Result<(), CodecError> {
+        Ok(())
+    }
+
+    /// End the current Adu early, so that the events after this point (e.g., after a scene cut)
+    /// go in an Adu which isn't predicted from the ones before it. Formats without Adus ignore
+    /// this.
+    fn force_intra_adu(&mut self) -> Result<(), CodecError> {
+        Ok(())
+    }
+
+    /// Record a change of the reference interval over which the source integrates its input.
+    /// Formats which can't carry changes reject them, since their events would be decoded with
+    /// the wrong interval.
+    #[allow(unused_variables)]
+    fn record_ref_interval(&mut self, change: RefIntervalChange) -> Result<(), CodecError> {
+        Err(CodecError::RefIntervalChangesUnsupported)
+    }
+
+    /// Record a change of the shift applied to the input frames by a stabilizing transcoder.
+    /// Formats which can't carry transforms ignore them.
+    #[allow(unused_variables)]
+    fn record_stabilization(
+        &mut self,
+        transform: StabilizationTransform,
+    ) -> Result<(), CodecError> {
+        Ok(())
+    }
+
+    /// The number of bytes written to the stream so far, including the header. Formats which
+    /// don't write anything out report 0.
+    fn bytes_written(&self) -> u64 {
+        0
+    }
+
+    /// Write out what can be written, and save the rest of the state needed to resume the stream
+    /// after it (such as the events which can't be written out yet) to the checkpoint
+    #[allow(unused_variables)]
+    fn checkpoint(&mut self, checkpoint: &mut EncoderCheckpoint) -> Result<(), CodecError> {
+        Err(CodecError::CheckpointUnsupported)
+    }
+
+    /// Restore the state saved by [`WriteCompression::checkpoint`]. The writer must be positioned
+    /// at the end of the data written before the checkpoint.
+    #[allow(unused_variables)]
+    fn resume(&mut self, checkpoint: &EncoderCheckpoint) -> Result<(), CodecError> {
+        Err(CodecError::CheckpointUnsupported)
+    }
+
+    /// Return the first failure of [`RoundTripCheck::Fail`] so far, if there was one. Only
+    /// compressed outputs check their round trip.
+    fn check_round_trip(&self) -> Result<(), CodecError> {
+        Ok(())
+    }
+
     // #[cfg(feature = "compression")]
     // fn ingest_event_debug(&mut self, event: Event) -> Result<Option<Adu>, CodecError>;
 }
@@ -159,6 +420,17 @@ pub trait ReadCompression<R: Read> {
     /// Returns the magic number for the codec
     fn magic(&self) -> Magic;
 
+    /// Accept the magic number read from a stream header, if this codec can read it. A codec
+    /// with several wire profiles picks the one the magic number names.
+    fn negotiate_magic(&mut self, magic: Magic) -> bool {
+        magic == self.magic()
+    }
+
+    /// The byte order of the events in the stream
+    fn byte_order(&self) -> ByteOrder {
+        ByteOrder::Big
+    }
+
     /// Returns a reference to the metadata
     fn meta(&self) -> &CodecMetadata;
 
@@ -189,18 +461,108 @@ pub trait ReadCompression<R: Read> {
         position: u64,
     ) -> Result<(), CodecError>;
 
+    /// Set the input stream position to the start of the data covering the given time, using the
+    /// stream's time index. Returns the timestamp that decoding resumes from, which may be
+    /// earlier than `t`.
+    #[allow(unused_variables)]
+    fn seek_to_time(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        t: AbsoluteT,
+    ) -> Result<AbsoluteT, CodecError> {
+        Err(CodecError::NoTimeIndex)
+    }
+
+    /// The absolute byte position of the first chunk holding only data at or after time `t`, using
+    /// the stream's time index. Returns `None` if there's no time index or no such chunk.
+    #[allow(unused_variables)]
+    fn time_end_position(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        t: AbsoluteT,
+    ) -> Result<Option<u64>, CodecError> {
+        Ok(None)
+    }
+
+    /// The absolute byte positions at which decoding can start afresh, in stream order. Each chunk
+    /// of the stream runs from its position to the next one (or to the end of the stream), and
+    /// can be decoded on its own after [`seek_to_chunk`](Self::seek_to_chunk).
+    fn chunk_positions(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<u64>, CodecError>;
+
+    /// Limit decoding to a region of interest, where the format allows skipping some of the work
+    /// for the rest of the plane. Events outside the region may still be returned. `None` decodes
+    /// the whole plane.
+    #[allow(unused_variables)]
+    fn set_roi(&mut self, roi: Option<Roi>) {}
+
+    /// Limit decoding to a single channel, where the format allows skipping some of the work for
+    /// the other channels. Events of other channels may still be returned. `None` decodes every
+    /// channel.
+    #[allow(unused_variables)]
+    fn set_channel(&mut self, channel: Option<u8>) {}
+
+    /// Limit decoding to the base layer and at most this many enhancement layers of a layered
+    /// stream, for a lower-fidelity preview. `None` decodes every layer.
+    #[allow(unused_variables)]
+    fn set_max_layers(&mut self, max_layers: Option<u8>) {}
+
+    /// Read the clock corrections recorded in the stream, in time order. The reader is left where
+    /// it was. Returns an empty list if the stream has none.
+    #[allow(unused_variables)]
+    fn clock_corrections(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<ClockCorrection>, CodecError> {
+        Ok(Vec::new())
+    }
+
+    /// Read the changes of the reference interval recorded in the stream, in time order. The
+    /// reader is left where it was. Returns an empty list if the stream has none.
+    #[allow(unused_variables)]
+    fn ref_interval_changes(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<RefIntervalChange>, CodecError> {
+        Ok(Vec::new())
+    }
+
+    /// Read the stabilization transforms recorded in the stream, in time order. The reader is
+    /// left where it was. Returns an empty list if the stream has none.
+    #[allow(unused_variables)]
+    fn stabilization_transforms(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<StabilizationTransform>, CodecError> {
+        Ok(Vec::new())
+    }
+
+    /// Set the input stream position to the start of a chunk returned by
+    /// [`chunk_positions`](Self::chunk_positions), resetting any state carried over from the
+    /// previously decoded data
+    fn seek_to_chunk(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+        position: u64,
+    ) -> Result<(), CodecError> {
+        self.set_input_stream_position(reader, position)
+    }
+
     // fn byte_align(&mut self) -> io::Result<()>;
 
     // fn decompress(&self, data: &[u8]) -> Vec<u8>;
 }
 
-// unsafe impl<R: Read> Send for ReadCompression {}
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
 #[cfg(feature = "compression")]
 use crate::codec::compressed::stream::{CompressedInput, CompressedOutput};
 use crate::codec::empty::stream::EmptyOutput;
-use crate::codec::rate_controller::Crf;
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::{LzInput, LzOutput};
+use crate::codec::rate_controller::{Crf, QualityMap};
 use crate::codec::raw::stream::{RawInput, RawOutput};
 use thiserror::Error;
 
@@ -240,6 +602,9 @@ pub enum CodecError {
     #[error("Plane error")]
     PlaneError(#[from] crate::PlaneError),
 
+    #[error("Raw event error")]
+    RawEventError(#[from] crate::raw_event::RawEventError),
+
     // #[cfg(feature = "compression")]
     // #[error("Blocking error")]
     // BlockError(#[from] crate::codec::compressed::blocks::block::BlockError),
@@ -253,6 +618,72 @@ pub enum CodecError {
 
     #[error("No more events to read")]
     NoMoreEvents,
+
+    #[error("Event sink has been closed")]
+    SinkClosed,
+
+    #[error("Stream has no time index")]
+    NoTimeIndex,
+
+    #[error("Adu checksum mismatch (expected {expected:#010x}, found {found:#010x})")]
+    ChecksumMismatch { expected: u32, found: u32 },
+
+    #[error("Compressed Adu data is corrupt")]
+    CorruptAdu,
+
+    #[error("Lost the compressed events from t={start_t} to t={end_t:?}")]
+    AduLost {
+        start_t: AbsoluteT,
+        /// The start of the next intact Adu, or `None` if the stream ends first
+        end_t: Option<AbsoluteT>,
+    },
+
+    #[error("Out-of-order event at ({x}, {y}, {c:?}): t={t} precedes the pixel's last t={last_t}")]
+    OutOfOrderEvent {
+        x: PixelAddress,
+        y: PixelAddress,
+        c: Option<u8>,
+        t: AbsoluteT,
+        last_t: AbsoluteT,
+    },
+
+    #[error("Duplicate event at ({x}, {y}, {c:?}): t={t} was already encoded for this pixel")]
+    DuplicateEvent {
+        x: PixelAddress,
+        y: PixelAddress,
+        c: Option<u8>,
+        t: AbsoluteT,
+    },
+
+    #[error("Event at t={t} arrived after its Adu was compressed (the current Adu starts at t={adu_start_t})")]
+    LateEvent {
+        t: AbsoluteT,
+        adu_start_t: AbsoluteT,
+    },
+
+    #[error("Unsupported time mode for this operation: {0:?}")]
+    UnsupportedTimeMode(TimeMode),
+
+    #[error("This output can't be checkpointed")]
+    CheckpointUnsupported,
+
+    #[error("Unsupported checkpoint version (expected {expected}, found {found})")]
+    UnsupportedCheckpointVersion { expected: u8, found: u8 },
+
+    #[error("This output can't record changes of the reference interval")]
+    RefIntervalChangesUnsupported,
+
+    #[error("Event D value {d} exceeds the stream's maximum of {d_max}")]
+    DOutOfRange { d: D, d_max: D },
+
+    #[error("The compressed Adu at t={start_t} doesn't decode to its events: {detail}")]
+    RoundTripMismatch { start_t: AbsoluteT, detail: String },
+
+    #[error("Plane mismatch (the input is {input:?}, but the output is {output:?})")]
+    PlaneMismatch { input: PlaneSize, output: PlaneSize },
+
+    #[error("Context priors mismatch (the stream needs {expected:#010x}, given {found:#010x})")]
+    ContextPriorsMismatch { expected: u32, found: u32 },
 }
 
 /*
@@ -261,7 +692,7 @@ Encoder options below
 
 /// Options related to encoder controls (what gets encoded and how)
 /// TODO: Move adu_interval into this, rather than be fixed for the whole compressed file
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct EncoderOptions {
     /// Allow the encoder to randomly drop events before compressing, if the event rate is too high
     pub event_drop: EventDrop,
@@ -269,7 +700,121 @@ pub struct EncoderOptions {
     /// Reorder the events according to their firing times
     pub event_order: EventOrder,
 
+    /// Check that each pixel's events are well-ordered before they are encoded
+    pub event_validation: EventValidation,
+
+    /// Append a table of the byte offset and start time of each Adu to the end of a compressed
+    /// stream, so that a decoder can seek to a given time
+    pub time_index: bool,
+
+    /// Store a CRC32 checksum of each Adu in its header in a compressed stream, so that a decoder
+    /// can detect corrupted data
+    pub adu_checksum: bool,
+
+    /// Write a sync marker before each Adu in a compressed stream, so that a decoder can skip a
+    /// damaged Adu and resume decoding at the next one
+    pub adu_sync_markers: bool,
+
+    /// Split each Adu of a compressed stream into a base layer with coarse timestamps, followed
+    /// by this many enhancement layers which each refine them, so that a decoder can produce a
+    /// low-fidelity preview from the first part of each Adu. Decoding every layer restores the
+    /// exact timestamps. At most `compressed::MAX_ENHANCEMENT_LAYERS`; ignored for raw streams.
+    pub enhancement_layers: u8,
+
     pub crf: Crf,
+
+    /// Override the maximum contrast threshold of the [`crf`](Self::crf) for each cube of a
+    /// compressed stream, so that some regions are encoded with less loss than others. Ignored for
+    /// raw streams.
+    pub quality_map: Option<Arc<QualityMap>>,
+
+    /// Cap the maximum contrast threshold of each color channel (R, G, B) of a compressed stream,
+    /// so that some channels are encoded with less loss than others (e.g., green, which carries
+    /// most of the luma). Each channel's threshold is the lower of its cap and the threshold it
+    /// would otherwise get from the [`crf`](Self::crf), [`quality_map`](Self::quality_map), or
+    /// [`target_kbps`](Self::target_kbps). A single-channel stream only uses the first cap.
+    /// Ignored for raw streams.
+    pub channel_c_thresh_max: Option<[u8; 3]>,
+
+    /// Target bitrate of a compressed stream, in kilobits per second. The maximum contrast
+    /// threshold of each Adu is adjusted according to the sizes of the Adus before it, starting
+    /// from that of the [`crf`](Self::crf). Ignored for raw streams.
+    pub target_kbps: Option<u32>,
+
+    /// Key-value metadata about the acquisition, to store in the stream header. Older headers
+    /// (before version 8) can't hold it, so it's dropped. It may take up to
+    /// [`MAX_USER_METADATA_SIZE`] bytes once serialized.
+    pub user_metadata: Option<Arc<UserMetadata>>,
+
+    /// Flush the writer of a raw stream after every this many events, so that a reader sees them
+    /// within a bounded delay. Each flush of a buffered writer costs a write to the underlying
+    /// file or socket, so flushing less often gives higher throughput. If `None`, the events are
+    /// only flushed when the writer's own buffer fills up. Ignored for compressed streams.
+    pub raw_flush_events: Option<u32>,
+
+    /// Override the number of reference intervals spanned by each Adu of a compressed stream.
+    /// Longer Adus give each cube more events to predict from, for a better compression ratio, but
+    /// an Adu is only written out once an event arrives beyond its time range, so the latency
+    /// grows with its length. The stream can only be decoded with the Adu length it was written
+    /// with, which is stored in its header. Ignored for raw streams.
+    pub adu_interval: Option<usize>,
+
+    /// How the timestamps of each pixel's events after its first are coded in a compressed
+    /// stream. It's signaled in the header of each Adu. Streams before version 9 can only use
+    /// predictive coding. Ignored for raw streams.
+    pub delta_t_coding: DeltaTCoding,
+
+    /// A Zstandard dictionary for an Lz stream, e.g., from [`lz::stream::train_dictionary`]. The
+    /// stream can only be decoded with the same dictionary. Ignored for other streams.
+    pub lz_dictionary: Option<Arc<Vec<u8>>>,
+
+    /// Search for the motion of each cube of a compressed stream since the previous Adu, and
+    /// predict the first events of its pixels from the shifted block of that Adu, where it helps.
+    /// Panning footage compresses much better, but each Adu can then only be compressed after
+    /// the one before it, and only decoded in sequence. Streams before version 11 can't use it.
+    /// The header declares it, so a decoder of a stream without it can start at any Adu. Ignored
+    /// for raw streams.
+    pub motion_compensation: bool,
+
+    /// Override the width and height of the cubes of a compressed stream. Smaller cubes suit
+    /// high-motion footage, and larger ones suit static scenes. The size is stored in the header.
+    /// Sizes other than [`BLOCK_SIZES`] are ignored, and streams before version 12 always use
+    /// [`DEFAULT_BLOCK_SIZE`]. Ignored for raw streams.
+    pub block_size: Option<usize>,
+
+    /// Decode each compressed Adu again as soon as it's compressed, and compare its events with
+    /// the ones which were ingested. This catches compression bugs when they happen, rather than
+    /// when the stream is decoded much later, at the cost of roughly doubling the work of
+    /// compressing. Ignored for raw and Lz streams.
+    pub round_trip_check: RoundTripCheck,
+
+    /// Compress the current Adu of a compressed stream early once it holds this many events, and
+    /// carry on with another Adu over the rest of its time range. This bounds the encoder's memory
+    /// for high event rates (e.g., 4K video), at some cost in compression ratio. The bitrate
+    /// control sees each part as a whole Adu. If `None`, an Adu holds all the events of its time
+    /// range. Ignored for raw streams.
+    pub max_adu_events: Option<usize>,
+
+    /// Prime the arithmetic coder of a compressed stream with statistics gathered on earlier
+    /// streams, e.g., from the same camera and scene, rather than with flat priors. Short clips
+    /// compress better. Their [`id`](ContextPriors::id) is stored in the header, and the stream
+    /// can only be decoded with the same priors. Streams before version 15 can't use them.
+    /// Ignored for raw streams.
+    pub context_priors: Option<Arc<ContextPriors>>,
+
+    /// How often a compressed stream with [motion compensation](Self::motion_compensation) codes
+    /// an Adu which isn't predicted from the one before it. A decoder can only join the stream
+    /// (or recover from a lost Adu) at such an Adu, so live streams want frequent refreshes, while
+    /// archives compress better with rare ones. Ignored for raw streams.
+    pub intra_refresh: IntraRefresh,
+
+    /// Override the bitshift which each frequency band of the wavelet coefficients of a compressed
+    /// stream's Δt values is quantized by, with the wavelet modes of
+    /// [`delta_t_coding`](Self::delta_t_coding). Coarser steps for the finer bands trade timing
+    /// jitter for bitrate, for experimenting with perceptually tuned tables. The table is stored
+    /// in the header. See [`QUANTIZATION_BANDS`]. Streams before version 16 code the coefficients
+    /// losslessly. Ignored for raw streams.
+    pub quantization_table: Option<[u8; QUANTIZATION_BANDS]>,
 }
 
 impl EncoderOptions {
@@ -277,11 +822,68 @@ impl EncoderOptions {
         Self {
             event_drop: Default::default(),
             event_order: Default::default(),
+            event_validation: Default::default(),
+            time_index: false,
+            adu_checksum: false,
+            adu_sync_markers: false,
+            enhancement_layers: 0,
             crf: Crf::new(None, plane),
+            quality_map: None,
+            channel_c_thresh_max: None,
+            target_kbps: None,
+            user_metadata: None,
+            raw_flush_events: None,
+            adu_interval: None,
+            delta_t_coding: Default::default(),
+            lz_dictionary: None,
+            motion_compensation: false,
+            block_size: None,
+            round_trip_check: Default::default(),
+            max_adu_events: None,
+            context_priors: None,
+            intra_refresh: Default::default(),
+            quantization_table: None,
         }
     }
 }
 
+/// What to do when a compressed Adu doesn't decode to the events it was compressed from. See
+/// [`EncoderOptions::round_trip_check`].
+///
+/// Timestamps are only compared when the Adu is coded losslessly, i.e., with a maximum contrast
+/// threshold of 0 and no quality map. Otherwise, each pixel must still have the same number of
+/// events, with the same D values.
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub enum RoundTripCheck {
+    /// Don't decode the compressed Adus
+    #[default]
+    None,
+
+    /// Print each mismatch to stderr, and carry on encoding
+    Log,
+
+    /// Return [`CodecError::RoundTripMismatch`] from the next call which ingests events, or from
+    /// [`Encoder::close_writer`](crate::codec::encoder::Encoder::close_writer)
+    Fail,
+}
+
+/// When a compressed stream with motion compensation starts afresh with an Adu which isn't
+/// predicted from the one before it. See [`EncoderOptions::intra_refresh`]. The first Adu is
+/// never predicted.
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub enum IntraRefresh {
+    /// Predict every Adu after the first from the one before it
+    #[default]
+    Never,
+
+    /// Refresh with the first Adu starting at least this many ticks after the last refresh
+    Time(AbsoluteT),
+
+    /// Refresh with the first Adu after at least this many events were compressed since the last
+    /// refresh
+    Events(u64),
+}
+
 /// Allow the encoder to randomly drop events before compressing, if the event rate is too high
 #[derive(Default, Copy, Clone, PartialEq, Debug)]
 pub enum EventDrop {
@@ -300,6 +902,25 @@ pub enum EventDrop {
     /// TODO: Implement this. Query the actual network bandwidth accoring to some stream handle
     /// and drop events accordingly.
     Auto,
+
+    /// Cap the number of events in each reference interval, for links with a fixed packet
+    /// budget. The events of an interval are held back until it ends, and if there are too many,
+    /// the least significant ones are dropped: empty events first, then those with the smallest
+    /// D. Ties go to the earlier event, then the one with the lower coordinates, so the same
+    /// events are always kept. The kept events stay in the order they were ingested in.
+    ///
+    /// An event's interval is found from its absolute timestamp. In
+    /// [`TimeMode::DeltaT`](crate::TimeMode::DeltaT), where events don't carry one, each batch
+    /// passed to [`Encoder::ingest_events_events`](encoder::Encoder::ingest_events_events) is an
+    /// interval, as the transcoder passes the events of one interval at a time. There, the delta
+    /// time of a dropped event is added to that of its pixel's next kept event, so the pixel's
+    /// later events still decode to the right times. [`TimeMode::Mixed`](crate::TimeMode::Mixed)
+    /// streams are encoded from the absolute timestamps of the kept events, so they need no such
+    /// correction.
+    PerFrameBudget {
+        /// The maximum number of events in each reference interval
+        max_events: usize,
+    },
 }
 
 /// Reorder the events according to their firing times
@@ -312,3 +933,56 @@ pub enum EventOrder {
     /// Reorder the events according to their firing times
     Interleaved,
 }
+
+/// How the timestamps of each pixel's events after its first are coded in an Adu of a compressed
+/// stream
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DeltaTCoding {
+    /// Predict each timestamp from the pixel's previous events, and code the residual. The
+    /// residuals may be quantized, according to the [`Crf`](rate_controller::Crf).
+    #[default]
+    Predictive,
+
+    /// Code the pixel's sequence of Δt values with a multi-level integer Haar wavelet transform.
+    /// The timestamps are coded losslessly, except in the base layer of a layered stream.
+    Haar,
+
+    /// Like [`DeltaTCoding::Haar`], but with the reversible CDF 5/3 wavelet of JPEG 2000. Its
+    /// longer filters compact the piecewise-smooth Δt sequences of slowly varying regions better.
+    Cdf53,
+}
+
+/// How the color channels of a compressed stream are laid out in each Adu. See
+/// [`PLANAR_CHANNELS_VERSION`](compressed::PLANAR_CHANNELS_VERSION).
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChannelLayout {
+    /// Each cube codes all the channels of its pixels together
+    #[default]
+    Interleaved,
+
+    /// Each channel is coded as a separate plane of cubes, one plane after another. A decoder
+    /// interested in a single channel only has to decode that channel's plane. See
+    /// [`Decoder::set_channel`](decoder::Decoder::set_channel).
+    Planar,
+}
+
+/// Check that each pixel's events arrive with strictly increasing timestamps. A buggy source may
+/// produce duplicate or out-of-order events, which would otherwise only cause a failure much later
+/// when the stream is decoded.
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub enum EventValidation {
+    /// Don't validate the events
+    #[default]
+    None,
+
+    /// Return an error as soon as a duplicate or out-of-order event is ingested
+    Reject,
+
+    /// Hold events in a buffer spanning `window` ticks and release them in timestamp order, thus
+    /// correcting any pixel's events which arrive out of order within that window. Duplicate
+    /// events, and events which arrive too late to be corrected, are dropped.
+    Reorder {
+        /// The number of ticks to buffer events for before releasing them
+        window: DeltaT,
+    },
+}
@@ -0,0 +1,365 @@
+use crate::codec::CodecError;
+use crate::{AbsoluteT, DeltaT, Event};
+use std::fmt;
+
+/// How strongly each new window pulls the [`HealthMonitor`]'s running average of the event rate
+const RATE_SMOOTHING: f64 = 0.1;
+
+/// The conditions under which a [`HealthMonitor`] raises a [`HealthAlert`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HealthThresholds {
+    /// The length of each window of time over which the event rate is measured, in ticks. This is
+    /// typically the length of an Adu.
+    pub window: DeltaT,
+
+    /// Alert when a window has fewer than this fraction of the running average number of events
+    pub rate_collapse_ratio: f64,
+
+    /// Only check for a collapse of the event rate once the running average has at least this
+    /// many events per window, so that a sparse stream doesn't raise false alarms
+    pub min_average_events: f64,
+
+    /// Alert when this many consecutive windows have no events
+    pub empty_windows: u32,
+
+    /// Alert when the event timestamps haven't advanced for this many consecutive batches
+    pub stall_batches: u32,
+}
+
+impl HealthThresholds {
+    /// Create thresholds with the default sensitivity, measuring the event rate over windows of
+    /// `window` ticks
+    pub fn new(window: DeltaT) -> Self {
+        Self {
+            window: window.max(1),
+            rate_collapse_ratio: 0.1,
+            min_average_events: 100.0,
+            empty_windows: 3,
+            stall_batches: 30,
+        }
+    }
+}
+
+/// An anomaly detected in a live ADΔER stream, which may indicate that the capture has silently
+/// failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthAlert {
+    /// The window starting at `t` had far fewer events than the running average
+    EventRateCollapse {
+        /// The start of the window
+        t: AbsoluteT,
+
+        /// The number of events in the window
+        events: u64,
+
+        /// The running average number of events per window, before this one
+        average: f64,
+    },
+
+    /// Several consecutive windows had no events
+    EmptyWindows {
+        /// The start of the last empty window
+        t: AbsoluteT,
+
+        /// The number of consecutive empty windows
+        count: u32,
+    },
+
+    /// The event timestamps have stopped advancing
+    TimestampStall {
+        /// The latest event timestamp
+        t: AbsoluteT,
+
+        /// The number of consecutive batches which haven't advanced past `t`
+        batches: u32,
+    },
+
+    /// The encoder or another sink failed to write out the events
+    SinkFailure {
+        /// The error which the sink returned
+        message: String,
+    },
+}
+
+impl fmt::Display for HealthAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthAlert::EventRateCollapse { t, events, average } => write!(
+                f,
+                "event rate collapsed to {events} events in the window at t={t} (average {average:.1})"
+            ),
+            HealthAlert::EmptyWindows { t, count } => {
+                write!(f, "{count} consecutive empty windows, up to t={t}")
+            }
+            HealthAlert::TimestampStall { t, batches } => {
+                write!(f, "timestamps stalled at t={t} for {batches} batches")
+            }
+            HealthAlert::SinkFailure { message } => write!(f, "sink failure: {message}"),
+        }
+    }
+}
+
+/// A callback which receives each [`HealthAlert`] raised by a [`HealthMonitor`]
+pub type HealthAlertCallback = Box<dyn FnMut(&HealthAlert) + Send>;
+
+/// Watches the batches of events of a live stream for anomalies, such as the event rate
+/// collapsing, windows with no events, timestamps which stop advancing, or failures to write out
+/// the events. Each anomaly raises a single [`HealthAlert`] when it begins, which is passed to the
+/// alert callback, or logged to stderr if there is none.
+///
+/// The events must have absolute timestamps.
+pub struct HealthMonitor {
+    thresholds: HealthThresholds,
+    callback: Option<HealthAlertCallback>,
+
+    /// The index of the window currently being counted, if any event has been seen
+    window_idx: Option<AbsoluteT>,
+    window_events: u64,
+    average_events: Option<f64>,
+    empty_run: u32,
+    collapsed: bool,
+
+    /// The latest event timestamp seen so far
+    last_t: Option<AbsoluteT>,
+    stall_run: u32,
+
+    alert_count: u64,
+}
+
+impl HealthMonitor {
+    /// Create a new monitor with the given thresholds
+    pub fn new(thresholds: HealthThresholds) -> Self {
+        Self {
+            thresholds,
+            callback: None,
+            window_idx: None,
+            window_events: 0,
+            average_events: None,
+            empty_run: 0,
+            collapsed: false,
+            last_t: None,
+            stall_run: 0,
+            alert_count: 0,
+        }
+    }
+
+    /// Send each alert to the given callback, rather than logging it to stderr
+    pub fn on_alert(mut self, callback: HealthAlertCallback) -> Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    /// The number of alerts raised so far
+    pub fn alert_count(&self) -> u64 {
+        self.alert_count
+    }
+
+    /// Observe the next batch of events of the stream, in order. A batch is whatever unit the
+    /// stream is produced in, e.g., the events from one input frame.
+    pub fn observe(&mut self, events: &[Event]) {
+        let batch_last_t = events.iter().map(|event| event.t).max();
+        match (batch_last_t, self.last_t) {
+            (Some(t), Some(last_t)) if t <= last_t => self.stall(last_t),
+            (Some(t), _) => {
+                self.last_t = Some(t);
+                self.stall_run = 0;
+            }
+            (None, Some(last_t)) => self.stall(last_t),
+            (None, None) => {}
+        }
+
+        for event in events {
+            let idx = event.t / self.thresholds.window;
+            match self.window_idx {
+                None => self.window_idx = Some(idx),
+                Some(current) if idx > current => {
+                    self.close_window(current);
+
+                    // Every window skipped over had no events
+                    let skipped = idx - current - 1;
+                    if skipped > 0 {
+                        self.empty_windows(idx - 1, skipped);
+                    }
+                    self.window_idx = Some(idx);
+                }
+                // Events which arrive late are counted in the current window
+                Some(_) => {}
+            }
+            self.window_events += 1;
+        }
+    }
+
+    /// Observe that the encoder or a sink failed to write out the events
+    pub fn observe_error(&mut self, error: &CodecError) {
+        self.alert(HealthAlert::SinkFailure {
+            message: error.to_string(),
+        });
+    }
+
+    fn stall(&mut self, t: AbsoluteT) {
+        self.stall_run = self.stall_run.saturating_add(1);
+        if self.stall_run == self.thresholds.stall_batches {
+            self.alert(HealthAlert::TimestampStall {
+                t,
+                batches: self.stall_run,
+            });
+        }
+    }
+
+    /// Finish counting the events of the window with the given index
+    fn close_window(&mut self, idx: AbsoluteT) {
+        let events = std::mem::take(&mut self.window_events);
+        if events == 0 {
+            self.empty_windows(idx, 1);
+            return;
+        }
+        self.empty_run = 0;
+
+        if let Some(average) = self.average_events {
+            let collapsed = average >= self.thresholds.min_average_events
+                && (events as f64) < average * self.thresholds.rate_collapse_ratio;
+            if collapsed && !self.collapsed {
+                self.alert(HealthAlert::EventRateCollapse {
+                    t: idx * self.thresholds.window,
+                    events,
+                    average,
+                });
+            }
+            self.collapsed = collapsed;
+        }
+        self.average_events = Some(match self.average_events {
+            None => events as f64,
+            Some(average) => average + RATE_SMOOTHING * (events as f64 - average),
+        });
+    }
+
+    /// Record `count` consecutive empty windows, ending with the window with index `last_idx`
+    fn empty_windows(&mut self, last_idx: AbsoluteT, count: AbsoluteT) {
+        let threshold = self.thresholds.empty_windows;
+        let before = self.empty_run;
+        self.empty_run = self.empty_run.saturating_add(count);
+        if before < threshold && self.empty_run >= threshold {
+            self.alert(HealthAlert::EmptyWindows {
+                t: last_idx * self.thresholds.window,
+                count: self.empty_run,
+            });
+        }
+    }
+
+    fn alert(&mut self, alert: HealthAlert) {
+        self.alert_count += 1;
+        match &mut self.callback {
+            Some(callback) => callback(&alert),
+            None => eprintln!("Stream health alert: {alert}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Coord;
+    use std::sync::mpsc::channel;
+
+    /// A batch of `n` events, evenly spread over the window starting at `start_t`
+    fn batch(start_t: AbsoluteT, n: u32) -> Vec<Event> {
+        (0..n)
+            .map(|i| Event {
+                coord: Coord {
+                    x: 0,
+                    y: 0,
+                    c: None,
+                },
+                d: 7,
+                t: start_t + i * 1000 / n.max(1),
+            })
+            .collect()
+    }
+
+    fn monitor() -> (HealthMonitor, std::sync::mpsc::Receiver<HealthAlert>) {
+        let (tx, rx) = channel();
+        let monitor = HealthMonitor::new(HealthThresholds::new(1000)).on_alert(Box::new(
+            move |alert: &HealthAlert| {
+                tx.send(alert.clone()).unwrap();
+            },
+        ));
+        (monitor, rx)
+    }
+
+    #[test]
+    fn healthy_stream() {
+        let (mut monitor, rx) = monitor();
+        for i in 0..20 {
+            monitor.observe(&batch(i * 1000, 500));
+        }
+        assert_eq!(monitor.alert_count(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn rate_collapse() {
+        let (mut monitor, rx) = monitor();
+        for i in 0..10 {
+            monitor.observe(&batch(i * 1000, 500));
+        }
+        // Only alert once while the rate stays low
+        for i in 10..15 {
+            monitor.observe(&batch(i * 1000, 10));
+        }
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HealthAlert::EventRateCollapse {
+                t: 10000,
+                events: 10,
+                average: 500.0
+            }
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn empty_windows() {
+        let (mut monitor, rx) = monitor();
+        monitor.observe(&batch(0, 500));
+        monitor.observe(&batch(1000, 500));
+
+        // Windows 2 through 5 are empty
+        monitor.observe(&batch(6000, 500));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HealthAlert::EmptyWindows { t: 5000, count: 4 }
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn timestamp_stall() {
+        let (mut monitor, rx) = monitor();
+        monitor.observe(&batch(0, 500));
+        for _ in 0..HealthThresholds::new(1000).stall_batches + 5 {
+            monitor.observe(&[]);
+        }
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            HealthAlert::TimestampStall {
+                t: 998,
+                batches: 30
+            }
+        );
+        assert!(rx.try_recv().is_err());
+
+        // The stall is over once the timestamps advance again
+        monitor.observe(&batch(1000, 500));
+        assert_eq!(monitor.alert_count(), 1);
+    }
+
+    #[test]
+    fn sink_failure() {
+        let (mut monitor, rx) = monitor();
+        monitor.observe_error(&CodecError::SinkClosed);
+        assert!(matches!(
+            rx.try_recv().unwrap(),
+            HealthAlert::SinkFailure { .. }
+        ));
+    }
+}
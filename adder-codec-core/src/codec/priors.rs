@@ -0,0 +1,240 @@
+use crate::codec::CodecError;
+use bincode::{DefaultOptions, Options};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+#[cfg(feature = "compression")]
+use crate::codec::compressed::stream::CompressedInput;
+#[cfg(feature = "compression")]
+use crate::codec::decoder::Decoder;
+#[cfg(feature = "compression")]
+use bitstream_io::{BigEndian, BitReader};
+#[cfg(feature = "compression")]
+use std::io::Seek;
+#[cfg(feature = "compression")]
+use std::sync::{Arc, Mutex};
+
+/// The most weight that the gathered counts of each context add to its default weights when
+/// priming it. The coder adapts to the stream from there, so this bounds how long a prior which
+/// doesn't fit the stream takes to be outweighed.
+const PRIOR_WEIGHT: u64 = 1 << 12;
+
+/// The statistics of the residual symbols of compressed streams, for priming the arithmetic coder
+/// of another stream with them rather than with flat priors. Short clips from the same camera and
+/// scene as the streams they were gathered on compress notably better, since the coder doesn't
+/// have to learn the statistics afresh in every Adu.
+///
+/// Gather the statistics from a compressed stream with [`ContextPriors::gather`] (and
+/// [`ContextPriors::merge`] those of several streams), or read a priors file written by
+/// [`ContextPriors::write_to`]. Prime a stream with them through
+/// [`EncoderOptions::context_priors`](crate::codec::EncoderOptions::context_priors). Its header
+/// records the priors' [`id`](ContextPriors::id), and it can only be decoded with the same priors,
+/// given to [`CompressedInput::with_context_priors`](crate::codec::compressed::stream::CompressedInput::with_context_priors).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContextPriors {
+    /// The number of times each D residual symbol was coded
+    pub(crate) d_counts: Vec<u64>,
+
+    /// The number of times each timestamp residual symbol (one byte of a residual) was coded
+    pub(crate) t_counts: Vec<u64>,
+}
+
+impl ContextPriors {
+    /// The number of symbols that the statistics were gathered from
+    pub fn symbols(&self) -> u64 {
+        self.d_counts.iter().chain(self.t_counts.iter()).sum()
+    }
+
+    /// Returns true if no symbols were gathered, so the priors are flat
+    pub fn is_empty(&self) -> bool {
+        self.symbols() == 0
+    }
+
+    /// Identifies the priors in the header of a stream primed with them, so that a decoder can
+    /// check that it has the right ones. Never 0, which marks a stream with flat priors.
+    pub fn id(&self) -> u32 {
+        // 32-bit FNV-1a hash of the counts
+        let hash = self
+            .d_counts
+            .iter()
+            .chain(self.t_counts.iter())
+            .flat_map(|count| count.to_be_bytes())
+            .fold(0x811C_9DC5_u32, |hash, byte| {
+                (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+            });
+        hash.max(1)
+    }
+
+    /// Add the statistics gathered on another stream
+    pub fn merge(&mut self, other: &ContextPriors) {
+        self.add(&other.d_counts, &other.t_counts);
+    }
+
+    /// Add the counts of some more coded symbols
+    pub(crate) fn add(&mut self, d_counts: &[u64], t_counts: &[u64]) {
+        for (counts, more) in [
+            (&mut self.d_counts, d_counts),
+            (&mut self.t_counts, t_counts),
+        ] {
+            if counts.len() < more.len() {
+                counts.resize(more.len(), 0);
+            }
+            for (count, more) in counts.iter_mut().zip(more) {
+                *count += more;
+            }
+        }
+    }
+
+    /// Write the priors, e.g., to a priors file kept alongside the streams
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), CodecError> {
+        Ok(bincode_options().serialize_into(writer, self)?)
+    }
+
+    /// Read priors written by [`ContextPriors::write_to`]
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, CodecError> {
+        Ok(bincode_options().deserialize_from(reader)?)
+    }
+
+    /// Gather the statistics of a compressed stream by decoding it. A stream which was itself
+    /// primed must be given the priors it was primed with.
+    #[cfg(feature = "compression")]
+    pub fn gather<R: Read + Seek>(
+        reader: R,
+        priors: Option<Arc<ContextPriors>>,
+    ) -> Result<Self, CodecError> {
+        let mut reader = BitReader::endian(reader, BigEndian);
+        let statistics = Arc::new(Mutex::new(ContextPriors::default()));
+        let mut input = CompressedInput::new(0, 0, 0);
+        if let Some(priors) = priors {
+            input = input.with_context_priors(priors);
+        }
+        input.statistics = Some(statistics.clone());
+
+        let mut decoder = Decoder::new_compressed(input, &mut reader)?;
+        for event in decoder.events(&mut reader) {
+            event?;
+        }
+        let priors = statistics.lock().unwrap().clone();
+        Ok(priors)
+    }
+}
+
+/// The weights to start a context from, primed with the gathered `counts` of its symbols. The
+/// counts are scaled down to at most [`PRIOR_WEIGHT`] in total and added to the `defaults`, so
+/// every symbol keeps some probability, except the ones which the defaults rule out.
+pub(crate) fn primed_counts(defaults: &[u64], counts: &[u64]) -> Vec<u64> {
+    let total: u64 = counts.iter().sum();
+    let scale = total.div_ceil(PRIOR_WEIGHT).max(1);
+    defaults
+        .iter()
+        .enumerate()
+        .map(|(symbol, &default)| match default {
+            0 => 0,
+            _ => default + counts.get(symbol).map_or(0, |count| count / scale),
+        })
+        .collect()
+}
+
+fn bincode_options() -> impl Options {
+    DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{primed_counts, ContextPriors, PRIOR_WEIGHT};
+    use crate::codec::CodecError;
+
+    #[test]
+    fn test_context_priors() -> Result<(), CodecError> {
+        let mut priors = ContextPriors::default();
+        assert!(priors.is_empty());
+        priors.add(&[0, 3, 1], &[5]);
+        priors.merge(&ContextPriors {
+            d_counts: vec![1],
+            t_counts: vec![0, 2],
+        });
+        assert_eq!(priors.d_counts, [1, 3, 1]);
+        assert_eq!(priors.t_counts, [5, 2]);
+        assert_eq!(priors.symbols(), 12);
+        assert_ne!(priors.id(), ContextPriors::default().id());
+
+        let mut bytes = Vec::new();
+        priors.write_to(&mut bytes)?;
+        assert_eq!(ContextPriors::read_from(&*bytes)?, priors);
+
+        // Symbols ruled out by the defaults stay ruled out, and large counts are scaled down
+        assert_eq!(primed_counts(&[1, 0, 2], &[4, 4]), [5, 0, 2]);
+        let primed = primed_counts(&[1, 1], &[PRIOR_WEIGHT * 3, PRIOR_WEIGHT]);
+        assert_eq!(primed, [PRIOR_WEIGHT * 3 / 4 + 1, PRIOR_WEIGHT / 4 + 1]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_primed_stream() -> Result<(), CodecError> {
+        use crate::codec::compressed::stream::CompressedInput;
+        use crate::codec::decoder::Decoder;
+        use crate::codec::testing::{decode_from_vec, encode_to_vec, sorted_by_pixel};
+        use crate::codec::{CodecMetadata, EncoderOptions, EncoderType};
+        use crate::{Coord, Event, PlaneSize, TimeMode};
+        use bitstream_io::{BigEndian, BitReader};
+        use std::io::Cursor;
+        use std::sync::Arc;
+
+        // Two short clips of the same scene, with slightly different timing
+        let clip = |offset: u32| -> Vec<Event> {
+            let mut events = Vec::new();
+            for interval in 0..8 {
+                for y in 0..16 {
+                    for x in 0..16 {
+                        events.push(Event {
+                            coord: Coord { x, y, c: None },
+                            d: 7 + (x % 2) as u8,
+                            t: interval * 255 + u32::from((x + y) % 4) + offset,
+                        });
+                    }
+                }
+            }
+            events.sort_by_key(|event| event.t);
+            events
+        };
+        let plane = PlaneSize::new(16, 16, 1)?;
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255,
+            adu_interval: 1,
+            ..Default::default()
+        };
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+
+        let earlier = encode_to_vec(EncoderType::Compressed, meta, options.clone(), &clip(0))?;
+        let priors = Arc::new(ContextPriors::gather(Cursor::new(earlier), None)?);
+        assert!(!priors.is_empty());
+        let id = priors.id();
+
+        let flat = encode_to_vec(EncoderType::Compressed, meta, options.clone(), &clip(1))?;
+        options.context_priors = Some(priors.clone());
+        let primed = encode_to_vec(EncoderType::Compressed, meta, options, &clip(1))?;
+        assert!(primed.len() < flat.len());
+
+        // The primed stream can't be decoded without its priors
+        assert!(matches!(
+            decode_from_vec(primed.clone()),
+            Err(CodecError::ContextPriorsMismatch { expected, found: 0 }) if expected == id
+        ));
+
+        let mut reader = BitReader::endian(Cursor::new(primed), BigEndian);
+        let input = CompressedInput::new(0, 0, 0).with_context_priors(priors);
+        let decoder = Decoder::new_compressed(input, &mut reader)?;
+        assert_eq!(decoder.meta().context_priors_id, id);
+        let decoded = decoder.into_events(reader).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(sorted_by_pixel(decoded), sorted_by_pixel(clip(1)));
+        Ok(())
+    }
+}
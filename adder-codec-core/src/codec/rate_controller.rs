@@ -1,4 +1,6 @@
-use crate::PlaneSize;
+use crate::codec::DEFAULT_BLOCK_SIZE;
+use crate::{AbsoluteT, Event, PixelAddress, PlaneSize, Roi};
+use ndarray::{Array2, Array3};
 
 /// Constant Rate Factor lookup table
 #[rustfmt::skip]
@@ -110,3 +112,331 @@ impl Crf {
         self.crf_quality
     }
 }
+
+/// Width and height (same number) of the blocks of a [`QualityMap`]. This matches the default size
+/// of the compressed encoder's event cubes, so each block sets the quality of exactly one cube. A
+/// cube of another size takes the quality of the block holding its top-left pixel.
+pub const QUALITY_BLOCK_SIZE: usize = DEFAULT_BLOCK_SIZE;
+
+/// A per-block override of the maximum contrast threshold used by the compressed encoder, so that
+/// some regions of the image plane (e.g., faces or license plates) can be encoded with less loss
+/// than the background.
+#[derive(Clone, PartialEq, Debug)]
+pub struct QualityMap {
+    c_thresh_max: Array2<u8>,
+}
+
+impl QualityMap {
+    /// Create a map which gives every block of the plane the same maximum contrast threshold
+    pub fn new(plane: PlaneSize, c_thresh_max: u8) -> Self {
+        let blocks_y = (plane.h_usize() + QUALITY_BLOCK_SIZE - 1) / QUALITY_BLOCK_SIZE;
+        let blocks_x = (plane.w_usize() + QUALITY_BLOCK_SIZE - 1) / QUALITY_BLOCK_SIZE;
+        Self {
+            c_thresh_max: Array2::from_elem((blocks_y.max(1), blocks_x.max(1)), c_thresh_max),
+        }
+    }
+
+    /// Set the maximum contrast threshold of every block which overlaps the region
+    pub fn set_region(&mut self, roi: &Roi, c_thresh_max: u8) {
+        self.c_thresh_max
+            .indexed_iter_mut()
+            .filter(|((y, x), _)| {
+                roi.intersects(
+                    (x * QUALITY_BLOCK_SIZE) as PixelAddress,
+                    (y * QUALITY_BLOCK_SIZE) as PixelAddress,
+                    QUALITY_BLOCK_SIZE as PixelAddress,
+                    QUALITY_BLOCK_SIZE as PixelAddress,
+                )
+            })
+            .for_each(|(_, value)| *value = c_thresh_max);
+    }
+
+    /// Set the maximum contrast threshold of the block at the given (y, x) block index. Indices
+    /// outside the map are ignored.
+    pub fn set_block(&mut self, block_y: usize, block_x: usize, c_thresh_max: u8) {
+        if let Some(value) = self.c_thresh_max.get_mut((block_y, block_x)) {
+            *value = c_thresh_max;
+        }
+    }
+
+    /// Get the maximum contrast threshold of the block containing the pixel at `(x, y)`. Pixels
+    /// beyond the edge of the map take the value of the nearest block.
+    pub fn c_thresh_max(&self, x: PixelAddress, y: PixelAddress) -> u8 {
+        let (blocks_y, blocks_x) = self.c_thresh_max.dim();
+        let block_y = (y as usize / QUALITY_BLOCK_SIZE).min(blocks_y - 1);
+        let block_x = (x as usize / QUALITY_BLOCK_SIZE).min(blocks_x - 1);
+        self.c_thresh_max[[block_y, block_x]]
+    }
+
+    /// The (height, width) of the map, in blocks
+    pub fn dim(&self) -> (usize, usize) {
+        self.c_thresh_max.dim()
+    }
+}
+
+/// The activity of one block of the plane, as gathered by [`TwoPassStats`]
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct BlockActivity {
+    /// The number of events in the block
+    pub events: u64,
+    dt_sum: f64,
+    dt_sq_sum: f64,
+}
+
+impl BlockActivity {
+    /// The mean time between each pixel's events in the block
+    pub fn dt_mean(&self) -> f64 {
+        if self.events == 0 {
+            return 0.0;
+        }
+        self.dt_sum / self.events as f64
+    }
+
+    /// The variance of the time between each pixel's events in the block
+    pub fn dt_variance(&self) -> f64 {
+        if self.events == 0 {
+            return 0.0;
+        }
+        let mean = self.dt_mean();
+        (self.dt_sq_sum / self.events as f64 - mean * mean).max(0.0)
+    }
+}
+
+/// The lowest sensitivity to loss which [`TwoPassStats`] assigns a block, so that a block whose
+/// events are perfectly regular doesn't take the entire quantization budget
+const MIN_LOSS_SENSITIVITY: f64 = 0.05;
+
+/// The first pass of a two-pass encode. Gathers the activity (event count and variance of the
+/// time between events) of each block of the plane, which lines up with the compressed encoder's
+/// cubes. The second pass encodes the stream with the [`QualityMap`] it allocates.
+///
+/// The events must have absolute timestamps.
+#[derive(Clone, Debug)]
+pub struct TwoPassStats {
+    plane: PlaneSize,
+
+    /// The timestamp of each pixel's last event
+    last_t: Array3<AbsoluteT>,
+    blocks: Array2<BlockActivity>,
+}
+
+impl TwoPassStats {
+    /// Create empty statistics for the given plane
+    pub fn new(plane: PlaneSize) -> Self {
+        let (blocks_y, blocks_x) = QualityMap::new(plane, 0).dim();
+        Self {
+            plane,
+            last_t: Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize())),
+            blocks: Array2::default((blocks_y, blocks_x)),
+        }
+    }
+
+    /// Count an event. Events outside the plane are ignored.
+    pub fn observe(&mut self, event: &Event) {
+        let Some(last_t) = self.last_t.get_mut((
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        )) else {
+            return;
+        };
+        let dt = f64::from(event.t.saturating_sub(*last_t));
+        *last_t = event.t;
+
+        let block = &mut self.blocks[[
+            event.coord.y_usize() / QUALITY_BLOCK_SIZE,
+            event.coord.x_usize() / QUALITY_BLOCK_SIZE,
+        ]];
+        block.events += 1;
+        block.dt_sum += dt;
+        block.dt_sq_sum += dt * dt;
+    }
+
+    /// Count each of the events, in order
+    pub fn observe_events(&mut self, events: &[Event]) {
+        for event in events {
+            self.observe(event);
+        }
+    }
+
+    /// Get the activity of the block at the given (y, x) block index
+    pub fn block(&self, block_y: usize, block_x: usize) -> Option<&BlockActivity> {
+        self.blocks.get((block_y, block_x))
+    }
+
+    /// Allocate the maximum contrast threshold of each block, so that the thresholds average out
+    /// to `c_thresh_max` over all the events.
+    ///
+    /// A block's sensitivity to loss is the coefficient of variation of the time between its
+    /// events: irregular timing (e.g., motion or texture) suffers from quantization, while regular
+    /// timing can be quantized coarsely with little distortion. Balancing the rate saved against
+    /// the distortion added in every block gives each block a threshold inversely proportional to
+    /// the square root of its sensitivity. Blocks without events get `c_thresh_max`.
+    pub fn quality_map(&self, c_thresh_max: u8) -> QualityMap {
+        let mut quality_map = QualityMap::new(self.plane, c_thresh_max);
+        let weight = |block: &BlockActivity| {
+            let mean = block.dt_mean();
+            let sensitivity = if mean > 0.0 {
+                block.dt_variance().sqrt() / mean
+            } else {
+                0.0
+            };
+            1.0 / (sensitivity + MIN_LOSS_SENSITIVITY).sqrt()
+        };
+
+        // Scale the thresholds so that their mean, weighted by the events, meets the budget
+        let (events, weighted) =
+            self.blocks
+                .iter()
+                .fold((0.0, 0.0), |(events, weighted), block| {
+                    let n = block.events as f64;
+                    (events + n, weighted + n * weight(block))
+                });
+        if weighted <= 0.0 {
+            return quality_map;
+        }
+        let scale = f64::from(c_thresh_max) * events / weighted;
+
+        for ((block_y, block_x), block) in self.blocks.indexed_iter() {
+            if block.events > 0 {
+                let c = (scale * weight(block))
+                    .round()
+                    .clamp(0.0, f64::from(u8::MAX));
+                quality_map.set_block(block_y, block_x, c as u8);
+            }
+        }
+        quality_map
+    }
+}
+
+/// How far the [`BitrateController`] moves the contrast threshold for each doubling of the Adu
+/// size away from its target
+const BITRATE_GAIN: f64 = 2.0;
+
+/// Closed-loop control of the compressed encoder's bitrate. After each Adu is compressed, its size
+/// is compared against the size which would meet the target bitrate, and the maximum contrast
+/// threshold for the following Adus is raised (more loss) if it was too large, or lowered (less
+/// loss) if it was too small.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BitrateController {
+    /// The number of bytes each Adu should take up to meet the target bitrate
+    target_adu_bytes: f64,
+
+    /// The current maximum contrast threshold. It's kept fractional, so that small, persistent
+    /// errors still move it over time.
+    c_thresh_max: f64,
+}
+
+impl BitrateController {
+    /// Create a controller which targets `target_kbps` kilobits per second, for Adus that each
+    /// span `adu_seconds`, starting from the given maximum contrast threshold
+    pub fn new(target_kbps: u32, adu_seconds: f64, c_thresh_max: u8) -> Self {
+        Self {
+            target_adu_bytes: f64::from(target_kbps) * 1000.0 / 8.0 * adu_seconds,
+            c_thresh_max: f64::from(c_thresh_max),
+        }
+    }
+
+    /// The maximum contrast threshold to compress the next Adu with
+    pub fn c_thresh_max(&self) -> u8 {
+        self.c_thresh_max.round() as u8
+    }
+
+    /// Adjust the contrast threshold after an Adu was compressed to `adu_bytes` bytes
+    pub fn observe(&mut self, adu_bytes: usize) {
+        if adu_bytes == 0 || self.target_adu_bytes <= 0.0 {
+            return;
+        }
+        let error = (adu_bytes as f64 / self.target_adu_bytes).log2();
+        self.c_thresh_max =
+            (self.c_thresh_max + BITRATE_GAIN * error).clamp(0.0, f64::from(u8::MAX));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitrateController, PixelAddress, QualityMap, TwoPassStats};
+    use crate::{Coord, Event, PlaneSize, Roi};
+
+    #[test]
+    fn test_quality_map_region() {
+        let plane = PlaneSize::new(100, 50, 1).unwrap();
+        let mut map = QualityMap::new(plane, 20);
+        assert_eq!(map.dim(), (4, 7));
+
+        // Overlaps the blocks at x in [1, 2], y in [0, 1]
+        map.set_region(&Roi::new(20, 10, 20, 10), 0);
+        assert_eq!(map.c_thresh_max(16, 0), 0);
+        assert_eq!(map.c_thresh_max(47, 31), 0);
+        assert_eq!(map.c_thresh_max(15, 0), 20);
+        assert_eq!(map.c_thresh_max(48, 0), 20);
+        assert_eq!(map.c_thresh_max(16, 32), 20);
+
+        // Pixels beyond the plane clamp to the edge blocks
+        map.set_block(3, 6, 5);
+        assert_eq!(map.c_thresh_max(99, 49), 5);
+        assert_eq!(map.c_thresh_max(PixelAddress::MAX, PixelAddress::MAX), 5);
+    }
+
+    #[test]
+    fn test_bitrate_controller() {
+        // 8 kbps over Adus of 1/8 second is 125 bytes per Adu
+        let mut controller = BitrateController::new(8, 0.125, 5);
+        assert_eq!(controller.c_thresh_max(), 5);
+
+        // Adus which are too large raise the threshold, and ones which are too small lower it
+        controller.observe(500);
+        assert_eq!(controller.c_thresh_max(), 9);
+        controller.observe(125);
+        assert_eq!(controller.c_thresh_max(), 9);
+        controller.observe(1);
+        assert_eq!(controller.c_thresh_max(), 0);
+
+        // Empty Adus don't move the threshold
+        controller.observe(0);
+        assert_eq!(controller.c_thresh_max(), 0);
+        for _ in 0..100 {
+            controller.observe(1_000_000);
+        }
+        assert_eq!(controller.c_thresh_max(), u8::MAX);
+    }
+
+    #[test]
+    fn test_two_pass_stats() {
+        let plane = PlaneSize::new(32, 16, 1).unwrap();
+        let mut stats = TwoPassStats::new(plane);
+
+        // The left block fires regularly, and the right block irregularly
+        for k in 0..10 {
+            for y in 0..16 {
+                for x in 0..32 {
+                    let t = if x < 16 {
+                        (k + 1) * 100
+                    } else {
+                        k / 2 * 200 + if k % 2 == 0 { 20 } else { 200 }
+                    };
+                    stats.observe(&Event {
+                        coord: Coord { x, y, c: None },
+                        d: 7,
+                        t,
+                    });
+                }
+            }
+        }
+        assert_eq!(stats.block(0, 0).unwrap().events, 2560);
+        assert_eq!(stats.block(0, 0).unwrap().dt_variance(), 0.0);
+        assert_eq!(stats.block(0, 1).unwrap().dt_mean(), 100.0);
+        assert!(stats.block(1, 0).is_none());
+
+        // The regular block is quantized more coarsely than the budget, and the irregular block
+        // more finely
+        let map = stats.quality_map(10);
+        assert_eq!(map.c_thresh_max(0, 0), 16);
+        assert_eq!(map.c_thresh_max(16, 0), 4);
+
+        // A lossless budget stays lossless
+        let map = stats.quality_map(0);
+        assert_eq!(map.c_thresh_max(0, 0), 0);
+        assert_eq!(map.c_thresh_max(16, 0), 0);
+    }
+}
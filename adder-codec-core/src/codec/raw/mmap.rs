@@ -0,0 +1,256 @@
+use crate::codec::decoder::Decoder;
+use crate::codec::raw::stream::{deserialize_event, RawInput};
+use crate::codec::{CodecError, CodecMetadata, UserMetadata};
+use crate::raw_event::ByteOrder;
+use crate::{Event, TimeMode};
+use bitstream_io::{BigEndian, BitReader};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+
+/// A raw stream mapped into memory. Raw events all have the same size, so any of them can be read
+/// in place, without reading the events before it or copying the file through a buffer. This
+/// allows random access, and scanning a large file from several threads at once, each with its
+/// own [`RawEvents`] view.
+///
+/// Streams in [`TimeMode::Mixed`] can't be mapped, since each event's timestamp depends on its
+/// pixel's previous events. The timestamps of [`TimeMode::DeltaT`] streams are returned as they're
+/// stored.
+pub struct MmapRawStream {
+    mmap: Mmap,
+    meta: CodecMetadata,
+    byte_order: ByteOrder,
+    user_metadata: UserMetadata,
+
+    /// The byte offset of the first event
+    start: usize,
+
+    /// The number of complete events, not counting the EOF marker of a finished stream
+    len: usize,
+}
+
+impl MmapRawStream {
+    /// Map the raw stream at the given path and read its header
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this process or any other, until the
+    /// stream and all its [`RawEvents`] views are dropped. The events are read straight from the
+    /// mapped pages, so a concurrent write would change memory behind a shared reference, and a
+    /// truncation would make reading it fault.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<Self, CodecError> {
+        let file = File::open(path)?;
+
+        // SAFETY: The caller guarantees that the file isn't modified while it's mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let mut bitreader = BitReader::endian(Cursor::new(&mmap[..]), BigEndian);
+        let decoder = Decoder::new_raw(RawInput::new(), &mut bitreader)?;
+        let meta = *decoder.meta();
+        let byte_order = decoder.byte_order();
+        let user_metadata = decoder.user_metadata().clone();
+        if meta.time_mode == TimeMode::Mixed {
+            return Err(CodecError::UnsupportedTimeMode(meta.time_mode));
+        }
+
+        let start = meta.header_size.min(mmap.len());
+        let event_size = usize::from(meta.event_size.max(1));
+        let mut stream = Self {
+            len: (mmap.len() - start) / event_size,
+            mmap,
+            meta,
+            byte_order,
+            user_metadata,
+            start,
+        };
+
+        // Leave out the EOF marker, if the stream was closed properly
+        if let Some(Ok(last)) = stream.events().last() {
+            if last.coord.is_eof() {
+                stream.len -= 1;
+            }
+        }
+        Ok(stream)
+    }
+
+    /// The metadata from the stream's header
+    pub fn meta(&self) -> &CodecMetadata {
+        &self.meta
+    }
+
+    /// The user metadata from the stream's header
+    pub fn user_metadata(&self) -> &UserMetadata {
+        &self.user_metadata
+    }
+
+    /// A view of all the stream's events
+    pub fn events(&self) -> RawEvents<'_> {
+        let event_size = usize::from(self.meta.event_size.max(1));
+        RawEvents {
+            bytes: &self.mmap[self.start..self.start + self.len * event_size],
+            meta: &self.meta,
+            byte_order: self.byte_order,
+        }
+    }
+}
+
+/// A view of a contiguous run of the events of an [`MmapRawStream`], which are read in place
+#[derive(Copy, Clone)]
+pub struct RawEvents<'a> {
+    bytes: &'a [u8],
+    meta: &'a CodecMetadata,
+    byte_order: ByteOrder,
+}
+
+impl<'a> RawEvents<'a> {
+    fn event_size(&self) -> usize {
+        usize::from(self.meta.event_size.max(1))
+    }
+
+    /// The number of events in the view
+    pub fn len(&self) -> usize {
+        self.bytes.len() / self.event_size()
+    }
+
+    /// Returns true if the view has no events
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the event at the given index of the view. Returns `None` if the index is out of range.
+    pub fn get(&self, index: usize) -> Option<Result<Event, CodecError>> {
+        let event_size = self.event_size();
+        let bytes = self
+            .bytes
+            .get(index * event_size..(index + 1) * event_size)?;
+        Some(
+            deserialize_event(self.meta, self.byte_order, bytes)
+                .map_err(|_| CodecError::Deserialize),
+        )
+    }
+
+    /// The first event of the view, if it isn't empty
+    pub fn first(&self) -> Option<Result<Event, CodecError>> {
+        self.get(0)
+    }
+
+    /// The last event of the view, if it isn't empty
+    pub fn last(&self) -> Option<Result<Event, CodecError>> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// A view of the events in the given range of indices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, as for a slice
+    pub fn slice(&self, range: std::ops::Range<usize>) -> RawEvents<'a> {
+        let event_size = self.event_size();
+        RawEvents {
+            bytes: &self.bytes[range.start * event_size..range.end * event_size],
+            meta: self.meta,
+            byte_order: self.byte_order,
+        }
+    }
+
+    /// Split the view into consecutive views of `chunk_len` events each (the last may have fewer),
+    /// e.g., to scan them on separate threads
+    pub fn chunks(&self, chunk_len: usize) -> impl Iterator<Item = RawEvents<'a>> + 'a {
+        let (meta, byte_order) = (self.meta, self.byte_order);
+        self.bytes
+            .chunks(chunk_len.max(1) * self.event_size())
+            .map(move |bytes| RawEvents {
+                bytes,
+                meta,
+                byte_order,
+            })
+    }
+
+    /// Iterate over the events of the view, in order
+    pub fn iter(&self) -> impl Iterator<Item = Result<Event, CodecError>> + 'a {
+        let view = *self;
+        (0..view.len()).filter_map(move |index| view.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MmapRawStream;
+    use crate::codec::encoder::Encoder;
+    use crate::codec::raw::stream::RawOutput;
+    use crate::codec::{CodecError, CodecMetadata, EncoderOptions};
+    use crate::{Coord, Event, PlaneSize, TimeMode};
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    fn write_stream(path: &std::path::Path, time_mode: TimeMode, events: &[Event]) {
+        let plane = PlaneSize::new(8, 4, 3).unwrap();
+        let meta = CodecMetadata {
+            time_mode,
+            plane,
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(File::create(path).unwrap())),
+            EncoderOptions::default(plane),
+        );
+        encoder.ingest_events(events).unwrap();
+        encoder.close_writer().unwrap();
+    }
+
+    #[test]
+    fn test_mmap_events() -> Result<(), CodecError> {
+        let events: Vec<Event> = (0..1000)
+            .map(|i| Event {
+                coord: Coord {
+                    x: i % 8,
+                    y: i / 8 % 4,
+                    c: Some((i % 3) as u8),
+                },
+                d: (i % 11) as u8,
+                t: 100 + i,
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!("adder_mmap_{}.adder", rand::random::<u32>()));
+        write_stream(&path, TimeMode::AbsoluteT, &events);
+
+        // SAFETY: Nothing else writes to the file until the stream is dropped
+        let stream = unsafe { MmapRawStream::open(&path)? };
+        let view = stream.events();
+        assert_eq!(view.len(), events.len());
+        assert_eq!(view.get(500).transpose()?, Some(events[500]));
+        assert_eq!(view.get(events.len()).transpose()?, None);
+        assert_eq!(view.last().transpose()?, events.last().copied());
+        assert_eq!(view.iter().collect::<Result<Vec<_>, _>>()?, events);
+        assert_eq!(
+            view.slice(10..20).iter().collect::<Result<Vec<_>, _>>()?,
+            events[10..20]
+        );
+
+        // Scan the chunks in parallel
+        let chunks: Vec<Vec<Event>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = view
+                .chunks(300)
+                .map(|chunk| scope.spawn(move || chunk.iter().collect::<Result<Vec<_>, _>>()))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Result<_, _>>()
+        })?;
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.concat(), events);
+
+        // Mixed-mode events can't be read in place
+        drop(stream);
+        write_stream(&path, TimeMode::Mixed, &events);
+        assert!(matches!(
+            // SAFETY: As above
+            unsafe { MmapRawStream::open(&path) },
+            Err(CodecError::UnsupportedTimeMode(TimeMode::Mixed))
+        ));
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -1,2 +1,6 @@
 /// Raw codec
 pub mod stream;
+
+/// Zero-copy access to the events of memory-mapped raw streams
+#[cfg(feature = "mmap")]
+pub mod mmap;
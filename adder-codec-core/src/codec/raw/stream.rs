@@ -1,50 +1,143 @@
 // #[cfg(feature = "compression")]
 // use crate::codec::compressed::adu::frame::Adu;
-use crate::codec::header::{Magic, MAGIC_RAW};
-use crate::codec::{CodecError, CodecMetadata, ReadCompression, WriteCompression};
-use crate::{Coord, Event, EventSingle, EOF_PX_ADDRESS};
-use bincode::config::{FixintEncoding, WithOtherEndian, WithOtherIntEncoding};
-use bincode::{DefaultOptions, Options};
+use crate::codec::checkpoint::EncoderCheckpoint;
+use crate::codec::header::{Magic, MAGIC_RAW, MAGIC_RAW_LE};
+use crate::codec::{CodecError, CodecMetadata, EncoderOptions, ReadCompression, WriteCompression};
+use crate::raw_event::{ByteOrder, RawEventError, RawEventFormat};
+use crate::{
+    AbsoluteT, Coord, DeltaT, Event, TimeMode, D_EMPTY, D_ZERO_INTEGRATION, EOF_PX_ADDRESS,
+};
 use bitstream_io::{BigEndian, BitRead, BitReader};
 use std::io::{Read, Seek, SeekFrom, Write};
 
+/// The number of events in each independently decodable chunk of a raw stream
+const RAW_CHUNK_EVENTS: u64 = 4096;
+
+/// Deserialize a single event, in the format of a raw stream with the given metadata and byte
+/// order. The timestamp is returned as it's stored, so it isn't decoded for [`TimeMode::Mixed`].
+pub(crate) fn deserialize_event(
+    meta: &CodecMetadata,
+    byte_order: ByteOrder,
+    bytes: &[u8],
+) -> Result<Event, RawEventError> {
+    RawEventFormat::new(meta.plane.c(), meta.wide_coordinates).read_ordered(byte_order, bytes)
+}
+
+/// The magic number naming a raw stream with events in the given byte order
+fn raw_magic(byte_order: ByteOrder) -> Magic {
+    match byte_order {
+        ByteOrder::Big => MAGIC_RAW,
+        ByteOrder::Little => MAGIC_RAW_LE,
+    }
+}
+
 /// Write uncompressed (raw) ADΔER data to a stream.
 pub struct RawOutput<W> {
     pub(crate) meta: CodecMetadata,
-    pub(crate) bincode: WithOtherEndian<
-        WithOtherIntEncoding<DefaultOptions, FixintEncoding>,
-        bincode::config::BigEndian,
-    >,
     pub(crate) stream: Option<W>,
+    pub(crate) mixed_time: MixedTime,
+
+    /// The byte order of the events. The header is always big-endian.
+    pub(crate) byte_order: ByteOrder,
+
+    /// Flush the writer after every this many events, if it's set
+    pub(crate) flush_events: Option<u32>,
+
+    /// The number of events written since the writer was last flushed
+    pub(crate) unflushed_events: u32,
+
+    /// The number of bytes written so far
+    pub(crate) bytes_written: u64,
 }
 
 /// Read uncompressed (raw) ADΔER data from a stream.
 pub struct RawInput<R: Read + Seek> {
     pub(crate) meta: CodecMetadata,
-    pub(crate) bincode: WithOtherEndian<
-        WithOtherIntEncoding<DefaultOptions, FixintEncoding>,
-        bincode::config::BigEndian,
-    >,
+    mixed_time: MixedTime,
+    byte_order: ByteOrder,
     _phantom: std::marker::PhantomData<R>,
 }
 
+/// Converts between absolute timestamps and their [`TimeMode::Mixed`] representation.
+///
+/// The stream's time is divided into ADUs of `ref_interval * adu_interval` ticks. The first event
+/// of each pixel within an ADU (its head) carries its absolute timestamp, and the pixel's later
+/// events within the same ADU carry the delta time from its previous event. A head's timestamp is
+/// never less than the time remaining in the ADU of the pixel's previous event, so the two
+/// representations can't be confused when decoding. Each pixel's events must be in time order.
+#[derive(Debug, Default)]
+pub(crate) struct MixedTime {
+    pub(crate) last_t: Vec<Option<AbsoluteT>>,
+}
+
+impl MixedTime {
+    fn px_last_t(&mut self, meta: &CodecMetadata, coord: Coord) -> &mut Option<AbsoluteT> {
+        let plane = meta.plane;
+        if self.last_t.len() != plane.volume() {
+            self.last_t = vec![None; plane.volume()];
+        }
+        let idx = (coord.y_usize() * plane.w_usize() + coord.x_usize()) * plane.c_usize()
+            + coord.c_usize();
+        &mut self.last_t[idx]
+    }
+
+    /// The first tick of the ADU following the one which contains `t`
+    fn next_adu_start(meta: &CodecMetadata, t: AbsoluteT) -> AbsoluteT {
+        let adu_len = (meta.ref_interval * meta.adu_interval as DeltaT).max(1);
+        (t / adu_len + 1).saturating_mul(adu_len)
+    }
+
+    /// Convert an absolute timestamp to the value stored in the stream
+    fn encode(&mut self, meta: &CodecMetadata, coord: Coord, t: AbsoluteT) -> AbsoluteT {
+        let last_t = self.px_last_t(meta, coord);
+        let stored = match *last_t {
+            Some(last) if t < Self::next_adu_start(meta, last) => t.saturating_sub(last),
+            _ => t,
+        };
+        *last_t = Some(t);
+        stored
+    }
+
+    /// Convert a value stored in the stream back to an absolute timestamp
+    fn decode(&mut self, meta: &CodecMetadata, coord: Coord, stored: AbsoluteT) -> AbsoluteT {
+        let last_t = self.px_last_t(meta, coord);
+        let t = match *last_t {
+            Some(last) if stored < Self::next_adu_start(meta, last) - last => last + stored,
+            _ => stored,
+        };
+        *last_t = Some(t);
+        t
+    }
+}
+
 impl<W: Write> RawOutput<W> {
     /// Create a new raw output stream.
-    pub fn new(mut meta: CodecMetadata, writer: W) -> Self {
-        let bincode = DefaultOptions::new()
-            .with_fixint_encoding()
-            .with_big_endian();
-        meta.event_size = match meta.plane.c() {
-            1 => bincode.serialized_size(&EventSingle::default()).unwrap() as u8,
-            _ => bincode.serialized_size(&Event::default()).unwrap() as u8,
-        };
+    pub fn new(meta: CodecMetadata, writer: W) -> Self {
+        Self::with_byte_order(meta, writer, ByteOrder::Big)
+    }
+
+    /// Create a new raw output stream, with the events in the given byte order. A
+    /// [little-endian](ByteOrder::Little) stream has its own magic number, so decoders pick up
+    /// its byte order from the header.
+    pub fn with_byte_order(mut meta: CodecMetadata, writer: W, byte_order: ByteOrder) -> Self {
+        meta.negotiate_coordinates();
+        meta.event_size = RawEventFormat::new(meta.plane.c(), meta.wide_coordinates).size() as u8;
         Self {
             meta,
-            bincode,
             stream: Some(writer),
+            mixed_time: MixedTime::default(),
+            byte_order,
+            flush_events: None,
+            unflushed_events: 0,
+            bytes_written: 0,
         }
     }
 
+    /// Keep the raw encoder's option state synchronized with the high-level encoder container
+    pub(crate) fn with_options(&mut self, options: EncoderOptions) {
+        self.flush_events = options.raw_flush_events.map(|events| events.max(1));
+    }
+
     fn stream(&mut self) -> &mut W {
         self.stream.as_mut().unwrap()
     }
@@ -54,7 +147,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> WriteCompressio
     for RawOutput<W>
 {
     fn magic(&self) -> Magic {
-        MAGIC_RAW
+        raw_magic(self.byte_order)
     }
 
     fn meta(&self) -> &CodecMetadata {
@@ -66,8 +159,9 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> WriteCompressio
     }
 
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
-        // Silently ignore the returned usize because we don't care about the number of bytes
-        self.stream().write(bytes).map(|_| ())
+        self.stream().write_all(bytes)?;
+        self.bytes_written += bytes.len() as u64;
+        Ok(())
     }
 
     // Will always be byte-aligned. Do nothing.
@@ -76,7 +170,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> WriteCompressio
     }
 
     // If `self.writer` is a `BufWriter`, you'll need to flush it yourself after this.
-    fn into_writer(&mut self) -> Option<W> {
+    fn into_writer(&mut self) -> Result<Option<W>, CodecError> {
         let eof = Event {
             coord: Coord {
                 x: EOF_PX_ADDRESS,
@@ -86,9 +180,18 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> WriteCompressio
             d: 0,
             t: 0,
         };
-        self.bincode.serialize_into(self.stream(), &eof).unwrap();
-        self.flush_writer().unwrap();
-        self.stream.take()
+        // The EOF marker always carries a channel, so it's at least as long as an event
+        let format = if self.meta.wide_coordinates {
+            RawEventFormat::Wide
+        } else {
+            RawEventFormat::Narrow
+        };
+        let mut bytes = [0; RawEventFormat::MAX_SIZE];
+        let size = format.write_ordered(self.byte_order, &eof, &mut bytes)?;
+        self.stream().write_all(&bytes[..size])?;
+        self.bytes_written += size as u64;
+        self.flush_writer()?;
+        Ok(self.stream.take())
     }
 
     fn flush_writer(&mut self) -> std::io::Result<()> {
@@ -97,28 +200,62 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> WriteCompressio
 
     /// Ingest an event into the codec.
     ///
-    /// This will always write the event immediately to the underlying writer.
-    fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+    /// This will always write the event immediately to the underlying writer, and flush the writer
+    /// if [`EncoderOptions::raw_flush_events`] events have been written since it was last flushed.
+    fn ingest_event(&mut self, mut event: Event) -> Result<(), CodecError> {
         // NOTE: for speed, the following checks only run in debug builds. It's entirely
         // possibly to encode nonsensical events if you want to.
         debug_assert!(event.coord.x < self.meta.plane.width || event.coord.x == EOF_PX_ADDRESS);
         debug_assert!(event.coord.y < self.meta.plane.height || event.coord.y == EOF_PX_ADDRESS);
 
-        // TODO: Switch functionality based on what the deltat mode is!
+        // Unlike the checks above, this one is cheap, and a reader may rely on the maximum which
+        // the header declares
+        if event.d > self.meta.d_max && event.d != D_ZERO_INTEGRATION && event.d != D_EMPTY {
+            return Err(CodecError::DOutOfRange {
+                d: event.d,
+                d_max: self.meta.d_max,
+            });
+        }
 
-        let output_event: EventSingle;
-        if self.meta.plane.channels == 1 {
-            // let event_to_write = self.queue.pop()
-            output_event = (&event).into();
-            self.bincode.serialize_into(self.stream(), &output_event)?;
-            // bincode::serialize_into(&mut *stream, &output_event, my_options).unwrap();
-        } else {
-            self.bincode.serialize_into(self.stream(), &event)?;
+        // Events are ingested with absolute timestamps in mixed mode
+        if self.meta.time_mode == TimeMode::Mixed && !event.coord.is_eof() {
+            event.t = self.mixed_time.encode(&self.meta, event.coord, event.t);
+        }
+
+        let format = RawEventFormat::new(self.meta.plane.c(), self.meta.wide_coordinates);
+        let mut bytes = [0; RawEventFormat::MAX_SIZE];
+        let size = format.write_ordered(self.byte_order, &event, &mut bytes)?;
+        self.stream().write_all(&bytes[..size])?;
+        self.bytes_written += size as u64;
+
+        if let Some(flush_events) = self.flush_events {
+            self.unflushed_events += 1;
+            if self.unflushed_events >= flush_events {
+                self.flush_writer()?;
+                self.unflushed_events = 0;
+            }
         }
 
         Ok(())
     }
 
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn checkpoint(&mut self, checkpoint: &mut EncoderCheckpoint) -> Result<(), CodecError> {
+        self.flush_writer()?;
+        checkpoint.bytes_written = self.bytes_written;
+        checkpoint.mixed_last_t = self.mixed_time.last_t.clone();
+        Ok(())
+    }
+
+    fn resume(&mut self, checkpoint: &EncoderCheckpoint) -> Result<(), CodecError> {
+        self.bytes_written = checkpoint.bytes_written;
+        self.mixed_time.last_t = checkpoint.mixed_last_t.clone();
+        Ok(())
+    }
+
     // #[cfg(feature = "compression")]
     // fn ingest_event_debug(&mut self, event: Event) -> Result<Option<Adu>, CodecError> {
     //     todo!()
@@ -139,10 +276,9 @@ impl<R: Read + Seek> RawInput<R> {
     {
         Self {
             meta: CodecMetadata::default(),
-            bincode: DefaultOptions::new()
-                .with_fixint_encoding()
-                .with_big_endian(),
             // stream: reader,
+            mixed_time: MixedTime::default(),
+            byte_order: ByteOrder::Big,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -150,7 +286,20 @@ impl<R: Read + Seek> RawInput<R> {
 
 impl<R: Read + Seek> ReadCompression<R> for RawInput<R> {
     fn magic(&self) -> Magic {
-        MAGIC_RAW
+        raw_magic(self.byte_order)
+    }
+
+    fn negotiate_magic(&mut self, magic: Magic) -> bool {
+        self.byte_order = match magic {
+            MAGIC_RAW => ByteOrder::Big,
+            MAGIC_RAW_LE => ByteOrder::Little,
+            _ => return false,
+        };
+        true
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        self.byte_order
     }
 
     fn meta(&self) -> &CodecMetadata {
@@ -178,25 +327,23 @@ impl<R: Read + Seek> ReadCompression<R> for RawInput<R> {
         // TODO: Why is the encoded event size wrong?
         let mut buffer: Vec<u8> = vec![0; self.meta.event_size as usize];
         reader.read_bytes(&mut buffer)?;
-        let event: Event = if self.meta.plane.channels == 1 {
-            match self.bincode.deserialize_from::<_, EventSingle>(&*buffer) {
-                Ok(ev) => ev.into(),
-                Err(_e) => return Err(CodecError::Deserialize),
-            }
-        } else {
-            match self.bincode.deserialize_from::<_, Event>(&*buffer) {
-                Ok(ev) => ev,
-                Err(e) => {
-                    dbg!(self.meta.event_size);
-                    eprintln!("Error deserializing event: {e}");
-                    return Err(CodecError::Deserialize);
-                }
+        let mut event = match deserialize_event(&self.meta, self.byte_order, &buffer) {
+            Ok(ev) => ev,
+            Err(e) => {
+                dbg!(self.meta.event_size);
+                eprintln!("Error deserializing event: {e}");
+                return Err(CodecError::Deserialize);
             }
         };
 
         if event.coord.is_eof() {
             return Err(CodecError::Eof);
         }
+
+        // Mixed-mode events are decoded to absolute timestamps
+        if self.meta.time_mode == TimeMode::Mixed {
+            event.t = self.mixed_time.decode(&self.meta, event.coord, event.t);
+        }
         Ok(event)
     }
 
@@ -222,6 +369,33 @@ impl<R: Read + Seek> ReadCompression<R> for RawInput<R> {
             return Err(CodecError::Seek);
         }
 
+        // The pixels' previous timestamps no longer apply. In mixed mode, seek to the start of an
+        // ADU so that each pixel's next event is its head.
+        self.mixed_time = MixedTime::default();
+
         Ok(())
     }
+
+    fn chunk_positions(
+        &mut self,
+        reader: &mut BitReader<R, BigEndian>,
+    ) -> Result<Vec<u64>, CodecError> {
+        let start = self.meta.header_size as u64;
+
+        // A mixed-mode event can only be decoded with its pixel's previous events in the same ADU,
+        // and the ADU boundaries aren't marked in a raw stream
+        if self.meta.time_mode == TimeMode::Mixed {
+            return Ok(vec![start]);
+        }
+
+        let pos = reader.position_in_bits()?;
+        reader.seek_bits(SeekFrom::End(0))?;
+        let end = reader.position_in_bits()? / 8;
+        reader.seek_bits(SeekFrom::Start(pos))?;
+
+        let chunk_size = (RAW_CHUNK_EVENTS * u64::from(self.meta.event_size)).max(1);
+        Ok((start..end.max(start + 1))
+            .step_by(chunk_size as usize)
+            .collect())
+    }
 }
@@ -0,0 +1,310 @@
+use crate::codec::{CodecError, CodecMetadata};
+use crate::{AbsoluteT, ColorSpace, Event, EventCoordless, PlaneSize, TimeMode, D_EMPTY};
+use ndarray::Array3;
+use std::collections::VecDeque;
+
+/// Reconstructs frames of intensities from ADΔER events, for rendering previews without the
+/// framer of `adder-codec-rs` (e.g., in a WASM or embedded viewer).
+///
+/// Each event is taken to have a constant intensity (2^D / Δt) over the time since its pixel's
+/// last event. A frame samples each pixel at its end, so its value is the intensity of the
+/// pixel's first event at or after that time, integrated over the frame's duration. The values
+/// are normalized so that 1.0 is the source's maximum intensity, unless
+/// [`FrameReconstructor::hdr`] maps them back to the intensities of an HDR source. A pixel which
+/// doesn't fire again holds its last value. The frames of a [`ColorSpace::YCbCr`] stream are
+/// converted to BGR as they're popped.
+///
+/// The events of each pixel must be in time order, but the events of different pixels may be
+/// interleaved out of order, as they are within each Adu of a compressed stream. A frame is
+/// complete once the events have passed its end by the span of an Adu plus `delta_t_max`, or when
+/// [`FrameReconstructor::finish`] is called.
+#[derive(Debug, Clone)]
+pub struct FrameReconstructor {
+    plane: PlaneSize,
+    ticks_per_frame: f64,
+
+    /// Scales an intensity per tick to a normalized frame value
+    scale: f64,
+
+    /// The source intensity of a normalized value of 1.0, by which HDR frames are scaled
+    intensity_peak: f64,
+
+    /// Whether to scale the frames by the intensity peak
+    hdr: bool,
+
+    /// The color space of the stream's channels
+    color_space: ColorSpace,
+
+    /// How far past a frame's end the events must be before it's complete
+    window: AbsoluteT,
+
+    /// The time of each pixel's last event
+    last_t: Vec<AbsoluteT>,
+
+    /// The number of frames which each pixel's events have covered
+    covered: Vec<u64>,
+
+    /// Each pixel's latest value, which new frames start with
+    current: Array3<f32>,
+
+    /// The frames which haven't been popped yet, starting with the frame at index `first_pending`
+    pending: VecDeque<Array3<f32>>,
+    first_pending: u64,
+
+    /// The index of the first frame which isn't complete
+    first_incomplete: u64,
+
+    /// The latest timestamp of the events
+    latest_t: AbsoluteT,
+}
+
+impl FrameReconstructor {
+    /// Create a reconstructor of frames at `fps` frames per second, for a stream with the given
+    /// metadata. The stream must have absolute timestamps.
+    pub fn new(meta: &CodecMetadata, fps: f64) -> Result<Self, CodecError> {
+        if meta.time_mode == TimeMode::DeltaT {
+            return Err(CodecError::UnsupportedTimeMode(meta.time_mode));
+        }
+        let plane = meta.plane;
+        let ticks_per_frame = (f64::from(meta.tps) / fps).max(1.0);
+        let adu_ticks = u64::from(meta.ref_interval) * meta.adu_interval.max(1) as u64;
+        Ok(Self {
+            plane,
+            ticks_per_frame,
+            scale: ticks_per_frame / meta.source_camera.source_type().max_intensity(),
+            intensity_peak: f64::from(meta.intensity_peak),
+            hdr: false,
+            color_space: meta.color_space,
+            window: AbsoluteT::try_from(adu_ticks + u64::from(meta.delta_t_max))
+                .unwrap_or(AbsoluteT::MAX),
+            last_t: vec![0; plane.volume()],
+            covered: vec![0; plane.volume()],
+            current: Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize())),
+            pending: VecDeque::new(),
+            first_pending: 0,
+            first_incomplete: 0,
+            latest_t: 0,
+        })
+    }
+
+    /// Scale the frames by the stream's [`CodecMetadata::intensity_peak`], so that a
+    /// floating-point HDR source (e.g., OpenEXR frames) is reconstructed in its own units, rather
+    /// than normalized to `[0, 1]`
+    pub fn hdr(mut self, hdr: bool) -> Self {
+        self.hdr = hdr;
+        self
+    }
+
+    /// The number of ticks that each frame spans
+    pub fn ticks_per_frame(&self) -> f64 {
+        self.ticks_per_frame
+    }
+
+    /// Take in an event. Any frames which it completes can then be taken with
+    /// [`FrameReconstructor::pop_frame`].
+    pub fn ingest_event(&mut self, event: Event) {
+        if event.coord.x >= self.plane.w()
+            || event.coord.y >= self.plane.h()
+            || event.coord.c_usize() >= self.plane.c_usize()
+        {
+            return;
+        }
+        let idx = (event.coord.y_usize() * self.plane.w_usize() + event.coord.x_usize())
+            * self.plane.c_usize()
+            + event.coord.c_usize();
+        let position = (
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        );
+
+        // The frames sampled within (last_t, t]
+        let end = (f64::from(event.t) / self.ticks_per_frame).floor() as u64;
+        let start = self.covered[idx].max(self.first_pending);
+        if event.d != D_EMPTY {
+            let dt = event.t.saturating_sub(self.last_t[idx]);
+            let mut value = EventCoordless { d: event.d, t: dt }.intensity() * self.scale;
+            if self.hdr {
+                value *= self.intensity_peak;
+            }
+            let value = value as f32;
+            self.extend_pending(end);
+            for frame in start..end {
+                self.pending[(frame - self.first_pending) as usize][position] = value;
+            }
+            self.current[position] = value;
+        }
+        self.covered[idx] = self.covered[idx].max(end);
+        self.last_t[idx] = event.t;
+
+        self.latest_t = self.latest_t.max(event.t);
+        let horizon = f64::from(self.latest_t.saturating_sub(self.window));
+        let complete = (horizon / self.ticks_per_frame).floor() as u64;
+        if complete > self.first_incomplete {
+            self.extend_pending(complete);
+            self.first_incomplete = complete;
+        }
+    }
+
+    /// Complete every frame up to the latest event, e.g., at the end of the stream
+    pub fn finish(&mut self) {
+        let end = (f64::from(self.latest_t) / self.ticks_per_frame).floor() as u64;
+        self.extend_pending(end);
+        self.first_incomplete = self.first_pending + self.pending.len() as u64;
+    }
+
+    /// Take the next complete frame, indexed by (y, x, c)
+    pub fn pop_frame(&mut self) -> Option<Array3<f32>> {
+        if self.first_pending >= self.first_incomplete {
+            return None;
+        }
+        let mut frame = self.pending.pop_front()?;
+        self.first_pending += 1;
+        if self.color_space == ColorSpace::YCbCr && self.plane.c() == 3 {
+            let max_intensity = if self.hdr { self.intensity_peak } else { 1.0 };
+            for mut pixel in frame.lanes_mut(ndarray::Axis(2)) {
+                let ycbcr = [pixel[0], pixel[1], pixel[2]].map(f64::from);
+                let bgr = ColorSpace::ycbcr_to_bgr(ycbcr, max_intensity);
+                for (value, converted) in pixel.iter_mut().zip(bgr) {
+                    *value = converted as f32;
+                }
+            }
+        }
+        Some(frame)
+    }
+
+    /// Start the frames up to (but not including) index `end`, holding each pixel's latest value
+    fn extend_pending(&mut self, end: u64) {
+        while self.first_pending + (self.pending.len() as u64) < end {
+            self.pending.push_back(self.current.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameReconstructor;
+    use crate::codec::CodecMetadata;
+    use crate::{ColorSpace, Coord, Event, PlaneSize, SourceCamera, TimeMode};
+    use ndarray::Array3;
+
+    fn event(x: u32, d: u8, t: u32) -> Event {
+        Event {
+            coord: Coord { x, y: 0, c: None },
+            d,
+            t,
+        }
+    }
+
+    fn assert_frame(frame: Option<Array3<f32>>, expected: [f32; 2]) {
+        let frame = frame.unwrap();
+        assert_eq!(frame.dim(), (1, 2, 1));
+        for (x, expected) in expected.iter().enumerate() {
+            assert!((frame[[0, x, 0]] - expected / 255.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_frames() {
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane: PlaneSize::new(2, 1, 1).unwrap(),
+            tps: 100,
+            ref_interval: 10,
+            delta_t_max: 10,
+            adu_interval: 1,
+            source_camera: SourceCamera::FramedU8,
+            ..Default::default()
+        };
+
+        // 10 ticks per frame, and frames are complete 20 ticks past their end
+        let mut reconstructor = FrameReconstructor::new(&meta, 10.0).unwrap();
+        assert_eq!(reconstructor.ticks_per_frame(), 10.0);
+
+        // The pixels' events may be out of order with each other. The second pixel's event spans
+        // three frames.
+        reconstructor.ingest_event(event(0, 7, 10));
+        reconstructor.ingest_event(event(1, 4, 30));
+        assert_frame(reconstructor.pop_frame(), [128.0, 16.0 / 3.0]);
+        assert!(reconstructor.pop_frame().is_none());
+
+        reconstructor.ingest_event(event(0, 6, 20));
+        reconstructor.ingest_event(event(0, 5, 30));
+        reconstructor.ingest_event(event(0, 5, 40));
+        assert_frame(reconstructor.pop_frame(), [64.0, 16.0 / 3.0]);
+        assert!(reconstructor.pop_frame().is_none());
+
+        // The second pixel holds its value after its last event
+        reconstructor.finish();
+        assert_frame(reconstructor.pop_frame(), [32.0, 16.0 / 3.0]);
+        assert_frame(reconstructor.pop_frame(), [32.0, 16.0 / 3.0]);
+        assert!(reconstructor.pop_frame().is_none());
+
+        let meta = CodecMetadata {
+            time_mode: TimeMode::DeltaT,
+            ..meta
+        };
+        assert!(FrameReconstructor::new(&meta, 10.0).is_err());
+    }
+
+    #[test]
+    fn test_reconstruct_hdr() {
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane: PlaneSize::new(2, 1, 1).unwrap(),
+            tps: 100,
+            ref_interval: 10,
+            delta_t_max: 20,
+            adu_interval: 1,
+            source_camera: SourceCamera::FramedF32,
+            intensity_peak: 4.0,
+            ..Default::default()
+        };
+
+        // A full-scale float sample over a frame, and half of one
+        let events = [event(0, 0, 10), event(1, 0, 20)];
+        for (hdr, expected) in [(false, [1.0, 0.5]), (true, [4.0, 2.0])] {
+            let mut reconstructor = FrameReconstructor::new(&meta, 10.0).unwrap().hdr(hdr);
+            for event in events {
+                reconstructor.ingest_event(event);
+            }
+            reconstructor.finish();
+            let frame = reconstructor.pop_frame().unwrap();
+            assert_eq!(frame.into_raw_vec(), expected);
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_ycbcr() {
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane: PlaneSize::new(1, 1, 3).unwrap(),
+            tps: 100,
+            ref_interval: 10,
+            delta_t_max: 20,
+            adu_interval: 1,
+            source_camera: SourceCamera::FramedF32,
+            color_space: ColorSpace::YCbCr,
+            ..Default::default()
+        };
+
+        // Full-scale luma with neutral chroma is white
+        let mut reconstructor = FrameReconstructor::new(&meta, 10.0).unwrap();
+        for (c, t) in [(0, 10), (1, 20), (2, 20)] {
+            reconstructor.ingest_event(Event {
+                coord: Coord {
+                    x: 0,
+                    y: 0,
+                    c: Some(c),
+                },
+                d: 0,
+                t,
+            });
+        }
+        reconstructor.finish();
+        let frame = reconstructor.pop_frame().unwrap();
+        for value in frame {
+            assert!((value - 1.0).abs() < 1e-6);
+        }
+    }
+}
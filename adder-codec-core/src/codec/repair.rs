@@ -0,0 +1,245 @@
+use crate::analysis::StreamAnalyzer;
+use crate::codec::decoder::Decoder;
+use crate::codec::encoder::Encoder;
+use crate::codec::raw::stream::{RawInput, RawOutput};
+use crate::codec::{CodecError, EncoderOptions, EncoderType};
+use crate::AbsoluteT;
+use bitstream_io::{BigEndian, BitReader};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+
+#[cfg(feature = "compression")]
+use crate::codec::compressed::stream::{CompressedInput, CompressedOutput};
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::{LzInput, LzOutput};
+
+/// The [user metadata](crate::codec::UserMetadata) key under which [`repair`] records the number
+/// of events it salvaged
+pub const EVENT_COUNT_KEY: &str = "event_count";
+
+/// The [user metadata](crate::codec::UserMetadata) key under which [`repair`] records the
+/// timestamp of the last event it salvaged, in ticks
+pub const DURATION_TICKS_KEY: &str = "duration_ticks";
+
+/// The number of events passed to the encoder at a time
+const BATCH_SIZE: usize = 4096;
+
+/// What [`repair`] salvaged from a damaged stream
+#[derive(Debug)]
+pub struct RepairReport {
+    /// The encoding of the stream, which the repaired stream keeps
+    pub encoder_type: EncoderType,
+
+    /// The number of intact events copied to the repaired stream
+    pub event_count: u64,
+
+    /// The timestamp of the last intact event, in ticks. Streams start at t=0.
+    pub duration_ticks: AbsoluteT,
+
+    /// The error which ended the intact events, if the stream was corrupt. A stream which was
+    /// only cut short (e.g., by a power loss during capture) ends without an error.
+    pub error: Option<CodecError>,
+}
+
+/// Salvage the intact events of a truncated or partially corrupt stream (raw, compressed, or Lz
+/// without a dictionary), and write them to a clean stream of the same encoding, which is closed
+/// with a proper EOF marker. Returns the writer along with what was salvaged.
+///
+/// The events are read up to the end of the stream or the first damaged event (or, for a
+/// compressed stream, the first damaged Adu), and everything after is dropped. The input is read
+/// twice, so that the header of the repaired stream can record the number of salvaged events and
+/// their duration, under [`EVENT_COUNT_KEY`] and [`DURATION_TICKS_KEY`] in its user metadata.
+/// Streams before version 8 can't hold user metadata, so they only get the EOF marker.
+///
+/// A compressed stream is re-encoded losslessly, so the repaired events match the decoded ones.
+/// Only the header must be intact; a stream whose header is damaged can't be repaired.
+pub fn repair<R, W>(input: R, output: W) -> Result<(W, RepairReport), CodecError>
+where
+    R: Read + Seek,
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+{
+    let mut reader = BitReader::endian(input, BigEndian);
+
+    // Find how far the intact events go
+    let mut decoder = open_decoder(&mut reader)?;
+    let mut analyzer = StreamAnalyzer::new(decoder.meta());
+    let mut error = None;
+    for event in decoder.events(&mut reader) {
+        match event {
+            Ok(event) => analyzer.ingest_event(&event),
+            Err(e) => error = Some(e),
+        }
+    }
+    let stats = analyzer.stats();
+
+    // Copy them to the repaired stream
+    let mut decoder = open_decoder(&mut reader)?;
+    let meta = *decoder.meta();
+    let encoder_type = decoder.get_compression_type();
+    let mut options = EncoderOptions::default(meta.plane);
+    let mut user_metadata = decoder.user_metadata().clone();
+    user_metadata.insert(EVENT_COUNT_KEY.into(), stats.event_count.to_string());
+    user_metadata.insert(DURATION_TICKS_KEY.into(), stats.duration_ticks.to_string());
+    options.user_metadata = Some(Arc::new(user_metadata));
+    let mut encoder = match encoder_type {
+        EncoderType::Raw => Encoder::new_raw(
+            RawOutput::with_byte_order(meta, output, decoder.byte_order()),
+            options,
+        ),
+        #[cfg(feature = "compression")]
+        EncoderType::Compressed => {
+            options.crf.override_c_thresh_max(0);
+            Encoder::new_compressed(CompressedOutput::new(meta, output), options)
+        }
+        #[cfg(feature = "lz")]
+        EncoderType::Lz => Encoder::new_lz(LzOutput::new(meta, output), options),
+        _ => return Err(CodecError::MalformedEncoder),
+    };
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    for event in decoder.events(&mut reader).take(stats.event_count as usize) {
+        batch.push(event?);
+        if batch.len() == BATCH_SIZE {
+            encoder.ingest_events(&batch)?;
+            batch.clear();
+        }
+    }
+    encoder.ingest_events(&batch)?;
+    let output = encoder
+        .close_writer()?
+        .ok_or(CodecError::UnitializedStream)?;
+
+    Ok((
+        output,
+        RepairReport {
+            encoder_type,
+            event_count: stats.event_count,
+            duration_ticks: stats.duration_ticks,
+            error,
+        },
+    ))
+}
+
+/// Open a decoder at the start of the stream, first as a raw stream, then as a compressed stream,
+/// and then as an Lz stream
+fn open_decoder<R: Read + Seek>(
+    reader: &mut BitReader<R, BigEndian>,
+) -> Result<Decoder<R>, CodecError> {
+    reader.seek_bits(SeekFrom::Start(0))?;
+    #[allow(unused_mut)]
+    let mut decoder = Decoder::new_raw(RawInput::new(), reader);
+    #[cfg(feature = "compression")]
+    if matches!(decoder, Err(CodecError::WrongMagic)) {
+        reader.seek_bits(SeekFrom::Start(0))?;
+        decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), reader);
+    }
+    #[cfg(feature = "lz")]
+    if matches!(decoder, Err(CodecError::WrongMagic)) {
+        reader.seek_bits(SeekFrom::Start(0))?;
+        decoder = Decoder::new_lz(LzInput::new(None), reader);
+    }
+    decoder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repair, DURATION_TICKS_KEY, EVENT_COUNT_KEY};
+    use crate::codec::testing::{decode_from_vec, decoder_from_vec, encode_to_vec};
+    use crate::codec::{CodecError, CodecMetadata, EncoderOptions, EncoderType};
+    use crate::{Coord, Event, PlaneSize, TimeMode};
+    use std::io::Cursor;
+
+    fn events() -> Vec<Event> {
+        (0..100)
+            .map(|t| Event {
+                coord: Coord {
+                    x: t % 4,
+                    y: t / 4 % 4,
+                    c: Some((t % 3) as u8),
+                },
+                d: 7,
+                t: t * 10,
+            })
+            .collect()
+    }
+
+    fn meta(plane: PlaneSize) -> CodecMetadata {
+        CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_repair_raw() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 3)?;
+        let bytes = encode_to_vec(
+            EncoderType::Raw,
+            meta(plane),
+            EncoderOptions::default(plane),
+            &events(),
+        )?;
+        let header_size = decode_from_vec(bytes.clone())?.0.header_size;
+        let event_size = 11;
+
+        // Cut the stream off partway through the 51st event, as a power loss would
+        let truncated = bytes[..header_size + 50 * event_size + 4].to_vec();
+        let (repaired, report) = repair(Cursor::new(truncated), Cursor::new(Vec::new()))?;
+        assert!(report.error.is_none());
+        assert_eq!(report.event_count, 50);
+        assert_eq!(report.duration_ticks, 490);
+
+        let repaired = repaired.into_inner();
+        let (decoder, _) = decoder_from_vec(repaired.clone())?;
+        assert_eq!(decoder.user_metadata()[EVENT_COUNT_KEY], "50");
+        assert_eq!(decoder.user_metadata()[DURATION_TICKS_KEY], "490");
+        let repaired_header_size = decoder.meta().header_size;
+
+        // The repaired stream ends with an EOF marker
+        assert_eq!(repaired.len(), repaired_header_size + 51 * event_size);
+        assert_eq!(decode_from_vec(repaired)?.1, events()[..50]);
+
+        // Corrupt the channel tag of the 71st event
+        let mut corrupt = bytes;
+        corrupt[header_size + 70 * event_size + 4] = 2;
+        let (repaired, report) = repair(Cursor::new(corrupt), Cursor::new(Vec::new()))?;
+        assert!(matches!(report.error, Some(CodecError::Deserialize)));
+        assert_eq!(report.event_count, 70);
+        assert_eq!(decode_from_vec(repaired.into_inner())?.1, events()[..70]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_repair_compressed() -> Result<(), CodecError> {
+        use crate::codec::testing::sorted_by_pixel;
+
+        let plane = PlaneSize::new(4, 4, 3)?;
+        let meta = CodecMetadata {
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255,
+            adu_interval: 1,
+            ..meta(plane)
+        };
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        let bytes = encode_to_vec(EncoderType::Compressed, meta, options, &events())?;
+
+        // Cut the stream off partway through its Adus
+        let header_size = decode_from_vec(bytes.clone())?.0.header_size;
+        let truncated = bytes[..header_size + (bytes.len() - header_size) / 2].to_vec();
+        let (repaired, report) = repair(Cursor::new(truncated), Cursor::new(Vec::new()))?;
+        assert_eq!(report.encoder_type, EncoderType::Compressed);
+        assert!(report.event_count > 0 && report.event_count < 100);
+
+        let (_, decoded) = decode_from_vec(repaired.into_inner())?;
+        assert_eq!(decoded.len() as u64, report.event_count);
+        assert_eq!(
+            sorted_by_pixel(decoded),
+            sorted_by_pixel(events()[..decoded.len()].to_vec())
+        );
+        Ok(())
+    }
+}
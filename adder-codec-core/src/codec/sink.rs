@@ -0,0 +1,469 @@
+use crate::codec::encoder::Encoder;
+use crate::codec::CodecError;
+use crate::Event;
+use std::io::Write;
+use std::sync::mpsc::{sync_channel, Sender, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+#[cfg(feature = "compression")]
+use crate::codec::clock::{ClockCorrection, RefIntervalChange};
+#[cfg(feature = "compression")]
+use crate::codec::compressed::stream::CompressedOutput;
+#[cfg(feature = "compression")]
+use crate::codec::raw::stream::RawOutput;
+#[cfg(feature = "compression")]
+use crate::codec::stabilization::StabilizationTransform;
+#[cfg(feature = "compression")]
+use crate::codec::{CodecMetadata, EncoderOptions};
+
+/// A destination for ADΔER events, such as an [`Encoder`] writing to a file or network stream, or
+/// a channel feeding a live framer preview.
+pub trait EventSink: Send {
+    /// Take in a batch of events, in order
+    fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError>;
+
+    /// Finish writing out any buffered data, consuming the sink in the process
+    fn close(self: Box<Self>) -> Result<(), CodecError> {
+        Ok(())
+    }
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> EventSink for Encoder<W> {
+    fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        Encoder::ingest_events(self, events)
+    }
+
+    fn close(self: Box<Self>) -> Result<(), CodecError> {
+        if let Some(mut writer) = self.close_writer()? {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl EventSink for Sender<Vec<Event>> {
+    fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        // If the receiver has hung up, there's nobody left to consume the events
+        self.send(events.to_vec())
+            .map_err(|_| CodecError::SinkClosed)
+    }
+}
+
+/// What a branch of an [`EventTee`] should do when its sink falls behind and its queue is full
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub enum Backpressure {
+    /// Wait for the sink to catch up. This slows down the whole tee.
+    #[default]
+    Block,
+
+    /// Discard the batch of events for this sink only, so that the other sinks are unaffected
+    Drop,
+}
+
+struct TeeBranch {
+    tx: Option<SyncSender<Vec<Event>>>,
+    handle: Option<JoinHandle<Result<(), CodecError>>>,
+    backpressure: Backpressure,
+    dropped_batches: u64,
+}
+
+impl TeeBranch {
+    /// Hang up the channel and wait for the sink's thread to finish
+    fn close(&mut self) -> Result<(), CodecError> {
+        self.tx = None;
+        match self.handle.take() {
+            Some(handle) => handle.join().map_err(|_| CodecError::SinkClosed)?,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Feed the same events to several [`EventSink`]s simultaneously.
+///
+/// Each sink runs on its own thread behind its own bounded queue, so a slow sink (e.g., a network
+/// stream) applies backpressure according to its own [`Backpressure`] policy rather than the
+/// policy of the fastest sink.
+#[derive(Default)]
+pub struct EventTee {
+    branches: Vec<TeeBranch>,
+}
+
+impl EventTee {
+    /// Create a tee with no sinks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a sink to the tee. Up to `capacity` batches of events may be queued for the sink
+    /// before `backpressure` takes effect.
+    ///
+    /// Returns the index of the new sink.
+    pub fn add_sink(
+        &mut self,
+        mut sink: Box<dyn EventSink>,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> usize {
+        let (tx, rx) = sync_channel::<Vec<Event>>(capacity);
+        let handle = std::thread::spawn(move || {
+            while let Ok(events) = rx.recv() {
+                sink.ingest_events(&events)?;
+            }
+            sink.close()
+        });
+        self.branches.push(TeeBranch {
+            tx: Some(tx),
+            handle: Some(handle),
+            backpressure,
+            dropped_batches: 0,
+        });
+        self.branches.len() - 1
+    }
+
+    /// The number of sinks attached to the tee
+    pub fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    /// Are there no sinks attached to the tee?
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+
+    /// The number of batches which the given sink has dropped because it fell behind
+    pub fn dropped_batches(&self, sink_idx: usize) -> u64 {
+        self.branches[sink_idx].dropped_batches
+    }
+
+    /// Send a batch of events to every sink.
+    ///
+    /// If a sink has failed, it is detached and its error is returned.
+    pub fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let mut result = Ok(());
+        for branch in &mut self.branches {
+            let Some(tx) = &branch.tx else {
+                continue;
+            };
+            let sent = match branch.backpressure {
+                Backpressure::Block => tx.send(events.to_vec()).is_ok(),
+                Backpressure::Drop => match tx.try_send(events.to_vec()) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        branch.dropped_batches += 1;
+                        true
+                    }
+                    Err(TrySendError::Disconnected(_)) => false,
+                },
+            };
+
+            // The sink's thread has exited early, so surface whatever went wrong
+            if !sent {
+                let closed = branch.close();
+                if result.is_ok() {
+                    result = closed.and(Err(CodecError::SinkClosed));
+                }
+            }
+        }
+        result
+    }
+
+    /// Send a vector of batches of events to every sink
+    pub fn ingest_events_events(&mut self, events: &[Vec<Event>]) -> Result<(), CodecError> {
+        for v in events {
+            self.ingest_events(v)?;
+        }
+        Ok(())
+    }
+
+    /// Close every sink, waiting for each to finish writing out its queued events
+    pub fn close(&mut self) -> Result<(), CodecError> {
+        let mut result = Ok(());
+        for mut branch in self.branches.drain(..) {
+            let closed = branch.close();
+            if result.is_ok() {
+                result = closed;
+            }
+        }
+        result
+    }
+}
+
+impl Drop for EventTee {
+    fn drop(&mut self) {
+        self.close().ok();
+    }
+}
+
+/// Write the same events to a raw stream and a compressed stream in a single pass, e.g., to
+/// archive the lossless stream while also producing a small one to share, without transcoding
+/// twice.
+///
+/// Unlike an [`EventTee`], both encoders run on the calling thread (although the compressed one
+/// compresses its Adus in the background), and their writers are handed back when the tee is
+/// closed.
+#[cfg(feature = "compression")]
+pub struct TeeEncoder<R, C>
+where
+    R: Write + std::marker::Send + std::marker::Sync + 'static,
+    C: Write + std::marker::Send + std::marker::Sync + 'static,
+{
+    raw: Encoder<R>,
+    compressed: Encoder<C>,
+}
+
+#[cfg(feature = "compression")]
+impl<R, C> TeeEncoder<R, C>
+where
+    R: Write + std::marker::Send + std::marker::Sync + 'static,
+    C: Write + std::marker::Send + std::marker::Sync + 'static,
+{
+    /// Pair up a raw encoder and a compressed encoder, which may have different options (e.g., a
+    /// lossy [`Crf`](crate::codec::rate_controller::Crf) for the compressed stream only). Returns
+    /// [`CodecError::PlaneMismatch`] if their planes differ.
+    ///
+    /// The encoders shouldn't drop events at random with
+    /// [`EventDrop::Manual`](crate::codec::EventDrop::Manual), since each would drop different
+    /// ones.
+    pub fn new(raw: Encoder<R>, compressed: Encoder<C>) -> Result<Self, CodecError> {
+        if raw.meta().plane != compressed.meta().plane {
+            return Err(CodecError::PlaneMismatch {
+                input: raw.meta().plane,
+                output: compressed.meta().plane,
+            });
+        }
+        Ok(Self { raw, compressed })
+    }
+
+    /// Create a tee which writes streams with the same metadata and options to the two writers
+    pub fn from_writers(
+        meta: CodecMetadata,
+        options: EncoderOptions,
+        raw_writer: R,
+        compressed_writer: C,
+    ) -> Self {
+        Self {
+            raw: Encoder::new_raw(RawOutput::new(meta, raw_writer), options.clone()),
+            compressed: Encoder::new_compressed(
+                CompressedOutput::new(meta, compressed_writer),
+                options,
+            ),
+        }
+    }
+
+    /// The encoder of the raw stream
+    pub fn raw(&mut self) -> &mut Encoder<R> {
+        &mut self.raw
+    }
+
+    /// The encoder of the compressed stream
+    pub fn compressed(&mut self) -> &mut Encoder<C> {
+        &mut self.compressed
+    }
+
+    /// Ingest an event into both streams
+    pub fn ingest_event(&mut self, event: Event) -> Result<(), CodecError> {
+        self.raw.ingest_event(event)?;
+        self.compressed.ingest_event(event)
+    }
+
+    /// Ingest an array of events into both streams
+    pub fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        self.raw.ingest_events(events)?;
+        self.compressed.ingest_events(events)
+    }
+
+    /// Ingest a vector of an array of events into both streams
+    pub fn ingest_events_events(&mut self, events: &[Vec<Event>]) -> Result<(), CodecError> {
+        for v in events {
+            self.ingest_events(v)?;
+        }
+        Ok(())
+    }
+
+    /// Record a correction of the clock against the wall clock. Only the compressed stream
+    /// carries it.
+    pub fn record_clock(&mut self, correction: ClockCorrection) -> Result<(), CodecError> {
+        self.compressed.record_clock(correction)
+    }
+
+    /// Record a change of the reference interval. Only the compressed stream carries it.
+    pub fn record_ref_interval(&mut self, change: RefIntervalChange) -> Result<(), CodecError> {
+        self.compressed.record_ref_interval(change)
+    }
+
+    /// Record a change of the stabilization shift. Only the compressed stream carries it.
+    pub fn record_stabilization(
+        &mut self,
+        transform: StabilizationTransform,
+    ) -> Result<(), CodecError> {
+        self.compressed.record_stabilization(transform)
+    }
+
+    /// End the current Adu of the compressed stream early. See [`Encoder::force_intra_adu`].
+    pub fn force_intra_adu(&mut self) -> Result<(), CodecError> {
+        self.compressed.force_intra_adu()
+    }
+
+    /// Close both encoders' writers and return them, as (raw, compressed). The compressed encoder
+    /// is closed even if closing the raw one fails.
+    pub fn close_writers(self) -> Result<(Option<R>, Option<C>), CodecError> {
+        let raw = self.raw.close_writer();
+        let compressed = self.compressed.close_writer()?;
+        Ok((raw?, compressed))
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<R, C> EventSink for TeeEncoder<R, C>
+where
+    R: Write + std::marker::Send + std::marker::Sync + 'static,
+    C: Write + std::marker::Send + std::marker::Sync + 'static,
+{
+    fn ingest_events(&mut self, events: &[Event]) -> Result<(), CodecError> {
+        TeeEncoder::ingest_events(self, events)
+    }
+
+    fn close(self: Box<Self>) -> Result<(), CodecError> {
+        let (raw, compressed) = self.close_writers()?;
+        if let Some(mut writer) = raw {
+            writer.flush()?;
+        }
+        if let Some(mut writer) = compressed {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::empty::stream::EmptyOutput;
+    use crate::codec::{CodecMetadata, EncoderOptions};
+    use crate::{Coord, PlaneSize};
+    use std::sync::mpsc::channel;
+
+    fn stock_events(n: u32) -> Vec<Event> {
+        (0..n)
+            .map(|t| Event {
+                coord: Coord {
+                    x: 0,
+                    y: 0,
+                    c: None,
+                },
+                d: 7,
+                t,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tee_to_channels() {
+        let mut tee = EventTee::new();
+        let (tx_a, rx_a) = channel();
+        let (tx_b, rx_b) = channel();
+        tee.add_sink(Box::new(tx_a), 4, Backpressure::Block);
+        tee.add_sink(Box::new(tx_b), 4, Backpressure::Drop);
+        tee.add_sink(
+            Box::new(Encoder::<std::io::Sink>::new_empty(
+                EmptyOutput::new(CodecMetadata::default(), std::io::sink()),
+                EncoderOptions::default(PlaneSize::default()),
+            )),
+            4,
+            Backpressure::Block,
+        );
+        assert_eq!(tee.len(), 3);
+
+        let events = stock_events(10);
+        tee.ingest_events(&events).unwrap();
+        tee.close().unwrap();
+
+        assert_eq!(rx_a.recv().unwrap(), events);
+        assert_eq!(rx_b.recv().unwrap(), events);
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn tee_encoder() -> Result<(), CodecError> {
+        use crate::codec::testing::{decode_from_vec, sorted_by_pixel};
+        use crate::TimeMode;
+        use std::io::Cursor;
+
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            ..Default::default()
+        };
+        let events: Vec<Event> = (0..200)
+            .map(|t| Event {
+                coord: Coord {
+                    x: t % 4,
+                    y: t / 4 % 4,
+                    c: None,
+                },
+                d: 7,
+                t: t * 20,
+            })
+            .collect();
+
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        let mut tee = TeeEncoder::from_writers(
+            meta,
+            options,
+            Cursor::new(Vec::new()),
+            Cursor::new(Vec::new()),
+        );
+        tee.ingest_events(&events[..100])?;
+        for event in &events[100..] {
+            tee.ingest_event(*event)?;
+        }
+        let (raw, compressed) = tee.close_writers()?;
+
+        let (_, decoded_raw) = decode_from_vec(raw.unwrap().into_inner())?;
+        assert_eq!(decoded_raw, events);
+        let (_, decoded_compressed) = decode_from_vec(compressed.unwrap().into_inner())?;
+        assert_eq!(sorted_by_pixel(decoded_compressed), sorted_by_pixel(events));
+
+        // The encoders must be for the same plane
+        let raw = Encoder::new_raw(
+            RawOutput::new(meta, std::io::sink()),
+            EncoderOptions::default(plane),
+        );
+        let other_meta = CodecMetadata {
+            plane: PlaneSize::new(8, 8, 1)?,
+            ..meta
+        };
+        let compressed = Encoder::new_compressed(
+            CompressedOutput::new(other_meta, std::io::sink()),
+            EncoderOptions::default(other_meta.plane),
+        );
+        assert!(matches!(
+            TeeEncoder::new(raw, compressed),
+            Err(CodecError::PlaneMismatch { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn tee_closed_sink() {
+        let mut tee = EventTee::new();
+        let (tx, rx) = channel();
+        tee.add_sink(Box::new(tx), 1, Backpressure::Block);
+        drop(rx);
+
+        let events = stock_events(3);
+
+        // The first batch may be queued before the sink notices its receiver is gone
+        let mut result = Ok(());
+        for _ in 0..3 {
+            result = result.and(tee.ingest_events(&events));
+        }
+        assert!(matches!(result, Err(CodecError::SinkClosed)));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::AbsoluteT;
+
+/// Marks the tick from which a stabilizing transcoder shifted its input frames by a different
+/// translation, to cancel out the global motion of a handheld camera. Until the first transform,
+/// the input wasn't shifted.
+///
+/// The shift moves the content of each input frame by (`dx`, `dy`) pixels, so the pixel at
+/// (x, y) of the stream saw the scene at (x - `dx`, y - `dy`) of the camera's own frame. Applying
+/// the opposite shift to the reconstructed frames un-warps them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StabilizationTransform {
+    /// The timestamp from which the shift applies, in ticks
+    pub t: AbsoluteT,
+
+    /// The horizontal shift, in pixels (positive is right)
+    pub dx: i32,
+
+    /// The vertical shift, in pixels (positive is down)
+    pub dy: i32,
+}
+
+/// The shift in effect at `t`, as (`dx`, `dy`), given the transforms (which must be in time order)
+pub fn shift_at(transforms: &[StabilizationTransform], t: AbsoluteT) -> (i32, i32) {
+    match transforms.partition_point(|transform| transform.t <= t) {
+        0 => (0, 0),
+        idx => (transforms[idx - 1].dx, transforms[idx - 1].dy),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_at() {
+        let transforms = [
+            StabilizationTransform {
+                t: 255,
+                dx: 3,
+                dy: -1,
+            },
+            StabilizationTransform {
+                t: 765,
+                dx: 0,
+                dy: 0,
+            },
+        ];
+        assert_eq!(shift_at(&transforms, 0), (0, 0));
+        assert_eq!(shift_at(&transforms, 255), (3, -1));
+        assert_eq!(shift_at(&transforms, 764), (3, -1));
+        assert_eq!(shift_at(&transforms, 1000), (0, 0));
+        assert_eq!(shift_at(&[], 1000), (0, 0));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::codec::decoder::Decoder;
+use crate::codec::encoder::Encoder;
+use crate::codec::raw::stream::{RawInput, RawOutput};
+use crate::codec::{CodecError, CodecMetadata, EncoderOptions, EncoderType};
+use crate::Event;
+use bitstream_io::{BigEndian, BitReader};
+use std::io::Cursor;
+
+#[cfg(feature = "compression")]
+use crate::codec::compressed::stream::{CompressedInput, CompressedOutput};
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::{LzInput, LzOutput};
+
+/// A [`Decoder`] reading from an in-memory stream
+pub type MemoryDecoder = Decoder<Cursor<Vec<u8>>>;
+
+/// The reader for a [`MemoryDecoder`]
+pub type MemoryReader = BitReader<Cursor<Vec<u8>>, BigEndian>;
+
+/// Encode the events to an in-memory stream of the given type, and return its bytes.
+///
+/// The events are ingested in the order given, so they should be in the order that the encoder
+/// expects (e.g., in time order for a compressed stream).
+pub fn encode_to_vec(
+    encoder_type: EncoderType,
+    meta: CodecMetadata,
+    options: EncoderOptions,
+    events: &[Event],
+) -> Result<Vec<u8>, CodecError> {
+    let writer = Cursor::new(Vec::new());
+    let mut encoder = match encoder_type {
+        EncoderType::Raw => Encoder::new_raw(RawOutput::new(meta, writer), options),
+        #[cfg(feature = "compression")]
+        EncoderType::Compressed => {
+            Encoder::new_compressed(CompressedOutput::new(meta, writer), options)
+        }
+        #[cfg(feature = "lz")]
+        EncoderType::Lz => Encoder::new_lz(LzOutput::new(meta, writer), options),
+        _ => return Err(CodecError::MalformedEncoder),
+    };
+    encoder.ingest_events(events)?;
+    let writer = encoder
+        .close_writer()?
+        .ok_or(CodecError::UnitializedStream)?;
+    Ok(writer.into_inner())
+}
+
+/// Open a decoder for an in-memory stream, which may be raw, compressed, or Lz (without a
+/// dictionary). The header is decoded immediately.
+pub fn decoder_from_vec(bytes: Vec<u8>) -> Result<(MemoryDecoder, MemoryReader), CodecError> {
+    let mut reader = BitReader::endian(Cursor::new(bytes), BigEndian);
+    #[allow(unused_mut)]
+    let mut decoder = Decoder::new_raw(RawInput::new(), &mut reader);
+    #[cfg(feature = "compression")]
+    if matches!(decoder, Err(CodecError::WrongMagic)) {
+        reader.seek_bits(std::io::SeekFrom::Start(0))?;
+        decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut reader);
+    }
+    #[cfg(feature = "lz")]
+    if matches!(decoder, Err(CodecError::WrongMagic)) {
+        reader.seek_bits(std::io::SeekFrom::Start(0))?;
+        decoder = Decoder::new_lz(LzInput::new(None), &mut reader);
+    }
+    Ok((decoder?, reader))
+}
+
+/// Decode every event of an in-memory stream, which may be raw, compressed, or Lz. Returns the
+/// stream's metadata along with the events.
+pub fn decode_from_vec(bytes: Vec<u8>) -> Result<(CodecMetadata, Vec<Event>), CodecError> {
+    let (decoder, reader) = decoder_from_vec(bytes)?;
+    let meta = *decoder.meta();
+    let events = decoder.into_events(reader).collect::<Result<_, _>>()?;
+    Ok((meta, events))
+}
+
+/// Encode the events to an in-memory stream of the given type, and decode them back.
+///
+/// A compressed stream may be lossy, and it decodes the events of each Adu in a different order
+/// than they were ingested, so compare the results with [`sorted_by_pixel`].
+pub fn round_trip(
+    encoder_type: EncoderType,
+    meta: CodecMetadata,
+    options: EncoderOptions,
+    events: &[Event],
+) -> Result<Vec<Event>, CodecError> {
+    let bytes = encode_to_vec(encoder_type, meta, options, events)?;
+    decode_from_vec(bytes).map(|(_, events)| events)
+}
+
+/// Sort the events by pixel, keeping the order of each pixel's events, so that streams which
+/// interleave the pixels' events differently can be compared
+pub fn sorted_by_pixel(mut events: Vec<Event>) -> Vec<Event> {
+    events.sort_by_key(|event| (event.coord.y, event.coord.x, event.coord.c));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw_event::ByteOrder;
+    use crate::{Coord, PlaneSize, TimeMode};
+
+    fn events() -> Vec<Event> {
+        (0..100)
+            .map(|t| Event {
+                coord: Coord {
+                    x: t % 4,
+                    y: t / 4 % 4,
+                    c: None,
+                },
+                d: 7,
+                t: t * 10,
+            })
+            .collect()
+    }
+
+    fn meta(plane: PlaneSize) -> CodecMetadata {
+        CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let decoded = round_trip(
+            EncoderType::Raw,
+            meta(plane),
+            EncoderOptions::default(plane),
+            &events(),
+        )?;
+        assert_eq!(decoded, events());
+        Ok(())
+    }
+
+    #[test]
+    fn raw_little_endian_round_trip() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let writer = Cursor::new(Vec::new());
+        let mut encoder = Encoder::new_raw(
+            RawOutput::with_byte_order(meta(plane), writer, ByteOrder::Little),
+            EncoderOptions::default(plane),
+        );
+        encoder.ingest_events(&events())?;
+        let bytes = encoder
+            .close_writer()?
+            .ok_or(CodecError::UnitializedStream)?
+            .into_inner();
+        assert_eq!(bytes[..5], *b"addel");
+
+        let (decoder, _) = decoder_from_vec(bytes.clone())?;
+        assert_eq!(decoder.byte_order(), ByteOrder::Little);
+        let start = decoder.meta().header_size;
+
+        // The last event's timestamp, least significant byte first
+        let last = start + 99 * 9;
+        assert_eq!(bytes[last + 5..last + 9], 990_u32.to_le_bytes());
+
+        let (_, decoded) = decode_from_vec(bytes)?;
+        assert_eq!(decoded, events());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn compressed_round_trip() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        let meta = CodecMetadata {
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..meta(plane)
+        };
+        let bytes = encode_to_vec(EncoderType::Compressed, meta, options, &events())?;
+        let (meta, decoded) = decode_from_vec(bytes)?;
+        assert_eq!(meta.plane, plane);
+        assert_eq!(sorted_by_pixel(decoded), sorted_by_pixel(events()));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "lz")]
+    fn lz_round_trip() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let decoded = round_trip(
+            EncoderType::Lz,
+            meta(plane),
+            EncoderOptions::default(plane),
+            &events(),
+        )?;
+        assert_eq!(decoded, events());
+        Ok(())
+    }
+
+    #[test]
+    fn empty_encoder() {
+        let plane = PlaneSize::default();
+        assert!(matches!(
+            encode_to_vec(
+                EncoderType::Empty,
+                meta(plane),
+                EncoderOptions::default(plane),
+                &[]
+            ),
+            Err(CodecError::MalformedEncoder)
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::codec::decoder::Decoder;
+use crate::codec::encoder::Encoder;
+use crate::codec::CodecError;
+use crate::{is_framed, AbsoluteT, TimeMode};
+use bitstream_io::{BigEndian, BitReader};
+use std::io::{Read, Seek, Write};
+
+/// The number of events passed to the encoder at a time
+const BATCH_SIZE: usize = 4096;
+
+/// Pipe the remaining events of `decoder` into `encoder`, e.g., to compress a raw capture or to
+/// decompress a stream for editing, without the full transcoder. Returns the number of events
+/// transcoded.
+///
+/// The events are passed on in small batches, so memory use is bounded by the plane size rather
+/// than the length of the stream. If only one of the streams has [delta](TimeMode::DeltaT)
+/// timestamps, the timestamps are converted along the way. The encoder isn't closed, so more
+/// events can be added before calling [`Encoder::close_writer`].
+pub fn transcode<R, W>(
+    decoder: &mut Decoder<R>,
+    reader: &mut BitReader<R, BigEndian>,
+    encoder: &mut Encoder<W>,
+) -> Result<u64, CodecError>
+where
+    R: Read + Seek,
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+{
+    let input_meta = *decoder.meta();
+    let output_meta = *encoder.meta();
+    if input_meta.plane != output_meta.plane {
+        return Err(CodecError::PlaneMismatch {
+            input: input_meta.plane,
+            output: output_meta.plane,
+        });
+    }
+
+    // Decoded `AbsoluteT` and `Mixed` events both carry absolute timestamps
+    let input_delta_t = input_meta.time_mode == TimeMode::DeltaT;
+    let convert = input_delta_t != (output_meta.time_mode == TimeMode::DeltaT);
+    let framed = is_framed(input_meta.source_camera) && input_meta.codec_version > 0;
+    let plane = input_meta.plane;
+
+    // The absolute time that each pixel's next delta timestamp counts from
+    let mut last_t: Vec<AbsoluteT> = if convert {
+        vec![0; plane.volume()]
+    } else {
+        Vec::new()
+    };
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut num_events = 0;
+    for event in decoder.events(reader) {
+        let mut event = event?;
+        if convert {
+            let idx = (event.coord.y_usize() * plane.w_usize() + event.coord.x_usize())
+                * plane.c_usize()
+                + event.coord.c_usize();
+            let t = &mut last_t[idx];
+            if input_delta_t {
+                *t += event.t;
+                event.t = *t;
+            } else {
+                event.t = event.t.saturating_sub(*t);
+                *t += event.t;
+            }
+
+            // For a framed source, the pixel's next event starts at the next frame boundary
+            if framed && *t % input_meta.ref_interval > 0 {
+                *t = ((*t / input_meta.ref_interval) + 1) * input_meta.ref_interval;
+            }
+        }
+
+        batch.push(event);
+        if batch.len() == BATCH_SIZE {
+            encoder.ingest_events(&batch)?;
+            num_events += batch.len() as u64;
+            batch.clear();
+        }
+    }
+    encoder.ingest_events(&batch)?;
+    num_events += batch.len() as u64;
+    Ok(num_events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::transcode;
+    use crate::codec::encoder::Encoder;
+    use crate::codec::raw::stream::RawOutput;
+    use crate::codec::testing::{decode_from_vec, decoder_from_vec, encode_to_vec};
+    use crate::codec::{CodecError, CodecMetadata, EncoderOptions, EncoderType};
+    use crate::{Coord, Event, PlaneSize, SourceCamera, TimeMode};
+    use std::io::Cursor;
+
+    fn events() -> Vec<Event> {
+        (0..100)
+            .map(|t| Event {
+                coord: Coord {
+                    x: t % 4,
+                    y: t / 4 % 4,
+                    c: None,
+                },
+                d: 7,
+                t: t * 10,
+            })
+            .collect()
+    }
+
+    fn meta(plane: PlaneSize, time_mode: TimeMode) -> CodecMetadata {
+        CodecMetadata {
+            time_mode,
+            plane,
+            source_camera: SourceCamera::Dvs,
+            ..Default::default()
+        }
+    }
+
+    /// Transcode an in-memory stream to a raw stream with the given time mode
+    fn transcode_raw(bytes: Vec<u8>, time_mode: TimeMode) -> Result<Vec<u8>, CodecError> {
+        let (mut decoder, mut reader) = decoder_from_vec(bytes)?;
+        let plane = decoder.meta().plane;
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(meta(plane, time_mode), Cursor::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        assert_eq!(transcode(&mut decoder, &mut reader, &mut encoder)?, 100);
+        Ok(encoder.close_writer()?.unwrap().into_inner())
+    }
+
+    #[test]
+    fn test_transcode_time_modes() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let bytes = encode_to_vec(
+            EncoderType::Raw,
+            meta(plane, TimeMode::AbsoluteT),
+            EncoderOptions::default(plane),
+            &events(),
+        )?;
+
+        // Each pixel fires every 160 ticks
+        let bytes = transcode_raw(bytes, TimeMode::DeltaT)?;
+        let (meta, decoded) = decode_from_vec(bytes.clone())?;
+        assert_eq!(meta.time_mode, TimeMode::DeltaT);
+        assert!(decoded[..16].iter().zip(events()).all(|(a, b)| a == &b));
+        assert!(decoded[16..].iter().all(|event| event.t == 160));
+
+        let bytes = transcode_raw(bytes, TimeMode::AbsoluteT)?;
+        assert_eq!(decode_from_vec(bytes)?.1, events());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_transcode_compressed() -> Result<(), CodecError> {
+        use crate::codec::compressed::stream::CompressedOutput;
+        use crate::codec::testing::sorted_by_pixel;
+
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let meta = CodecMetadata {
+            tps: 7650,
+            ref_interval: 255,
+            delta_t_max: 255 * 5,
+            adu_interval: 5,
+            ..meta(plane, TimeMode::AbsoluteT)
+        };
+        let raw = encode_to_vec(
+            EncoderType::Raw,
+            meta,
+            EncoderOptions::default(plane),
+            &events(),
+        )?;
+
+        let (mut decoder, mut reader) = decoder_from_vec(raw)?;
+        let mut options = EncoderOptions::default(plane);
+        options.crf.override_c_thresh_max(0);
+        let mut encoder = Encoder::new_compressed(
+            CompressedOutput::new(meta, Cursor::new(Vec::new())),
+            options,
+        );
+        assert_eq!(transcode(&mut decoder, &mut reader, &mut encoder)?, 100);
+        let compressed = encoder.close_writer()?.unwrap().into_inner();
+
+        let (meta, decoded) = decode_from_vec(compressed)?;
+        assert_eq!(meta.time_mode, TimeMode::AbsoluteT);
+        assert_eq!(sorted_by_pixel(decoded), sorted_by_pixel(events()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcode_plane_mismatch() -> Result<(), CodecError> {
+        let plane = PlaneSize::new(4, 4, 1)?;
+        let bytes = encode_to_vec(
+            EncoderType::Raw,
+            meta(plane, TimeMode::AbsoluteT),
+            EncoderOptions::default(plane),
+            &events(),
+        )?;
+        let (mut decoder, mut reader) = decoder_from_vec(bytes)?;
+        let other_plane = PlaneSize::new(8, 8, 1)?;
+        let mut encoder = Encoder::new_raw(
+            RawOutput::new(
+                meta(other_plane, TimeMode::AbsoluteT),
+                Cursor::new(Vec::new()),
+            ),
+            EncoderOptions::default(other_plane),
+        );
+        assert!(matches!(
+            transcode(&mut decoder, &mut reader, &mut encoder),
+            Err(CodecError::PlaneMismatch { .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::codec::CodecError;
+use crate::{AbsoluteT, Event, PlaneSize};
+
+/// Checks that each pixel's events have strictly increasing timestamps, as the encoder requires
+/// and the decoder assumes. The events of different pixels may be interleaved in any order, as
+/// they are within each Adu of a compressed stream.
+///
+/// The events must have absolute timestamps.
+#[derive(Debug, Clone)]
+pub struct OrderValidator {
+    plane: PlaneSize,
+
+    /// The timestamp of each pixel's last valid event
+    last_t: Vec<Option<AbsoluteT>>,
+}
+
+impl OrderValidator {
+    /// Create a validator for events on `plane`
+    pub fn new(plane: PlaneSize) -> Self {
+        Self {
+            plane,
+            last_t: vec![None; plane.volume()],
+        }
+    }
+
+    /// Check that the event comes after its pixel's last valid event, and if it does, record it as
+    /// the pixel's latest. Returns [`CodecError::DuplicateEvent`] or
+    /// [`CodecError::OutOfOrderEvent`] if it doesn't. Events outside the plane aren't checked.
+    pub fn validate(&mut self, event: &Event) -> Result<(), CodecError> {
+        let (x, y) = (event.coord.x, event.coord.y);
+        if x >= self.plane.w()
+            || y >= self.plane.h()
+            || event.coord.c_usize() >= self.plane.c_usize()
+        {
+            return Ok(());
+        }
+        let idx = (y as usize * self.plane.w_usize() + x as usize) * self.plane.c_usize()
+            + event.coord.c_usize();
+        check_order(&mut self.last_t[idx], event)
+    }
+
+    /// Forget each pixel's last timestamp, e.g., after seeking to another part of the stream
+    pub fn reset(&mut self) {
+        self.last_t.fill(None);
+    }
+}
+
+/// Check that the event comes after `px_last_t`, the timestamp of its pixel's last valid event.
+/// If it does, record it as the pixel's latest.
+pub(crate) fn check_order(
+    px_last_t: &mut Option<AbsoluteT>,
+    event: &Event,
+) -> Result<(), CodecError> {
+    let (x, y, c, t) = (event.coord.x, event.coord.y, event.coord.c, event.t);
+    match *px_last_t {
+        Some(last_t) if t == last_t => Err(CodecError::DuplicateEvent { x, y, c, t }),
+        Some(last_t) if t < last_t => Err(CodecError::OutOfOrderEvent { x, y, c, t, last_t }),
+        _ => {
+            *px_last_t = Some(t);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderValidator;
+    use crate::codec::CodecError;
+    use crate::{Coord, Event, PlaneSize};
+
+    fn event(x: u32, t: u32) -> Event {
+        Event {
+            coord: Coord { x, y: 0, c: None },
+            d: 7,
+            t,
+        }
+    }
+
+    #[test]
+    fn test_validate_order() {
+        let mut validator = OrderValidator::new(PlaneSize::new(2, 1, 1).unwrap());
+        assert!(validator.validate(&event(0, 10)).is_ok());
+
+        // Pixels are independent
+        assert!(validator.validate(&event(1, 5)).is_ok());
+        assert!(validator.validate(&event(0, 20)).is_ok());
+
+        assert!(matches!(
+            validator.validate(&event(0, 20)),
+            Err(CodecError::DuplicateEvent { x: 0, t: 20, .. })
+        ));
+        assert!(matches!(
+            validator.validate(&event(0, 15)),
+            Err(CodecError::OutOfOrderEvent {
+                x: 0,
+                t: 15,
+                last_t: 20,
+                ..
+            })
+        ));
+
+        // An invalid event doesn't move the pixel's last timestamp back
+        assert!(validator.validate(&event(0, 18)).is_err());
+        assert!(validator.validate(&event(0, 21)).is_ok());
+
+        validator.reset();
+        assert!(validator.validate(&event(0, 1)).is_ok());
+    }
+}
@@ -1,31 +1,55 @@
 #![warn(missing_docs)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 //! # adder-codec-core
 //!
 //! The core types and utilities for encoding and decoding ADΔER events
+//!
+//! Without the default `std` feature, only the event types, their constants, and the
+//! [fixed-size raw event format](raw_event::RawEventFormat) are available, for emitting events
+//! from firmware and other `no_std + alloc` targets.
+
+extern crate alloc;
+
+/// Statistics of event streams
+#[cfg(feature = "std")]
+pub mod analysis;
 
 /// Expose public API for encoding and decoding
+#[cfg(feature = "std")]
 pub mod codec;
 
+/// Fixed-size (de)serialization of events in the layout of a raw stream
+pub mod raw_event;
+
+#[cfg(feature = "std")]
 pub use bitstream_io;
-use bitstream_io::{BigEndian, BitReader};
-use std::cmp::Ordering;
+#[cfg(feature = "std")]
+use bitstream_io::{BigEndian, BitRead, BitReader};
+use core::cmp::Ordering;
+use core::ops::{Add, Sub};
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::BufReader;
-use std::ops::Add;
 
+#[cfg(feature = "std")]
 use thiserror::Error;
 
 /// Error type for the `PlaneSize` struct
 #[allow(missing_docs)]
-#[derive(Error, Debug)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[derive(Debug)]
 pub enum PlaneError {
-    #[error(
-        "plane dimensions invalid. All must be positive. Found {width:?}, {height:?}, {channels:?}"
+    #[cfg_attr(
+        feature = "std",
+        error(
+            "plane dimensions invalid. All must be positive. Found {width:?}, {height:?}, {channels:?}"
+        )
     )]
     InvalidPlane {
-        width: u16,
-        height: u16,
+        width: PixelAddress,
+        height: PixelAddress,
         channels: u8,
     },
 }
@@ -46,6 +70,25 @@ pub enum SourceCamera {
     Asint,
 }
 
+impl SourceCamera {
+    /// Get the source data representation of the camera
+    #[allow(clippy::match_same_arms)]
+    pub fn source_type(self) -> SourceType {
+        match self {
+            SourceCamera::FramedU8 => SourceType::U8,
+            SourceCamera::FramedU16 => SourceType::U16,
+            SourceCamera::FramedU32 => SourceType::U32,
+            SourceCamera::FramedU64 => SourceType::U64,
+            SourceCamera::FramedF32 => SourceType::F32,
+            SourceCamera::FramedF64 => SourceType::F64,
+            SourceCamera::Dvs => SourceType::U8,
+            SourceCamera::DavisU8 => SourceType::U8,
+            SourceCamera::Atis => SourceType::U8,
+            SourceCamera::Asint => SourceType::F64,
+        }
+    }
+}
+
 /// Is the given source camera a framed source?
 pub fn is_framed(source_camera: SourceCamera) -> bool {
     matches!(
@@ -63,9 +106,20 @@ pub fn is_framed(source_camera: SourceCamera) -> bool {
 // use crate::codec::compressed::blocks::{DeltaTResidual, EventResidual};
 #[cfg(feature = "compression")]
 use crate::codec::compressed::stream::CompressedInput;
-use crate::codec::decoder::Decoder;
-use crate::codec::raw::stream::RawInput;
-use crate::codec::CodecError;
+#[cfg(feature = "std")]
+use crate::codec::decoder::{read_header, read_header_extension, Decoder};
+#[cfg(feature = "compression")]
+use crate::codec::header::MAGIC_COMPRESSED;
+#[cfg(feature = "lz")]
+use crate::codec::header::MAGIC_LZ;
+#[cfg(feature = "std")]
+use crate::codec::header::{MAGIC_RAW, MAGIC_RAW_LE};
+#[cfg(feature = "lz")]
+use crate::codec::lz::stream::LzInput;
+#[cfg(feature = "std")]
+use crate::codec::raw::stream::{deserialize_event, RawInput};
+#[cfg(feature = "std")]
+use crate::codec::{CodecError, CodecMetadata, EncoderType, UserMetadata};
 use serde::{Deserialize, Serialize};
 
 /// The type of time used in the ADΔER representation
@@ -78,15 +132,87 @@ pub enum TimeMode {
     #[default]
     AbsoluteT,
 
-    /// TODO
+    /// Each pixel's first event in an ADU carries the absolute time, and its later events in the
+    /// same ADU carry the delta time from its previous event. Events are encoded from and decoded
+    /// to absolute times.
     Mixed,
 }
 
+/// How the color channels of an ADΔER stream are sampled
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// Every channel is sampled at full resolution
+    #[default]
+    None,
+
+    /// The first channel is sampled at full resolution, while the second and third channels are
+    /// sampled at half resolution in each dimension. Only the pixels with even x and y
+    /// coordinates produce events for those channels.
+    Half,
+}
+
+impl ChromaSubsampling {
+    /// Does the given pixel produce events under this subsampling scheme?
+    pub fn is_sampled(&self, coord: Coord) -> bool {
+        match self {
+            ChromaSubsampling::None => true,
+            ChromaSubsampling::Half => {
+                coord.c.unwrap_or(0) == 0 || (coord.x % 2 == 0 && coord.y % 2 == 0)
+            }
+        }
+    }
+
+    /// The other pixels which an event at `coord` stands in for, for upsampling the subsampled
+    /// channels back to full resolution. Empty if `coord` is not a subsampled pixel.
+    pub fn upsampled_coords(&self, coord: Coord, plane: PlaneSize) -> impl Iterator<Item = Coord> {
+        let replicate = *self == ChromaSubsampling::Half
+            && coord.c.unwrap_or(0) > 0
+            && coord.x % 2 == 0
+            && coord.y % 2 == 0;
+        [(1, 0), (0, 1), (1, 1)]
+            .into_iter()
+            .filter(move |_| replicate)
+            .map(move |(dx, dy)| Coord {
+                x: coord.x + dx,
+                y: coord.y + dy,
+                c: coord.c,
+            })
+            .filter(move |px| px.x < plane.w() && px.y < plane.h())
+    }
+}
+
+/// The color space of the channels of a 3-channel ADΔER stream
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Blue, green, and red channels
+    #[default]
+    Bgr,
+
+    /// Luma (Y), blue-difference (Cb), and red-difference (Cr) channels, as output natively by
+    /// video decoders. The conversion follows BT.601 at full range, with the chroma channels
+    /// offset by half the maximum intensity.
+    YCbCr,
+}
+
+impl ColorSpace {
+    /// Convert a (Y, Cb, Cr) pixel to (B, G, R), where `max_intensity` is the maximum intensity
+    /// of each channel. The result may fall outside `[0, max_intensity]`.
+    pub fn ycbcr_to_bgr([y, cb, cr]: [f64; 3], max_intensity: f64) -> [f64; 3] {
+        let cb = cb - max_intensity / 2.0;
+        let cr = cr - max_intensity / 2.0;
+        [
+            y + 1.772 * cb,
+            y - 0.344_136 * cb - 0.714_136 * cr,
+            y + 1.402 * cr,
+        ]
+    }
+}
+
 /// The size of the image plane in pixels
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PlaneSize {
-    width: u16,
-    height: u16,
+    width: PixelAddress,
+    height: PixelAddress,
     channels: u8,
 }
 
@@ -102,7 +228,11 @@ impl Default for PlaneSize {
 
 impl PlaneSize {
     /// Create a new `PlaneSize` with the given width, height, and channels
-    pub fn new(width: u16, height: u16, channels: u8) -> Result<Self, PlaneError> {
+    pub fn new(
+        width: PixelAddress,
+        height: PixelAddress,
+        channels: u8,
+    ) -> Result<Self, PlaneError> {
         if width == 0 || height == 0 || channels == 0 {
             return Err(PlaneError::InvalidPlane {
                 width,
@@ -117,7 +247,7 @@ impl PlaneSize {
         })
     }
     /// The width, shorthand for `self.width`
-    pub fn w(&self) -> u16 {
+    pub fn w(&self) -> PixelAddress {
         self.width
     }
 
@@ -127,7 +257,7 @@ impl PlaneSize {
     }
 
     /// The height, shorthand for `self.height`
-    pub fn h(&self) -> u16 {
+    pub fn h(&self) -> PixelAddress {
         self.height
     }
 
@@ -167,20 +297,29 @@ impl PlaneSize {
     }
 
     /// The smaller of the width and height dimensions
-    pub fn min_resolution(&self) -> u16 {
+    pub fn min_resolution(&self) -> PixelAddress {
         self.width.min(self.height)
     }
 
     /// The larger of the width and height dimensions
-    pub fn max_resolution(&self) -> u16 {
+    pub fn max_resolution(&self) -> PixelAddress {
         self.width.max(self.height)
     }
+
+    /// Whether the plane is too large for its pixel addresses to fit in the narrow (16-bit)
+    /// event format, so its events must use the wide (32-bit) format
+    pub fn needs_wide_coordinates(&self) -> bool {
+        self.max_resolution() > PixelAddress::from(NARROW_EOF_PX_ADDRESS)
+    }
 }
 
 /// Decimation value; a pixel's sensitivity.
 pub type D = u8;
 
-/// The maximum possible [`D`] value
+/// The maximum possible [`D`] value. A stream may declare a smaller maximum in its
+/// [`CodecMetadata::d_max`](codec::CodecMetadata::d_max), but not a larger one: the values above
+/// it are taken by [`D_ZERO_INTEGRATION`] and [`D_EMPTY`], so a wider range (e.g., for HDR
+/// sources) would need a new event format.
 pub const D_MAX: D = 127;
 
 /// Special symbol signifying no information (filler dt)
@@ -204,7 +343,7 @@ pub enum Mode {
     Continuous,
 }
 
-#[derive(Clone, Copy, PartialEq, Default, Debug)]
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
 pub enum PixelMultiMode {
     Normal,
 
@@ -234,8 +373,9 @@ make_d_shift_array!(D_SHIFT, UDshift);
 make_d_shift_array!(D_SHIFT_F64, f64);
 make_d_shift_array!(D_SHIFT_F32, f32);
 
-/// The maximum intensity representation for input data. Currently 255 for 8-bit framed input.
-pub const MAX_INTENSITY: f32 = 255.0; // TODO: make variable, dependent on input bit depth
+/// The maximum intensity representation for 8-bit input data. For other bit depths, see
+/// [`SourceType::max_intensity`].
+pub const MAX_INTENSITY: f32 = 255.0;
 
 /// The default [`D`] value for every pixel at the beginning of transcode
 pub const D_START: D = 7;
@@ -254,10 +394,17 @@ pub type BigT = u64;
 pub type Intensity = f64;
 
 /// Pixel x- or y- coordinate address in the ADΔER model
-pub type PixelAddress = u16;
+pub type PixelAddress = u32;
 
 /// Special pixel address when signifying the end of a sequence of [Events](Event)
-pub const EOF_PX_ADDRESS: PixelAddress = u16::MAX;
+pub const EOF_PX_ADDRESS: PixelAddress = PixelAddress::MAX;
+
+/// Pixel x- or y- coordinate address in the narrow event format of a raw stream, which is used
+/// unless the stream declares wide coordinates
+pub type NarrowPixelAddress = u16;
+
+/// Special pixel address when signifying the end of a sequence of narrow events
+pub const NARROW_EOF_PX_ADDRESS: NarrowPixelAddress = NarrowPixelAddress::MAX;
 
 /// Pixel channel address in the ADΔER model
 #[repr(packed)]
@@ -358,6 +505,62 @@ impl Coord {
     }
 }
 
+/// A rectangular region of interest of the image plane, spanning all of its channels
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Roi {
+    /// The x-coordinate of the region's left column
+    pub x: PixelAddress,
+
+    /// The y-coordinate of the region's top row
+    pub y: PixelAddress,
+
+    /// The width of the region, in pixels
+    pub width: PixelAddress,
+
+    /// The height of the region, in pixels
+    pub height: PixelAddress,
+}
+
+impl Roi {
+    /// Creates a new region with its top-left corner at `(x, y)`
+    pub fn new(
+        x: PixelAddress,
+        y: PixelAddress,
+        width: PixelAddress,
+        height: PixelAddress,
+    ) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns true if the coordinate falls inside the region
+    pub fn contains(&self, coord: Coord) -> bool {
+        self.intersects(coord.x, coord.y, 1, 1)
+    }
+
+    /// Returns true if the region overlaps the rectangle with its top-left corner at `(x, y)`
+    pub fn intersects(
+        &self,
+        x: PixelAddress,
+        y: PixelAddress,
+        width: PixelAddress,
+        height: PixelAddress,
+    ) -> bool {
+        let overlaps = |start: PixelAddress,
+                        len: PixelAddress,
+                        other_start: PixelAddress,
+                        other_len: PixelAddress| {
+            u64::from(start) < u64::from(other_start) + u64::from(other_len)
+                && u64::from(other_start) < u64::from(start) + u64::from(len)
+        };
+        overlaps(self.x, self.width, x, width) && overlaps(self.y, self.height, y, height)
+    }
+}
+
 /// A 2D coordinate representation
 #[allow(missing_docs)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -447,6 +650,21 @@ pub enum SourceType {
     F64,
 }
 
+impl SourceType {
+    /// The maximum intensity of a single source sample. Floating-point sources are normalized to
+    /// the range `[0, 1]`.
+    pub fn max_intensity(self) -> f64 {
+        match self {
+            SourceType::U8 => f64::from(u8::MAX),
+            SourceType::U16 => f64::from(u16::MAX),
+            SourceType::U32 => f64::from(u32::MAX),
+            SourceType::U64 => u64::MAX as f64,
+            SourceType::F32 | SourceType::F64 => 1.0,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 const EOF_EVENT: Event = Event {
     coord: Coord {
         x: EOF_PX_ADDRESS,
@@ -457,7 +675,9 @@ const EOF_EVENT: Event = Event {
     t: 0,
 };
 
-/// Helper function for opening a file as a raw or compressed input ADΔER stream
+/// Helper function for opening a file as a raw, compressed, or Lz (without a dictionary) input
+/// ADΔER stream
+#[cfg(feature = "std")]
 pub fn open_file_decoder(
     file_path: &str,
 ) -> Result<
@@ -467,31 +687,118 @@ pub fn open_file_decoder(
     ),
     CodecError,
 > {
-    let mut bufreader = BufReader::new(File::open(file_path)?);
-    let compression = RawInput::new();
-    let mut bitreader = BitReader::endian(bufreader, BigEndian);
-
-    // First try opening the file as a raw file, then try as a compressed file
-    let stream = match Decoder::new_raw(compression, &mut bitreader) {
-        Ok(reader) => reader,
-        Err(CodecError::WrongMagic) => {
-            #[cfg(feature = "compression")]
-            {
-                dbg!("Opening as compressed");
-                bufreader = BufReader::new(File::open(file_path)?);
-                let compression = CompressedInput::new(0, 0, 0); // TODO: temporary args. Need to refactor.
-                bitreader = BitReader::endian(bufreader, BigEndian);
-                Decoder::new_compressed(compression, &mut bitreader)?
-            }
+    let open = || -> Result<_, CodecError> {
+        Ok(BitReader::endian(
+            BufReader::new(File::open(file_path)?),
+            BigEndian,
+        ))
+    };
+    let mut bitreader = open()?;
+
+    // First try opening the file as a raw file, then as a compressed file, then as an Lz file
+    #[allow(unused_mut)]
+    let mut stream = Decoder::new_raw(RawInput::new(), &mut bitreader);
+    #[cfg(feature = "compression")]
+    if matches!(stream, Err(CodecError::WrongMagic)) {
+        dbg!("Opening as compressed");
+        bitreader = open()?;
+        let compression = CompressedInput::new(0, 0, 0); // TODO: temporary args. Need to refactor.
+        stream = Decoder::new_compressed(compression, &mut bitreader);
+    }
+    #[cfg(feature = "lz")]
+    if matches!(stream, Err(CodecError::WrongMagic)) {
+        bitreader = open()?;
+        stream = Decoder::new_lz(LzInput::new(None), &mut bitreader);
+    }
+    Ok((stream?, bitreader))
+}
 
-            #[cfg(not(feature = "compression"))]
-            return Err(CodecError::WrongMagic);
-        }
-        Err(e) => {
-            return Err(e);
+/// The properties of an ADΔER file, as read by [`probe`]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeInfo {
+    /// How the stream is encoded
+    pub encoder_type: EncoderType,
+
+    /// The metadata from the stream's header, including its codec version, plane size, ticks per
+    /// second, source camera, and time mode
+    pub meta: CodecMetadata,
+
+    /// The size of the file, in bytes
+    pub file_size: u64,
+
+    /// The estimated duration of the stream, in ticks. This is the timestamp of the last event of
+    /// a raw stream with absolute timestamps, or the end of the last Adu of a compressed stream
+    /// with a time index. It's `None` for other streams, whose duration can't be found without
+    /// decoding them entirely.
+    pub duration_ticks: Option<AbsoluteT>,
+}
+
+#[cfg(feature = "std")]
+impl ProbeInfo {
+    /// The estimated duration of the stream, in seconds
+    pub fn duration_seconds(&self) -> Option<f64> {
+        self.duration_ticks
+            .map(|ticks| f64::from(ticks) / f64::from(self.meta.tps.max(1)))
+    }
+}
+
+/// Read the properties of an ADΔER file (raw, compressed, or Lz) from its header, without decoding
+/// any of its events. The duration is estimated from the end of the file where possible: the last
+/// event of a raw stream, or the time index of a compressed stream. No decoder is set up, so this
+/// is cheap even for large planes.
+#[cfg(feature = "std")]
+pub fn probe(file_path: &str) -> Result<ProbeInfo, CodecError> {
+    let file_size = std::fs::metadata(file_path)?.len();
+    let mut reader = BitReader::endian(BufReader::new(File::open(file_path)?), BigEndian);
+    let (magic, mut meta) = read_header(&mut reader)?;
+    let encoder_type = match magic {
+        MAGIC_RAW | MAGIC_RAW_LE => EncoderType::Raw,
+        #[cfg(feature = "compression")]
+        MAGIC_COMPRESSED => EncoderType::Compressed,
+        #[cfg(feature = "lz")]
+        MAGIC_LZ => EncoderType::Lz,
+        _ => return Err(CodecError::WrongMagic),
+    };
+    read_header_extension(&mut reader, &mut meta, &mut UserMetadata::new())?;
+
+    let duration_ticks = match encoder_type {
+        EncoderType::Raw if meta.time_mode == TimeMode::AbsoluteT => {
+            // Read the last event, skipping the EOF marker if the stream was closed properly
+            let byte_order = match magic {
+                MAGIC_RAW_LE => raw_event::ByteOrder::Little,
+                _ => raw_event::ByteOrder::Big,
+            };
+            let event_size = u64::from(meta.event_size.max(1));
+            let num_records = file_size.saturating_sub(meta.header_size as u64) / event_size;
+            let mut bytes = vec![0; event_size as usize];
+            (num_records.saturating_sub(2)..num_records)
+                .rev()
+                .find_map(|idx| {
+                    let pos = meta.header_size as u64 + idx * event_size;
+                    reader.seek_bits(std::io::SeekFrom::Start(pos * 8)).ok()?;
+                    reader.read_bytes(&mut bytes).ok()?;
+                    deserialize_event(&meta, byte_order, &bytes)
+                        .ok()
+                        .filter(|event| !event.coord.is_eof())
+                        .map(|event| event.t)
+                })
         }
+        // The last Adu starts at the last entry of the time index
+        #[cfg(feature = "compression")]
+        EncoderType::Compressed => CompressedInput::read_time_index(&mut reader)
+            .ok()
+            .and_then(|time_index| time_index.last().map(|entry| entry.start_t))
+            .map(|start_t| start_t.saturating_add(meta.delta_t_max)),
+        _ => None,
     };
-    Ok((stream, bitreader))
+
+    Ok(ProbeInfo {
+        encoder_type,
+        meta,
+        file_size,
+        duration_ticks,
+    })
 }
 
 /// An ADΔER event representation
@@ -511,9 +818,9 @@ pub struct EventCoordlessRelative {
     pub delta_t: DeltaT,
 }
 
-impl Into<f64> for EventCoordless {
-    fn into(self) -> f64 {
-        panic!("Not implemented")
+impl From<EventCoordless> for f64 {
+    fn from(event: EventCoordless) -> Self {
+        event.intensity()
     }
 }
 
@@ -523,6 +830,76 @@ impl EventCoordless {
     pub fn t(&self) -> AbsoluteT {
         self.t as AbsoluteT
     }
+
+    /// The intensity of the event, treating `t` as the Δt since the pixel's last event. A Δt of 0
+    /// is treated as 1 tick. Events with a special D value (e.g., [`D_EMPTY`]) have no intensity.
+    #[inline]
+    pub fn intensity(&self) -> Intensity {
+        match D_SHIFT_F64.get(usize::from(self.d)) {
+            Some(integration) => integration / f64::from(self.t.max(1)),
+            None => 0.0,
+        }
+    }
+
+    /// Add the D and t values, saturating at their bounds
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self {
+            d: self.d.saturating_add(rhs.d),
+            t: self.t.saturating_add(rhs.t),
+        }
+    }
+
+    /// Subtract the D and t values, saturating at 0
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self {
+            d: self.d.saturating_sub(rhs.d),
+            t: self.t.saturating_sub(rhs.t),
+        }
+    }
+
+    /// The signed (D, t) residuals of this event from a `prediction` of it
+    #[inline]
+    pub fn residual(&self, prediction: &Self) -> (i64, i64) {
+        (
+            i64::from(self.d) - i64::from(prediction.d),
+            i64::from(self.t) - i64::from(prediction.t),
+        )
+    }
+
+    /// Reconstruct an event from this prediction of it and the residuals given by
+    /// [`EventCoordless::residual`]. Returns `None` if the D or t value would be out of range.
+    #[inline]
+    pub fn checked_add_residual(&self, d_residual: i64, t_residual: i64) -> Option<Self> {
+        Some(Self {
+            d: D::try_from(i64::from(self.d).checked_add(d_residual)?).ok()?,
+            t: AbsoluteT::try_from(i64::from(self.t).checked_add(t_residual)?).ok()?,
+        })
+    }
+
+    /// Interpolate between this event (at a `weight` of 0) and `other` (at a `weight` of 1). The
+    /// t values are interpolated linearly. The D values are interpolated by the intensity they
+    /// integrate (2^D) rather than by D itself, and rounded to the nearest D. If either event has
+    /// a special D value, the D of the nearer event is used.
+    #[cfg(feature = "std")]
+    pub fn interpolate(&self, other: &Self, weight: f64) -> Self {
+        let weight = weight.clamp(0.0, 1.0);
+        let t = f64::from(self.t) + (f64::from(other.t) - f64::from(self.t)) * weight;
+        let d = if self.d > D_MAX || other.d > D_MAX {
+            if weight < 0.5 {
+                self.d
+            } else {
+                other.d
+            }
+        } else {
+            let integration = D_SHIFT_F64[usize::from(self.d)] * (1.0 - weight)
+                + D_SHIFT_F64[usize::from(other.d)] * weight;
+            (integration.log2().round() as D).min(D_MAX)
+        };
+        Self {
+            d,
+            t: t.round() as AbsoluteT,
+        }
+    }
 }
 
 impl From<Event> for EventCoordless {
@@ -537,8 +914,22 @@ impl From<Event> for EventCoordless {
 impl Add<EventCoordless> for EventCoordless {
     type Output = EventCoordless;
 
-    fn add(self, _rhs: EventCoordless) -> EventCoordless {
-        todo!()
+    fn add(self, rhs: EventCoordless) -> EventCoordless {
+        EventCoordless {
+            d: self.d + rhs.d,
+            t: self.t + rhs.t,
+        }
+    }
+}
+
+impl Sub<EventCoordless> for EventCoordless {
+    type Output = EventCoordless;
+
+    fn sub(self, rhs: EventCoordless) -> EventCoordless {
+        EventCoordless {
+            d: self.d - rhs.d,
+            t: self.t - rhs.t,
+        }
     }
 }
 
@@ -556,6 +947,193 @@ impl num_traits::Zero for EventCoordless {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_roi() {
+        let roi = Roi::new(10, 20, 32, 32);
+        assert!(roi.contains(Coord::new_2d(10, 20)));
+        assert!(roi.contains(Coord::new_3d(41, 51, 2)));
+        assert!(!roi.contains(Coord::new_2d(42, 20)));
+        assert!(!roi.contains(Coord::new_2d(9, 30)));
+        assert!(roi.intersects(0, 0, 16, 32));
+        assert!(!roi.intersects(0, 0, 10, 32));
+        assert!(Roi::new(PixelAddress::MAX - 1, 0, PixelAddress::MAX, 1)
+            .contains(Coord::new_2d(PixelAddress::MAX - 1, 0)));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_probe() -> Result<(), CodecError> {
+        use crate::codec::encoder::Encoder;
+        use crate::codec::raw::stream::RawOutput;
+        use crate::codec::EncoderOptions;
+        use std::io::BufWriter;
+
+        let plane = PlaneSize::new(4, 4, 1).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("adder_probe_{}.adder", rand::random::<u32>()));
+        for time_mode in [TimeMode::AbsoluteT, TimeMode::DeltaT] {
+            let meta = CodecMetadata {
+                time_mode,
+                plane,
+                tps: 1000,
+                ..Default::default()
+            };
+            let mut encoder = Encoder::new_raw(
+                RawOutput::new(meta, BufWriter::new(File::create(&path)?)),
+                EncoderOptions::default(plane),
+            );
+            for t in 1..=20 {
+                encoder.ingest_event(Event {
+                    coord: Coord::new_2d(t % 4, 0),
+                    d: 5,
+                    t: t * 100,
+                })?;
+            }
+            encoder.close_writer()?;
+
+            let info = probe(path.to_str().unwrap())?;
+            assert_eq!(info.encoder_type, EncoderType::Raw);
+            assert_eq!(info.meta.plane, plane);
+            assert_eq!(info.meta.tps, 1000);
+            assert_eq!(info.meta.time_mode, time_mode);
+            assert_eq!(
+                info.file_size,
+                (info.meta.header_size + 21 * usize::from(info.meta.event_size)) as u64
+            );
+            if time_mode == TimeMode::AbsoluteT {
+                assert_eq!(info.duration_ticks, Some(2000));
+                assert_eq!(info.duration_seconds(), Some(2.0));
+            } else {
+                assert_eq!(info.duration_ticks, None);
+            }
+        }
+
+        // A little-endian raw stream's last event is read in its own byte order
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 1000,
+            ..Default::default()
+        };
+        let mut encoder = Encoder::new_raw(
+            RawOutput::with_byte_order(
+                meta,
+                BufWriter::new(File::create(&path)?),
+                raw_event::ByteOrder::Little,
+            ),
+            EncoderOptions::default(plane),
+        );
+        encoder.ingest_event(Event {
+            coord: Coord::new_2d(1, 2),
+            d: 5,
+            t: 1500,
+        })?;
+        encoder.close_writer()?;
+        assert_eq!(probe(path.to_str().unwrap())?.duration_ticks, Some(1500));
+
+        // A compressed stream's duration comes from its time index, as when seeking to its end
+        #[cfg(feature = "compression")]
+        {
+            use crate::codec::compressed::stream::CompressedOutput;
+            use crate::codec::LATEST_CODEC_VERSION;
+
+            let plane = PlaneSize::new(16, 16, 1).unwrap();
+            let meta = CodecMetadata {
+                codec_version: LATEST_CODEC_VERSION,
+                time_mode: TimeMode::AbsoluteT,
+                plane,
+                tps: 1000,
+                ref_interval: 100,
+                delta_t_max: 500,
+                adu_interval: 5,
+                ..Default::default()
+            };
+            let mut options = EncoderOptions::default(plane);
+            options.time_index = true;
+            let mut encoder = Encoder::new_compressed(
+                CompressedOutput::new(meta, BufWriter::new(File::create(&path)?)),
+                options,
+            );
+            for t in 1..=20 {
+                encoder.ingest_event(Event {
+                    coord: Coord::new_2d(t % 16, 0),
+                    d: 5,
+                    t: t * 100,
+                })?;
+            }
+            encoder.close_writer()?;
+
+            let info = probe(path.to_str().unwrap())?;
+            assert_eq!(info.encoder_type, EncoderType::Compressed);
+            assert_eq!(info.meta.plane, plane);
+            let (mut decoder, mut bitreader) = open_file_decoder(path.to_str().unwrap())?;
+            let last_start_t = decoder.seek_to_time(&mut bitreader, AbsoluteT::MAX)?;
+            assert_eq!(info.duration_ticks, Some(last_start_t + 500));
+
+            // A corrupt or truncated stream has no duration, rather than failing the probe
+            let mut bytes = std::fs::read(&path)?;
+            let footer = bytes.len() - 8;
+            bytes[footer..footer + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+            std::fs::write(&path, &bytes)?;
+            assert_eq!(probe(path.to_str().unwrap())?.duration_ticks, None);
+            std::fs::write(&path, &bytes[..info.meta.header_size + 3])?;
+            let info = probe(path.to_str().unwrap())?;
+            assert_eq!(info.encoder_type, EncoderType::Compressed);
+            assert_eq!(info.duration_ticks, None);
+        }
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_coordless_arithmetic() {
+        use num_traits::Zero;
+
+        let a = EventCoordless { d: 5, t: 100 };
+        let b = EventCoordless { d: 7, t: 300 };
+        assert_eq!(a + b, EventCoordless { d: 12, t: 400 });
+        assert_eq!(b - a, EventCoordless { d: 2, t: 200 });
+        assert_eq!(a + EventCoordless::zero(), a);
+        assert_eq!(a.saturating_sub(b), EventCoordless { d: 0, t: 0 });
+        assert_eq!(
+            b.saturating_add(EventCoordless {
+                d: D_EMPTY,
+                t: AbsoluteT::MAX
+            }),
+            EventCoordless {
+                d: D_EMPTY,
+                t: AbsoluteT::MAX
+            }
+        );
+
+        // Residuals round-trip, and out-of-range reconstructions are caught
+        let (d_residual, t_residual) = a.residual(&b);
+        assert_eq!((d_residual, t_residual), (-2, -200));
+        assert_eq!(b.checked_add_residual(d_residual, t_residual), Some(a));
+        assert_eq!(a.checked_add_residual(-6, 0), None);
+        assert_eq!(a.checked_add_residual(0, -101), None);
+
+        assert_eq!(a.intensity(), 32.0 / 100.0);
+        assert_eq!(f64::from(EventCoordless { d: 3, t: 0 }), 8.0);
+        assert_eq!(EventCoordless { d: D_EMPTY, t: 10 }.intensity(), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_event_coordless_interpolate() {
+        let a = EventCoordless { d: 5, t: 100 };
+        let b = EventCoordless { d: 7, t: 300 };
+
+        // D is interpolated by integration: halfway between 2^5 and 2^7 is 80, or ~2^6.3
+        assert_eq!(a.interpolate(&b, 0.0), a);
+        assert_eq!(a.interpolate(&b, 1.0), b);
+        assert_eq!(a.interpolate(&b, 0.5), EventCoordless { d: 6, t: 200 });
+        assert_eq!(a.interpolate(&b, 0.75), EventCoordless { d: 7, t: 250 });
+        let empty = EventCoordless { d: D_EMPTY, t: 500 };
+        assert_eq!(a.interpolate(&empty, 0.25), EventCoordless { d: 5, t: 200 });
+        assert_eq!(a.interpolate(&empty, 0.5).d, D_EMPTY);
+    }
+
     #[test]
     fn test_dshift_arrays() {
         assert_eq!(D_SHIFT[0], 1);
@@ -590,6 +1168,56 @@ mod tests {
         assert_eq!(plane_size.volume(), 8);
     }
 
+    #[test]
+    fn test_source_max_intensity() {
+        assert_eq!(
+            SourceCamera::FramedU8.source_type().max_intensity(),
+            f64::from(MAX_INTENSITY)
+        );
+        assert_eq!(
+            SourceCamera::FramedU16.source_type().max_intensity(),
+            65535.0
+        );
+        assert_eq!(SourceCamera::DavisU8.source_type(), SourceType::U8);
+        assert_eq!(SourceCamera::FramedF32.source_type().max_intensity(), 1.0);
+    }
+
+    #[test]
+    fn test_chroma_subsampling() {
+        let plane = PlaneSize::new(3, 3, 3).unwrap();
+        let half = ChromaSubsampling::Half;
+        assert!(half.is_sampled(Coord::new(1, 1, Some(0))));
+        assert!(half.is_sampled(Coord::new(2, 0, Some(1))));
+        assert!(!half.is_sampled(Coord::new(1, 0, Some(2))));
+        assert!(ChromaSubsampling::None.is_sampled(Coord::new(1, 0, Some(2))));
+
+        let coords: Vec<Coord> = half
+            .upsampled_coords(Coord::new(0, 0, Some(1)), plane)
+            .collect();
+        assert_eq!(
+            coords,
+            vec![
+                Coord::new(1, 0, Some(1)),
+                Coord::new(0, 1, Some(1)),
+                Coord::new(1, 1, Some(1))
+            ]
+        );
+
+        // Clipped at the edge of the plane
+        assert_eq!(
+            half.upsampled_coords(Coord::new(2, 0, Some(2)), plane)
+                .count(),
+            1
+        );
+
+        // The full-resolution channel is never replicated
+        assert_eq!(
+            half.upsampled_coords(Coord::new(0, 0, Some(0)), plane)
+                .count(),
+            0
+        );
+    }
+
     #[test]
     fn test_coord() {
         let coord = Coord::new(1, 2, Some(3));
@@ -0,0 +1,317 @@
+use crate::{
+    Coord, Event, NarrowPixelAddress, PixelAddress, D, EOF_PX_ADDRESS, NARROW_EOF_PX_ADDRESS,
+};
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use thiserror::Error;
+
+/// Error type for reading and writing events with a [`RawEventFormat`]
+#[allow(missing_docs)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawEventError {
+    #[cfg_attr(
+        feature = "std",
+        error("buffer too small for a raw event (expected {expected} bytes, found {found})")
+    )]
+    BufferTooSmall { expected: usize, found: usize },
+
+    #[cfg_attr(feature = "std", error("invalid channel tag in a raw event: {0}"))]
+    InvalidChannelTag(u8),
+}
+
+/// The byte order of the multi-byte values of a raw event
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Most significant byte first, as in the rest of an ADΔER stream
+    #[default]
+    Big,
+
+    /// Least significant byte first, as FPGA and DMA producers on little-endian hosts naturally
+    /// emit their words
+    Little,
+}
+
+impl ByteOrder {
+    fn u16_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Big => value.to_be_bytes(),
+            ByteOrder::Little => value.to_le_bytes(),
+        }
+    }
+
+    fn u32_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Big => value.to_be_bytes(),
+            ByteOrder::Little => value.to_le_bytes(),
+        }
+    }
+
+    fn read_u16(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+        }
+    }
+
+    fn read_u32(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// The fixed-size layout of an [`Event`] in a raw stream. All values are big-endian, except in
+/// the [little-endian](ByteOrder::Little) profile of the raw format.
+///
+/// A stream with a single channel leaves out the channel of each event, and a stream without
+/// wide coordinates stores its pixel addresses in 16 bits. Events are laid out as `x`, `y`, an
+/// optional channel (a tag byte of 1, then the channel), `D`, and `t`. This doesn't need `std`, so
+/// firmware can write events which the raw decoder reads directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawEventFormat {
+    /// 16-bit pixel addresses, without a channel
+    NarrowSingle,
+
+    /// 16-bit pixel addresses, with a channel
+    Narrow,
+
+    /// 32-bit pixel addresses, without a channel
+    WideSingle,
+
+    /// 32-bit pixel addresses, with a channel
+    Wide,
+}
+
+impl RawEventFormat {
+    /// The size of the largest format, in bytes
+    pub const MAX_SIZE: usize = 15;
+
+    /// The format of the events of a raw stream with the given number of channels
+    pub fn new(channels: u8, wide_coordinates: bool) -> Self {
+        match (channels == 1, wide_coordinates) {
+            (true, false) => RawEventFormat::NarrowSingle,
+            (false, false) => RawEventFormat::Narrow,
+            (true, true) => RawEventFormat::WideSingle,
+            (false, true) => RawEventFormat::Wide,
+        }
+    }
+
+    /// The size of an event in this format, in bytes
+    pub const fn size(self) -> usize {
+        self.address_size() * 2 + self.channel_size() + 5
+    }
+
+    const fn address_size(self) -> usize {
+        match self {
+            RawEventFormat::NarrowSingle | RawEventFormat::Narrow => 2,
+            RawEventFormat::WideSingle | RawEventFormat::Wide => 4,
+        }
+    }
+
+    const fn channel_size(self) -> usize {
+        match self {
+            RawEventFormat::NarrowSingle | RawEventFormat::WideSingle => 0,
+            RawEventFormat::Narrow | RawEventFormat::Wide => 2,
+        }
+    }
+
+    /// Write an event to the start of `bytes`, returning the number of bytes written. A 2D event
+    /// is written to channel 0 of a format with channels. Addresses which don't fit in 16 bits are
+    /// truncated by the narrow formats, except for [`EOF_PX_ADDRESS`].
+    pub fn write(self, event: &Event, bytes: &mut [u8]) -> Result<usize, RawEventError> {
+        self.write_ordered(ByteOrder::Big, event, bytes)
+    }
+
+    /// Like [`RawEventFormat::write`], with the values in the given byte order
+    pub fn write_ordered(
+        self,
+        order: ByteOrder,
+        event: &Event,
+        bytes: &mut [u8],
+    ) -> Result<usize, RawEventError> {
+        let size = self.size();
+        let found = bytes.len();
+        let bytes = bytes.get_mut(..size).ok_or(RawEventError::BufferTooSmall {
+            expected: size,
+            found,
+        })?;
+        let coord = event.coord;
+        let mut pos = 0;
+        for address in [coord.x, coord.y] {
+            if self.address_size() == 2 {
+                bytes[pos..pos + 2].copy_from_slice(&order.u16_bytes(narrow_address(address)));
+            } else {
+                bytes[pos..pos + 4].copy_from_slice(&order.u32_bytes(address));
+            }
+            pos += self.address_size();
+        }
+        if self.channel_size() > 0 {
+            bytes[pos] = 1;
+            bytes[pos + 1] = coord.c.unwrap_or(0);
+            pos += 2;
+        }
+        bytes[pos] = event.d;
+        bytes[pos + 1..pos + 5].copy_from_slice(&order.u32_bytes(event.t));
+        Ok(size)
+    }
+
+    /// Read an event from the start of `bytes`
+    pub fn read(self, bytes: &[u8]) -> Result<Event, RawEventError> {
+        self.read_ordered(ByteOrder::Big, bytes)
+    }
+
+    /// Like [`RawEventFormat::read`], with the values in the given byte order
+    pub fn read_ordered(self, order: ByteOrder, bytes: &[u8]) -> Result<Event, RawEventError> {
+        let size = self.size();
+        let bytes = bytes.get(..size).ok_or(RawEventError::BufferTooSmall {
+            expected: size,
+            found: bytes.len(),
+        })?;
+        let mut pos = 0;
+        let mut address = || {
+            let address = if self.address_size() == 2 {
+                wide_address(order.read_u16([bytes[pos], bytes[pos + 1]]))
+            } else {
+                order.read_u32([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+            };
+            pos += self.address_size();
+            address
+        };
+        let x = address();
+        let y = address();
+        let c = if self.channel_size() > 0 {
+            let c = match bytes[pos] {
+                0 => None,
+                1 => Some(bytes[pos + 1]),
+                tag => return Err(RawEventError::InvalidChannelTag(tag)),
+            };
+            pos += 2;
+            c
+        } else {
+            None
+        };
+        let d: D = bytes[pos];
+        let t = order.read_u32([
+            bytes[pos + 1],
+            bytes[pos + 2],
+            bytes[pos + 3],
+            bytes[pos + 4],
+        ]);
+        Ok(Event {
+            coord: Coord { x, y, c },
+            d,
+            t,
+        })
+    }
+
+    /// Append the events to `bytes` in this format, with the values in the given byte order
+    pub fn extend(self, order: ByteOrder, bytes: &mut Vec<u8>, events: &[Event]) {
+        let mut buffer = [0; Self::MAX_SIZE];
+        bytes.reserve(events.len() * self.size());
+        for event in events {
+            // The buffer fits every format
+            let size = self
+                .write_ordered(order, event, &mut buffer)
+                .unwrap_or_default();
+            bytes.extend_from_slice(&buffer[..size]);
+        }
+    }
+}
+
+fn narrow_address(address: PixelAddress) -> NarrowPixelAddress {
+    if address == EOF_PX_ADDRESS {
+        NARROW_EOF_PX_ADDRESS
+    } else {
+        address as NarrowPixelAddress
+    }
+}
+
+fn wide_address(address: NarrowPixelAddress) -> PixelAddress {
+    if address == NARROW_EOF_PX_ADDRESS {
+        EOF_PX_ADDRESS
+    } else {
+        address.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteOrder, RawEventError, RawEventFormat};
+    use crate::{Coord, Event, EOF_PX_ADDRESS};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_raw_event_format() {
+        let event = Event {
+            coord: Coord {
+                x: 300,
+                y: 2,
+                c: Some(1),
+            },
+            d: 7,
+            t: 0x0102_0304,
+        };
+        let mut bytes = [0; RawEventFormat::MAX_SIZE];
+
+        let format = RawEventFormat::new(3, false);
+        assert_eq!(format.write(&event, &mut bytes), Ok(11));
+        assert_eq!(bytes[..11], [1, 44, 0, 2, 1, 1, 7, 1, 2, 3, 4]);
+        assert_eq!(format.read(&bytes), Ok(event));
+
+        // A single-channel format leaves out the channel
+        let format = RawEventFormat::new(1, true);
+        assert_eq!(format.write(&event, &mut bytes), Ok(13));
+        assert_eq!(bytes[..13], [0, 0, 1, 44, 0, 0, 0, 2, 7, 1, 2, 3, 4]);
+        let event_2d = Event {
+            coord: Coord {
+                c: None,
+                ..event.coord
+            },
+            ..event
+        };
+        assert_eq!(format.read(&bytes), Ok(event_2d));
+
+        // The narrow EOF address is widened
+        let eof = Event {
+            coord: Coord {
+                x: EOF_PX_ADDRESS,
+                y: EOF_PX_ADDRESS,
+                c: None,
+            },
+            ..event
+        };
+        let format = RawEventFormat::new(1, false);
+        assert_eq!(format.write(&eof, &mut bytes), Ok(9));
+        assert_eq!(format.read(&bytes), Ok(eof));
+
+        let mut encoded = Vec::new();
+        RawEventFormat::Wide.extend(ByteOrder::Big, &mut encoded, &[event, event]);
+        assert_eq!(encoded.len(), 30);
+        assert_eq!(RawEventFormat::Wide.read(&encoded[15..]), Ok(event));
+
+        assert_eq!(
+            RawEventFormat::Wide.read(&bytes[..10]),
+            Err(RawEventError::BufferTooSmall {
+                expected: 15,
+                found: 10
+            })
+        );
+        encoded[8] = 2;
+        assert_eq!(
+            RawEventFormat::Wide.read(&encoded),
+            Err(RawEventError::InvalidChannelTag(2))
+        );
+
+        // Little-endian values are reversed
+        let format = RawEventFormat::new(3, false);
+        assert_eq!(
+            format.write_ordered(ByteOrder::Little, &event, &mut bytes),
+            Ok(11)
+        );
+        assert_eq!(bytes[..11], [44, 1, 2, 0, 1, 1, 7, 4, 3, 2, 1]);
+        assert_eq!(format.read_ordered(ByteOrder::Little, &bytes), Ok(event));
+    }
+}
@@ -1,12 +1,18 @@
 extern crate adder_codec_core;
 
-use adder_codec_core::codec::compressed::stream::CompressedOutput;
+use adder_codec_core::codec::compressed::stream::{CompressedInput, CompressedOutput};
+use adder_codec_core::codec::decoder::Decoder;
 use adder_codec_core::codec::encoder::Encoder;
 
-use adder_codec_core::codec::{CodecError, EncoderOptions};
-use adder_codec_core::open_file_decoder;
+use adder_codec_core::codec::rate_controller::Crf;
+use adder_codec_core::codec::{CodecError, CodecMetadata, EncoderOptions, LATEST_CODEC_VERSION};
+use adder_codec_core::{
+    open_file_decoder, Coord, Event, PlaneSize, Roi, SourceCamera, TimeMode, D_MAX,
+};
+use bitstream_io::{BigEndian, BitReader};
 use std::error::Error;
-use std::io::BufWriter;
+use std::io::{BufWriter, Cursor};
+use std::time::Duration;
 
 #[test]
 fn test_read_adder_raw() -> Result<(), Box<dyn Error>> {
@@ -78,3 +84,381 @@ fn test_build_many_frames() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Encode the batches of events from a sparse, DVS-like source, and decode them again
+fn dvs_encode(
+    plane: PlaneSize,
+    adu_interval: usize,
+    batches: &[Vec<Event>],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    dvs_encode_layered(plane, adu_interval, batches, 0)
+}
+
+/// Encode the batches of events like [`dvs_encode`], with the given number of enhancement layers
+fn dvs_encode_layered(
+    plane: PlaneSize,
+    adu_interval: usize,
+    batches: &[Vec<Event>],
+    enhancement_layers: u8,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let meta = CodecMetadata {
+        codec_version: LATEST_CODEC_VERSION,
+        header_size: 0,
+        time_mode: TimeMode::AbsoluteT,
+        plane,
+        tps: 1_000_000,
+        ref_interval: 255,
+        delta_t_max: 255 * adu_interval as u32,
+        event_size: 0,
+        source_camera: SourceCamera::Dvs,
+        adu_interval,
+        chroma_subsampling: Default::default(),
+        epoch: None,
+        enhancement_layers: 0,
+        wide_coordinates: false,
+        block_size: 16,
+        d_max: D_MAX,
+        channel_layout: Default::default(),
+        context_priors_id: 0,
+        quantization_table: Default::default(),
+        intensity_peak: 1.0,
+        color_space: Default::default(),
+        motion_compensation: false,
+    };
+    let compression = CompressedOutput::new(meta, BufWriter::new(vec![]));
+    let mut encoder: Encoder<BufWriter<Vec<u8>>> = Encoder::new_compressed(
+        compression,
+        EncoderOptions {
+            event_drop: Default::default(),
+            event_order: Default::default(),
+            event_validation: Default::default(),
+            time_index: true,
+            adu_checksum: false,
+            adu_sync_markers: false,
+            enhancement_layers,
+            crf: Crf::new(Some(0), plane),
+            quality_map: None,
+            channel_c_thresh_max: None,
+            target_kbps: None,
+            user_metadata: None,
+            raw_flush_events: None,
+            adu_interval: None,
+            delta_t_coding: Default::default(),
+            lz_dictionary: None,
+            motion_compensation: false,
+            block_size: None,
+            round_trip_check: Default::default(),
+            max_adu_events: None,
+            context_priors: None,
+            intra_refresh: Default::default(),
+            quantization_table: None,
+        },
+    );
+    encoder.ingest_events_events(batches)?;
+    Ok(encoder.close_writer()?.unwrap().into_inner()?)
+}
+
+fn dvs_round_trip(
+    plane: PlaneSize,
+    adu_interval: usize,
+    batches: &[Vec<Event>],
+) -> Result<Vec<Event>, Box<dyn Error>> {
+    let compressed = dvs_encode(plane, adu_interval, batches)?;
+    let mut bitreader = BitReader::endian(Cursor::new(compressed), BigEndian);
+    let mut decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut bitreader)?;
+    assert_eq!(decoder.meta().source_camera, SourceCamera::Dvs);
+    let mut events = Vec::new();
+    loop {
+        match decoder.digest_event(&mut bitreader) {
+            Ok(event) => events.push(event),
+            Err(CodecError::Eof) => break,
+            Err(e) => return Err(Box::new(e)),
+        }
+    }
+    Ok(events)
+}
+
+#[test]
+fn test_compressed_sparse_dvs() -> Result<(), Box<dyn Error>> {
+    let plane = PlaneSize::new(32, 24, 1)?;
+    let adu_interval = 10;
+    let adu_len = 255 * adu_interval as u32;
+
+    // Bursts of activity separated by long stretches with no events at all, as from a DVS
+    // camera watching a mostly static scene. Each burst is split into two chunks of rows, whose
+    // timestamps overlap, like the chunked output of the DAVIS transcoder.
+    let mut batches = Vec::new();
+    let mut expected = Vec::new();
+    for burst_t in [100, 3 * adu_len + 50, 40 * adu_len + 2000] {
+        for rows in [0..12, 12..24] {
+            let mut batch = Vec::new();
+            for y in rows {
+                for x in 0..32 {
+                    for i in 0..3 {
+                        batch.push(Event {
+                            coord: Coord { x, y, c: None },
+                            t: burst_t + x + 100 * i,
+                            d: 7,
+                        });
+                    }
+                }
+            }
+            expected.extend_from_slice(&batch);
+            batches.push(batch);
+        }
+    }
+
+    let mut decoded = dvs_round_trip(plane, adu_interval, &batches)?;
+    assert_eq!(decoded.len(), expected.len());
+
+    // Every event is decoded at its pixel, within the time range of the Adu it was written to
+    let adu_idx = |event: &Event| (event.t - 1) / adu_len;
+    let key = |event: &Event| (adu_idx(event), event.coord.y, event.coord.x, event.t);
+    decoded.sort_by_key(key);
+    expected.sort_by_key(key);
+    for (decoded, expected) in decoded.iter().zip(expected.iter()) {
+        assert_eq!(decoded.coord, expected.coord);
+        assert_eq!(adu_idx(decoded), adu_idx(expected));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compressed_dvs_late_first_event() -> Result<(), Box<dyn Error>> {
+    // A single event far from the start of the stream, with an Adu spanning a single dt_ref
+    let plane = PlaneSize::new(16, 16, 1)?;
+    let event = Event {
+        coord: Coord {
+            x: 3,
+            y: 5,
+            c: None,
+        },
+        t: 1_000_000,
+        d: 5,
+    };
+    let decoded = dvs_round_trip(plane, 1, &[vec![event]])?;
+    assert_eq!(decoded.len(), 1);
+    assert_eq!(decoded[0].coord, event.coord);
+    assert_eq!((decoded[0].t - 1) / 255, (event.t - 1) / 255);
+    Ok(())
+}
+
+#[test]
+fn test_compressed_events_rev() -> Result<(), Box<dyn Error>> {
+    let plane = PlaneSize::new(16, 16, 1)?;
+    let batches: Vec<Vec<Event>> = (0..20)
+        .map(|i| {
+            (0..16)
+                .map(|x| Event {
+                    coord: Coord {
+                        x,
+                        y: i % 16,
+                        c: None,
+                    },
+                    t: 1 + 300 * i + x,
+                    d: 7,
+                })
+                .collect()
+        })
+        .collect();
+    let compressed = dvs_encode(plane, 2, &batches)?;
+
+    let mut bitreader = BitReader::endian(Cursor::new(compressed), BigEndian);
+    let mut decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut bitreader)?;
+    let mut forward: Vec<Event> = decoder.events(&mut bitreader).collect::<Result<_, _>>()?;
+    let reverse: Vec<Event> = decoder
+        .events_rev(&mut bitreader)?
+        .collect::<Result<_, _>>()?;
+
+    // Every event is visited once, from the end of the stream back to its start
+    assert_eq!(reverse.len(), forward.len());
+    assert!(reverse.windows(2).all(|pair| pair[0].t >= pair[1].t));
+    let key = |event: &Event| (event.t, event.coord.y, event.coord.x);
+    let mut reverse_sorted = reverse.clone();
+    reverse_sorted.sort_by_key(key);
+    forward.sort_by_key(key);
+    assert_eq!(reverse_sorted, forward);
+    Ok(())
+}
+
+#[test]
+fn test_compressed_decode_between() -> Result<(), Box<dyn Error>> {
+    let plane = PlaneSize::new(16, 16, 1)?;
+    let batches: Vec<Vec<Event>> = (0..20)
+        .map(|i| {
+            (0..16)
+                .map(|x| Event {
+                    coord: Coord {
+                        x,
+                        y: i % 16,
+                        c: None,
+                    },
+                    t: 1 + 300 * i + x,
+                    d: 7,
+                })
+                .collect()
+        })
+        .collect();
+    let compressed = dvs_encode(plane, 2, &batches)?;
+    let len = compressed.len() as u64;
+
+    let mut bitreader = BitReader::endian(Cursor::new(compressed), BigEndian);
+    let mut decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut bitreader)?;
+    let all: Vec<Event> = decoder.events(&mut bitreader).collect::<Result<_, _>>()?;
+
+    let (t_start, t_end) = (1000, 1500);
+    let mut expected: Vec<Event> = all
+        .into_iter()
+        .filter(|event| (t_start..t_end).contains(&event.t))
+        .collect();
+    assert!(!expected.is_empty());
+
+    let mut clip: Vec<Event> = decoder
+        .decode_between(&mut bitreader, t_start, t_end)?
+        .collect::<Result<_, _>>()?;
+    let key = |event: &Event| (event.t, event.coord.y, event.coord.x);
+    clip.sort_by_key(key);
+    expected.sort_by_key(key);
+    assert_eq!(clip, expected);
+
+    // Decoding stopped early, rather than running on to the end of the stream
+    assert!(decoder.get_input_stream_position(&mut bitreader)? < len / 2);
+    Ok(())
+}
+
+#[test]
+fn test_compressed_time_window() -> Result<(), Box<dyn Error>> {
+    let plane = PlaneSize::new(16, 16, 1)?;
+    let batches: Vec<Vec<Event>> = (0..20)
+        .map(|i| {
+            (0..16)
+                .map(|x| Event {
+                    coord: Coord {
+                        x,
+                        y: i % 16,
+                        c: None,
+                    },
+                    t: 1 + 300 * i + x,
+                    d: 7,
+                })
+                .collect()
+        })
+        .collect();
+    let compressed = dvs_encode(plane, 2, &batches)?;
+
+    let mut bitreader = BitReader::endian(Cursor::new(compressed), BigEndian);
+    let mut decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut bitreader)?;
+    let all: Vec<Event> = decoder.events(&mut bitreader).collect::<Result<_, _>>()?;
+
+    // At 1,000,000 ticks per second, the window covers t=1000 up to t=1500
+    decoder.set_time_window(
+        &mut bitreader,
+        Duration::from_millis(1),
+        Some(Duration::from_micros(500)),
+    )?;
+    let mut window: Vec<Event> = decoder.events(&mut bitreader).collect::<Result<_, _>>()?;
+
+    // The timestamps are relative to the start of the window
+    let mut expected: Vec<Event> = all
+        .into_iter()
+        .filter(|event| (1000..1500).contains(&event.t))
+        .map(|event| Event {
+            t: event.t - 1000,
+            ..event
+        })
+        .collect();
+    assert!(!expected.is_empty());
+    let key = |event: &Event| (event.t, event.coord.y, event.coord.x);
+    window.sort_by_key(key);
+    expected.sort_by_key(key);
+    assert_eq!(window, expected);
+    Ok(())
+}
+
+#[test]
+fn test_compressed_roi_decode() -> Result<(), Box<dyn Error>> {
+    let plane = PlaneSize::new(64, 48, 1)?;
+    let batches: Vec<Vec<Event>> = (0..4)
+        .map(|i| {
+            let mut batch = Vec::new();
+            for y in 0..48 {
+                for x in 0..64 {
+                    batch.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 1 + 300 * i + u32::from(x % 7),
+                        d: 7,
+                    });
+                }
+            }
+            batch
+        })
+        .collect();
+    let compressed = dvs_encode(plane, 2, &batches)?;
+
+    let decode = |roi: Option<Roi>| -> Result<Vec<Event>, Box<dyn Error>> {
+        let mut bitreader = BitReader::endian(Cursor::new(compressed.clone()), BigEndian);
+        let mut decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut bitreader)?;
+        decoder.set_roi(roi);
+        Ok(decoder.events(&mut bitreader).collect::<Result<_, _>>()?)
+    };
+
+    // A patch straddling the boundaries of the cubes
+    let roi = Roi::new(20, 10, 8, 12);
+    let full = decode(None)?;
+    let patch = decode(Some(roi))?;
+    assert!(!patch.is_empty());
+    assert!(patch.iter().all(|event| roi.contains(event.coord)));
+    let expected: Vec<Event> = full
+        .into_iter()
+        .filter(|event| roi.contains(event.coord))
+        .collect();
+    assert_eq!(patch, expected);
+    Ok(())
+}
+
+#[test]
+fn test_compressed_layered_preview() -> Result<(), Box<dyn Error>> {
+    let plane = PlaneSize::new(64, 48, 1)?;
+    let batches: Vec<Vec<Event>> = (0..4)
+        .map(|i| {
+            let mut batch = Vec::new();
+            for y in 0..48 {
+                for x in 0..64 {
+                    batch.push(Event {
+                        coord: Coord { x, y, c: None },
+                        t: 1 + 300 * i + u32::from(x % 7) + u32::from(y % 5) * 3,
+                        d: 7,
+                    });
+                }
+            }
+            batch
+        })
+        .collect();
+    let layered = dvs_encode_layered(plane, 2, &batches, 2)?;
+
+    let decode = |max_layers: Option<u8>| -> Result<Vec<Event>, Box<dyn Error>> {
+        let mut bitreader = BitReader::endian(Cursor::new(layered.clone()), BigEndian);
+        let mut decoder = Decoder::new_compressed(CompressedInput::new(0, 0, 0), &mut bitreader)?;
+        assert_eq!(decoder.enhancement_layers(), 2);
+        decoder.set_max_layers(max_layers);
+        Ok(decoder.events(&mut bitreader).collect::<Result<_, _>>()?)
+    };
+
+    // With every layer, the events are the same as those of a stream without layers
+    let plain = dvs_round_trip(plane, 2, &batches)?;
+    let full = decode(None)?;
+    assert_eq!(full, plain);
+
+    // The base layer alone gives the same events with coarser timestamps
+    let preview = decode(Some(0))?;
+    assert_eq!(preview.len(), full.len());
+    assert!(preview
+        .iter()
+        .zip(full.iter())
+        .all(|(event, exact)| event.coord == exact.coord && event.d == exact.d));
+    assert!(preview
+        .iter()
+        .zip(full.iter())
+        .any(|(event, exact)| event.t != exact.t));
+    Ok(())
+}
@@ -29,6 +29,8 @@ fn simul_proc(video_path: &str, scale: f64, thread_count: u8, _chunk_rows: usize
         delta_t_max: 120000,
         frame_count_max: 300,
         frame_idx_start: 0,
+        start_time: 0.0,
+        duration: 0.0,
         show_display: false,
         input_filename: video_path.to_string(),
         output_events_filename: "".parse().unwrap(),
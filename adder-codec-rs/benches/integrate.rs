@@ -0,0 +1,99 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+use adder_codec_rs::transcoder::source::raw_video::{RawPixelFormat, RawVideo};
+#[cfg(feature = "simd")]
+use adder_codec_rs::transcoder::source::video::integrate_lanes_simd;
+use adder_codec_rs::transcoder::source::video::{integrate_lanes_scalar, Source, LANES};
+
+use std::io::{Cursor, Sink};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FRAMES: usize = 30;
+
+/// Mostly-static grayscale frames: a fixed gradient with a little flicker, and a bright square
+/// sweeping across, so that most pixels integrate without firing in a given frame
+fn frames() -> Vec<u8> {
+    let (width, height) = (WIDTH as usize, HEIGHT as usize);
+    let mut bytes = Vec::with_capacity(width * height * FRAMES);
+    for frame in 0..FRAMES {
+        for y in 0..height {
+            for x in 0..width {
+                let moving = (frame * 16..frame * 16 + 32).contains(&x) && (64..96).contains(&y);
+                bytes.push(if moving {
+                    250
+                } else {
+                    ((x + y) % 200) as u8 + (frame % 2) as u8
+                });
+            }
+        }
+    }
+    bytes
+}
+
+fn transcode(bytes: Vec<u8>) {
+    let mut source: RawVideo<Sink> = RawVideo::new(
+        Cursor::new(bytes),
+        RawPixelFormat::Gray,
+        WIDTH,
+        HEIGHT,
+        30.0,
+        false,
+    )
+    .unwrap();
+    while source.consume().is_ok() {}
+}
+
+fn integrate(c: &mut Criterion) {
+    let bytes = frames();
+    let mut group = c.benchmark_group("integrate");
+    group.sample_size(10);
+    group.bench_function("gray_640x480_30_frames", |b| {
+        b.iter_batched(|| bytes.clone(), transcode, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+/// The lane arithmetic of a 640x480 frame, done by both the scalar and the SIMD versions, so they
+/// can be compared in one run
+fn lanes(c: &mut Criterion) {
+    let batches = (WIDTH * HEIGHT) as usize / LANES;
+    let lane_values = |offset: f32| std::array::from_fn(|lane| lane as f32 * 10.0 + offset);
+    let (integration, delta_t) = (lane_values(5.0), lane_values(100.0));
+    let (intensity, threshold) = (lane_values(1.0), lane_values(60.0));
+
+    let mut group = c.benchmark_group("lanes");
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            for _ in 0..batches {
+                black_box(integrate_lanes_scalar(
+                    black_box(integration),
+                    black_box(delta_t),
+                    black_box(intensity),
+                    black_box(threshold),
+                    255.0,
+                    5100.0,
+                ));
+            }
+        })
+    });
+    #[cfg(feature = "simd")]
+    group.bench_function("simd", |b| {
+        b.iter(|| {
+            for _ in 0..batches {
+                black_box(integrate_lanes_simd(
+                    black_box(integration),
+                    black_box(delta_t),
+                    black_box(intensity),
+                    black_box(threshold),
+                    255.0,
+                    5100.0,
+                ));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, integrate, lanes);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+fn main() {
+    // The Metavision SDK is C++, so the `metavision` feature builds a small C shim around it
+    #[cfg(feature = "metavision")]
+    {
+        println!("cargo:rerun-if-changed=metavision/shim.cpp");
+        println!("cargo:rerun-if-env-changed=METAVISION_SDK_DIR");
+
+        let mut build = cc::Build::new();
+        build.cpp(true).std("c++17").file("metavision/shim.cpp");
+        if let Ok(sdk_dir) = std::env::var("METAVISION_SDK_DIR") {
+            build.include(format!("{sdk_dir}/include"));
+            println!("cargo:rustc-link-search=native={sdk_dir}/lib");
+        }
+        build.compile("metavision_shim");
+
+        for lib in [
+            "metavision_sdk_driver",
+            "metavision_sdk_core",
+            "metavision_sdk_base",
+            "metavision_hal",
+        ] {
+            println!("cargo:rustc-link-lib=dylib={lib}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// A minimal C interface to a live camera through the Metavision SDK, for the `metavision`
+// feature of adder-codec-rs. The SDK delivers CD events on its own thread; they're queued here
+// until `mv_poll` hands them to Rust.
+
+#include <chrono>
+#include <condition_variable>
+#include <cstddef>
+#include <cstdint>
+#include <deque>
+#include <exception>
+#include <mutex>
+#include <string>
+
+#include <metavision/hal/facilities/i_ll_biases.h>
+#include <metavision/sdk/driver/camera.h>
+
+extern "C" {
+
+struct mv_event {
+    uint16_t x;
+    uint16_t y;
+    int16_t p;
+    int64_t t;
+};
+
+struct mv_camera {
+    Metavision::Camera camera;
+    std::mutex mutex;
+    std::condition_variable ready;
+    std::deque<mv_event> events;
+};
+
+static thread_local std::string last_error;
+
+const char *mv_last_error(void) {
+    return last_error.c_str();
+}
+
+mv_camera *mv_open(const char *serial) {
+    try {
+        auto *handle = new mv_camera{
+            (serial && *serial) ? Metavision::Camera::from_serial(serial)
+                                : Metavision::Camera::from_first_available(),
+        };
+        handle->camera.cd().add_callback(
+            [handle](const Metavision::EventCD *begin, const Metavision::EventCD *end) {
+                {
+                    std::lock_guard<std::mutex> lock(handle->mutex);
+                    for (auto *ev = begin; ev != end; ++ev) {
+                        handle->events.push_back(mv_event{ev->x, ev->y, ev->p, ev->t});
+                    }
+                }
+                handle->ready.notify_one();
+            });
+        return handle;
+    } catch (const std::exception &e) {
+        last_error = e.what();
+        return nullptr;
+    }
+}
+
+void mv_geometry(mv_camera *handle, int *width, int *height) {
+    *width = handle->camera.geometry().width();
+    *height = handle->camera.geometry().height();
+}
+
+int mv_set_bias(mv_camera *handle, const char *name, int value) {
+    try {
+        auto *biases = handle->camera.get_device().get_facility<Metavision::I_LL_Biases>();
+        if (!biases) {
+            last_error = "the camera has no configurable biases";
+            return -1;
+        }
+        if (!biases->set(name, value)) {
+            last_error = std::string("could not set bias ") + name;
+            return -1;
+        }
+        return 0;
+    } catch (const std::exception &e) {
+        last_error = e.what();
+        return -1;
+    }
+}
+
+int mv_start(mv_camera *handle) {
+    try {
+        return handle->camera.start() ? 0 : -1;
+    } catch (const std::exception &e) {
+        last_error = e.what();
+        return -1;
+    }
+}
+
+// Move up to `capacity` queued events into `buffer`, waiting up to `timeout_ms` for some to
+// arrive. Returns the number of events, or -1 once the camera has stopped and the queue is empty.
+long mv_poll(mv_camera *handle, mv_event *buffer, size_t capacity, int timeout_ms) {
+    std::unique_lock<std::mutex> lock(handle->mutex);
+    handle->ready.wait_for(lock, std::chrono::milliseconds(timeout_ms),
+                           [handle] { return !handle->events.empty(); });
+    if (handle->events.empty()) {
+        return handle->camera.is_running() ? 0 : -1;
+    }
+    size_t count = 0;
+    while (count < capacity && !handle->events.empty()) {
+        buffer[count++] = handle->events.front();
+        handle->events.pop_front();
+    }
+    return static_cast<long>(count);
+}
+
+void mv_close(mv_camera *handle) {
+    try {
+        handle->camera.stop();
+    } catch (const std::exception &) {
+    }
+    delete handle;
+}
+
+}
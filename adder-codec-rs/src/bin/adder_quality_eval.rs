@@ -0,0 +1,65 @@
+use adder_codec_rs::utils::cv::QualityMetrics;
+use adder_codec_rs::utils::evaluation::ReferenceEvaluator;
+use clap::Parser;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Evaluate the quality of an ADΔER file against the video it was transcoded from
+#[derive(Parser, Debug, Default)]
+#[clap(author, version, about, long_about = None)]
+pub struct MyArgs {
+    /// Path to input ADΔER file
+    #[clap(short, long)]
+    pub input: String,
+
+    /// Path to the reference video
+    #[clap(short, long)]
+    pub reference: String,
+
+    /// The frame index of the reference video at which the transcode started
+    #[clap(long, default_value_t = 0)]
+    pub frame_idx_start: u32,
+
+    /// Also evaluate SSIM (slow)
+    #[clap(long, action)]
+    pub ssim: bool,
+
+    /// Path to write the per-frame and aggregate metrics as JSON
+    #[clap(short, long, default_value = "")]
+    pub output: String,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: MyArgs = MyArgs::parse();
+
+    let evaluator =
+        ReferenceEvaluator::new(Path::new(&args.input), PathBuf::from(&args.reference))?
+            .frame_start(args.frame_idx_start)?
+            .metrics(QualityMetrics {
+                psnr: Some(0.0),
+                mse: Some(0.0),
+                ssim: if args.ssim { Some(0.0) } else { None },
+            });
+
+    let evaluation = evaluator.evaluate()?;
+    for frame in &evaluation.frames {
+        println!(
+            "Frame {} ({:.3}s): {:?}",
+            frame.frame_idx, frame.timestamp, frame.metrics
+        );
+    }
+    println!(
+        "Mean over {} frames: {:?}",
+        evaluation.frames.len(),
+        evaluation.aggregate
+    );
+
+    if !args.output.is_empty() {
+        let writer = BufWriter::new(File::create(&args.output)?);
+        serde_json::to_writer_pretty(writer, &evaluation)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::codec::raw::stream::RawOutput;
+use adder_codec_core::codec::EncoderOptions;
+use adder_codec_core::open_file_decoder;
+use adder_codec_rs::utils::stream_reversal::reverse_stream;
+use clap::Parser;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Reverse an ADΔER stream in time, writing the result as a raw stream
+#[derive(Parser, Debug, Default)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    /// Path to input ADΔER file
+    #[clap(short, long)]
+    pub input: String,
+
+    /// Path to output ADΔER file
+    #[clap(short, long)]
+    pub output: String,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Args = Args::parse();
+
+    let (input_stream, mut bitreader) = open_file_decoder(&args.input)?;
+    let meta = *input_stream.meta();
+
+    let bufwriter = BufWriter::new(File::create(args.output)?);
+    let encoder: Encoder<BufWriter<File>> = Encoder::new_raw(
+        RawOutput::new(meta, bufwriter),
+        EncoderOptions::default(meta.plane),
+    );
+
+    let encoder = reverse_stream(input_stream, &mut bitreader, encoder)?;
+    if let Some(mut writer) = encoder.close_writer()? {
+        writer.flush()?;
+    }
+    println!("Done!");
+    Ok(())
+}
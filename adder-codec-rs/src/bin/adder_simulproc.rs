@@ -1,6 +1,12 @@
 extern crate core;
 
-use adder_codec_rs::transcoder::source::video::{Source, VideoBuilder};
+use adder_codec_rs::transcoder::source::denoise::TemporalFilter;
+use adder_codec_rs::transcoder::source::noise::NoiseModel;
+use adder_codec_rs::transcoder::source::quality::QualityTarget;
+use adder_codec_rs::transcoder::source::undistort::LensModel;
+use adder_codec_rs::transcoder::source::video::{
+    Crop, IntensityLut, Orientation, Rotation, Source, VideoBuilder,
+};
 use adder_codec_rs::utils::simulproc::{SimulProcArgs, SimulProcessor};
 
 use clap::Parser;
@@ -10,12 +16,17 @@ use std::error::Error;
 use std::fs::File;
 
 use adder_codec_core::codec::{EncoderOptions, EncoderType};
-use adder_codec_core::SourceCamera::FramedU8;
-use adder_codec_core::{PixelMultiMode, TimeMode};
-use adder_codec_rs::transcoder::source::framed::Framed;
+use adder_codec_core::{ChromaSubsampling, PixelMultiMode, SourceCamera, TimeMode};
+use adder_codec_rs::transcoder::source::framed::{
+    is_stream_url, AdaptiveRefInterval, Framed, LiveStreamOptions,
+};
+use adder_codec_rs::transcoder::source::raw_video::{RawPixelFormat, RawVideo};
+use adder_codec_rs::transcoder::source::stabilization::Stabilization;
+use adder_codec_rs::transcoder::source::AdderSource;
 use std::io::{BufWriter, Cursor};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
 #[allow(dead_code)]
 async fn download_file() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -68,32 +79,111 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // args.output_raw_video_filename = "./tests/samples/videos/drop_out".to_string();
     //////////////////////////////////////////////////////
 
-    let mut source: Framed<BufWriter<File>> =
-        Framed::new(args.input_filename.into(), args.color_input, args.scale)?
+    let chroma_subsampling = if args.chroma_subsampling {
+        ChromaSubsampling::Half
+    } else {
+        ChromaSubsampling::None
+    };
+    let lut = match args.lut_filename.as_str() {
+        "" => None,
+        path => Some(IntensityLut::from_file(Path::new(path))?),
+    };
+    let lens = match args.lens_filename.as_str() {
+        "" => None,
+        path => Some(LensModel::from_file(Path::new(path))?),
+    };
+    let crop = match args.crop.as_str() {
+        "" => None,
+        crop => Some(crop.parse::<Crop>()?),
+    };
+    let orientation = Orientation {
+        flip_horizontal: args.flip_horizontal,
+        flip_vertical: args.flip_vertical,
+        rotation: Rotation::from_degrees(args.rotation)?,
+    };
+    let noise_model = (args.read_noise > 0.0 || args.shot_noise > 0.0)
+        .then(|| NoiseModel::new(args.read_noise, args.shot_noise, args.noise_sigmas))
+        .transpose()?;
+    let temporal_filter = (args.denoise_strength > 0.0).then_some(TemporalFilter {
+        strength: args.denoise_strength,
+        edge: args.denoise_edge,
+    });
+    let quality_target = (args.target_psnr > 0.0)
+        .then(|| QualityTarget::new(args.target_psnr, args.psnr_tolerance))
+        .transpose()?;
+
+    let (source, source_fps, ref_time): (AdderSource<_>, _, _) = if args.input_filename == "-" {
+        let (width, height) = args
+            .raw_size
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+            .ok_or("raw_size must be given as WIDTHxHEIGHT when reading from stdin")?;
+        let pix_fmt: RawPixelFormat = args.raw_pix_fmt.parse()?;
+        let source: RawVideo<BufWriter<File>> =
+            RawVideo::from_stdin(pix_fmt, width, height, args.raw_fps, args.color_input)?
+                .bayer_planes(args.raw_bayer_planes)?
+                .crop(crop)?
+                .orientation(Some(orientation))?
+                .crf(args.crf)
+                .auto_time_parameters(args.ref_time, args.delta_t_max, None)?
+                .chroma_subsampling(chroma_subsampling)?
+                .intensity_lut(lut)
+                .noise_model(noise_model)
+                .temporal_filter(temporal_filter)?
+                .quality_target(quality_target)
+                .lens_undistortion(lens)?
+                .gpu_integration(args.gpu)?;
+        let source_camera = source.source_camera();
+        let source = configure(source, &args, source_camera, time_mode, integration_mode)?;
+        let (source_fps, ref_time) = (source.source_fps, source.get_ref_time());
+        (source.into(), source_fps, ref_time)
+    } else {
+        let source: Framed<BufWriter<File>> = if is_stream_url(&args.input_filename) {
+            Framed::new_stream(
+                &args.input_filename,
+                args.color_input,
+                args.scale,
+                LiveStreamOptions::default(),
+            )?
+        } else {
+            let input_path = PathBuf::from(&args.input_filename);
+            Framed::new(input_path, args.color_input, args.scale)?
+        };
+        let source = source
             // .chunk_rows(64)
             .frame_start(args.frame_idx_start)?
+            .crop(crop)?
+            .orientation(Some(orientation))?
+            .time_lapse(args.time_lapse)?
+            .adaptive_ref_interval((args.adaptive_ref_interval_max > 1).then(|| {
+                AdaptiveRefInterval {
+                    max_multiple: args.adaptive_ref_interval_max,
+                    ..Default::default()
+                }
+            }))?
+            .scene_cut_threshold(
+                (args.scene_cut_threshold > 0.0).then_some(args.scene_cut_threshold),
+            )?
+            .stabilization((args.stabilization_max_shift > 0).then(|| Stabilization {
+                max_shift: args.stabilization_max_shift,
+                ..Default::default()
+            }))?
             .crf(args.crf)
-            .auto_time_parameters(args.ref_time, args.delta_t_max, None)?;
-
-    if !args.output_events_filename.is_empty() {
-        let path = Path::new(&args.output_events_filename);
-        let file = File::create(path)?;
-        let plane = source.get_video_ref().state.plane;
-        source = *source.write_out(
-            FramedU8,
-            time_mode,
-            integration_mode,
-            Some((args.delta_t_max / args.ref_time) as usize),
-            EncoderType::Compressed,
-            EncoderOptions::default(plane),
-            BufWriter::new(file),
-        )?;
-    }
-
-    let source_fps = source.source_fps;
+            .auto_time_parameters(args.ref_time, args.delta_t_max, None)?
+            .chroma_subsampling(chroma_subsampling)?
+            .intensity_lut(lut)
+            .noise_model(noise_model)
+            .temporal_filter(temporal_filter)?
+            .quality_target(quality_target)
+            .lens_undistortion(lens)?
+            .gpu_integration(args.gpu)?;
+        let source_camera = source.source_camera();
+        let source = configure(source, &args, source_camera, time_mode, integration_mode)?;
+        let (source_fps, ref_time) = (source.source_fps, source.get_ref_time());
+        (source.into(), source_fps, ref_time)
+    };
     let plane = source.get_video_ref().state.plane;
 
-    let ref_time = source.get_ref_time();
     let num_threads = match args.thread_count {
         0 => current_num_threads(),
         num => num as usize,
@@ -119,8 +209,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("{:.1} average frames transcoded per second", fps);
 
     // Use ffmpeg to encode the raw frame data as an mp4
-    let color_str = match args.color_input {
-        true => "bgr24",
+    let color_str = match plane.c() {
+        3 => "bgr24",
         _ => "gray",
     };
 
@@ -147,6 +237,48 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Apply the time window and output options common to all the framed sources
+fn configure<S: Source<BufWriter<File>> + VideoBuilder<BufWriter<File>>>(
+    mut source: S,
+    args: &SimulProcArgs,
+    source_camera: SourceCamera,
+    time_mode: TimeMode,
+    integration_mode: PixelMultiMode,
+) -> Result<S, Box<dyn Error>> {
+    if args.start_time > 0.0 {
+        source = source.start_time(Duration::from_secs_f64(args.start_time))?;
+    }
+    if args.duration > 0.0 {
+        source = source.duration(Some(Duration::from_secs_f64(args.duration)));
+    }
+
+    if !args.output_events_filename.is_empty() {
+        let path = Path::new(&args.output_events_filename);
+        let file = File::create(path)?;
+        let plane = source.get_video_ref().state.plane;
+        source = *source.write_out(
+            source_camera,
+            time_mode,
+            integration_mode,
+            Some((args.delta_t_max / args.ref_time) as usize),
+            EncoderType::Compressed,
+            EncoderOptions::default(plane),
+            BufWriter::new(file),
+        )?;
+
+        // Write the renditions alongside the main output, e.g., `out_crf6.adder`
+        for crf in args.rendition_crfs.split(',').filter(|crf| !crf.is_empty()) {
+            let crf: u8 = crf.trim().parse()?;
+            let rendition_path = format!("{}_crf{crf}.adder", path.with_extension("").display());
+            let file = File::create(rendition_path)?;
+            source
+                .get_video_mut()
+                .add_rendition(crf, BufWriter::new(file))?;
+        }
+    }
+    Ok(source)
+}
+
 #[cfg(test)]
 mod tests {
     use adder_codec_core::codec::rate_controller::Crf;
@@ -174,20 +306,46 @@ mod tests {
         let args: SimulProcArgs = SimulProcArgs {
             args_filename: String::new(),
             color_input: false,
+            chroma_subsampling: false,
             ref_time: 255,
             delta_t_max: 6120,
             frame_count_max: 0,
             frame_idx_start: 1,
+            start_time: 0.0,
+            duration: 0.0,
+            time_lapse: 1,
+            scene_cut_threshold: 0.0,
+            adaptive_ref_interval_max: 0,
+            stabilization_max_shift: 0,
+            lut_filename: String::new(),
+            lens_filename: String::new(),
             show_display: false,
             input_filename: manifest_path_str.clone() + "/tests/samples/lake_scaled_hd_crop.mp4",
+            raw_pix_fmt: "rgb24".to_string(),
+            raw_size: String::new(),
+            raw_fps: 30.0,
+            raw_bayer_planes: false,
             output_events_filename: manifest_path_str.clone()
                 + "/tests/samples/TEST_lake_scaled_hd_crop.adder",
             output_raw_video_filename: manifest_path_str
                 + "/tests/samples/TEST_lake_scaled_hd_crop",
             scale: 1.0,
+            crop: String::new(),
+            rotation: 0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            gpu: false,
+            read_noise: 0.0,
+            shot_noise: 0.0,
+            noise_sigmas: 2.0,
+            denoise_strength: 0.0,
+            denoise_edge: 8.0,
             thread_count: 1, // Multithreading causes some issues in testing
             time_mode: "delta_t".to_string(),
             crf: 0,
+            target_psnr: 0.0,
+            psnr_tolerance: 0.5,
+            rendition_crfs: String::new(),
             integration_mode: "".to_string(),
         };
         let mut source = Framed::new(args.input_filename.into(), args.color_input, args.scale)?
@@ -215,7 +373,27 @@ mod tests {
                 EncoderOptions {
                     event_drop: Default::default(),
                     event_order: Default::default(),
+                    event_validation: Default::default(),
+                    time_index: false,
+                    adu_checksum: false,
+                    adu_sync_markers: false,
+                    enhancement_layers: 0,
                     crf: Crf::new(Some(0), plane),
+                    quality_map: None,
+                    channel_c_thresh_max: None,
+                    target_kbps: None,
+                    user_metadata: None,
+                    raw_flush_events: None,
+                    adu_interval: None,
+                    delta_t_coding: Default::default(),
+                    lz_dictionary: None,
+                    motion_compensation: false,
+                    block_size: None,
+                    round_trip_check: Default::default(),
+                    max_adu_events: None,
+                    context_priors: None,
+                    intra_refresh: Default::default(),
+                    quantization_table: None,
                 },
                 writer,
             )?;
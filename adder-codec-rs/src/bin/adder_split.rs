@@ -0,0 +1,46 @@
+use adder_codec_rs::utils::stream_split::{segment_file, split_file};
+use clap::Parser;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Split an ADΔER stream into self-contained raw shards, which can be processed independently, or
+/// into self-contained segments of a fixed duration
+#[derive(Parser, Debug, Default)]
+#[clap(author, version, about, long_about = None)]
+pub struct Args {
+    /// Path to input ADΔER file
+    #[clap(short, long)]
+    pub input: String,
+
+    /// Prefix of the output paths. Shard (or segment) `i` is written to `<prefix>_<i>.adder`
+    #[clap(short, long)]
+    pub output_prefix: String,
+
+    /// Number of shards to split the stream into
+    #[clap(short, long, default_value_t = 2)]
+    pub shards: usize,
+
+    /// Split the stream into segments of this many seconds each, with rebased timestamps, rather
+    /// than into a number of shards
+    #[clap(long)]
+    pub segment_seconds: Option<f64>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Args = Args::parse();
+
+    let make_writer = |index: usize| -> Result<BufWriter<File>, Box<dyn Error>> {
+        let path = format!("{}_{index}.adder", args.output_prefix);
+        Ok(BufWriter::new(File::create(path)?))
+    };
+    let writers = match args.segment_seconds {
+        Some(segment_seconds) => segment_file(&args.input, segment_seconds, make_writer)?,
+        None => split_file(&args.input, args.shards, make_writer)?,
+    };
+    for mut writer in writers {
+        writer.flush()?;
+    }
+    println!("Done!");
+    Ok(())
+}
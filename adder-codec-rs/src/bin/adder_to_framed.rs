@@ -6,6 +6,7 @@ use adder_codec_core::codec::{EncoderOptions, EncoderType};
 use adder_codec_core::SourceCamera::{DavisU8, Dvs, FramedU8};
 use adder_codec_core::SourceType::U8;
 use adder_codec_core::{open_file_decoder, PixelMultiMode, TimeMode};
+use adder_codec_rs::framer::colormap::Colormap;
 use adder_codec_rs::framer::driver::FramerMode::INSTANTANEOUS;
 use adder_codec_rs::framer::driver::{FrameSequence, Framer, FramerBuilder};
 use adder_codec_rs::transcoder::source::prophesee::Prophesee;
@@ -55,6 +56,11 @@ pub struct MyArgs {
     /// This is useful for slow motion or fast forward
     #[clap(short, long, default_value_t = 1.0)]
     pub playback_speed: f64,
+
+    /// Render a single-channel stream in false color through this colormap ("viridis" or
+    /// "turbo"), rather than in grayscale
+    #[clap(long)]
+    pub colormap: Option<Colormap>,
 }
 
 #[tokio::main]
@@ -76,6 +82,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .mode(INSTANTANEOUS)
         .source(U8, meta.source_camera)
+        .chroma_subsampling(meta.chroma_subsampling)
+        .color_space(meta.color_space)
+        .colormap(args.colormap)
         .finish::<u8>();
 
     let mut output_stream = BufWriter::new(File::create(&args.output)?);
@@ -156,7 +165,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dbg!(frame_count);
 
     // Use ffmpeg to encode the raw frame data as an mp4
-    let color_str = match meta.plane.c() != 1 {
+    let color_str = match meta.plane.c() != 1 || args.colormap.is_some() {
         true => "rgb24",
         _ => "gray",
     };
@@ -10,6 +10,7 @@ use clap::Parser;
 use rayon::current_num_threads;
 use std::fs::File;
 use std::io::BufWriter;
+use std::time::Duration;
 
 #[derive(Parser, Debug, Default, serde::Deserialize)]
 #[clap(author, version, about, long_about = None)]
@@ -22,7 +23,7 @@ pub struct MyArgs {
     #[clap(short, long, default_value_t = 2)]
     pub delta_t_max: u32,
 
-    /// Path to input file
+    /// Path to input file (a Prophesee .dat file, or an AEDAT 2.0, 3.x, or 4.0 file)
     #[clap(short, long, default_value = "./in.dat")]
     pub input: String,
 
@@ -40,6 +41,14 @@ pub struct MyArgs {
 
     #[clap(short, long, action)]
     pub features: bool,
+
+    /// Seconds into the input to start transcoding from
+    #[clap(long, default_value_t = 0.0)]
+    pub start_time: f64,
+
+    /// Seconds of the input to transcode from the start (0 = no limit)
+    #[clap(long, default_value_t = 0.0)]
+    pub duration: f64,
 }
 
 #[tokio::main]
@@ -47,7 +56,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut args: MyArgs = MyArgs::parse();
 
     let mut prophesee_source: Prophesee<BufWriter<File>> =
-        Prophesee::new(args.ref_time, args.input)?.crf(args.crf);
+        Prophesee::new(args.ref_time, args.input)?
+            .crf(args.crf)
+            .start_time(Duration::from_secs_f64(args.start_time.max(0.0)))?
+            .duration((args.duration > 0.0).then(|| Duration::from_secs_f64(args.duration)));
     let adu_interval =
         (prophesee_source.get_video_ref().state.tps as f32 / args.ref_time as f32) as usize;
     let plane = prophesee_source.get_video_ref().state.plane;
@@ -62,7 +74,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         EncoderOptions {
             event_drop: Default::default(),
             event_order: Default::default(),
+            event_validation: Default::default(),
+            time_index: false,
+            adu_checksum: false,
+            adu_sync_markers: false,
+            enhancement_layers: 0,
             crf: Crf::new(Some(args.crf), plane),
+            quality_map: None,
+            channel_c_thresh_max: None,
+            target_kbps: None,
+            user_metadata: None,
+            raw_flush_events: None,
+            adu_interval: None,
+            delta_t_coding: Default::default(),
+            lz_dictionary: None,
+            motion_compensation: false,
+            block_size: None,
+            round_trip_check: Default::default(),
+            max_adu_events: None,
+            context_priors: None,
+            intra_refresh: Default::default(),
+            quantization_table: None,
         },
         writer,
     )?;
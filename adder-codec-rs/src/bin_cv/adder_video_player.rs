@@ -87,16 +87,16 @@ fn main() -> Result<(), Box<dyn Error>> {
     match meta.plane.c() {
         1 => {
             create_continuous(
-                i32::from(meta.plane.h()),
-                i32::from(meta.plane.w()),
+                meta.plane.h() as i32,
+                meta.plane.w() as i32,
                 CV_64F,
                 &mut display_mat,
             )?;
         }
         3 => {
             create_continuous(
-                i32::from(meta.plane.h()),
-                i32::from(meta.plane.w()),
+                meta.plane.h() as i32,
+                meta.plane.w() as i32,
                 CV_64FC3,
                 &mut display_mat,
             )?;
@@ -133,11 +133,11 @@ fn main() -> Result<(), Box<dyn Error>> {
         match stream.digest_event(&mut bitreader) {
             Ok(mut event) if event.d <= D_ZERO_INTEGRATION => {
                 event_count += 1;
-                let y = i32::from(event.coord.y);
-                let x = i32::from(event.coord.x);
+                let y = event.coord.y as i32;
+                let x = event.coord.x as i32;
                 let c = i32::from(event.coord.c.unwrap_or(0));
 
-                if time_mode == TimeMode::AbsoluteT {
+                if time_mode != TimeMode::DeltaT {
                     if event.t > current_t {
                         current_t = event.t;
                     }
@@ -0,0 +1,161 @@
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A colormap for rendering the intensities of a single-channel reconstruction in false color
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Colormap {
+    /// Perceptually uniform, from dark purple through green to yellow
+    Viridis,
+
+    /// High-contrast rainbow, from near-black through blue, green, and yellow to dark red
+    Turbo,
+}
+
+/// A table of the RGB color of each of 256 evenly spaced intensity levels
+pub type ColormapLut = [[u8; 3]; 256];
+
+impl Colormap {
+    /// Get the RGB color of `value`, where 0 is the darkest and 1 is the brightest intensity
+    pub fn rgb(&self, value: f64) -> [u8; 3] {
+        let x = value.clamp(0.0, 1.0);
+        let rgb = match self {
+            // Polynomial fit of matplotlib's viridis
+            Colormap::Viridis => [
+                polynomial(
+                    x,
+                    &[
+                        0.277_727_327,
+                        0.105_093_043,
+                        -0.330_861_829,
+                        -4.634_230_499,
+                        6.228_269_936,
+                        4.776_384_998,
+                        -5.435_455_856,
+                    ],
+                ),
+                polynomial(
+                    x,
+                    &[
+                        0.005_407_345,
+                        1.404_613_530,
+                        0.214_847_559,
+                        -5.799_100_973,
+                        14.179_933_367,
+                        -13.745_145_378,
+                        4.645_852_612,
+                    ],
+                ),
+                polynomial(
+                    x,
+                    &[
+                        0.334_099_805,
+                        1.384_590_163,
+                        0.095_095_163,
+                        -19.332_440_956,
+                        56.690_552_601,
+                        -65.353_032_633,
+                        26.312_435_250,
+                    ],
+                ),
+            ],
+            // Polynomial fit of Google's turbo
+            Colormap::Turbo => [
+                polynomial(
+                    x,
+                    &[
+                        0.135_721_38,
+                        4.615_392_60,
+                        -42.660_322_58,
+                        132.131_082_34,
+                        -152.942_393_96,
+                        59.286_379_43,
+                    ],
+                ),
+                polynomial(
+                    x,
+                    &[
+                        0.091_402_61,
+                        2.194_188_39,
+                        4.842_966_58,
+                        -14.185_033_33,
+                        4.277_298_57,
+                        2.829_566_04,
+                    ],
+                ),
+                polynomial(
+                    x,
+                    &[
+                        0.106_673_30,
+                        12.641_946_08,
+                        -60.582_048_36,
+                        110.362_767_71,
+                        -89.903_109_12,
+                        27.348_249_73,
+                    ],
+                ),
+            ],
+        };
+        rgb.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+    }
+
+    /// Build a lookup table of the colormap, for rendering whole frames quickly
+    pub fn lut(&self) -> ColormapLut {
+        let mut lut = [[0; 3]; 256];
+        for (i, rgb) in lut.iter_mut().enumerate() {
+            *rgb = self.rgb(i as f64 / 255.0);
+        }
+        lut
+    }
+}
+
+/// Evaluate the polynomial with the given coefficients, in increasing order of degree
+fn polynomial(x: f64, coefficients: &[f64]) -> f64 {
+    coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+impl fmt::Display for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Colormap::Viridis => write!(f, "viridis"),
+            Colormap::Turbo => write!(f, "turbo"),
+        }
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "viridis" => Ok(Colormap::Viridis),
+            "turbo" => Ok(Colormap::Turbo),
+            _ => Err(format!(
+                "Unknown colormap '{s}'. Expected 'viridis' or 'turbo'"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Colormap;
+
+    #[test]
+    fn colormap_endpoints() {
+        // Viridis runs from dark purple to yellow
+        let viridis = Colormap::Viridis.lut();
+        assert!(viridis[0][2] > viridis[0][0] && viridis[0][2] > viridis[0][1]);
+        assert!(viridis[255][0] > 200 && viridis[255][1] > 200 && viridis[255][2] < 100);
+
+        // Turbo runs from near-black through blue to dark red
+        let turbo = Colormap::Turbo.lut();
+        assert!(turbo[0].iter().all(|channel| *channel < 64));
+        assert!(turbo[64][2] > turbo[64][0]);
+        assert!(turbo[255][0] > turbo[255][1] && turbo[255][0] > turbo[255][2]);
+
+        assert_eq!("Turbo".parse::<Colormap>(), Ok(Colormap::Turbo));
+        assert!("jet".parse::<Colormap>().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::framer::colormap::{Colormap, ColormapLut};
 use crate::framer::scale_intensity::{FrameValue, SaeTime};
 use bincode::config::{BigEndian, FixintEncoding, WithOtherEndian, WithOtherIntEncoding};
 use bincode::{DefaultOptions, Options};
@@ -7,11 +8,13 @@ use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt;
 
+use adder_codec_core::codec::decoder::Decoder;
 use adder_codec_core::{
-    BigT, Coord, DeltaT, Event, PlaneSize, SourceCamera, SourceType, TimeMode, D_EMPTY,
+    BigT, ChromaSubsampling, ColorSpace, Coord, DeltaT, Event, PixelAddress, PlaneSize,
+    SourceCamera, SourceType, TimeMode, D_EMPTY,
 };
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Read, Seek, Write};
 
 // Want one main framer with the same functions
 // Want additional functions
@@ -46,6 +49,9 @@ pub struct FramerBuilder {
     delta_t_max: DeltaT,
     detect_features: bool,
     buffer_limit: Option<u32>,
+    chroma_subsampling: ChromaSubsampling,
+    color_space: ColorSpace,
+    colormap: Option<Colormap>,
 
     /// The number of rows to process in each chunk (thread).
     pub chunk_rows: usize,
@@ -70,6 +76,9 @@ impl FramerBuilder {
             delta_t_max: 5000,
             detect_features: false,
             buffer_limit: None,
+            chroma_subsampling: ChromaSubsampling::None,
+            color_space: ColorSpace::Bgr,
+            colormap: None,
         }
     }
 
@@ -125,6 +134,30 @@ impl FramerBuilder {
         self
     }
 
+    /// Set the chroma subsampling of the source stream, so that the subsampled channels are
+    /// upsampled back to full resolution.
+    #[must_use]
+    pub fn chroma_subsampling(mut self, chroma_subsampling: ChromaSubsampling) -> FramerBuilder {
+        self.chroma_subsampling = chroma_subsampling;
+        self
+    }
+
+    /// Set the color space of the source stream, so that YCbCr channels are converted to BGR when
+    /// the frames are written out.
+    #[must_use]
+    pub fn color_space(mut self, color_space: ColorSpace) -> FramerBuilder {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Render the frames written out by [`FrameSequence::write_frame_bytes`] through a colormap,
+    /// as 8-bit RGB, rather than as raw intensities. Only applies to single-channel planes.
+    #[must_use]
+    pub fn colormap(mut self, colormap: Option<Colormap>) -> FramerBuilder {
+        self.colormap = colormap;
+        self
+    }
+
     /// Build a [`Framer`].
     /// TODO: Make this return a result
     #[must_use]
@@ -181,6 +214,44 @@ pub trait Framer {
 pub(crate) struct Frame<T> {
     pub(crate) array: Array3<T>,
     pub(crate) filled_count: usize,
+    pub(crate) stats: FrameFillStats,
+}
+
+/// How the pixels of a reconstructed frame got their values. In a frame-perfect reconstruction,
+/// every pixel is filled by an event. Pixels which were carried over or left unfilled indicate
+/// that the events didn't keep up with the frames, e.g., because Δt_max is too long.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FrameFillStats {
+    /// Pixels set by an event which spanned the frame
+    pub events: usize,
+
+    /// Pixels which repeated their previous intensity because of an empty event
+    pub empty_events: usize,
+
+    /// Pixels which no event reached before the frame was flushed out, so the framer repeated
+    /// their previous intensity
+    pub carried_over: usize,
+
+    /// Pixels which had no value when the frame was popped (e.g., because the buffer limit forced
+    /// it out), and are written out as the default value
+    pub unfilled: usize,
+}
+
+impl FrameFillStats {
+    /// The total number of pixels in the frame
+    #[must_use]
+    pub fn total(&self) -> usize {
+        self.events + self.empty_events + self.carried_over + self.unfilled
+    }
+}
+
+impl std::ops::AddAssign for FrameFillStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.events += rhs.events;
+        self.empty_events += rhs.empty_events;
+        self.carried_over += rhs.carried_over;
+        self.unfilled += rhs.unfilled;
+    }
 }
 
 /// Errors that can occur when working with [`FrameSequence`]
@@ -197,6 +268,9 @@ pub enum FrameSequenceError {
 
     /// An impossible "fill count" encountered
     BadFillCount,
+
+    /// A frame stride of zero
+    InvalidStride,
 }
 
 impl fmt::Display for FrameSequenceError {
@@ -206,6 +280,7 @@ impl fmt::Display for FrameSequenceError {
             FrameSequenceError::UninitializedFrame => write!(f, "Uninitialized frame"),
             FrameSequenceError::UninitializedFrameChunk => write!(f, "Uninitialized frame chunk"),
             FrameSequenceError::BadFillCount => write!(f, "Bad fill count"),
+            FrameSequenceError::InvalidStride => write!(f, "Frame stride must be at least 1"),
         }
     }
 }
@@ -242,6 +317,8 @@ pub struct FrameSequenceState {
     source_dtm: DeltaT,
     view_mode: FramedViewMode,
     time_mode: TimeMode,
+    chroma_subsampling: ChromaSubsampling,
+    color_space: ColorSpace,
 }
 
 impl FrameSequenceState {
@@ -279,12 +356,18 @@ pub struct FrameSequence<T> {
 
     pub(crate) running_intensities: Array3<u8>,
 
+    /// The colormap to render frames through when writing them out, if any
+    colormap_lut: Option<ColormapLut>,
+
+    /// The fill statistics of the last frame popped from each chunk
+    last_chunk_stats: Vec<FrameFillStats>,
+
     /// Number of rows per chunk (per thread)
     pub chunk_rows: usize,
     bincode: WithOtherEndian<WithOtherIntEncoding<DefaultOptions, FixintEncoding>, BigEndian>,
 }
 
-use ndarray::{Array, Array3};
+use ndarray::{Array, Array3, Array4};
 
 use crate::transcoder::source::video::FramedViewMode;
 use crate::utils::cv::is_feature;
@@ -323,6 +406,7 @@ impl<
             VecDeque::from(vec![Frame {
                 array,
                 filled_count: 0,
+                stats: FrameFillStats::default(),
             }]);
             num_chunks
         ];
@@ -332,6 +416,7 @@ impl<
             *last = VecDeque::from(vec![Frame {
                 array: last_array,
                 filled_count: 0,
+                stats: FrameFillStats::default(),
             }]);
         };
 
@@ -378,6 +463,8 @@ impl<
                 ref_interval: builder.ref_interval,
                 source_dtm: builder.delta_t_max,
                 time_mode: builder.time_mode,
+                chroma_subsampling: builder.chroma_subsampling,
+                color_space: builder.color_space,
             },
             frames,
             frame_idx_offsets: vec![0; num_chunks],
@@ -396,6 +483,11 @@ impl<
             features: VecDeque::with_capacity(
                 (builder.delta_t_max / builder.ref_interval) as usize,
             ),
+            colormap_lut: builder
+                .colormap
+                .filter(|_| plane.c() == 1)
+                .map(|colormap| colormap.lut()),
+            last_chunk_stats: vec![FrameFillStats::default(); num_chunks],
             chunk_rows,
             bincode: DefaultOptions::new()
                 .with_fixint_encoding()
@@ -440,6 +532,15 @@ impl<
     /// assert_eq!(*elem, Some(32));
     /// ```
     fn ingest_event(&mut self, event: &mut Event, last_event: Option<Event>) -> bool {
+        // Upsample a subsampled channel by copying the event to the rest of its block
+        for coord in self
+            .state
+            .chroma_subsampling
+            .upsampled_coords(event.coord, self.state.plane)
+        {
+            self.ingest_event(&mut Event { coord, ..*event }, last_event);
+        }
+
         let channel = event.coord.c.unwrap_or(0);
         let chunk_num = event.coord.y as usize / self.chunk_rows;
 
@@ -449,16 +550,16 @@ impl<
         }
 
         let time = event.t;
-        event.coord.y -= (chunk_num * self.chunk_rows) as u16; // Modify the coordinate here, so it gets ingested at the right place
+        event.coord.y -= (chunk_num * self.chunk_rows) as PixelAddress; // Modify the coordinate here, so it gets ingested at the right place
 
         let frame_chunk = &mut self.frames[chunk_num];
         let last_filled_frame_ref = &mut self.last_filled_tracker[chunk_num]
-            [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+            [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
         let running_ts_ref = &mut self.pixel_ts_tracker[chunk_num]
-            [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+            [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
         let frame_idx_offset = &mut self.frame_idx_offsets[chunk_num];
         let last_frame_intensity_ref = &mut self.last_frame_intensity_tracker[chunk_num]
-            [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+            [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
 
         let (filled, grew) = ingest_event_for_chunk(
             event,
@@ -486,11 +587,11 @@ impl<
 
         if self.detect_features {
             let last_frame_intensity_ref = &mut self.last_frame_intensity_tracker[chunk_num]
-                [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+                [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
             // Revert the y coordinate
-            event.coord.y += (chunk_num * self.chunk_rows) as u16;
+            event.coord.y += (chunk_num * self.chunk_rows) as PixelAddress;
             self.running_intensities
-                [[event.coord.y.into(), event.coord.x.into(), channel.into()]] =
+                [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]] =
                 <T as Into<f64>>::into(*last_frame_intensity_ref) as u8;
 
             if let Some(last) = last_event {
@@ -570,6 +671,10 @@ impl<
         // Make sure that the chunk division is aligned between the source and the framer
         assert_eq!(events.len(), self.frames.len());
 
+        if self.state.chroma_subsampling != ChromaSubsampling::None {
+            events = self.upsample_chroma(events);
+        }
+
         (
             &mut events,
             &mut self.frames,
@@ -593,13 +698,13 @@ impl<
                     for event in a {
                         let channel = event.coord.c.unwrap_or(0);
                         let chunk_num = event.coord.y as usize / self.chunk_rows;
-                        event.coord.y -= (chunk_num * self.chunk_rows) as u16; // Modify the coordinate here, so it gets ingested at the right place
+                        event.coord.y -= (chunk_num * self.chunk_rows) as PixelAddress; // Modify the coordinate here, so it gets ingested at the right place
                         let last_filled_frame_ref = &mut chunk_last_filled_tracker
-                            [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+                            [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
                         let running_ts_ref = &mut chunk_ts_tracker
-                            [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+                            [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
                         let last_frame_intensity_ref = &mut last_frame_intensity_tracker
-                            [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+                            [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
 
                         let (filled, _grew) = ingest_event_for_chunk(
                             event,
@@ -653,6 +758,7 @@ impl<
 
                         // Update the fill tracker
                         frame_chunk.filled_count += 1;
+                        frame_chunk.stats.carried_over += 1;
 
                         // Update the last filled tracker
                         self.last_filled_tracker[chunk_num][[y, x, c]] += 1;
@@ -682,6 +788,78 @@ impl<
     }
 }
 
+impl<
+        T: Clone
+            + Default
+            + FrameValue<Output = T>
+            + Copy
+            + Serialize
+            + Send
+            + Sync
+            + num_traits::identities::Zero
+            + Into<f64>,
+    > FrameSequence<T>
+{
+    /// Decode the rest of the stream and reconstruct all of its frames as a single array, with
+    /// axes `(t, h, w, c)`.
+    ///
+    /// Only every `stride`-th frame is kept (starting with the first), so that a long clip can be
+    /// subsampled in time without holding every frame in memory. A `stride` of 1 keeps all frames.
+    ///
+    /// # Errors
+    /// * If `stride` is 0
+    /// * If the stream could not be decoded
+    /// * If a frame chunk has not been initialized
+    pub fn reconstruct_all<R: Read + Seek>(
+        &mut self,
+        decoder: &mut Decoder<R>,
+        bitreader: &mut bitstream_io::BitReader<R, bitstream_io::BigEndian>,
+        stride: usize,
+    ) -> Result<Array4<T>, Box<dyn Error>> {
+        if stride == 0 {
+            return Err(FrameSequenceError::InvalidStride.into());
+        }
+        let plane = self.state.plane;
+        let mut data: Vec<T> = Vec::new();
+        let mut frame_idx = 0;
+        let mut frames_kept = 0;
+
+        let mut take_filled_frames = |framer: &mut Self| -> Result<(), Box<dyn Error>> {
+            while framer.is_frame_filled(0)? {
+                let chunks = framer
+                    .pop_next_frame()
+                    .ok_or(FrameSequenceError::UninitializedFrameChunk)?;
+                if frame_idx % stride == 0 {
+                    // The chunks are consecutive bands of rows, so they concatenate in order
+                    data.extend(chunks.iter().flatten().map(|px| px.unwrap_or_default()));
+                    frames_kept += 1;
+                }
+                frame_idx += 1;
+            }
+            Ok(())
+        };
+
+        for event in decoder.events(bitreader) {
+            if self.ingest_event(&mut event?, None) {
+                take_filled_frames(self)?;
+            }
+        }
+        while self.flush_frame_buffer() {
+            take_filled_frames(self)?;
+        }
+
+        Ok(Array4::from_shape_vec(
+            (
+                frames_kept,
+                plane.h_usize(),
+                plane.w_usize(),
+                plane.c_usize(),
+            ),
+            data,
+        )?)
+    }
+}
+
 fn handle_dtm<
     T: Clone
         + Default
@@ -715,6 +893,7 @@ fn handle_dtm<
 
                 // Update the fill tracker
                 frame_chunk.filled_count += 1;
+                frame_chunk.stats.carried_over += 1;
 
                 // Update the last filled tracker
                 chunk_last_filled_tracker[[y, x, c]] += 1;
@@ -877,6 +1056,18 @@ impl<T: Clone + Default + FrameValue<Output = T> + Serialize> FrameSequence<T> {
         self.features.pop_front()
     }
 
+    /// Get how the pixels of the last frame popped were filled, summed over its chunks. Check this
+    /// after each call to [`pop_next_frame`](Self::pop_next_frame) to verify that a reconstruction
+    /// is frame-perfect, or to detect when the events fall behind the frames.
+    #[must_use]
+    pub fn last_frame_stats(&self) -> FrameFillStats {
+        let mut stats = FrameFillStats::default();
+        for chunk_stats in &self.last_chunk_stats {
+            stats += *chunk_stats;
+        }
+        stats
+    }
+
     /// Pop the next frame for all chunks
     ///
     /// returns: the frame
@@ -909,13 +1100,19 @@ impl<T: Clone + Default + FrameValue<Output = T> + Serialize> FrameSequence<T> {
         self.frames[chunk_num].rotate_left(1);
         match self.frames[chunk_num].pop_back() {
             Some(a) => {
+                self.last_chunk_stats[chunk_num] = FrameFillStats {
+                    unfilled: a.array.iter().filter(|px| px.is_none()).count(),
+                    ..a.stats
+                };
+
                 // If this is the only frame left, then add a new one to prevent invalid accesses later
                 if self.frames[chunk_num].is_empty() {
                     let array: Array3<Option<T>> = Array3::<Option<T>>::default(a.array.raw_dim());
                     self.frames[chunk_num].append(&mut VecDeque::from(vec![
                         Frame {
                             array,
-                            filled_count: 0
+                            filled_count: 0,
+                            stats: FrameFillStats::default(),
                         };
                         1
                     ]));
@@ -929,6 +1126,48 @@ impl<T: Clone + Default + FrameValue<Output = T> + Serialize> FrameSequence<T> {
         }
     }
 
+    /// Copy the events of subsampled channels to the rest of their blocks, placing each copy in
+    /// the chunk which it belongs to. A block may straddle two chunks.
+    fn upsample_chroma(&self, events: Vec<Vec<Event>>) -> Vec<Vec<Event>> {
+        let mut upsampled: Vec<Vec<Event>> = events
+            .iter()
+            .map(|chunk| Vec::with_capacity(chunk.len()))
+            .collect();
+        for event in events.into_iter().flatten() {
+            for coord in self
+                .state
+                .chroma_subsampling
+                .upsampled_coords(event.coord, self.state.plane)
+            {
+                upsampled[coord.y as usize / self.chunk_rows].push(Event { coord, ..event });
+            }
+            upsampled[event.coord.y as usize / self.chunk_rows].push(event);
+        }
+        upsampled
+    }
+
+    /// Convert the (Y, Cb, Cr) channels of a popped frame (or frame chunk) to (B, G, R) in place,
+    /// if the source stream is YCbCr. The converted values are clamped to the range of `T`.
+    /// Pixels which haven't been filled are left empty.
+    pub fn convert_to_bgr(&self, frame: &mut Array3<Option<T>>)
+    where
+        T: Copy + Into<f64> + num_traits::FromPrimitive,
+    {
+        if self.state.color_space != ColorSpace::YCbCr || self.state.plane.c() != 3 {
+            return;
+        }
+        let max = f64::from(T::max_f32());
+        for mut pixel in frame.lanes_mut(ndarray::Axis(2)) {
+            let ycbcr = match (pixel[0], pixel[1], pixel[2]) {
+                (Some(y), Some(cb), Some(cr)) => [y.into(), cb.into(), cr.into()],
+                _ => continue,
+            };
+            for (value, converted) in pixel.iter_mut().zip(ColorSpace::ycbcr_to_bgr(ycbcr, max)) {
+                *value = T::from_f64(converted.round().clamp(0.0, max));
+            }
+        }
+    }
+
     /// Write out the next frame to the given writer
     /// # Arguments
     /// * `writer` - The writer to write the frame to
@@ -937,22 +1176,30 @@ impl<T: Clone + Default + FrameValue<Output = T> + Serialize> FrameSequence<T> {
     /// # Errors
     /// * If the frame chunk has not been initialized
     /// * If the data cannot be written
-    pub fn write_frame_bytes(
-        &mut self,
-        writer: &mut BufWriter<File>,
-    ) -> Result<(), Box<dyn Error>> {
+    pub fn write_frame_bytes(&mut self, writer: &mut BufWriter<File>) -> Result<(), Box<dyn Error>>
+    where
+        T: Copy + Into<f64> + num_traits::FromPrimitive,
+    {
         let none_val = T::default();
         for chunk_num in 0..self.frames.len() {
             match self.pop_next_frame_for_chunk(chunk_num) {
-                Some(arr) => {
+                Some(mut arr) => {
+                    self.convert_to_bgr(&mut arr);
                     for px in arr.iter() {
-                        self.bincode.serialize_into(
-                            &mut *writer,
-                            match px {
-                                Some(event) => event,
-                                None => &none_val,
-                            },
-                        )?;
+                        let px = match px {
+                            Some(event) => event,
+                            None => &none_val,
+                        };
+                        match &self.colormap_lut {
+                            Some(lut) => {
+                                let value: f64 = (*px).into();
+                                let level = (value / f64::from(T::max_f32()) * 255.0)
+                                    .round()
+                                    .clamp(0.0, 255.0);
+                                writer.write_all(&lut[level as usize])?;
+                            }
+                            None => self.bincode.serialize_into(&mut *writer, px)?,
+                        }
                     }
                 }
                 None => {
@@ -975,7 +1222,10 @@ impl<T: Clone + Default + FrameValue<Output = T> + Serialize> FrameSequence<T> {
     pub fn write_multi_frame_bytes(
         &mut self,
         writer: &mut BufWriter<File>,
-    ) -> Result<i32, Box<dyn Error>> {
+    ) -> Result<i32, Box<dyn Error>>
+    where
+        T: Copy + Into<f64> + num_traits::FromPrimitive,
+    {
         let mut frame_count = 0;
         while self.is_frame_filled(0)? {
             self.write_frame_bytes(writer)?;
@@ -1004,7 +1254,7 @@ fn ingest_event_for_chunk<
     let prev_last_filled_frame = *last_filled_frame_ref;
     let prev_running_ts = *running_ts_ref;
 
-    if state.codec_version >= 2 && state.time_mode == TimeMode::AbsoluteT {
+    if state.codec_version >= 2 && state.time_mode != TimeMode::DeltaT {
         if prev_running_ts >= event.t as BigT {
             return (
                 frame_chunk[0].filled_count == frame_chunk[0].array.len(),
@@ -1025,7 +1275,7 @@ fn ingest_event_for_chunk<
             let practical_d_max =
                 fast_math::log2_raw(T::max_f32() * (state.source_dtm / state.ref_interval) as f32);
             if state.codec_version >= 2
-                && state.time_mode == TimeMode::AbsoluteT
+                && state.time_mode != TimeMode::DeltaT
                 && state.view_mode != FramedViewMode::SAE
             {
                 // event.delta_t -= ((*last_filled_frame_ref + 1) * state.ref_interval as i64) as u32;
@@ -1057,7 +1307,8 @@ fn ingest_event_for_chunk<
                 frame_chunk.append(&mut VecDeque::from(vec![
                     Frame {
                         array,
-                        filled_count: 0
+                        filled_count: 0,
+                        stats: FrameFillStats::default(),
                     };
                     a as usize
                 ]));
@@ -1084,12 +1335,18 @@ fn ingest_event_for_chunk<
         for i in prev_last_filled_frame..*last_filled_frame_ref {
             if i - state.frames_written + 1 >= 0 {
                 px = &mut frame_chunk[(i - state.frames_written + 1) as usize].array
-                    [[event.coord.y.into(), event.coord.x.into(), channel.into()]];
+                    [[event.coord.y_usize(), event.coord.x_usize(), channel.into()]];
                 match px {
                     Some(_val) => {}
                     None => {
                         *px = Some(*last_frame_intensity_ref);
-                        frame_chunk[(i - state.frames_written + 1) as usize].filled_count += 1;
+                        let frame = &mut frame_chunk[(i - state.frames_written + 1) as usize];
+                        frame.filled_count += 1;
+                        if event.d == D_EMPTY {
+                            frame.stats.empty_events += 1;
+                        } else {
+                            frame.stats.events += 1;
+                        }
                     }
                 }
             }
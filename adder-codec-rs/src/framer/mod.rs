@@ -1,3 +1,6 @@
+/// Colormaps for rendering single-channel reconstructions in false color
+pub mod colormap;
+
 /// Provides a `Framer` trait for encoding and decoding frames from events
 pub mod driver;
 
@@ -1,7 +1,5 @@
 use crate::transcoder::source::video::FramedViewMode;
-use adder_codec_core::{
-    DeltaT, Event, EventCoordless, Intensity, SourceType, D_SHIFT, D_SHIFT_F64,
-};
+use adder_codec_core::{DeltaT, Event, EventCoordless, Intensity, SourceType};
 
 /// A trait for types that can be used as the value of a pixel in a `Frame`.
 pub trait FrameValue {
@@ -68,7 +66,7 @@ impl FrameValue for u8 {
             FramedViewMode::Intensity => {
                 let intensity = event_to_intensity(event);
                 match source_type {
-                    SourceType::U8 => ((intensity * tpf)) as u8,
+                    SourceType::U8 => (intensity * tpf) as u8,
                     SourceType::U16 => {
                         (intensity / f64::from(u16::MAX) * tpf * f64::from(u8::MAX)) as u8
                     }
@@ -78,11 +76,9 @@ impl FrameValue for u8 {
                     SourceType::U64 => {
                         (intensity / u64::MAX as f64 * tpf * f64::from(u8::MAX)) as u8
                     }
-                    SourceType::F32 => {
-                        todo!()
-                    }
-                    SourceType::F64 => {
-                        todo!()
+                    // Floating-point sources are normalized to [0, 1]
+                    SourceType::F32 | SourceType::F64 => {
+                        (intensity * tpf * f64::from(u8::MAX)) as u8
                     }
                 }
             }
@@ -125,22 +121,18 @@ impl FrameValue for u16 {
                 let intensity = event_to_intensity(event);
                 match source_type {
                     SourceType::U8 => {
-                        (intensity / f64::from(u8::MAX) * tpf * f64::from(u16::MAX))
-                            as u16
+                        (intensity / f64::from(u8::MAX) * tpf * f64::from(u16::MAX)) as u16
                     }
                     SourceType::U16 => (intensity * tpf) as u16,
                     SourceType::U32 => {
-                        (intensity / f64::from(u32::MAX) * tpf * f64::from(u16::MAX))
-                            as u16
+                        (intensity / f64::from(u32::MAX) * tpf * f64::from(u16::MAX)) as u16
                     }
                     SourceType::U64 => {
                         (intensity / u64::MAX as f64 * tpf * f64::from(u16::MAX)) as u16
                     }
-                    SourceType::F32 => {
-                        todo!()
-                    }
-                    SourceType::F64 => {
-                        todo!()
+                    // Floating-point sources are normalized to [0, 1]
+                    SourceType::F32 | SourceType::F64 => {
+                        (intensity * tpf * f64::from(u16::MAX)) as u16
                     }
                 }
             }
@@ -178,22 +170,18 @@ impl FrameValue for u32 {
                 let intensity = event_to_intensity(event);
                 match source_type {
                     SourceType::U8 => {
-                        (intensity / f64::from(u8::MAX) * tpf * f64::from(u32::MAX))
-                            as u32
+                        (intensity / f64::from(u8::MAX) * tpf * f64::from(u32::MAX)) as u32
                     }
                     SourceType::U16 => {
-                        (intensity / f64::from(u16::MAX) * tpf * f64::from(u32::MAX))
-                            as u32
+                        (intensity / f64::from(u16::MAX) * tpf * f64::from(u32::MAX)) as u32
                     }
                     SourceType::U32 => (intensity * tpf) as u32,
                     SourceType::U64 => {
                         (intensity / u64::MAX as f64 * tpf * f64::from(u32::MAX)) as u32
                     }
-                    SourceType::F32 => {
-                        todo!()
-                    }
-                    SourceType::F64 => {
-                        todo!()
+                    // Floating-point sources are normalized to [0, 1]
+                    SourceType::F32 | SourceType::F64 => {
+                        (intensity * tpf * f64::from(u32::MAX)) as u32
                     }
                 }
             }
@@ -238,12 +226,8 @@ impl FrameValue for u64 {
                         (intensity / f64::from(u32::MAX) * tpf * u64::MAX as f64) as u64
                     }
                     SourceType::U64 => (intensity * tpf) as u64,
-                    SourceType::F32 => {
-                        todo!()
-                    }
-                    SourceType::F64 => {
-                        todo!()
-                    }
+                    // Floating-point sources are normalized to [0, 1]
+                    SourceType::F32 | SourceType::F64 => (intensity * tpf * u64::MAX as f64) as u64,
                 }
             }
             FramedViewMode::D => ((f32::from(event.d) / practical_d_max) * u64::MAX as f32) as u64,
@@ -264,18 +248,5 @@ impl FrameValue for u64 {
 /// Convert an event to an intensity value.
 #[must_use]
 pub fn event_to_intensity(event: &Event) -> Intensity {
-    match event.d as usize {
-        a if a >= D_SHIFT.len() => f64::from(0),
-        _ => match event.t {
-            0 => D_SHIFT_F64[event.d as usize], // treat it as dt = 1
-            _ => D_SHIFT_F64[event.d as usize] / f64::from(event.t),
-        },
-    }
-}
-
-fn _eventcoordless_to_intensity(event: EventCoordless) -> Intensity {
-    match event.d as usize {
-        a if a >= D_SHIFT.len() => f64::from(0),
-        _ => D_SHIFT[event.d as usize] as Intensity / f64::from(event.t),
-    }
+    EventCoordless::from(*event).intensity()
 }
@@ -261,6 +261,3 @@ impl DControl for Manual {
         todo!()
     }
 }
-
-unsafe impl Sync for DController {}
-unsafe impl Send for DController {}
@@ -1,8 +1,15 @@
+#[cfg(feature = "gpu")]
+use crate::transcoder::gpu::{
+    GpuPixel, FLAG_DTM_REACHED, FLAG_ELIGIBLE, FLAG_POPPED_DTM, FLAG_QUIET,
+};
+use crate::transcoder::source::noise::NoiseFloor;
+use crate::transcoder::source::video::EventBudget;
 use adder_codec_core::Mode::{Continuous, FramePerfect};
 use adder_codec_core::{
     AbsoluteT, Coord, DeltaT, Event, Mode, PixelMultiMode, TimeMode, D, D_SHIFT_F32,
 };
 use adder_codec_core::{UDshift, D_EMPTY, D_MAX, D_SHIFT, D_ZERO_INTEGRATION};
+use serde::{Deserialize, Serialize};
 use smallvec::{smallvec, SmallVec};
 use std::cmp::min;
 
@@ -13,7 +20,7 @@ pub type Intensity32 = f32;
 // pub type PixelAddress = u16;
 
 #[repr(packed)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Event32 {
     pub coord: Coord,
     pub d: D,
@@ -31,7 +38,7 @@ impl From<Event32> for Event {
 }
 
 #[repr(packed)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct PixelState {
     d: D,
     integration: Intensity32,
@@ -39,7 +46,7 @@ pub(crate) struct PixelState {
 }
 
 #[repr(packed)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct PixelNode {
     /// Specifies if the next pixel in the arena vec exists
     alt: Option<()>,
@@ -50,6 +57,7 @@ pub struct PixelNode {
 
 // Each PixelNode is ~20 bytes. Each PixelArena is at least 20 + (6*20) 140 bytes, but takes at
 // least 144 bytes of space, I think?
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PixelArena {
     pub coord: Coord,
     time_mode: TimeMode,
@@ -61,8 +69,26 @@ pub struct PixelArena {
     pub arena: SmallVec<[PixelNode; 6]>,
     pub(crate) c_thresh: u8,
     pub(crate) c_increase_counter: u8,
+
+    /// The (baseline, maximum) contrast thresholds of the pixel's region of a
+    /// [`ContrastMask`](crate::transcoder::source::video::ContrastMask), which override the
+    /// global ones
+    pub(crate) c_thresh_bounds: Option<(u8, u8)>,
     dtm_reached: bool,
     popped_dtm: bool,
+
+    /// The minimum D value the pixel may use, as imposed by an [`EventBudget`]
+    pub(crate) d_floor: D,
+    budget_window_start: f32,
+    budget_count: u32,
+    throttled: bool,
+
+    /// The number of budget windows in which the pixel exceeded its [`EventBudget`]
+    pub(crate) throttled_windows: u32,
+
+    /// Whether the pixel was already integrated for the current input interval on the GPU
+    #[serde(skip)]
+    pub(crate) integrated_on_gpu: bool,
 }
 
 impl PixelArena {
@@ -81,8 +107,15 @@ impl PixelArena {
             arena,
             c_thresh: 10,
             c_increase_counter: 1,
+            c_thresh_bounds: None,
             dtm_reached: false,
             popped_dtm: false,
+            d_floor: 0,
+            budget_window_start: 0.0,
+            budget_count: 0,
+            throttled: false,
+            throttled_windows: 0,
+            integrated_on_gpu: false,
         }
     }
 
@@ -116,8 +149,8 @@ impl PixelArena {
         mode: Mode,
         ref_time: DeltaT,
     ) -> Event {
-        // Handle AbsoluteT mode
-        if self.time_mode == TimeMode::AbsoluteT {
+        // Handle AbsoluteT and Mixed modes
+        if self.time_mode != TimeMode::DeltaT {
             event.delta_t += self.last_fired_t;
             self.last_fired_t = event.delta_t;
             if mode == FramePerfect {
@@ -293,7 +326,7 @@ impl PixelArena {
     ) -> Option<Event> {
         assert!(self.arena[0].best_event.is_none()); // Should only be called after popping events
                                                      // let head = &mut self.arena[0];
-        let next_d = get_d_from_intensity(next_intensity);
+        let next_d = get_d_from_intensity(next_intensity).max(self.d_floor);
         let ret = if next_d < self.arena[0].state.d && self.arena[0].state.delta_t > 0.0 {
             let mut ret32 = Event32 {
                 coord: self.coord,
@@ -331,7 +364,7 @@ impl PixelArena {
         }
         let tail = &mut self.arena[self.length - 1];
         if tail.state.delta_t == 0.0 && tail.state.integration == 0.0 {
-            tail.state.d = get_d_from_intensity(intensity);
+            tail.state.d = get_d_from_intensity(intensity).max(self.d_floor);
         }
         self.running_t += time;
 
@@ -399,6 +432,19 @@ impl PixelArena {
         // safely cast it to integer [`D`] type.
         // (!self.dtm_reached && unsafe { self.arena[0].state.delta_t.to_int_unchecked::<DeltaT>() } >= dtm);
 
+        self.raise_c_thresh(start_time, ref_time, c_thresh_max, c_increase_velocity);
+    }
+
+    /// Raise the contrast threshold of a pixel which has integrated for `time` ticks, if it has
+    /// stayed within the threshold for long enough
+    fn raise_c_thresh(
+        &mut self,
+        time: f32,
+        ref_time: DeltaT,
+        c_thresh_max: u8,
+        c_increase_velocity: u8,
+    ) {
+        let c_thresh_max = self.c_thresh_bounds.map_or(c_thresh_max, |(_, max)| max);
         if self.c_thresh < c_thresh_max {
             if self.c_increase_counter >= c_increase_velocity - 1 {
                 // Increment the threshold
@@ -407,11 +453,219 @@ impl PixelArena {
             } else {
                 self.c_increase_counter = self
                     .c_increase_counter
-                    .saturating_add((start_time as DeltaT / ref_time) as u8);
+                    .saturating_add((time as DeltaT / ref_time) as u8);
             }
         }
     }
 
+    /// How far the pixel's input may move from its base value without firing its events: its
+    /// contrast threshold, raised to the noise floor of its base value, if there is one
+    pub(crate) fn contrast_band(&self, noise_floor: Option<&NoiseFloor>) -> u8 {
+        noise_floor.map_or(self.c_thresh, |floor| {
+            self.c_thresh.max(floor[usize::from(self.base_val)])
+        })
+    }
+
+    /// The D at which the pixel would integrate `intensity`, if it can be integrated in bulk by
+    /// [`integrate_for_pxs`]: it has a single node and no top event to pop, and `frame_val` is
+    /// within its contrast threshold. It still fires if the node's integration reaches the
+    /// threshold of that D.
+    ///
+    /// [`integrate_for_pxs`]: crate::transcoder::source::video::integrate_for_pxs
+    pub(crate) fn bulk_d(
+        &self,
+        intensity: Intensity32,
+        frame_val: u8,
+        noise_floor: Option<&NoiseFloor>,
+    ) -> Option<D> {
+        let c_thresh = self.contrast_band(noise_floor);
+        if self.length != 1
+            || self.need_to_pop_top
+            || frame_val < self.base_val.saturating_sub(c_thresh)
+            || frame_val > self.base_val.saturating_add(c_thresh)
+        {
+            return None;
+        }
+        let state = self.arena[0].state;
+        let d = if state.delta_t == 0.0 && state.integration == 0.0 {
+            get_d_from_intensity(intensity).max(self.d_floor)
+        } else {
+            state.d
+        };
+        (d != D_MAX).then_some(d)
+    }
+
+    /// The integration and Δt of the pixel's top node
+    pub(crate) fn head_integration(&self) -> (Intensity32, f32) {
+        let state = self.arena[0].state;
+        (state.integration, state.delta_t)
+    }
+
+    /// Integrate an interval in which the pixel doesn't fire, given the new `d`, `integration`,
+    /// and `delta_t` of its only node, as worked out by [`integrate_for_pxs`]. Returns false,
+    /// leaving the pixel as it was, if reaching Δt_max means that it must fire after all.
+    ///
+    /// [`integrate_for_pxs`]: crate::transcoder::source::video::integrate_for_pxs
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn integrate_bulk(
+        &mut self,
+        d: D,
+        integration: Intensity32,
+        delta_t: f32,
+        dtm_reached: bool,
+        time: f32,
+        ref_time: DeltaT,
+        c_thresh_max: u8,
+        c_increase_velocity: u8,
+    ) -> bool {
+        if dtm_reached && !self.popped_dtm {
+            return false;
+        }
+        if self.arena.capacity() > self.arena.len() {
+            self.arena.shrink_to_fit();
+        }
+        self.arena[0].state = PixelState {
+            d,
+            integration,
+            delta_t,
+        };
+        self.running_t += time;
+        self.dtm_reached = dtm_reached;
+        self.need_to_pop_top = false;
+        self.raise_c_thresh(time, ref_time, c_thresh_max, c_increase_velocity);
+        true
+    }
+
+    /// Reset the contrast threshold to its baseline: that of the pixel's region, if it has one, or
+    /// else the given global baseline
+    pub(crate) fn reset_c_thresh(&mut self, c_thresh_baseline: u8) {
+        self.c_thresh = self
+            .c_thresh_bounds
+            .map_or(c_thresh_baseline, |(baseline, _)| baseline);
+        self.c_increase_counter = 0;
+    }
+
+    /// Fire the pixel's pending events and start it over with nothing integrated, e.g., at a
+    /// scene cut, so that its next events don't blend light from before the reset. The time
+    /// integrated since the last event is closed off with an empty event, to keep the pixel's
+    /// timestamps in step with the source. The contrast threshold returns to its baseline, but
+    /// the pixel's [`EventBudget`] state is kept.
+    pub(crate) fn reset(
+        &mut self,
+        buffer: &mut Vec<Event>,
+        mode: Mode,
+        multi_mode: PixelMultiMode,
+        ref_time: DeltaT,
+        c_thresh_baseline: u8,
+    ) {
+        if self.need_to_pop_top {
+            buffer.push(self.pop_top_event(0.0, mode, ref_time));
+        }
+        self.pop_best_events(buffer, mode, multi_mode, ref_time, 0.0);
+        if self.arena[0].state.delta_t > 0.0 {
+            let mut event = Event32 {
+                coord: self.coord,
+                d: D_EMPTY,
+                delta_t: self.arena[0].state.delta_t,
+            };
+            buffer.push(self.delta_t_to_absolute_t(&mut event, mode, ref_time));
+        }
+        self.arena[0] = PixelNode::new(0.0);
+        self.reset_c_thresh(c_thresh_baseline);
+    }
+
+    /// Count the events which the pixel just fired against its budget. The first time the pixel
+    /// exceeds its budget in a window, its minimum D is raised above that of the events it fired,
+    /// so that it must integrate more light before firing again. If the pixel stays well within
+    /// its budget for a whole window, the minimum D is relaxed.
+    pub(crate) fn enforce_budget(&mut self, events: &[Event], budget: &EventBudget) {
+        self.budget_count += events.len() as u32;
+        if self.budget_count > budget.max_events && !self.throttled {
+            let fired_d = events
+                .iter()
+                .map(|event| event.d)
+                .filter(|d| *d <= D_MAX)
+                .max()
+                .unwrap_or(0);
+            self.d_floor = (fired_d.max(self.d_floor) + 1).min(D_MAX);
+            self.throttled = true;
+            self.throttled_windows += 1;
+        }
+
+        if self.running_t - self.budget_window_start >= budget.window as f32 {
+            if self.budget_count <= budget.max_events / 2 {
+                self.d_floor = self.d_floor.saturating_sub(1);
+            }
+            self.budget_window_start = self.running_t;
+            self.budget_count = 0;
+            self.throttled = false;
+        }
+    }
+
+    /// Pack the pixel's state and its next input for the [`GpuIntegrator`]. Only a pixel with a
+    /// single node and no top event to pop may be integrated on the GPU.
+    ///
+    /// [`GpuIntegrator`]: crate::transcoder::gpu::GpuIntegrator
+    #[cfg(feature = "gpu")]
+    pub(crate) fn to_gpu(
+        &self,
+        intensity: Intensity32,
+        frame_val: u8,
+        c_thresh_max: u8,
+        noise_floor: Option<&NoiseFloor>,
+        sampled: bool,
+    ) -> GpuPixel {
+        let state = self.arena[0].state;
+        let mut flags = 0;
+        if sampled && self.length == 1 && !self.need_to_pop_top {
+            flags |= FLAG_ELIGIBLE;
+        }
+        if self.popped_dtm {
+            flags |= FLAG_POPPED_DTM;
+        }
+        GpuPixel {
+            integration: state.integration,
+            delta_t: state.delta_t,
+            running_t: self.running_t,
+            intensity,
+            d: u32::from(state.d),
+            flags,
+            base_val: u32::from(self.base_val),
+            frame_val: u32::from(frame_val),
+            c_thresh: u32::from(self.c_thresh),
+            contrast_band: u32::from(self.contrast_band(noise_floor)),
+            c_increase_counter: u32::from(self.c_increase_counter),
+            c_thresh_max: u32::from(self.c_thresh_bounds.map_or(c_thresh_max, |(_, max)| max)),
+            d_floor: u32::from(self.d_floor),
+        }
+    }
+
+    /// Take the state integrated by the [`GpuIntegrator`], if the pixel didn't fire. Otherwise,
+    /// the pixel is left as it was, to be integrated on the CPU.
+    ///
+    /// [`GpuIntegrator`]: crate::transcoder::gpu::GpuIntegrator
+    #[cfg(feature = "gpu")]
+    pub(crate) fn apply_gpu(&mut self, px: &GpuPixel) {
+        self.integrated_on_gpu = px.flags & FLAG_QUIET != 0;
+        if !self.integrated_on_gpu {
+            return;
+        }
+        if self.arena.capacity() > self.arena.len() {
+            self.arena.shrink_to_fit();
+        }
+        // The nodes are packed, so their fields can't be borrowed
+        self.arena[0].state = PixelState {
+            d: px.d as D,
+            integration: px.integration,
+            delta_t: px.delta_t,
+        };
+        self.running_t = px.running_t;
+        self.dtm_reached = px.flags & FLAG_DTM_REACHED != 0;
+        self.need_to_pop_top = false;
+        self.c_thresh = px.c_thresh as u8;
+        self.c_increase_counter = px.c_increase_counter as u8;
+    }
+
     /// Integrate an intensity for a given node. Returns `Some()` if the node fires an event, so
     /// that the newly-created branch's node only gets integrated with the remaining intensity.
     #[allow(clippy::similar_names)]
@@ -1256,4 +1510,186 @@ mod tests {
         assert_eq!(dt, 110);
         assert_eq!(ev.d, 255);
     }
+
+    #[test]
+    fn test_reset() {
+        let mut tree = make_tree();
+        tree.c_thresh = 40;
+
+        let mut events = Vec::new();
+        tree.reset(&mut events, Continuous, PixelMultiMode::Normal, 20, 10);
+
+        // The two fired events, then the rest of the integrated time with no intensity
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].d, 7);
+        assert_eq!(events[1].d, 6);
+        assert_eq!(events[2].d, D_EMPTY);
+        assert_eq!(events[2].t, 1);
+
+        assert_eq!(tree.length, 1);
+        assert!(tree.arena[0].best_event.is_none());
+        assert!(tree.arena[0].alt.is_none());
+        let (integration, delta_t) = (tree.arena[0].state.integration, tree.arena[0].state.delta_t);
+        assert_eq!(integration, 0.0);
+        assert_eq!(delta_t, 0.0);
+        assert_eq!(tree.c_thresh, 10);
+    }
+
+    #[test]
+    fn test_enforce_budget() {
+        let mut tree = PixelArena::new(
+            100.0,
+            Coord {
+                x: 0,
+                y: 0,
+                c: None,
+            },
+        );
+        let budget = EventBudget {
+            max_events: 2,
+            window: 100,
+        };
+        let event = |d| Event {
+            coord: tree.coord,
+            d,
+            t: 0,
+        };
+        let events = [event(5), event(6), event(D_EMPTY)];
+
+        // Over budget, so the minimum D is raised past the largest D fired
+        tree.running_t = 10.0;
+        tree.enforce_budget(&events, &budget);
+        assert_eq!(tree.d_floor, 7);
+        assert_eq!(tree.throttled_windows, 1);
+
+        // Only throttled once per window
+        tree.running_t = 20.0;
+        tree.enforce_budget(&events, &budget);
+        assert_eq!(tree.d_floor, 7);
+        assert_eq!(tree.throttled_windows, 1);
+
+        // Window ends. The next window stays within budget, so the floor is relaxed at its end
+        tree.running_t = 100.0;
+        tree.enforce_budget(&[], &budget);
+        assert_eq!(tree.d_floor, 7);
+        tree.running_t = 200.0;
+        tree.enforce_budget(&events[..1], &budget);
+        assert_eq!(tree.d_floor, 6);
+
+        // The floor applies to the next integration
+        tree.integrate(
+            10.0,
+            1.0,
+            FramePerfect,
+            1000,
+            1,
+            0,
+            255,
+            PixelMultiMode::Normal,
+        );
+        assert_eq!(tree.arena[0].state.d, 6);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn test_integrate_lanes_simd_matches_scalar() {
+        use crate::transcoder::source::video::{
+            integrate_lanes_scalar, integrate_lanes_simd, LANES,
+        };
+
+        let lane_values = |scale: f32, offset: f32| -> [f32; LANES] {
+            std::array::from_fn(|lane| lane as f32 * scale + offset)
+        };
+        for time in [0.0, 255.0, 5100.0] {
+            let args = (
+                lane_values(20.0, 5.0),
+                lane_values(700.0, 100.0),
+                lane_values(3.0, 1.0),
+                lane_values(-5.0, 60.0),
+                time,
+                5100.0,
+            );
+            assert_eq!(
+                integrate_lanes_simd(args.0, args.1, args.2, args.3, args.4, args.5),
+                integrate_lanes_scalar(args.0, args.1, args.2, args.3, args.4, args.5)
+            );
+        }
+    }
+
+    #[test]
+    fn test_integrate_for_pxs() {
+        use crate::transcoder::source::video::{
+            integrate_for_px, integrate_for_pxs, VideoStateParams,
+        };
+        use adder_codec_core::codec::rate_controller::CrfParameters;
+        use adder_codec_core::ChromaSubsampling;
+
+        let parameters = CrfParameters {
+            c_thresh_baseline: 2,
+            c_thresh_max: 8,
+            c_increase_velocity: 2,
+            feature_c_radius: 0,
+        };
+        let coord = |x| Coord { x, y: 0, c: None };
+
+        for mode in [FramePerfect, Continuous] {
+            let params = VideoStateParams {
+                pixel_tree_mode: mode,
+                delta_t_max: 255 * 20,
+                ref_time: 255,
+                ..Default::default()
+            };
+
+            // More pixels than fit in one batch of lanes
+            let mut bulk: Vec<PixelArena> =
+                (0..13).map(|x| PixelArena::new(100.0, coord(x))).collect();
+            let mut single = bulk.clone();
+            let (mut bulk_events, mut single_events) = (Vec::new(), Vec::new());
+            let mut base_val = 0;
+            for interval in 0..60_u16 {
+                // Mostly steady intensities, with a few pixels brightening partway through
+                let intensities: Vec<f32> = (0..13_u16)
+                    .map(|x| {
+                        if x % 4 == 0 && interval > 30 {
+                            200.0
+                        } else {
+                            f32::from(x * 5 + 3)
+                        }
+                    })
+                    .collect();
+                integrate_for_pxs(
+                    &mut bulk,
+                    &intensities,
+                    1.0,
+                    255.0,
+                    &mut bulk_events,
+                    &params,
+                    &parameters,
+                    ChromaSubsampling::None,
+                );
+                for (px, intensity) in single.iter_mut().zip(&intensities) {
+                    integrate_for_px(
+                        px,
+                        &mut base_val,
+                        *intensity as u8,
+                        *intensity,
+                        255.0,
+                        &mut single_events,
+                        &params,
+                        &parameters,
+                    );
+                }
+            }
+
+            // Integrating in bulk gives the same events and pixel states as one pixel at a time
+            assert!(!single_events.is_empty());
+            assert_eq!(bulk_events, single_events);
+            for (bulk, single) in bulk.iter().zip(&single) {
+                assert_eq!(
+                    bincode::serialize(bulk).unwrap(),
+                    bincode::serialize(single).unwrap()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,107 @@
+// Integrates one input interval for every pixel which won't fire an event in it, exactly as
+// `PixelArena::integrate` would. Pixels which would fire are left untouched, for the CPU to
+// integrate them.
+
+struct Pixel {
+    integration: f32,
+    delta_t: f32,
+    running_t: f32,
+    intensity: f32,
+    d: u32,
+    flags: u32,
+    base_val: u32,
+    frame_val: u32,
+    c_thresh: u32,
+    contrast_band: u32,
+    c_increase_counter: u32,
+    c_thresh_max: u32,
+    d_floor: u32,
+}
+
+struct Params {
+    time_spanned: f32,
+    delta_t_max: f32,
+    counter_step: u32,
+    c_increase_velocity: u32,
+    pixel_count: u32,
+    row_width: u32,
+    d_max: u32,
+    d_zero_integration: u32,
+}
+
+const FLAG_ELIGIBLE: u32 = 1u;
+const FLAG_POPPED_DTM: u32 = 2u;
+const FLAG_QUIET: u32 = 4u;
+const FLAG_DTM_REACHED: u32 = 8u;
+
+@group(0) @binding(0) var<storage, read_write> pixels: array<Pixel>;
+@group(0) @binding(1) var<storage, read> d_shift: array<f32>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+fn d_from_intensity(intensity: f32) -> u32 {
+    if (intensity < 1.0) {
+        return params.d_zero_integration;
+    }
+    return min(firstLeadingBit(u32(intensity)), params.d_max);
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x + id.y * params.row_width;
+    if (index >= params.pixel_count) {
+        return;
+    }
+    var px = pixels[index];
+    if ((px.flags & FLAG_ELIGIBLE) == 0u) {
+        return;
+    }
+
+    // Leaving the contrast threshold band makes the pixel fire its best events
+    let low = select(px.base_val - px.contrast_band, 0u, px.contrast_band > px.base_val);
+    let high = min(px.base_val + px.contrast_band, 255u);
+    if (px.frame_val < low || px.frame_val > high) {
+        return;
+    }
+
+    // A node with nothing integrated takes its D from the new intensity
+    var d = px.d;
+    if (px.delta_t == 0.0 && px.integration == 0.0) {
+        if (px.intensity >= 4294967296.0) {
+            return;
+        }
+        d = max(d_from_intensity(px.intensity), px.d_floor);
+    }
+
+    // Reaching the threshold of the node's D fires an event
+    if (d >= arrayLength(&d_shift) || px.integration + px.intensity >= d_shift[d]) {
+        return;
+    }
+
+    // So does reaching D_MAX, or Δt_max if the pixel hasn't already fired for it
+    let delta_t = px.delta_t + params.time_spanned;
+    let dtm_reached = delta_t >= params.delta_t_max;
+    if (d == params.d_max || (dtm_reached && (px.flags & FLAG_POPPED_DTM) == 0u)) {
+        return;
+    }
+
+    px.d = d;
+    px.integration = px.integration + px.intensity;
+    px.delta_t = delta_t;
+    px.running_t = px.running_t + params.time_spanned;
+
+    // Raise the contrast threshold of a pixel which stays within it
+    if (px.c_thresh < px.c_thresh_max) {
+        if (px.c_increase_counter >= ((params.c_increase_velocity + 255u) & 255u)) {
+            px.c_thresh = min(px.c_thresh + 1u, 255u);
+            px.c_increase_counter = 0u;
+        } else {
+            px.c_increase_counter = min(px.c_increase_counter + params.counter_step, 255u);
+        }
+    }
+
+    px.flags = px.flags | FLAG_QUIET;
+    if (dtm_reached) {
+        px.flags = px.flags | FLAG_DTM_REACHED;
+    }
+    pixels[index] = px;
+}
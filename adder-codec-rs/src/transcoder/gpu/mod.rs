@@ -0,0 +1,327 @@
+use crate::transcoder::event_pixel_tree::PixelArena;
+use crate::transcoder::source::video::VideoStateParams;
+use adder_codec_core::codec::rate_controller::CrfParameters;
+use adder_codec_core::{ChromaSubsampling, DeltaT, D_MAX, D_SHIFT_F32, D_ZERO_INTEGRATION};
+use bytemuck::{Pod, Zeroable};
+use ndarray::{Array3, Zip};
+use thiserror::Error;
+use wgpu::util::DeviceExt;
+
+/// The number of invocations in each workgroup of the kernel, as declared in the shader
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The pixel may be integrated on the GPU, if it doesn't fire
+pub(crate) const FLAG_ELIGIBLE: u32 = 1;
+
+/// The pixel already fired an event for reaching Δt_max
+pub(crate) const FLAG_POPPED_DTM: u32 = 2;
+
+/// The pixel didn't fire, and was integrated on the GPU
+pub(crate) const FLAG_QUIET: u32 = 4;
+
+/// The pixel's top node reached Δt_max
+pub(crate) const FLAG_DTM_REACHED: u32 = 8;
+
+/// Errors that can occur when integrating on the GPU
+#[derive(Error, Debug)]
+pub enum GpuError {
+    /// There is no GPU adapter to run the kernel on
+    #[error("No GPU adapter is available")]
+    NoAdapter,
+
+    /// The GPU device could not be opened
+    #[error("Could not open the GPU device")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+
+    /// The integrated pixels could not be read back from the GPU
+    #[error("Could not read back from the GPU")]
+    Readback(#[from] wgpu::BufferAsyncError),
+
+    /// The GPU device was lost while integrating
+    #[error("The GPU device was lost")]
+    DeviceLost,
+}
+
+/// The integration state of a pixel and its input for one interval, as laid out for the kernel
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub(crate) struct GpuPixel {
+    pub(crate) integration: f32,
+    pub(crate) delta_t: f32,
+    pub(crate) running_t: f32,
+    pub(crate) intensity: f32,
+    pub(crate) d: u32,
+    pub(crate) flags: u32,
+    pub(crate) base_val: u32,
+    pub(crate) frame_val: u32,
+    pub(crate) c_thresh: u32,
+    pub(crate) contrast_band: u32,
+    pub(crate) c_increase_counter: u32,
+    pub(crate) c_thresh_max: u32,
+    pub(crate) d_floor: u32,
+}
+
+/// The parameters of the kernel which are shared by every pixel
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+struct GpuParams {
+    time_spanned: f32,
+    delta_t_max: f32,
+    counter_step: u32,
+    c_increase_velocity: u32,
+    pixel_count: u32,
+    row_width: u32,
+    d_max: u32,
+    d_zero_integration: u32,
+}
+
+/// The buffers holding a batch of pixels on the GPU, and the readback buffer they're copied to
+struct BatchBuffers {
+    capacity: usize,
+    pixels: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Integrates the pixels of a [`Video`] with a compute kernel over the whole frame.
+///
+/// Most pixels don't fire an event in a given input interval; they just add the intensity to
+/// their integration and raise their contrast threshold. The kernel does that for every such
+/// pixel at once. The pixels which would fire, or whose state is too involved for the kernel
+/// (e.g., with several nodes in their tree), are left for [`integrate_for_px`] on the CPU, so the
+/// result is the same as a CPU transcode.
+///
+/// [`Video`]: crate::transcoder::source::video::Video
+/// [`integrate_for_px`]: crate::transcoder::source::video::integrate_for_px
+pub(crate) struct GpuIntegrator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    d_shift: wgpu::Buffer,
+    params: wgpu::Buffer,
+
+    /// The most pixels which fit in one dispatch, given the device's limits
+    max_batch: usize,
+
+    /// The most workgroups which may be dispatched along one dimension
+    max_workgroups: u32,
+
+    batch: Option<BatchBuffers>,
+}
+
+impl GpuIntegrator {
+    /// Open the most powerful GPU adapter available and build the kernel on it
+    pub(crate) fn new() -> Result<Self, GpuError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .ok_or(GpuError::NoAdapter)?;
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("adder integration"),
+                required_features: wgpu::Features::empty(),
+                required_limits: limits.clone(),
+            },
+            None,
+        ))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("adder integration"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("integrate.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("adder integration"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        // The kernel may assume its inputs are finite, so the threshold of D_ZERO_INTEGRATION
+        // (2^128) is clamped to the largest finite one. No finite integration reaches either.
+        let d_shift: Vec<f32> = D_SHIFT_F32.iter().map(|t| t.min(f32::MAX)).collect();
+        let d_shift = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("adder D thresholds"),
+            contents: bytemuck::cast_slice(&d_shift),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("adder integration parameters"),
+            size: std::mem::size_of::<GpuParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let max_bytes =
+            u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size);
+        let max_batch = (max_bytes / std::mem::size_of::<GpuPixel>() as u64) as usize;
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            d_shift,
+            params,
+            max_batch,
+            max_workgroups: limits.max_compute_workgroups_per_dimension,
+            batch: None,
+        })
+    }
+
+    /// Integrate one input interval for every pixel which doesn't fire in it. Those pixels are
+    /// marked as integrated, and the rest are left as they were, for the CPU.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn integrate(
+        &mut self,
+        pixels: &mut Array3<PixelArena>,
+        intensities: &Array3<f32>,
+        frame_scale: f64,
+        time_spanned: f32,
+        params: &VideoStateParams,
+        parameters: &CrfParameters,
+        chroma_subsampling: ChromaSubsampling,
+    ) -> Result<(), GpuError> {
+        let mut packed = Zip::from(&*pixels)
+            .and(intensities)
+            .par_map_collect(|px, intensity| {
+                px.to_gpu(
+                    *intensity,
+                    (f64::from(*intensity) * frame_scale) as u8,
+                    parameters.c_thresh_max,
+                    params.noise_floor.as_ref(),
+                    chroma_subsampling.is_sampled(px.coord),
+                )
+            });
+
+        let gpu_params = GpuParams {
+            time_spanned,
+            delta_t_max: params.delta_t_max as f32,
+            counter_step: u32::from((time_spanned as DeltaT / params.ref_time) as u8),
+            c_increase_velocity: u32::from(parameters.c_increase_velocity),
+            pixel_count: 0,
+            row_width: 0,
+            d_max: u32::from(D_MAX),
+            d_zero_integration: u32::from(D_ZERO_INTEGRATION),
+        };
+        let slice = packed
+            .as_slice_memory_order_mut()
+            .expect("collected arrays are contiguous");
+        for batch in slice.chunks_mut(self.max_batch.max(1)) {
+            self.integrate_batch(batch, gpu_params)?;
+        }
+
+        Zip::from(pixels)
+            .and(&packed)
+            .par_for_each(|px, gpu_px| px.apply_gpu(gpu_px));
+        Ok(())
+    }
+
+    /// Run the kernel over one batch of pixels, and read the results back into it
+    fn integrate_batch(
+        &mut self,
+        batch: &mut [GpuPixel],
+        mut gpu_params: GpuParams,
+    ) -> Result<(), GpuError> {
+        let workgroups = (batch.len() as u32).div_ceil(WORKGROUP_SIZE);
+        let groups_x = workgroups.min(self.max_workgroups);
+        let groups_y = workgroups.div_ceil(groups_x);
+        gpu_params.pixel_count = batch.len() as u32;
+        gpu_params.row_width = groups_x * WORKGROUP_SIZE;
+
+        self.reserve_batch(batch.len());
+        let buffers = self.batch.as_ref().expect("batch buffers are reserved");
+        let size = std::mem::size_of_val(batch) as u64;
+        self.queue
+            .write_buffer(&buffers.pixels, 0, bytemuck::cast_slice(batch));
+        self.queue
+            .write_buffer(&self.params, 0, bytemuck::bytes_of(&gpu_params));
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("adder integration"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("adder integration"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &buffers.bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        encoder.copy_buffer_to_buffer(&buffers.pixels, 0, &buffers.readback, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let readback = buffers.readback.slice(..aligned_size(size));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|_| GpuError::DeviceLost)??;
+        {
+            let data = readback.get_mapped_range();
+            batch.copy_from_slice(bytemuck::cast_slice(&data[..size as usize]));
+        }
+        buffers.readback.unmap();
+        Ok(())
+    }
+
+    /// Make sure the batch buffers can hold `len` pixels, reallocating them if they're too small
+    fn reserve_batch(&mut self, len: usize) {
+        if self
+            .batch
+            .as_ref()
+            .map_or(true, |batch| batch.capacity < len)
+        {
+            let size = aligned_size((len * std::mem::size_of::<GpuPixel>()) as u64);
+            let pixels = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("adder pixels"),
+                size,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("adder pixels readback"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("adder integration"),
+                layout: &self.pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: pixels.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.d_shift.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.params.as_entire_binding(),
+                    },
+                ],
+            });
+            self.batch = Some(BatchBuffers {
+                capacity: len,
+                pixels,
+                readback,
+                bind_group,
+            });
+        }
+    }
+}
+
+/// Round a buffer size up to the alignment required for mapping it
+fn aligned_size(size: u64) -> u64 {
+    size.div_ceil(wgpu::MAP_ALIGNMENT) * wgpu::MAP_ALIGNMENT
+}
@@ -1,5 +1,9 @@
 mod d_controller;
 pub(crate) mod event_pixel_tree;
 
+/// Integrating the pixels of a transcode which don't fire an event on the GPU
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
 /// The tools for casting various source videos to ADΔER
 pub mod source;
@@ -0,0 +1,568 @@
+use crate::transcoder::source::prophesee::DvsEvent;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, ErrorKind, Read};
+
+/// The first bytes of every AEDAT file, followed by its version
+const AEDAT_MAGIC: &[u8] = b"#!AER-DAT";
+
+/// The line ending the header of an AEDAT 3.x file
+const AEDAT3_END_HEADER: &[u8] = b"#!END-HEADER";
+
+/// The AEDAT 3.x event type of polarity (DVS) events
+const AEDAT3_POLARITY_EVENT: i16 = 1;
+
+/// The size in bytes of an AEDAT 3.x packet header
+const AEDAT3_PACKET_HEADER_SIZE: usize = 28;
+
+/// The largest packet which is read, in bytes, so that a corrupt packet header can't make the
+/// reader allocate without bound. Real packets hold at most a few MB of events.
+const MAX_PACKET_SIZE: usize = 256 << 20;
+
+/// The size in bytes of an AEDAT4 packet header: the stream ID and the size of the packet
+const AEDAT4_PACKET_HEADER_SIZE: usize = 8;
+
+/// The FlatBuffers file identifier of AEDAT4 event (DVS) packets
+const AEDAT4_EVENT_PACKET: &[u8] = b"EVTS";
+
+/// The size in bytes of an AEDAT4 event: a 64-bit timestamp, 16-bit x and y, and the polarity
+const AEDAT4_EVENT_SIZE: usize = 16;
+
+/// The version of an AEDAT file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AedatVersion {
+    /// AEDAT 2.0: big-endian 32-bit addresses and timestamps, with a chip-specific address
+    /// layout
+    V2,
+    /// AEDAT 3.0/3.1: little-endian packets of typed events
+    V3,
+    /// AEDAT 4.0: packets of FlatBuffers-serialized data, as recorded by DV, which may be
+    /// compressed
+    V4,
+}
+
+/// How the packets of an AEDAT4 file are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aedat4Compression {
+    None,
+    Lz4,
+    Zstd,
+}
+
+/// A sensor whose recordings are found in AEDAT files, identified from the file header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AedatChip {
+    /// The original DVS128
+    Dvs128,
+    /// DAVIS240 (A, B, or C)
+    Davis240,
+    /// DAVIS346
+    Davis346,
+    /// DAVIS640
+    Davis640,
+}
+
+impl AedatChip {
+    /// The (width, height) of the sensor
+    pub fn size(self) -> (u16, u16) {
+        match self {
+            AedatChip::Dvs128 => (128, 128),
+            AedatChip::Davis240 => (240, 180),
+            AedatChip::Davis346 => (346, 260),
+            AedatChip::Davis640 => (640, 480),
+        }
+    }
+
+    /// Identify the sensor from the class or source names in an AEDAT header, such as
+    /// `# AEChip: eu.seebetter.ini.chips.davis.DAVIS240C` or `#Source 1: DVS128`
+    fn from_header(header: &str) -> Option<Self> {
+        let header = header.to_uppercase();
+        [
+            ("DVS128", AedatChip::Dvs128),
+            ("DAVIS240", AedatChip::Davis240),
+            ("DAVIS346", AedatChip::Davis346),
+            ("DAVIS640", AedatChip::Davis640),
+        ]
+        .into_iter()
+        .find(|(name, _)| header.contains(name))
+        .map(|(_, chip)| chip)
+    }
+}
+
+/// Reads the polarity events of an AEDAT file: a legacy AEDAT 2.0 or 3.x file, as recorded by
+/// jAER and cAER, or an AEDAT4 file, as recorded by DV. This is pure Rust, so DVS recordings can
+/// be transcoded (events only, without the deblurring of the `open-cv` feature's DAVIS source)
+/// on machines without OpenCV. Other events (APS frames, IMU samples, special events, triggers)
+/// are skipped.
+///
+/// Event coordinates are the raw sensor addresses, and timestamps are in microseconds from the
+/// first polarity event.
+pub struct AedatReader<R: BufRead> {
+    reader: R,
+    version: AedatVersion,
+    chip: Option<AedatChip>,
+
+    /// The (width, height) of the sensor, from the chip or the AEDAT4 stream info
+    size: Option<(u16, u16)>,
+
+    /// How the packets of an AEDAT4 file are compressed
+    compression: Aedat4Compression,
+
+    /// The number of bytes read from an AEDAT4 file, and where its packets end (at the file's
+    /// data table), if that's known
+    position: u64,
+    packets_end: Option<u64>,
+
+    /// The ID of the AEDAT4 stream of polarity events, if the stream info names it. Packets of
+    /// other streams (such as a second camera's events) are skipped unread.
+    event_stream: Option<i32>,
+
+    /// The decoded polarity events of the current AEDAT 3.x or 4 packet not yet returned
+    packet: VecDeque<DvsEvent>,
+
+    /// The timestamp of the first polarity event, which the returned timestamps count from
+    first_t: Option<u64>,
+}
+
+impl<R: BufRead> AedatReader<R> {
+    /// Parse the header of an AEDAT 2.0, 3.x, or 4.0 file
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut line = Vec::new();
+        let line_length = reader.read_until(b'\n', &mut line)?;
+        let Some(version) = line.strip_prefix(AEDAT_MAGIC) else {
+            return Err(invalid_data("not an AEDAT file"));
+        };
+        let version = match version.first() {
+            Some(b'2') => AedatVersion::V2,
+            Some(b'3') => AedatVersion::V3,
+            Some(b'4') => return Self::new_v4(reader, line_length as u64),
+            _ => {
+                return Err(invalid_data(format!(
+                    "unsupported AEDAT version {}",
+                    String::from_utf8_lossy(version).trim()
+                )))
+            }
+        };
+
+        // The AEDAT 2.0 header is the comment lines at the start of the file. The AEDAT 3.x
+        // header ends with an explicit line.
+        let mut header = String::new();
+        loop {
+            if version == AedatVersion::V2 && reader.fill_buf()?.first() != Some(&b'#') {
+                break;
+            }
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            if line.starts_with(AEDAT3_END_HEADER) {
+                break;
+            }
+            header.push_str(&String::from_utf8_lossy(&line));
+        }
+
+        let chip = AedatChip::from_header(&header);
+        if version == AedatVersion::V2 && chip.is_none() {
+            // The address layout of AEDAT 2.0 events depends on the chip
+            return Err(invalid_data("unknown sensor in AEDAT 2.0 header"));
+        }
+
+        Ok(Self {
+            reader,
+            version,
+            chip,
+            size: chip.map(AedatChip::size),
+            compression: Aedat4Compression::None,
+            position: 0,
+            packets_end: None,
+            event_stream: None,
+            packet: VecDeque::new(),
+            first_t: None,
+        })
+    }
+
+    /// Parse the IO header of an AEDAT4 file, which follows its version line of `position` bytes
+    fn new_v4(mut reader: R, mut position: u64) -> io::Result<Self> {
+        let mut size = [0; 4];
+        reader.read_exact(&mut size)?;
+        let size = u32::from_le_bytes(size) as usize;
+        if size > MAX_PACKET_SIZE {
+            return Err(invalid_data("AEDAT4 IO header too large"));
+        }
+        let mut header = vec![0; size];
+        reader.read_exact(&mut header)?;
+        position += 4 + header.len() as u64;
+
+        // The IO header is a FlatBuffers table of the compression type, the position of the
+        // file's data table (or -1), and the XML description of its streams
+        let header = Table::root(&header)?;
+        let compression = match header.scalar::<4>(0)?.map(i32::from_le_bytes) {
+            None | Some(0) => Aedat4Compression::None,
+            Some(1 | 2) => Aedat4Compression::Lz4,
+            Some(3 | 4) => Aedat4Compression::Zstd,
+            Some(compression) => {
+                return Err(invalid_data(format!(
+                    "unsupported AEDAT4 compression type {compression}"
+                )))
+            }
+        };
+        let packets_end = header
+            .scalar::<8>(1)?
+            .map(i64::from_le_bytes)
+            .and_then(|position| u64::try_from(position).ok());
+        let info = match header.vector(2, 1)? {
+            Some(info) => String::from_utf8_lossy(info).into_owned(),
+            None => String::new(),
+        };
+
+        // The sensor size is read from the event stream's own info, since other streams (of
+        // another camera, or of processed frames) may have other sizes
+        let event_stream = event_stream(&info);
+        let stream_info = event_stream.map_or(info.as_str(), |(_, stream_info)| stream_info);
+        let chip = AedatChip::from_header(&info);
+        let size = match (
+            info_attr(stream_info, "sizeX"),
+            info_attr(stream_info, "sizeY"),
+        ) {
+            (Some(width), Some(height)) => Some((width, height)),
+            _ => chip.map(AedatChip::size),
+        };
+        let event_stream = event_stream.map(|(id, _)| id);
+
+        Ok(Self {
+            reader,
+            version: AedatVersion::V4,
+            chip,
+            size,
+            compression,
+            position,
+            packets_end,
+            event_stream,
+            packet: VecDeque::new(),
+            first_t: None,
+        })
+    }
+
+    /// The version of the file
+    pub fn version(&self) -> AedatVersion {
+        self.version
+    }
+
+    /// The sensor named in the header, if it's a known one
+    pub fn chip(&self) -> Option<AedatChip> {
+        self.chip
+    }
+
+    /// The (width, height) of the sensor, if the header gives it (directly, or by naming a
+    /// known sensor)
+    pub fn size(&self) -> Option<(u16, u16)> {
+        self.size
+    }
+
+    /// Read the next polarity event. Returns an [`ErrorKind::UnexpectedEof`] error at the end of
+    /// the file.
+    pub fn next_event(&mut self) -> io::Result<DvsEvent> {
+        loop {
+            let event = match self.version {
+                AedatVersion::V2 => self.read_v2_event()?,
+                AedatVersion::V3 => match self.packet.pop_front() {
+                    Some(event) => Some(event),
+                    None => {
+                        self.read_v3_packet()?;
+                        continue;
+                    }
+                },
+                AedatVersion::V4 => match self.packet.pop_front() {
+                    Some(event) => Some(event),
+                    None => {
+                        self.read_v4_packet()?;
+                        continue;
+                    }
+                },
+            };
+            if let Some(event) = event {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Read an AEDAT 2.0 event, or `None` if it isn't a polarity event within the sensor
+    fn read_v2_event(&mut self) -> io::Result<Option<DvsEvent>> {
+        let mut buffer = [0; 8];
+        self.reader.read_exact(&mut buffer)?;
+        let address = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        let t = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]);
+
+        let chip = self.chip.unwrap_or(AedatChip::Dvs128);
+        let (x, y, p) = match chip {
+            AedatChip::Dvs128 => (
+                (address >> 1) & 0x7F,
+                (address >> 8) & 0x7F,
+                1 - (address & 1),
+            ),
+            _ => {
+                // The top bit flags APS and IMU samples
+                if address & 0x8000_0000 != 0 {
+                    return Ok(None);
+                }
+                (
+                    (address >> 12) & 0x3FF,
+                    (address >> 22) & 0x1FF,
+                    (address >> 11) & 1,
+                )
+            }
+        };
+        Ok(self.event(u64::from(t), x, y, p))
+    }
+
+    /// Read the next AEDAT 3.x packet, keeping its valid polarity events
+    fn read_v3_packet(&mut self) -> io::Result<()> {
+        let mut header = [0; AEDAT3_PACKET_HEADER_SIZE];
+        self.reader.read_exact(&mut header)?;
+        let field =
+            |i: usize| i32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let event_type = i16::from_le_bytes([header[0], header[1]]);
+        let event_size = field(4);
+        let ts_overflow = field(12);
+        let event_capacity = field(16);
+        let (Ok(event_size), Ok(event_capacity)) =
+            (usize::try_from(event_size), usize::try_from(event_capacity))
+        else {
+            return Err(invalid_data("invalid AEDAT 3 packet header"));
+        };
+        let size = event_size
+            .checked_mul(event_capacity)
+            .filter(|&size| size <= MAX_PACKET_SIZE)
+            .ok_or_else(|| invalid_data("AEDAT 3 packet too large"))?;
+
+        let mut events = vec![0; size];
+        self.reader.read_exact(&mut events)?;
+        if event_type != AEDAT3_POLARITY_EVENT || event_size < 8 {
+            return Ok(());
+        }
+
+        for event in events.chunks_exact(event_size) {
+            let data = u32::from_le_bytes([event[0], event[1], event[2], event[3]]);
+            let ts = i32::from_le_bytes([event[4], event[5], event[6], event[7]]);
+            // Bit 0 marks valid events
+            if data & 1 == 0 {
+                continue;
+            }
+            let t = (u64::from(ts_overflow as u32) << 31) | u64::from(ts as u32);
+            let (x, y, p) = ((data >> 17) & 0x7FFF, (data >> 2) & 0x7FFF, (data >> 1) & 1);
+            if let Some(event) = self.event(t, x, y, p) {
+                self.packet.push_back(event);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the next AEDAT4 packet, keeping its polarity events if it's an event packet
+    fn read_v4_packet(&mut self) -> io::Result<()> {
+        if self.packets_end.is_some_and(|end| self.position >= end) {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let mut header = [0; AEDAT4_PACKET_HEADER_SIZE];
+        self.reader.read_exact(&mut header)?;
+        let stream = i32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let size = i32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let Ok(size) = usize::try_from(size) else {
+            return Err(invalid_data("invalid AEDAT4 packet header"));
+        };
+        if size > MAX_PACKET_SIZE {
+            return Err(invalid_data("AEDAT4 packet too large"));
+        }
+        self.position += (AEDAT4_PACKET_HEADER_SIZE + size) as u64;
+
+        if self.event_stream.is_some_and(|id| id != stream) {
+            let skipped = io::copy(&mut (&mut self.reader).take(size as u64), &mut io::sink())?;
+            if skipped < size as u64 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            return Ok(());
+        }
+        let mut data = vec![0; size];
+        self.reader.read_exact(&mut data)?;
+
+        let data = match self.compression {
+            Aedat4Compression::None => data,
+            #[cfg(feature = "aedat-compression")]
+            Aedat4Compression::Lz4 => {
+                read_packet(lz4_flex::frame::FrameDecoder::new(data.as_slice()))?
+            }
+            #[cfg(feature = "aedat-compression")]
+            Aedat4Compression::Zstd => read_packet(
+                ruzstd::StreamingDecoder::new(data.as_slice())
+                    .map_err(|e| invalid_data(e.to_string()))?,
+            )?,
+            #[cfg(not(feature = "aedat-compression"))]
+            Aedat4Compression::Lz4 | Aedat4Compression::Zstd => {
+                return Err(invalid_data(
+                    "compressed AEDAT4 files require the `aedat-compression` feature",
+                ))
+            }
+        };
+
+        // Each packet is a size-prefixed FlatBuffer, identified by its type. Frames, IMU
+        // samples, and triggers are skipped.
+        let Some(data) = data.get(4..) else {
+            return Err(invalid_data("truncated AEDAT4 packet"));
+        };
+        if data.get(4..8) != Some(AEDAT4_EVENT_PACKET) {
+            return Ok(());
+        }
+        let Some(events) = Table::root(data)?.vector(0, AEDAT4_EVENT_SIZE)? else {
+            return Ok(());
+        };
+        for event in events.chunks_exact(AEDAT4_EVENT_SIZE) {
+            let t = i64::from_le_bytes(event[0..8].try_into().unwrap());
+            let x = i16::from_le_bytes([event[8], event[9]]);
+            let y = i16::from_le_bytes([event[10], event[11]]);
+            let (Ok(t), Ok(x), Ok(y)) = (u64::try_from(t), u32::try_from(x), u32::try_from(y))
+            else {
+                continue;
+            };
+            if let Some(event) = self.event(t, x, y, u32::from(event[12] != 0)) {
+                self.packet.push_back(event);
+            }
+        }
+        Ok(())
+    }
+
+    /// Make a polarity event with its timestamp counted from the first event, or `None` if it
+    /// lies outside the sensor
+    fn event(&mut self, t: u64, x: u32, y: u32, p: u32) -> Option<DvsEvent> {
+        if let Some((width, height)) = self.size {
+            if x >= u32::from(width) || y >= u32::from(height) {
+                return None;
+            }
+        }
+        let first_t = *self.first_t.get_or_insert(t);
+        let t = u32::try_from(t.saturating_sub(first_t)).unwrap_or(u32::MAX);
+        Some(DvsEvent::new(t, x as u16, y as u16, p as u8))
+    }
+}
+
+/// Returns true if the reader is at the start of an AEDAT file
+pub fn is_aedat<R: BufRead>(reader: &mut R) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(AEDAT_MAGIC))
+}
+
+/// A table of a FlatBuffer, read without generated code, as the AEDAT4 schemas are small
+struct Table<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Table<'a> {
+    /// The root table of a FlatBuffer (without its size prefix)
+    fn root(buffer: &'a [u8]) -> io::Result<Self> {
+        let position = read_u32(buffer, 0)? as usize;
+        Ok(Self { buffer, position })
+    }
+
+    /// The position in the buffer of the field with the given index, or `None` if it's absent
+    /// (i.e., it has its default value)
+    fn field(&self, index: usize) -> io::Result<Option<usize>> {
+        let vtable = self.position as i64 - i64::from(read_u32(self.buffer, self.position)? as i32);
+        let vtable = usize::try_from(vtable).map_err(|_| invalid_data("invalid FlatBuffer"))?;
+        let vtable_size = read_u16(self.buffer, vtable)? as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_size {
+            return Ok(None);
+        }
+        Ok(match read_u16(self.buffer, vtable + entry)? {
+            0 => None,
+            offset => Some(self.position + offset as usize),
+        })
+    }
+
+    /// The little-endian bytes of a scalar field, or `None` if it's absent
+    fn scalar<const N: usize>(&self, index: usize) -> io::Result<Option<[u8; N]>> {
+        self.field(index)?
+            .map(|position| {
+                self.buffer
+                    .get(position..position + N)
+                    .map(|bytes| bytes.try_into().unwrap())
+                    .ok_or_else(|| invalid_data("truncated FlatBuffer"))
+            })
+            .transpose()
+    }
+
+    /// The bytes of a vector (or string) field with elements of the given size, or `None` if
+    /// it's absent
+    fn vector(&self, index: usize, element_size: usize) -> io::Result<Option<&'a [u8]>> {
+        let Some(position) = self.field(index)? else {
+            return Ok(None);
+        };
+        let start = position + read_u32(self.buffer, position)? as usize;
+        let length = read_u32(self.buffer, start)? as usize;
+        self.buffer
+            .get(start + 4..start + 4 + length * element_size)
+            .map(Some)
+            .ok_or_else(|| invalid_data("truncated FlatBuffer"))
+    }
+}
+
+fn read_u16(buffer: &[u8], position: usize) -> io::Result<u16> {
+    buffer
+        .get(position..position + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid_data("truncated FlatBuffer"))
+}
+
+fn read_u32(buffer: &[u8], position: usize) -> io::Result<u32> {
+    buffer
+        .get(position..position + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid_data("truncated FlatBuffer"))
+}
+
+/// Decompress a packet, failing instead of allocating more than `MAX_PACKET_SIZE` bytes
+#[cfg(feature = "aedat-compression")]
+fn read_packet(decoder: impl Read) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    decoder
+        .take(MAX_PACKET_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_PACKET_SIZE {
+        return Err(invalid_data("decompressed AEDAT4 packet too large"));
+    }
+    Ok(data)
+}
+
+/// The ID of the first polarity event stream in the XML stream info of an AEDAT4 header, and
+/// the part of the info describing it. Each stream is a node named by its ID, such as
+/// `<node name="0"><attr key="typeIdentifier" type="string">EVTS</attr>...`.
+fn event_stream(info: &str) -> Option<(i32, &str)> {
+    const NODE: &str = "<node name=\"";
+    let streams: Vec<(i32, usize)> = info
+        .match_indices(NODE)
+        .filter_map(|(start, _)| {
+            let name = &info[start + NODE.len()..];
+            Some((name[..name.find('"')?].parse().ok()?, start))
+        })
+        .collect();
+    streams.iter().enumerate().find_map(|(i, &(id, start))| {
+        let end = streams.get(i + 1).map_or(info.len(), |&(_, end)| end);
+        let stream_info = &info[start..end];
+        (info_value(stream_info, "typeIdentifier")? == "EVTS").then_some((id, stream_info))
+    })
+}
+
+/// The value of the first attribute with the given key in the XML stream info of an AEDAT4
+/// header, such as `<attr key="sizeX" type="int">346</attr>`
+fn info_value<'a>(info: &'a str, key: &str) -> Option<&'a str> {
+    let attr = &info[info.find(&format!("key=\"{key}\""))?..];
+    let value = &attr[attr.find('>')? + 1..];
+    Some(value[..value.find('<')?].trim())
+}
+
+/// The value of the first integer attribute with the given key in the XML stream info of an
+/// AEDAT4 header
+fn info_attr(info: &str, key: &str) -> Option<u16> {
+    info_value(info, key)?.parse().ok()
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,138 @@
+use crate::transcoder::event_pixel_tree::PixelArena;
+use crate::transcoder::source::denoise::TemporalDenoiser;
+use crate::transcoder::source::quality::QualityController;
+use crate::transcoder::source::stabilization::Stabilizer;
+use crate::transcoder::source::video::SourceError;
+use adder_codec_core::codec::checkpoint::EncoderCheckpoint;
+use adder_codec_core::codec::CodecError;
+use adder_codec_core::{AbsoluteT, DeltaT, PixelAddress, PixelMultiMode};
+use bincode::{DefaultOptions, Options};
+use ndarray::Array3;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The version of the layout of a saved [`VideoCheckpoint`]. It's bumped whenever a field is
+/// added or changed, so that a checkpoint saved by another version is rejected rather than
+/// misread.
+pub(crate) const CHECKPOINT_VERSION: u8 = 1;
+
+/// The state of a [`Video`] transcode between two input intervals, as taken by
+/// [`Video::save_state`]. Saved to disk, it lets an interrupted transcode continue exactly where
+/// it left off with [`Video::restore_state`], rather than starting over from the first frame.
+///
+/// Every pixel's integration state and running timestamp is kept, along with the position of the
+/// encoder in its output (if it's writing one) and of the source in its input. So is the history
+/// of the temporal denoiser and of the quality controller, and that of a framed source's scene
+/// cut detection, adaptive reference interval, and stabilizer, with their settings.
+///
+/// [`Video`]: crate::transcoder::source::video::Video
+/// [`Video::save_state`]: crate::transcoder::source::video::Video::save_state
+/// [`Video::restore_state`]: crate::transcoder::source::video::Video::restore_state
+#[derive(Serialize, Deserialize)]
+pub struct VideoCheckpoint {
+    /// The version of the checkpoint's layout. It comes first, so it can be checked before the
+    /// rest is read.
+    pub(crate) version: u8,
+
+    /// The (width, height, channels) of the plane
+    pub(crate) plane: (PixelAddress, PixelAddress, u8),
+
+    /// The ticks per second, reference interval, and Δt_max of the transcode
+    pub(crate) time_parameters: (DeltaT, DeltaT, DeltaT),
+
+    /// Whether each pixel fires every event it accumulates, or collapses them
+    pub(crate) pixel_multi_mode: PixelMultiMode,
+
+    /// The integration state of every pixel
+    pub(crate) event_pixel_trees: Array3<PixelArena>,
+
+    /// The number of input intervals processed so far
+    pub(crate) in_interval_count: u32,
+
+    /// The instantaneous frame reconstructed from the pixels
+    pub(crate) running_intensities: Array3<u8>,
+
+    /// The timestamp of each pixel's last event, for the event statistics
+    pub(crate) event_stats_last_t: Array3<AbsoluteT>,
+
+    /// Whether the pixels were reset, and must set their D values anew from the next frame
+    pub(crate) pixels_reset: bool,
+
+    /// The state of the encoder, if it's writing an output
+    pub(crate) encoder: Option<EncoderCheckpoint>,
+
+    /// The temporal denoiser and its filtered frame, if the input is denoised
+    pub(crate) denoiser: Option<TemporalDenoiser>,
+
+    /// The quality controller and the CRF level it reached, if a quality target is held
+    pub(crate) quality_controller: Option<QualityController>,
+
+    /// The state of a framed source, which sets it when it saves its state
+    pub(crate) framed: Option<FramedCheckpoint>,
+
+    /// The position of the source in its input, such as the index of the next frame to read.
+    /// Sources which can seek set it when they save their state, and seek back to it when they
+    /// restore it.
+    pub source_position: u64,
+}
+
+impl VideoCheckpoint {
+    /// The state of the encoder, if it was writing an output
+    pub fn encoder(&self) -> Option<&EncoderCheckpoint> {
+        self.encoder.as_ref()
+    }
+
+    /// Write the checkpoint, e.g., to a file alongside the output
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), SourceError> {
+        Ok(bincode_options().serialize_into(writer, self)?)
+    }
+
+    /// Read a checkpoint written by [`VideoCheckpoint::write_to`]. A checkpoint written by a
+    /// version of the transcoder with a different layout is rejected.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, SourceError> {
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        check_version(version[0])?;
+        Ok(bincode_options().deserialize_from((&version[..]).chain(reader))?)
+    }
+
+    /// Reject a checkpoint saved with another layout
+    pub(crate) fn check_version(&self) -> Result<(), SourceError> {
+        check_version(self.version)
+    }
+}
+
+/// The state of a [`Framed`] source, beyond its position in the input
+///
+/// [`Framed`]: crate::transcoder::source::framed::Framed
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FramedCheckpoint {
+    /// The previous input frame, which the next one is compared against
+    pub(crate) input_frame: Array3<u8>,
+
+    /// The number of scene cuts detected so far
+    pub(crate) scene_cuts: u32,
+
+    /// The number of input frames to integrate at once next
+    pub(crate) ref_multiple: u32,
+
+    /// The stabilizer and the camera path it tracked, if the input is stabilized
+    pub(crate) stabilizer: Option<Stabilizer>,
+}
+
+fn check_version(version: u8) -> Result<(), SourceError> {
+    if version != CHECKPOINT_VERSION {
+        return Err(CodecError::UnsupportedCheckpointVersion {
+            expected: CHECKPOINT_VERSION,
+            found: version,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn bincode_options() -> impl Options {
+    DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+}
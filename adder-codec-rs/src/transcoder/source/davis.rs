@@ -1,7 +1,8 @@
+use crate::transcoder::source::undistort::LensModel;
 use crate::transcoder::source::video::FramedViewMode::SAE;
 use crate::transcoder::source::video::SourceError::BufferEmpty;
 use crate::transcoder::source::video::{
-    integrate_for_px, Source, SourceError, Video, VideoBuilder,
+    integrate_for_px, ContrastMask, Crop, Orientation, Source, SourceError, Video, VideoBuilder,
 };
 use adder_codec_core::Mode::{Continuous, FramePerfect};
 use adder_codec_core::{DeltaT, PixelMultiMode};
@@ -23,6 +24,7 @@ use std::error::Error;
 use std::io::Write;
 use std::mem::swap;
 use std::thread;
+use std::time::Duration;
 
 use adder_codec_core::codec::{CodecError, EncoderOptions, EncoderType};
 use adder_codec_core::{Event, PlaneSize, SourceCamera, SourceType, TimeMode};
@@ -100,14 +102,21 @@ pub struct Davis<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     time_change: f64,
     num_dvs_events: usize,
     ref_time_divisor: f64,
-}
 
-unsafe impl<W: Write + std::marker::Send + std::marker::Sync> Sync for Davis<W> {}
+    /// The start of the window to transcode, in microseconds from the first frame
+    window_start_us: i64,
+
+    /// The length of the window to transcode in microseconds, if it's limited
+    window_duration_us: Option<i64>,
+
+    /// The timestamp of the first frame from the reconstructor
+    first_frame_timestamp: Option<i64>,
+}
 
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
     /// Create a new `Davis` transcoder
     pub fn new(reconstructor: Reconstructor, mode: TranscoderMode) -> Result<Self, Box<dyn Error>> {
-        let plane = PlaneSize::new(reconstructor.width, reconstructor.height, 1)?;
+        let plane = PlaneSize::new(reconstructor.width.into(), reconstructor.height.into(), 1)?;
 
         let video = Video::new(
             plane,
@@ -128,7 +137,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
         let timestamps = vec![0_i64; video.state.plane.volume()];
 
         let dvs_last_timestamps: Array3<i64> = Array3::from_shape_vec(
-            (plane.h().into(), plane.w().into(), plane.c().into()),
+            (plane.h_usize(), plane.w_usize(), plane.c_usize()),
             timestamps,
         )?;
 
@@ -167,6 +176,9 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
             time_change: 0.0,
             num_dvs_events: 0,
             ref_time_divisor: 1.0,
+            window_start_us: 0,
+            window_duration_us: None,
+            first_frame_timestamp: None,
         };
 
         Ok(davis_source)
@@ -186,6 +198,20 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
         self
     }
 
+    /// Declare the wall-clock time of tick 0, in UTC nanoseconds since the Unix epoch. See
+    /// [`Video::epoch`].
+    pub fn epoch(mut self, epoch_ns: Option<u64>) -> Self {
+        self.video = self.video.epoch(epoch_ns);
+        self
+    }
+
+    /// Record corrections for the drift of the camera's clock relative to the host's. See
+    /// [`Video::drift_correction`].
+    pub fn drift_correction(mut self, enabled: bool) -> Self {
+        self.video = self.video.drift_correction(enabled);
+        self
+    }
+
     // #[allow(clippy::cast_precision_loss)]
     // fn control_latency(&mut self, opt_timestamp: Option<Instant>) {
     //     if self.optimize_adder_controller {
@@ -213,6 +239,16 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Davis<W> {
     //     }
     // }
 
+    /// Rectify the deblurred frames and the events, correcting the distortion of the given lens,
+    /// so that the stream lines up with an ideal pinhole camera. The EDI reconstruction still
+    /// works in sensor coordinates; each event is moved to the undistorted position of its pixel
+    /// only when it's integrated, and dropped if that lands outside the sensor. `None` removes
+    /// the correction.
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        self.video = self.video.lens_undistortion(model)?;
+        Ok(self)
+    }
+
     /// Get an immutable reference to the [`Reconstructor`]
     pub fn get_reconstructor(&self) -> &Option<Reconstructor> {
         &self.reconstructor
@@ -254,8 +290,23 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Integration<W>
 
         let mut chunk_idx;
         for dvs_event in dvs_events {
+            // Move the event to the undistorted position of its pixel, as the frames are
+            let dvs_event = match &video.state.undistortion {
+                Some(undistortion) => {
+                    let (Ok(x), Ok(y)) =
+                        (u16::try_from(dvs_event.x()), u16::try_from(dvs_event.y()))
+                    else {
+                        continue;
+                    };
+                    let Some((x, y)) = undistortion.undistort_coord(x, y) else {
+                        continue;
+                    };
+                    DvsEvent::new(dvs_event.t(), x as i16, y as i16, dvs_event.on())
+                }
+                None => *dvs_event,
+            };
             chunk_idx = dvs_event.y() as usize / (video.state.plane.h_usize() / 4);
-            dvs_chunks[chunk_idx].push(*dvs_event);
+            dvs_chunks[chunk_idx].push(dvs_event);
         }
 
         let chunk_rows = video.state.chunk_rows;
@@ -449,11 +500,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Integration<W>
                 *running = *val;
             });
 
-        for events in &big_buffer {
-            for e1 in events.iter() {
-                video.encoder.ingest_event(*e1)?;
-            }
-        }
+        video.encode_events(&big_buffer)?;
 
         if let Err(e) = video.handle_features(&big_buffer) {
             return Err(CodecError::VisionError(e.to_string()));
@@ -585,11 +632,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Integration<W>
                 *running = *val;
             });
 
-        for events in &big_buffer {
-            for e1 in events.iter() {
-                video.encoder.ingest_event(*e1)?;
-            }
-        }
+        video.encode_events(&big_buffer)?;
 
         video.handle_features(&big_buffer)?;
 
@@ -619,6 +662,20 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
             return Err(SourceError::NoData);
         }
 
+        // Drop the frames before the start of the window, and end the input at its end. Only the
+        // frames in the raw modes carry timestamps to compare against.
+        if let Some(Some((_, _, Some((_, _, _, img_start_ts, _)), _))) = &self.cached_mat_opt {
+            let ts = img_start_ts - *self.first_frame_timestamp.get_or_insert(*img_start_ts);
+            if ts < self.window_start_us {
+                self.cached_mat_opt = None;
+            } else if self
+                .window_duration_us
+                .is_some_and(|duration| ts >= self.window_start_us.saturating_add(duration))
+            {
+                self.cached_mat_opt = Some(None);
+            }
+        }
+
         let mut reconstructor_holder = None;
         swap(&mut self.reconstructor, &mut reconstructor_holder);
         let mut thread_pool_holder = None;
@@ -664,7 +721,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
                         })
                         .collect();
 
-                    self.video.encoder.ingest_events_events(&big_buffer)?;
+                    self.video.encode_events(&big_buffer)?;
 
                     return Err(SourceError::NoData);
                 }
@@ -942,6 +999,11 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for D
     }
 }
 
+/// Convert a length of time to microseconds, saturating at the largest timestamp
+fn duration_to_us(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W> for Davis<W> {
     fn crf(mut self, crf: u8) -> Self {
         self.video.update_crf(crf);
@@ -971,6 +1033,31 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         self
     }
 
+    /// Events can't be cropped, so a crop is rejected
+    fn crop(self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        match crop {
+            Some(_) => Err(SourceError::BadParams(
+                "Event sources can't be cropped".to_string(),
+            )),
+            None => Ok(self),
+        }
+    }
+
+    /// Events can't be turned, so an orientation is rejected
+    fn orientation(self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        match orientation {
+            Some(orientation) if !orientation.is_identity() => Err(SourceError::BadParams(
+                "Event sources can't be flipped or rotated".to_string(),
+            )),
+            _ => Ok(self),
+        }
+    }
+
+    fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.video = self.video.contrast_mask(mask)?;
+        Ok(self)
+    }
+
     fn time_parameters(
         mut self,
         tps: DeltaT,
@@ -1012,6 +1099,19 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         self
     }
 
+    /// Drop the frames (and their events) before the start time, measured from the timestamp of
+    /// the first frame. In [`TranscoderMode::Framed`], the deblurred frames carry no timestamps,
+    /// so the whole source is transcoded.
+    fn start_time(mut self, start_time: Duration) -> Result<Self, SourceError> {
+        self.window_start_us = duration_to_us(start_time);
+        Ok(self)
+    }
+
+    fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.window_duration_us = duration.map(duration_to_us);
+        self
+    }
+
     #[cfg(feature = "feature-logging")]
     fn log_path(self, _name: String) -> Self {
         todo!()
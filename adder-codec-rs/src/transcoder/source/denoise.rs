@@ -0,0 +1,99 @@
+use crate::transcoder::source::video::SourceError;
+use ndarray::{Array3, Zip};
+use serde::{Deserialize, Serialize};
+
+/// How the input frames are denoised over time before integration. Each pixel is blended with
+/// its filtered value from the frames before it, as a recursive (IIR) low-pass filter. The blend
+/// is bilateral: the further the new intensity is from the history, the less of the history is
+/// kept, so that small fluctuations are smoothed out but real changes (e.g., motion) come
+/// through at once.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TemporalFilter {
+    /// How much of the history a pixel keeps when its intensity is unchanged, in [0, 1). At 0,
+    /// nothing is filtered.
+    pub strength: f32,
+
+    /// The scale of the changes which are treated as noise, on the 8-bit scale of the contrast
+    /// thresholds. A change this large keeps about 60% as much of the history as no change, and
+    /// a change three times as large keeps almost none of it.
+    pub edge: f32,
+}
+
+impl Default for TemporalFilter {
+    fn default() -> Self {
+        Self {
+            strength: 0.6,
+            edge: 8.0,
+        }
+    }
+}
+
+impl TemporalFilter {
+    /// Check that the filter's parameters are in range
+    pub(crate) fn validate(&self) -> Result<(), SourceError> {
+        if !(0.0..1.0).contains(&self.strength) || !(self.edge.is_finite() && self.edge > 0.0) {
+            return Err(SourceError::BadParams(format!(
+                "temporal filter strength must be in [0, 1) and its edge must be positive, not \
+                 {} and {}",
+                self.strength, self.edge
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Filters the frames of a source over time with a [`TemporalFilter`], holding the filtered
+/// frame from the last interval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TemporalDenoiser {
+    options: TemporalFilter,
+
+    /// The filtered intensities of the previous frame
+    history: Option<Array3<f32>>,
+}
+
+impl TemporalDenoiser {
+    pub(crate) fn new(options: TemporalFilter) -> Self {
+        Self {
+            options,
+            history: None,
+        }
+    }
+
+    pub(crate) fn options(&self) -> TemporalFilter {
+        self.options
+    }
+
+    /// Change the filter's parameters, keeping its history
+    pub(crate) fn set_options(&mut self, options: TemporalFilter) {
+        self.options = options;
+    }
+
+    /// Filter the next frame of intensities, which are brought to the 8-bit scale of the filter's
+    /// edge by multiplying by `frame_scale`. The first frame (or the first after a reset or a
+    /// change of resolution) passes through as it is.
+    pub(crate) fn filter(&mut self, mut frame: Array3<f32>, frame_scale: f32) -> Array3<f32> {
+        match &mut self.history {
+            Some(history) if history.dim() == frame.dim() => {
+                let TemporalFilter { strength, edge } = self.options;
+                let denominator = 2.0 * edge * edge;
+                Zip::from(&mut frame)
+                    .and(history)
+                    .par_for_each(|intensity, history| {
+                        let diff = (*intensity - *history) * frame_scale;
+                        let keep = strength * (-diff * diff / denominator).exp();
+                        *intensity = keep * *history + (1.0 - keep) * *intensity;
+                        *history = *intensity;
+                    });
+            }
+            _ => self.history = Some(frame.clone()),
+        }
+        frame
+    }
+
+    /// Forget the history, e.g., at a scene cut, so that the next frame isn't blended with the
+    /// ones before it
+    pub(crate) fn reset(&mut self) {
+        self.history = None;
+    }
+}
@@ -1,33 +1,221 @@
-use crate::transcoder::source::video::SourceError;
+use crate::transcoder::source::checkpoint::{FramedCheckpoint, VideoCheckpoint};
+use crate::transcoder::source::denoise::TemporalFilter;
+use crate::transcoder::source::high_bit_depth::HighBitDepthDecoder;
+use crate::transcoder::source::noise::NoiseModel;
+use crate::transcoder::source::quality::QualityTarget;
+use crate::transcoder::source::stabilization::{self, Stabilization, Stabilizer};
+use crate::transcoder::source::undistort::LensModel;
 use crate::transcoder::source::video::Video;
+use crate::transcoder::source::video::{
+    ContrastMask, Crop, IntensityLut, Orientation, SourceError,
+};
 use crate::transcoder::source::video::{Source, VideoBuilder};
 use adder_codec_core::Mode::FramePerfect;
-use adder_codec_core::{DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode};
+use adder_codec_core::{
+    is_framed, ChromaSubsampling, DeltaT, Event, PixelAddress, PixelMultiMode, PlaneSize,
+    SourceCamera, SourceType, TimeMode,
+};
 
 use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::clock::RefIntervalChange;
+use adder_codec_core::codec::stabilization::StabilizationTransform;
 use adder_codec_core::codec::{EncoderOptions, EncoderType};
 
-use crate::utils::cv::handle_color;
 #[cfg(feature = "feature-logging")]
 use crate::utils::cv::{calculate_quality_metrics, QualityMetrics};
+use crate::utils::cv::{handle_color, luma_intensities};
 
+use ndarray::Array3;
 use rayon::ThreadPool;
-use std::io::Write;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "feature-logging")]
 use chrono::Local;
 use tokio::runtime::Runtime;
+use url::Url;
 use video_rs_adder_dep::{self, Decoder, Frame, Locator, Options, Resize};
 
+/// The URL schemes of the network streams which [`Framed::new_stream`] can open
+const STREAM_SCHEMES: [&str; 5] = ["rtsp", "rtsps", "rtmp", "http", "https"];
+
+/// The decoder of a framed video, chosen by its bit depth
+enum FramedInput {
+    /// 8-bit video, decoded to RGB24 by video-rs
+    Rgb8(Decoder),
+
+    /// 10-bit to 16-bit video, decoded at full precision
+    HighBitDepth(HighBitDepthDecoder),
+
+    /// A live network stream, decoded to RGB24 by video-rs
+    Live(Decoder, LiveStream),
+}
+
+/// How a [`Framed`] source reading a live network stream (e.g., from an IP camera) recovers from
+/// dropouts and keeps up with the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiveStreamOptions {
+    /// The number of consecutive attempts to reconnect after the stream drops, before the source
+    /// ends
+    pub max_reconnects: u32,
+
+    /// The delay before each attempt to reconnect
+    pub reconnect_delay: Duration,
+
+    /// How far the transcoder may fall behind the camera before frames are skipped to catch up.
+    /// The time of the skipped frames is integrated into the next frame, so the timeline of the
+    /// ADΔER stream still matches the camera's. `None` never skips frames.
+    pub max_latency: Option<Duration>,
+}
+
+impl Default for LiveStreamOptions {
+    fn default() -> Self {
+        Self {
+            max_reconnects: 5,
+            reconnect_delay: Duration::from_secs(1),
+            max_latency: Some(Duration::from_millis(500)),
+        }
+    }
+}
+
+/// How a [`Framed`] source adapts its reference interval to the motion in the scene. While the
+/// scene is static, several input frames are averaged and integrated at once, spanning a multiple
+/// of `ref_time`, so that noise between the frames fires fewer events. When motion picks up, the
+/// source drops back to integrating each frame on its own, to keep the temporal fidelity.
+///
+/// The motion is measured as the mean absolute difference between successive integrated frames,
+/// as a fraction of full scale, per input frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveRefInterval {
+    /// The most input frames integrated at once. It's also limited by Δt_max.
+    pub max_multiple: u32,
+
+    /// The motion below which the scene is static, so the number of frames integrated at once is
+    /// doubled
+    pub static_threshold: f32,
+
+    /// The motion above which the scene is moving quickly, so each frame is integrated on its own
+    pub motion_threshold: f32,
+}
+
+impl Default for AdaptiveRefInterval {
+    fn default() -> Self {
+        Self {
+            max_multiple: 8,
+            static_threshold: 0.002,
+            motion_threshold: 0.01,
+        }
+    }
+}
+
+impl AdaptiveRefInterval {
+    /// The number of input frames to integrate at once next, after `multiple` frames were
+    /// integrated with the given `motion`
+    fn next_multiple(&self, multiple: u32, motion: f32) -> u32 {
+        if motion > self.motion_threshold {
+            1
+        } else if motion < self.static_threshold {
+            multiple.saturating_mul(2).min(self.max_multiple)
+        } else {
+            multiple
+        }
+    }
+}
+
+/// The connection to a live network stream
+struct LiveStream {
+    url: Url,
+    options: LiveStreamOptions,
+
+    /// The (width, height) of the decoded frames, which must stay the same across reconnections
+    size_out: (u32, u32),
+
+    /// The wall-clock time at which the first frame since connecting was decoded, and that
+    /// frame's timestamp in seconds
+    clock: Option<(Instant, f64)>,
+}
+
+impl LiveStream {
+    /// Open the stream with FFmpeg's low-latency options, resizing its frames if given a `resize`
+    fn connect(&self, resize: Option<Resize>) -> Result<Decoder, SourceError> {
+        let options = Options::from(HashMap::from([
+            ("rtsp_transport".to_string(), "tcp".to_string()),
+            ("fflags".to_string(), "nobuffer".to_string()),
+            ("flags".to_string(), "low_delay".to_string()),
+            // Give up on a stalled connection after 5 seconds, so that it can be reestablished
+            ("rw_timeout".to_string(), "5000000".to_string()),
+        ]));
+        let source = Locator::Url(self.url.clone());
+        Ok(match resize {
+            Some(resize) => Decoder::new_with_options_and_resize(&source, &options, resize)?,
+            None => Decoder::new_with_options(&source, &options)?,
+        })
+    }
+
+    /// Reconnect after the stream drops. Returns [`SourceError::BufferEmpty`], ending the source,
+    /// if every attempt fails.
+    fn reconnect(&mut self) -> Result<Decoder, SourceError> {
+        self.clock = None;
+        let (width, height) = self.size_out;
+        for attempt in 1..=self.options.max_reconnects {
+            std::thread::sleep(self.options.reconnect_delay);
+            match self.connect(Some(Resize::Exact(width, height))) {
+                Ok(cap) => return Ok(cap),
+                Err(e) => eprintln!(
+                    "Reconnection {attempt}/{} to {} failed: {e}",
+                    self.options.max_reconnects, self.url
+                ),
+            }
+        }
+        Err(SourceError::BufferEmpty)
+    }
+
+    /// The timestamp (in seconds) before which frames are too far behind the camera, and should
+    /// be skipped
+    fn skip_before(&self) -> Option<f64> {
+        let max_latency = self.options.max_latency?;
+        let (start, start_timestamp) = self.clock?;
+        let elapsed = start.elapsed().saturating_sub(max_latency);
+        Some(start_timestamp + elapsed.as_secs_f64())
+    }
+}
+
+/// Whether the input names a network stream (e.g., `rtsp://...`) for [`Framed::new_stream`],
+/// rather than a file
+pub fn is_stream_url(input: &str) -> bool {
+    Url::parse(input).is_ok_and(|url| STREAM_SCHEMES.contains(&url.scheme()))
+}
+
 /// Attributes of a framed video -> ADΔER transcode
+///
+/// Video with more than 8 bits per sample (e.g., 10-bit HEVC) is transcoded as
+/// [`SourceCamera::FramedU16`], so it must be written out with the matching
+/// [`Framed::source_camera`].
+///
+/// Live network streams are opened with [`Framed::new_stream`], and transcoded continuously
+/// until the connection is lost for good.
 pub struct Framed<W: Write + 'static + std::marker::Send + std::marker::Sync> {
-    cap: Decoder,
+    cap: FramedInput,
+
+    /// The previous input frame, as 8-bit intensities for display
     pub(crate) input_frame: Frame,
 
     /// Index of the first frame to be read from the input video
     pub frame_idx_start: u32,
 
+    /// Index of the next frame to be read from the input video
+    frame_idx: u32,
+
+    /// Index of the first frame of the window set by [`VideoBuilder::start_time`]
+    window_frame_start: u32,
+
+    /// The number of input frames to transcode from the start of the window, if it's limited by
+    /// [`VideoBuilder::duration`]
+    window_frame_count: Option<u32>,
+
     /// FPS of the input video. Set automatically by `Framed::new()`
     pub source_fps: f32,
 
@@ -37,9 +225,30 @@ pub struct Framed<W: Write + 'static + std::marker::Send + std::marker::Sync> {
     /// Whether the input video is color
     color_input: bool,
 
+    /// Number of input frames averaged into each integrated frame, for time-lapse transcoding
+    time_lapse: u32,
+
+    /// The difference between successive frames above which a scene cut is detected, if cuts are
+    /// detected. See [`Framed::scene_cut_threshold`].
+    scene_cut_threshold: Option<f32>,
+
+    /// The number of scene cuts detected so far
+    scene_cuts: u32,
+
+    /// How the reference interval adapts to motion, if it does. See
+    /// [`Framed::adaptive_ref_interval`].
+    adaptive_ref_interval: Option<AdaptiveRefInterval>,
+
+    /// The number of input frames to integrate at once next, as chosen by the
+    /// `adaptive_ref_interval`
+    ref_multiple: u32,
+
+    /// Cancels out the global motion of the camera, if the input is stabilized. See
+    /// [`Framed::stabilization`].
+    stabilizer: Option<Stabilizer>,
+
     pub(crate) video: Video<W>,
 }
-unsafe impl<W: Write + std::marker::Send + std::marker::Sync> Sync for Framed<W> {}
 
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
     /// Create a new `Framed` source
@@ -48,50 +257,385 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
         color_input: bool,
         scale: f64,
     ) -> Result<Framed<W>, SourceError> {
-        let source = Locator::Path(input_path);
-        let mut cap = Decoder::new(&source)?;
-        let (width, height) = cap.size();
-        let width = ((width as f64) * scale) as u32;
-        let height = ((height as f64) * scale) as u32;
-
-        cap = Decoder::new_with_options_and_resize(
-            &source,
-            &Options::default(),
-            Resize::Fit(width, height),
-        )?;
+        let deep_cap = HighBitDepthDecoder::new(&input_path, scale)?;
+        let (cap, source_fps, (width, height)) = if deep_cap.bit_depth() > 8 {
+            let source_fps = deep_cap.frame_rate();
+            let size_out = deep_cap.size_out();
+            (FramedInput::HighBitDepth(deep_cap), source_fps, size_out)
+        } else {
+            drop(deep_cap);
+            let source = Locator::Path(input_path);
+            let mut cap = Decoder::new(&source)?;
+            let (width, height) = cap.size();
+            let width = ((width as f64) * scale) as u32;
+            let height = ((height as f64) * scale) as u32;
 
-        // Calculate TPS based on ticks per frame and source FPS
+            cap = Decoder::new_with_options_and_resize(
+                &source,
+                &Options::default(),
+                Resize::Fit(width, height),
+            )?;
+            let source_fps = cap.frame_rate();
+            let size_out = cap.size_out();
+            (FramedInput::Rgb8(cap), source_fps, size_out)
+        };
+        Self::from_input(cap, source_fps, (width, height), color_input, scale)
+    }
+
+    /// Create a new `Framed` source reading a live network stream, such as an RTSP or HTTP(S) IP
+    /// camera. The stream is decoded at 8 bits.
+    ///
+    /// If the stream drops, it's reconnected as set by `options`, so it can be transcoded
+    /// indefinitely. A live stream can't seek, so it can't be given a start frame or time.
+    pub fn new_stream(
+        url: &str,
+        color_input: bool,
+        scale: f64,
+        options: LiveStreamOptions,
+    ) -> Result<Framed<W>, SourceError> {
+        if !is_stream_url(url) {
+            return Err(SourceError::BadParams(format!(
+                "`{url}` is not a network stream URL"
+            )));
+        }
+        let mut live = LiveStream {
+            url: Url::parse(url).map_err(|e| SourceError::BadParams(e.to_string()))?,
+            options,
+            size_out: (0, 0),
+            clock: None,
+        };
+        let mut cap = live.connect(None)?;
+        if scale != 1.0 {
+            let (width, height) = cap.size();
+            let width = ((width as f64) * scale) as u32;
+            let height = ((height as f64) * scale) as u32;
+            cap = live.connect(Some(Resize::Fit(width, height)))?;
+        }
         let source_fps = cap.frame_rate();
-        let (width, height) = cap.size_out();
+        let size_out = cap.size_out();
+        live.size_out = size_out;
+        Self::from_input(
+            FramedInput::Live(cap, live),
+            source_fps,
+            size_out,
+            color_input,
+            scale,
+        )
+    }
 
-        let plane = PlaneSize::new(width as u16, height as u16, if color_input { 3 } else { 1 })?;
+    /// Set up the transcoder for an opened input
+    fn from_input(
+        cap: FramedInput,
+        source_fps: f32,
+        (width, height): (u32, u32),
+        color_input: bool,
+        scale: f64,
+    ) -> Result<Framed<W>, SourceError> {
+        // Calculate TPS based on ticks per frame and source FPS
+        let plane = PlaneSize::new(width, height, if color_input { 3 } else { 1 })?;
 
-        let video = Video::new(plane, FramePerfect, None)?;
+        let mut video = Video::new(plane, FramePerfect, None)?;
+        if let FramedInput::HighBitDepth(_) = cap {
+            video.state.source_camera = SourceCamera::FramedU16;
+        }
 
         Ok(Framed {
             cap,
-            input_frame: Frame::default((height as usize, width as usize, 3)), // Note that this will be limited to 8-bit precision (due to video-rs crate)
+            input_frame: Frame::default((height as usize, width as usize, 3)),
             frame_idx_start: 0,
+            frame_idx: 0,
+            window_frame_start: 0,
+            window_frame_count: None,
             source_fps,
             scale,
             color_input,
+            time_lapse: 1,
+            scene_cut_threshold: None,
+            scene_cuts: 0,
+            adaptive_ref_interval: None,
+            ref_multiple: 1,
+            stabilizer: None,
             video,
         })
     }
 
     /// Set the start frame of the source
     pub fn frame_start(mut self, frame_idx_start: u32) -> Result<Self, SourceError> {
-        let video_frame_count = self.cap.frame_count();
-        if frame_idx_start >= video_frame_count as u32 {
+        let video_frame_count = match &self.cap {
+            FramedInput::Rgb8(cap) => Some(cap.frame_count()),
+            FramedInput::HighBitDepth(cap) => cap.frame_count(),
+            FramedInput::Live(..) if frame_idx_start == 0 => return Ok(self),
+            FramedInput::Live(..) => {
+                return Err(SourceError::BadParams(
+                    "a live stream can't seek".to_string(),
+                ))
+            }
+        };
+        // A video whose length is unknown is read until it runs out
+        if video_frame_count.is_some_and(|count| u64::from(frame_idx_start) >= count) {
             return Err(SourceError::StartOutOfBounds(frame_idx_start));
         };
         let ts_millis = (frame_idx_start as f32 / self.source_fps * 1000.0) as i64;
-        self.cap.reader.seek(ts_millis)?;
+        match &mut self.cap {
+            FramedInput::Rgb8(cap) | FramedInput::Live(cap, _) => cap.reader.seek(ts_millis)?,
+            FramedInput::HighBitDepth(cap) => cap.seek(ts_millis)?,
+        }
 
         self.frame_idx_start = frame_idx_start;
+        self.frame_idx = frame_idx_start;
         Ok(self)
     }
 
+    /// Save the state of the transcode, with the index of the next frame to read, so that it can
+    /// be continued from this point with [`Framed::restore_state`] or
+    /// [`Framed::resume_write_out`]. The previous frame, the number of scene cuts, the multiple of
+    /// an adaptive reference interval, and the stabilizer's camera path are saved as well. See
+    /// [`Video::save_state`].
+    pub fn save_state(&mut self) -> Result<VideoCheckpoint, SourceError> {
+        let mut checkpoint = self.video.save_state()?;
+        checkpoint.source_position = u64::from(self.frame_idx);
+        checkpoint.framed = Some(FramedCheckpoint {
+            input_frame: self.input_frame.clone(),
+            scene_cuts: self.scene_cuts,
+            ref_multiple: self.ref_multiple,
+            stabilizer: self.stabilizer.clone(),
+        });
+        Ok(checkpoint)
+    }
+
+    /// Restore the state of a transcode saved by [`Framed::save_state`], and seek to the frame
+    /// where it left off. The source must be set up as the saved one was, including its start
+    /// frame and whether it's stabilized. See [`Video::restore_state`].
+    pub fn restore_state(mut self, checkpoint: &VideoCheckpoint) -> Result<Self, SourceError> {
+        self.video.restore_state(checkpoint)?;
+        self.seek_to_checkpoint(checkpoint)
+    }
+
+    /// Restore the framed source's own state from a checkpoint, and seek to the next frame to
+    /// read when it was taken, keeping the start frame (from which the stream's timestamps are
+    /// counted) as it is
+    fn seek_to_checkpoint(mut self, checkpoint: &VideoCheckpoint) -> Result<Self, SourceError> {
+        let framed = checkpoint.framed.as_ref().ok_or_else(|| {
+            SourceError::BadParams("The checkpoint wasn't saved by a framed source".to_string())
+        })?;
+        if framed.stabilizer.is_some() != self.stabilizer.is_some() {
+            return Err(SourceError::BadParams(
+                "The checkpoint's stabilization doesn't match the source's".to_string(),
+            ));
+        }
+        let frame_idx = u32::try_from(checkpoint.source_position).map_err(|_| {
+            SourceError::BadParams("The checkpoint's frame is out of range".to_string())
+        })?;
+        if frame_idx < self.frame_idx_start {
+            return Err(SourceError::BadParams(format!(
+                "The checkpoint is at frame {frame_idx}, before the start frame {}",
+                self.frame_idx_start
+            )));
+        }
+        let frame_idx_start = self.frame_idx_start;
+        self = self.frame_start(frame_idx)?;
+        self.frame_idx_start = frame_idx_start;
+        self.input_frame = framed.input_frame.clone();
+        self.scene_cuts = framed.scene_cuts;
+        self.ref_multiple = framed.ref_multiple;
+        self.stabilizer = framed.stabilizer.clone();
+        Ok(self)
+    }
+
+    /// The camera type of the video, by its bit depth: [`SourceCamera::FramedU16`] for video
+    /// with more than 8 bits per sample, or else [`SourceCamera::FramedU8`]
+    pub fn source_camera(&self) -> SourceCamera {
+        match self.cap {
+            FramedInput::Rgb8(_) | FramedInput::Live(..) => SourceCamera::FramedU8,
+            FramedInput::HighBitDepth(_) => SourceCamera::FramedU16,
+        }
+    }
+
+    /// Convert a length of time to a number of input frames
+    fn duration_to_frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() * f64::from(self.source_fps)).round() as u32
+    }
+
+    /// Transcode the color channels with the given subsampling. See
+    /// [`Video::chroma_subsampling`].
+    pub fn chroma_subsampling(
+        mut self,
+        chroma_subsampling: ChromaSubsampling,
+    ) -> Result<Self, SourceError> {
+        self.video = self.video.chroma_subsampling(chroma_subsampling)?;
+        Ok(self)
+    }
+
+    /// Apply a lookup table to the input intensities before integration. See
+    /// [`Video::intensity_lut`].
+    pub fn intensity_lut(mut self, lut: Option<IntensityLut>) -> Self {
+        self.video = self.video.intensity_lut(lut);
+        self
+    }
+
+    /// Model the noise of the sensor, so that it doesn't fire events. See
+    /// [`Video::noise_model`].
+    pub fn noise_model(mut self, model: Option<NoiseModel>) -> Self {
+        self.video = self.video.noise_model(model);
+        self
+    }
+
+    /// Adjust the CRF to hold a target quality. See [`Video::quality_target`].
+    pub fn quality_target(mut self, target: Option<QualityTarget>) -> Self {
+        self.video = self.video.quality_target(target);
+        self
+    }
+
+    /// Denoise the input frames over time before integration. See
+    /// [`Video::temporal_filter`].
+    pub fn temporal_filter(mut self, filter: Option<TemporalFilter>) -> Result<Self, SourceError> {
+        self.video = self.video.temporal_filter(filter)?;
+        Ok(self)
+    }
+
+    /// Cap the number of events each pixel may fire per second. See [`Video::event_budget`].
+    pub fn event_budget(mut self, events_per_second: Option<u32>) -> Self {
+        self.video = self.video.event_budget(events_per_second);
+        self
+    }
+
+    /// Integrate the pixels which don't fire on the GPU. See [`Video::gpu_integration`].
+    pub fn gpu_integration(mut self, enabled: bool) -> Result<Self, SourceError> {
+        self.video = self.video.gpu_integration(enabled)?;
+        Ok(self)
+    }
+
+    /// Correct the lens distortion of the input frames before integration. See
+    /// [`Video::lens_undistortion`]. The lens is calibrated at the video's own resolution, and
+    /// rescaled along with the frames.
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        let scale = self.scale as f32;
+        self.video = self
+            .video
+            .lens_undistortion(model.map(|model| model.scaled(scale)))?;
+        Ok(self)
+    }
+
+    /// Speed up time by the given factor, for condensing long recordings from a static camera.
+    ///
+    /// Each group of `factor` consecutive input frames is averaged and integrated as a single
+    /// frame spanning `ref_time` ticks. The ticks per second are unchanged, so the output stream
+    /// plays back `factor` times faster, and Δt_max still spans the same number of output frames.
+    /// A live stream can't be sped up, nor can a source with an adaptive reference interval.
+    pub fn time_lapse(mut self, factor: u32) -> Result<Self, SourceError> {
+        if factor == 0 {
+            return Err(SourceError::BadParams(
+                "time-lapse factor must be at least 1".to_string(),
+            ));
+        }
+        if factor > 1 && matches!(self.cap, FramedInput::Live(..)) {
+            return Err(SourceError::BadParams(
+                "a live stream can't be time-lapsed".to_string(),
+            ));
+        }
+        if factor > 1 && self.adaptive_ref_interval.is_some() {
+            return Err(SourceError::BadParams(
+                "a source with an adaptive reference interval can't be time-lapsed".to_string(),
+            ));
+        }
+        self.time_lapse = factor;
+        Ok(self)
+    }
+
+    /// Detect hard cuts between scenes, where the mean absolute difference between successive
+    /// frames exceeds `threshold`, as a fraction of full scale (e.g., 0.25). At each cut, every
+    /// pixel is reset and the encoder starts an intra Adu, so that no pixel integrates light
+    /// across the cut. See [`Video::reset_pixels`]. `None` disables detection.
+    pub fn scene_cut_threshold(mut self, threshold: Option<f32>) -> Result<Self, SourceError> {
+        if let Some(threshold) = threshold {
+            if threshold.is_nan() || threshold <= 0.0 || threshold > 1.0 {
+                return Err(SourceError::BadParams(
+                    "scene cut threshold must be in (0, 1]".to_string(),
+                ));
+            }
+        }
+        self.scene_cut_threshold = threshold;
+        Ok(self)
+    }
+
+    /// Get the number of scene cuts detected so far
+    pub fn scene_cuts(&self) -> u32 {
+        self.scene_cuts
+    }
+
+    /// Adapt the reference interval to the motion in the scene, so that static scenes fire fewer
+    /// events and fast ones keep their temporal fidelity. See [`AdaptiveRefInterval`]. Each change
+    /// of the interval is recorded in the stream (if it's compressed), where a decoder can read
+    /// it with `Decoder::read_ref_interval_changes`. `None` keeps the interval fixed at
+    /// `ref_time`.
+    ///
+    /// A live stream or a time-lapse can't adapt its interval.
+    pub fn adaptive_ref_interval(
+        mut self,
+        adaptive: Option<AdaptiveRefInterval>,
+    ) -> Result<Self, SourceError> {
+        if let Some(adaptive) = adaptive {
+            if adaptive.max_multiple == 0 {
+                return Err(SourceError::BadParams(
+                    "the maximum reference interval multiple must be at least 1".to_string(),
+                ));
+            }
+            if adaptive.static_threshold > adaptive.motion_threshold {
+                return Err(SourceError::BadParams(
+                    "the static threshold must not exceed the motion threshold".to_string(),
+                ));
+            }
+            if matches!(self.cap, FramedInput::Live(..)) || self.time_lapse > 1 {
+                return Err(SourceError::BadParams(
+                    "a live stream or a time-lapse can't adapt its reference interval".to_string(),
+                ));
+            }
+        }
+        self.adaptive_ref_interval = adaptive;
+        self.ref_multiple = 1;
+        Ok(self)
+    }
+
+    /// Get the number of input frames which will be integrated at once next, as chosen by the
+    /// [`AdaptiveRefInterval`] (1 if the interval doesn't adapt)
+    pub fn ref_multiple(&self) -> u32 {
+        self.ref_multiple
+    }
+
+    /// Stabilize the input, estimating the global motion between successive frames and shifting
+    /// each frame to cancel out the camera's shake before it's integrated. This greatly reduces
+    /// the events fired by handheld footage. See [`Stabilization`]. The shift of each frame is
+    /// recorded in the stream (if it's compressed), where a decoder can read it with
+    /// `Decoder::read_stabilization_transforms` to un-warp the reconstructed frames. `None`
+    /// leaves the input as it is.
+    pub fn stabilization(
+        mut self,
+        stabilization: Option<Stabilization>,
+    ) -> Result<Self, SourceError> {
+        if let Some(stabilization) = stabilization {
+            if stabilization.max_motion == 0 {
+                return Err(SourceError::BadParams(
+                    "the maximum stabilization motion must be at least 1".to_string(),
+                ));
+            }
+            if !(0.0..=1.0).contains(&stabilization.smoothing) {
+                return Err(SourceError::BadParams(
+                    "stabilization smoothing must be in [0, 1]".to_string(),
+                ));
+            }
+        }
+        self.stabilizer = stabilization.map(Stabilizer::new);
+        Ok(self)
+    }
+
+    /// Get the shift applied to the latest input frame by the stabilization, as (`dx`, `dy`)
+    /// pixels ((0, 0) if the input isn't stabilized)
+    pub fn stabilization_shift(&self) -> (i32, i32) {
+        self.stabilizer
+            .as_ref()
+            .map_or((0, 0), |stabilizer| stabilizer.shift())
+    }
+
     /// Automatically derive the ticks per second from the source FPS and `ref_time`
     pub fn auto_time_parameters(
         mut self,
@@ -123,17 +667,213 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Framed<W> {
     }
 }
 
+impl Framed<BufWriter<File>> {
+    /// Continue a transcode saved by [`Framed::save_state`], appending to the output file it was
+    /// writing in place of [`VideoBuilder::write_out`], and seek to the frame where it left off.
+    /// See [`Video::resume_write_out`].
+    pub fn resume_write_out(
+        mut self,
+        file_path: &str,
+        encoder_options: EncoderOptions,
+        checkpoint: &VideoCheckpoint,
+    ) -> Result<Self, SourceError> {
+        self.video = self
+            .video
+            .resume_write_out(file_path, encoder_options, checkpoint)?;
+        self.seek_to_checkpoint(checkpoint)
+    }
+}
+
+/// The mean absolute difference between two 8-bit frames, as a fraction of full scale
+fn frame_difference(previous: &Frame, next: &Frame) -> f32 {
+    if previous.dim() != next.dim() || next.is_empty() {
+        return 0.0;
+    }
+    let sum: u64 = previous
+        .iter()
+        .zip(next.iter())
+        .map(|(previous, next)| u64::from(previous.abs_diff(*next)))
+        .sum();
+    sum as f32 / (next.len() as f32 * f32::from(u8::MAX))
+}
+
+impl FramedInput {
+    /// Read the next frame to integrate, as BGR or luma intensities on the scale of the video's
+    /// bit depth. If `frames` is above 1 (for a time-lapse, or an adaptive reference interval),
+    /// this is the mean of the next `frames` input frames.
+    ///
+    /// A live stream is reconnected if it drops, and frames which are too far behind the camera
+    /// are skipped.
+    ///
+    /// Returns the intensities and the number of input frames read.
+    fn next_intensities(
+        &mut self,
+        color_input: bool,
+        frames: u32,
+    ) -> Result<(Array3<f32>, u32), SourceError> {
+        match self {
+            FramedInput::Rgb8(cap) => {
+                let (_, frame) = cap.decode()?;
+                let frame = handle_color(frame, color_input)?;
+                if frames == 1 {
+                    return Ok((frame.mapv(f32::from), 1));
+                }
+
+                let mut sum = frame.mapv(u32::from);
+                let mut count = 1;
+                while count < frames {
+                    // If the video ends partway through the group, just average the frames we got
+                    let Ok((_, frame)) = cap.decode() else {
+                        break;
+                    };
+                    sum += &handle_color(frame, color_input)?.mapv(u32::from);
+                    count += 1;
+                }
+                Ok((sum.mapv(|v| ((v + count / 2) / count) as f32), count))
+            }
+            FramedInput::HighBitDepth(cap) => {
+                let Some(mut sum) = cap.decode()? else {
+                    return Err(SourceError::BufferEmpty);
+                };
+                let mut count = 1;
+                while count < frames {
+                    let Ok(Some(frame)) = cap.decode() else {
+                        break;
+                    };
+                    sum += &frame;
+                    count += 1;
+                }
+                sum /= count as f32;
+                if color_input {
+                    return Ok((sum, count));
+                }
+                Ok((luma_intensities(&sum), count))
+            }
+            FramedInput::Live(cap, live) => {
+                // Fixed before decoding, so that the skipping ends even if decoding is slower
+                // than the camera
+                let skip_before = live.skip_before();
+                let mut count = 0;
+                let frame = loop {
+                    let (time, frame) = match cap.decode() {
+                        Ok(decoded) => decoded,
+                        Err(e) => {
+                            eprintln!("Lost the stream from {} ({e}), reconnecting", live.url);
+                            *cap = live.reconnect()?;
+                            continue;
+                        }
+                    };
+                    count += 1;
+                    let timestamp = time.as_secs_f64();
+                    live.clock.get_or_insert((Instant::now(), timestamp));
+                    if skip_before.map_or(true, |skip_before| timestamp >= skip_before) {
+                        break frame;
+                    }
+                };
+                let frame = handle_color(frame, color_input)?;
+                Ok((frame.mapv(f32::from), count))
+            }
+        }
+    }
+}
+
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for Framed<W> {
     /// Get pixel-wise intensities directly from source frame, and integrate them with
     /// `ref_time` (the number of ticks each frame is said to span)
     fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
-        let (_, frame) = self.cap.decode()?;
-        self.input_frame = handle_color(frame, self.color_input)?;
+        let mut ref_multiple = self.ref_multiple;
+        if let Some(count) = self.window_frame_count {
+            let window_end = self.window_frame_start.saturating_add(count);
+            if self.frame_idx >= window_end {
+                return Err(SourceError::BufferEmpty);
+            }
+            // Don't read past the end of the window to fill out an adaptive reference interval
+            ref_multiple = ref_multiple.min(window_end - self.frame_idx);
+        }
+        let (raw_intensities, frames_read) = self
+            .cap
+            .next_intensities(self.color_input, self.time_lapse * ref_multiple)?;
+        // Only frames after the first one read can be compared to the one before
+        let has_previous = self.frame_idx > self.frame_idx_start;
+        let ref_time = self.video.state.params.ref_time;
+        let start_t = (self.frame_idx - self.frame_idx_start) / self.time_lapse * ref_time;
+        self.frame_idx += frames_read;
 
-        let res = self.video.integrate_matrix(
-            self.input_frame.clone(),
-            self.video.state.params.ref_time as f32,
+        // The frame of a live stream also spans the time of any frames skipped before it, and
+        // that of an adaptive reference interval spans each of the frames averaged into it
+        let mut time_spanned = ref_time as f32;
+        if matches!(self.cap, FramedInput::Live(..)) || self.adaptive_ref_interval.is_some() {
+            time_spanned *= frames_read as f32;
+        }
+        if self.adaptive_ref_interval.is_some() {
+            self.video.encoder.record_ref_interval(RefIntervalChange {
+                t: start_t,
+                ref_interval: ref_time * frames_read,
+            })?;
+        }
+
+        // Shift the frame to cancel out the camera's motion
+        let shift = match &mut self.stabilizer {
+            Some(stabilizer) => stabilizer.track(&raw_intensities),
+            None => (0, 0),
+        };
+        let mut stabilized =
+            (shift != (0, 0)).then(|| stabilization::shift_frame(&raw_intensities, shift));
+
+        let scale = f32::from(u8::MAX) / self.source_camera().source_type().max_intensity() as f32;
+        let input_frame = self.video.fit_to_plane(
+            stabilized
+                .as_ref()
+                .unwrap_or(&raw_intensities)
+                .mapv(|intensity| (intensity * scale) as u8),
         );
+
+        let measure = self.scene_cut_threshold.is_some() || self.adaptive_ref_interval.is_some();
+        let difference =
+            (has_previous && measure).then(|| frame_difference(&self.input_frame, &input_frame));
+        self.input_frame = input_frame;
+
+        let mut reset_events = None;
+        if let (Some(threshold), Some(difference)) = (self.scene_cut_threshold, difference) {
+            if difference > threshold {
+                self.scene_cuts += 1;
+                reset_events = Some(self.video.reset_pixels()?);
+
+                // The motion estimated across the cut is meaningless, so the new scene starts
+                // out unshifted
+                if let Some(stabilizer) = &mut self.stabilizer {
+                    stabilizer.reset();
+                }
+                if stabilized.take().is_some() {
+                    self.input_frame = self
+                        .video
+                        .fit_to_plane(raw_intensities.mapv(|intensity| (intensity * scale) as u8));
+                }
+            }
+        }
+        if self.stabilizer.is_some() {
+            let (dx, dy) = self.stabilization_shift();
+            self.video
+                .encoder
+                .record_stabilization(StabilizationTransform { t: start_t, dx, dy })?;
+        }
+        if let (Some(adaptive), Some(difference)) = (self.adaptive_ref_interval, difference) {
+            // Each integration must fit within Δt_max
+            let max_multiple = (self.video.state.params.delta_t_max / ref_time).max(1);
+            self.ref_multiple = adaptive
+                .next_multiple(self.ref_multiple, difference / frames_read as f32)
+                .min(max_multiple);
+        }
+
+        let intensities = stabilized.unwrap_or(raw_intensities);
+        let mut res = self.video.integrate_intensities(intensities, time_spanned);
+        // The events fired by the reset come first in each chunk
+        if let (Some(reset_events), Ok(events)) = (reset_events, &mut res) {
+            for (chunk, mut reset_chunk) in events.iter_mut().zip(reset_events) {
+                reset_chunk.append(chunk);
+                *chunk = reset_chunk;
+            }
+        }
         #[cfg(feature = "feature-logging")]
         {
             if let Some(handle) = &mut self.video.state.feature_log_handle {
@@ -180,9 +920,14 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for F
 
     fn get_running_input_bitrate(&self) -> f64 {
         let video = self.get_video_ref();
+        let bits = match self.source_camera().source_type() {
+            SourceType::U16 => 16.0,
+            _ => 8.0,
+        };
         video.get_tps() as f64 / video.get_ref_time() as f64
+            * self.time_lapse as f64
             * video.state.plane.volume() as f64
-            * 8.0
+            * bits
     }
 }
 
@@ -215,6 +960,29 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         self
     }
 
+    /// The crop is given in pixels of the source video, before it's resized by the scale factor
+    fn crop(mut self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        let scale = |value: PixelAddress| (f64::from(value) * self.scale) as PixelAddress;
+        let crop = crop.map(|crop| Crop {
+            x: scale(crop.x),
+            y: scale(crop.y),
+            width: scale(crop.width),
+            height: scale(crop.height),
+        });
+        self.video = self.video.crop(crop)?;
+        Ok(self)
+    }
+
+    fn orientation(mut self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        self.video = self.video.orientation(orientation)?;
+        Ok(self)
+    }
+
+    fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.video = self.video.contrast_mask(mask)?;
+        Ok(self)
+    }
+
     fn time_parameters(
         mut self,
         tps: DeltaT,
@@ -232,6 +1000,8 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         Ok(self)
     }
 
+    /// The `source_camera` must be the video's own [`Framed::source_camera`], since it sets the
+    /// scale of the intensities
     fn write_out(
         mut self,
         source_camera: SourceCamera,
@@ -242,6 +1012,13 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         encoder_options: EncoderOptions,
         write: W,
     ) -> Result<Box<Self>, SourceError> {
+        if is_framed(source_camera) && source_camera != self.source_camera() {
+            return Err(SourceError::BadParams(format!(
+                "the video is {:?}, not {:?}",
+                self.source_camera(),
+                source_camera
+            )));
+        }
         self.video = self.video.write_out(
             Some(source_camera),
             Some(time_mode),
@@ -259,6 +1036,19 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
         self
     }
 
+    /// Seek to the input frame nearest the start time
+    fn start_time(mut self, start_time: Duration) -> Result<Self, SourceError> {
+        let frame_idx_start = self.duration_to_frames(start_time);
+        self = self.frame_start(frame_idx_start)?;
+        self.window_frame_start = frame_idx_start;
+        Ok(self)
+    }
+
+    fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.window_frame_count = duration.map(|duration| self.duration_to_frames(duration));
+        self
+    }
+
     #[cfg(feature = "feature-logging")]
     fn log_path(mut self, name: String) -> Self {
         let date_time = Local::now();
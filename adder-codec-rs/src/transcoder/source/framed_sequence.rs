@@ -0,0 +1,520 @@
+use crate::transcoder::source::denoise::TemporalFilter;
+use crate::transcoder::source::noise::NoiseModel;
+use crate::transcoder::source::quality::QualityTarget;
+use crate::transcoder::source::undistort::LensModel;
+use crate::transcoder::source::video::Video;
+use crate::transcoder::source::video::{
+    ContrastMask, Crop, IntensityLut, Orientation, SourceError,
+};
+use crate::transcoder::source::video::{Source, VideoBuilder};
+use adder_codec_core::Mode::FramePerfect;
+use adder_codec_core::{
+    is_framed, ChromaSubsampling, DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera,
+    SourceType, TimeMode,
+};
+
+use crate::utils::cv::luma_intensities;
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+
+use image::{ColorType, DynamicImage};
+use ndarray::{Array3, Axis};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(feature = "feature-logging")]
+use chrono::Local;
+use video_rs_adder_dep::Frame;
+
+/// The file extensions of the images read from a sequence directory
+const SEQUENCE_EXTENSIONS: [&str; 4] = ["png", "tif", "tiff", "exr"];
+
+/// Attributes of an image sequence -> ADΔER transcode. The sequence is a directory of numbered
+/// PNG, TIFF, or OpenEXR images (e.g., from a microscope or a renderer), read in the order of
+/// their numbers.
+///
+/// 16-bit images are transcoded at their full bit depth, so [`VideoBuilder::write_out`] must be
+/// given the matching [`FramedSequenceSource::source_camera`]. Floating-point (HDR) images are
+/// normalized by their intensity peak (see [`FramedSequenceSource::intensity_peak`]), which is
+/// recorded in the stream header so that the frames can be reconstructed in their own units.
+pub struct FramedSequenceSource<W: Write + 'static + std::marker::Send + std::marker::Sync> {
+    /// The images of the sequence, in order
+    paths: Vec<PathBuf>,
+
+    /// The last input frame, scaled to 8 bits for display
+    pub(crate) input_frame: Frame,
+
+    /// Index of the next image to be read
+    frame_idx: u32,
+
+    /// Index of the first image of the window set by [`VideoBuilder::start_time`]
+    window_frame_start: u32,
+
+    /// The number of images to transcode from the start of the window, if it's limited by
+    /// [`VideoBuilder::duration`]
+    window_frame_count: Option<u32>,
+
+    /// The length of time that each image of the sequence spans
+    pub frame_duration: Duration,
+
+    /// The sample type of the images. Set automatically from the first image.
+    source_camera: SourceCamera,
+
+    /// Whether the input video is color
+    color_input: bool,
+
+    pub(crate) video: Video<W>,
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> FramedSequenceSource<W> {
+    /// Create a new `FramedSequenceSource` from the numbered images in `directory`, each of which
+    /// spans `frame_duration`. All the images must have the same dimensions.
+    pub fn new(
+        directory: PathBuf,
+        color_input: bool,
+        frame_duration: Duration,
+    ) -> Result<FramedSequenceSource<W>, SourceError> {
+        if frame_duration.is_zero() {
+            return Err(SourceError::BadParams(
+                "frame duration must be greater than zero".to_string(),
+            ));
+        }
+        let paths = sequence_paths(&directory)?;
+        let Some(first) = paths.first() else {
+            return Err(SourceError::BadParams(format!(
+                "no PNG, TIFF, or EXR images in {}",
+                directory.display()
+            )));
+        };
+
+        let image = image::open(first)?;
+        let source_camera = match image.color() {
+            ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => {
+                SourceCamera::FramedU8
+            }
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+                SourceCamera::FramedU16
+            }
+            _ => SourceCamera::FramedF32,
+        };
+
+        let plane = PlaneSize::new(
+            image.width(),
+            image.height(),
+            if color_input { 3 } else { 1 },
+        )?;
+
+        let mut video = Video::new(plane, FramePerfect, None)?;
+        video.state.source_camera = source_camera;
+        if source_camera == SourceCamera::FramedF32 {
+            // Samples up to 1.0 are on the usual scale. Brighter HDR samples raise the peak.
+            let peak = image
+                .to_rgb32f()
+                .iter()
+                .filter(|sample| sample.is_finite())
+                .fold(1.0, |peak: f32, &sample| peak.max(sample));
+            video = video.intensity_peak(peak)?;
+        }
+
+        Ok(FramedSequenceSource {
+            paths,
+            input_frame: Frame::default((image.height() as usize, image.width() as usize, 3)),
+            frame_idx: 0,
+            window_frame_start: 0,
+            window_frame_count: None,
+            frame_duration,
+            source_camera,
+            color_input,
+            video,
+        })
+    }
+
+    /// The sample type of the images, to give to [`VideoBuilder::write_out`]
+    pub fn source_camera(&self) -> SourceCamera {
+        self.source_camera
+    }
+
+    /// Set the intensity which a full-scale sample of a floating-point sequence stands for.
+    /// Brighter samples are clipped. By default, it's the brightest sample of the first image, or
+    /// 1.0 if that's brighter.
+    pub fn intensity_peak(mut self, peak: f32) -> Result<Self, SourceError> {
+        if self.source_camera != SourceCamera::FramedF32 {
+            return Err(SourceError::BadParams(format!(
+                "only floating-point images have an intensity peak, not {:?}",
+                self.source_camera
+            )));
+        }
+        self.video = self.video.intensity_peak(peak)?;
+        Ok(self)
+    }
+
+    /// The number of images in the sequence
+    pub fn frame_count(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Set the start frame of the source
+    pub fn frame_start(mut self, frame_idx_start: u32) -> Result<Self, SourceError> {
+        if frame_idx_start as usize >= self.paths.len() {
+            return Err(SourceError::StartOutOfBounds(frame_idx_start));
+        };
+        self.frame_idx = frame_idx_start;
+        Ok(self)
+    }
+
+    /// Convert a length of time to a number of images
+    fn duration_to_frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() / self.frame_duration.as_secs_f64()).round() as u32
+    }
+
+    /// Transcode the color channels with the given subsampling. See
+    /// [`Video::chroma_subsampling`].
+    pub fn chroma_subsampling(
+        mut self,
+        chroma_subsampling: ChromaSubsampling,
+    ) -> Result<Self, SourceError> {
+        self.video = self.video.chroma_subsampling(chroma_subsampling)?;
+        Ok(self)
+    }
+
+    /// Apply a lookup table to the input intensities before integration. See
+    /// [`Video::intensity_lut`].
+    pub fn intensity_lut(mut self, lut: Option<IntensityLut>) -> Self {
+        self.video = self.video.intensity_lut(lut);
+        self
+    }
+
+    /// Model the noise of the sensor, so that it doesn't fire events. See
+    /// [`Video::noise_model`].
+    pub fn noise_model(mut self, model: Option<NoiseModel>) -> Self {
+        self.video = self.video.noise_model(model);
+        self
+    }
+
+    /// Adjust the CRF to hold a target quality. See [`Video::quality_target`].
+    pub fn quality_target(mut self, target: Option<QualityTarget>) -> Self {
+        self.video = self.video.quality_target(target);
+        self
+    }
+
+    /// Denoise the input frames over time before integration. See
+    /// [`Video::temporal_filter`].
+    pub fn temporal_filter(mut self, filter: Option<TemporalFilter>) -> Result<Self, SourceError> {
+        self.video = self.video.temporal_filter(filter)?;
+        Ok(self)
+    }
+
+    /// Cap the number of events each pixel may fire per second. See [`Video::event_budget`].
+    pub fn event_budget(mut self, events_per_second: Option<u32>) -> Self {
+        self.video = self.video.event_budget(events_per_second);
+        self
+    }
+
+    /// Correct the lens distortion of the input frames before integration. See
+    /// [`Video::lens_undistortion`].
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        self.video = self.video.lens_undistortion(model)?;
+        Ok(self)
+    }
+
+    /// Automatically derive the ticks per second from the frame duration and `ref_time`
+    pub fn auto_time_parameters(
+        mut self,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            let tps = (f64::from(ref_time) / self.frame_duration.as_secs_f64()).round() as DeltaT;
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            return Err(SourceError::BadParams(
+                "delta_t_max must be a multiple of ref_time".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Get the number of ticks each frame is said to span
+    pub fn get_ref_time(&self) -> u32 {
+        self.video.state.params.ref_time
+    }
+
+    /// Get the previous input frame, scaled to 8 bits
+    pub fn get_last_input_frame(&self) -> &Frame {
+        &self.input_frame
+    }
+
+    /// Read the next image as intensities on the scale of its bit depth
+    fn next_intensities(&mut self) -> Result<Array3<f32>, SourceError> {
+        let Some(path) = self.paths.get(self.frame_idx as usize) else {
+            return Err(SourceError::BufferEmpty);
+        };
+        let image = image::open(path)?;
+        let plane = self.video.state.plane;
+        if image.width() != plane.w() || image.height() != plane.h() {
+            return Err(SourceError::BadParams(format!(
+                "{} is {}x{}, but the sequence is {}x{}",
+                path.display(),
+                image.width(),
+                image.height(),
+                plane.w(),
+                plane.h()
+            )));
+        }
+        self.frame_idx += 1;
+        image_intensities(
+            &image,
+            self.source_camera,
+            self.video.state.intensity_peak,
+            self.color_input,
+        )
+    }
+}
+
+/// The images in `directory`, ordered by the last number in their file names (so that
+/// `frame_2.png` comes before `frame_10.png`), then by name
+fn sequence_paths(directory: &Path) -> Result<Vec<PathBuf>, SourceError> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let is_image = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                SEQUENCE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+            });
+        if is_image && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort_by_cached_key(|path| {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let digits: String = stem
+            .rsplit(|c: char| !c.is_ascii_digit())
+            .find(|digits| !digits.is_empty())
+            .unwrap_or_default()
+            .to_string();
+        (digits.parse::<u64>().ok(), path.clone())
+    });
+    Ok(paths)
+}
+
+/// Convert an image to an array of intensities on the scale of the source camera's bit depth,
+/// with 3 color channels (in BGR order, like the other framed sources) or a single luma channel.
+/// Samples are normalized by `intensity_peak` first, which is 1.0 except for HDR images.
+fn image_intensities(
+    image: &DynamicImage,
+    source_camera: SourceCamera,
+    intensity_peak: f32,
+    color_input: bool,
+) -> Result<Array3<f32>, SourceError> {
+    let max_intensity = source_camera.source_type().max_intensity() as f32;
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut intensities = Array3::from_shape_vec((height, width, 3), image.to_rgb32f().into_raw())?;
+    intensities.invert_axis(Axis(2));
+    intensities.mapv_inplace(|intensity| {
+        // NaN samples (which OpenEXR allows) are taken as black
+        let normalized = (intensity / intensity_peak).clamp(0.0, 1.0);
+        if normalized.is_nan() {
+            0.0
+        } else {
+            normalized * max_intensity
+        }
+    });
+    if color_input {
+        return Ok(intensities);
+    }
+    Ok(luma_intensities(&intensities))
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W>
+    for FramedSequenceSource<W>
+{
+    /// Read the next image of the sequence, and integrate its pixel-wise intensities with
+    /// `ref_time` (the number of ticks each frame is said to span)
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if let Some(count) = self.window_frame_count {
+            if self.frame_idx >= self.window_frame_start.saturating_add(count) {
+                return Err(SourceError::BufferEmpty);
+            }
+        }
+        let intensities = self.next_intensities()?;
+
+        let scale = f32::from(u8::MAX) / self.source_camera.source_type().max_intensity() as f32;
+        self.input_frame = self
+            .video
+            .fit_to_plane(intensities.mapv(|intensity| (intensity * scale) as u8));
+
+        self.video
+            .integrate_intensities(intensities, self.video.state.params.ref_time as f32)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        Some(self.get_last_input_frame())
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        let video = self.get_video_ref();
+        let bits = match self.source_camera.source_type() {
+            SourceType::U8 => 8.0,
+            SourceType::U16 => 16.0,
+            _ => 32.0,
+        };
+        video.get_tps() as f64 / video.get_ref_time() as f64
+            * video.state.plane.volume() as f64
+            * bits
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W>
+    for FramedSequenceSource<W>
+{
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn crop(mut self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        self.video = self.video.crop(crop)?;
+        Ok(self)
+    }
+
+    fn orientation(mut self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        self.video = self.video.orientation(orientation)?;
+        Ok(self)
+    }
+
+    fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.video = self.video.contrast_mask(mask)?;
+        Ok(self)
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            eprintln!("delta_t_max must be a multiple of ref_time");
+        }
+        Ok(self)
+    }
+
+    /// The `source_camera` must be the sequence's own [`FramedSequenceSource::source_camera`],
+    /// since it sets the scale of the intensities
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        if is_framed(source_camera) && source_camera != self.source_camera {
+            return Err(SourceError::BadParams(format!(
+                "the image sequence is {:?}, not {:?}",
+                self.source_camera, source_camera
+            )));
+        }
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    /// Skip to the image nearest the start time
+    fn start_time(mut self, start_time: Duration) -> Result<Self, SourceError> {
+        let frame_idx_start = self.duration_to_frames(start_time);
+        self = self.frame_start(frame_idx_start)?;
+        self.window_frame_start = frame_idx_start;
+        Ok(self)
+    }
+
+    fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.window_frame_count = duration.map(|duration| self.duration_to_frames(duration));
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(mut self, name: String) -> Self {
+        let date_time = Local::now();
+        let formatted = format!("{}_{}.log", name, date_time.format("%d_%m_%Y_%H_%M_%S"));
+        let log_handle = std::fs::File::create(formatted).ok();
+        self.video.state.feature_log_handle = log_handle;
+
+        // Write the plane size to the log file
+        if let Some(handle) = &mut self.video.state.feature_log_handle {
+            writeln!(
+                handle,
+                "{}x{}x{}",
+                self.video.state.plane.w(),
+                self.video.state.plane.h(),
+                self.video.state.plane.c()
+            )
+            .unwrap();
+        }
+        self
+    }
+}
@@ -0,0 +1,173 @@
+use ffmpeg_next::codec::context::Context as CodecContext;
+use ffmpeg_next::format::context::Input;
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling::{Context as Scaler, Flags};
+use ffmpeg_next::util::frame::video::Video as VideoFrame;
+use ffmpeg_next::{decoder, media, Error};
+use ndarray::Array3;
+use std::path::Path;
+
+/// The timestamp units of [`Input::seek`] (`AV_TIME_BASE`), per millisecond
+const TIME_BASE_PER_MILLI: i64 = 1000;
+
+/// Decodes video of any bit depth to 16-bit RGB frames with FFmpeg, so that 10-bit and 12-bit
+/// (e.g., HEVC) video is transcoded at full precision. The video-rs decoder that the 8-bit path
+/// uses always converts to RGB24.
+pub struct HighBitDepthDecoder {
+    input: Input,
+
+    /// The index of the video stream in the input
+    stream_index: usize,
+
+    decoder: decoder::Video,
+    scaler: Scaler,
+
+    /// The (width, height) of the decoded frames, after resizing
+    size_out: (u32, u32),
+
+    frame_rate: f32,
+
+    /// The number of frames in the video, if its container gives it
+    frame_count: Option<u64>,
+
+    /// Whether the end of the input has been sent to the decoder
+    draining: bool,
+}
+
+// SAFETY: the scaler holds a raw pointer to its `SwsContext`, which makes the decoder `!Send`.
+// Like the demuxer and decoder contexts, the scaler's context is owned by this decoder alone, and
+// FFmpeg doesn't tie it to the thread which created it. It's only used through `&mut self`, and
+// the decoder isn't `Sync`, so moving the decoder to another thread can't lead to it being used
+// from two threads at once.
+unsafe impl Send for HighBitDepthDecoder {}
+
+impl HighBitDepthDecoder {
+    /// Open the best video stream of a file, resizing its frames by `scale`
+    pub fn new(path: &Path, scale: f64) -> Result<Self, Error> {
+        let input = ffmpeg_next::format::input(&path)?;
+        let stream = input
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(Error::StreamNotFound)?;
+        let stream_index = stream.index();
+        let frame_rate = match stream.avg_frame_rate() {
+            rate if rate.numerator() > 0 && rate.denominator() > 0 => rate,
+            _ => stream.rate(),
+        };
+        let frame_rate = f64::from(frame_rate) as f32;
+        let frame_count = u64::try_from(stream.frames())
+            .ok()
+            .filter(|&frames| frames > 0);
+
+        let decoder = CodecContext::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+        let size_out = (
+            ((f64::from(decoder.width()) * scale) as u32).max(1),
+            ((f64::from(decoder.height()) * scale) as u32).max(1),
+        );
+        let scaler = Scaler::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGB48LE,
+            size_out.0,
+            size_out.1,
+            Flags::AREA,
+        )?;
+
+        Ok(Self {
+            input,
+            stream_index,
+            decoder,
+            scaler,
+            size_out,
+            frame_rate,
+            frame_count,
+            draining: false,
+        })
+    }
+
+    /// The number of bits per sample of the video, before it's decoded to 16 bits
+    pub fn bit_depth(&self) -> u8 {
+        match self.decoder.format().descriptor() {
+            // SAFETY: `descriptor()` only returns a descriptor for a non-null pointer from
+            // `av_pix_fmt_desc_get`, which points into FFmpeg's static, immutable table of pixel
+            // formats, so it's valid for the life of the program. `comp` is a fixed-size array,
+            // so its first element is always there (with a depth of 0 for formats without
+            // components, such as hardware surfaces). ffmpeg-next doesn't expose the component
+            // depths safely.
+            Some(descriptor) => unsafe { (*descriptor.as_ptr()).comp[0].depth as u8 },
+            None => 8,
+        }
+    }
+
+    /// The (width, height) of the decoded frames, after resizing
+    pub fn size_out(&self) -> (u32, u32) {
+        self.size_out
+    }
+
+    /// The average frame rate of the video
+    pub fn frame_rate(&self) -> f32 {
+        self.frame_rate
+    }
+
+    /// The number of frames in the video, as given by its container. `None` if the container
+    /// doesn't say, as is common for Matroska.
+    pub fn frame_count(&self) -> Option<u64> {
+        self.frame_count
+    }
+
+    /// Seek to the keyframe at or before the given time
+    pub fn seek(&mut self, ts_millis: i64) -> Result<(), Error> {
+        let timestamp = ts_millis * TIME_BASE_PER_MILLI;
+        self.input.seek(timestamp, ..timestamp)?;
+        self.decoder.flush();
+        self.draining = false;
+        Ok(())
+    }
+
+    /// Decode the next frame as BGR intensities in `[0, 65535]`. Returns `None` at the end of the
+    /// video.
+    pub fn decode(&mut self) -> Result<Option<Array3<f32>>, Error> {
+        let mut decoded = VideoFrame::empty();
+        loop {
+            match self.decoder.receive_frame(&mut decoded) {
+                Ok(()) => break,
+                Err(Error::Eof) => return Ok(None),
+                Err(Error::Other { errno }) if errno == ffmpeg_next::error::EAGAIN => {}
+                Err(e) => return Err(e),
+            }
+            if self.draining {
+                return Ok(None);
+            }
+            match self.input.packets().next() {
+                Some((stream, packet)) if stream.index() == self.stream_index => {
+                    self.decoder.send_packet(&packet)?;
+                }
+                Some(_) => {}
+                None => {
+                    self.decoder.send_eof()?;
+                    self.draining = true;
+                }
+            }
+        }
+
+        let mut rgb = VideoFrame::empty();
+        self.scaler.run(&decoded, &mut rgb)?;
+        Ok(Some(rgb48_intensities(
+            rgb.data(0),
+            rgb.stride(0),
+            self.size_out,
+        )))
+    }
+}
+
+/// Convert a plane of little-endian RGB48 samples, with rows `stride` bytes apart, to BGR
+/// intensities
+fn rgb48_intensities(data: &[u8], stride: usize, (width, height): (u32, u32)) -> Array3<f32> {
+    Array3::from_shape_fn((height as usize, width as usize, 3), |(y, x, c)| {
+        let offset = y * stride + (x * 3 + 2 - c) * 2;
+        f32::from(u16::from_le_bytes([data[offset], data[offset + 1]]))
+    })
+}
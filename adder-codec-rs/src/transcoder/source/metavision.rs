@@ -0,0 +1,304 @@
+use crate::transcoder::source::prophesee::DvsEvent;
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, c_long, CStr, CString};
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+
+/// The most events to take from the camera's queue at a time
+const EVENT_BATCH: usize = 4096;
+
+/// How long to wait for events before checking whether the camera is still running
+const POLL_TIMEOUT_MS: c_int = 100;
+
+/// A CD event, as laid out by the C shim
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct MvEvent {
+    x: u16,
+    y: u16,
+    p: i16,
+    t: i64,
+}
+
+/// A camera handle of the C shim (`metavision/shim.cpp`)
+#[repr(C)]
+struct MvCamera {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn mv_last_error() -> *const c_char;
+    fn mv_open(serial: *const c_char) -> *mut MvCamera;
+    fn mv_geometry(camera: *mut MvCamera, width: *mut c_int, height: *mut c_int);
+    fn mv_set_bias(camera: *mut MvCamera, name: *const c_char, value: c_int) -> c_int;
+    fn mv_start(camera: *mut MvCamera) -> c_int;
+    fn mv_poll(
+        camera: *mut MvCamera,
+        buffer: *mut MvEvent,
+        capacity: usize,
+        timeout_ms: c_int,
+    ) -> c_long;
+    fn mv_close(camera: *mut MvCamera);
+}
+
+/// A request to the thread which owns the camera
+enum Request {
+    SetBias(CString, c_int),
+    Start,
+}
+
+/// A live Prophesee camera, streamed through the Metavision SDK.
+///
+/// The camera clock starts from an arbitrary value, so event timestamps are counted in
+/// microseconds from the first event received after [`MetavisionCamera::start`].
+///
+/// The SDK's camera handle stays on a thread of its own, from opening to closing, so that it's
+/// never shared or moved between threads. This struct only talks to that thread through channels.
+pub struct MetavisionCamera {
+    /// The (width, height) of the sensor
+    size: (u16, u16),
+
+    /// Requests to the camera's thread. Dropping it stops the thread, which closes the camera.
+    requests: Option<Sender<Request>>,
+
+    /// The results of the requests, in order
+    replies: Receiver<io::Result<()>>,
+
+    /// The batches of events polled from the camera, once it's started. Disconnected once the
+    /// camera stops streaming.
+    batches: Receiver<Vec<MvEvent>>,
+
+    /// Whether the camera has been started, so that events will come
+    started: bool,
+
+    /// The events taken from the camera, not yet returned
+    events: VecDeque<DvsEvent>,
+
+    /// The camera timestamp of the first event, which the returned timestamps count from
+    first_t: Option<i64>,
+
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetavisionCamera {
+    /// Open the camera with the given serial number, or else the first one available. Events
+    /// aren't streamed until [`MetavisionCamera::start`], so the biases can be set first.
+    pub fn open(serial: Option<&str>) -> io::Result<Self> {
+        let serial = CString::new(serial.unwrap_or_default())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        let (opened_tx, opened_rx) = mpsc::channel();
+        let (requests_tx, requests_rx) = mpsc::channel();
+        let (replies_tx, replies_rx) = mpsc::channel();
+        let (batches_tx, batches_rx) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("metavision".to_string())
+            .spawn(move || {
+                run_camera(&serial, &opened_tx, &requests_rx, &replies_tx, &batches_tx)
+            })?;
+
+        let size = match opened_rx.recv() {
+            Ok(Ok(size)) => size,
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                return Err(e);
+            }
+            Err(_) => return Err(thread_lost()),
+        };
+        Ok(Self {
+            size,
+            requests: Some(requests_tx),
+            replies: replies_rx,
+            batches: batches_rx,
+            started: false,
+            events: VecDeque::new(),
+            first_t: None,
+            thread: Some(thread),
+        })
+    }
+
+    /// The (width, height) of the sensor
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    /// Set a bias of the sensor, by its name (e.g., `bias_diff_on`)
+    pub fn set_bias(&mut self, name: &str, value: i32) -> io::Result<()> {
+        let name = CString::new(name).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        self.request(Request::SetBias(name, value))
+    }
+
+    /// Start streaming events
+    pub fn start(&mut self) -> io::Result<()> {
+        self.request(Request::Start)?;
+        self.started = true;
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> io::Result<()> {
+        self.requests
+            .as_ref()
+            .and_then(|requests| requests.send(request).ok())
+            .ok_or_else(thread_lost)?;
+        self.replies.recv().map_err(|_| thread_lost())?
+    }
+
+    /// Wait for the next event. Returns an [`ErrorKind::UnexpectedEof`] error once the camera
+    /// stops streaming, e.g., when it's unplugged, or if it was never started.
+    pub fn next_event(&mut self) -> io::Result<DvsEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            if !self.started {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            let Ok(batch) = self.batches.recv() else {
+                return Err(ErrorKind::UnexpectedEof.into());
+            };
+            for event in &batch {
+                let first_t = *self.first_t.get_or_insert(event.t);
+                let t = u32::try_from(event.t.saturating_sub(first_t)).unwrap_or(u32::MAX);
+                self.events
+                    .push_back(DvsEvent::new(t, event.x, event.y, u8::from(event.p > 0)));
+            }
+        }
+    }
+}
+
+impl Drop for MetavisionCamera {
+    fn drop(&mut self) {
+        // The thread notices within a poll timeout, and closes the camera before it exits
+        self.requests = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The body of the thread which owns the camera. It reports the sensor's size once the camera is
+/// open, serves requests until the camera is started, then forwards batches of events until the
+/// camera stops or the [`MetavisionCamera`] is dropped.
+fn run_camera(
+    serial: &CStr,
+    opened: &Sender<io::Result<(u16, u16)>>,
+    requests: &Receiver<Request>,
+    replies: &Sender<io::Result<()>>,
+    batches: &Sender<Vec<MvEvent>>,
+) {
+    let handle = unsafe { mv_open(serial.as_ptr()) };
+    if handle.is_null() {
+        let _ = opened.send(Err(last_error(ErrorKind::NotFound)));
+        return;
+    }
+
+    let (mut width, mut height) = (0, 0);
+    unsafe { mv_geometry(handle, &mut width, &mut height) };
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        unsafe { mv_close(handle) };
+        let _ = opened.send(Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "invalid sensor geometry",
+        )));
+        return;
+    };
+    if opened.send(Ok((width, height))).is_ok() {
+        serve_requests(handle, requests, replies);
+        stream_events(handle, requests, replies, batches);
+    }
+    unsafe { mv_close(handle) };
+}
+
+/// Serve requests to the camera until it's started, or the [`MetavisionCamera`] is dropped
+fn serve_requests(
+    handle: *mut MvCamera,
+    requests: &Receiver<Request>,
+    replies: &Sender<io::Result<()>>,
+) {
+    while let Ok(request) = requests.recv() {
+        let start = matches!(request, Request::Start);
+        let result = handle_request(handle, request);
+        let started = start && result.is_ok();
+        if replies.send(result).is_err() || started {
+            return;
+        }
+    }
+}
+
+/// Forward batches of events until the camera stops, or the [`MetavisionCamera`] is dropped.
+/// Requests are still served between polls, e.g., to adjust the biases while streaming.
+fn stream_events(
+    handle: *mut MvCamera,
+    requests: &Receiver<Request>,
+    replies: &Sender<io::Result<()>>,
+    batches: &Sender<Vec<MvEvent>>,
+) {
+    let mut buffer = vec![MvEvent::default(); EVENT_BATCH];
+    loop {
+        match requests.try_recv() {
+            Ok(request) => {
+                if replies.send(handle_request(handle, request)).is_err() {
+                    return;
+                }
+            }
+            Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) => {}
+        }
+        let count = unsafe { mv_poll(handle, buffer.as_mut_ptr(), buffer.len(), POLL_TIMEOUT_MS) };
+        let Ok(count) = usize::try_from(count) else {
+            return;
+        };
+        if count > 0 && batches.send(buffer[..count].to_vec()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Carry out a request on the camera's thread
+fn handle_request(handle: *mut MvCamera, request: Request) -> io::Result<()> {
+    match request {
+        Request::SetBias(name, value) => {
+            let status = unsafe { mv_set_bias(handle, name.as_ptr(), value) };
+            match status {
+                0 => Ok(()),
+                _ => Err(last_error(ErrorKind::InvalidInput)),
+            }
+        }
+        Request::Start => match unsafe { mv_start(handle) } {
+            0 => Ok(()),
+            _ => Err(last_error(ErrorKind::Other)),
+        },
+    }
+}
+
+/// The camera's thread exited before answering
+fn thread_lost() -> io::Error {
+    io::Error::new(ErrorKind::BrokenPipe, "the camera thread exited")
+}
+
+/// Read a Metavision bias file, whose lines are each `<value> % <name>`, such as
+/// `0 % bias_diff_off`. Blank lines and lines starting with `%` are skipped.
+pub fn read_bias_file(path: &Path) -> io::Result<Vec<(String, i32)>> {
+    parse_biases(&std::fs::read_to_string(path)?)
+}
+
+fn parse_biases(text: &str) -> io::Result<Vec<(String, i32)>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('%'))
+        .map(|line| {
+            let bias = line.split_once('%').and_then(|(value, name)| {
+                Some((name.trim().to_string(), value.trim().parse().ok()?))
+            });
+            bias.ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidData, format!("invalid bias line: {line}"))
+            })
+        })
+        .collect()
+}
+
+fn last_error(kind: ErrorKind) -> io::Error {
+    let message = unsafe { CStr::from_ptr(mv_last_error()) };
+    io::Error::new(kind, message.to_string_lossy().into_owned())
+}
@@ -8,10 +8,23 @@ use video_rs_adder_dep::Frame;
 #[cfg(feature = "open-cv")]
 use crate::transcoder::source::davis::Davis;
 use crate::transcoder::source::framed::Framed;
+use crate::transcoder::source::framed_sequence::FramedSequenceSource;
 use crate::transcoder::source::prophesee::Prophesee;
+use crate::transcoder::source::raw_video::RawVideo;
+#[cfg(feature = "webcam")]
+use crate::transcoder::source::webcam::Webcam;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 
+/// Parsing of AEDAT 2.0, 3.x, and 4.0 DVS recordings
+pub mod aedat;
+
+/// Saving and restoring the state of a transcode, to pause and resume it
+pub mod checkpoint;
+
+/// Temporal denoising of framed input before integration
+pub mod denoise;
+
 /// Tools for transcoding from a DVS/DAVIS video source to ADΔER
 #[cfg(feature = "open-cv")]
 pub mod davis;
@@ -19,16 +32,59 @@ pub mod davis;
 /// Tools for transcoding from a framed video source to ADΔER
 pub mod framed;
 
+/// Tools for transcoding from a directory of numbered images to ADΔER
+pub mod framed_sequence;
+
+/// Decoding of 10-bit and 12-bit video at full precision
+pub mod high_bit_depth;
+
+/// Correction of lens distortion, for rectified streams
+pub mod undistort;
+
+/// Modeling of sensor noise, so that it doesn't fire events
+pub mod noise;
+
+/// Closed-loop control of the CRF to hold a target quality
+pub mod quality;
+
 /// Common functions and structs for all transcoder sources
 pub mod video;
 
+/// Synchronized transcoding of several sources with a shared clock
+pub mod multi;
+
 /// Tools for transcoding from a Prophesee video source to ADΔER
 pub mod prophesee;
 
+/// Tools for transcoding from raw video frames read from stdin or a pipe to ADΔER
+pub mod raw_video;
+
+/// Additional outputs of a transcode at other CRF levels
+mod rendition;
+
+/// Global motion estimation and compensation for stabilizing handheld framed video
+pub mod stabilization;
+
+/// Reading of DVS events recorded in ROS 2 bags
+#[cfg(feature = "rosbag")]
+pub mod rosbag;
+
+/// Live streaming from Prophesee cameras through the Metavision SDK
+#[cfg(feature = "metavision")]
+pub mod metavision;
+
+/// Tools for transcoding live from a webcam to ADΔER, without OpenCV
+#[cfg(feature = "webcam")]
+pub mod webcam;
+
 #[enum_dispatch(Source<W>)]
 pub enum AdderSource<W: Write + 'static + std::marker::Send + std::marker::Sync> {
     Framed(Framed<W>),
+    FramedSequence(FramedSequenceSource<W>),
     #[cfg(feature = "open-cv")]
     Davis(Davis<W>),
     Prophesee(Prophesee<W>),
+    RawVideo(RawVideo<W>),
+    #[cfg(feature = "webcam")]
+    Webcam(Webcam<W>),
 }
@@ -0,0 +1,276 @@
+use crate::transcoder::source::video::{utc_now_ns, Source, SourceError, VideoBuilder};
+use adder_codec_core::codec::{EncoderOptions, EncoderType, UserMetadata};
+use adder_codec_core::{Event, PixelMultiMode, SourceCamera, TimeMode};
+use std::io::Write;
+use std::sync::Arc;
+
+/// The user metadata key holding the id shared by the streams of a synchronized group
+pub const SYNC_GROUP_KEY: &str = "sync.group";
+
+/// The user metadata key holding the index of a stream in its synchronized group
+pub const SYNC_INDEX_KEY: &str = "sync.index";
+
+/// The user metadata key holding the number of streams in a synchronized group
+pub const SYNC_COUNT_KEY: &str = "sync.count";
+
+/// Where a stream belongs in a group transcoded by a [`MultiSource`], as recorded in its header
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SyncInfo {
+    /// The id shared by the streams of the group
+    pub group: u64,
+
+    /// The index of the stream in the group, in the order its sources were given
+    pub index: usize,
+
+    /// The number of streams in the group
+    pub count: usize,
+}
+
+impl SyncInfo {
+    /// Read the sync info from the user metadata of a stream's header. Returns `None` if the
+    /// stream wasn't transcoded in a synchronized group.
+    pub fn from_user_metadata(user_metadata: &UserMetadata) -> Option<Self> {
+        let get = |key: &str| -> Option<usize> { user_metadata.get(key)?.parse().ok() };
+        Some(Self {
+            group: user_metadata.get(SYNC_GROUP_KEY)?.parse().ok()?,
+            index: get(SYNC_INDEX_KEY)?,
+            count: get(SYNC_COUNT_KEY)?,
+        })
+    }
+
+    /// Add the sync info to the user metadata of a stream's header
+    pub fn add_to_user_metadata(&self, user_metadata: &mut UserMetadata) {
+        user_metadata.insert(SYNC_GROUP_KEY.to_string(), self.group.to_string());
+        user_metadata.insert(SYNC_INDEX_KEY.to_string(), self.index.to_string());
+        user_metadata.insert(SYNC_COUNT_KEY.to_string(), self.count.to_string());
+    }
+}
+
+/// The output of one source of a [`MultiSource`]
+pub struct MultiOutput<W> {
+    /// The camera type of the source, as given to [`VideoBuilder::write_out`]
+    pub source_camera: SourceCamera,
+
+    /// The type of encoder to write the stream with
+    pub encoder_type: EncoderType,
+
+    /// The encoder options of the stream. The [`SyncInfo`] is added to its user metadata.
+    pub encoder_options: EncoderOptions,
+
+    /// Where to write the stream
+    pub write: W,
+}
+
+/// A source which can join a [`MultiSource`]. It's implemented for every [`Source`] which is
+/// also a [`VideoBuilder`], and unlike [`VideoBuilder`] it's object safe, so a group can mix
+/// kinds of sources (e.g., a framed camera and an event camera) as `Box<dyn SyncSource<W>>`.
+pub trait SyncSource<W>: Source<W>
+where
+    W: Write + 'static + std::marker::Send + std::marker::Sync,
+{
+    /// Write out the stream of the source to the given output, as with
+    /// [`VideoBuilder::write_out`]
+    fn write_out_boxed(
+        self: Box<Self>,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        output: MultiOutput<W>,
+    ) -> Result<Box<dyn SyncSource<W>>, SourceError>;
+}
+
+impl<W, S> SyncSource<W> for S
+where
+    W: Write + 'static + std::marker::Send + std::marker::Sync,
+    S: Source<W> + VideoBuilder<W> + 'static,
+{
+    fn write_out_boxed(
+        self: Box<Self>,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        output: MultiOutput<W>,
+    ) -> Result<Box<dyn SyncSource<W>>, SourceError> {
+        Ok((*self).write_out(
+            output.source_camera,
+            time_mode,
+            pixel_multi_mode,
+            adu_interval,
+            output.encoder_type,
+            output.encoder_options,
+            output.write,
+        )?)
+    }
+}
+
+/// Transcodes several sources (e.g., the cameras of a stereo rig or an array) in lockstep, so
+/// that their streams share a clock.
+///
+/// Every source must have the same ticks per second, `ref_time`, and `delta_t_max`, so each
+/// [`MultiSource::consume`] integrates the same span of time from each of them, and an
+/// [`AbsoluteT`](adder_codec_core::AbsoluteT) timestamp means the same instant in every stream.
+/// The streams are also given the same epoch, and their headers record their [`SyncInfo`]. The
+/// group ends as soon as any of its sources does.
+pub struct MultiSource<W>
+where
+    W: Write + 'static + std::marker::Send + std::marker::Sync,
+{
+    sources: Vec<Box<dyn SyncSource<W>>>,
+
+    /// The id shared by the streams of the group
+    group: u64,
+}
+
+impl<W> MultiSource<W>
+where
+    W: Write + 'static + std::marker::Send + std::marker::Sync,
+{
+    /// Group the given sources, whose time parameters must already be set. They're given the
+    /// epoch of the first source which declares one, or else the current time.
+    pub fn new(mut sources: Vec<Box<dyn SyncSource<W>>>) -> Result<Self, SourceError> {
+        let Some(first) = sources.first() else {
+            return Err(SourceError::BadParams(
+                "a synchronized group needs at least one source".to_string(),
+            ));
+        };
+        let clock = |source: &dyn SyncSource<W>| {
+            let video = source.get_video_ref();
+            (
+                video.get_tps(),
+                video.get_ref_time(),
+                video.get_delta_t_max(),
+            )
+        };
+        let first_clock = clock(first.as_ref());
+        if let Some(index) = sources
+            .iter()
+            .position(|source| clock(source.as_ref()) != first_clock)
+        {
+            return Err(SourceError::BadParams(format!(
+                "source {index} has (tps, ref_time, delta_t_max) of {:?}, not {first_clock:?}",
+                clock(sources[index].as_ref())
+            )));
+        }
+
+        let epoch = sources
+            .iter()
+            .find_map(|source| source.get_video_ref().state.epoch)
+            .unwrap_or_else(utc_now_ns);
+        for source in &mut sources {
+            source.get_video_mut().state.epoch = Some(epoch);
+        }
+
+        Ok(Self {
+            sources,
+            group: rand::random(),
+        })
+    }
+
+    /// The sync info of the source at `index`, as recorded in its stream's header
+    pub fn sync_info(&self, index: usize) -> SyncInfo {
+        SyncInfo {
+            group: self.group,
+            index,
+            count: self.sources.len(),
+        }
+    }
+
+    /// Write out the stream of each source, with one output per source in the same order
+    pub fn write_out(
+        self,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        outputs: Vec<MultiOutput<W>>,
+    ) -> Result<Self, SourceError> {
+        if outputs.len() != self.sources.len() {
+            return Err(SourceError::BadParams(format!(
+                "{} outputs were given for {} sources",
+                outputs.len(),
+                self.sources.len()
+            )));
+        }
+        let sync_infos: Vec<SyncInfo> = (0..self.sources.len())
+            .map(|index| self.sync_info(index))
+            .collect();
+        let sources = self
+            .sources
+            .into_iter()
+            .zip(outputs)
+            .zip(sync_infos)
+            .map(|((source, output), sync_info)| {
+                let mut encoder_options = output.encoder_options;
+                let mut user_metadata = encoder_options
+                    .user_metadata
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default();
+                sync_info.add_to_user_metadata(&mut user_metadata);
+                encoder_options.user_metadata = Some(Arc::new(user_metadata));
+                source.write_out_boxed(
+                    time_mode,
+                    pixel_multi_mode,
+                    adu_interval,
+                    MultiOutput {
+                        encoder_options,
+                        ..output
+                    },
+                )
+            })
+            .collect::<Result<_, SourceError>>()?;
+        Ok(Self {
+            sources,
+            group: self.group,
+        })
+    }
+
+    /// Consume one interval of every source, returning the events of each in the order of the
+    /// sources. Every source consumes the interval even if another fails, so that none falls
+    /// an interval behind the others. If any source ends or fails, so does the group, and the
+    /// [`SourceError::GroupError`] holds the error of each which did.
+    pub fn consume(&mut self) -> Result<Vec<Vec<Vec<Event>>>, SourceError> {
+        gather(self.sources.iter_mut().map(|source| source.consume()))
+    }
+
+    /// Finish writing the stream of each source, returning their writers. Every stream is
+    /// finished even if another fails.
+    pub fn end_write_stream(&mut self) -> Result<Vec<Option<W>>, SourceError> {
+        gather(
+            self.sources
+                .iter_mut()
+                .map(|source| source.get_video_mut().end_write_stream()),
+        )
+    }
+
+    /// The sources of the group
+    pub fn sources(&self) -> &[Box<dyn SyncSource<W>>] {
+        &self.sources
+    }
+
+    /// The sources of the group, for adjusting their parameters between intervals
+    pub fn sources_mut(&mut self) -> &mut [Box<dyn SyncSource<W>>] {
+        &mut self.sources
+    }
+
+    /// Split the group back into its sources
+    pub fn into_sources(self) -> Vec<Box<dyn SyncSource<W>>> {
+        self.sources
+    }
+}
+
+/// Collect the result of each source without stopping at the first error, so that every
+/// source takes the same step
+fn gather<T>(results: impl Iterator<Item = Result<T, SourceError>>) -> Result<Vec<T>, SourceError> {
+    let (mut values, mut errors) = (Vec::new(), Vec::new());
+    for (index, result) in results.enumerate() {
+        match result {
+            Ok(value) => values.push(value),
+            Err(e) => errors.push((index, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(SourceError::GroupError(errors))
+    }
+}
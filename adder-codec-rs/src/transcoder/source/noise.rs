@@ -0,0 +1,62 @@
+use crate::transcoder::source::video::SourceError;
+
+/// The smallest contrast threshold which clears the noise, for each base value of a pixel on the
+/// 8-bit scale of the contrast thresholds
+pub type NoiseFloor = [u8; 256];
+
+/// A model of an image sensor's noise as a function of the intensity it measures: a constant
+/// read noise, plus a shot noise whose variance grows with the intensity. Intensities are in the
+/// units of the source samples (e.g., `[0, 255]` for 8-bit video), as are the noise parameters.
+///
+/// A pixel fires its events when its intensity moves away from its base value by more than its
+/// contrast threshold. With a noise model, that threshold is raised to a number of standard
+/// deviations of the noise at the base value, so that fluctuations of the noise alone don't fire
+/// events. Dim pixels, where the noise is large relative to the signal, need larger changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseModel {
+    /// The standard deviation of the read noise
+    pub read_noise: f32,
+
+    /// The variance of the shot noise per unit of intensity, i.e., the reciprocal of the sensor's
+    /// gain in electrons per unit
+    pub shot_noise: f32,
+
+    /// How many standard deviations of the noise a change must exceed to fire events
+    pub sigmas: f32,
+}
+
+impl NoiseModel {
+    /// Create a noise model, checking that its parameters are finite and non-negative
+    pub fn new(read_noise: f32, shot_noise: f32, sigmas: f32) -> Result<Self, SourceError> {
+        if [read_noise, shot_noise, sigmas]
+            .iter()
+            .any(|param| !param.is_finite() || *param < 0.0)
+        {
+            return Err(SourceError::BadParams(
+                "noise parameters must be finite and non-negative".to_string(),
+            ));
+        }
+        Ok(Self {
+            read_noise,
+            shot_noise,
+            sigmas,
+        })
+    }
+
+    /// The standard deviation of the noise in a measurement of the given intensity
+    pub fn std_dev(&self, intensity: f32) -> f32 {
+        (self.read_noise * self.read_noise + self.shot_noise * intensity.max(0.0)).sqrt()
+    }
+
+    /// The noise floor of each base value, for intensities which are brought to the 8-bit scale
+    /// of the contrast thresholds by multiplying by `frame_scale`
+    pub fn noise_floor(&self, frame_scale: f64) -> NoiseFloor {
+        let mut floor = [0; 256];
+        for (base_val, threshold) in floor.iter_mut().enumerate() {
+            let intensity = base_val as f64 / frame_scale;
+            let noise = f64::from(self.sigmas) * f64::from(self.std_dev(intensity as f32));
+            *threshold = (noise * frame_scale).ceil().min(f64::from(u8::MAX)) as u8;
+        }
+        floor
+    }
+}
@@ -1,7 +1,13 @@
 use crate::framer::scale_intensity::{FrameValue, SaeTime};
+use crate::transcoder::source::aedat::{is_aedat, AedatReader};
+#[cfg(feature = "metavision")]
+use crate::transcoder::source::metavision::MetavisionCamera;
+#[cfg(feature = "rosbag")]
+use crate::transcoder::source::rosbag::RosbagReader;
+use crate::transcoder::source::undistort::LensModel;
 use crate::transcoder::source::video::FramedViewMode::SAE;
 use crate::transcoder::source::video::{
-    integrate_for_px, Source, SourceError, Video, VideoBuilder,
+    integrate_for_px, ContrastMask, Crop, Orientation, Source, SourceError, Video, VideoBuilder,
 };
 use crate::utils::cv::{clamp_u8, mid_clamp_u8};
 use crate::utils::viz::ShowFeatureMode;
@@ -10,30 +16,44 @@ use adder_codec_core::Mode::Continuous;
 use adder_codec_core::{
     DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, SourceType, TimeMode,
 };
+#[cfg(feature = "rosbag")]
+use ndarray::Array2;
 use ndarray::Array3;
 use rayon::ThreadPool;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "rosbag")]
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 use tokio::runtime::Runtime;
 use video_rs_adder_dep::Frame;
 
 /// The temporal granularity of the source (ticks per second)
 const PROPHESEE_SOURCE_TPS: u32 = 1000000;
 
-/// Attributes of a framed video -> ADΔER transcode
+/// Attributes of a DVS video -> ADΔER transcode. The input is either a Prophesee `.dat` file or
+/// an AEDAT 2.0, 3.x, or 4.0 file (see [`AedatReader`]), told apart by their headers. This is
+/// the events-only counterpart of the `open-cv` feature's `Davis` source in its `RawDvs` mode,
+/// without a dependency on OpenCV. With the `rosbag` feature, it may also be a rosbag2 recording
+/// (see [`Prophesee::from_rosbag`]), and with the `metavision` feature, a live camera (see
+/// [`Prophesee::from_camera`]).
 pub struct Prophesee<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     pub(crate) video: Video<W>,
 
-    input_reader: BufReader<File>,
+    input: DvsInput,
 
     running_t: u32,
 
+    /// The start of the window to transcode, in source microseconds
     t_subtract: u32,
 
+    /// The length of the window to transcode in source microseconds, if it's limited
+    window_duration: Option<u32>,
+
     /// The timestamp (in-camera) of the last DVS event integrated for each pixel
     pub dvs_last_timestamps: Array3<u32>,
 
@@ -52,18 +72,122 @@ pub struct DvsEvent {
     p: u8,
 }
 
-unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Sync for Prophesee<W> {}
+impl DvsEvent {
+    pub(crate) fn new(t: u32, x: u16, y: u16, p: u8) -> Self {
+        Self { t, x, y, p }
+    }
+}
+
+/// The DVS event file being read
+enum DvsInput {
+    Prophesee(BufReader<File>),
+    Aedat(AedatReader<BufReader<File>>),
+    #[cfg(feature = "rosbag")]
+    Rosbag(RosbagReader),
+    #[cfg(feature = "metavision")]
+    Metavision(MetavisionCamera),
+}
+
+impl DvsInput {
+    fn next_event(&mut self) -> io::Result<DvsEvent> {
+        match self {
+            DvsInput::Prophesee(reader) => decode_event(reader),
+            DvsInput::Aedat(reader) => reader.next_event(),
+            #[cfg(feature = "rosbag")]
+            DvsInput::Rosbag(reader) => reader.next_event(),
+            #[cfg(feature = "metavision")]
+            DvsInput::Metavision(camera) => camera.next_event(),
+        }
+    }
+}
 
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
     /// Create a new `Prophesee` transcoder
     pub fn new(ref_time: u32, input_filename: String) -> Result<Self, Box<dyn Error>> {
-        let source = File::open(PathBuf::from(input_filename))?;
-        let mut input_reader = BufReader::new(source);
+        let path = PathBuf::from(input_filename);
+        #[cfg(feature = "rosbag")]
+        if path.is_dir() || path.extension().is_some_and(|extension| extension == "db3") {
+            return Self::from_rosbag(ref_time, &path, None, None);
+        }
+        let mut input_reader = BufReader::new(File::open(&path)?);
 
         // Parse header
-        let (_, _, _, size) = parse_header(&mut input_reader).unwrap();
+        let (input, size) = if is_aedat(&mut input_reader)? {
+            let reader = AedatReader::new(input_reader)?;
+            let (width, height) = match reader.size() {
+                Some(size) => size,
+                None => aedat_size(AedatReader::new(BufReader::new(File::open(&path)?))?)?,
+            };
+            (
+                DvsInput::Aedat(reader),
+                (u32::from(height), u32::from(width)),
+            )
+        } else {
+            let (_, _, _, size) = parse_header(&mut input_reader).unwrap();
+            (DvsInput::Prophesee(input_reader), size)
+        };
+        Self::with_input(ref_time, input, size)
+    }
 
-        let plane = PlaneSize::new(size.1 as u16, size.0 as u16, 1)?;
+    /// Create a new `Prophesee` transcoder reading the events of a rosbag2 recording (see
+    /// [`RosbagReader`]), from the given `EventArray` topic or else the first one in the bag. If
+    /// an `image_topic` is given, the pixels start from the intensities of its first image,
+    /// rather than mid-gray.
+    #[cfg(feature = "rosbag")]
+    pub fn from_rosbag(
+        ref_time: u32,
+        path: &Path,
+        event_topic: Option<&str>,
+        image_topic: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let reader = RosbagReader::open(path, event_topic)?;
+        let image = match image_topic {
+            Some(topic) => reader.first_image(topic)?,
+            None => None,
+        };
+        let (width, height) = reader.size();
+        let mut source = Self::with_input(
+            ref_time,
+            DvsInput::Rosbag(reader),
+            (u32::from(height), u32::from(width)),
+        )?;
+        if let Some(image) = image {
+            source.seed_intensities(&image)?;
+        }
+        Ok(source)
+    }
+
+    /// Create a new `Prophesee` transcoder streaming live from a Prophesee camera (see
+    /// [`MetavisionCamera`]) with the given serial number, or else the first one available. The
+    /// given `biases` (e.g., from [`read_bias_file`](super::metavision::read_bias_file)) are set
+    /// before the camera starts.
+    #[cfg(feature = "metavision")]
+    pub fn from_camera(
+        ref_time: u32,
+        serial: Option<&str>,
+        biases: &[(String, i32)],
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut camera = MetavisionCamera::open(serial)?;
+        for (name, value) in biases {
+            camera.set_bias(name, *value)?;
+        }
+        let (width, height) = camera.size();
+        camera.start()?;
+        Self::with_input(
+            ref_time,
+            DvsInput::Metavision(camera),
+            (u32::from(height), u32::from(width)),
+        )
+    }
+
+    /// Create a new `Prophesee` transcoder reading from `input`, for a sensor of the given
+    /// (height, width)
+    fn with_input(
+        ref_time: u32,
+        input: DvsInput,
+        size: (u32, u32),
+    ) -> Result<Self, Box<dyn Error>> {
+        let plane = PlaneSize::new(size.1, size.0, 1)?;
 
         let mut video = Video::new(plane, Continuous, None)?
             .chunk_rows(1)
@@ -80,7 +204,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
 
         let start_intensities = vec![128_u8; video.state.plane.volume()];
         video.state.running_intensities = Array3::from_shape_vec(
-            (plane.h().into(), plane.w().into(), plane.c().into()),
+            (plane.h_usize(), plane.w_usize(), plane.c_usize()),
             start_intensities,
         )?;
         video.display_frame_features = video.state.running_intensities.clone();
@@ -88,7 +212,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
         let timestamps = vec![2_u32; video.state.plane.volume()];
 
         let dvs_last_timestamps: Array3<u32> = Array3::from_shape_vec(
-            (plane.h().into(), plane.w().into(), plane.c().into()),
+            (plane.h_usize(), plane.w_usize(), plane.c_usize()),
             timestamps,
         )?;
 
@@ -103,9 +227,10 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
 
         let prophesee_source = Prophesee {
             video,
-            input_reader,
+            input,
             running_t: 0,
             t_subtract: 0,
+            window_duration: None,
             dvs_last_timestamps,
             dvs_last_ln_val,
             camera_theta: 0.02, // A fixed assumption
@@ -113,6 +238,36 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Prophesee<W> {
 
         Ok(prophesee_source)
     }
+
+    /// Rectify the events, moving each one to the undistorted position of its pixel for the given
+    /// lens, so that the stream lines up with an ideal pinhole camera. Events which land outside
+    /// the sensor are dropped. The undistorted pixels don't line up one-to-one with the sensor's,
+    /// so a few may never receive events. `None` removes the correction.
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        self.video = self.video.lens_undistortion(model)?;
+        Ok(self)
+    }
+
+    /// Start each pixel from the given 8-bit intensity, instead of mid-gray
+    #[cfg(feature = "rosbag")]
+    fn seed_intensities(&mut self, image: &Array2<u8>) -> Result<(), SourceError> {
+        let plane = self.video.state.plane;
+        if image.dim() != (plane.h_usize(), plane.w_usize()) {
+            return Err(SourceError::BadParams(format!(
+                "the image is {}x{}, but the events are {}x{}",
+                image.ncols(),
+                image.nrows(),
+                plane.w(),
+                plane.h()
+            )));
+        }
+        for ((y, x), &intensity) in image.indexed_iter() {
+            self.video.state.running_intensities[[y, x, 0]] = intensity;
+            self.dvs_last_ln_val[[y, x, 0]] = (f64::from(intensity) / 255.0).ln_1p();
+        }
+        self.video.display_frame_features = self.video.state.running_intensities.clone();
+        Ok(())
+    }
 }
 
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for Prophesee<W> {
@@ -146,14 +301,26 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for P
         loop {
             // TODO: integrate to fill in the rest of time once the eof is reached
 
-            dvs_event = match decode_event(&mut self.input_reader) {
+            dvs_event = match self.input.next_event() {
                 Ok(mut dvs_event) => {
                     // if self.running_t == 2 && dvs_events.is_empty() {
                     //     self.t_subtract = dvs_event.t;
                     //     eprintln!("t_subtract: {}", self.t_subtract);
                     // }
 
+                    // Skip the events before the start of the window, and end the input at its
+                    // end, just like at the end of the file
+                    if dvs_event.t < self.t_subtract {
+                        continue;
+                    }
                     dvs_event.t -= self.t_subtract;
+                    if self
+                        .window_duration
+                        .is_some_and(|duration| dvs_event.t >= duration)
+                    {
+                        end_events(self);
+                        return Err(SourceError::BufferEmpty);
+                    }
 
                     if dvs_event.t > self.running_t {
                         self.running_t = dvs_event.t;
@@ -178,8 +345,17 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for P
         // For every dvs event in our queue, integrate the previously seen intensity for all the
         // time between the pixel's last input and the current event
         for dvs_event in dvs_events {
-            let x = dvs_event.x as usize;
-            let y = dvs_event.y as usize;
+            let (x, y) = match &self.video.state.undistortion {
+                Some(undistortion) => {
+                    match undistortion.undistort_coord(dvs_event.x, dvs_event.y) {
+                        Some(coord) => coord,
+                        None => continue,
+                    }
+                }
+                None => (dvs_event.x, dvs_event.y),
+            };
+            let x = x as usize;
+            let y = y as usize;
             let p = dvs_event.p as usize;
             let t = dvs_event.t;
 
@@ -290,11 +466,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Source<W> for P
 
         self.video.handle_features(&events_nested)?;
 
-        for events in &events_nested {
-            for event in events {
-                self.video.encoder.ingest_event(*event)?;
-            }
-        }
+        self.video.encode_events(&events_nested)?;
 
         Ok(events_nested)
     }
@@ -424,6 +596,22 @@ fn parse_header(file: &mut BufReader<File>) -> io::Result<(u64, u8, u8, (u32, u3
     ))
 }
 
+/// Find the size of the sensor of an AEDAT file which doesn't name it, from the largest event
+/// addresses in the file
+fn aedat_size<R: BufRead>(mut reader: AedatReader<R>) -> io::Result<(u16, u16)> {
+    let (mut width, mut height) = (1, 1);
+    loop {
+        match reader.next_event() {
+            Ok(event) => {
+                width = width.max(event.x + 1);
+                height = height.max(event.y + 1);
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok((width, height)),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 fn line_to_hw(words: Vec<&[u8]>) -> Option<u32> {
     let word = words.get(2).unwrap();
     let mut new_word = *word;
@@ -453,6 +641,11 @@ fn decode_event(reader: &mut BufReader<File>) -> io::Result<DvsEvent> {
     Ok(DvsEvent { t, x, y, p })
 }
 
+/// Convert a length of time to source microseconds, saturating at the largest timestamp
+fn duration_to_us(duration: Duration) -> u32 {
+    u32::try_from(duration.as_micros()).unwrap_or(u32::MAX)
+}
+
 impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W> for Prophesee<W> {
     fn crf(mut self, crf: u8) -> Self {
         self.video.update_crf(crf);
@@ -482,6 +675,31 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W>
         self
     }
 
+    /// Events can't be cropped, so a crop is rejected
+    fn crop(self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        match crop {
+            Some(_) => Err(SourceError::BadParams(
+                "Event sources can't be cropped".to_string(),
+            )),
+            None => Ok(self),
+        }
+    }
+
+    /// Events can't be turned, so an orientation is rejected
+    fn orientation(self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        match orientation {
+            Some(orientation) if !orientation.is_identity() => Err(SourceError::BadParams(
+                "Event sources can't be flipped or rotated".to_string(),
+            )),
+            _ => Ok(self),
+        }
+    }
+
+    fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.video = self.video.contrast_mask(mask)?;
+        Ok(self)
+    }
+
     fn time_parameters(
         mut self,
         tps: DeltaT,
@@ -522,6 +740,16 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> VideoBuilder<W>
         self
     }
 
+    fn start_time(mut self, start_time: Duration) -> Result<Self, SourceError> {
+        self.t_subtract = duration_to_us(start_time);
+        Ok(self)
+    }
+
+    fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.window_duration = duration.map(duration_to_us);
+        self
+    }
+
     #[cfg(feature = "feature-logging")]
     fn log_path(self, _name: String) -> Self {
         todo!()
@@ -0,0 +1,114 @@
+use crate::transcoder::source::video::SourceError;
+use adder_codec_core::codec::rate_controller::CRF;
+use ndarray::Array3;
+use serde::{Deserialize, Serialize};
+
+/// How much of each interval's MSE goes into the smoothed MSE, so that the CRF follows the
+/// quality of the scene rather than the noise of single frames
+const MSE_SMOOTHING: f64 = 0.1;
+
+/// How far the [`QualityController`] moves the CRF level, per interval, for each dB of PSNR away
+/// from its target
+const QUALITY_GAIN: f64 = 0.02;
+
+/// The PSNR, in dB, of 8-bit frames with the given mean squared error
+pub fn psnr_from_mse(mse: f64) -> f64 {
+    // Make sure that PSNR isn't undefined for identical frames
+    10.0 * (f64::from(u8::MAX).powi(2) / mse.max(0.000_000_1)).log10()
+}
+
+/// A quality for the transcoder to hold, analogous to the constant-quality mode of conventional
+/// encoders. Each interval, the frame reconstructed from the pixels' latest events is compared
+/// against the source frame, and the CRF is lowered (less loss) while the quality is below the
+/// target, or raised (more loss, fewer events) while it's above.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QualityTarget {
+    /// The target peak signal-to-noise ratio, in dB, on the 8-bit scale of the contrast
+    /// thresholds
+    pub psnr: f64,
+
+    /// How far, in dB, the smoothed PSNR may stray from the target before the CRF is adjusted
+    pub tolerance: f64,
+}
+
+impl QualityTarget {
+    /// Target a PSNR in dB, checking that it and the tolerance are finite and positive
+    pub fn new(psnr: f64, tolerance: f64) -> Result<Self, SourceError> {
+        if !(psnr.is_finite() && psnr > 0.0) || !(tolerance.is_finite() && tolerance >= 0.0) {
+            return Err(SourceError::BadParams(format!(
+                "target PSNR must be positive and its tolerance non-negative, not {psnr} and \
+                 {tolerance}"
+            )));
+        }
+        Ok(Self { psnr, tolerance })
+    }
+
+    /// Target a mean squared error on the 8-bit scale instead, as its equivalent PSNR
+    pub fn from_mse(mse: f64, tolerance: f64) -> Result<Self, SourceError> {
+        if !(mse.is_finite() && mse > 0.0) {
+            return Err(SourceError::BadParams(format!(
+                "target MSE must be positive, not {mse}"
+            )));
+        }
+        Self::new(psnr_from_mse(mse), tolerance)
+    }
+}
+
+/// Closed-loop control of the CRF level to hold a [`QualityTarget`]. The CRF level is kept
+/// fractional, so that small, persistent errors still move it over time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct QualityController {
+    target: QualityTarget,
+
+    /// The exponential moving average of the MSE of each interval
+    mse: Option<f64>,
+
+    /// The current CRF level
+    crf: f64,
+
+    /// The 8-bit intensities reconstructed from the pixels' latest events, kept here while the
+    /// view mode isn't [`Intensity`](crate::transcoder::source::video::FramedViewMode::Intensity),
+    /// since the running intensities of the transcode then hold something else
+    pub(crate) reconstruction: Option<Array3<u8>>,
+}
+
+impl QualityController {
+    pub(crate) fn new(target: QualityTarget, crf: u8) -> Self {
+        Self {
+            target,
+            mse: None,
+            crf: f64::from(crf),
+            reconstruction: None,
+        }
+    }
+
+    pub(crate) fn target(&self) -> QualityTarget {
+        self.target
+    }
+
+    /// Change the target, keeping the CRF level reached so far
+    pub(crate) fn set_target(&mut self, target: QualityTarget) {
+        self.target = target;
+    }
+
+    /// The smoothed PSNR of the intervals observed so far
+    pub(crate) fn psnr(&self) -> Option<f64> {
+        self.mse.map(psnr_from_mse)
+    }
+
+    /// Adjust the CRF level after an interval was reconstructed with the given MSE, and return
+    /// the level to transcode the next interval with
+    pub(crate) fn observe(&mut self, mse: f64) -> u8 {
+        let smoothed = match self.mse {
+            Some(smoothed) => smoothed + MSE_SMOOTHING * (mse - smoothed),
+            None => mse,
+        };
+        self.mse = Some(smoothed);
+
+        let error = psnr_from_mse(smoothed) - self.target.psnr;
+        if error.abs() > self.target.tolerance {
+            self.crf = (self.crf + QUALITY_GAIN * error).clamp(0.0, (CRF.len() - 1) as f64);
+        }
+        self.crf.round() as u8
+    }
+}
@@ -0,0 +1,772 @@
+use crate::transcoder::source::denoise::TemporalFilter;
+use crate::transcoder::source::noise::NoiseModel;
+use crate::transcoder::source::quality::QualityTarget;
+use crate::transcoder::source::undistort::LensModel;
+use crate::transcoder::source::video::Video;
+use crate::transcoder::source::video::{
+    ContrastMask, Crop, IntensityLut, Orientation, SourceError,
+};
+use crate::transcoder::source::video::{Source, VideoBuilder};
+use adder_codec_core::Mode::FramePerfect;
+use adder_codec_core::{
+    is_framed, ChromaSubsampling, ColorSpace, DeltaT, Event, PixelAddress, PixelMultiMode,
+    PlaneSize, SourceCamera, TimeMode,
+};
+
+use crate::utils::cv::luma_intensities;
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+
+use ndarray::{Array2, Array3, Axis};
+use std::io::{ErrorKind, Read, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(feature = "feature-logging")]
+use chrono::Local;
+use video_rs_adder_dep::Frame;
+
+/// The pixel format of raw video frames. The names match FFmpeg's `-pix_fmt` names, so that the
+/// output of `ffmpeg -f rawvideo -pix_fmt <format> -` can be piped straight into a [`RawVideo`]
+/// source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawPixelFormat {
+    /// 8-bit grayscale (`gray`)
+    Gray,
+    /// 16-bit little-endian grayscale (`gray16le`)
+    Gray16Le,
+    /// 8-bit packed RGB (`rgb24`)
+    Rgb24,
+    /// 8-bit packed BGR (`bgr24`)
+    Bgr24,
+    /// 16-bit little-endian packed RGB (`rgb48le`)
+    Rgb48Le,
+    /// 8-bit Bayer mosaic, as read from the sensor of a raw camera (`bayer_rggb8`, etc.)
+    Bayer8(BayerPattern),
+    /// 16-bit little-endian Bayer mosaic (`bayer_rggb16le`, etc.)
+    Bayer16Le(BayerPattern),
+    /// 8-bit planar YCbCr, with chroma planes at half resolution in each dimension (`yuv420p`)
+    Yuv420p,
+    /// 8-bit planar YCbCr, with chroma planes at full resolution (`yuv444p`)
+    Yuv444p,
+    /// 16-bit little-endian planar YCbCr, with chroma planes at half resolution in each
+    /// dimension (`yuv420p16le`)
+    Yuv420p16Le,
+    /// 16-bit little-endian planar YCbCr, with chroma planes at full resolution (`yuv444p16le`)
+    Yuv444p16Le,
+}
+
+/// The color filter pattern of a Bayer sensor, named by the colors of its top-left 2x2 block,
+/// row by row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BayerPattern {
+    /// Red, green / green, blue
+    Rggb,
+    /// Blue, green / green, red
+    Bggr,
+    /// Green, red / blue, green
+    Grbg,
+    /// Green, blue / red, green
+    Gbrg,
+}
+
+impl BayerPattern {
+    /// The BGR channel index of the filter over the pixel at (`x`, `y`)
+    pub fn channel(self, x: usize, y: usize) -> usize {
+        let block = match self {
+            BayerPattern::Rggb => [2, 1, 1, 0],
+            BayerPattern::Bggr => [0, 1, 1, 2],
+            BayerPattern::Grbg => [1, 2, 0, 1],
+            BayerPattern::Gbrg => [1, 0, 2, 1],
+        };
+        block[(y % 2) * 2 + x % 2]
+    }
+}
+
+impl RawPixelFormat {
+    /// The number of samples of each pixel. A Bayer mosaic has a single sample per pixel, of the
+    /// color of its filter. A planar YCbCr pixel has 3 samples, though a 4:2:0 pixel shares its
+    /// chroma samples with the rest of its 2x2 block.
+    pub fn channels(self) -> usize {
+        match self {
+            RawPixelFormat::Gray
+            | RawPixelFormat::Gray16Le
+            | RawPixelFormat::Bayer8(_)
+            | RawPixelFormat::Bayer16Le(_) => 1,
+            RawPixelFormat::Rgb24
+            | RawPixelFormat::Bgr24
+            | RawPixelFormat::Rgb48Le
+            | RawPixelFormat::Yuv420p
+            | RawPixelFormat::Yuv444p
+            | RawPixelFormat::Yuv420p16Le
+            | RawPixelFormat::Yuv444p16Le => 3,
+        }
+    }
+
+    /// The number of bytes of each sample
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            RawPixelFormat::Gray
+            | RawPixelFormat::Rgb24
+            | RawPixelFormat::Bgr24
+            | RawPixelFormat::Bayer8(_)
+            | RawPixelFormat::Yuv420p
+            | RawPixelFormat::Yuv444p => 1,
+            RawPixelFormat::Gray16Le
+            | RawPixelFormat::Rgb48Le
+            | RawPixelFormat::Bayer16Le(_)
+            | RawPixelFormat::Yuv420p16Le
+            | RawPixelFormat::Yuv444p16Le => 2,
+        }
+    }
+
+    /// Whether the frames are planar YCbCr
+    pub fn is_ycbcr(self) -> bool {
+        matches!(
+            self,
+            RawPixelFormat::Yuv420p
+                | RawPixelFormat::Yuv444p
+                | RawPixelFormat::Yuv420p16Le
+                | RawPixelFormat::Yuv444p16Le
+        )
+    }
+
+    /// The (width, height) of the chroma planes of a planar YCbCr frame, or `None` for the other
+    /// formats
+    pub fn chroma_plane_size(self, width: usize, height: usize) -> Option<(usize, usize)> {
+        match self {
+            RawPixelFormat::Yuv420p | RawPixelFormat::Yuv420p16Le => Some((width / 2, height / 2)),
+            RawPixelFormat::Yuv444p | RawPixelFormat::Yuv444p16Le => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes of a `width`x`height` frame
+    pub fn frame_len(self, width: usize, height: usize) -> usize {
+        let samples = match self.chroma_plane_size(width, height) {
+            Some((chroma_width, chroma_height)) => {
+                width * height + 2 * chroma_width * chroma_height
+            }
+            None => width * height * self.channels(),
+        };
+        samples * self.bytes_per_sample()
+    }
+
+    /// The filter pattern of a Bayer mosaic, or `None` for the other formats
+    pub fn bayer_pattern(self) -> Option<BayerPattern> {
+        match self {
+            RawPixelFormat::Bayer8(pattern) | RawPixelFormat::Bayer16Le(pattern) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    /// Whether the frames hold color
+    pub fn is_color(self) -> bool {
+        self.channels() == 3 || self.bayer_pattern().is_some()
+    }
+
+    /// The source camera matching the bit depth of the samples
+    pub fn source_camera(self) -> SourceCamera {
+        match self.bytes_per_sample() {
+            1 => SourceCamera::FramedU8,
+            _ => SourceCamera::FramedU16,
+        }
+    }
+}
+
+impl FromStr for RawPixelFormat {
+    type Err = SourceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gray" => Ok(RawPixelFormat::Gray),
+            "gray16le" => Ok(RawPixelFormat::Gray16Le),
+            "rgb24" => Ok(RawPixelFormat::Rgb24),
+            "bgr24" => Ok(RawPixelFormat::Bgr24),
+            "rgb48le" => Ok(RawPixelFormat::Rgb48Le),
+            "bayer_rggb8" => Ok(RawPixelFormat::Bayer8(BayerPattern::Rggb)),
+            "bayer_bggr8" => Ok(RawPixelFormat::Bayer8(BayerPattern::Bggr)),
+            "bayer_grbg8" => Ok(RawPixelFormat::Bayer8(BayerPattern::Grbg)),
+            "bayer_gbrg8" => Ok(RawPixelFormat::Bayer8(BayerPattern::Gbrg)),
+            "bayer_rggb16le" => Ok(RawPixelFormat::Bayer16Le(BayerPattern::Rggb)),
+            "bayer_bggr16le" => Ok(RawPixelFormat::Bayer16Le(BayerPattern::Bggr)),
+            "bayer_grbg16le" => Ok(RawPixelFormat::Bayer16Le(BayerPattern::Grbg)),
+            "bayer_gbrg16le" => Ok(RawPixelFormat::Bayer16Le(BayerPattern::Gbrg)),
+            "yuv420p" => Ok(RawPixelFormat::Yuv420p),
+            "yuv444p" => Ok(RawPixelFormat::Yuv444p),
+            "yuv420p16le" => Ok(RawPixelFormat::Yuv420p16Le),
+            "yuv444p16le" => Ok(RawPixelFormat::Yuv444p16Le),
+            _ => Err(SourceError::BadParams(format!(
+                "unsupported raw pixel format `{s}`"
+            ))),
+        }
+    }
+}
+
+/// Attributes of a raw video -> ADΔER transcode. Frames of the caller-specified pixel format
+/// and resolution are read back to back from any reader, such as stdin or a pipe, so no
+/// temporary video file is needed.
+///
+/// Bayer mosaics from raw cameras are demosaiced before integration, unless
+/// [`RawVideo::bayer_planes`] is set.
+///
+/// Planar YCbCr frames, as output by most video decoders, are transcoded directly with
+/// `color_input`, skipping the conversion to BGR. The stream is marked as
+/// [`ColorSpace::YCbCr`], so that its frames are converted back to BGR when they're
+/// reconstructed. The chroma planes of 4:2:0 frames are upsampled to full resolution, so such a
+/// source is best transcoded with [`ChromaSubsampling::Half`]. Without `color_input`, only the
+/// luma plane is transcoded.
+pub struct RawVideo<W: Write + 'static + std::marker::Send + std::marker::Sync> {
+    reader: Box<dyn Read + Send>,
+
+    /// The bytes of the frame being read
+    buffer: Vec<u8>,
+
+    pub(crate) input_frame: Frame,
+
+    /// The pixel format of the frames
+    pub pix_fmt: RawPixelFormat,
+
+    /// Number of frames read so far
+    frame_idx: u32,
+
+    /// Number of frames read before the window set by [`VideoBuilder::start_time`]
+    window_frame_start: u32,
+
+    /// The number of frames to transcode from the start of the window, if it's limited by
+    /// [`VideoBuilder::duration`]
+    window_frame_count: Option<u32>,
+
+    /// FPS of the input video, as given to `RawVideo::new()`
+    pub source_fps: f32,
+
+    /// Whether the input video is color. Always false for grayscale pixel formats.
+    color_input: bool,
+
+    /// Whether to transcode the planes of a Bayer mosaic separately, rather than demosaicing it
+    bayer_planes: bool,
+
+    pub(crate) video: Video<W>,
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> RawVideo<W> {
+    /// Create a new `RawVideo` source, reading `width`x`height` frames of the given pixel format
+    /// from `reader`
+    pub fn new<R: Read + Send + 'static>(
+        reader: R,
+        pix_fmt: RawPixelFormat,
+        width: PixelAddress,
+        height: PixelAddress,
+        source_fps: f32,
+        color_input: bool,
+    ) -> Result<RawVideo<W>, SourceError> {
+        if source_fps.is_nan() || source_fps <= 0.0 {
+            return Err(SourceError::BadParams(
+                "source FPS must be greater than zero".to_string(),
+            ));
+        }
+        let color_input = color_input && pix_fmt.is_color();
+        let subsampled = matches!(
+            pix_fmt,
+            RawPixelFormat::Yuv420p | RawPixelFormat::Yuv420p16Le
+        );
+        if (pix_fmt.bayer_pattern().is_some() || subsampled) && (width % 2 != 0 || height % 2 != 0)
+        {
+            return Err(SourceError::BadParams(format!(
+                "{pix_fmt:?} frames must have an even width and height"
+            )));
+        }
+        let plane = PlaneSize::new(width, height, if color_input { 3 } else { 1 })?;
+
+        let mut video = Video::new(plane, FramePerfect, None)?;
+        video.state.source_camera = pix_fmt.source_camera();
+        if color_input && pix_fmt.is_ycbcr() {
+            video = video.color_space(ColorSpace::YCbCr)?;
+        }
+
+        Ok(RawVideo {
+            reader: Box::new(reader),
+            buffer: vec![0; pix_fmt.frame_len(width as usize, height as usize)],
+            input_frame: Frame::default((height as usize, width as usize, 3)),
+            pix_fmt,
+            frame_idx: 0,
+            window_frame_start: 0,
+            window_frame_count: None,
+            source_fps,
+            color_input,
+            bayer_planes: false,
+            video,
+        })
+    }
+
+    /// Create a new `RawVideo` source reading from stdin, e.g., piped from
+    /// `ffmpeg -i <video> -f rawvideo -pix_fmt <format> -`
+    pub fn from_stdin(
+        pix_fmt: RawPixelFormat,
+        width: PixelAddress,
+        height: PixelAddress,
+        source_fps: f32,
+        color_input: bool,
+    ) -> Result<RawVideo<W>, SourceError> {
+        Self::new(
+            std::io::stdin(),
+            pix_fmt,
+            width,
+            height,
+            source_fps,
+            color_input,
+        )
+    }
+
+    /// The source camera matching the bit depth of the frames, to give to
+    /// [`VideoBuilder::write_out`]
+    pub fn source_camera(&self) -> SourceCamera {
+        self.pix_fmt.source_camera()
+    }
+
+    /// Transcode each plane of a Bayer mosaic separately, instead of demosaicing it. This skips
+    /// the lossy interpolation of the missing colors.
+    ///
+    /// The planes are laid out as the four quarters of a single channel frame, in the order of
+    /// the filter pattern. For RGGB, that's red at the top left, the two greens at the top right
+    /// and bottom left, and blue at the bottom right. As it's a single channel, the source must
+    /// not be made with `color_input`.
+    pub fn bayer_planes(mut self, bayer_planes: bool) -> Result<Self, SourceError> {
+        if bayer_planes && self.pix_fmt.bayer_pattern().is_none() {
+            return Err(SourceError::BadParams(format!(
+                "{:?} frames are not a Bayer mosaic",
+                self.pix_fmt
+            )));
+        }
+        if bayer_planes && self.color_input {
+            return Err(SourceError::BadParams(
+                "Bayer planes are transcoded as a single channel, not color".to_string(),
+            ));
+        }
+        self.bayer_planes = bayer_planes;
+        Ok(self)
+    }
+
+    /// Transcode the color channels with the given subsampling. See
+    /// [`Video::chroma_subsampling`].
+    pub fn chroma_subsampling(
+        mut self,
+        chroma_subsampling: ChromaSubsampling,
+    ) -> Result<Self, SourceError> {
+        self.video = self.video.chroma_subsampling(chroma_subsampling)?;
+        Ok(self)
+    }
+
+    /// Apply a lookup table to the input intensities before integration. See
+    /// [`Video::intensity_lut`].
+    pub fn intensity_lut(mut self, lut: Option<IntensityLut>) -> Self {
+        self.video = self.video.intensity_lut(lut);
+        self
+    }
+
+    /// Model the noise of the sensor, so that it doesn't fire events. See
+    /// [`Video::noise_model`].
+    pub fn noise_model(mut self, model: Option<NoiseModel>) -> Self {
+        self.video = self.video.noise_model(model);
+        self
+    }
+
+    /// Adjust the CRF to hold a target quality. See [`Video::quality_target`].
+    pub fn quality_target(mut self, target: Option<QualityTarget>) -> Self {
+        self.video = self.video.quality_target(target);
+        self
+    }
+
+    /// Denoise the input frames over time before integration. See
+    /// [`Video::temporal_filter`].
+    pub fn temporal_filter(mut self, filter: Option<TemporalFilter>) -> Result<Self, SourceError> {
+        self.video = self.video.temporal_filter(filter)?;
+        Ok(self)
+    }
+
+    /// Cap the number of events each pixel may fire per second. See [`Video::event_budget`].
+    pub fn event_budget(mut self, events_per_second: Option<u32>) -> Self {
+        self.video = self.video.event_budget(events_per_second);
+        self
+    }
+
+    /// Integrate the pixels which don't fire on the GPU. See [`Video::gpu_integration`].
+    pub fn gpu_integration(mut self, enabled: bool) -> Result<Self, SourceError> {
+        self.video = self.video.gpu_integration(enabled)?;
+        Ok(self)
+    }
+
+    /// Correct the lens distortion of the input frames before integration. See
+    /// [`Video::lens_undistortion`].
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        self.video = self.video.lens_undistortion(model)?;
+        Ok(self)
+    }
+
+    /// Automatically derive the ticks per second from the source FPS and `ref_time`
+    pub fn auto_time_parameters(
+        mut self,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            let tps = (ref_time as f32 * self.source_fps) as DeltaT;
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            return Err(SourceError::BadParams(
+                "delta_t_max must be a multiple of ref_time".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Get the number of ticks each frame is said to span
+    pub fn get_ref_time(&self) -> u32 {
+        self.video.state.params.ref_time
+    }
+
+    /// Get the previous input frame, scaled to 8 bits
+    pub fn get_last_input_frame(&self) -> &Frame {
+        &self.input_frame
+    }
+
+    /// Convert a length of time to a number of input frames
+    fn duration_to_frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() * f64::from(self.source_fps)).round() as u32
+    }
+
+    /// Read the bytes of the next frame. Running out of input, even partway through a frame,
+    /// ends the video.
+    fn read_frame(&mut self) -> Result<(), SourceError> {
+        match self.reader.read_exact(&mut self.buffer) {
+            Ok(()) => {
+                self.frame_idx += 1;
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Err(SourceError::BufferEmpty),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Convert the frame just read to intensities on the scale of its bit depth, with 3 color
+    /// channels (in BGR order, like the other framed sources, or YCbCr for planar YCbCr frames)
+    /// or a single luma channel. Bayer mosaics are demosaiced, or else rearranged into their
+    /// separate planes.
+    fn frame_intensities(&self) -> Result<Array3<f32>, SourceError> {
+        let plane = self.video.state.plane;
+        let samples: Vec<f32> = match self.pix_fmt.bytes_per_sample() {
+            1 => self
+                .buffer
+                .iter()
+                .map(|&sample| f32::from(sample))
+                .collect(),
+            _ => self
+                .buffer
+                .chunks_exact(2)
+                .map(|bytes| f32::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+                .collect(),
+        };
+        if let Some(chroma_size) = self
+            .pix_fmt
+            .chroma_plane_size(plane.w_usize(), plane.h_usize())
+        {
+            return ycbcr_planes(&samples, plane, chroma_size, self.color_input);
+        }
+        let mut intensities = Array3::from_shape_vec(
+            (plane.h_usize(), plane.w_usize(), self.pix_fmt.channels()),
+            samples,
+        )?;
+        if let Some(pattern) = self.pix_fmt.bayer_pattern() {
+            let mosaic = intensities.index_axis_move(Axis(2), 0);
+            if self.bayer_planes {
+                return Ok(bayer_planes(&mosaic));
+            }
+            let bgr = demosaic(&mosaic, pattern);
+            if self.color_input {
+                return Ok(bgr);
+            }
+            return Ok(luma_intensities(&bgr));
+        }
+        if matches!(
+            self.pix_fmt,
+            RawPixelFormat::Rgb24 | RawPixelFormat::Rgb48Le
+        ) {
+            intensities.invert_axis(Axis(2));
+        }
+        if self.pix_fmt.channels() == 3 && !self.color_input {
+            return Ok(luma_intensities(&intensities));
+        }
+        Ok(intensities)
+    }
+}
+
+/// Arrange the samples of a planar YCbCr frame as (Y, Cb, Cr) channels, upsampling subsampled
+/// chroma planes by repeating each sample over its block. Without `color_input`, only the luma
+/// plane is kept.
+fn ycbcr_planes(
+    samples: &[f32],
+    plane: PlaneSize,
+    (chroma_width, chroma_height): (usize, usize),
+    color_input: bool,
+) -> Result<Array3<f32>, SourceError> {
+    let (width, height) = (plane.w_usize(), plane.h_usize());
+    let luma_len = width * height;
+    let luma = Array2::from_shape_vec((height, width), samples[..luma_len].to_vec())?;
+    if !color_input {
+        return Ok(luma.insert_axis(Axis(2)));
+    }
+    let chroma_len = chroma_width * chroma_height;
+    let cb = Array2::from_shape_vec(
+        (chroma_height, chroma_width),
+        samples[luma_len..luma_len + chroma_len].to_vec(),
+    )?;
+    let cr = Array2::from_shape_vec(
+        (chroma_height, chroma_width),
+        samples[luma_len + chroma_len..luma_len + 2 * chroma_len].to_vec(),
+    )?;
+    let (block_width, block_height) = (width / chroma_width, height / chroma_height);
+    Ok(Array3::from_shape_fn(
+        (height, width, 3),
+        |(y, x, c)| match c {
+            0 => luma[[y, x]],
+            1 => cb[[y / block_height, x / block_width]],
+            _ => cr[[y / block_height, x / block_width]],
+        },
+    ))
+}
+
+/// Interpolate the missing colors of each pixel of a Bayer mosaic (with an even width and
+/// height) bilinearly, from the neighboring pixels with filters of that color
+fn demosaic(mosaic: &Array2<f32>, pattern: BayerPattern) -> Array3<f32> {
+    let (height, width) = mosaic.dim();
+    Array3::from_shape_fn((height, width, 3), |(y, x, c)| {
+        if pattern.channel(x, y) == c {
+            return mosaic[[y, x]];
+        }
+        // Every 2x2 block has each color, so even a corner pixel has a neighbor of each color
+        let (mut sum, mut count) = (0.0, 0.0);
+        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                if pattern.channel(nx, ny) == c {
+                    sum += mosaic[[ny, nx]];
+                    count += 1.0;
+                }
+            }
+        }
+        sum / count
+    })
+}
+
+/// Rearrange a Bayer mosaic (with an even width and height) into a single channel frame whose
+/// quarters are the planes of each position in the 2x2 filter pattern
+fn bayer_planes(mosaic: &Array2<f32>) -> Array3<f32> {
+    let (height, width) = mosaic.dim();
+    let (half_height, half_width) = (height / 2, width / 2);
+    Array3::from_shape_fn((height, width, 1), |(y, x, _)| {
+        let (plane_y, plane_x) = (y / half_height, x / half_width);
+        mosaic[[
+            (y % half_height) * 2 + plane_y,
+            (x % half_width) * 2 + plane_x,
+        ]]
+    })
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for RawVideo<W> {
+    /// Read the next frame, and integrate its pixel-wise intensities with `ref_time` (the number
+    /// of ticks each frame is said to span)
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if let Some(count) = self.window_frame_count {
+            if self.frame_idx >= self.window_frame_start.saturating_add(count) {
+                return Err(SourceError::BufferEmpty);
+            }
+        }
+        self.read_frame()?;
+        let intensities = self.frame_intensities()?;
+
+        let max_intensity = self.source_camera().source_type().max_intensity();
+        let scale = f32::from(u8::MAX) / max_intensity as f32;
+        let display_intensities = self.video.fit_to_plane(intensities.clone());
+        self.input_frame = display_intensities.mapv(|intensity| (intensity * scale) as u8);
+        if self.video.state.color_space == ColorSpace::YCbCr {
+            // The input frame is for display, so it's always BGR
+            for (mut display, pixel) in self
+                .input_frame
+                .lanes_mut(Axis(2))
+                .into_iter()
+                .zip(display_intensities.lanes(Axis(2)))
+            {
+                let ycbcr = [pixel[0], pixel[1], pixel[2]].map(f64::from);
+                let bgr = ColorSpace::ycbcr_to_bgr(ycbcr, max_intensity);
+                for (value, converted) in display.iter_mut().zip(bgr) {
+                    *value = (converted * f64::from(scale)).clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+
+        self.video
+            .integrate_intensities(intensities, self.video.state.params.ref_time as f32)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        Some(self.get_last_input_frame())
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        let video = self.get_video_ref();
+        video.get_tps() as f64 / video.get_ref_time() as f64 * (self.buffer.len() * 8) as f64
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W> for RawVideo<W> {
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn crop(mut self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        self.video = self.video.crop(crop)?;
+        Ok(self)
+    }
+
+    fn orientation(mut self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        self.video = self.video.orientation(orientation)?;
+        Ok(self)
+    }
+
+    fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.video = self.video.contrast_mask(mask)?;
+        Ok(self)
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            eprintln!("delta_t_max must be a multiple of ref_time");
+        }
+        Ok(self)
+    }
+
+    /// The `source_camera` must be the [`RawVideo::source_camera`] of the pixel format, since it
+    /// sets the scale of the intensities
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        if is_framed(source_camera) && source_camera != self.source_camera() {
+            return Err(SourceError::BadParams(format!(
+                "the {:?} frames are {:?}, not {:?}",
+                self.pix_fmt,
+                self.source_camera(),
+                source_camera
+            )));
+        }
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    /// A pipe can't seek, so the frames before the start time are read and discarded
+    fn start_time(mut self, start_time: Duration) -> Result<Self, SourceError> {
+        let frame_idx_start = self.duration_to_frames(start_time);
+        while self.frame_idx < frame_idx_start {
+            self.read_frame()?;
+        }
+        self.window_frame_start = self.frame_idx;
+        Ok(self)
+    }
+
+    fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.window_frame_count = duration.map(|duration| self.duration_to_frames(duration));
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(mut self, name: String) -> Self {
+        let date_time = Local::now();
+        let formatted = format!("{}_{}.log", name, date_time.format("%d_%m_%Y_%H_%M_%S"));
+        let log_handle = std::fs::File::create(formatted).ok();
+        self.video.state.feature_log_handle = log_handle;
+
+        // Write the plane size to the log file
+        if let Some(handle) = &mut self.video.state.feature_log_handle {
+            writeln!(
+                handle,
+                "{}x{}x{}",
+                self.video.state.plane.w(),
+                self.video.state.plane.h(),
+                self.video.state.plane.c()
+            )
+            .unwrap();
+        }
+        self
+    }
+}
@@ -0,0 +1,73 @@
+use crate::transcoder::event_pixel_tree::PixelArena;
+use crate::transcoder::source::video::SourceError;
+#[cfg(feature = "compression")]
+use adder_codec_core::codec::compressed::stream::CompressedOutput;
+use adder_codec_core::codec::encoder::Encoder;
+#[cfg(feature = "lz")]
+use adder_codec_core::codec::lz::stream::LzOutput;
+use adder_codec_core::codec::rate_controller::CrfParameters;
+use adder_codec_core::codec::raw::stream::RawOutput;
+use adder_codec_core::codec::{CodecMetadata, EncoderOptions, EncoderType};
+use ndarray::Array3;
+use std::io::Write;
+
+/// An additional output of a transcode at another CRF level, for adaptive-bitrate delivery. It
+/// shares the decoded and preprocessed input frames of the transcode, but its pixels integrate
+/// them with its own contrast thresholds, so they fire their own events.
+pub(crate) struct Rendition<W: Write + std::marker::Send + std::marker::Sync + 'static> {
+    /// The pixels which integrate the input for this rendition
+    pub(crate) pixels: Array3<PixelArena>,
+
+    /// Writes the rendition's events, with the rendition's CRF in its options
+    pub(crate) encoder: Encoder<W>,
+}
+
+impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Rendition<W> {
+    /// The quality parameters which the rendition's pixels integrate with
+    pub(crate) fn crf_parameters(&self) -> CrfParameters {
+        *self.encoder.options.crf.get_parameters()
+    }
+}
+
+/// Create an encoder of the given type, which writes a stream with the given metadata
+pub(crate) fn new_encoder<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+    encoder_type: EncoderType,
+    meta: CodecMetadata,
+    options: EncoderOptions,
+    write: W,
+) -> Result<Encoder<W>, SourceError> {
+    match encoder_type {
+        EncoderType::Compressed => {
+            #[cfg(feature = "compression")]
+            {
+                Ok(Encoder::new_compressed(
+                    CompressedOutput::new(meta, write),
+                    options,
+                ))
+            }
+            #[cfg(not(feature = "compression"))]
+            {
+                Err(SourceError::BadParams(
+                    "Compressed representation is experimental and is not enabled by default!"
+                        .to_string(),
+                ))
+            }
+        }
+        EncoderType::Raw => Ok(Encoder::new_raw(RawOutput::new(meta, write), options)),
+        EncoderType::Lz => {
+            #[cfg(feature = "lz")]
+            {
+                Ok(Encoder::new_lz(LzOutput::new(meta, write), options))
+            }
+            #[cfg(not(feature = "lz"))]
+            {
+                Err(SourceError::BadParams(
+                    "Lz representation is not enabled in this build!".to_string(),
+                ))
+            }
+        }
+        EncoderType::Empty => Err(SourceError::BadParams(
+            "An empty encoder doesn't write an output".to_string(),
+        )),
+    }
+}
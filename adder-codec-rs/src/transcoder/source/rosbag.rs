@@ -0,0 +1,326 @@
+use crate::transcoder::source::prophesee::DvsEvent;
+use ndarray::Array2;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// The number of messages to read from the bag at a time
+const MESSAGE_BATCH: usize = 64;
+
+/// Reads the events of an `EventArray` topic (`dvs_msgs/msg/EventArray` or
+/// `prophesee_event_msgs/msg/EventArray`) from a rosbag2 recording in the default sqlite3
+/// storage, so that recorded bags can be transcoded directly.
+///
+/// The plane size is taken from the messages, and timestamps are the events' own stamps in
+/// microseconds from the first event, rather than the times the messages were recorded.
+pub struct RosbagReader {
+    connection: Connection,
+
+    /// The row id of the event topic in the bag
+    topic_id: i64,
+
+    /// The row id of the last message read
+    last_message_id: i64,
+
+    /// The (width, height) of the event camera
+    size: (u16, u16),
+
+    /// The decoded events of the messages read, not yet returned
+    events: VecDeque<DvsEvent>,
+
+    /// The timestamp of the first event in nanoseconds, which the returned timestamps count from
+    first_t: Option<u64>,
+}
+
+impl RosbagReader {
+    /// Open a bag, given either its directory or its `.db3` file. With no `topic`, the first
+    /// `EventArray` topic in the bag is read.
+    pub fn open(path: &Path, topic: Option<&str>) -> io::Result<Self> {
+        let connection =
+            Connection::open_with_flags(db_path(path)?, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(sql_error)?;
+
+        let topics = topics(&connection)?;
+        let Some((topic_id, _, _)) = topics.iter().find(|(_, name, message_type)| {
+            message_type.ends_with("/msg/EventArray")
+                && (topic.is_none() || topic == Some(name.as_str()))
+        }) else {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "no EventArray topic {} in the bag",
+                    topic.unwrap_or_default()
+                ),
+            ));
+        };
+
+        let mut reader = RosbagReader {
+            connection,
+            topic_id: *topic_id,
+            last_message_id: 0,
+            size: (0, 0),
+            events: VecDeque::new(),
+            first_t: None,
+        };
+        // The plane size comes with the events
+        reader.read_messages()?;
+        if reader.size.0 == 0 || reader.size.1 == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "the event messages give no sensor size",
+            ));
+        }
+        Ok(reader)
+    }
+
+    /// The (width, height) of the event camera
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    /// Read the next event. Returns an [`ErrorKind::UnexpectedEof`] error at the end of the topic.
+    pub fn next_event(&mut self) -> io::Result<DvsEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+            if self.read_messages()? == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// The first message of an image topic (`sensor_msgs/msg/Image`), as 8-bit intensities.
+    /// Supports the `mono8`, `mono16`, `rgb8`, and `bgr8` encodings.
+    pub fn first_image(&self, topic: &str) -> io::Result<Option<Array2<u8>>> {
+        let Some((topic_id, _, _)) = topics(&self.connection)?
+            .into_iter()
+            .find(|(_, name, _)| name == topic)
+        else {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("no image topic {topic} in the bag"),
+            ));
+        };
+        let data: Option<Vec<u8>> = self
+            .connection
+            .query_row(
+                "SELECT data FROM messages WHERE topic_id = ?1 ORDER BY timestamp LIMIT 1",
+                [topic_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        data.map(|data| decode_image(&data)).transpose()
+    }
+
+    /// Read the next batch of event messages. Returns the number of messages read.
+    fn read_messages(&mut self) -> io::Result<usize> {
+        let mut statement = self
+            .connection
+            .prepare_cached(
+                "SELECT id, data FROM messages WHERE topic_id = ?1 AND id > ?2 ORDER BY id \
+                 LIMIT ?3",
+            )
+            .map_err(sql_error)?;
+        let messages = statement
+            .query_map(
+                (self.topic_id, self.last_message_id, MESSAGE_BATCH as i64),
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?)),
+            )
+            .map_err(sql_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sql_error)?;
+        drop(statement);
+
+        for (id, data) in &messages {
+            self.last_message_id = *id;
+            self.decode_event_array(data)?;
+        }
+        Ok(messages.len())
+    }
+
+    /// Decode the events of a CDR-serialized `EventArray` message
+    fn decode_event_array(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut cdr = Cdr::new(data)?;
+        cdr.skip_header()?;
+        let height = cdr.u32()?;
+        let width = cdr.u32()?;
+        if self.size == (0, 0) {
+            self.size = (
+                u16::try_from(width).map_err(|_| invalid_data("sensor too wide"))?,
+                u16::try_from(height).map_err(|_| invalid_data("sensor too tall"))?,
+            );
+        }
+
+        let count = cdr.u32()?;
+        for _ in 0..count {
+            let x = cdr.u16()?;
+            let y = cdr.u16()?;
+            let t = cdr.time()?;
+            let p = cdr.u8()?;
+            if x >= self.size.0 || y >= self.size.1 {
+                continue;
+            }
+            let first_t = *self.first_t.get_or_insert(t);
+            // Events slightly out of order across messages are clamped to the first timestamp
+            let t = t.saturating_sub(first_t) / 1000;
+            let t = u32::try_from(t).unwrap_or(u32::MAX);
+            self.events
+                .push_back(DvsEvent::new(t, x, y, u8::from(p != 0)));
+        }
+        Ok(())
+    }
+}
+
+/// The sqlite3 database of a bag, given either its directory or the database itself
+fn db_path(path: &Path) -> io::Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    std::fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "db3"))
+        .min()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no .db3 file in the bag directory"))
+}
+
+/// The (id, name, type) of each topic in the bag
+fn topics(connection: &Connection) -> io::Result<Vec<(i64, String, String)>> {
+    let mut statement = connection
+        .prepare("SELECT id, name, type FROM topics ORDER BY id")
+        .map_err(sql_error)?;
+    let topics = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(sql_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(sql_error);
+    topics
+}
+
+/// Decode a CDR-serialized `sensor_msgs/msg/Image` message to 8-bit intensities
+fn decode_image(data: &[u8]) -> io::Result<Array2<u8>> {
+    let mut cdr = Cdr::new(data)?;
+    cdr.skip_header()?;
+    let height = cdr.u32()? as usize;
+    let width = cdr.u32()? as usize;
+    let encoding = cdr.string()?;
+    let big_endian = cdr.u8()? != 0;
+    let step = cdr.u32()? as usize;
+    let pixels = cdr.bytes()?;
+
+    let (bytes_per_pixel, intensity): (usize, fn(&[u8], bool) -> u8) = match encoding.as_str() {
+        "mono8" => (1, |px, _| px[0]),
+        "mono16" => (2, |px, big_endian| match big_endian {
+            true => px[0],
+            false => px[1],
+        }),
+        // Rec. 601 luma
+        "rgb8" => (3, |px, _| {
+            (0.299 * f32::from(px[0]) + 0.587 * f32::from(px[1]) + 0.114 * f32::from(px[2])) as u8
+        }),
+        "bgr8" => (3, |px, _| {
+            (0.114 * f32::from(px[0]) + 0.587 * f32::from(px[1]) + 0.299 * f32::from(px[2])) as u8
+        }),
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported image encoding {encoding}"
+            )))
+        }
+    };
+    if step < width * bytes_per_pixel || pixels.len() < step * height {
+        return Err(invalid_data("image data is too short"));
+    }
+    Ok(Array2::from_shape_fn((height, width), |(y, x)| {
+        let offset = y * step + x * bytes_per_pixel;
+        intensity(&pixels[offset..offset + bytes_per_pixel], big_endian)
+    }))
+}
+
+/// A reader of the Common Data Representation that ROS 2 serializes messages with
+struct Cdr<'a> {
+    /// The message, after the encapsulation header
+    data: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> Cdr<'a> {
+    fn new(data: &'a [u8]) -> io::Result<Self> {
+        if data.len() < 4 {
+            return Err(invalid_data("message is too short"));
+        }
+        Ok(Self {
+            data: &data[4..],
+            position: 0,
+            // The second byte of the encapsulation header is 0 for big-endian CDR
+            big_endian: data[1] == 0,
+        })
+    }
+
+    /// Take the next `len` bytes, after padding to their `alignment`
+    fn take(&mut self, len: usize, alignment: usize) -> io::Result<&'a [u8]> {
+        let start = self.position.next_multiple_of(alignment);
+        let Some(bytes) = self.data.get(start..start + len) else {
+            return Err(invalid_data("message is truncated"));
+        };
+        self.position = start + len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1, 1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes: [u8; 2] = self.take(2, 2)?.try_into().unwrap_or_default();
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes: [u8; 4] = self.take(4, 4)?.try_into().unwrap_or_default();
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// A sequence of bytes, prefixed with its length
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len, 1)
+    }
+
+    /// A string, prefixed with its length including the terminating null
+    fn string(&mut self) -> io::Result<String> {
+        let bytes = self.bytes()?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// A `builtin_interfaces/msg/Time`, in nanoseconds
+    fn time(&mut self) -> io::Result<u64> {
+        let sec = self.u32()? as i32;
+        let nanosec = self.u32()?;
+        Ok(u64::try_from(sec).unwrap_or(0) * 1_000_000_000 + u64::from(nanosec))
+    }
+
+    /// Skip a `std_msgs/msg/Header`
+    fn skip_header(&mut self) -> io::Result<()> {
+        self.time()?;
+        self.string()?;
+        Ok(())
+    }
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,225 @@
+use ndarray::{s, Array2, Array3, ArrayView2, Zip};
+use serde::{Deserialize, Serialize};
+
+/// The widest search for the motion at the coarsest level of the pyramid, in pixels. Larger
+/// motions are found by searching at coarser levels.
+const COARSE_RADIUS: i32 = 4;
+
+/// The smallest width or height of a pyramid level
+const MIN_LEVEL_SIZE: usize = 16;
+
+/// How a framed source cancels out the global motion of a handheld camera. The translation of
+/// each input frame relative to the one before it is estimated, and the frame is shifted so that
+/// the view follows a smoothed version of the camera's path. Shake is removed, but deliberate
+/// pans still come through, slowly.
+///
+/// The pixels which a shift uncovers at the edges of the frame repeat the nearest edge pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stabilization {
+    /// The largest motion between successive frames which is searched for, in pixels
+    pub max_motion: u32,
+
+    /// The largest shift applied to a frame, in pixels. When the camera strays further than this
+    /// from the smoothed path, the view is dragged along with it.
+    pub max_shift: u32,
+
+    /// How slowly the view follows the camera's path, in [0, 1]. At 0 the view moves with the
+    /// camera, so nothing is stabilized, and at 1 it stays put (up to `max_shift`).
+    pub smoothing: f32,
+}
+
+impl Default for Stabilization {
+    fn default() -> Self {
+        Self {
+            max_motion: 16,
+            max_shift: 32,
+            smoothing: 0.9,
+        }
+    }
+}
+
+/// Tracks the camera's path across the frames of a source, and chooses the shift which
+/// stabilizes each one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Stabilizer {
+    options: Stabilization,
+
+    /// The luma of the previous input frame, before it was shifted
+    previous: Option<Array2<f32>>,
+
+    /// The total motion of the content since the first frame, as (x, y) pixels
+    path: (f32, f32),
+
+    /// The smoothed `path`, which the view follows
+    smoothed: (f32, f32),
+
+    /// The shift applied to the latest frame
+    shift: (i32, i32),
+}
+
+impl Stabilizer {
+    pub(crate) fn new(options: Stabilization) -> Self {
+        Self {
+            options,
+            previous: None,
+            path: (0.0, 0.0),
+            smoothed: (0.0, 0.0),
+            shift: (0, 0),
+        }
+    }
+
+    /// The shift applied to the latest frame, as (`dx`, `dy`) pixels
+    pub(crate) fn shift(&self) -> (i32, i32) {
+        self.shift
+    }
+
+    /// Estimate the motion of the next input frame (BGR or luma intensities, before shifting) and
+    /// choose the shift which stabilizes it, as (`dx`, `dy`) pixels
+    pub(crate) fn track(&mut self, intensities: &Array3<f32>) -> (i32, i32) {
+        let luma = luma_plane(intensities);
+        if let Some(previous) = &self.previous {
+            let (dx, dy) =
+                estimate_translation(previous.view(), luma.view(), self.options.max_motion);
+            self.path.0 += dx as f32;
+            self.path.1 += dy as f32;
+        }
+        self.previous = Some(luma);
+
+        let follow = 1.0 - self.options.smoothing;
+        self.smoothed.0 += follow * (self.path.0 - self.smoothed.0);
+        self.smoothed.1 += follow * (self.path.1 - self.smoothed.1);
+
+        // Cancel out the content's motion away from the smoothed path. If that takes too large a
+        // shift, drag the smoothed path along so it stays within reach.
+        let max_shift = self.options.max_shift as f32;
+        let shift_x = (self.smoothed.0 - self.path.0)
+            .round()
+            .clamp(-max_shift, max_shift);
+        let shift_y = (self.smoothed.1 - self.path.1)
+            .round()
+            .clamp(-max_shift, max_shift);
+        self.smoothed = (self.path.0 + shift_x, self.path.1 + shift_y);
+        self.shift = (shift_x as i32, shift_y as i32);
+        self.shift
+    }
+
+    /// Forget the camera's path, e.g., after a scene cut, so the latest frame isn't shifted and
+    /// the path starts again from it
+    pub(crate) fn reset(&mut self) {
+        self.path = (0.0, 0.0);
+        self.smoothed = (0.0, 0.0);
+        self.shift = (0, 0);
+    }
+}
+
+/// The luma of BGR intensities, or the only channel of luma intensities
+fn luma_plane(intensities: &Array3<f32>) -> Array2<f32> {
+    if intensities.dim().2 == 3 {
+        Array2::from_shape_fn((intensities.dim().0, intensities.dim().1), |(y, x)| {
+            0.114 * intensities[[y, x, 0]]
+                + 0.587 * intensities[[y, x, 1]]
+                + 0.299 * intensities[[y, x, 2]]
+        })
+    } else {
+        intensities.slice(s![.., .., 0]).to_owned()
+    }
+}
+
+/// Shift the content of a frame by (`dx`, `dy`) pixels, repeating the edge pixels into the area
+/// it uncovers
+pub fn shift_frame(frame: &Array3<f32>, (dx, dy): (i32, i32)) -> Array3<f32> {
+    let (height, width, _) = frame.dim();
+    Array3::from_shape_fn(frame.dim(), |(y, x, c)| {
+        let src_y = (y as i64 - i64::from(dy)).clamp(0, height as i64 - 1) as usize;
+        let src_x = (x as i64 - i64::from(dx)).clamp(0, width as i64 - 1) as usize;
+        frame[[src_y, src_x, c]]
+    })
+}
+
+/// Estimate the global translation of the content from `previous` to `next`, as (`dx`, `dy`)
+/// pixels, such that `next` at (x, y) best matches `previous` at (x - `dx`, y - `dy`). Motions up
+/// to `max_motion` pixels in either direction are searched for, coarse to fine over an image
+/// pyramid.
+pub fn estimate_translation(
+    previous: ArrayView2<f32>,
+    next: ArrayView2<f32>,
+    max_motion: u32,
+) -> (i32, i32) {
+    if previous.dim() != next.dim() || next.is_empty() {
+        return (0, 0);
+    }
+
+    let mut levels = vec![(previous.to_owned(), next.to_owned())];
+    let mut radius = max_motion as i32;
+    loop {
+        let (previous, next) = levels.last().unwrap();
+        let (height, width) = next.dim();
+        if radius <= COARSE_RADIUS || height / 2 < MIN_LEVEL_SIZE || width / 2 < MIN_LEVEL_SIZE {
+            break;
+        }
+        let level = (downsample(previous), downsample(next));
+        levels.push(level);
+        radius = (radius + 1) / 2;
+    }
+
+    // Search the whole range at the coarsest level, then refine the motion at each finer one
+    let mut motion = (0, 0);
+    for (idx, (previous, next)) in levels.iter().enumerate().rev() {
+        motion = best_translation(previous.view(), next.view(), motion, radius);
+        if idx > 0 {
+            motion = (motion.0 * 2, motion.1 * 2);
+            radius = 1;
+        }
+    }
+    let max_motion = max_motion as i32;
+    (
+        motion.0.clamp(-max_motion, max_motion),
+        motion.1.clamp(-max_motion, max_motion),
+    )
+}
+
+/// Halve the width and height of a plane, averaging each 2x2 block
+fn downsample(plane: &Array2<f32>) -> Array2<f32> {
+    let (height, width) = plane.dim();
+    Array2::from_shape_fn((height / 2, width / 2), |(y, x)| {
+        (plane[[2 * y, 2 * x]]
+            + plane[[2 * y, 2 * x + 1]]
+            + plane[[2 * y + 1, 2 * x]]
+            + plane[[2 * y + 1, 2 * x + 1]])
+            / 4.0
+    })
+}
+
+/// The translation within `radius` of `center` with the lowest mean absolute difference over the
+/// overlap of the two planes. Ties go to the smallest translation.
+fn best_translation(
+    previous: ArrayView2<f32>,
+    next: ArrayView2<f32>,
+    center: (i32, i32),
+    radius: i32,
+) -> (i32, i32) {
+    let (height, width) = (next.dim().0 as i32, next.dim().1 as i32);
+    let mut best = (center, f32::INFINITY);
+    for dy in center.1 - radius..=center.1 + radius {
+        for dx in center.0 - radius..=center.0 + radius {
+            // The overlap must cover at least half of each dimension
+            if dx.abs() * 2 > width || dy.abs() * 2 > height {
+                continue;
+            }
+            let (y0, y1) = (dy.max(0), height + dy.min(0));
+            let (x0, x1) = (dx.max(0), width + dx.min(0));
+            let next_overlap = next.slice(s![y0..y1, x0..x1]);
+            let previous_overlap = previous.slice(s![y0 - dy..y1 - dy, x0 - dx..x1 - dx]);
+            let sum = Zip::from(&next_overlap)
+                .and(&previous_overlap)
+                .fold(0.0, |sum, next, previous| sum + (next - previous).abs());
+            let cost = sum / next_overlap.len() as f32;
+
+            let magnitude = |(dx, dy): (i32, i32)| dx.abs() + dy.abs();
+            if cost < best.1 || (cost == best.1 && magnitude((dx, dy)) < magnitude(best.0)) {
+                best = ((dx, dy), cost);
+            }
+        }
+    }
+    best.0
+}
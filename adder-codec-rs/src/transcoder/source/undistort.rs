@@ -0,0 +1,212 @@
+use crate::transcoder::source::video::SourceError;
+use ndarray::Array3;
+
+/// The number of fixed-point iterations used to invert the distortion of a point
+const UNDISTORT_ITERATIONS: usize = 10;
+
+/// The intrinsics and lens distortion of a camera, in the Brown-Conrady model used by OpenCV's
+/// `calibrateCamera` (with the coefficients in OpenCV's order). Coordinates are in pixels.
+///
+/// The undistorted image keeps the same intrinsics, so its pixels line up with an ideal pinhole
+/// camera with focal lengths (`fx`, `fy`) and principal point (`cx`, `cy`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensModel {
+    /// The horizontal focal length
+    pub fx: f32,
+
+    /// The vertical focal length
+    pub fy: f32,
+
+    /// The horizontal position of the principal point
+    pub cx: f32,
+
+    /// The vertical position of the principal point
+    pub cy: f32,
+
+    /// The first radial distortion coefficient
+    pub k1: f32,
+
+    /// The second radial distortion coefficient
+    pub k2: f32,
+
+    /// The first tangential distortion coefficient
+    pub p1: f32,
+
+    /// The second tangential distortion coefficient
+    pub p2: f32,
+
+    /// The third radial distortion coefficient
+    pub k3: f32,
+}
+
+impl LensModel {
+    /// Load a lens model from a text file of its 9 parameters, separated by whitespace or commas,
+    /// in the order `fx fy cx cy k1 k2 p1 p2 k3`
+    pub fn from_file(path: &std::path::Path) -> Result<Self, SourceError> {
+        let content = std::fs::read_to_string(path)?;
+        let params = content
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<f32>()
+                    .map_err(|_| SourceError::BadParams(format!("Invalid lens parameter `{s}`")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let [fx, fy, cx, cy, k1, k2, p1, p2, k3] = params[..] else {
+            return Err(SourceError::BadParams(format!(
+                "Expected 9 lens parameters, but found {}",
+                params.len()
+            )));
+        };
+        Ok(Self {
+            fx,
+            fy,
+            cx,
+            cy,
+            k1,
+            k2,
+            p1,
+            p2,
+            k3,
+        })
+    }
+
+    /// The same lens, for images resized by `scale` (e.g., by [`Framed`]'s scale factor). The
+    /// distortion coefficients are unaffected.
+    ///
+    /// [`Framed`]: crate::transcoder::source::framed::Framed
+    pub fn scaled(self, scale: f32) -> Self {
+        Self {
+            fx: self.fx * scale,
+            fy: self.fy * scale,
+            cx: self.cx * scale,
+            cy: self.cy * scale,
+            ..self
+        }
+    }
+
+    /// Distort normalized image coordinates
+    fn distort_normalized(&self, x: f32, y: f32) -> (f32, f32) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    /// Where the light of an undistorted pixel position falls in the distorted input image
+    pub fn distort(&self, x: f32, y: f32) -> (f32, f32) {
+        let (xd, yd) = self.distort_normalized((x - self.cx) / self.fx, (y - self.cy) / self.fy);
+        (xd * self.fx + self.cx, yd * self.fy + self.cy)
+    }
+
+    /// The undistorted position of a pixel of the distorted input image. The distortion is
+    /// inverted iteratively, as by OpenCV's `undistortPoints`.
+    pub fn undistort(&self, x: f32, y: f32) -> (f32, f32) {
+        let xd = (x - self.cx) / self.fx;
+        let yd = (y - self.cy) / self.fy;
+        let (mut xu, mut yu) = (xd, yd);
+        for _ in 0..UNDISTORT_ITERATIONS {
+            let (x_est, y_est) = self.distort_normalized(xu, yu);
+            xu += xd - x_est;
+            yu += yd - y_est;
+        }
+        (xu * self.fx + self.cx, yu * self.fy + self.cy)
+    }
+}
+
+/// Rectifies the frames or event coordinates of a source with a given [`LensModel`], with the
+/// mapping precomputed for the source's resolution
+#[derive(Debug, Clone)]
+pub struct Undistortion {
+    model: LensModel,
+
+    width: usize,
+
+    height: usize,
+
+    /// The position in the input frame sampled by each output pixel, in row-major order
+    frame_map: Vec<(f32, f32)>,
+
+    /// The output pixel of each input pixel, in row-major order, if it lands in the frame
+    coord_map: Vec<Option<(u16, u16)>>,
+}
+
+impl Undistortion {
+    /// Precompute the undistortion of a `width` x `height` source
+    pub fn new(model: LensModel, width: usize, height: usize) -> Result<Self, SourceError> {
+        let finite = [
+            model.fx, model.fy, model.cx, model.cy, model.k1, model.k2, model.p1, model.p2,
+            model.k3,
+        ]
+        .iter()
+        .all(|param| param.is_finite());
+        if !finite || model.fx <= 0.0 || model.fy <= 0.0 {
+            return Err(SourceError::BadParams(
+                "lens parameters must be finite, with positive focal lengths".to_string(),
+            ));
+        }
+        if width > usize::from(u16::MAX) + 1 || height > usize::from(u16::MAX) + 1 {
+            return Err(SourceError::BadParams(
+                "the source is too large to undistort".to_string(),
+            ));
+        }
+
+        let mut frame_map = Vec::with_capacity(width * height);
+        let mut coord_map = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                frame_map.push(model.distort(x as f32, y as f32));
+
+                let (xu, yu) = model.undistort(x as f32, y as f32);
+                let (xu, yu) = (xu.round(), yu.round());
+                coord_map.push(
+                    (xu >= 0.0 && yu >= 0.0 && xu < width as f32 && yu < height as f32)
+                        .then_some((xu as u16, yu as u16)),
+                );
+            }
+        }
+        Ok(Self {
+            model,
+            width,
+            height,
+            frame_map,
+            coord_map,
+        })
+    }
+
+    /// The lens model being corrected
+    pub fn model(&self) -> &LensModel {
+        &self.model
+    }
+
+    /// Undistort a frame of intensities (of any number of channels), sampling the input
+    /// bilinearly. Output pixels whose light falls outside the input take the nearest edge pixel.
+    pub fn undistort_frame(&self, frame: &Array3<f32>) -> Array3<f32> {
+        let (height, width, channels) = frame.dim();
+        debug_assert_eq!((height, width), (self.height, self.width));
+        Array3::from_shape_fn((height, width, channels), |(y, x, c)| {
+            let (src_x, src_y) = self.frame_map[y * width + x];
+            let src_x = src_x.clamp(0.0, (width - 1) as f32);
+            let src_y = src_y.clamp(0.0, (height - 1) as f32);
+            let (x0, y0) = (src_x as usize, src_y as usize);
+            let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+            let (fx, fy) = (src_x - x0 as f32, src_y - y0 as f32);
+            let top = frame[[y0, x0, c]] * (1.0 - fx) + frame[[y0, x1, c]] * fx;
+            let bottom = frame[[y1, x0, c]] * (1.0 - fx) + frame[[y1, x1, c]] * fx;
+            top * (1.0 - fy) + bottom * fy
+        })
+    }
+
+    /// The undistorted pixel of an event at (`x`, `y`) in the input, rounded to the nearest one.
+    /// `None` if it lands outside the frame (or the input pixel is out of bounds), so the event
+    /// should be dropped.
+    pub fn undistort_coord(&self, x: u16, y: u16) -> Option<(u16, u16)> {
+        let (x, y) = (usize::from(x), usize::from(y));
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.coord_map[y * self.width + x]
+    }
+}
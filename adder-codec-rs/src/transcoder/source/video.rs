@@ -6,23 +6,28 @@ use std::cmp::min;
 use std::collections::HashSet;
 #[cfg(feature = "feature-logging")]
 use std::ffi::c_void;
-use std::io::{sink, Write};
+use std::fs::File;
+use std::io::{sink, BufWriter, Write};
 use std::mem::swap;
 
+use adder_codec_core::codec::clock::DriftEstimator;
 use adder_codec_core::codec::empty::stream::EmptyOutput;
 use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::codec::monitor::HealthMonitor;
 use adder_codec_core::codec::raw::stream::RawOutput;
+use adder_codec_core::codec::sink::{Backpressure, EventSink, EventTee};
 use adder_codec_core::codec::{
-    CodecError, CodecMetadata, EncoderOptions, EncoderType, LATEST_CODEC_VERSION,
+    CodecError, CodecMetadata, EncoderOptions, EncoderType, DEFAULT_BLOCK_SIZE,
+    LATEST_CODEC_VERSION,
 };
 use adder_codec_core::{
-    Coord, DeltaT, Event, Mode, PixelAddress, PixelMultiMode, PlaneError, PlaneSize, SourceCamera,
-    SourceType, TimeMode, D_EMPTY, D_ZERO_INTEGRATION,
+    open_file_decoder, AbsoluteT, ChromaSubsampling, ColorSpace, Coord, DeltaT, Event, Mode,
+    PixelAddress, PixelMultiMode, PlaneError, PlaneSize, SourceCamera, TimeMode, D_EMPTY, D_MAX,
+    D_SHIFT_F32, D_ZERO_INTEGRATION,
 };
-use bumpalo::Bump;
 
 use std::sync::mpsc::{channel, Sender};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crate::framer::scale_intensity::{FrameValue, SaeTime};
 use crate::transcoder::event_pixel_tree::{Intensity32, PixelArena};
@@ -34,23 +39,35 @@ use opencv::{highgui, imgproc::resize};
 
 #[cfg(feature = "compression")]
 use adder_codec_core::codec::compressed::stream::CompressedOutput;
+#[cfg(feature = "lz")]
+use adder_codec_core::codec::lz::stream::LzOutput;
 use adder_codec_core::Mode::Continuous;
 use itertools::Itertools;
-use ndarray::{Array, Array3, Axis, ShapeError};
+use ndarray::{s, Array, Array2, Array3, ArrayView3, ArrayViewMut3, Axis, ShapeError, Zip};
 use rayon::iter::IndexedParallelIterator;
 use rayon::iter::IntoParallelIterator;
 use rayon::iter::ParallelIterator;
 use rayon::ThreadPool;
 
+#[cfg(feature = "gpu")]
+use crate::transcoder::gpu::{GpuError, GpuIntegrator};
+use crate::transcoder::source::checkpoint::{VideoCheckpoint, CHECKPOINT_VERSION};
+use crate::transcoder::source::denoise::{TemporalDenoiser, TemporalFilter};
+use crate::transcoder::source::noise::{NoiseFloor, NoiseModel};
+use crate::transcoder::source::quality::{QualityController, QualityTarget};
+use crate::transcoder::source::rendition::{new_encoder, Rendition};
+use crate::transcoder::source::undistort::{LensModel, Undistortion};
 use crate::transcoder::source::video::FramedViewMode::SAE;
 use crate::utils::cv::is_feature;
 
 use crate::utils::viz::{draw_feature_coord, draw_rect, ShowFeatureMode};
-use adder_codec_core::codec::rate_controller::{Crf, CrfParameters};
+use adder_codec_core::codec::rate_controller::{Crf, CrfParameters, CRF, DEFAULT_CRF_QUALITY};
 use kiddo::{KdTree, SquaredEuclidean};
 use thiserror::Error;
 use tokio::task::JoinError;
 use video_rs_adder_dep::Frame;
+#[cfg(feature = "simd")]
+use wide::{f32x8, CmpGe};
 
 /// Various errors that can occur during an ADΔER transcode
 #[derive(Error, Debug)]
@@ -92,6 +109,19 @@ pub enum SourceError {
     #[error("video-rs error")]
     VideoError(video_rs_adder_dep::Error),
 
+    /// FFmpeg error, when decoding high bit depth video
+    #[error("FFmpeg error")]
+    FfmpegError(#[from] ffmpeg_next::Error),
+
+    /// Image decoding error
+    #[error("Image error")]
+    ImageError(#[from] image::ImageError),
+
+    #[cfg(feature = "webcam")]
+    /// Webcam capture error
+    #[error("Webcam error")]
+    WebcamError(nokhwa::NokhwaError),
+
     /// Codec error
     #[error("Codec core error")]
     CodecError(CodecError),
@@ -120,6 +150,20 @@ pub enum SourceError {
     /// I/O error
     #[error("I/O error")]
     IoError(#[from] std::io::Error),
+
+    /// Serialization error, when saving or restoring a [`VideoCheckpoint`]
+    #[error("Serialization error")]
+    SerializationError(#[from] bincode::Error),
+
+    /// Some sources of a [`MultiSource`](crate::transcoder::source::multi::MultiSource) failed
+    /// to consume an interval, with the index of each in the group
+    #[error("{} sources of the group failed", .0.len())]
+    GroupError(Vec<(usize, SourceError)>),
+
+    #[cfg(feature = "gpu")]
+    /// GPU integration error
+    #[error("GPU error")]
+    GpuError(#[from] GpuError),
 }
 
 #[cfg(feature = "open-cv")]
@@ -140,6 +184,13 @@ impl From<video_rs_adder_dep::Error> for SourceError {
     }
 }
 
+#[cfg(feature = "webcam")]
+impl From<nokhwa::NokhwaError> for SourceError {
+    fn from(value: nokhwa::NokhwaError) -> Self {
+        SourceError::WebcamError(value)
+    }
+}
+
 /// The display mode
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
 pub enum FramedViewMode {
@@ -169,6 +220,13 @@ pub struct VideoStateParams {
 
     /// The reference time in ticks
     pub ref_time: u32,
+
+    /// The cap on the number of events each pixel may fire, if any
+    pub event_budget: Option<EventBudget>,
+
+    /// The contrast threshold below which changes are put down to noise, for each base value,
+    /// under the [`VideoState::noise_model`]
+    pub noise_floor: Option<NoiseFloor>,
 }
 
 impl Default for VideoStateParams {
@@ -178,10 +236,398 @@ impl Default for VideoStateParams {
             pixel_multi_mode: Default::default(),
             delta_t_max: 7650,
             ref_time: 255,
+            event_budget: None,
+            noise_floor: None,
         }
     }
 }
 
+/// A cap on the number of events each pixel may fire in a window of time. When a pixel exceeds
+/// its budget (e.g., a flickering light source), its D is raised so that it must integrate more
+/// light before firing again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventBudget {
+    /// The maximum number of events per pixel in each window
+    pub max_events: u32,
+
+    /// The length of the window, in ticks
+    pub window: DeltaT,
+}
+
+/// Contrast thresholds which vary across the image plane, so that the transcoder is sensitive in
+/// the regions which matter and saves events (and power) elsewhere. Each pixel has a region label,
+/// and each region may be given its own baseline and maximum contrast threshold. The pixels of
+/// regions without thresholds use the global ones, from the CRF or
+/// [`VideoBuilder::quality_manual`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContrastMask {
+    /// The region label of each pixel, indexed by (y, x)
+    labels: Array2<u8>,
+
+    /// The (baseline, maximum) contrast thresholds of each region, indexed by label
+    thresholds: Vec<Option<(u8, u8)>>,
+}
+
+impl ContrastMask {
+    /// Create a mask from the region label of each pixel, indexed by (y, x)
+    pub fn new(labels: Array2<u8>) -> Self {
+        Self {
+            labels,
+            thresholds: vec![None; usize::from(u8::MAX) + 1],
+        }
+    }
+
+    /// Load a mask from an image, whose gray levels are the region labels (e.g., black for the
+    /// background and white for the regions of interest). A color image is converted to gray.
+    pub fn from_image(path: &std::path::Path) -> Result<Self, SourceError> {
+        let image = image::open(path)?.to_luma8();
+        let (width, height) = image.dimensions();
+        let labels = Array2::from_shape_vec((height as usize, width as usize), image.into_raw())?;
+        Ok(Self::new(labels))
+    }
+
+    /// Create a `width`x`height` mask from polygons, each a list of (x, y) vertices. The pixels
+    /// whose centers are inside the polygon at index `i` are labeled `i + 1`, with later polygons
+    /// taking precedence, and the rest are labeled 0.
+    pub fn from_polygons(
+        width: PixelAddress,
+        height: PixelAddress,
+        polygons: &[Vec<(f32, f32)>],
+    ) -> Result<Self, SourceError> {
+        if polygons.len() > usize::from(u8::MAX) {
+            return Err(SourceError::BadParams(format!(
+                "A contrast mask can have at most {} polygons",
+                u8::MAX
+            )));
+        }
+        let labels = Array2::from_shape_fn((height as usize, width as usize), |(y, x)| {
+            let center = (x as f32 + 0.5, y as f32 + 0.5);
+            polygons
+                .iter()
+                .rposition(|polygon| polygon_contains(polygon, center))
+                .map_or(0, |index| index as u8 + 1)
+        });
+        Ok(Self::new(labels))
+    }
+
+    /// Set the contrast thresholds of the region with the given label. The baseline is capped at
+    /// the maximum.
+    pub fn region(mut self, label: u8, c_thresh_baseline: u8, c_thresh_max: u8) -> Self {
+        self.thresholds[usize::from(label)] =
+            Some((c_thresh_baseline.min(c_thresh_max), c_thresh_max));
+        self
+    }
+
+    /// The region label of each pixel, indexed by (y, x)
+    pub fn labels(&self) -> &Array2<u8> {
+        &self.labels
+    }
+
+    /// The (baseline, maximum) contrast thresholds of the pixel at (`x`, `y`), if its region has
+    /// its own
+    pub fn thresholds(&self, x: PixelAddress, y: PixelAddress) -> Option<(u8, u8)> {
+        let label = self.labels.get((y as usize, x as usize))?;
+        self.thresholds[usize::from(*label)]
+    }
+}
+
+/// Whether a point is inside a polygon, by the even-odd rule
+fn polygon_contains(polygon: &[(f32, f32)], (x, y): (f32, f32)) -> bool {
+    let mut inside = false;
+    for (&(x1, y1), &(x2, y2)) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) / (y2 - y1) * (x2 - x1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Per-pixel statistics on how an [`EventBudget`] has throttled the transcode
+#[derive(Debug, Clone)]
+pub struct ThrottleStats {
+    /// The number of windows in which each pixel exceeded its budget
+    pub throttled_windows: Array3<u32>,
+
+    /// The minimum D value currently imposed on each pixel
+    pub d_floor: Array3<D>,
+}
+
+impl ThrottleStats {
+    /// The number of pixels which have exceeded their budget at least once
+    pub fn throttled_pixels(&self) -> usize {
+        self.throttled_windows.iter().filter(|n| **n > 0).count()
+    }
+}
+
+/// The number of power-of-two bins in [`EventStats::delta_t_counts`]
+pub const DELTA_T_BINS: usize = 32;
+
+/// The distribution of the D and Δt values of a batch of events emitted by the transcoder, as
+/// reported to an [`EventStatsCallback`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventStats {
+    /// The number of events with each D value, indexed by D. Empty events ([`D_EMPTY`]) are
+    /// counted at the last index.
+    pub d_counts: Vec<u64>,
+
+    /// The number of events in each power-of-two bin of Δt. Bin `i` counts the events with
+    /// `2^i <= Δt < 2^(i+1)`, and events with Δt = 0 are counted in bin 0.
+    pub delta_t_counts: Vec<u64>,
+}
+
+impl Default for EventStats {
+    fn default() -> Self {
+        Self {
+            d_counts: vec![0; usize::from(D_EMPTY) + 1],
+            delta_t_counts: vec![0; DELTA_T_BINS],
+        }
+    }
+}
+
+impl EventStats {
+    /// Count an event with the given D and Δt
+    pub fn record(&mut self, d: D, delta_t: DeltaT) {
+        self.d_counts[usize::from(d)] += 1;
+        self.delta_t_counts[delta_t.checked_ilog2().unwrap_or(0) as usize] += 1;
+    }
+
+    /// Add the counts of another batch to this one
+    pub fn merge(&mut self, other: &EventStats) {
+        for (count, other) in self.d_counts.iter_mut().zip(&other.d_counts) {
+            *count += other;
+        }
+        for (count, other) in self.delta_t_counts.iter_mut().zip(&other.delta_t_counts) {
+            *count += other;
+        }
+    }
+
+    /// The total number of events counted
+    pub fn total(&self) -> u64 {
+        self.d_counts.iter().sum()
+    }
+}
+
+/// A callback which receives the [`EventStats`] of each batch of events the transcoder emits
+pub type EventStatsCallback = Box<dyn FnMut(&EventStats) + Send>;
+
+/// A 1D lookup table applied to the input intensities before integration, such as the inverse
+/// of a camera's response function. Linearizing the source's response makes the transcoded
+/// intensities proportional to the scene radiance.
+///
+/// The table's entries are evenly spaced over the input range `[0, max]`, where `max` is the
+/// maximum intensity of the source's bit depth, and each entry is the output intensity on the
+/// same scale. Intensities between entries are linearly interpolated, so the table need not have
+/// an entry for every input level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntensityLut {
+    table: Vec<f32>,
+}
+
+impl IntensityLut {
+    /// Create a LUT from its entries. There must be at least two entries, and all must be finite
+    /// and non-negative.
+    pub fn new(table: Vec<f32>) -> Result<Self, SourceError> {
+        if table.len() < 2 {
+            return Err(SourceError::BadParams(
+                "Intensity LUT must have at least 2 entries".to_string(),
+            ));
+        }
+        if table.iter().any(|v| !v.is_finite() || *v < 0.0) {
+            return Err(SourceError::BadParams(
+                "Intensity LUT entries must be finite and non-negative".to_string(),
+            ));
+        }
+        Ok(Self { table })
+    }
+
+    /// Load a LUT from a text file of entries separated by whitespace or commas (e.g., one entry
+    /// per line, or a single CSV row)
+    pub fn from_file(path: &std::path::Path) -> Result<Self, SourceError> {
+        let content = std::fs::read_to_string(path)?;
+        let table = content
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<f32>().map_err(|_| {
+                    SourceError::BadParams(format!("Invalid intensity LUT entry `{s}`"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(table)
+    }
+
+    /// The entries of the table
+    pub fn table(&self) -> &[f32] {
+        &self.table
+    }
+
+    /// Map an input intensity in `[0, max_intensity]` through the table
+    pub fn apply(&self, intensity: f32, max_intensity: f32) -> f32 {
+        let last = self.table.len() - 1;
+        let pos = (intensity / max_intensity).clamp(0.0, 1.0) * last as f32;
+        let idx = (pos as usize).min(last - 1);
+        let frac = pos - idx as f32;
+        self.table[idx] + (self.table[idx + 1] - self.table[idx]) * frac
+    }
+}
+
+/// A rectangle of the input frames to transcode, so that a window of high-resolution footage can
+/// be transcoded on its own. Positions are in pixels of the input given to the [`Video`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    /// The column of the window's left edge
+    pub x: PixelAddress,
+
+    /// The row of the window's top edge
+    pub y: PixelAddress,
+
+    /// The width of the window
+    pub width: PixelAddress,
+
+    /// The height of the window
+    pub height: PixelAddress,
+}
+
+impl Crop {
+    /// Whether the window fits in a `width`x`height` frame, and isn't empty
+    pub fn fits(&self, width: PixelAddress, height: PixelAddress) -> bool {
+        self.width > 0
+            && self.height > 0
+            && u64::from(self.x) + u64::from(self.width) <= u64::from(width)
+            && u64::from(self.y) + u64::from(self.height) <= u64::from(height)
+    }
+
+    /// Take the window of a frame, indexed by (y, x, c)
+    pub fn apply<T: Clone>(&self, frame: &Array3<T>) -> Array3<T> {
+        let (x, y) = (self.x as usize, self.y as usize);
+        frame
+            .slice(s![
+                y..y + self.height as usize,
+                x..x + self.width as usize,
+                ..
+            ])
+            .to_owned()
+    }
+}
+
+impl std::str::FromStr for Crop {
+    type Err = SourceError;
+
+    /// Parse a window given as `WIDTHxHEIGHT+X+Y`, e.g., `640x480+100+50`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SourceError::BadParams(format!("Invalid crop `{s}`, expected WxH+X+Y"));
+        let (size, offset) = s.split_once('+').ok_or_else(invalid)?;
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let (x, y) = offset.split_once('+').ok_or_else(invalid)?;
+        let parse = |value: &str| value.trim().parse::<PixelAddress>().map_err(|_| invalid());
+        Ok(Self {
+            x: parse(x)?,
+            y: parse(y)?,
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+/// A rotation of the input frames, clockwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// No rotation
+    #[default]
+    None,
+
+    /// A quarter turn clockwise
+    Cw90,
+
+    /// A half turn
+    Cw180,
+
+    /// A quarter turn counterclockwise
+    Cw270,
+}
+
+impl Rotation {
+    /// The rotation by the given number of degrees clockwise, which must be a multiple of 90
+    pub fn from_degrees(degrees: u32) -> Result<Self, SourceError> {
+        match degrees % 360 {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Cw90),
+            180 => Ok(Rotation::Cw180),
+            270 => Ok(Rotation::Cw270),
+            _ => Err(SourceError::BadParams(format!(
+                "Rotation must be a multiple of 90 degrees, not {degrees}"
+            ))),
+        }
+    }
+
+    /// Whether the rotation swaps the width and height of the frames
+    pub fn is_transposed(&self) -> bool {
+        matches!(self, Rotation::Cw90 | Rotation::Cw270)
+    }
+}
+
+/// How the input frames are turned before integration, e.g., to fix the orientation of phone
+/// footage or an upside-down mounted camera without re-encoding the source. The frames are
+/// flipped, then rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    /// Mirror the frames left to right
+    pub flip_horizontal: bool,
+
+    /// Mirror the frames top to bottom
+    pub flip_vertical: bool,
+
+    /// Rotate the (flipped) frames
+    pub rotation: Rotation,
+}
+
+impl Orientation {
+    /// Whether the frames are left as they are
+    pub fn is_identity(&self) -> bool {
+        *self == Orientation::default()
+    }
+
+    /// The (width, height) of a `width`x`height` frame once it's turned
+    pub fn turned_size(
+        &self,
+        width: PixelAddress,
+        height: PixelAddress,
+    ) -> (PixelAddress, PixelAddress) {
+        if self.rotation.is_transposed() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Turn a frame, indexed by (y, x, c)
+    pub fn apply<T: Clone>(&self, frame: &Array3<T>) -> Array3<T> {
+        let mut view = frame.view();
+        if self.flip_horizontal {
+            view.invert_axis(Axis(1));
+        }
+        if self.flip_vertical {
+            view.invert_axis(Axis(0));
+        }
+        match self.rotation {
+            Rotation::None => {}
+            Rotation::Cw90 => {
+                view.swap_axes(0, 1);
+                view.invert_axis(Axis(1));
+            }
+            Rotation::Cw180 => {
+                view.invert_axis(Axis(0));
+                view.invert_axis(Axis(1));
+            }
+            Rotation::Cw270 => {
+                view.swap_axes(0, 1);
+                view.invert_axis(Axis(0));
+            }
+        }
+        view.as_standard_layout().into_owned()
+    }
+}
+
 /// Running state of the video transcode
 #[derive(Debug)]
 pub struct VideoState {
@@ -190,6 +636,15 @@ pub struct VideoState {
     /// The size of the imaging plane
     pub plane: PlaneSize,
 
+    /// The size of the input frames, which differs from the `plane` if they're cropped or rotated
+    pub input_plane: PlaneSize,
+
+    /// The window of the input frames which is transcoded, if they're cropped
+    pub crop: Option<Crop>,
+
+    /// How the (cropped) input frames are turned before integration, if they are
+    pub orientation: Option<Orientation>,
+
     /// The number of rows of pixels to process at a time (per thread)
     pub chunk_rows: usize,
 
@@ -199,6 +654,34 @@ pub struct VideoState {
     /// The number of ticks per second
     pub tps: DeltaT,
 
+    /// How the color channels are sampled
+    pub chroma_subsampling: ChromaSubsampling,
+
+    /// The color space of the channels, recorded in the stream header
+    pub color_space: ColorSpace,
+
+    /// The wall-clock time of tick 0, in UTC nanoseconds since the Unix epoch, if known
+    pub epoch: Option<u64>,
+
+    /// Whether to record corrections for the drift of a live sensor's clock in the stream
+    pub drift_correction: bool,
+
+    /// The type of video source, which determines the bit depth of the input intensities
+    pub source_camera: SourceCamera,
+
+    /// The lookup table applied to the input intensities before integration, if any
+    pub intensity_lut: Option<IntensityLut>,
+
+    /// The correction of the lens distortion applied to the input before integration, if any
+    pub undistortion: Option<Undistortion>,
+
+    /// The noise of the sensor, which raises the contrast thresholds of noisy pixels, if set
+    pub noise_model: Option<NoiseModel>,
+
+    /// The source intensity of a full-scale sample, recorded in the stream header. Above 1.0 for
+    /// HDR sources, whose samples are normalized by it.
+    pub intensity_peak: f32,
+
     /// Whether or not to detect features
     pub feature_detection: bool,
 
@@ -219,10 +702,22 @@ impl Default for VideoState {
     fn default() -> Self {
         VideoState {
             plane: PlaneSize::default(),
+            input_plane: PlaneSize::default(),
+            crop: None,
+            orientation: None,
             params: VideoStateParams::default(),
             chunk_rows: 1,
             in_interval_count: 1,
             tps: 7650,
+            chroma_subsampling: ChromaSubsampling::None,
+            epoch: None,
+            drift_correction: false,
+            source_camera: SourceCamera::default(),
+            intensity_lut: None,
+            undistortion: None,
+            noise_model: None,
+            intensity_peak: 1.0,
+            color_space: ColorSpace::Bgr,
             feature_detection: false,
             running_intensities: Default::default(),
             show_features: ShowFeatureMode::Off,
@@ -278,6 +773,22 @@ pub trait VideoBuilder<W> {
     /// Set the chunk rows
     fn chunk_rows(self, chunk_rows: usize) -> Self;
 
+    /// Transcode only a window of the input. See [`Video::crop`].
+    fn crop(self, crop: Option<Crop>) -> Result<Self, SourceError>
+    where
+        Self: std::marker::Sized;
+
+    /// Flip and rotate the input. See [`Video::orientation`].
+    fn orientation(self, orientation: Option<Orientation>) -> Result<Self, SourceError>
+    where
+        Self: std::marker::Sized;
+
+    /// Vary the contrast thresholds across the image plane by region. See
+    /// [`Video::contrast_mask`].
+    fn contrast_mask(self, mask: Option<ContrastMask>) -> Result<Self, SourceError>
+    where
+        Self: std::marker::Sized;
+
     /// Set the time parameters
     fn time_parameters(
         self,
@@ -304,6 +815,16 @@ pub trait VideoBuilder<W> {
     /// Set whether or not to detect features, and whether or not to display the features
     fn detect_features(self, detect_features: bool, show_features: ShowFeatureMode) -> Self;
 
+    /// Skip the first `start_time` of the source, for a partial transcode. The output stream's
+    /// timestamps start from 0 at the start time.
+    fn start_time(self, start_time: Duration) -> Result<Self, SourceError>
+    where
+        Self: std::marker::Sized;
+
+    /// Stop transcoding after `duration` of the source, measured from the start time. With
+    /// `None`, the whole rest of the source is transcoded.
+    fn duration(self, duration: Option<Duration>) -> Self;
+
     #[cfg(feature = "feature-logging")]
     fn log_path(self, name: String) -> Self;
 }
@@ -331,11 +852,43 @@ pub struct Video<W: Write + std::marker::Send + std::marker::Sync + 'static> {
 
     /// The type of encoder being used (e.g., compressed or raw)
     pub encoder_type: EncoderType,
+
+    /// Additional destinations (e.g., a network stream or a live framer) which receive the same
+    /// events as the `encoder`, each with its own backpressure
+    pub sinks: EventTee,
+
+    /// Receives the distribution of each batch of emitted events
+    event_stats_callback: Option<EventStatsCallback>,
+
+    /// The timestamp of each pixel's last event, for deriving Δt from absolute timestamps
+    event_stats_last_t: Array3<AbsoluteT>,
+
+    /// Estimates the drift of the sensor's clock, if [`VideoState::drift_correction`] is set
+    drift_estimator: Option<DriftEstimator>,
+
+    /// Watches the emitted events for signs that a live transcode has silently failed
+    health_monitor: Option<HealthMonitor>,
+
+    /// Whether the pixels were reset by [`Video::reset_pixels`], so that their D values must be
+    /// set anew from the next frame
+    pixels_reset: bool,
+
+    /// Denoises the input frames over time, if [`Video::temporal_filter`] is set
+    denoiser: Option<TemporalDenoiser>,
+
+    /// Adjusts the CRF to hold a target quality, if [`Video::quality_target`] is set
+    quality_controller: Option<QualityController>,
+
+    /// Additional outputs at other CRF levels, added by [`Video::add_rendition`]
+    renditions: Vec<Rendition<W>>,
+
+    /// Integrates the pixels which don't fire on the GPU, if [`Video::gpu_integration`] is set
+    #[cfg(feature = "gpu")]
+    gpu_integrator: Option<GpuIntegrator>,
     // TODO: Hold multiple encoder options and an enum, so that boxing isn't required.
     // Also hold a state for whether or not to write out events at all, so that a null writer isn't required.
     // Eric: this is somewhat addressed above
 }
-unsafe impl<W: Write + std::marker::Send + std::marker::Sync + 'static> Send for Video<W> {}
 
 impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video<W> {
     /// Initialize the Video with default parameters.
@@ -379,6 +932,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize()));
 
         state.plane = plane;
+        state.input_plane = plane;
         let instantaneous_view_mode = FramedViewMode::Intensity;
         let (event_sender, _) = channel();
         let meta = CodecMetadata {
@@ -392,6 +946,18 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             event_size: 0,
             source_camera: SourceCamera::default(), // TODO: Allow for setting this
             adu_interval: Default::default(),
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: DEFAULT_BLOCK_SIZE,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
         };
 
         match writer {
@@ -408,6 +974,17 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                     event_sender,
                     encoder,
                     encoder_type: EncoderType::Empty,
+                    sinks: EventTee::new(),
+                    event_stats_callback: None,
+                    event_stats_last_t: Array3::zeros((0, 0, 0)),
+                    drift_estimator: None,
+                    health_monitor: None,
+                    pixels_reset: false,
+                    denoiser: None,
+                    quality_controller: None,
+                    renditions: Vec::new(),
+                    #[cfg(feature = "gpu")]
+                    gpu_integrator: None,
                 })
             }
             Some(w) => {
@@ -424,6 +1001,17 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                     event_sender,
                     encoder,
                     encoder_type: EncoderType::Empty,
+                    sinks: EventTee::new(),
+                    event_stats_callback: None,
+                    event_stats_last_t: Array3::zeros((0, 0, 0)),
+                    drift_estimator: None,
+                    health_monitor: None,
+                    pixels_reset: false,
+                    denoiser: None,
+                    quality_controller: None,
+                    renditions: Vec::new(),
+                    #[cfg(feature = "gpu")]
+                    gpu_integrator: None,
                 })
             }
         }
@@ -470,6 +1058,371 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         self
     }
 
+    /// Transcode the color channels with the given subsampling. Must be set before
+    /// [`Video::write_out`], so that the subsampling is recorded in the stream header.
+    pub fn chroma_subsampling(
+        mut self,
+        chroma_subsampling: ChromaSubsampling,
+    ) -> Result<Self, SourceError> {
+        if chroma_subsampling != ChromaSubsampling::None && self.state.plane.c() != 3 {
+            return Err(SourceError::BadParams(
+                "Chroma subsampling requires a color source".to_string(),
+            ));
+        }
+        self.state.chroma_subsampling = chroma_subsampling;
+        Ok(self)
+    }
+
+    /// Declare the wall-clock time of tick 0, in UTC nanoseconds since the Unix epoch. Live
+    /// capture sources should set this, so that events can be correlated with other sensors.
+    /// Must be set before [`Video::write_out`], so that the epoch is recorded in the stream header.
+    pub fn epoch(mut self, epoch_ns: Option<u64>) -> Self {
+        self.state.epoch = epoch_ns;
+        self
+    }
+
+    /// Periodically record corrections of the stream's clock against the host's wall clock, so
+    /// that long live recordings stay aligned with it even if the sensor's clock drifts. If no
+    /// epoch has been declared, the current time is used as the epoch. Must be set before
+    /// [`Video::write_out`]. Only compressed streams carry the corrections, at most one per Adu.
+    pub fn drift_correction(mut self, enabled: bool) -> Self {
+        self.state.drift_correction = enabled;
+        if enabled && self.state.epoch.is_none() {
+            self.state.epoch = Some(utc_now_ns());
+        }
+        self.drift_estimator = None;
+        self
+    }
+
+    /// Declare the color space of the intensities given to [`Video::integrate_intensities`]. With
+    /// [`ColorSpace::YCbCr`], the Y, Cb, and Cr planes output by a decoder are integrated
+    /// directly, without converting them to BGR, and the frames are converted back to BGR when
+    /// they're reconstructed. Must be set before [`Video::write_out`], so that the color space is
+    /// recorded in the stream header.
+    pub fn color_space(mut self, color_space: ColorSpace) -> Result<Self, SourceError> {
+        if color_space != ColorSpace::Bgr && self.state.plane.c() != 3 {
+            return Err(SourceError::BadParams(
+                "A color space requires a color source".to_string(),
+            ));
+        }
+        self.state.color_space = color_space;
+        Ok(self)
+    }
+
+    /// Declare the source intensity that a full-scale sample stands for, e.g., the brightest value
+    /// of an HDR source whose samples are normalized by it. Must be set before
+    /// [`Video::write_out`], so that the peak is recorded in the stream header.
+    pub fn intensity_peak(mut self, peak: f32) -> Result<Self, SourceError> {
+        if !(peak.is_finite() && peak > 0.0) {
+            return Err(SourceError::BadParams(format!(
+                "intensity peak must be positive, not {peak}"
+            )));
+        }
+        self.state.intensity_peak = peak;
+        Ok(self)
+    }
+
+    /// Apply a lookup table to the input intensities before integration, e.g., to linearize the
+    /// camera's response. `None` removes the table.
+    pub fn intensity_lut(mut self, lut: Option<IntensityLut>) -> Self {
+        self.state.intensity_lut = lut;
+        self
+    }
+
+    /// Denoise the input frames over time before integration, so that a noisy source doesn't
+    /// fire a flood of events. See [`TemporalFilter`]. `None` removes the filter.
+    pub fn temporal_filter(mut self, filter: Option<TemporalFilter>) -> Result<Self, SourceError> {
+        self.update_temporal_filter(filter)?;
+        Ok(self)
+    }
+
+    /// Set, change, or remove the [`TemporalFilter`] during a transcode. A filter whose
+    /// parameters change keeps its history, so the output doesn't jump.
+    pub fn update_temporal_filter(
+        &mut self,
+        filter: Option<TemporalFilter>,
+    ) -> Result<(), SourceError> {
+        let Some(filter) = filter else {
+            self.denoiser = None;
+            return Ok(());
+        };
+        filter.validate()?;
+        match &mut self.denoiser {
+            Some(denoiser) => denoiser.set_options(filter),
+            None => self.denoiser = Some(TemporalDenoiser::new(filter)),
+        }
+        Ok(())
+    }
+
+    /// The [`TemporalFilter`] applied to the input frames, if any
+    pub fn get_temporal_filter(&self) -> Option<TemporalFilter> {
+        self.denoiser.as_ref().map(TemporalDenoiser::options)
+    }
+
+    /// Hold a target quality by adjusting the CRF level each interval, in place of a fixed CRF.
+    /// The quality is measured against the source frame (after any [`TemporalFilter`] and
+    /// [`IntensityLut`]), from the intensities of the pixels' latest events, whatever the view
+    /// mode of the instantaneous frame. See [`QualityTarget`]. `None` removes the target, keeping
+    /// the CRF level it reached.
+    pub fn quality_target(mut self, target: Option<QualityTarget>) -> Self {
+        self.update_quality_target(target);
+        self
+    }
+
+    /// Set, change, or remove the [`QualityTarget`] during a transcode. A changed target keeps
+    /// the CRF level reached so far.
+    pub fn update_quality_target(&mut self, target: Option<QualityTarget>) {
+        let Some(target) = target else {
+            self.quality_controller = None;
+            return;
+        };
+        match &mut self.quality_controller {
+            Some(controller) => controller.set_target(target),
+            None => {
+                let crf = self
+                    .encoder
+                    .options
+                    .crf
+                    .get_quality()
+                    .unwrap_or(DEFAULT_CRF_QUALITY);
+                self.quality_controller = Some(QualityController::new(target, crf));
+            }
+        }
+    }
+
+    /// The [`QualityTarget`] the transcoder holds, if any
+    pub fn get_quality_target(&self) -> Option<QualityTarget> {
+        self.quality_controller
+            .as_ref()
+            .map(QualityController::target)
+    }
+
+    /// The smoothed PSNR, in dB, measured for the [`QualityTarget`] so far, if any
+    pub fn measured_psnr(&self) -> Option<f64> {
+        self.quality_controller
+            .as_ref()
+            .and_then(QualityController::psnr)
+    }
+
+    /// Model the noise of the sensor, so that pixels don't fire events for changes which are
+    /// within the noise at their intensity. Each pixel's contrast threshold is raised to the
+    /// noise floor of its base intensity. The model is in the units of the source intensities,
+    /// after any [`IntensityLut`], and applies to framed sources. `None` removes the model.
+    pub fn noise_model(mut self, model: Option<NoiseModel>) -> Self {
+        self.state.noise_model = model;
+        self
+    }
+
+    /// Rectify the input frames before integration, correcting the distortion of the given lens,
+    /// so that the stream lines up with an ideal pinhole camera. The lens must be calibrated at
+    /// the resolution of the input, before any [`Video::crop`]. Event sources must also remap
+    /// their event coordinates, as [`Prophesee::lens_undistortion`] (for DVS and AEDAT files) and
+    /// `Davis::lens_undistortion` (with the `open-cv` feature) do. `None` removes the correction.
+    ///
+    /// [`Prophesee::lens_undistortion`]: crate::transcoder::source::prophesee::Prophesee::lens_undistortion
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        let input_plane = self.state.input_plane;
+        let (width, height) = (input_plane.w_usize(), input_plane.h_usize());
+        self.state.undistortion = model
+            .map(|model| Undistortion::new(model, width, height))
+            .transpose()?;
+        Ok(self)
+    }
+
+    /// Transcode only a window of the input frames. The plane shrinks to the window, while the
+    /// frames given to [`Video::integrate_intensities`] stay the size of the input; the window is
+    /// taken after any [`Video::lens_undistortion`]. `None` leaves the input uncropped.
+    ///
+    /// Must be set before [`Video::orientation`], [`Video::write_out`], and
+    /// [`Video::contrast_mask`], which are sized by the plane, and can only be set once.
+    pub fn crop(mut self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        let Some(crop) = crop else {
+            return Ok(self);
+        };
+        if self.state.crop.is_some() {
+            return Err(SourceError::BadParams(
+                "The input is already cropped".to_string(),
+            ));
+        }
+        if self.state.orientation.is_some() {
+            return Err(SourceError::BadParams(
+                "The crop must be set before the orientation".to_string(),
+            ));
+        }
+        self.check_not_written("crop")?;
+        let input_plane = self.state.input_plane;
+        if !crop.fits(input_plane.w(), input_plane.h()) {
+            return Err(SourceError::BadParams(format!(
+                "The crop {crop:?} doesn't fit in the {}x{} input",
+                input_plane.w(),
+                input_plane.h()
+            )));
+        }
+        let plane = PlaneSize::new(crop.width, crop.height, input_plane.c())?;
+
+        // Keep the settings of the pixels in the window
+        let event_pixel_trees = crop.apply(&self.event_pixel_trees);
+        self.state.crop = Some(crop);
+        Ok(self.replace_plane(plane, event_pixel_trees))
+    }
+
+    /// Flip and rotate the input frames before integration, after any [`Video::crop`]. The plane
+    /// takes the turned frames' dimensions, so a quarter turn swaps its width and height. `None`
+    /// leaves the frames as they are.
+    ///
+    /// Must be set before [`Video::write_out`] and [`Video::contrast_mask`], which are sized by
+    /// the plane, and can only be set once.
+    pub fn orientation(mut self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        let Some(orientation) = orientation.filter(|orientation| !orientation.is_identity()) else {
+            return Ok(self);
+        };
+        if self.state.orientation.is_some() {
+            return Err(SourceError::BadParams(
+                "The input is already oriented".to_string(),
+            ));
+        }
+        self.check_not_written("orientation")?;
+        let (width, height) = orientation.turned_size(self.state.plane.w(), self.state.plane.h());
+        let plane = PlaneSize::new(width, height, self.state.plane.c())?;
+
+        // Turn the pixels along with the frames, so they keep their settings
+        let event_pixel_trees = orientation.apply(&self.event_pixel_trees);
+        self.state.orientation = Some(orientation);
+        Ok(self.replace_plane(plane, event_pixel_trees))
+    }
+
+    /// Changes to the plane are only possible before the stream's header is written
+    fn check_not_written(&self, setting: &str) -> Result<(), SourceError> {
+        if self.encoder_type != EncoderType::Empty {
+            return Err(SourceError::BadParams(format!(
+                "The {setting} must be set before writing out"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Resize the plane, with the given pixels, which are given their new coordinates
+    fn replace_plane(mut self, plane: PlaneSize, event_pixel_trees: Array3<PixelArena>) -> Self {
+        self.event_pixel_trees = event_pixel_trees;
+        for ((y, x, _), px) in self.event_pixel_trees.indexed_iter_mut() {
+            px.coord.x = x as PixelAddress;
+            px.coord.y = y as PixelAddress;
+        }
+        let dim = (plane.h_usize(), plane.w_usize(), plane.c_usize());
+        self.state.running_intensities = Array3::zeros(dim);
+        self.display_frame_features = Array3::zeros(dim);
+        self.state.plane = plane;
+        self.encoder.options.crf.plane = plane;
+        let chunk_rows = self.state.chunk_rows;
+        self.chunk_rows(chunk_rows)
+    }
+
+    /// Crop and turn an input-sized frame (e.g., a source's display frame) as the integrated
+    /// frames are, so that it lines up with the plane
+    pub(crate) fn fit_to_plane<T: Clone>(&self, mut frame: Array3<T>) -> Array3<T> {
+        if let Some(crop) = &self.state.crop {
+            frame = crop.apply(&frame);
+        }
+        if let Some(orientation) = &self.state.orientation {
+            frame = orientation.apply(&frame);
+        }
+        frame
+    }
+
+    /// Cap the number of events each pixel may fire per second. Pixels which exceed the cap have
+    /// their D raised adaptively, and their throttling is reported by [`Video::throttle_stats`].
+    /// `None` removes the cap.
+    ///
+    /// The window is derived from the current ticks per second, so this should be set after the
+    /// time parameters.
+    pub fn event_budget(mut self, events_per_second: Option<u32>) -> Self {
+        self.state.params.event_budget = events_per_second.map(|max_events| EventBudget {
+            max_events,
+            window: self.state.tps,
+        });
+        self
+    }
+
+    /// Integrate the pixels which don't fire on the GPU, with a compute kernel over the whole
+    /// frame. Requires the `gpu` feature. If no GPU is available, the transcode stays on the CPU.
+    ///
+    /// Only the quiet pixels of an interval, which are usually most of them, are offloaded: the
+    /// kernel adds their intensity and raises their contrast threshold. Events are never fired on
+    /// the GPU. A pixel which fires, or which has more than one node in its tree, is integrated on
+    /// the CPU as usual, so the events are the same either way. The speedup is therefore bounded
+    /// by the share of pixels which fire, and a busy scene gains little.
+    ///
+    /// An [`EventBudget`] keeps the whole integration on the CPU.
+    pub fn gpu_integration(self, enabled: bool) -> Result<Self, SourceError> {
+        #[cfg(feature = "gpu")]
+        {
+            let mut video = self;
+            video.gpu_integrator = None;
+            if enabled {
+                match GpuIntegrator::new() {
+                    Ok(integrator) => video.gpu_integrator = Some(integrator),
+                    Err(e) => eprintln!("GPU integration is unavailable ({e}). Using the CPU."),
+                }
+            }
+            Ok(video)
+        }
+        #[cfg(not(feature = "gpu"))]
+        {
+            if enabled {
+                return Err(SourceError::BadParams(
+                    "GPU integration is not enabled in this build!".to_string(),
+                ));
+            }
+            Ok(self)
+        }
+    }
+
+    /// Get the per-pixel throttling statistics of the [`EventBudget`]
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            throttled_windows: self.event_pixel_trees.map(|px| px.throttled_windows),
+            d_floor: self.event_pixel_trees.map(|px| px.d_floor),
+        }
+    }
+
+    /// Vary the contrast thresholds across the image plane by region. `None` gives every pixel the
+    /// global thresholds. Should be set after the CRF or [`VideoBuilder::quality_manual`], which
+    /// reset the pixels to their baselines.
+    pub fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.update_contrast_mask(mask)?;
+        Ok(self)
+    }
+
+    /// Replace the [`ContrastMask`] mid-transcode, resetting each pixel to its baseline threshold.
+    /// The mask applies to the pixels of every rendition as well.
+    pub fn update_contrast_mask(&mut self, mask: Option<ContrastMask>) -> Result<(), SourceError> {
+        if let Some(mask) = &mask {
+            let dim = (self.state.plane.h_usize(), self.state.plane.w_usize());
+            if mask.labels.dim() != dim {
+                return Err(SourceError::BadParams(format!(
+                    "The contrast mask is {:?}, but the plane is {dim:?}",
+                    mask.labels.dim()
+                )));
+            }
+        }
+        let c_thresh_baseline = self.encoder.options.crf.get_parameters().c_thresh_baseline;
+        let renditions = self.renditions.iter_mut().map(|rendition| {
+            let c_thresh_baseline = rendition.crf_parameters().c_thresh_baseline;
+            (&mut rendition.pixels, c_thresh_baseline)
+        });
+        for (pixels, c_thresh_baseline) in
+            std::iter::once((&mut self.event_pixel_trees, c_thresh_baseline)).chain(renditions)
+        {
+            for px in pixels.iter_mut() {
+                px.c_thresh_bounds = mask
+                    .as_ref()
+                    .and_then(|mask| mask.thresholds(px.coord.x, px.coord.y));
+                px.reset_c_thresh(c_thresh_baseline);
+            }
+        }
+        Ok(())
+    }
+
     /// Set the time parameters for the video.
     ///
     /// These parameters, in conjunction, determine the temporal resolution and maximum transcode
@@ -538,13 +1491,33 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
     pub fn write_out(
         mut self,
         source_camera: Option<SourceCamera>,
-        time_mode: Option<TimeMode>,
+        mut time_mode: Option<TimeMode>,
         pixel_multi_mode: Option<PixelMultiMode>,
-        adu_interval: Option<usize>,
+        mut adu_interval: Option<usize>,
         encoder_type: EncoderType,
         encoder_options: EncoderOptions,
         write: W,
     ) -> Result<Self, SourceError> {
+        if let Some(source_camera) = source_camera {
+            self.state.source_camera = source_camera;
+        }
+        if encoder_type == EncoderType::Compressed {
+            // The compressed codec places events in their Adus by their absolute timestamps
+            if time_mode == Some(TimeMode::DeltaT) {
+                eprintln!(
+                    "Compressed representation requires absolute timestamps. Using {:?}.",
+                    TimeMode::AbsoluteT
+                );
+                time_mode = Some(TimeMode::AbsoluteT);
+            }
+            // Without an explicit Adu size (e.g., for a DVS source, which has no frame rate),
+            // let each Adu span delta_t_max
+            if adu_interval.unwrap_or_default() == 0 {
+                adu_interval = Some(
+                    (self.state.params.delta_t_max / self.state.params.ref_time).max(1) as usize,
+                );
+            }
+        }
         let encoder: Encoder<_> = match encoder_type {
             EncoderType::Compressed => {
                 #[cfg(feature = "compression")]
@@ -563,6 +1536,18 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                             event_size: 0,
                             source_camera: source_camera.unwrap_or_default(),
                             adu_interval: adu_interval.unwrap_or_default(),
+                            chroma_subsampling: self.state.chroma_subsampling,
+                            epoch: self.state.epoch,
+                            enhancement_layers: 0,
+                            wide_coordinates: false,
+                            block_size: DEFAULT_BLOCK_SIZE,
+                            d_max: D_MAX,
+                            channel_layout: Default::default(),
+                            context_priors_id: 0,
+                            quantization_table: Default::default(),
+                            intensity_peak: self.state.intensity_peak,
+                            color_space: self.state.color_space,
+                            motion_compensation: false,
                         },
                         write,
                     );
@@ -591,11 +1576,64 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                         event_size: 0,
                         source_camera: source_camera.unwrap_or_default(),
                         adu_interval: Default::default(),
+                        chroma_subsampling: self.state.chroma_subsampling,
+                        epoch: self.state.epoch,
+                        enhancement_layers: 0,
+                        wide_coordinates: false,
+                        block_size: DEFAULT_BLOCK_SIZE,
+                        d_max: D_MAX,
+                        channel_layout: Default::default(),
+                        context_priors_id: 0,
+                        quantization_table: Default::default(),
+                        intensity_peak: self.state.intensity_peak,
+                        color_space: self.state.color_space,
+                        motion_compensation: false,
                     },
                     write,
                 );
                 Encoder::new_raw(compression, encoder_options)
             }
+            EncoderType::Lz => {
+                #[cfg(feature = "lz")]
+                {
+                    self.state.params.pixel_multi_mode =
+                        pixel_multi_mode.unwrap_or(PixelMultiMode::Collapse);
+                    let compression = LzOutput::new(
+                        CodecMetadata {
+                            codec_version: LATEST_CODEC_VERSION,
+                            header_size: 0,
+                            time_mode: time_mode.unwrap_or_default(),
+                            plane: self.state.plane,
+                            tps: self.state.tps,
+                            ref_interval: self.state.params.ref_time,
+                            delta_t_max: self.state.params.delta_t_max,
+                            event_size: 0,
+                            source_camera: source_camera.unwrap_or_default(),
+                            adu_interval: Default::default(),
+                            chroma_subsampling: self.state.chroma_subsampling,
+                            epoch: self.state.epoch,
+                            enhancement_layers: 0,
+                            wide_coordinates: false,
+                            block_size: DEFAULT_BLOCK_SIZE,
+                            d_max: D_MAX,
+                            channel_layout: Default::default(),
+                            context_priors_id: 0,
+                            quantization_table: Default::default(),
+                            intensity_peak: self.state.intensity_peak,
+                            color_space: self.state.color_space,
+                            motion_compensation: false,
+                        },
+                        write,
+                    );
+                    Encoder::new_lz(compression, encoder_options)
+                }
+                #[cfg(not(feature = "lz"))]
+                {
+                    return Err(SourceError::BadParams(
+                        "Lz representation is not enabled in this build!".to_string(),
+                    ));
+                }
+            }
             EncoderType::Empty => {
                 self.state.params.pixel_multi_mode =
                     pixel_multi_mode.unwrap_or(PixelMultiMode::Collapse);
@@ -611,6 +1649,18 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                         event_size: 0,
                         source_camera: source_camera.unwrap_or_default(),
                         adu_interval: Default::default(),
+                        chroma_subsampling: self.state.chroma_subsampling,
+                        epoch: self.state.epoch,
+                        enhancement_layers: 0,
+                        wide_coordinates: false,
+                        block_size: DEFAULT_BLOCK_SIZE,
+                        d_max: D_MAX,
+                        channel_layout: Default::default(),
+                        context_priors_id: 0,
+                        quantization_table: Default::default(),
+                        intensity_peak: self.state.intensity_peak,
+                        color_space: self.state.color_space,
+                        motion_compensation: false,
                     },
                     sink(),
                 );
@@ -631,38 +1681,241 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
     /// # Errors
     /// Returns an error if the stream writer cannot be closed cleanly.
     pub fn end_write_stream(&mut self) -> Result<Option<W>, SourceError> {
+        self.sinks.close()?;
+        for mut writer in self.end_rendition_streams()?.into_iter().flatten() {
+            writer.flush()?;
+        }
         let mut tmp: Encoder<W> = Encoder::new_empty(
             EmptyOutput::new(CodecMetadata::default(), sink()),
-            self.encoder.options,
+            self.encoder.options.clone(),
         );
         swap(&mut self.encoder, &mut tmp);
         Ok(tmp.close_writer()?)
     }
 
+    /// Write an additional output of the transcode at another CRF level, e.g., for
+    /// adaptive-bitrate delivery. The rendition's stream has the same type and metadata as the
+    /// main output, set by [`Video::write_out`], which must be called first. The input frames are
+    /// decoded and preprocessed once for all the outputs, but each rendition's pixels integrate
+    /// them with its own contrast thresholds. The regions of a [`ContrastMask`] keep their own
+    /// thresholds in every output, whatever its CRF, and the thresholds around the features found
+    /// in the main output are lowered in each rendition by its own CRF's feature radius. A
+    /// [`QualityTarget`], GPU integration, and [`Video::save_state`] only apply to the main
+    /// output.
+    ///
+    /// Returns the index of the rendition among those added.
+    pub fn add_rendition(&mut self, crf: u8, write: W) -> Result<usize, SourceError> {
+        if usize::from(crf) >= CRF.len() {
+            return Err(SourceError::BadParams(format!(
+                "CRF must be at most {}, not {crf}",
+                CRF.len() - 1
+            )));
+        }
+        if self.encoder_type == EncoderType::Empty {
+            return Err(SourceError::BadParams(
+                "Renditions need the main output to be set with write_out".to_string(),
+            ));
+        }
+
+        let mut meta = *self.encoder.meta();
+        meta.header_size = 0;
+        let mut options = self.encoder.options.clone();
+        options.crf = Crf::new(Some(crf), self.state.plane);
+        let c_thresh_baseline = options.crf.get_parameters().c_thresh_baseline;
+
+        // Start from the state of the main output's pixels, so the rendition joins in mid-stream
+        let mut pixels = self.event_pixel_trees.clone();
+        pixels.par_map_inplace(|px| px.reset_c_thresh(c_thresh_baseline));
+
+        let encoder = new_encoder(self.encoder_type, meta, options, write)?;
+        self.renditions.push(Rendition { pixels, encoder });
+        Ok(self.renditions.len() - 1)
+    }
+
+    /// The number of renditions added by [`Video::add_rendition`]
+    pub fn rendition_count(&self) -> usize {
+        self.renditions.len()
+    }
+
+    /// Close and flush the streams of the renditions, in the order they were added, and remove
+    /// them from the transcode. [`Video::end_write_stream`] closes any which are left.
+    pub fn end_rendition_streams(&mut self) -> Result<Vec<Option<W>>, SourceError> {
+        self.renditions
+            .drain(..)
+            .map(|rendition| Ok(rendition.encoder.close_writer()?))
+            .collect()
+    }
+
+    /// Attach an additional sink which will receive all subsequent events, alongside the
+    /// encoder. See [`EventTee::add_sink`].
+    pub fn add_sink(
+        &mut self,
+        sink: Box<dyn EventSink>,
+        capacity: usize,
+        backpressure: Backpressure,
+    ) -> usize {
+        self.sinks.add_sink(sink, capacity, backpressure)
+    }
+
+    /// Set a callback which receives the distribution of the D and Δt values of each batch of
+    /// emitted events, e.g., for plotting while tuning the transcoder parameters. `None` removes
+    /// the callback.
+    pub fn event_stats_callback(&mut self, callback: Option<EventStatsCallback>) {
+        self.event_stats_last_t = Array3::zeros((
+            self.state.plane.h_usize(),
+            self.state.plane.w_usize(),
+            self.state.plane.c_usize(),
+        ));
+        self.event_stats_callback = callback;
+    }
+
+    /// Watch the emitted events with a [`HealthMonitor`], which raises alerts for anomalies such
+    /// as the event rate collapsing or the encoder failing to write. The events are only watched
+    /// if the stream has absolute timestamps. `None` removes the monitor.
+    pub fn health_monitor(&mut self, monitor: Option<HealthMonitor>) {
+        self.health_monitor = monitor;
+    }
+
+    /// Pass the events along to the encoder and to any additional sinks
+    pub(crate) fn encode_events(&mut self, big_buffer: &[Vec<Event>]) -> Result<(), CodecError> {
+        let result = self.encode_events_unmonitored(big_buffer);
+        if let Some(monitor) = &mut self.health_monitor {
+            if self.encoder.meta().time_mode != TimeMode::DeltaT {
+                let events: Vec<Event> = big_buffer.iter().flatten().copied().collect();
+                monitor.observe(&events);
+            }
+            if let Err(e) = &result {
+                monitor.observe_error(e);
+            }
+        }
+        result
+    }
+
+    fn encode_events_unmonitored(&mut self, big_buffer: &[Vec<Event>]) -> Result<(), CodecError> {
+        if let Some(callback) = &mut self.event_stats_callback {
+            let absolute_t = self.encoder.meta().time_mode != TimeMode::DeltaT;
+            let mut stats = EventStats::default();
+            for event in big_buffer.iter().flatten() {
+                let delta_t = if absolute_t {
+                    let last_t = &mut self.event_stats_last_t[[
+                        event.coord.y_usize(),
+                        event.coord.x_usize(),
+                        event.coord.c_usize(),
+                    ]];
+                    let delta_t = event.t.saturating_sub(*last_t);
+                    *last_t = event.t;
+                    delta_t
+                } else {
+                    event.t
+                };
+                stats.record(event.d, delta_t);
+            }
+            callback(&stats);
+        }
+        self.encoder.ingest_events_events(big_buffer)?;
+
+        if self.state.drift_correction && self.encoder.meta().time_mode != TimeMode::DeltaT {
+            if let Some(t) = big_buffer.iter().flatten().map(|event| event.t).max() {
+                self.observe_clock(t)?;
+            }
+        }
+
+        self.sinks.ingest_events_events(big_buffer)
+    }
+
+    /// Compare the sensor's time `t` with the host's clock, and record a clock correction in the
+    /// stream when one is due
+    fn observe_clock(&mut self, t: AbsoluteT) -> Result<(), CodecError> {
+        let meta = *self.encoder.meta();
+        let epoch_ns = self.state.epoch.unwrap_or_default();
+        let estimator = self.drift_estimator.get_or_insert_with(|| {
+            let interval = meta.ref_interval * meta.adu_interval.max(1) as AbsoluteT;
+            DriftEstimator::new(epoch_ns, meta.tps, interval)
+        });
+        match estimator.observe(t, utc_now_ns()) {
+            Some(correction) => self.encoder.record_clock(correction),
+            None => Ok(()),
+        }
+    }
+
     #[allow(clippy::needless_pass_by_value)]
     pub(crate) fn integrate_matrix(
         &mut self,
         matrix: Frame,
         time_spanned: f32,
     ) -> Result<Vec<Vec<Event>>, SourceError> {
-        if self.state.in_interval_count == 0 {
-            self.set_initial_d(&matrix);
+        // let matrix_f32 = convert_u8_to_f32_simd(&matrix.into_raw_vec());
+        self.integrate_intensities(matrix.mapv(f32::from), time_spanned)
+    }
+
+    /// Integrate a frame of intensities, spanning `time_spanned` ticks. The intensities are on
+    /// the scale of the source camera's bit depth (e.g., `[0, 65535]` for
+    /// [`SourceCamera::FramedU16`]), as set by [`Video::write_out`], so high bit depth sources
+    /// are transcoded without clipping to 8 bits. The [`Undistortion`], [`Crop`],
+    /// [`Orientation`], [`TemporalFilter`], and [`IntensityLut`], if any, are applied first.
+    ///
+    /// Returns the events fired by each chunk of rows.
+    pub fn integrate_intensities(
+        &mut self,
+        mut matrix: Array3<f32>,
+        time_spanned: f32,
+    ) -> Result<Vec<Vec<Event>>, SourceError> {
+        let source_type = self.state.source_camera.source_type();
+        // Contrast thresholds are defined on an 8-bit scale, regardless of the source bit depth
+        let frame_scale = f64::from(u8::MAX) / source_type.max_intensity();
+
+        if let Some(undistortion) = &self.state.undistortion {
+            matrix = undistortion.undistort_frame(&matrix);
+        }
+        matrix = self.fit_to_plane(matrix);
+        if let Some(denoiser) = &mut self.denoiser {
+            matrix = denoiser.filter(matrix, frame_scale as f32);
+        }
+        if let Some(lut) = &self.state.intensity_lut {
+            let max_intensity = source_type.max_intensity() as f32;
+            matrix.mapv_inplace(|intensity| lut.apply(intensity, max_intensity));
+        }
+
+        if self.state.in_interval_count == 0 || std::mem::take(&mut self.pixels_reset) {
+            self.set_initial_d(&matrix, frame_scale);
         }
 
         let parameters = *self.encoder.options.crf.get_parameters();
 
         self.state.in_interval_count += 1;
 
-        // let matrix_f32 = convert_u8_to_f32_simd(&matrix.into_raw_vec());
-        let matrix = matrix.mapv(f32::from);
+        let chroma_subsampling = self.state.chroma_subsampling;
+        if chroma_subsampling == ChromaSubsampling::Half {
+            subsample_chroma(&mut matrix);
+        }
 
-        // TODO: When there's full support for various bit-depth sources, modify this accordingly
         let practical_d_max = fast_math::log2_raw(
-            255.0 * (self.state.params.delta_t_max / self.state.params.ref_time) as f32,
+            source_type.max_intensity() as f32
+                * (self.state.params.delta_t_max / self.state.params.ref_time) as f32,
         );
 
         let tpf = self.state.params.ref_time as f64;
 
+        self.state.params.noise_floor = self
+            .state
+            .noise_model
+            .map(|model| model.noise_floor(frame_scale));
+
+        #[cfg(feature = "gpu")]
+        if let Some(integrator) = &mut self.gpu_integrator {
+            if self.state.params.event_budget.is_none() {
+                integrator.integrate(
+                    &mut self.event_pixel_trees,
+                    &matrix,
+                    frame_scale,
+                    time_spanned,
+                    &self.state.params,
+                    &parameters,
+                    chroma_subsampling,
+                )?;
+            }
+        }
+
         let params = &self.state.params;
         // Important: if framing the events simultaneously, then the chunk division must be
         // exactly the same as it is for the framer
@@ -683,29 +1936,25 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             )
             .map(|((mut px_chunk, matrix_chunk), mut running_chunk)| {
                 let mut buffer: Vec<Event> = Vec::with_capacity(10);
-                let bump = Bump::new();
-                let base_val = bump.alloc(0);
-
-                for ((px, input), running) in px_chunk
-                    .iter_mut()
-                    .zip(matrix_chunk.iter())
-                    .zip(running_chunk.iter_mut())
-                {
-                    integrate_for_px(
-                        px,
-                        base_val,
-                        *input as u8,
-                        *input, // In this case, frame val is the same as intensity to integrate
-                        time_spanned,
-                        &mut buffer,
-                        params,
-                        &parameters,
-                    );
+                integrate_chunk(
+                    px_chunk.view_mut(),
+                    matrix_chunk,
+                    frame_scale,
+                    time_spanned,
+                    &mut buffer,
+                    params,
+                    &parameters,
+                    chroma_subsampling,
+                );
 
+                for (px, running) in px_chunk.iter().zip(running_chunk.iter_mut()) {
+                    if !chroma_subsampling.is_sampled(px.coord) {
+                        continue;
+                    }
                     if let Some(event) = px.arena[0].best_event {
                         *running = u8::get_frame_value(
                             &event.into(),
-                            SourceType::U8,
+                            source_type,
                             tpf,
                             practical_d_max,
                             self.state.params.delta_t_max,
@@ -725,12 +1974,20 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             })
             .collect();
 
-        for events in &big_buffer {
-            for e1 in events.iter() {
-                self.encoder.ingest_event(*e1)?;
+        self.encode_events(&big_buffer)?;
+
+        if self.quality_controller.is_some() {
+            let mse = self.reconstruction_mse(&matrix, frame_scale, tpf, practical_d_max);
+            if let Some(controller) = &mut self.quality_controller {
+                let crf = controller.observe(mse);
+                if self.encoder.options.crf.get_quality() != Some(crf) {
+                    self.update_crf(crf);
+                }
             }
         }
 
+        self.integrate_renditions(&matrix, frame_scale, time_spanned)?;
+
         self.display_frame_features = self.state.running_intensities.clone();
 
         self.handle_features(&big_buffer)?;
@@ -767,27 +2024,275 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         Ok(big_buffer)
     }
 
-    fn set_initial_d(&mut self, frame: &Frame) {
-        self.event_pixel_trees
+    /// Integrate the frame of intensities into the pixels of each rendition, and encode their
+    /// events
+    fn integrate_renditions(
+        &mut self,
+        matrix: &Array3<f32>,
+        frame_scale: f64,
+        time_spanned: f32,
+    ) -> Result<(), SourceError> {
+        let params = &self.state.params;
+        let chunk_rows = self.state.chunk_rows;
+        let chroma_subsampling = self.state.chroma_subsampling;
+        for rendition in &mut self.renditions {
+            let parameters = rendition.crf_parameters();
+            let big_buffer: Vec<Vec<Event>> = rendition
+                .pixels
+                .axis_chunks_iter_mut(Axis(0), chunk_rows)
+                .into_par_iter()
+                .zip(matrix.axis_chunks_iter(Axis(0), chunk_rows).into_par_iter())
+                .map(|(px_chunk, matrix_chunk)| {
+                    let mut buffer: Vec<Event> = Vec::with_capacity(10);
+                    integrate_chunk(
+                        px_chunk,
+                        matrix_chunk,
+                        frame_scale,
+                        time_spanned,
+                        &mut buffer,
+                        params,
+                        &parameters,
+                        chroma_subsampling,
+                    );
+                    buffer
+                })
+                .collect();
+            rendition.encoder.ingest_events_events(&big_buffer)?;
+        }
+        Ok(())
+    }
+
+    /// The mean squared error, on the 8-bit scale, of the frame reconstructed from the pixels'
+    /// latest events against the frame of intensities which was just integrated. In the
+    /// [`FramedViewMode::Intensity`] view mode, that's the frame of running intensities; in the
+    /// others, the [`QualityController`] reconstructs its own.
+    fn reconstruction_mse(
+        &mut self,
+        matrix: &Array3<f32>,
+        frame_scale: f64,
+        tpf: f64,
+        practical_d_max: f32,
+    ) -> f64 {
+        let chroma_subsampling = self.state.chroma_subsampling;
+        let reconstruction = match &mut self.quality_controller {
+            Some(controller) if self.instantaneous_view_mode != FramedViewMode::Intensity => {
+                let source_type = self.state.source_camera.source_type();
+                let delta_t_max = self.state.params.delta_t_max;
+                let reconstruction = controller
+                    .reconstruction
+                    .get_or_insert_with(|| self.state.running_intensities.clone());
+                Zip::from(&mut *reconstruction)
+                    .and(&self.event_pixel_trees)
+                    .par_for_each(|intensity, px| {
+                        if let Some(event) = px.arena[0].best_event {
+                            *intensity = u8::get_frame_value(
+                                &event.into(),
+                                source_type,
+                                tpf,
+                                practical_d_max,
+                                delta_t_max,
+                                FramedViewMode::Intensity,
+                                None,
+                            );
+                        }
+                    });
+                &*reconstruction
+            }
+            controller => {
+                // Start over from the running intensities if the view mode changes again
+                if let Some(controller) = controller {
+                    controller.reconstruction = None;
+                }
+                &self.state.running_intensities
+            }
+        };
+        let (error_sum, count) = Zip::from(&self.event_pixel_trees)
+            .and(matrix)
+            .and(reconstruction)
+            .par_fold(
+                || (0.0, 0_usize),
+                |(error_sum, count), px, intensity, running| {
+                    if !chroma_subsampling.is_sampled(px.coord) {
+                        return (error_sum, count);
+                    }
+                    let original = (f64::from(*intensity) * frame_scale).clamp(0.0, 255.0);
+                    let error = original - f64::from(*running);
+                    (error_sum + error * error, count + 1)
+                },
+                |(sum_a, count_a), (sum_b, count_b)| (sum_a + sum_b, count_a + count_b),
+            );
+        error_sum / count.max(1) as f64
+    }
+
+    /// Reset the state of every pixel, e.g., at a scene cut, so that no pixel integrates light
+    /// across it. Each pixel fires its pending events, and its D value is set anew from the next
+    /// frame integrated, as for the first frame of the video. The encoder then starts an Adu which
+    /// isn't predicted from the ones before it.
+    ///
+    /// Returns the events fired by each chunk of rows, which are already encoded.
+    pub fn reset_pixels(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        let params = &self.state.params;
+        let c_thresh_baseline = self.encoder.options.crf.get_parameters().c_thresh_baseline;
+        let big_buffer: Vec<Vec<Event>> = self
+            .event_pixel_trees
             .axis_chunks_iter_mut(Axis(0), self.state.chunk_rows)
             .into_par_iter()
-            .zip(
-                frame
-                    .axis_chunks_iter(Axis(0), self.state.chunk_rows)
-                    .into_par_iter(),
-            )
-            .for_each(|(mut px, frame_chunk)| {
-                for (px, frame_val) in px.iter_mut().zip(frame_chunk.iter()) {
-                    let d_start = if *frame_val == 0 {
-                        D_ZERO_INTEGRATION
-                    } else {
-                        (f32::from(*frame_val)).log2().floor() as D
-                    };
-
-                    px.arena[0].set_d(d_start);
-                    px.base_val = *frame_val as u8;
+            .map(|mut px_chunk| {
+                let mut buffer: Vec<Event> = Vec::new();
+                for px in px_chunk.iter_mut() {
+                    px.reset(
+                        &mut buffer,
+                        params.pixel_tree_mode,
+                        params.pixel_multi_mode,
+                        params.ref_time,
+                        c_thresh_baseline,
+                    );
                 }
-            });
+                buffer
+            })
+            .collect();
+
+        self.encode_events(&big_buffer)?;
+        self.encoder.force_intra_adu()?;
+
+        let params = &self.state.params;
+        for rendition in &mut self.renditions {
+            let c_thresh_baseline = rendition.crf_parameters().c_thresh_baseline;
+            let mut buffer = Vec::new();
+            for px in rendition.pixels.iter_mut() {
+                px.reset(
+                    &mut buffer,
+                    params.pixel_tree_mode,
+                    params.pixel_multi_mode,
+                    params.ref_time,
+                    c_thresh_baseline,
+                );
+            }
+            rendition.encoder.ingest_events(&buffer)?;
+            rendition.encoder.force_intra_adu()?;
+        }
+        self.pixels_reset = true;
+        if let Some(denoiser) = &mut self.denoiser {
+            denoiser.reset();
+        }
+        Ok(big_buffer)
+    }
+
+    /// Save the state of the transcode, so that it can be continued from this point with
+    /// [`Video::restore_state`], e.g., after an intentional pause or a crash, or to go on in a
+    /// different output file. It should be saved between input intervals. If the video is writing
+    /// an output, the encoder's state is saved as well, with [`Encoder::checkpoint`].
+    ///
+    /// The returned checkpoint's `source_position` is 0; sources which can seek set it to their
+    /// position in the input, and framed sources add the state of their own processing.
+    pub fn save_state(&mut self) -> Result<VideoCheckpoint, SourceError> {
+        let encoder = match self.encoder_type {
+            EncoderType::Empty => None,
+            _ => Some(self.encoder.checkpoint()?),
+        };
+        Ok(VideoCheckpoint {
+            version: CHECKPOINT_VERSION,
+            plane: (
+                self.state.plane.w(),
+                self.state.plane.h(),
+                self.state.plane.c(),
+            ),
+            time_parameters: (
+                self.state.tps,
+                self.state.params.ref_time,
+                self.state.params.delta_t_max,
+            ),
+            pixel_multi_mode: self.state.params.pixel_multi_mode,
+            event_pixel_trees: self.event_pixel_trees.clone(),
+            in_interval_count: self.state.in_interval_count,
+            running_intensities: self.state.running_intensities.clone(),
+            event_stats_last_t: self.event_stats_last_t.clone(),
+            pixels_reset: self.pixels_reset,
+            encoder,
+            denoiser: self.denoiser.clone(),
+            quality_controller: self.quality_controller.clone(),
+            framed: None,
+            source_position: 0,
+        })
+    }
+
+    /// Restore the state of every pixel from a checkpoint taken by [`Video::save_state`], so that
+    /// the transcode continues where it left off. The video must have the same plane and time
+    /// parameters as the one which was saved. The output isn't affected: to go on appending to the
+    /// same output, use [`Video::resume_write_out`] instead of [`Video::write_out`], or write out
+    /// to a new one.
+    ///
+    /// The video must also have a [`TemporalFilter`] and a [`QualityTarget`] if and only if the
+    /// saved one did. Their history is restored along with the settings they were saved with.
+    pub fn restore_state(&mut self, checkpoint: &VideoCheckpoint) -> Result<(), SourceError> {
+        checkpoint.check_version()?;
+        let plane = (
+            self.state.plane.w(),
+            self.state.plane.h(),
+            self.state.plane.c(),
+        );
+        if checkpoint.plane != plane {
+            return Err(SourceError::BadParams(format!(
+                "The checkpoint's plane is {:?}, but the video's is {plane:?}",
+                checkpoint.plane
+            )));
+        }
+        let time_parameters = (
+            self.state.tps,
+            self.state.params.ref_time,
+            self.state.params.delta_t_max,
+        );
+        if checkpoint.time_parameters != time_parameters {
+            return Err(SourceError::BadParams(format!(
+                "The checkpoint's time parameters are {:?}, but the video's are {time_parameters:?}",
+                checkpoint.time_parameters
+            )));
+        }
+        if checkpoint.denoiser.is_some() != self.denoiser.is_some() {
+            return Err(SourceError::BadParams(
+                "The checkpoint's temporal filter doesn't match the video's".to_string(),
+            ));
+        }
+        if checkpoint.quality_controller.is_some() != self.quality_controller.is_some() {
+            return Err(SourceError::BadParams(
+                "The checkpoint's quality target doesn't match the video's".to_string(),
+            ));
+        }
+        self.event_pixel_trees = checkpoint.event_pixel_trees.clone();
+        self.denoiser = checkpoint.denoiser.clone();
+        self.quality_controller = checkpoint.quality_controller.clone();
+        self.state.params.pixel_multi_mode = checkpoint.pixel_multi_mode;
+        self.state.in_interval_count = checkpoint.in_interval_count;
+        self.state.running_intensities = checkpoint.running_intensities.clone();
+        self.event_stats_last_t = checkpoint.event_stats_last_t.clone();
+        self.pixels_reset = checkpoint.pixels_reset;
+        Ok(())
+    }
+
+    fn set_initial_d(&mut self, frame: &Array3<f32>, frame_scale: f64) {
+        let chunk_rows = self.state.chunk_rows;
+        let renditions = self
+            .renditions
+            .iter_mut()
+            .map(|rendition| &mut rendition.pixels);
+        for pixels in std::iter::once(&mut self.event_pixel_trees).chain(renditions) {
+            pixels
+                .axis_chunks_iter_mut(Axis(0), chunk_rows)
+                .into_par_iter()
+                .zip(frame.axis_chunks_iter(Axis(0), chunk_rows).into_par_iter())
+                .for_each(|(mut px, frame_chunk)| {
+                    for (px, frame_val) in px.iter_mut().zip(frame_chunk.iter()) {
+                        let d_start = if *frame_val < 1.0 {
+                            D_ZERO_INTEGRATION
+                        } else {
+                            frame_val.log2().floor() as D
+                        };
+
+                        px.arena[0].set_d(d_start);
+                        px.base_val = (f64::from(*frame_val) * frame_scale) as u8;
+                    }
+                });
+        }
     }
 
     /// Get `ref_time`
@@ -892,8 +2397,8 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         let mut new_features = new_features
             .iter()
             .flat_map(|feature_set| feature_set.iter().map(|coord| [coord.x, coord.y]))
-            .collect::<Vec<[u16; 2]>>();
-        let new_features: HashSet<[u16; 2]> = new_features.drain(..).collect();
+            .collect::<Vec<[PixelAddress; 2]>>();
+        let new_features: HashSet<[PixelAddress; 2]> = new_features.drain(..).collect();
 
         #[cfg(feature = "feature-logging")]
         {
@@ -1046,7 +2551,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
             }
         }
 
-        let parameters = self.encoder.options.crf.get_parameters();
+        let parameters = *self.encoder.options.crf.get_parameters();
 
         for coord in &new_features {
             if self.state.show_features == ShowFeatureMode::Instant {
@@ -1058,20 +2563,15 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
                     None,
                 );
             }
-            if self.state.feature_rate_adjustment && parameters.feature_c_radius > 0 {
-                eprintln!("Adjusting feature rate");
-                let radius = parameters.feature_c_radius as i32;
-                for row in (coord[1] as i32 - radius).max(0)
-                    ..=(coord[1] as i32 + radius).min(self.state.plane.h() as i32 - 1)
-                {
-                    for col in (coord[0] as i32 - radius).max(0)
-                        ..=(coord[0] as i32 + radius).min(self.state.plane.w() as i32 - 1)
-                    {
-                        for c in 0..self.state.plane.c() {
-                            self.event_pixel_trees[[row as usize, col as usize, c as usize]]
-                                .c_thresh = min(parameters.c_thresh_baseline, 2);
-                        }
-                    }
+            if self.state.feature_rate_adjustment {
+                if parameters.feature_c_radius > 0 {
+                    eprintln!("Adjusting feature rate");
+                }
+                let plane = self.state.plane;
+                lower_c_thresh_near(&mut self.event_pixel_trees, *coord, &parameters, plane);
+                for rendition in &mut self.renditions {
+                    let parameters = rendition.crf_parameters();
+                    lower_c_thresh_near(&mut rendition.pixels, *coord, &parameters, plane);
                 }
             }
         }
@@ -1083,7 +2583,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         Ok(())
     }
 
-    fn cluster(&mut self, set: &HashSet<[u16; 2]>) {
+    fn cluster(&mut self, set: &HashSet<[PixelAddress; 2]>) {
         let points: Vec<[f32; 2]> = set
             .into_iter()
             .map(|coord| [coord[0] as f32, coord[1] as f32])
@@ -1217,8 +2717,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         let c_thresh_baseline = self.encoder.options.crf.get_parameters().c_thresh_baseline;
 
         for px in self.event_pixel_trees.iter_mut() {
-            px.c_thresh = c_thresh_baseline;
-            px.c_increase_counter = 0;
+            px.reset_c_thresh(c_thresh_baseline);
         }
     }
 
@@ -1253,8 +2752,7 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
         self.encoder.sync_crf();
 
         for px in self.event_pixel_trees.iter_mut() {
-            px.c_thresh = c_thresh_baseline;
-            px.c_increase_counter = 0;
+            px.reset_c_thresh(c_thresh_baseline);
         }
     }
 
@@ -1268,6 +2766,88 @@ impl<W: Write + 'static + std::marker::Send + std::marker::Sync + 'static> Video
     }
 }
 
+impl Video<BufWriter<File>> {
+    /// Continue a paused transcode from a checkpoint taken by [`Video::save_state`], appending to
+    /// the output file it was writing, in place of [`Video::write_out`]. Anything written to the
+    /// file after the checkpoint is discarded. The pixels are restored as by
+    /// [`Video::restore_state`].
+    ///
+    /// The stream's header is kept as it is, so the source camera and time mode are those of the
+    /// original output.
+    pub fn resume_write_out(
+        mut self,
+        file_path: &str,
+        encoder_options: EncoderOptions,
+        checkpoint: &VideoCheckpoint,
+    ) -> Result<Self, SourceError> {
+        let encoder_checkpoint = checkpoint.encoder().ok_or_else(|| {
+            SourceError::BadParams("The checkpoint wasn't writing an output".to_string())
+        })?;
+        self.restore_state(checkpoint)?;
+        let encoder_type = open_file_decoder(file_path)?.0.get_compression_type();
+        self.encoder = Encoder::resume(file_path, encoder_options, encoder_checkpoint)?;
+        self.encoder_type = encoder_type;
+        self.state.source_camera = self.encoder.meta().source_camera;
+        Ok(self)
+    }
+}
+
+/// The current wall-clock time, in UTC nanoseconds since the Unix epoch
+pub(crate) fn utc_now_ns() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .and_then(|elapsed| u64::try_from(elapsed.as_nanos()).ok())
+        .unwrap_or_default()
+}
+
+/// Lower the contrast threshold of the pixels within the feature radius of the given parameters
+/// around a feature at `coord`, so that the detail there is kept at a higher rate
+fn lower_c_thresh_near(
+    pixels: &mut Array3<PixelArena>,
+    coord: [PixelAddress; 2],
+    parameters: &CrfParameters,
+    plane: PlaneSize,
+) {
+    if parameters.feature_c_radius == 0 {
+        return;
+    }
+    let radius = parameters.feature_c_radius as i32;
+    for row in
+        (coord[1] as i32 - radius).max(0)..=(coord[1] as i32 + radius).min(plane.h() as i32 - 1)
+    {
+        for col in
+            (coord[0] as i32 - radius).max(0)..=(coord[0] as i32 + radius).min(plane.w() as i32 - 1)
+        {
+            for c in 0..plane.c() {
+                pixels[[row as usize, col as usize, c as usize]].c_thresh =
+                    min(parameters.c_thresh_baseline, 2);
+            }
+        }
+    }
+}
+
+/// Replace the second and third channels of each 2x2 block of pixels with their mean, stored at
+/// the top-left pixel of the block (the only one which is integrated for those channels)
+fn subsample_chroma(matrix: &mut Array3<f32>) {
+    let (height, width, _) = matrix.dim();
+    for y in (0..height).step_by(2) {
+        for x in (0..width).step_by(2) {
+            for c in 1..3 {
+                let mut sum = 0.0;
+                let mut count = 0.0;
+                for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    if let Some(val) = matrix.get((y + dy, x + dx, c)) {
+                        sum += val;
+                        count += 1.0;
+                    }
+                }
+                matrix[(y, x, c)] = sum / count;
+            }
+        }
+    }
+}
+
 /// Integrate an intensity value for a pixel, over a given time span
 ///
 /// # Arguments
@@ -1293,7 +2873,7 @@ pub fn integrate_for_px(
     params: &VideoStateParams,
     parameters: &CrfParameters,
 ) -> bool {
-    let _start_len = buffer.len();
+    let start_len = buffer.len();
     let mut grew_buffer = false;
     if px.need_to_pop_top {
         buffer.push(px.pop_top_event(intensity, params.pixel_tree_mode, params.ref_time));
@@ -1301,9 +2881,10 @@ pub fn integrate_for_px(
     }
 
     *base_val = px.base_val;
+    let c_thresh = px.contrast_band(params.noise_floor.as_ref());
 
-    if frame_val < base_val.saturating_sub(px.c_thresh)
-        || frame_val > base_val.saturating_add(px.c_thresh)
+    if frame_val < base_val.saturating_sub(c_thresh)
+        || frame_val > base_val.saturating_add(c_thresh)
     {
         let _tmp = buffer.len();
         px.pop_best_events(
@@ -1341,12 +2922,234 @@ pub fn integrate_for_px(
         grew_buffer = true;
     }
 
+    if let Some(budget) = &params.event_budget {
+        px.enforce_budget(&buffer[start_len..], budget);
+    }
+
     // if buffer.len() - start_len > 5 {
     //     dbg!("hm", buffer.len() - start_len);
     // }
     grew_buffer
 }
 
+/// The number of pixels integrated together by [`integrate_for_pxs`]
+pub const LANES: usize = 8;
+
+/// How [`integrate_for_pxs`] integrates a pixel
+#[derive(Clone, Copy)]
+enum Lane {
+    /// The pixel isn't sampled, or was already integrated on the GPU
+    Skip,
+
+    /// The pixel may be integrated in bulk at the given D, if it doesn't reach its threshold
+    Bulk(D),
+
+    /// The pixel must be integrated by [`integrate_for_px`]
+    Single,
+}
+
+/// Integrate a chunk of rows of intensities into their pixels, in bulk where the chunk is
+/// contiguous
+#[allow(clippy::too_many_arguments)]
+fn integrate_chunk(
+    mut px_chunk: ArrayViewMut3<PixelArena>,
+    matrix_chunk: ArrayView3<Intensity32>,
+    frame_scale: f64,
+    time_spanned: f32,
+    buffer: &mut Vec<Event>,
+    params: &VideoStateParams,
+    parameters: &CrfParameters,
+    chroma_subsampling: ChromaSubsampling,
+) {
+    match (px_chunk.as_slice_mut(), matrix_chunk.as_slice()) {
+        (Some(pxs), Some(inputs)) => integrate_for_pxs(
+            pxs,
+            inputs,
+            frame_scale,
+            time_spanned,
+            buffer,
+            params,
+            parameters,
+            chroma_subsampling,
+        ),
+        _ => {
+            for (px, input) in px_chunk.iter_mut().zip(matrix_chunk.iter()) {
+                integrate_for_pxs(
+                    std::slice::from_mut(px),
+                    std::slice::from_ref(input),
+                    frame_scale,
+                    time_spanned,
+                    buffer,
+                    params,
+                    parameters,
+                    chroma_subsampling,
+                );
+            }
+        }
+    }
+}
+
+/// Integrate intensity values for a run of pixels, over a given time span, as
+/// [`integrate_for_px`] does for each one. In a given interval, most pixels just integrate without
+/// firing an event, so their threshold comparisons and Δt accumulation are batched [`LANES`] pixels
+/// at a time, with SIMD under the `simd` feature. The pixels which fire fall back to
+/// [`integrate_for_px`].
+///
+/// Pixels which aren't sampled under `chroma_subsampling`, or which were already integrated on
+/// the GPU, are skipped.
+///
+/// # Arguments
+///
+/// * `pxs`: the pixels to integrate
+/// * `intensities`: the intensity to integrate for each pixel
+/// * `frame_scale`: the factor which brings the intensities to the 8-bit scale of the contrast
+/// thresholds
+/// * `time_spanned`: the time spanned by the intensity values
+/// * `buffer`: the buffer to push events to
+/// * `params`: the parameters of the video source
+/// * `parameters`: the CRF parameters of the video source
+/// * `chroma_subsampling`: the subsampling of the color channels
+#[allow(clippy::too_many_arguments)]
+pub fn integrate_for_pxs(
+    pxs: &mut [PixelArena],
+    intensities: &[Intensity32],
+    frame_scale: f64,
+    time_spanned: f32,
+    buffer: &mut Vec<Event>,
+    params: &VideoStateParams,
+    parameters: &CrfParameters,
+    chroma_subsampling: ChromaSubsampling,
+) {
+    let mut base_val = 0;
+    let delta_t_max = params.delta_t_max as f32;
+
+    for (pxs, intensities) in pxs.chunks_mut(LANES).zip(intensities.chunks(LANES)) {
+        let mut lanes = [Lane::Skip; LANES];
+        let mut frame_vals = [0_u8; LANES];
+        let mut integration = [0.0; LANES];
+        let mut delta_t = [0.0; LANES];
+        let mut intensity = [0.0; LANES];
+        let mut threshold = [0.0; LANES];
+        for (lane, (px, input)) in pxs.iter_mut().zip(intensities).enumerate() {
+            if !chroma_subsampling.is_sampled(px.coord) || std::mem::take(&mut px.integrated_on_gpu)
+            {
+                continue;
+            }
+            frame_vals[lane] = (f64::from(*input) * frame_scale) as u8;
+            lanes[lane] = match px.bulk_d(*input, frame_vals[lane], params.noise_floor.as_ref()) {
+                Some(d) => {
+                    (integration[lane], delta_t[lane]) = px.head_integration();
+                    intensity[lane] = *input;
+                    threshold[lane] = D_SHIFT_F32[usize::from(d)];
+                    Lane::Bulk(d)
+                }
+                None => Lane::Single,
+            };
+        }
+
+        let (integration, delta_t, fires, dtm_reached) = integrate_lanes(
+            integration,
+            delta_t,
+            intensity,
+            threshold,
+            time_spanned,
+            delta_t_max,
+        );
+
+        for (lane, (px, input)) in pxs.iter_mut().zip(intensities).enumerate() {
+            let bit = 1 << lane;
+            match lanes[lane] {
+                Lane::Skip => {}
+                Lane::Bulk(d)
+                    if fires & bit == 0
+                        && px.integrate_bulk(
+                            d,
+                            integration[lane],
+                            delta_t[lane],
+                            dtm_reached & bit != 0,
+                            time_spanned,
+                            params.ref_time,
+                            parameters.c_thresh_max,
+                            parameters.c_increase_velocity,
+                        ) =>
+                {
+                    if let Some(budget) = &params.event_budget {
+                        px.enforce_budget(&[], budget);
+                    }
+                }
+                Lane::Bulk(_) | Lane::Single => {
+                    integrate_for_px(
+                        px,
+                        &mut base_val,
+                        frame_vals[lane],
+                        *input,
+                        time_spanned,
+                        buffer,
+                        params,
+                        parameters,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "simd")]
+use integrate_lanes_simd as integrate_lanes;
+
+#[cfg(not(feature = "simd"))]
+use integrate_lanes_scalar as integrate_lanes;
+
+/// Add `intensity` and `time` to the integration and Δt of a batch of lanes, with SIMD. Returns
+/// the new integrations and Δts, and the bitmasks of the lanes which reach their `threshold` and
+/// `delta_t_max`, respectively. [`integrate_for_pxs`] uses this under the `simd` feature.
+#[cfg(feature = "simd")]
+pub fn integrate_lanes_simd(
+    integration: [f32; LANES],
+    delta_t: [f32; LANES],
+    intensity: [f32; LANES],
+    threshold: [f32; LANES],
+    time: f32,
+    delta_t_max: f32,
+) -> ([f32; LANES], [f32; LANES], i32, i32) {
+    let integration = f32x8::from(integration) + f32x8::from(intensity);
+    let delta_t = f32x8::from(delta_t) + f32x8::splat(time);
+    let fires = integration.cmp_ge(f32x8::from(threshold)).move_mask();
+    let dtm_reached = delta_t.cmp_ge(f32x8::splat(delta_t_max)).move_mask();
+    (
+        integration.to_array(),
+        delta_t.to_array(),
+        fires,
+        dtm_reached,
+    )
+}
+
+/// Add `intensity` and `time` to the integration and Δt of a batch of lanes, one lane at a time.
+/// The results are the same as those of `integrate_lanes_simd`, which [`integrate_for_pxs`]
+/// uses instead under the `simd` feature.
+pub fn integrate_lanes_scalar(
+    mut integration: [f32; LANES],
+    mut delta_t: [f32; LANES],
+    intensity: [f32; LANES],
+    threshold: [f32; LANES],
+    time: f32,
+    delta_t_max: f32,
+) -> ([f32; LANES], [f32; LANES], i32, i32) {
+    let (mut fires, mut dtm_reached) = (0, 0);
+    for (lane, ((integration, delta_t), (intensity, threshold))) in integration
+        .iter_mut()
+        .zip(&mut delta_t)
+        .zip(intensity.iter().zip(&threshold))
+        .enumerate()
+    {
+        *integration += intensity;
+        *delta_t += time;
+        fires |= i32::from(*integration >= *threshold) << lane;
+        dtm_reached |= i32::from(*delta_t >= delta_t_max) << lane;
+    }
+    (integration, delta_t, fires, dtm_reached)
+}
+
 #[cfg(feature = "open-cv")]
 /// Shows the given [`Mat`] in an `OpenCV` window with the given name.
 /// This function is the same as [`show_display`], except that it does not check
@@ -1400,7 +3203,9 @@ pub trait Source<W: Write + std::marker::Send + std::marker::Sync + 'static> {
 
     /// Get the [`Video`] object associated with this [`Source`], consuming the [`Source`] in the
     /// process.
-    fn get_video(self) -> Video<W>;
+    fn get_video(self) -> Video<W>
+    where
+        Self: std::marker::Sized;
 
     /// Get the input frame from the source
     fn get_input(&self) -> Option<&Frame>;
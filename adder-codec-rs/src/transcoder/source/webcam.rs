@@ -0,0 +1,370 @@
+use crate::transcoder::source::denoise::TemporalFilter;
+use crate::transcoder::source::noise::NoiseModel;
+use crate::transcoder::source::quality::QualityTarget;
+use crate::transcoder::source::undistort::LensModel;
+use crate::transcoder::source::video::Video;
+use crate::transcoder::source::video::{
+    ContrastMask, Crop, IntensityLut, Orientation, SourceError,
+};
+use crate::transcoder::source::video::{Source, VideoBuilder};
+use adder_codec_core::Mode::FramePerfect;
+use adder_codec_core::{
+    ChromaSubsampling, DeltaT, Event, PixelMultiMode, PlaneSize, SourceCamera, TimeMode,
+};
+
+use crate::utils::viz::ShowFeatureMode;
+use adder_codec_core::codec::{EncoderOptions, EncoderType};
+
+use crate::utils::cv::handle_color;
+
+use nokhwa::pixel_format::RgbFormat;
+use nokhwa::utils::{
+    CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType, Resolution,
+};
+use nokhwa::Camera;
+use std::io::Write;
+use std::time::Duration;
+
+#[cfg(feature = "feature-logging")]
+use chrono::Local;
+use video_rs_adder_dep::Frame;
+
+/// Attributes of a live webcam -> ADΔER transcode. Frames are captured through the platform's
+/// native capture API (e.g., V4L2 on Linux), so no OpenCV install is needed.
+pub struct Webcam<W: Write + 'static + std::marker::Send + std::marker::Sync> {
+    camera: Camera,
+    pub(crate) input_frame: Frame,
+
+    /// Number of frames captured so far
+    frame_idx: u32,
+
+    /// Number of frames captured before the window set by [`VideoBuilder::start_time`]
+    window_frame_start: u32,
+
+    /// The number of frames to transcode from the start of the window, if it's limited by
+    /// [`VideoBuilder::duration`]
+    window_frame_count: Option<u32>,
+
+    /// FPS of the camera stream, as negotiated by `Webcam::new()`
+    pub source_fps: f32,
+
+    /// Whether the input video is color
+    color_input: bool,
+
+    pub(crate) video: Video<W>,
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Webcam<W> {
+    /// Open the camera at `index` and start capturing from it. The camera is asked for the given
+    /// `resolution` (width, height) and `fps`, or the closest format it supports. With neither,
+    /// it captures at its highest frame rate.
+    pub fn new(
+        index: u32,
+        color_input: bool,
+        resolution: Option<(u32, u32)>,
+        fps: Option<u32>,
+    ) -> Result<Webcam<W>, SourceError> {
+        let resolution = resolution.map(|(width, height)| Resolution::new(width, height));
+        let format_type = match (resolution, fps) {
+            (None, None) => RequestedFormatType::AbsoluteHighestFrameRate,
+            (Some(resolution), None) => RequestedFormatType::HighestResolution(resolution),
+            (None, Some(fps)) => RequestedFormatType::HighestFrameRate(fps),
+            (Some(resolution), Some(fps)) => {
+                RequestedFormatType::Closest(CameraFormat::new(resolution, FrameFormat::MJPEG, fps))
+            }
+        };
+        let mut camera = Camera::new(
+            CameraIndex::Index(index),
+            RequestedFormat::new::<RgbFormat>(format_type),
+        )?;
+        camera.open_stream()?;
+
+        let source_fps = camera.frame_rate() as f32;
+        let resolution = camera.resolution();
+        let (width, height) = (resolution.width(), resolution.height());
+
+        let plane = PlaneSize::new(width, height, if color_input { 3 } else { 1 })?;
+
+        let video = Video::new(plane, FramePerfect, None)?;
+
+        Ok(Webcam {
+            camera,
+            input_frame: Frame::default((height as usize, width as usize, 3)),
+            frame_idx: 0,
+            window_frame_start: 0,
+            window_frame_count: None,
+            source_fps,
+            color_input,
+            video,
+        })
+    }
+
+    /// Transcode the color channels with the given subsampling. See
+    /// [`Video::chroma_subsampling`].
+    pub fn chroma_subsampling(
+        mut self,
+        chroma_subsampling: ChromaSubsampling,
+    ) -> Result<Self, SourceError> {
+        self.video = self.video.chroma_subsampling(chroma_subsampling)?;
+        Ok(self)
+    }
+
+    /// Apply a lookup table to the input intensities before integration. See
+    /// [`Video::intensity_lut`].
+    pub fn intensity_lut(mut self, lut: Option<IntensityLut>) -> Self {
+        self.video = self.video.intensity_lut(lut);
+        self
+    }
+
+    /// Model the noise of the sensor, so that it doesn't fire events. See
+    /// [`Video::noise_model`].
+    pub fn noise_model(mut self, model: Option<NoiseModel>) -> Self {
+        self.video = self.video.noise_model(model);
+        self
+    }
+
+    /// Adjust the CRF to hold a target quality. See [`Video::quality_target`].
+    pub fn quality_target(mut self, target: Option<QualityTarget>) -> Self {
+        self.video = self.video.quality_target(target);
+        self
+    }
+
+    /// Denoise the input frames over time before integration. See
+    /// [`Video::temporal_filter`].
+    pub fn temporal_filter(mut self, filter: Option<TemporalFilter>) -> Result<Self, SourceError> {
+        self.video = self.video.temporal_filter(filter)?;
+        Ok(self)
+    }
+
+    /// Cap the number of events each pixel may fire per second. See [`Video::event_budget`].
+    pub fn event_budget(mut self, events_per_second: Option<u32>) -> Self {
+        self.video = self.video.event_budget(events_per_second);
+        self
+    }
+
+    /// Correct the lens distortion of the input frames before integration. See
+    /// [`Video::lens_undistortion`].
+    pub fn lens_undistortion(mut self, model: Option<LensModel>) -> Result<Self, SourceError> {
+        self.video = self.video.lens_undistortion(model)?;
+        Ok(self)
+    }
+
+    /// Automatically derive the ticks per second from the camera FPS and `ref_time`
+    pub fn auto_time_parameters(
+        mut self,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            let tps = (ref_time as f32 * self.source_fps) as DeltaT;
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            return Err(SourceError::BadParams(
+                "delta_t_max must be a multiple of ref_time".to_string(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Get the number of ticks each frame is said to span
+    pub fn get_ref_time(&self) -> u32 {
+        self.video.state.params.ref_time
+    }
+
+    /// Get the previous input frame
+    pub fn get_last_input_frame(&self) -> &Frame {
+        &self.input_frame
+    }
+
+    /// Convert a length of time to a number of captured frames
+    fn duration_to_frames(&self, duration: Duration) -> u32 {
+        (duration.as_secs_f64() * f64::from(self.source_fps)).round() as u32
+    }
+
+    /// Capture the next frame from the camera, decoded to 8-bit RGB
+    fn capture_frame(&mut self) -> Result<Frame, SourceError> {
+        let resolution = self.camera.resolution();
+        let shape = (resolution.height() as usize, resolution.width() as usize, 3);
+        let mut frame = Frame::default(shape);
+        let buffer = self.camera.frame()?;
+        let Some(data) = frame.as_slice_mut() else {
+            return Err(SourceError::UninitializedData);
+        };
+        buffer.decode_image_to_buffer::<RgbFormat>(data)?;
+        self.frame_idx += 1;
+        Ok(frame)
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> Source<W> for Webcam<W> {
+    /// Capture the next frame from the camera and integrate its pixel-wise intensities with
+    /// `ref_time` (the number of ticks each frame is said to span)
+    fn consume(&mut self) -> Result<Vec<Vec<Event>>, SourceError> {
+        if let Some(count) = self.window_frame_count {
+            if self.frame_idx >= self.window_frame_start.saturating_add(count) {
+                return Err(SourceError::BufferEmpty);
+            }
+        }
+        let frame = handle_color(self.capture_frame()?, self.color_input)?;
+        self.input_frame = self.video.fit_to_plane(frame.clone());
+
+        self.video
+            .integrate_matrix(frame, self.video.state.params.ref_time as f32)
+    }
+
+    fn crf(&mut self, crf: u8) {
+        self.video.update_crf(crf);
+    }
+
+    fn get_video_mut(&mut self) -> &mut Video<W> {
+        &mut self.video
+    }
+
+    fn get_video_ref(&self) -> &Video<W> {
+        &self.video
+    }
+
+    fn get_video(self) -> Video<W> {
+        self.video
+    }
+
+    fn get_input(&self) -> Option<&Frame> {
+        Some(self.get_last_input_frame())
+    }
+
+    fn get_running_input_bitrate(&self) -> f64 {
+        let video = self.get_video_ref();
+        video.get_tps() as f64 / video.get_ref_time() as f64
+            * video.state.plane.volume() as f64
+            * 8.0
+    }
+}
+
+impl<W: Write + 'static + std::marker::Send + std::marker::Sync> VideoBuilder<W> for Webcam<W> {
+    fn crf(mut self, crf: u8) -> Self {
+        self.video.update_crf(crf);
+        self
+    }
+
+    fn quality_manual(
+        mut self,
+        c_thresh_baseline: u8,
+        c_thresh_max: u8,
+        delta_t_max_multiplier: u32,
+        c_increase_velocity: u8,
+        feature_c_radius_denom: f32,
+    ) -> Self {
+        self.video.update_quality_manual(
+            c_thresh_baseline,
+            c_thresh_max,
+            delta_t_max_multiplier,
+            c_increase_velocity,
+            feature_c_radius_denom,
+        );
+        self
+    }
+
+    fn chunk_rows(mut self, chunk_rows: usize) -> Self {
+        self.video = self.video.chunk_rows(chunk_rows);
+        self
+    }
+
+    fn crop(mut self, crop: Option<Crop>) -> Result<Self, SourceError> {
+        self.video = self.video.crop(crop)?;
+        Ok(self)
+    }
+
+    fn orientation(mut self, orientation: Option<Orientation>) -> Result<Self, SourceError> {
+        self.video = self.video.orientation(orientation)?;
+        Ok(self)
+    }
+
+    fn contrast_mask(mut self, mask: Option<ContrastMask>) -> Result<Self, SourceError> {
+        self.video = self.video.contrast_mask(mask)?;
+        Ok(self)
+    }
+
+    fn time_parameters(
+        mut self,
+        tps: DeltaT,
+        ref_time: DeltaT,
+        delta_t_max: DeltaT,
+        time_mode: Option<TimeMode>,
+    ) -> Result<Self, SourceError> {
+        if delta_t_max % ref_time == 0 {
+            self.video = self
+                .video
+                .time_parameters(tps, ref_time, delta_t_max, time_mode)?;
+        } else {
+            eprintln!("delta_t_max must be a multiple of ref_time");
+        }
+        Ok(self)
+    }
+
+    fn write_out(
+        mut self,
+        source_camera: SourceCamera,
+        time_mode: TimeMode,
+        pixel_multi_mode: PixelMultiMode,
+        adu_interval: Option<usize>,
+        encoder_type: EncoderType,
+        encoder_options: EncoderOptions,
+        write: W,
+    ) -> Result<Box<Self>, SourceError> {
+        self.video = self.video.write_out(
+            Some(source_camera),
+            Some(time_mode),
+            Some(pixel_multi_mode),
+            adu_interval,
+            encoder_type,
+            encoder_options,
+            write,
+        )?;
+        Ok(Box::new(self))
+    }
+
+    fn detect_features(mut self, detect_features: bool, show_features: ShowFeatureMode) -> Self {
+        self.video = self.video.detect_features(detect_features, show_features);
+        self
+    }
+
+    /// A live source can't seek, so the frames captured before the start time are discarded,
+    /// e.g., to let the camera's auto-exposure settle
+    fn start_time(mut self, start_time: Duration) -> Result<Self, SourceError> {
+        let frame_idx_start = self.duration_to_frames(start_time);
+        while self.frame_idx < frame_idx_start {
+            self.capture_frame()?;
+        }
+        self.window_frame_start = self.frame_idx;
+        Ok(self)
+    }
+
+    fn duration(mut self, duration: Option<Duration>) -> Self {
+        self.window_frame_count = duration.map(|duration| self.duration_to_frames(duration));
+        self
+    }
+
+    #[cfg(feature = "feature-logging")]
+    fn log_path(mut self, name: String) -> Self {
+        let date_time = Local::now();
+        let formatted = format!("{}_{}.log", name, date_time.format("%d_%m_%Y_%H_%M_%S"));
+        let log_handle = std::fs::File::create(formatted).ok();
+        self.video.state.feature_log_handle = log_handle;
+
+        // Write the plane size to the log file
+        if let Some(handle) = &mut self.video.state.feature_log_handle {
+            writeln!(
+                handle,
+                "{}x{}x{}",
+                self.video.state.plane.w(),
+                self.video.state.plane.h(),
+                self.video.state.plane.c()
+            )
+            .unwrap();
+        }
+        self
+    }
+}
@@ -227,6 +227,17 @@ pub fn handle_color(mut input: Frame, color: bool) -> Result<Frame, SourceError>
     Ok(input)
 }
 
+/// Convert an array of BGR intensities to a single channel of luma intensities, on the same
+/// scale. Unlike [`handle_color`], this works at any bit depth.
+pub fn luma_intensities(bgr: &Array3<f32>) -> Array3<f32> {
+    let (height, width, _) = bgr.dim();
+    let mut luma = Array3::zeros((height, width, 1));
+    for (mut luma, bgr) in luma.lanes_mut(Axis(2)).into_iter().zip(bgr.lanes(Axis(2))) {
+        luma[0] = 0.114 * bgr[0] + 0.587 * bgr[1] + 0.299 * bgr[2];
+    }
+    luma
+}
+
 #[cfg(feature = "open-cv")]
 pub fn feature_precision_recall_accuracy(
     gt: &opencv::core::Vector<opencv::core::KeyPoint>,
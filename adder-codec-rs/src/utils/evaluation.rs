@@ -0,0 +1,259 @@
+use crate::framer::driver::FramerMode::INSTANTANEOUS;
+use crate::framer::driver::{FrameSequence, Framer, FramerBuilder};
+use crate::transcoder::source::video::SourceError;
+use crate::utils::cv::{calculate_quality_metrics, handle_color, QualityMetrics};
+use adder_codec_core::codec::decoder::Decoder;
+use adder_codec_core::{open_file_decoder, PlaneSize};
+use bitstream_io::{BigEndian, BitReader};
+use ndarray::{s, Array3};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use video_rs_adder_dep::{Decoder as VideoDecoder, Locator, Options, Resize};
+
+/// The quality metrics of a single reconstructed frame
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct FrameQualityMetrics {
+    /// Index of the frame reconstructed from the ADΔER stream
+    pub frame_idx: u64,
+
+    /// Presentation time of the reference frame, in seconds from the start of the evaluation
+    pub timestamp: f64,
+
+    /// The metrics for this frame
+    pub metrics: QualityMetrics,
+}
+
+/// The results of evaluating an ADΔER stream against its reference video
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QualityEvaluation {
+    /// The metrics of each frame, in order
+    pub frames: Vec<FrameQualityMetrics>,
+
+    /// The mean of each metric across all frames
+    pub aggregate: QualityMetrics,
+}
+
+/// Compares the frames reconstructed from an `.adder` file against the framed video it was
+/// transcoded from, outside of the live transcoder loop.
+///
+/// Reconstructed frames are produced at the frame rate of the reference video, and the two are
+/// aligned by the presentation timestamp of each reference frame.
+pub struct ReferenceEvaluator {
+    reader: Decoder<BufReader<File>>,
+    bitreader: BitReader<BufReader<File>, BigEndian>,
+    framer: FrameSequence<u8>,
+    reference: VideoDecoder,
+    reference_fps: f64,
+    reference_start: Option<f64>,
+    plane: PlaneSize,
+    recon_frame: Option<Array3<u8>>,
+    recon_idx: u64,
+    metrics: QualityMetrics,
+}
+
+impl ReferenceEvaluator {
+    /// Open an `.adder` file and the reference video it was transcoded from. The reference is
+    /// resized to the dimensions of the ADΔER stream, and converted to grayscale if the stream
+    /// is not in color.
+    pub fn new(adder_path: &Path, reference_path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let (reader, bitreader) = open_file_decoder(
+            adder_path
+                .to_str()
+                .ok_or("Invalid path for the ADΔER file")?,
+        )?;
+        let meta = *reader.meta();
+
+        let reference = VideoDecoder::new_with_options_and_resize(
+            &Locator::Path(reference_path),
+            &Options::default(),
+            Resize::Fit(meta.plane.w(), meta.plane.h()),
+        )
+        .map_err(SourceError::from)?;
+        let reference_fps = reference.frame_rate() as f64;
+
+        let framer = FramerBuilder::new(meta.plane, 1)
+            .codec_version(meta.codec_version, meta.time_mode)
+            .time_parameters(
+                meta.tps,
+                meta.ref_interval,
+                meta.delta_t_max,
+                Some(reference_fps as f32),
+            )
+            .mode(INSTANTANEOUS)
+            .source(reader.get_source_type(), meta.source_camera)
+            .chroma_subsampling(meta.chroma_subsampling)
+            .finish::<u8>();
+
+        Ok(Self {
+            reader,
+            bitreader,
+            framer,
+            reference,
+            reference_fps,
+            reference_start: None,
+            plane: meta.plane,
+            recon_frame: None,
+            recon_idx: 0,
+            metrics: QualityMetrics {
+                psnr: Some(0.0),
+                mse: Some(0.0),
+                ssim: None,
+            },
+        })
+    }
+
+    /// Choose which metrics to evaluate by making them `Some()`. By default, PSNR and MSE are
+    /// evaluated.
+    pub fn metrics(mut self, metrics: QualityMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Skip ahead in the reference video, for streams that were transcoded starting partway
+    /// through it
+    pub fn frame_start(mut self, frame_idx_start: u32) -> Result<Self, SourceError> {
+        let video_frame_count = self.reference.frame_count();
+        if frame_idx_start >= video_frame_count as u32 {
+            return Err(SourceError::StartOutOfBounds(frame_idx_start));
+        };
+        let ts_millis = (frame_idx_start as f64 / self.reference_fps * 1000.0) as i64;
+        self.reference.reader.seek(ts_millis)?;
+        Ok(self)
+    }
+
+    /// Evaluate the next reference frame against the reconstructed frame at the same timestamp.
+    ///
+    /// Returns `None` once either the reference video or the ADΔER stream is exhausted.
+    pub fn next_frame_metrics(&mut self) -> Result<Option<FrameQualityMetrics>, Box<dyn Error>> {
+        let (time, frame) = match self.reference.decode() {
+            Ok(a) => a,
+            Err(_) => return Ok(None),
+        };
+        let reference_frame = handle_color(frame, self.plane.c() == 3)?;
+
+        // Timestamps are relative to the first frame we evaluate
+        let ts = time.as_secs_f64();
+        let timestamp = ts - *self.reference_start.get_or_insert(ts);
+        let target_idx = (timestamp * self.reference_fps).round() as u64;
+
+        // Advance the reconstruction to the frame at the same timestamp. If the reference repeats
+        // a timestamp, we compare against the same reconstructed frame again.
+        while self.recon_frame.is_none() || self.recon_idx < target_idx {
+            match self.next_reconstructed_frame()? {
+                Some(frame) => {
+                    if self.recon_frame.is_some() {
+                        self.recon_idx += 1;
+                    }
+                    self.recon_frame = Some(frame);
+                }
+                None => return Ok(None),
+            }
+        }
+
+        let recon_frame = self.recon_frame.as_ref().ok_or("No reconstructed frame")?;
+        let metrics = calculate_quality_metrics(&reference_frame, recon_frame, self.metrics)?;
+
+        Ok(Some(FrameQualityMetrics {
+            frame_idx: self.recon_idx,
+            timestamp,
+            metrics,
+        }))
+    }
+
+    /// Evaluate every frame, returning the per-frame metrics and their means
+    pub fn evaluate(mut self) -> Result<QualityEvaluation, Box<dyn Error>> {
+        let mut frames = Vec::new();
+        while let Some(frame_metrics) = self.next_frame_metrics()? {
+            frames.push(frame_metrics);
+        }
+        let aggregate = aggregate_metrics(&frames);
+        Ok(QualityEvaluation { frames, aggregate })
+    }
+
+    /// Decode events until the framer has a full frame ready, then assemble it into an image
+    fn next_reconstructed_frame(&mut self) -> Result<Option<Array3<u8>>, Box<dyn Error>> {
+        loop {
+            if self.framer.is_frame_0_filled() {
+                let chunks = self
+                    .framer
+                    .pop_next_frame()
+                    .ok_or("Frame was filled, but couldn't be popped")?;
+                let mut image = Array3::zeros((
+                    self.plane.h_usize(),
+                    self.plane.w_usize(),
+                    self.plane.c_usize(),
+                ));
+                let mut y = 0;
+                for chunk in chunks {
+                    let rows = chunk.shape()[0];
+                    image
+                        .slice_mut(s![y..y + rows, .., ..])
+                        .zip_mut_with(&chunk, |px, val| *px = val.unwrap_or(0));
+                    y += rows;
+                }
+                return Ok(Some(image));
+            }
+
+            match self.reader.digest_event(&mut self.bitreader) {
+                Ok(mut event) => {
+                    self.framer.ingest_event(&mut event, None);
+                }
+                Err(_) => {
+                    // End of the stream. Fill out whatever frames are left.
+                    if !self.framer.flush_frame_buffer() {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Take the mean of each metric across all frames
+pub fn aggregate_metrics(frames: &[FrameQualityMetrics]) -> QualityMetrics {
+    let mean = |metric: fn(&QualityMetrics) -> Option<f64>| {
+        let values: Vec<f64> = frames.iter().filter_map(|f| metric(&f.metrics)).collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.iter().sum::<f64>() / values.len() as f64)
+        }
+    };
+
+    QualityMetrics {
+        psnr: mean(|m| m.psnr),
+        mse: mean(|m| m.mse),
+        ssim: mean(|m| m.ssim),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate_means() {
+        let frames: Vec<FrameQualityMetrics> = [(30.0, 2.0), (40.0, 4.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, (psnr, mse))| FrameQualityMetrics {
+                frame_idx: i as u64,
+                timestamp: i as f64 / 30.0,
+                metrics: QualityMetrics {
+                    psnr: Some(*psnr),
+                    mse: Some(*mse),
+                    ssim: None,
+                },
+            })
+            .collect();
+
+        let aggregate = aggregate_metrics(&frames);
+        assert_eq!(aggregate.psnr, Some(35.0));
+        assert_eq!(aggregate.mse, Some(3.0));
+        assert!(aggregate.ssim.is_none());
+        assert!(aggregate_metrics(&[]).psnr.is_none());
+    }
+}
@@ -1,13 +1,13 @@
 
-use adder_codec_core::Coord;
+use adder_codec_core::{Coord, PixelAddress};
 use opencv::core::KeyPoint;
 use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
 
 #[derive(Debug, Clone, Copy)]
 pub struct LogFeature {
-    pub x: u16,
-    pub y: u16,
+    pub x: PixelAddress,
+    pub y: PixelAddress,
     pub non_max_suppression: bool,
     pub source: LogFeatureSource,
 }
@@ -57,8 +57,8 @@ impl LogFeature {
 
         let p = points.get(0).unwrap();
         Self {
-            x: p.x as u16,
-            y: p.y as u16,
+            x: p.x as PixelAddress,
+            y: p.y as PixelAddress,
             non_max_suppression,
             source,
         }
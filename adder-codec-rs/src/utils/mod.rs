@@ -5,9 +5,18 @@ pub mod simulproc;
 /// A module for migrating streams from one format to another
 pub mod stream_migration;
 
+/// A module for reversing streams in time
+pub mod stream_reversal;
+
+/// A module for splitting streams into self-contained shards, and joining them back together
+pub mod stream_split;
+
 /// Computer vision utilities
 pub mod cv;
 
+/// Offline evaluation of ADΔER reconstruction quality against a reference video
+pub mod evaluation;
+
 #[cfg(feature = "feature-logging")]
 pub mod logging;
 /// A module for visualizing streams
@@ -2,8 +2,8 @@ use crate::framer::driver::FramerMode::INSTANTANEOUS;
 use crate::framer::driver::{Framer, FramerBuilder};
 use crate::framer::scale_intensity;
 use crate::framer::scale_intensity::FrameValue;
-use crate::transcoder::source::framed::Framed;
 use crate::transcoder::source::video::Source;
+use crate::transcoder::source::AdderSource;
 use adder_codec_core::DeltaT;
 use clap::Parser;
 use rayon::ThreadPool;
@@ -14,8 +14,6 @@ use std::fs::File;
 use std::io;
 use std::io::{BufWriter, Write};
 
-use adder_codec_core::SourceCamera::FramedU8;
-use adder_codec_core::SourceType::U8;
 use adder_codec_core::{Event, TimeMode};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::Instant;
@@ -32,6 +30,11 @@ pub struct SimulProcArgs {
     #[clap(long, action)]
     pub color_input: bool,
 
+    /// Transcode the second and third color channels at half resolution
+    #[clap(long, action)]
+    #[serde(default)]
+    pub chroma_subsampling: bool,
+
     /// Number of ticks per input frame // TODO: modularize for different sources
     #[clap(short, long, default_value_t = 255)]
     pub ref_time: u32,
@@ -48,14 +51,83 @@ pub struct SimulProcArgs {
     #[clap(long, default_value_t = 0)]
     pub frame_idx_start: u32,
 
+    /// Seconds into the input to start transcoding from, instead of `frame_idx_start` (0 = off)
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub start_time: f64,
+
+    /// Seconds of the input to transcode from the start (0 = no limit)
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub duration: f64,
+
+    /// Number of input frames to average into each output frame, to speed up time (1 = off)
+    #[clap(long, default_value_t = 1)]
+    #[serde(default = "default_time_lapse")]
+    pub time_lapse: u32,
+
+    /// Mean difference between successive framed input frames, as a fraction of full scale,
+    /// above which a scene cut is detected and every pixel is reset (0 = off)
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub scene_cut_threshold: f32,
+
+    /// Most framed input frames to integrate at once while the scene is static, adapting the
+    /// reference interval to the motion (0 or 1 = off)
+    #[clap(long, default_value_t = 0)]
+    #[serde(default)]
+    pub adaptive_ref_interval_max: u32,
+
+    /// Largest shift, in pixels, applied to each framed input frame to cancel out the global
+    /// motion of a handheld camera (0 = no stabilization)
+    #[clap(long, default_value_t = 0)]
+    #[serde(default)]
+    pub stabilization_max_shift: u32,
+
+    /// Path to an intensity lookup table (e.g., an inverse camera response function) to apply to
+    /// the input frames, as whitespace- or comma-separated values (optional)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub lut_filename: String,
+
+    /// Path to the camera's lens calibration, as the whitespace- or comma-separated values
+    /// `fx fy cx cy k1 k2 p1 p2 k3`, to undistort the input frames (optional)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub lens_filename: String,
+
     /// Show live view displays?
     #[clap(short, long, action)]
     pub show_display: bool,
 
-    /// Path to input file
+    /// Path to input file, URL of a live network stream (e.g., `rtsp://...` or `https://...`),
+    /// or "-" to read raw frames from stdin (e.g., piped from `ffmpeg -f rawvideo -`), as
+    /// described by `raw_pix_fmt`, `raw_size`, and `raw_fps`
     #[clap(short, long, default_value = "./in.mp4")]
     pub input_filename: String,
 
+    /// Pixel format of the raw frames read from stdin (gray, gray16le, rgb24, bgr24, rgb48le, a
+    /// Bayer mosaic such as bayer_rggb8 or bayer_rggb16le, or planar YCbCr such as yuv420p or
+    /// yuv444p16le)
+    #[clap(long, default_value = "rgb24")]
+    #[serde(default = "default_raw_pix_fmt")]
+    pub raw_pix_fmt: String,
+
+    /// Resolution of the raw frames read from stdin, as WIDTHxHEIGHT
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub raw_size: String,
+
+    /// Frame rate of the raw frames read from stdin
+    #[clap(long, default_value_t = 30.0)]
+    #[serde(default = "default_raw_fps")]
+    pub raw_fps: f32,
+
+    /// Transcode each plane of raw Bayer frames separately, instead of demosaicing them
+    #[clap(long, action)]
+    #[serde(default)]
+    pub raw_bayer_planes: bool,
+
     /// Path to output events file
     #[clap(long, default_value = "")]
     pub output_events_filename: String,
@@ -68,10 +140,82 @@ pub struct SimulProcArgs {
     #[clap(short('z'), long, default_value_t = 1.0)]
     pub scale: f64,
 
+    /// Transcode only a window of the input, given as `WIDTHxHEIGHT+X+Y` in pixels of the source
+    /// video (before it's resized by the scale), e.g., `640x480+100+50` (optional)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub crop: String,
+
+    /// Rotate the input clockwise by this many degrees (0, 90, 180, or 270), after any crop
+    #[clap(long, default_value_t = 0)]
+    #[serde(default)]
+    pub rotation: u32,
+
+    /// Mirror the input left to right, before it's rotated
+    #[clap(long, action)]
+    #[serde(default)]
+    pub flip_horizontal: bool,
+
+    /// Mirror the input top to bottom, before it's rotated
+    #[clap(long, action)]
+    #[serde(default)]
+    pub flip_vertical: bool,
+
+    /// Integrate the pixels which don't fire an event on the GPU, if the transcoder is built with
+    /// the `gpu` feature. Events are still fired on the CPU.
+    #[clap(long, action)]
+    #[serde(default)]
+    pub gpu: bool,
+
+    /// The standard deviation of the sensor's read noise, in source intensity units. With
+    /// `shot_noise`, it keeps noise from firing events (optional)
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub read_noise: f32,
+
+    /// The variance of the sensor's shot noise per unit of source intensity (optional)
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub shot_noise: f32,
+
+    /// How many standard deviations of the noise a change must exceed to fire events
+    #[clap(long, default_value_t = 2.0)]
+    #[serde(default = "default_noise_sigmas")]
+    pub noise_sigmas: f32,
+
+    /// How much of each pixel's history the temporal denoising filter keeps, in [0, 1). 0 turns
+    /// the filter off
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub denoise_strength: f32,
+
+    /// The scale of the changes which the temporal denoising filter smooths out, on an 8-bit
+    /// intensity scale. Larger changes pass through.
+    #[clap(long, default_value_t = 8.0)]
+    #[serde(default = "default_denoise_edge")]
+    pub denoise_edge: f32,
+
     /// CRF quality level
     #[clap(long, default_value_t = 3)]
     pub crf: u8,
 
+    /// A PSNR, in dB, to hold by adjusting the CRF level as the transcode goes, starting from
+    /// `crf`. 0 keeps the CRF fixed
+    #[clap(long, default_value_t = 0.0)]
+    #[serde(default)]
+    pub target_psnr: f64,
+
+    /// How far, in dB, the PSNR may stray from `target_psnr` before the CRF is adjusted
+    #[clap(long, default_value_t = 0.5)]
+    #[serde(default = "default_psnr_tolerance")]
+    pub psnr_tolerance: f64,
+
+    /// Comma-separated CRF levels of additional outputs to write in the same pass, each next to
+    /// the output events file with its CRF in the name (optional)
+    #[clap(long, default_value = "")]
+    #[serde(default)]
+    pub rendition_crfs: String,
+
     /// Number of threads to use. If not provided, will default to the number of cores on the
     /// system.
     #[clap(long, default_value_t = 4)]
@@ -85,11 +229,35 @@ pub struct SimulProcArgs {
     pub integration_mode: String,
 }
 
+fn default_time_lapse() -> u32 {
+    1
+}
+
+fn default_raw_pix_fmt() -> String {
+    "rgb24".to_string()
+}
+
+fn default_raw_fps() -> f32 {
+    30.0
+}
+
+fn default_noise_sigmas() -> f32 {
+    2.0
+}
+
+fn default_denoise_edge() -> f32 {
+    8.0
+}
+
+fn default_psnr_tolerance() -> f64 {
+    0.5
+}
+
 /// A struct for simultaneously transcoding a video source to ADΔER and reconstructing a framed
 /// video from ADΔER
 pub struct SimulProcessor<W: Write + std::marker::Send + std::marker::Sync + 'static> {
     /// Framed transcoder hook
-    pub source: Framed<W>,
+    pub source: AdderSource<W>,
     thread_pool: tokio::runtime::Runtime,
     events_tx: Sender<Vec<Vec<Event>>>,
 }
@@ -99,7 +267,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> SimulProcessor<
     ///
     /// # Arguments
     ///
-    /// * `source`: [`Framed<W>`] source
+    /// * `source`: framed source, e.g., a [`Framed<W>`](crate::transcoder::source::framed::Framed)
     /// * `ref_time`: ticks per source frame
     /// * `output_path`: path to output file
     /// * `frame_max`: max number of frames to transcode
@@ -112,7 +280,7 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> SimulProcessor<
     /// # Examples
     /// TODO: add examples
     pub fn new<T>(
-        source: Framed<W>,
+        source: impl Into<AdderSource<W>>,
         ref_time: DeltaT,
         output_path: &str,
         frame_max: i32,
@@ -131,7 +299,8 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> SimulProcessor<
             + FrameValue<Output = T>
             + Serialize
             + num_traits::Zero
-            + Into<f64>,
+            + Into<f64>
+            + num_traits::FromPrimitive,
     {
         let thread_pool_framer = rayon::ThreadPoolBuilder::new()
             .num_threads(max(num_threads / 2, 1))
@@ -139,26 +308,27 @@ impl<W: Write + std::marker::Send + std::marker::Sync + 'static> SimulProcessor<
         let thread_pool_transcoder = tokio::runtime::Builder::new_multi_thread()
             .worker_threads(max(num_threads, 1))
             .build()?;
-        let reconstructed_frame_rate = source.source_fps;
+        let source = source.into();
+        let video = source.get_video_ref();
         // For instantaneous reconstruction, make sure the frame rate matches the source video rate
-        assert_eq!(
-            source.video.state.tps / ref_time,
-            reconstructed_frame_rate as u32
-        );
+        let reconstructed_frame_rate = video.state.tps as f32 / ref_time as f32;
+        let source_camera = video.state.source_camera;
 
-        let plane = source.get_video_ref().state.plane;
+        let plane = video.state.plane;
 
         let mut framer = thread_pool_framer.install(|| {
-            FramerBuilder::new(plane, source.video.state.chunk_rows)
+            FramerBuilder::new(plane, video.state.chunk_rows)
                 .codec_version(codec_version, time_mode)
                 .time_parameters(
-                    source.video.state.tps,
+                    video.state.tps,
                     ref_time,
-                    source.video.state.params.delta_t_max,
+                    video.state.params.delta_t_max,
                     Some(reconstructed_frame_rate),
                 )
                 .mode(INSTANTANEOUS)
-                .source(U8, FramedU8)
+                .source(source_camera.source_type(), source_camera)
+                .chroma_subsampling(video.state.chroma_subsampling)
+                .color_space(video.state.color_space)
                 .finish::<T>()
         });
 
@@ -1,10 +1,14 @@
 use adder_codec_core::codec::decoder::Decoder;
 use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::codec::raw::stream::{RawInput, RawOutput};
+use adder_codec_core::codec::transcode::transcode;
+use adder_codec_core::codec::{EncoderOptions, LATEST_CODEC_VERSION};
 use adder_codec_core::{DeltaT, Event, SourceCamera, TimeMode};
-use bitstream_io::BigEndian;
+use bitstream_io::{BigEndian, BitReader};
 use ndarray::Array3;
 use std::error::Error;
 use std::io::{Read, Seek, Write};
+use std::sync::Arc;
 
 /// Transforms an [`Event`] with an [absolute](TimeMode::AbsoluteT) timestamp to am [`Event`] with
 /// a [delta](TimeMode::DeltaT) timestamp.
@@ -58,7 +62,7 @@ pub fn migrate_v2<W: Write + std::marker::Send + std::marker::Sync + 'static, R:
 
         *t += event.t;
 
-        if output_stream.meta().time_mode == TimeMode::AbsoluteT {
+        if output_stream.meta().time_mode != TimeMode::DeltaT {
             event.t = *t;
 
             // If framed video source, we can take advantage of scheme that reduces event rate by half
@@ -87,6 +91,86 @@ pub fn migrate_v2<W: Write + std::marker::Send + std::marker::Sync + 'static, R:
     Ok(output_stream)
 }
 
+/// Transforms an input stream to a new output stream with the output stream's [`TimeMode`], as in
+/// [`transcode`].
+///
+/// # Arguments
+///
+/// * `input_stream`: input stream to be migrated
+/// * `bitreader`: bitreader to be used for reading the input stream
+/// * `output_stream`: output stream to be written to
+///
+/// returns: `Result<Encoder<W>, Box<dyn Error, Global>>` where `W` is the type of the output stream
+pub fn migrate_time_mode<
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    R: Read + Seek,
+>(
+    mut input_stream: Decoder<R>,
+    bitreader: &mut bitstream_io::BitReader<R, BigEndian>,
+    mut output_stream: Encoder<W>,
+) -> Result<Encoder<W>, Box<dyn Error>> {
+    transcode(&mut input_stream, bitreader, &mut output_stream)?;
+    Ok(output_stream)
+}
+
+/// Upgrades a raw stream of any codec version to a raw stream of `target_version`, rewriting its
+/// header in that version's format. A v1 stream (which only has [delta](TimeMode::DeltaT)
+/// timestamps) is converted to [absolute](TimeMode::AbsoluteT) timestamps, as in [`migrate_v2`].
+/// Newer streams keep their [`TimeMode`], and their user metadata is carried over.
+///
+/// # Arguments
+///
+/// * `reader`: the input stream, from its start
+/// * `writer`: the output stream to be written to
+/// * `target_version`: the codec version to upgrade to, from 2 up to [`LATEST_CODEC_VERSION`]. It
+///   can't be older than the input stream's version.
+///
+/// returns: `Result<W, Box<dyn Error, Global>>`, the writer of the upgraded stream
+pub fn migrate<R: Read + Seek, W: Write + std::marker::Send + std::marker::Sync + 'static>(
+    reader: R,
+    writer: W,
+    target_version: u8,
+) -> Result<W, Box<dyn Error>> {
+    if !(2..=LATEST_CODEC_VERSION).contains(&target_version) {
+        return Err(format!(
+            "Can't migrate to codec version {target_version}. Must be from 2 to {LATEST_CODEC_VERSION}"
+        )
+        .into());
+    }
+
+    let mut bitreader = BitReader::endian(reader, BigEndian);
+    let input_stream = Decoder::new_raw(RawInput::new(), &mut bitreader)?;
+    let mut meta = *input_stream.meta();
+    if meta.codec_version > target_version {
+        return Err(format!(
+            "Can't migrate a v{} stream back to codec version {target_version}",
+            meta.codec_version
+        )
+        .into());
+    }
+
+    let mut options = EncoderOptions::default(meta.plane);
+    if !input_stream.user_metadata().is_empty() {
+        options.user_metadata = Some(Arc::new(input_stream.user_metadata().clone()));
+    }
+
+    // v1 streams only have delta timestamps, and are migrated as for v2
+    let v1 = meta.codec_version < 2;
+    meta.codec_version = target_version;
+    if v1 {
+        meta.time_mode = TimeMode::AbsoluteT;
+    }
+    let output_stream = Encoder::new_raw(RawOutput::new(meta, writer), options);
+    let output_stream = if v1 {
+        migrate_v2(input_stream, &mut bitreader, output_stream)?
+    } else {
+        migrate_time_mode(input_stream, &mut bitreader, output_stream)?
+    };
+    Ok(output_stream
+        .close_writer()?
+        .ok_or("The migrated stream has no writer")?)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::framer::driver::FramerMode::INSTANTANEOUS;
@@ -95,10 +179,10 @@ mod tests {
     use adder_codec_core::codec::decoder::Decoder;
     use adder_codec_core::codec::encoder::Encoder;
     use adder_codec_core::codec::raw::stream::{RawInput, RawOutput};
-    use adder_codec_core::codec::{CodecMetadata, EncoderOptions};
+    use adder_codec_core::codec::{CodecMetadata, EncoderOptions, LATEST_CODEC_VERSION};
     use adder_codec_core::SourceCamera::FramedU8;
     use adder_codec_core::TimeMode::AbsoluteT;
-    use adder_codec_core::{Coord, Event, PlaneSize, TimeMode};
+    use adder_codec_core::{Coord, Event, PlaneSize, TimeMode, D_MAX};
     use bitstream_io::{BigEndian, BitReader};
     use ndarray::Array3;
     use std::fs::File;
@@ -126,6 +210,18 @@ mod tests {
                 event_size: 0,
                 source_camera: FramedU8,
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -177,6 +273,18 @@ mod tests {
                 event_size: 0,
                 source_camera: FramedU8,
                 adu_interval: 1,
+                chroma_subsampling: Default::default(),
+                epoch: None,
+                enhancement_layers: 0,
+                wide_coordinates: false,
+                block_size: 16,
+                d_max: D_MAX,
+                channel_layout: Default::default(),
+                context_priors_id: 0,
+                quantization_table: Default::default(),
+                intensity_peak: 1.0,
+                color_space: Default::default(),
+                motion_compensation: false,
             },
             bufwriter,
         );
@@ -279,6 +387,87 @@ mod tests {
         Ok(())
     }
 
+    /// Test the `migrate` function by upgrading a v1 stream to the latest codec version, checking
+    /// its events against the same video encoded in v2, and that it can't be migrated back
+    #[test]
+    fn test_migrate() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::utils::stream_migration::migrate;
+
+        let bufwriter = migrate(
+            BufReader::new(File::open("./tests/samples/nyc_v1_1px.adder")?),
+            BufWriter::new(Vec::new()),
+            LATEST_CODEC_VERSION,
+        )?;
+        let bytes = bufwriter.into_inner()?;
+        let mut bitreader_migrate = BitReader::endian(Cursor::new(&*bytes), BigEndian);
+        let mut reader_migrate = Decoder::new_raw(RawInput::new(), &mut bitreader_migrate)?;
+        assert_eq!(reader_migrate.meta().codec_version, LATEST_CODEC_VERSION);
+        assert_eq!(reader_migrate.meta().time_mode, AbsoluteT);
+
+        let bufreader = BufReader::new(File::open("./tests/samples/nyc_source_v2_2_1px.adder")?);
+        let mut bitreader_gt = BitReader::endian(bufreader, BigEndian);
+        let mut reader_gt = Decoder::new_raw(RawInput::new(), &mut bitreader_gt)?;
+
+        let events_migrate = reader_migrate
+            .events(&mut bitreader_migrate)
+            .collect::<Result<Vec<_>, _>>()?;
+        let events_gt = reader_gt
+            .events(&mut bitreader_gt)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut event_count = 0;
+        for (event_migrate, event_gt) in events_migrate.iter().zip(events_gt.iter()) {
+            assert_eq!(event_migrate, event_gt);
+            event_count += 1;
+        }
+        assert_eq!(event_count, 5);
+
+        assert!(migrate(Cursor::new(&*bytes), Vec::new(), 2).is_err());
+        assert!(migrate(Cursor::new(&*bytes), Vec::new(), LATEST_CODEC_VERSION + 1).is_err());
+
+        Ok(())
+    }
+
+    /// Test the `migrate_time_mode` function by converting a `DeltaT` stream to a `Mixed` stream,
+    /// and checking the decoded events against the same video encoded in `AbsoluteT` mode
+    #[test]
+    fn test_migrate_time_mode_mixed() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::utils::stream_migration::migrate_time_mode;
+
+        let bufreader = BufReader::new(File::open("./tests/samples/bunny_v2_dt.adder")?);
+        let mut bitreader = BitReader::endian(bufreader, BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+
+        let mut meta = *reader.meta();
+        meta.codec_version = LATEST_CODEC_VERSION;
+        meta.time_mode = TimeMode::Mixed;
+        let compression = RawOutput::new(meta, BufWriter::new(Vec::new()));
+        let mut stream = Encoder::new_raw(compression, EncoderOptions::default(meta.plane));
+
+        stream = migrate_time_mode(reader, &mut bitreader, stream)?;
+
+        let writer = stream.close_writer().unwrap().unwrap();
+        let bytes = writer.into_inner().unwrap();
+        let bufreader = BufReader::new(Cursor::new(&*bytes));
+        let mut bitreader_mixed = BitReader::endian(bufreader, BigEndian);
+        let mut reader_mixed = Decoder::new_raw(RawInput::new(), &mut bitreader_mixed).unwrap();
+        assert_eq!(reader_mixed.meta().time_mode, TimeMode::Mixed);
+
+        let bufreader = BufReader::new(File::open("./tests/samples/bunny_v2_t.adder")?);
+        let mut bitreader_gt = BitReader::endian(bufreader, BigEndian);
+        let mut reader_gt = Decoder::new_raw(RawInput::new(), &mut bitreader_gt).unwrap();
+
+        let events_mixed = reader_mixed
+            .events(&mut bitreader_mixed)
+            .collect::<Result<Vec<_>, _>>()?;
+        let events_gt = reader_gt
+            .events(&mut bitreader_gt)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(events_mixed.len(), 333);
+        assert_eq!(events_mixed, events_gt);
+
+        Ok(())
+    }
+
     /// Test that when reconstructing framed video, we get the same results with both `DeltaT` and
     /// `AbsoluteT` time modes
     #[test]
@@ -0,0 +1,227 @@
+use adder_codec_core::codec::decoder::Decoder;
+use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::{AbsoluteT, Event, TimeMode, D_EMPTY};
+use bitstream_io::BigEndian;
+use ndarray::Array3;
+use std::error::Error;
+use std::io::{Read, Seek, Write};
+
+/// Reverses the events of a stream in time.
+///
+/// Each event describes the intensity a pixel integrated between its previous event and its own
+/// timestamp. When the stream is reversed, that same span runs from `end_t - t` to
+/// `end_t - prev_t`, where `end_t` is the last timestamp of the whole stream, so each event is
+/// moved to the reversed end of its span and keeps its decimation. If a pixel stopped firing
+/// before `end_t`, it begins the reversed stream with an [empty](D_EMPTY) event marking the span
+/// with no information.
+///
+/// The whole stream is held in memory, since the last events must be written first. The output
+/// events are sorted by time, and are converted to the output stream's [`TimeMode`].
+///
+/// # Arguments
+///
+/// * `input_stream`: input stream to be reversed
+/// * `bitreader`: bitreader to be used for reading the input stream
+/// * `output_stream`: output stream to be written to
+///
+/// returns: `Result<Encoder<W>, Box<dyn Error, Global>>` where `W` is the type of the output stream
+pub fn reverse_stream<
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    R: Read + Seek,
+>(
+    mut input_stream: Decoder<R>,
+    bitreader: &mut bitstream_io::BitReader<R, BigEndian>,
+    mut output_stream: Encoder<W>,
+) -> Result<Encoder<W>, Box<dyn Error>> {
+    let plane = input_stream.meta().plane;
+    let input_delta_t = input_stream.meta().time_mode == TimeMode::DeltaT;
+
+    // The absolute timestamps of each pixel's events, in order
+    let mut px_events: Array3<Vec<Event>> = Array3::from_elem(
+        (plane.h_usize(), plane.w_usize(), plane.c_usize()),
+        Vec::new(),
+    );
+    let mut end_t: AbsoluteT = 0;
+
+    loop {
+        let mut event = match input_stream.digest_event(bitreader) {
+            Ok(event) => event,
+            Err(_) => {
+                break;
+            }
+        };
+        let events = &mut px_events[[
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        ]];
+        if input_delta_t {
+            event.t += events.last().map_or(0, |last| last.t);
+        }
+        end_t = end_t.max(event.t);
+        events.push(event);
+    }
+
+    let mut reversed = Vec::new();
+    for events in px_events.iter() {
+        let Some(last) = events.last() else {
+            continue;
+        };
+        if last.t < end_t {
+            reversed.push(Event {
+                coord: last.coord,
+                d: D_EMPTY,
+                t: end_t - last.t,
+            });
+        }
+        for (i, event) in events.iter().enumerate().rev() {
+            let prev_t = if i > 0 { events[i - 1].t } else { 0 };
+            reversed.push(Event {
+                coord: event.coord,
+                d: event.d,
+                t: end_t - prev_t,
+            });
+        }
+    }
+
+    // A stable sort keeps each pixel's events in order, even if it fires twice at the same time
+    reversed.sort_by_key(|event| event.t);
+
+    let output_delta_t = output_stream.meta().time_mode == TimeMode::DeltaT;
+    let mut t_tree: Array3<AbsoluteT> =
+        Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize()));
+    for mut event in reversed {
+        if output_delta_t {
+            let last_t = &mut t_tree[[
+                event.coord.y_usize(),
+                event.coord.x_usize(),
+                event.coord.c_usize(),
+            ]];
+            let t = event.t;
+            event.t -= *last_t;
+            *last_t = t;
+        }
+        output_stream.ingest_event(event)?;
+    }
+
+    Ok(output_stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adder_codec_core::codec::raw::stream::{RawInput, RawOutput};
+    use adder_codec_core::codec::{CodecMetadata, EncoderOptions};
+    use adder_codec_core::SourceCamera::FramedU8;
+    use adder_codec_core::{Coord, PixelAddress, PlaneSize, D_MAX};
+    use bitstream_io::BitReader;
+    use std::io::{BufReader, BufWriter, Cursor};
+
+    fn encode(meta: CodecMetadata, events: &[Event]) -> Vec<u8> {
+        let mut stream = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(meta.plane),
+        );
+        for event in events {
+            stream.ingest_event(*event).unwrap();
+        }
+        let writer = stream.close_writer().unwrap().unwrap();
+        writer.into_inner().unwrap()
+    }
+
+    fn decode_all(bytes: &[u8]) -> Vec<Event> {
+        let mut bitreader = BitReader::endian(BufReader::new(Cursor::new(bytes)), BigEndian);
+        let mut reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let mut events = Vec::new();
+        while let Ok(event) = reader.digest_event(&mut bitreader) {
+            events.push(event);
+        }
+        events
+    }
+
+    fn event(x: PixelAddress, d: u8, t: AbsoluteT) -> Event {
+        Event {
+            coord: Coord { x, y: 0, c: None },
+            d,
+            t,
+        }
+    }
+
+    #[test]
+    fn test_reverse_stream() -> Result<(), Box<dyn Error>> {
+        let plane = PlaneSize::new(2, 1, 1)?;
+        let meta = CodecMetadata {
+            codec_version: 4,
+            header_size: 0,
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 255 * 30,
+            ref_interval: 255,
+            delta_t_max: 2550,
+            event_size: 0,
+            source_camera: FramedU8,
+            adu_interval: 1,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: 16,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
+        };
+        let bytes = encode(
+            meta,
+            &[
+                event(0, 5, 255),
+                event(1, 7, 510),
+                event(0, 6, 765),
+                event(0, 8, 1020),
+            ],
+        );
+
+        let mut bitreader = BitReader::endian(Cursor::new(bytes), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader)?;
+        let output = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        let output = reverse_stream(reader, &mut bitreader, output)?;
+        let reversed = decode_all(&output.close_writer()?.unwrap().into_inner()?);
+
+        assert_eq!(
+            reversed,
+            vec![
+                event(0, 8, 255),
+                event(1, D_EMPTY, 510),
+                event(0, 6, 765),
+                event(0, 5, 1020),
+                event(1, 7, 1020),
+            ]
+        );
+
+        // Reversing twice recovers the original spans
+        let bytes = encode(meta, &reversed);
+        let mut bitreader = BitReader::endian(Cursor::new(bytes), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader)?;
+        let output = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        let output = reverse_stream(reader, &mut bitreader, output)?;
+        let twice = decode_all(&output.close_writer()?.unwrap().into_inner()?);
+        assert_eq!(
+            twice
+                .iter()
+                .filter(|e| e.coord.x == 0)
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![event(0, 5, 255), event(0, 6, 765), event(0, 8, 1020)]
+        );
+        Ok(())
+    }
+}
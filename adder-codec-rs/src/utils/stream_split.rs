@@ -0,0 +1,635 @@
+use adder_codec_core::codec::decoder::Decoder;
+use adder_codec_core::codec::encoder::Encoder;
+use adder_codec_core::codec::raw::stream::RawOutput;
+use adder_codec_core::codec::{EncoderOptions, UserMetadata, LATEST_CODEC_VERSION};
+use adder_codec_core::{
+    open_file_decoder, AbsoluteT, Coord, Event, PixelAddress, TimeMode, D_EMPTY,
+};
+use bitstream_io::{BigEndian, BitReader};
+use ndarray::Array3;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::sync::Arc;
+
+/// The user metadata key holding the index of a shard
+pub const SHARD_INDEX_KEY: &str = "shard.index";
+
+/// The user metadata key holding the number of shards the stream was split into
+pub const SHARD_COUNT_KEY: &str = "shard.count";
+
+/// The user metadata key holding the number of intra events which begin a shard
+pub const SHARD_INTRA_EVENTS_KEY: &str = "shard.intra_events";
+
+/// Where a shard belongs in the stream it was split from, as recorded in its header
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ShardInfo {
+    /// The index of the shard, starting from 0
+    pub index: usize,
+
+    /// The number of shards the stream was split into
+    pub count: usize,
+
+    /// The number of intra events which begin the shard. Each is an [empty](D_EMPTY) event at the
+    /// time of its pixel's last event in the earlier shards, so that the pixel's first real event
+    /// in the shard spans the right time. They're dropped when the shards are concatenated.
+    pub intra_events: usize,
+}
+
+impl ShardInfo {
+    /// Read the shard info from the user metadata of a stream's header. Returns `None` if the
+    /// stream isn't a shard.
+    pub fn from_user_metadata(user_metadata: &UserMetadata) -> Option<Self> {
+        let get = |key: &str| -> Option<usize> { user_metadata.get(key)?.parse().ok() };
+        Some(Self {
+            index: get(SHARD_INDEX_KEY)?,
+            count: get(SHARD_COUNT_KEY)?,
+            intra_events: get(SHARD_INTRA_EVENTS_KEY)?,
+        })
+    }
+
+    /// Add the shard info to the user metadata of a stream's header
+    pub fn add_to_user_metadata(&self, user_metadata: &mut UserMetadata) {
+        user_metadata.insert(SHARD_INDEX_KEY.to_string(), self.index.to_string());
+        user_metadata.insert(SHARD_COUNT_KEY.to_string(), self.count.to_string());
+        user_metadata.insert(
+            SHARD_INTRA_EVENTS_KEY.to_string(),
+            self.intra_events.to_string(),
+        );
+    }
+}
+
+/// The user metadata key holding the index of a segment
+pub const SEGMENT_INDEX_KEY: &str = "segment.index";
+
+/// The user metadata key holding the number of segments the stream was split into
+pub const SEGMENT_COUNT_KEY: &str = "segment.count";
+
+/// The user metadata key holding the timestamp in the original stream which a segment's
+/// timestamps are rebased from
+pub const SEGMENT_START_T_KEY: &str = "segment.start_t";
+
+/// The user metadata key holding the number of intra events which begin a segment
+pub const SEGMENT_INTRA_EVENTS_KEY: &str = "segment.intra_events";
+
+/// Where a segment belongs in the stream it was split from, as recorded in its header
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// The index of the segment, starting from 0
+    pub index: usize,
+
+    /// The number of segments the stream was split into
+    pub count: usize,
+
+    /// The timestamp in the original stream which corresponds to time 0 in the segment
+    pub start_t: AbsoluteT,
+
+    /// The number of intra events which begin the segment. Each is an [empty](D_EMPTY) event at
+    /// time 0 for a pixel which fired in an earlier segment. They're dropped when the segments are
+    /// concatenated.
+    pub intra_events: usize,
+}
+
+impl SegmentInfo {
+    /// Read the segment info from the user metadata of a stream's header. Returns `None` if the
+    /// stream isn't a segment.
+    pub fn from_user_metadata(user_metadata: &UserMetadata) -> Option<Self> {
+        let get = |key: &str| -> Option<usize> { user_metadata.get(key)?.parse().ok() };
+        Some(Self {
+            index: get(SEGMENT_INDEX_KEY)?,
+            count: get(SEGMENT_COUNT_KEY)?,
+            start_t: user_metadata.get(SEGMENT_START_T_KEY)?.parse().ok()?,
+            intra_events: get(SEGMENT_INTRA_EVENTS_KEY)?,
+        })
+    }
+
+    /// Add the segment info to the user metadata of a stream's header
+    pub fn add_to_user_metadata(&self, user_metadata: &mut UserMetadata) {
+        user_metadata.insert(SEGMENT_INDEX_KEY.to_string(), self.index.to_string());
+        user_metadata.insert(SEGMENT_COUNT_KEY.to_string(), self.count.to_string());
+        user_metadata.insert(SEGMENT_START_T_KEY.to_string(), self.start_t.to_string());
+        user_metadata.insert(
+            SEGMENT_INTRA_EVENTS_KEY.to_string(),
+            self.intra_events.to_string(),
+        );
+    }
+}
+
+/// Splits a stream into `num_shards` self-contained raw streams with about the same number of
+/// events, so that they can be processed independently and later joined back together with
+/// [`concatenate_shards`].
+///
+/// Each shard has a full header, which records its [`ShardInfo`] in the user metadata. A shard
+/// begins with an intra start point: an [empty](D_EMPTY) event for each pixel which fired in an
+/// earlier shard, at the time of its last event. The events keep their timestamps from the
+/// original stream.
+///
+/// The stream is read twice, once to count its events and once to split them, so `open` is
+/// called twice to open it from the start.
+///
+/// # Arguments
+///
+/// * `open`: opens the input stream from the start
+/// * `num_shards`: the number of shards to split the stream into
+/// * `make_writer`: creates the writer for the shard with the given index
+///
+/// returns: `Result<Vec<W>, Box<dyn Error>>`, the writers of the shards, in order
+pub fn split_stream<R, W, O, M>(
+    mut open: O,
+    num_shards: usize,
+    mut make_writer: M,
+) -> Result<Vec<W>, Box<dyn Error>>
+where
+    R: Read + Seek,
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    O: FnMut() -> Result<(Decoder<R>, BitReader<R, BigEndian>), Box<dyn Error>>,
+    M: FnMut(usize) -> Result<W, Box<dyn Error>>,
+{
+    if num_shards == 0 {
+        return Err("Must split the stream into at least one shard".into());
+    }
+
+    let (mut input_stream, mut bitreader) = open()?;
+    let mut num_events = 0;
+    while input_stream.digest_event(&mut bitreader).is_ok() {
+        num_events += 1;
+    }
+
+    let (mut input_stream, mut bitreader) = open()?;
+    let mut meta = *input_stream.meta();
+    meta.codec_version = LATEST_CODEC_VERSION;
+
+    // The shards are raw streams, which can't group the events into Adus
+    if meta.time_mode == TimeMode::Mixed {
+        meta.time_mode = TimeMode::AbsoluteT;
+    }
+    let plane = meta.plane;
+    let delta_t = meta.time_mode == TimeMode::DeltaT;
+
+    // The absolute timestamp of each pixel's last event, if it has fired
+    let mut last_t: Array3<Option<AbsoluteT>> =
+        Array3::from_elem((plane.h_usize(), plane.w_usize(), plane.c_usize()), None);
+
+    // The absolute timestamp of each pixel's last event written to the current shard
+    let mut shard_t: Array3<AbsoluteT> =
+        Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize()));
+
+    let mut writers = Vec::with_capacity(num_shards);
+    let mut event_idx = 0;
+    for index in 0..num_shards {
+        // Start each pixel which has already fired from the time of its last event
+        let mut intra_events = Vec::new();
+        for ((y, x, c), t) in last_t.indexed_iter() {
+            if let Some(t) = t {
+                intra_events.push(Event {
+                    coord: Coord {
+                        x: x as PixelAddress,
+                        y: y as PixelAddress,
+                        c: (plane.c() > 1).then_some(c as u8),
+                    },
+                    d: D_EMPTY,
+                    t: *t,
+                });
+            }
+        }
+        intra_events.sort_by_key(|event| event.t);
+
+        let mut user_metadata = UserMetadata::new();
+        ShardInfo {
+            index,
+            count: num_shards,
+            intra_events: intra_events.len(),
+        }
+        .add_to_user_metadata(&mut user_metadata);
+        let mut options = EncoderOptions::default(plane);
+        options.user_metadata = Some(Arc::new(user_metadata));
+        let mut shard = Encoder::new_raw(RawOutput::new(meta, make_writer(index)?), options);
+
+        shard_t.fill(0);
+        for event in intra_events {
+            ingest_absolute(&mut shard, &mut shard_t, event, delta_t)?;
+        }
+
+        let shard_end = (index + 1) * num_events / num_shards;
+        while event_idx < shard_end {
+            let mut event = input_stream.digest_event(&mut bitreader)?;
+            let px_last_t = &mut last_t[[
+                event.coord.y_usize(),
+                event.coord.x_usize(),
+                event.coord.c_usize(),
+            ]];
+            if delta_t {
+                event.t += px_last_t.unwrap_or(0);
+            }
+            *px_last_t = Some(event.t);
+            ingest_absolute(&mut shard, &mut shard_t, event, delta_t)?;
+            event_idx += 1;
+        }
+        writers.extend(shard.close_writer()?);
+    }
+    Ok(writers)
+}
+
+/// Splits an ADΔER file into `num_shards` raw shards, as in [`split_stream`]
+pub fn split_file<W, M>(
+    input_path: &str,
+    num_shards: usize,
+    make_writer: M,
+) -> Result<Vec<W>, Box<dyn Error>>
+where
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    M: FnMut(usize) -> Result<W, Box<dyn Error>>,
+{
+    split_stream::<BufReader<File>, _, _, _>(
+        || Ok(open_file_decoder(input_path)?),
+        num_shards,
+        make_writer,
+    )
+}
+
+/// Splits a stream into self-contained raw segments, each spanning `segment_seconds` of the
+/// stream's time, e.g., for chunked delivery or for processing the segments in parallel. They can
+/// be joined back together with [`concatenate_shards`].
+///
+/// Each segment has a full header, which records its [`SegmentInfo`] in the user metadata. Its
+/// timestamps are rebased to begin at 0, and its epoch (if the stream has one) is moved to match.
+/// A segment begins with an intra start point: an [empty](D_EMPTY) event at time 0 for each pixel
+/// which fired in an earlier segment. The first event of such a pixel in the segment thus spans
+/// the time since the segment began. The segments are written with absolute timestamps, whatever
+/// the time mode of the input. Segment `k` holds the events with timestamps in
+/// `(k * length, (k + 1) * length]`, so it ends with its last tick. Every segment up to the last
+/// event is written, even if it has no events of its own.
+///
+/// The stream is read twice, once to find which segment each pixel first fires in and once to
+/// split the events, so `open` is called twice to open it from the start. The events needn't be
+/// in timestamp order, so every segment is held open until the end.
+///
+/// # Arguments
+///
+/// * `open`: opens the input stream from the start
+/// * `segment_seconds`: the length of each segment, in seconds
+/// * `make_writer`: creates the writer for the segment with the given index
+///
+/// returns: `Result<Vec<W>, Box<dyn Error>>`, the writers of the segments, in order
+pub fn segment_stream<R, W, O, M>(
+    mut open: O,
+    segment_seconds: f64,
+    mut make_writer: M,
+) -> Result<Vec<W>, Box<dyn Error>>
+where
+    R: Read + Seek,
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    O: FnMut() -> Result<(Decoder<R>, BitReader<R, BigEndian>), Box<dyn Error>>,
+    M: FnMut(usize) -> Result<W, Box<dyn Error>>,
+{
+    let (mut input_stream, mut bitreader) = open()?;
+    let mut meta = *input_stream.meta();
+    let segment_ticks = (segment_seconds * f64::from(meta.tps)).round();
+    if !(1.0..=f64::from(AbsoluteT::MAX)).contains(&segment_ticks) {
+        return Err("Each segment must span at least one tick".into());
+    }
+    let segment_ticks = segment_ticks as AbsoluteT;
+    let segment_of = |t: AbsoluteT| (t.saturating_sub(1) / segment_ticks) as usize;
+
+    let plane = meta.plane;
+    let delta_t = meta.time_mode == TimeMode::DeltaT;
+    let mut last_t: Array3<AbsoluteT> =
+        Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize()));
+    let absolute_t = |last_t: &mut Array3<AbsoluteT>, event: &mut Event| {
+        let px_last_t = &mut last_t[[
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        ]];
+        if delta_t {
+            event.t += *px_last_t;
+        }
+        *px_last_t = event.t;
+    };
+
+    // The segment which each pixel first fires in
+    let mut first_segment: Array3<Option<usize>> =
+        Array3::from_elem((plane.h_usize(), plane.w_usize(), plane.c_usize()), None);
+    let mut num_segments = 0;
+    while let Ok(mut event) = input_stream.digest_event(&mut bitreader) {
+        absolute_t(&mut last_t, &mut event);
+        let segment = segment_of(event.t);
+        let px_first_segment = &mut first_segment[[
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        ]];
+        *px_first_segment = Some(px_first_segment.map_or(segment, |first| first.min(segment)));
+        num_segments = num_segments.max(segment + 1);
+    }
+
+    meta.codec_version = LATEST_CODEC_VERSION;
+    meta.time_mode = TimeMode::AbsoluteT;
+    let epoch = meta.epoch;
+    let tps = u128::from(meta.tps.max(1));
+
+    let mut segments = Vec::with_capacity(num_segments);
+    for index in 0..num_segments {
+        let start_t = index as AbsoluteT * segment_ticks;
+
+        // Start each pixel which has already fired from the beginning of the segment
+        let intra_events: Vec<Event> = first_segment
+            .indexed_iter()
+            .filter(|(_, first)| first.is_some_and(|first| first < index))
+            .map(|((y, x, c), _)| Event {
+                coord: Coord {
+                    x: x as PixelAddress,
+                    y: y as PixelAddress,
+                    c: (plane.c() > 1).then_some(c as u8),
+                },
+                d: D_EMPTY,
+                t: 0,
+            })
+            .collect();
+
+        let mut user_metadata = UserMetadata::new();
+        SegmentInfo {
+            index,
+            count: num_segments,
+            start_t,
+            intra_events: intra_events.len(),
+        }
+        .add_to_user_metadata(&mut user_metadata);
+        let mut options = EncoderOptions::default(plane);
+        options.user_metadata = Some(Arc::new(user_metadata));
+        meta.epoch = epoch.map(|epoch| epoch + (u128::from(start_t) * 1_000_000_000 / tps) as u64);
+        let mut segment = Encoder::new_raw(RawOutput::new(meta, make_writer(index)?), options);
+        segment.ingest_events(&intra_events)?;
+        segments.push(segment);
+    }
+
+    let (mut input_stream, mut bitreader) = open()?;
+    last_t.fill(0);
+    while let Ok(mut event) = input_stream.digest_event(&mut bitreader) {
+        absolute_t(&mut last_t, &mut event);
+        let index = segment_of(event.t);
+        event.t -= index as AbsoluteT * segment_ticks;
+        segments[index].ingest_event(event)?;
+    }
+
+    let mut writers = Vec::with_capacity(num_segments);
+    for segment in segments {
+        writers.extend(segment.close_writer()?);
+    }
+    Ok(writers)
+}
+
+/// Splits an ADΔER file into raw segments of `segment_seconds` each, as in [`segment_stream`]
+pub fn segment_file<W, M>(
+    input_path: &str,
+    segment_seconds: f64,
+    make_writer: M,
+) -> Result<Vec<W>, Box<dyn Error>>
+where
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+    M: FnMut(usize) -> Result<W, Box<dyn Error>>,
+{
+    segment_stream::<BufReader<File>, _, _, _>(
+        || Ok(open_file_decoder(input_path)?),
+        segment_seconds,
+        make_writer,
+    )
+}
+
+/// Joins shards made by [`split_stream`], or segments made by [`segment_stream`], back into a
+/// single stream, dropping the intra events which begin each one and restoring the segments'
+/// original timestamps. The shards must be given in order. Streams which are neither are appended
+/// whole.
+///
+/// returns: `Result<Encoder<W>, Box<dyn Error>>`, the output stream
+pub fn concatenate_shards<
+    R: Read + Seek,
+    W: Write + std::marker::Send + std::marker::Sync + 'static,
+>(
+    shards: Vec<(Decoder<R>, BitReader<R, BigEndian>)>,
+    mut output_stream: Encoder<W>,
+) -> Result<Encoder<W>, Box<dyn Error>> {
+    let plane = output_stream.meta().plane;
+    let output_delta_t = output_stream.meta().time_mode == TimeMode::DeltaT;
+    let mut output_t: Array3<AbsoluteT> =
+        Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize()));
+
+    for (mut shard, mut bitreader) in shards {
+        let (intra_events, start_t) = match (
+            ShardInfo::from_user_metadata(shard.user_metadata()),
+            SegmentInfo::from_user_metadata(shard.user_metadata()),
+        ) {
+            (Some(info), _) => (info.intra_events, 0),
+            (None, Some(info)) => (info.intra_events, info.start_t),
+            (None, None) => (0, 0),
+        };
+        let input_delta_t = shard.meta().time_mode == TimeMode::DeltaT;
+        let mut input_t: Array3<AbsoluteT> =
+            Array3::zeros((plane.h_usize(), plane.w_usize(), plane.c_usize()));
+
+        let mut event_idx = 0;
+        while let Ok(mut event) = shard.digest_event(&mut bitreader) {
+            if input_delta_t {
+                let t = &mut input_t[[
+                    event.coord.y_usize(),
+                    event.coord.x_usize(),
+                    event.coord.c_usize(),
+                ]];
+                event.t += *t;
+                *t = event.t;
+            }
+            event_idx += 1;
+            if event_idx > intra_events {
+                event.t += start_t;
+                ingest_absolute(&mut output_stream, &mut output_t, event, output_delta_t)?;
+            }
+        }
+    }
+
+    Ok(output_stream)
+}
+
+/// Ingest an event with an absolute timestamp, converting it to a delta time if the stream is in
+/// [`TimeMode::DeltaT`]
+fn ingest_absolute<W: Write + std::marker::Send + std::marker::Sync + 'static>(
+    encoder: &mut Encoder<W>,
+    t_tree: &mut Array3<AbsoluteT>,
+    mut event: Event,
+    delta_t: bool,
+) -> Result<(), Box<dyn Error>> {
+    if delta_t {
+        let last_t = &mut t_tree[[
+            event.coord.y_usize(),
+            event.coord.x_usize(),
+            event.coord.c_usize(),
+        ]];
+        let t = event.t;
+        event.t -= *last_t;
+        *last_t = t;
+    }
+    encoder.ingest_event(event)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adder_codec_core::codec::raw::stream::RawInput;
+    use adder_codec_core::codec::CodecMetadata;
+    use adder_codec_core::PlaneSize;
+    use std::io::{BufWriter, Cursor};
+
+    fn event(x: PixelAddress, d: u8, t: AbsoluteT) -> Event {
+        Event {
+            coord: Coord { x, y: 0, c: None },
+            d,
+            t,
+        }
+    }
+
+    fn open(
+        bytes: &[u8],
+    ) -> (
+        Decoder<Cursor<Vec<u8>>>,
+        BitReader<Cursor<Vec<u8>>, BigEndian>,
+    ) {
+        let mut bitreader = BitReader::endian(Cursor::new(bytes.to_vec()), BigEndian);
+        let decoder = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        (decoder, bitreader)
+    }
+
+    fn decode_all(bytes: &[u8]) -> Vec<Event> {
+        let (mut decoder, mut bitreader) = open(bytes);
+        let mut events = Vec::new();
+        while let Ok(event) = decoder.digest_event(&mut bitreader) {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_split_concatenate() -> Result<(), Box<dyn Error>> {
+        let plane = PlaneSize::new(3, 1, 1)?;
+        let meta = CodecMetadata {
+            time_mode: TimeMode::DeltaT,
+            plane,
+            ..Default::default()
+        };
+        let events = vec![
+            event(0, 5, 100),
+            event(1, 6, 150),
+            event(0, 7, 100),
+            event(1, 5, 100),
+            event(0, 6, 300),
+            event(2, 8, 700),
+        ];
+        let mut stream = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        stream.ingest_events(&events)?;
+        let bytes = stream.close_writer()?.unwrap().into_inner()?;
+
+        let shards = split_stream(|| Ok(open(&bytes)), 3, |_| Ok(Cursor::new(Vec::new())))?;
+        assert_eq!(shards.len(), 3);
+        let shards: Vec<Vec<u8>> = shards.into_iter().map(Cursor::into_inner).collect();
+
+        // The second shard starts each pixel which fired in the first shard from its last event
+        let (decoder, _) = open(&shards[1]);
+        assert_eq!(
+            ShardInfo::from_user_metadata(decoder.user_metadata()),
+            Some(ShardInfo {
+                index: 1,
+                count: 3,
+                intra_events: 2,
+            })
+        );
+        assert_eq!(
+            decode_all(&shards[1]),
+            vec![
+                event(0, D_EMPTY, 100),
+                event(1, D_EMPTY, 150),
+                event(0, 7, 100),
+                event(1, 5, 100),
+            ]
+        );
+
+        // Joining the shards recovers the original stream
+        let output = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        let output = concatenate_shards(shards.iter().map(|shard| open(shard)).collect(), output)?;
+        assert_eq!(
+            decode_all(&output.close_writer()?.unwrap().into_inner()?),
+            events
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_segment_concatenate() -> Result<(), Box<dyn Error>> {
+        let plane = PlaneSize::new(2, 1, 1)?;
+        let meta = CodecMetadata {
+            time_mode: TimeMode::AbsoluteT,
+            plane,
+            tps: 1000,
+            epoch: Some(5_000_000_000),
+            ..Default::default()
+        };
+        let events = vec![
+            event(0, 5, 50),
+            event(1, 6, 120),
+            event(0, 7, 150),
+            event(1, 5, 350),
+        ];
+        let mut stream = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        stream.ingest_events(&events)?;
+        let bytes = stream.close_writer()?.unwrap().into_inner()?;
+
+        // Segments of 100 ticks, including an empty one
+        let segments = segment_stream(|| Ok(open(&bytes)), 0.1, |_| Ok(Cursor::new(Vec::new())))?;
+        assert_eq!(segments.len(), 4);
+        let segments: Vec<Vec<u8>> = segments.into_iter().map(Cursor::into_inner).collect();
+
+        // The second segment is rebased to its start, and starts the pixel which fired in the
+        // first segment from there
+        let (decoder, _) = open(&segments[1]);
+        assert_eq!(
+            SegmentInfo::from_user_metadata(decoder.user_metadata()),
+            Some(SegmentInfo {
+                index: 1,
+                count: 4,
+                start_t: 100,
+                intra_events: 1,
+            })
+        );
+        assert_eq!(decoder.meta().epoch, Some(5_100_000_000));
+        assert_eq!(
+            decode_all(&segments[1]),
+            vec![event(0, D_EMPTY, 0), event(1, 6, 20), event(0, 7, 50)]
+        );
+        assert_eq!(
+            decode_all(&segments[2]),
+            vec![event(0, D_EMPTY, 0), event(1, D_EMPTY, 0)]
+        );
+
+        // Joining the segments recovers the original stream
+        let output = Encoder::new_raw(
+            RawOutput::new(meta, BufWriter::new(Vec::new())),
+            EncoderOptions::default(plane),
+        );
+        let output = concatenate_shards(
+            segments.iter().map(|segment| open(segment)).collect(),
+            output,
+        )?;
+        assert_eq!(
+            decode_all(&output.close_writer()?.unwrap().into_inner()?),
+            events
+        );
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use adder_codec_core::codec::EncoderOptions;
 use adder_codec_core::SourceCamera::FramedU8;
 use adder_codec_core::SourceType::*;
 use adder_codec_core::TimeMode::DeltaT;
-use adder_codec_core::{Coord, Event, EventCoordless, PlaneSize, TimeMode};
+use adder_codec_core::{Coord, Event, EventCoordless, PlaneSize, TimeMode, D_MAX};
 use bitstream_io::{BigEndian, BitReader};
 use ndarray::{Array3, Axis};
 use std::fs;
@@ -19,6 +19,12 @@ use std::process::Command;
 use adder_codec_rs::framer::driver::FramerMode::INSTANTANEOUS;
 use adder_codec_rs::framer::driver::{FrameSequence, Framer, FramerBuilder};
 
+use adder_codec_rs::transcoder::source::aedat::{AedatChip, AedatReader, AedatVersion};
+use adder_codec_rs::transcoder::source::framed_sequence::FramedSequenceSource;
+use adder_codec_rs::transcoder::source::prophesee::Prophesee;
+use adder_codec_rs::transcoder::source::raw_video::{BayerPattern, RawPixelFormat, RawVideo};
+use adder_codec_rs::transcoder::source::video::{IntensityLut, Video};
+use adder_codec_rs::transcoder::source::video::{Source, SourceError};
 use rand::Rng;
 
 #[test]
@@ -124,6 +130,142 @@ fn test_sample_perfect_dt() {
     fs::remove_file(output_path).unwrap();
 }
 
+#[test]
+fn test_reconstruct_all() {
+    let open_sample = || {
+        let tmp = File::open("./tests/samples/sample_1_raw_events.adder").unwrap();
+        let mut bitreader = BitReader::endian(BufReader::new(tmp), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let frame_sequence: FrameSequence<u8> = FramerBuilder::new(reader.meta().plane, 64)
+            .codec_version(reader.meta().codec_version, TimeMode::DeltaT)
+            .time_parameters(
+                reader.meta().tps,
+                reader.meta().ref_interval,
+                reader.meta().delta_t_max,
+                Some(24.0),
+            )
+            .mode(INSTANTANEOUS)
+            .source(reader.get_source_type(), reader.meta().source_camera)
+            .finish();
+        (reader, bitreader, frame_sequence)
+    };
+
+    let (mut reader, mut bitreader, mut frame_sequence) = open_sample();
+    let plane = reader.meta().plane;
+    let frames = frame_sequence
+        .reconstruct_all(&mut reader, &mut bitreader, 1)
+        .unwrap();
+    assert_eq!(
+        &frames.shape()[1..],
+        &[plane.h_usize(), plane.w_usize(), plane.c_usize()]
+    );
+
+    // The stack begins with the same frames as the ingest/write loop produces. The trailing
+    // partial frames are flushed as well.
+    let expected = fs::read("./tests/samples/sample_1_instant_framed.gray").unwrap();
+    assert!(frames.shape()[0] >= 221);
+    assert_eq!(
+        frames
+            .iter()
+            .take(expected.len())
+            .copied()
+            .collect::<Vec<u8>>(),
+        expected
+    );
+
+    let (mut reader, mut bitreader, mut frame_sequence) = open_sample();
+    let strided = frame_sequence
+        .reconstruct_all(&mut reader, &mut bitreader, 10)
+        .unwrap();
+    assert_eq!(strided.shape()[0], (frames.shape()[0] + 9) / 10);
+    assert_eq!(
+        strided.index_axis(Axis(0), 1),
+        frames.index_axis(Axis(0), 10)
+    );
+
+    let (mut reader, mut bitreader, mut frame_sequence) = open_sample();
+    assert!(frame_sequence
+        .reconstruct_all(&mut reader, &mut bitreader, 0)
+        .is_err());
+}
+
+#[test]
+fn test_intensity_lut() {
+    // A gamma-2 response, with entries for every fourth of the input range
+    let lut = IntensityLut::new(vec![0.0, 15.9375, 63.75, 143.4375, 255.0]).unwrap();
+    assert_eq!(lut.apply(0.0, 255.0), 0.0);
+    assert_eq!(lut.apply(255.0, 255.0), 255.0);
+    assert_eq!(lut.apply(127.5, 255.0), 63.75);
+    // Linearly interpolated between entries, and clamped to the input range
+    assert_eq!(lut.apply(95.625, 255.0), (63.75 + 15.9375) / 2.0);
+    assert_eq!(lut.apply(300.0, 255.0), 255.0);
+    // Entries span the source's bit depth
+    assert_eq!(lut.apply(32767.5, 65535.0), 63.75);
+
+    assert!(IntensityLut::new(vec![1.0]).is_err());
+    assert!(IntensityLut::new(vec![0.0, f32::NAN]).is_err());
+
+    let path = std::env::temp_dir().join(format!("adder_lut_{}.txt", rand::random::<u32>()));
+    fs::write(&path, "0.0, 15.9375\n63.75 143.4375\n255\n").unwrap();
+    assert_eq!(IntensityLut::from_file(&path).unwrap(), lut);
+    fs::write(&path, "0.0 bright").unwrap();
+    assert!(IntensityLut::from_file(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_contrast_mask() {
+    use adder_codec_rs::transcoder::source::video::{ContrastMask, VideoBuilder};
+
+    // A triangle over the top-left corner of a 4x4 plane, and a square over its bottom-right
+    let mask = ContrastMask::from_polygons(
+        4,
+        4,
+        &[
+            vec![(0.0, 0.0), (2.5, 0.0), (0.0, 2.5)],
+            vec![(2.0, 2.0), (4.0, 2.0), (4.0, 4.0), (2.0, 4.0)],
+        ],
+    )
+    .unwrap()
+    .region(1, 2, 4)
+    .region(2, 40, 30);
+    assert_eq!(
+        mask.labels().iter().copied().collect::<Vec<u8>>(),
+        [1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 2, 2, 0, 0, 2, 2]
+    );
+    assert_eq!(mask.thresholds(0, 0), Some((2, 4)));
+    // The baseline is capped at the maximum
+    assert_eq!(mask.thresholds(3, 3), Some((30, 30)));
+    // Unlisted regions use the global thresholds
+    assert_eq!(mask.thresholds(3, 0), None);
+
+    let path = std::env::temp_dir().join(format!("adder_mask_{}.png", rand::random::<u32>()));
+    image::GrayImage::from_fn(4, 4, |x, _| image::Luma([if x < 2 { 255 } else { 0 }]))
+        .save(&path)
+        .unwrap();
+    let from_image = ContrastMask::from_image(&path).unwrap();
+    assert_eq!(from_image.labels()[[3, 1]], 255);
+    assert_eq!(from_image.labels()[[3, 2]], 0);
+    fs::remove_file(&path).unwrap();
+
+    // The mask must match the plane
+    let source = || {
+        RawVideo::<BufWriter<File>>::new(
+            std::io::Cursor::new(vec![0; 16]),
+            RawPixelFormat::Gray,
+            4,
+            4,
+            24.0,
+            false,
+        )
+        .unwrap()
+    };
+    assert!(source().contrast_mask(Some(mask)).is_ok());
+    assert!(source()
+        .contrast_mask(Some(ContrastMask::new(ndarray::Array2::zeros((2, 4)))))
+        .is_err());
+}
+
 #[test]
 fn test_sample_perfect_dt_color() {
     let input_path = "./tests/samples/sample_2_raw_events.adder";
@@ -262,6 +404,18 @@ fn setup_raw_writer_v0(rand_num: u32) -> Encoder<BufWriter<File>> {
             event_size: 0,
             source_camera: Default::default(),
             adu_interval: 1,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: 16,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
         },
         bufwriter,
     );
@@ -288,6 +442,18 @@ fn setup_raw_writer_v1(rand_num: u32) -> Encoder<BufWriter<File>> {
             event_size: 0,
             source_camera: FramedU8,
             adu_interval: 1,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: 16,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
         },
         bufwriter,
     );
@@ -314,6 +480,18 @@ fn setup_raw_writer_v2(rand_num: u32) -> Encoder<BufWriter<File>> {
             event_size: 0,
             source_camera: FramedU8,
             adu_interval: 1,
+            chroma_subsampling: Default::default(),
+            epoch: None,
+            enhancement_layers: 0,
+            wide_coordinates: false,
+            block_size: 16,
+            d_max: D_MAX,
+            channel_layout: Default::default(),
+            context_priors_id: 0,
+            quantization_table: Default::default(),
+            intensity_peak: 1.0,
+            color_space: Default::default(),
+            motion_compensation: false,
         },
         bufwriter,
     );
@@ -815,6 +993,78 @@ fn test_get_empty_frame() {
     fs::remove_file(&path).unwrap();
 }
 
+#[test]
+fn test_frame_fill_stats() {
+    use adder_codec_rs::framer::driver::FramerMode::INSTANTANEOUS;
+    use adder_codec_rs::framer::driver::{FrameFillStats, FrameSequence, Framer};
+    let plane = PlaneSize::new(5, 5, 1).unwrap();
+    let mut frame_sequence: FrameSequence<u8> = FramerBuilder::new(plane, 64)
+        .codec_version(1, TimeMode::DeltaT)
+        .time_parameters(50000, 1000, 1000, Some(50.0))
+        .mode(INSTANTANEOUS)
+        .source(U8, FramedU8)
+        .finish();
+
+    // Every pixel has an event spanning the first 6 frames
+    for x in 0..5 {
+        for y in 0..5 {
+            let mut event = Event {
+                coord: Coord { x, y, c: None },
+                d: 5,
+                t: 5100,
+            };
+            frame_sequence.ingest_event(&mut event, None);
+        }
+    }
+    for _ in 0..6 {
+        frame_sequence.pop_next_frame().unwrap();
+        assert_eq!(
+            frame_sequence.last_frame_stats(),
+            FrameFillStats {
+                events: 25,
+                ..Default::default()
+            }
+        );
+    }
+
+    // Only one pixel has an event spanning the next 2 frames
+    let mut event = Event {
+        coord: Coord {
+            x: 0,
+            y: 0,
+            c: None,
+        },
+        d: 5,
+        t: 2000,
+    };
+    frame_sequence.ingest_event(&mut event, None);
+
+    // The other pixels are carried over when the next frame is flushed...
+    frame_sequence.flush_frame_buffer();
+    frame_sequence.pop_next_frame().unwrap();
+    assert_eq!(
+        frame_sequence.last_frame_stats(),
+        FrameFillStats {
+            events: 1,
+            carried_over: 24,
+            ..Default::default()
+        }
+    );
+
+    // ...or left unfilled if the frame is popped before it's ready
+    frame_sequence.pop_next_frame().unwrap();
+    let stats = frame_sequence.last_frame_stats();
+    assert_eq!(
+        stats,
+        FrameFillStats {
+            events: 1,
+            unfilled: 24,
+            ..Default::default()
+        }
+    );
+    assert_eq!(stats.total(), 25);
+}
+
 #[test]
 fn test_sample_unordered() {
     let input_path = "./tests/samples/sample_3_unordered.adder";
@@ -1068,3 +1318,1597 @@ fn array3_test() {
         .collect();
     assert_eq!(ret, vec![1, 1]);
 }
+
+#[test]
+fn test_sources_are_send() {
+    // Transcoders are moved onto worker threads, so they must be `Send` without any unsafe impls
+    fn assert_send<T: Send>() {}
+    assert_send::<Video<BufWriter<File>>>();
+    assert_send::<Prophesee<BufWriter<File>>>();
+    assert_send::<FramedSequenceSource<BufWriter<File>>>();
+    assert_send::<RawVideo<BufWriter<File>>>();
+}
+
+#[test]
+fn test_framed_sequence() {
+    let dir = std::env::temp_dir().join(format!("adder_sequence_{}", rand::random::<u32>()));
+    fs::create_dir(&dir).unwrap();
+    // Numbered out of lexicographic order, with 16-bit samples beyond the range of 8 bits
+    for (number, value) in [(10, 60000), (2, 40000), (1, 20000)] {
+        let image = image::ImageBuffer::from_pixel(4, 3, image::Luma([value as u16]));
+        image.save(dir.join(format!("frame_{number}.png"))).unwrap();
+    }
+    fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+    let mut source: FramedSequenceSource<BufWriter<File>> =
+        FramedSequenceSource::new(dir.clone(), false, std::time::Duration::from_millis(40))
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap();
+    assert_eq!(source.frame_count(), 3);
+    assert_eq!(
+        source.source_camera(),
+        adder_codec_core::SourceCamera::FramedU16
+    );
+    assert_eq!(source.get_video_ref().get_tps(), 255 * 25);
+
+    let mut inputs = Vec::new();
+    loop {
+        match source.consume() {
+            Ok(_) => inputs.push(source.get_last_input_frame()[[0, 0, 0]]),
+            Err(SourceError::BufferEmpty) => break,
+            Err(e) => panic!("{e}"),
+        }
+    }
+    assert_eq!(inputs, vec![77, 155, 233]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_framed_sequence_hdr() {
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::{PixelMultiMode, SourceCamera};
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    let dir = std::env::temp_dir().join(format!("adder_hdr_{}", rand::random::<u32>()));
+    fs::create_dir(&dir).unwrap();
+    // Scene-linear samples beyond 1.0, as rendered to OpenEXR
+    for (number, value) in [(1, 4.0), (2, 2.0)] {
+        let image = image::Rgb32FImage::from_pixel(4, 3, image::Rgb([value, value, value]));
+        image.save(dir.join(format!("frame_{number}.exr"))).unwrap();
+    }
+
+    let source: FramedSequenceSource<BufWriter<File>> =
+        FramedSequenceSource::new(dir.clone(), false, std::time::Duration::from_millis(40))
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap();
+    assert_eq!(source.source_camera(), SourceCamera::FramedF32);
+    assert_eq!(source.get_video_ref().state.intensity_peak, 4.0);
+    assert!(source.intensity_peak(0.0).is_err());
+
+    let mut source: FramedSequenceSource<BufWriter<File>> =
+        FramedSequenceSource::new(dir.clone(), false, std::time::Duration::from_millis(40))
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap()
+            .intensity_peak(8.0)
+            .unwrap();
+    let plane = source.get_video_ref().state.plane;
+    let output_path = dir.join("hdr.adder");
+    source = *source
+        .write_out(
+            SourceCamera::FramedF32,
+            TimeMode::AbsoluteT,
+            PixelMultiMode::Collapse,
+            None,
+            EncoderType::Raw,
+            EncoderOptions::default(plane),
+            BufWriter::new(File::create(&output_path).unwrap()),
+        )
+        .unwrap();
+    let mut inputs = Vec::new();
+    while source.consume().is_ok() {
+        inputs.push(source.get_last_input_frame()[[0, 0, 0]]);
+    }
+    assert_eq!(inputs, vec![127, 63]);
+    source.get_video_mut().end_write_stream().unwrap();
+
+    // The peak is recorded in the header
+    let mut bitreader =
+        BitReader::endian(BufReader::new(File::open(&output_path).unwrap()), BigEndian);
+    let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+    assert_eq!(reader.meta().source_camera, SourceCamera::FramedF32);
+    assert_eq!(reader.meta().intensity_peak, 8.0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stream_url() {
+    use adder_codec_rs::transcoder::source::framed::{is_stream_url, Framed, LiveStreamOptions};
+
+    assert!(is_stream_url("rtsp://192.168.1.64:554/stream1"));
+    assert!(is_stream_url("https://example.com/live/camera.m3u8"));
+    assert!(!is_stream_url("./tests/samples/bunny_crop4.mp4"));
+    assert!(!is_stream_url("file:///tmp/camera.mp4"));
+
+    // Files are opened with `Framed::new`
+    assert!(Framed::<BufWriter<File>>::new_stream(
+        "./tests/samples/bunny_crop4.mp4",
+        false,
+        1.0,
+        LiveStreamOptions::default(),
+    )
+    .is_err());
+}
+
+#[test]
+fn test_scene_cut() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+
+    let open = || {
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap()
+    };
+    assert!(open().scene_cut_threshold(Some(0.0)).is_err());
+    assert!(open().scene_cut_threshold(Some(1.5)).is_err());
+
+    let transcode = |threshold| {
+        let mut source = open().scene_cut_threshold(threshold).unwrap();
+        let mut num_events = 0;
+        for _ in 0..10 {
+            num_events += source.consume().unwrap().concat().len();
+        }
+        (source.scene_cuts(), num_events)
+    };
+    let (cuts, uncut_events) = transcode(None);
+    assert_eq!(cuts, 0);
+    assert_eq!(transcode(Some(1.0)).0, 0);
+
+    // Any change at all is a cut, and each cut fires every pixel's pending events
+    let (cuts, cut_events) = transcode(Some(f32::MIN_POSITIVE));
+    assert!(cuts > 0);
+    assert!(cut_events > uncut_events);
+}
+
+#[test]
+fn test_adaptive_ref_interval() {
+    use adder_codec_core::codec::clock::RefIntervalChange;
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::{open_file_decoder, PixelMultiMode, SourceCamera};
+    use adder_codec_rs::transcoder::source::framed::{AdaptiveRefInterval, Framed};
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    let open = || {
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap()
+    };
+    let adaptive = |static_threshold, motion_threshold| AdaptiveRefInterval {
+        max_multiple: 4,
+        static_threshold,
+        motion_threshold,
+    };
+    assert!(open()
+        .adaptive_ref_interval(Some(adaptive(0.5, 0.1)))
+        .is_err());
+    assert!(open()
+        .adaptive_ref_interval(Some(adaptive(0.0, 0.1)))
+        .unwrap()
+        .time_lapse(2)
+        .is_err());
+
+    // Treat every scene as static, so the interval grows to its maximum
+    let mut source = open()
+        .adaptive_ref_interval(Some(adaptive(1.0, 1.0)))
+        .unwrap();
+    let plane = source.get_video_ref().state.plane;
+    let output_path =
+        std::env::temp_dir().join(format!("adder_adaptive_{}.adder", rand::random::<u32>()));
+    source = *source
+        .write_out(
+            SourceCamera::FramedU8,
+            TimeMode::AbsoluteT,
+            PixelMultiMode::Collapse,
+            None,
+            EncoderType::Compressed,
+            EncoderOptions::default(plane),
+            BufWriter::new(File::create(&output_path).unwrap()),
+        )
+        .unwrap();
+    let mut multiples = Vec::new();
+    for _ in 0..4 {
+        source.consume().unwrap();
+        multiples.push(source.ref_multiple());
+    }
+    assert_eq!(multiples, vec![1, 2, 4, 4]);
+    source.get_video_mut().end_write_stream().unwrap();
+
+    // The frames were integrated over 1, 1, 2, and 4 reference intervals
+    let (mut decoder, mut bitreader) = open_file_decoder(output_path.to_str().unwrap()).unwrap();
+    let change = |t, ref_interval| RefIntervalChange { t, ref_interval };
+    assert_eq!(
+        decoder.read_ref_interval_changes(&mut bitreader).unwrap(),
+        &[change(255 * 2, 255 * 2), change(255 * 4, 255 * 4)]
+    );
+    assert_eq!(decoder.ref_interval_at(255), 255);
+    assert_eq!(decoder.ref_interval_at(255 * 5), 255 * 4);
+    fs::remove_file(output_path).unwrap();
+}
+
+#[test]
+fn test_estimate_translation() {
+    use adder_codec_rs::transcoder::source::stabilization::{estimate_translation, shift_frame};
+    use ndarray::{s, Array2};
+
+    // A smooth, aperiodic texture
+    let texture = |x: i32, y: i32| {
+        (x as f32 * 0.37).sin() * 40.0
+            + (y as f32 * 0.23).cos() * 40.0
+            + ((x + 2 * y) as f32 * 0.11).sin() * 30.0
+    };
+    let previous = Array2::from_shape_fn((48, 64), |(y, x)| texture(x as i32, y as i32));
+    for (dx, dy) in [(0, 0), (1, 0), (5, -3), (-11, 7)] {
+        let next = Array2::from_shape_fn((48, 64), |(y, x)| texture(x as i32 - dx, y as i32 - dy));
+        assert_eq!(
+            estimate_translation(previous.view(), next.view(), 16),
+            (dx, dy)
+        );
+    }
+
+    // Shifting a frame moves its content, and repeats the edge into the uncovered area
+    let frame = Array3::from_shape_fn((4, 5, 1), |(y, x, _)| (y * 5 + x) as f32);
+    let shifted = shift_frame(&frame, (2, -1));
+    assert_eq!(shifted[[0, 2, 0]], frame[[1, 0, 0]]);
+    assert_eq!(shifted[[2, 4, 0]], frame[[3, 2, 0]]);
+    assert_eq!(shifted.slice(s![.., 0, 0]), shifted.slice(s![.., 2, 0]));
+    assert_eq!(shifted.slice(s![3, .., 0]), shifted.slice(s![2, .., 0]));
+}
+
+#[test]
+fn test_stabilization() {
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::{open_file_decoder, PixelMultiMode};
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::stabilization::Stabilization;
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    let open = || {
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap()
+    };
+    let stabilization = |max_motion, smoothing| Stabilization {
+        max_motion,
+        max_shift: 4,
+        smoothing,
+    };
+    assert!(open().stabilization(Some(stabilization(0, 0.9))).is_err());
+    assert!(open().stabilization(Some(stabilization(8, 1.5))).is_err());
+
+    let transcode = |smoothing| {
+        let mut source = open()
+            .stabilization(Some(stabilization(8, smoothing)))
+            .unwrap();
+        let plane = source.get_video_ref().state.plane;
+        let output_path =
+            std::env::temp_dir().join(format!("adder_stabilized_{}.adder", rand::random::<u32>()));
+        source = *source
+            .write_out(
+                FramedU8,
+                TimeMode::AbsoluteT,
+                PixelMultiMode::Collapse,
+                None,
+                EncoderType::Compressed,
+                EncoderOptions::default(plane),
+                BufWriter::new(File::create(&output_path).unwrap()),
+            )
+            .unwrap();
+        for _ in 0..10 {
+            source.consume().unwrap();
+            let (dx, dy) = source.stabilization_shift();
+            assert!(dx.abs() <= 4 && dy.abs() <= 4);
+        }
+        source.get_video_mut().end_write_stream().unwrap();
+
+        let (mut decoder, mut bitreader) =
+            open_file_decoder(output_path.to_str().unwrap()).unwrap();
+        let transforms = decoder
+            .read_stabilization_transforms(&mut bitreader)
+            .unwrap()
+            .to_vec();
+        for transform in &transforms {
+            assert_eq!(transform.t % 255, 0);
+            assert!(transform.dx.abs() <= 4 && transform.dy.abs() <= 4);
+            assert_eq!(
+                decoder.stabilization_at(transform.t),
+                (transform.dx, transform.dy)
+            );
+        }
+        fs::remove_file(output_path).unwrap();
+        transforms
+    };
+
+    // With no smoothing, the view follows the camera, so no frame is shifted
+    assert!(transcode(0.0).is_empty());
+    transcode(1.0);
+}
+
+#[test]
+fn test_save_restore_state() {
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::{open_file_decoder, PixelMultiMode};
+    use adder_codec_rs::transcoder::source::checkpoint::VideoCheckpoint;
+    use adder_codec_rs::transcoder::source::denoise::TemporalFilter;
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::stabilization::Stabilization;
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+    use std::io::Cursor;
+
+    let open = |scale, filtered: bool| {
+        let source =
+            Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, scale)
+                .unwrap()
+                .auto_time_parameters(255, 255 * 30, None)
+                .unwrap();
+        if !filtered {
+            return source;
+        }
+        source
+            .temporal_filter(Some(TemporalFilter::default()))
+            .unwrap()
+            .stabilization(Some(Stabilization::default()))
+            .unwrap()
+    };
+    let mut source = open(1.0, true);
+    let plane = source.get_video_ref().state.plane;
+    let output_path =
+        std::env::temp_dir().join(format!("adder_resumed_{}.adder", rand::random::<u32>()));
+    source = *source
+        .write_out(
+            FramedU8,
+            TimeMode::AbsoluteT,
+            PixelMultiMode::Collapse,
+            None,
+            EncoderType::Raw,
+            EncoderOptions::default(plane),
+            BufWriter::new(File::create(&output_path).unwrap()),
+        )
+        .unwrap();
+    for _ in 0..3 {
+        source.consume().unwrap();
+    }
+    let mut saved = Vec::new();
+    source.save_state().unwrap().write_to(&mut saved).unwrap();
+    let running_intensities = source.get_video_ref().state.running_intensities.clone();
+    let input_frame = source.get_last_input_frame().clone();
+
+    // Interrupt the transcode later on. What's written after the checkpoint is discarded.
+    source.consume().unwrap();
+    source.get_video_mut().end_write_stream().unwrap();
+
+    let checkpoint = VideoCheckpoint::read_from(Cursor::new(saved)).unwrap();
+    assert_eq!(checkpoint.source_position, 3);
+    assert!(checkpoint.encoder().is_some());
+    assert!(open(0.5, true).restore_state(&checkpoint).is_err());
+
+    // The denoiser and stabilizer carry history, so a source without them can't take it up
+    assert!(open(1.0, false).restore_state(&checkpoint).is_err());
+
+    let mut source = open(1.0, true)
+        .resume_write_out(
+            output_path.to_str().unwrap(),
+            EncoderOptions::default(plane),
+            &checkpoint,
+        )
+        .unwrap();
+    assert_eq!(
+        source.get_video_ref().state.running_intensities,
+        running_intensities
+    );
+    assert_eq!(source.get_last_input_frame(), &input_frame);
+    for _ in 0..3 {
+        source.consume().unwrap();
+    }
+    source.get_video_mut().end_write_stream().unwrap();
+
+    let (mut decoder, mut bitreader) = open_file_decoder(output_path.to_str().unwrap()).unwrap();
+    let mut event_count = 0;
+    while decoder.digest_event(&mut bitreader).is_ok() {
+        event_count += 1;
+    }
+    assert!(event_count > 0);
+    fs::remove_file(output_path).unwrap();
+}
+
+#[test]
+fn test_lens_undistortion() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::undistort::{LensModel, Undistortion};
+
+    let pinhole = LensModel {
+        fx: 40.0,
+        fy: 40.0,
+        cx: 15.5,
+        cy: 11.5,
+        k1: 0.0,
+        k2: 0.0,
+        p1: 0.0,
+        p2: 0.0,
+        k3: 0.0,
+    };
+    let barrel = LensModel {
+        k1: -0.3,
+        k2: 0.1,
+        p1: 0.001,
+        p2: -0.002,
+        ..pinhole
+    };
+
+    // Undistorting a point reverses its distortion
+    for (x, y) in [(0.0, 0.0), (31.0, 23.0), (15.5, 11.5), (4.0, 20.0)] {
+        let (xd, yd) = barrel.distort(x, y);
+        let (xu, yu) = barrel.undistort(xd, yd);
+        assert!((xu - x).abs() < 1e-3 && (yu - y).abs() < 1e-3);
+    }
+
+    // A lens without distortion leaves the frame and the event coordinates as they are
+    let frame = Array3::from_shape_fn((24, 32, 1), |(y, x, _)| (y * 32 + x) as f32);
+    let identity = Undistortion::new(pinhole, 32, 24).unwrap();
+    assert!(identity
+        .undistort_frame(&frame)
+        .iter()
+        .zip(frame.iter())
+        .all(|(undistorted, original)| (undistorted - original).abs() < 1e-3));
+    assert_eq!(identity.undistort_coord(7, 9), Some((7, 9)));
+    assert_eq!(identity.undistort_coord(32, 9), None);
+
+    // Barrel distortion squeezes the edges of the image towards the center, so undistorting
+    // moves the corner events out of the frame
+    let undistortion = Undistortion::new(barrel, 32, 24).unwrap();
+    assert_eq!(undistortion.undistort_coord(16, 12), Some((16, 12)));
+    assert_eq!(undistortion.undistort_coord(0, 0), None);
+    assert!(Undistortion::new(LensModel { fx: 0.0, ..barrel }, 32, 24).is_err());
+
+    let lens_path = std::env::temp_dir().join(format!("adder_lens_{}.txt", rand::random::<u32>()));
+    fs::write(&lens_path, "40 40 15.5 11.5\n-0.3, 0.1, 0.001, -0.002, 0").unwrap();
+    assert_eq!(LensModel::from_file(&lens_path).unwrap(), barrel);
+    fs::write(&lens_path, "40 40 15.5 11.5").unwrap();
+    assert!(LensModel::from_file(&lens_path).is_err());
+    fs::remove_file(lens_path).unwrap();
+
+    // The lens is rescaled along with the frames
+    let mut source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 0.5)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap()
+            .lens_undistortion(Some(barrel))
+            .unwrap();
+    let scaled = source
+        .get_video_ref()
+        .state
+        .undistortion
+        .as_ref()
+        .unwrap()
+        .model()
+        .fx;
+    assert_eq!(scaled, 20.0);
+    for _ in 0..3 {
+        source.consume().unwrap();
+    }
+}
+
+#[test]
+fn test_crop() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::video::{Crop, VideoBuilder};
+
+    let crop: Crop = "8x6+4+2".parse().unwrap();
+    assert_eq!(
+        crop,
+        Crop {
+            x: 4,
+            y: 2,
+            width: 8,
+            height: 6
+        }
+    );
+    assert!("8x6".parse::<Crop>().is_err());
+    assert!("8x6+4".parse::<Crop>().is_err());
+    assert!(crop.fits(12, 8));
+    assert!(!crop.fits(11, 8));
+
+    let frame = Array3::from_shape_fn((8, 12, 1), |(y, x, _)| y * 12 + x);
+    let window = crop.apply(&frame);
+    assert_eq!(window.dim(), (6, 8, 1));
+    assert_eq!(window[[0, 0, 0]], 2 * 12 + 4);
+
+    // The crop is given in pixels of the source video, and rescaled along with the frames
+    let source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 0.5)
+            .unwrap();
+    let input_plane = source.get_video_ref().state.plane;
+    let mut source = source
+        .crop(Some(crop))
+        .unwrap()
+        .auto_time_parameters(255, 255 * 30, None)
+        .unwrap();
+    let video = source.get_video_ref();
+    assert_eq!(video.state.input_plane, input_plane);
+    assert_eq!((video.state.plane.w(), video.state.plane.h()), (4, 3));
+
+    for _ in 0..3 {
+        let events = source.consume().unwrap();
+        assert!(events
+            .iter()
+            .flatten()
+            .all(|event| event.coord.x < 4 && event.coord.y < 3));
+    }
+    assert_eq!(source.get_input().unwrap().dim(), (3, 4, 1));
+    assert_eq!(
+        source.get_video_ref().state.running_intensities.dim(),
+        (3, 4, 1)
+    );
+
+    // The crop can only be set once, and must fit in the input
+    assert!(source.crop(Some(crop)).is_err());
+    let source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 0.5)
+            .unwrap();
+    let too_wide = Crop {
+        width: input_plane.w() * 2 + 2,
+        ..crop
+    };
+    assert!(source.crop(Some(too_wide)).is_err());
+}
+
+#[test]
+fn test_orientation() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::video::{Crop, Orientation, Rotation, VideoBuilder};
+
+    assert_eq!(Rotation::from_degrees(450).unwrap(), Rotation::Cw90);
+    assert!(Rotation::from_degrees(45).is_err());
+
+    // A 2x3 frame:
+    // 0 1 2
+    // 3 4 5
+    let frame = Array3::from_shape_fn((2, 3, 1), |(y, x, _)| y * 3 + x);
+    let turn = |orientation: Orientation| orientation.apply(&frame).into_raw_vec();
+    let rotation = |rotation| Orientation {
+        rotation,
+        ..Default::default()
+    };
+    assert_eq!(turn(rotation(Rotation::None)), vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(turn(rotation(Rotation::Cw90)), vec![3, 0, 4, 1, 5, 2]);
+    assert_eq!(turn(rotation(Rotation::Cw180)), vec![5, 4, 3, 2, 1, 0]);
+    assert_eq!(turn(rotation(Rotation::Cw270)), vec![2, 5, 1, 4, 0, 3]);
+    let flip_horizontal = Orientation {
+        flip_horizontal: true,
+        ..Default::default()
+    };
+    assert_eq!(turn(flip_horizontal), vec![2, 1, 0, 5, 4, 3]);
+    let flip_then_turn = Orientation {
+        flip_vertical: true,
+        rotation: Rotation::Cw90,
+        ..Default::default()
+    };
+    assert_eq!(turn(flip_then_turn), vec![0, 3, 1, 4, 2, 5]);
+    assert_eq!(flip_then_turn.apply(&frame).dim(), (3, 2, 1));
+
+    // A quarter turn swaps the plane's width and height
+    let crop = Crop {
+        x: 0,
+        y: 0,
+        width: 6,
+        height: 4,
+    };
+    let mut source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .crop(Some(crop))
+            .unwrap()
+            .orientation(Some(rotation(Rotation::Cw90)))
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap();
+    let plane = source.get_video_ref().state.plane;
+    assert_eq!((plane.w(), plane.h()), (4, 6));
+    for _ in 0..3 {
+        let events = source.consume().unwrap();
+        assert!(events
+            .iter()
+            .flatten()
+            .all(|event| event.coord.x < 4 && event.coord.y < 6));
+    }
+    assert_eq!(source.get_input().unwrap().dim(), (6, 4, 1));
+
+    // The crop comes before the orientation, which can only be set once
+    let source = source.orientation(Some(flip_horizontal));
+    assert!(source.is_err());
+    let source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .orientation(Some(flip_horizontal))
+            .unwrap();
+    assert!(source.crop(Some(crop)).is_err());
+}
+
+#[test]
+fn test_raw_video() {
+    assert_eq!(
+        "RGB24".parse::<RawPixelFormat>().unwrap(),
+        RawPixelFormat::Rgb24
+    );
+    assert!("yuv420p".parse::<RawPixelFormat>().is_err());
+
+    // Two 3x2 rgb24 frames of pure red, then pure blue, and a truncated third frame
+    let mut bytes = Vec::new();
+    for pixel in [[255, 0, 0], [0, 0, 255]] {
+        bytes.extend(pixel.repeat(6));
+    }
+    bytes.extend([0; 5]);
+
+    let mut source: RawVideo<BufWriter<File>> = RawVideo::new(
+        std::io::Cursor::new(bytes.clone()),
+        RawPixelFormat::Rgb24,
+        3,
+        2,
+        24.0,
+        true,
+    )
+    .unwrap()
+    .auto_time_parameters(255, 255 * 30, None)
+    .unwrap();
+    assert_eq!(source.get_video_ref().state.plane.c(), 3);
+    assert_eq!(source.get_video_ref().get_tps(), 255 * 24);
+
+    // Channels are in BGR order, like the other framed sources
+    source.consume().unwrap();
+    assert_eq!(source.get_last_input_frame()[[1, 2, 2]], 255);
+    source.consume().unwrap();
+    assert_eq!(source.get_last_input_frame()[[1, 2, 0]], 255);
+    assert!(matches!(source.consume(), Err(SourceError::BufferEmpty)));
+
+    // Transcoded as luma
+    let mut source: RawVideo<BufWriter<File>> = RawVideo::new(
+        std::io::Cursor::new(bytes),
+        RawPixelFormat::Rgb24,
+        3,
+        2,
+        24.0,
+        false,
+    )
+    .unwrap();
+    assert_eq!(source.get_video_ref().state.plane.c(), 1);
+    source.consume().unwrap();
+    assert_eq!(source.get_last_input_frame()[[0, 0, 0]], 76);
+}
+
+#[test]
+fn test_raw_video_bayer() {
+    assert_eq!(
+        "bayer_gbrg16le".parse::<RawPixelFormat>().unwrap(),
+        RawPixelFormat::Bayer16Le(BayerPattern::Gbrg)
+    );
+
+    // A 4x2 RGGB mosaic, with red of 200 and 100 and uniform green and blue
+    let bytes = vec![200, 100, 100, 100, 100, 50, 100, 50];
+    let bayer = |color_input| {
+        RawVideo::<BufWriter<File>>::new(
+            std::io::Cursor::new(bytes.clone()),
+            RawPixelFormat::Bayer8(BayerPattern::Rggb),
+            4,
+            2,
+            24.0,
+            color_input,
+        )
+        .unwrap()
+    };
+
+    // Demosaiced, interpolating red between the two red pixels
+    let mut source = bayer(true);
+    assert_eq!(source.get_video_ref().state.plane.c(), 3);
+    source.consume().unwrap();
+    let frame = source.get_last_input_frame();
+    assert_eq!(frame[[0, 0, 2]], 200);
+    assert_eq!(frame[[0, 1, 2]], 150);
+    assert_eq!(frame[[0, 0, 1]], 100);
+    assert_eq!(frame[[0, 0, 0]], 50);
+
+    // Each plane in its own quarter of the frame
+    assert!(bayer(true).bayer_planes(true).is_err());
+    let mut source = bayer(false).bayer_planes(true).unwrap();
+    assert_eq!(source.get_video_ref().state.plane.c(), 1);
+    source.consume().unwrap();
+    let frame = source.get_last_input_frame();
+    assert_eq!(
+        frame.iter().copied().collect::<Vec<u8>>(),
+        [200, 100, 100, 100, 100, 100, 50, 50]
+    );
+
+    // The 2x2 pattern must tile the frame
+    assert!(RawVideo::<BufWriter<File>>::new(
+        std::io::Cursor::new(bytes),
+        RawPixelFormat::Bayer8(BayerPattern::Rggb),
+        3,
+        2,
+        24.0,
+        false,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_raw_video_ycbcr() {
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::{ChromaSubsampling, ColorSpace, PixelMultiMode, SourceCamera};
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    assert_eq!(
+        "yuv420p16le".parse::<RawPixelFormat>().unwrap(),
+        RawPixelFormat::Yuv420p16Le
+    );
+    assert_eq!(RawPixelFormat::Yuv420p.frame_len(4, 2), 12);
+
+    // Two 4x2 4:2:0 frames: full luma with neutral chroma (white), then a dimmer left half
+    let mut bytes = vec![255; 8];
+    bytes.extend([128; 4]);
+    bytes.extend([100, 100, 255, 255, 100, 100, 255, 255]);
+    bytes.extend([128; 4]);
+    let yuv = |color_input| {
+        RawVideo::<BufWriter<File>>::new(
+            std::io::Cursor::new(bytes.clone()),
+            RawPixelFormat::Yuv420p,
+            4,
+            2,
+            24.0,
+            color_input,
+        )
+        .unwrap()
+    };
+
+    // Only the luma plane
+    let mut source = yuv(false);
+    assert_eq!(source.get_video_ref().state.plane.c(), 1);
+    assert_eq!(source.get_video_ref().state.color_space, ColorSpace::Bgr);
+    source.consume().unwrap();
+    source.consume().unwrap();
+    assert_eq!(source.get_last_input_frame()[[1, 0, 0]], 100);
+
+    // The planes are transcoded directly, and the input frame is displayed as BGR
+    let mut source = yuv(true)
+        .chroma_subsampling(ChromaSubsampling::Half)
+        .unwrap();
+    assert_eq!(source.get_video_ref().state.plane.c(), 3);
+    assert_eq!(source.get_video_ref().state.color_space, ColorSpace::YCbCr);
+    let plane = source.get_video_ref().state.plane;
+    let output_path =
+        std::env::temp_dir().join(format!("adder_ycbcr_{}.adder", rand::random::<u32>()));
+    source = *source
+        .write_out(
+            SourceCamera::FramedU8,
+            TimeMode::AbsoluteT,
+            PixelMultiMode::Collapse,
+            None,
+            EncoderType::Raw,
+            EncoderOptions::default(plane),
+            BufWriter::new(File::create(&output_path).unwrap()),
+        )
+        .unwrap();
+    source.consume().unwrap();
+    assert!(source
+        .get_last_input_frame()
+        .iter()
+        .all(|&value| value >= 254));
+    source.consume().unwrap();
+    assert_eq!(source.get_last_input_frame()[[0, 0, 0]], 100);
+    source.get_video_mut().end_write_stream().unwrap();
+
+    // The color space is recorded in the header
+    let mut bitreader =
+        BitReader::endian(BufReader::new(File::open(&output_path).unwrap()), BigEndian);
+    let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+    assert_eq!(reader.meta().color_space, ColorSpace::YCbCr);
+    fs::remove_file(&output_path).unwrap();
+
+    // The chroma planes must tile the frame
+    assert!(RawVideo::<BufWriter<File>>::new(
+        std::io::Cursor::new(bytes),
+        RawPixelFormat::Yuv420p,
+        3,
+        2,
+        24.0,
+        false,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_multi_source() {
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::{PixelMultiMode, SourceCamera};
+    use adder_codec_rs::transcoder::source::multi::{
+        MultiOutput, MultiSource, SyncInfo, SyncSource,
+    };
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    // A stereo pair of 2x2 grayscale cameras of different kinds: a raw video of three frames,
+    // and an image sequence of two
+    let camera = |frames: Vec<u8>, fps| {
+        RawVideo::<BufWriter<File>>::new(
+            std::io::Cursor::new(frames),
+            RawPixelFormat::Gray,
+            2,
+            2,
+            fps,
+            false,
+        )
+        .unwrap()
+        .auto_time_parameters(255, 255 * 30, None)
+        .unwrap()
+    };
+    let left = camera([[50; 4], [100; 4], [150; 4]].concat(), 25.0);
+    let dir = std::env::temp_dir().join(format!("adder_multi_{}", rand::random::<u32>()));
+    fs::create_dir(&dir).unwrap();
+    for (number, value) in [(1, 200), (2, 100)] {
+        let image = image::ImageBuffer::from_pixel(2, 2, image::Luma([value as u8]));
+        image.save(dir.join(format!("frame_{number}.png"))).unwrap();
+    }
+    let right: FramedSequenceSource<BufWriter<File>> =
+        FramedSequenceSource::new(dir.clone(), false, std::time::Duration::from_millis(40))
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap();
+
+    // The cameras must share a clock
+    let sources: Vec<Box<dyn SyncSource<_>>> = vec![
+        Box::new(camera(vec![0; 12], 25.0)),
+        Box::new(camera(vec![0; 12], 30.0)),
+    ];
+    assert!(MultiSource::new(sources).is_err());
+
+    let sources: Vec<Box<dyn SyncSource<_>>> = vec![Box::new(left), Box::new(right)];
+    let group = MultiSource::new(sources).unwrap();
+    let paths: Vec<_> = (0..2)
+        .map(|index| {
+            std::env::temp_dir().join(format!(
+                "adder_multi_{index}_{}.adder",
+                rand::random::<u32>()
+            ))
+        })
+        .collect();
+    let outputs = paths
+        .iter()
+        .map(|path| MultiOutput {
+            source_camera: SourceCamera::FramedU8,
+            encoder_type: EncoderType::Raw,
+            encoder_options: EncoderOptions::default(PlaneSize::new(2, 2, 1).unwrap()),
+            write: BufWriter::new(File::create(path).unwrap()),
+        })
+        .collect();
+    let mut group = group
+        .write_out(TimeMode::AbsoluteT, PixelMultiMode::Collapse, None, outputs)
+        .unwrap();
+    let mut intervals = 0;
+    let error = loop {
+        match group.consume() {
+            Ok(events) => assert_eq!(events.len(), 2),
+            Err(e) => break e,
+        }
+        intervals += 1;
+    };
+    assert_eq!(intervals, 2);
+
+    // The image sequence ended, but the raw video still consumed its last frame
+    let SourceError::GroupError(errors) = error else {
+        panic!("{error}");
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], (1, SourceError::BufferEmpty)));
+    assert_eq!(group.sources()[0].get_input().unwrap()[[0, 0, 0]], 150);
+    group.end_write_stream().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The streams share an epoch and a group
+    let mut headers = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let mut bitreader = BitReader::endian(BufReader::new(File::open(path).unwrap()), BigEndian);
+        let reader = Decoder::new_raw(RawInput::new(), &mut bitreader).unwrap();
+        let sync_info = SyncInfo::from_user_metadata(reader.user_metadata()).unwrap();
+        assert_eq!((sync_info.index, sync_info.count), (index, 2));
+        headers.push((reader.epoch(), sync_info.group));
+        fs::remove_file(path).unwrap();
+    }
+    assert!(headers[0].0.is_some());
+    assert_eq!(headers[0], headers[1]);
+}
+
+#[test]
+fn test_aedat_legacy() {
+    // AEDAT 2.0 from a DVS128: (x, y, off) at t = 1000, then (x, y, on) at t = 1250
+    let mut bytes = b"#!AER-DAT2.0\r\n# AEChip: ch.unizh.ini.jaer.chip.retina.DVS128\r\n".to_vec();
+    for (address, t) in [
+        ((9_u32 << 8) | (5 << 1) | 1, 1000_u32),
+        ((9 << 8) | (5 << 1), 1250),
+    ] {
+        bytes.extend(address.to_be_bytes());
+        bytes.extend(t.to_be_bytes());
+    }
+    let mut reader = AedatReader::new(std::io::Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.version(), AedatVersion::V2);
+    assert_eq!(reader.chip(), Some(AedatChip::Dvs128));
+    let events: Vec<_> = (0..2)
+        .map(|_| format!("{:?}", reader.next_event().unwrap()))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "DvsEvent { t: 0, x: 5, y: 9, p: 0 }",
+            "DvsEvent { t: 250, x: 5, y: 9, p: 1 }"
+        ]
+    );
+    assert!(reader.next_event().is_err());
+
+    // AEDAT 3.1 from a DAVIS240: a frame packet to skip, then a polarity packet with an invalid
+    // event
+    let mut bytes = b"#!AER-DAT3.1\r\n#Source 1: DAVIS240C\r\n#!END-HEADER\r\n".to_vec();
+    let packet_header = |event_type: i16, size: i32, capacity: i32, overflow: i32| {
+        let mut header = event_type.to_le_bytes().to_vec();
+        header.extend(1_i16.to_le_bytes());
+        for field in [size, 4, overflow, capacity, capacity, capacity] {
+            header.extend(field.to_le_bytes());
+        }
+        header
+    };
+    bytes.extend(packet_header(2, 4, 2, 0));
+    bytes.extend([0; 8]);
+    bytes.extend(packet_header(1, 8, 3, 1));
+    for (x, y, p, valid, ts) in [
+        (7_u32, 3_u32, 1, 1, 10_i32),
+        (1, 1, 1, 0, 20),
+        (8, 4, 0, 1, 30),
+    ] {
+        bytes.extend(((x << 17) | (y << 2) | (p << 1) | valid).to_le_bytes());
+        bytes.extend(ts.to_le_bytes());
+    }
+    let mut reader = AedatReader::new(std::io::Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.version(), AedatVersion::V3);
+    assert_eq!(reader.chip(), Some(AedatChip::Davis240));
+    let events: Vec<_> = (0..2)
+        .map(|_| format!("{:?}", reader.next_event().unwrap()))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "DvsEvent { t: 0, x: 7, y: 3, p: 1 }",
+            "DvsEvent { t: 20, x: 8, y: 4, p: 0 }"
+        ]
+    );
+    assert!(reader.next_event().is_err());
+
+    // A packet header claiming more events than could be in memory is rejected, not allocated
+    let mut bytes = b"#!AER-DAT3.1\r\n#!END-HEADER\r\n".to_vec();
+    bytes.extend(packet_header(1, i32::MAX, i32::MAX, 0));
+    let mut reader = AedatReader::new(std::io::Cursor::new(bytes)).unwrap();
+    assert_eq!(
+        reader.next_event().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+
+    assert!(AedatReader::new(std::io::Cursor::new(b"% Prophesee".to_vec())).is_err());
+}
+
+/// Build a FlatBuffer with the given file identifier (or none), whose root table has the given
+/// inline fields (empty if absent), where field `vector.0` is the offset to a vector of
+/// `vector.1` elements with the bytes `vector.2`
+fn flatbuffer(identifier: &[u8], fields: &[&[u8]], vector: (usize, u32, &[u8])) -> Vec<u8> {
+    let vtable = 4 + identifier.len();
+    let vtable_size = 4 + 2 * fields.len();
+    let table = vtable + (vtable_size + 3) / 4 * 4;
+    let (mut inline, mut offsets, mut vector_field) = (Vec::new(), Vec::new(), 0);
+    for (index, field) in fields.iter().enumerate() {
+        if index == vector.0 {
+            offsets.push(4 + inline.len() as u16);
+            vector_field = table + 4 + inline.len();
+            inline.extend([0; 4]);
+        } else if field.is_empty() {
+            offsets.push(0);
+        } else {
+            offsets.push(4 + inline.len() as u16);
+            inline.extend(*field);
+        }
+    }
+
+    let mut buffer = (table as u32).to_le_bytes().to_vec();
+    buffer.extend(identifier);
+    buffer.extend((vtable_size as u16).to_le_bytes());
+    buffer.extend((4 + inline.len() as u16).to_le_bytes());
+    for offset in offsets {
+        buffer.extend(offset.to_le_bytes());
+    }
+    buffer.resize(table, 0);
+    buffer.extend(((table - vtable) as i32).to_le_bytes());
+    buffer.extend(inline);
+    let vector_offset = (buffer.len() - vector_field) as u32;
+    buffer[vector_field..vector_field + 4].copy_from_slice(&vector_offset.to_le_bytes());
+    buffer.extend(vector.1.to_le_bytes());
+    buffer.extend(vector.2);
+    buffer
+}
+
+#[test]
+fn test_aedat4() {
+    // A DAVIS346 recording with a frame packet to skip, an event packet of another stream (with
+    // a larger sensor) to skip, then an event packet with an event outside the sensor
+    let info = br#"<dv version="2.0"><node name="outInfo"><node name="1"><attr key="typeIdentifier" type="string">FRME</attr><node name="info"><attr key="sizeX" type="int">640</attr><attr key="sizeY" type="int">480</attr></node></node><node name="0"><attr key="typeIdentifier" type="string">EVTS</attr><node name="info"><attr key="sizeX" type="int">346</attr><attr key="sizeY" type="int">260</attr><attr key="source" type="string">DAVIS346_00000499</attr></node></node></node></dv>"#;
+    let mut events = Vec::new();
+    for (t, x, y, on) in [
+        (1_700_000_000_000_i64, 7_i16, 3_i16, true),
+        (1_700_000_000_010, 400, 3, true),
+        (1_700_000_000_020, 8, 259, false),
+    ] {
+        events.extend(t.to_le_bytes());
+        events.extend(x.to_le_bytes());
+        events.extend(y.to_le_bytes());
+        events.extend([u8::from(on), 0, 0, 0]);
+    }
+    let mut other_events = 1_700_000_000_005_i64.to_le_bytes().to_vec();
+    other_events.extend([1, 0, 1, 0, 1, 0, 0, 0]);
+    let packets = [
+        (1_i32, flatbuffer(b"FRME", &[&[]], (0, 4, &[0; 4]))),
+        (2, flatbuffer(b"EVTS", &[&[]], (0, 1, &other_events))),
+        (0, flatbuffer(b"EVTS", &[&[]], (0, 3, &events))),
+    ];
+
+    let file = |compression: i32, compress: &dyn Fn(Vec<u8>) -> Vec<u8>| {
+        let mut bytes = b"#!AER-DAT4.0\r\n".to_vec();
+        let header = flatbuffer(
+            &[],
+            &[&compression.to_le_bytes(), &[], &[]],
+            (2, info.len() as u32, info),
+        );
+        bytes.extend((header.len() as u32).to_le_bytes());
+        bytes.extend(header);
+        for (stream, packet) in &packets {
+            let mut data = (packet.len() as u32).to_le_bytes().to_vec();
+            data.extend(packet);
+            let data = compress(data);
+            bytes.extend(stream.to_le_bytes());
+            bytes.extend((data.len() as i32).to_le_bytes());
+            bytes.extend(data);
+        }
+        bytes
+    };
+    #[allow(unused_mut)]
+    let mut files = vec![file(0, &|data| data)];
+    #[cfg(feature = "aedat-compression")]
+    files.push(file(1, &|data| {
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap()
+    }));
+
+    for bytes in files {
+        let mut reader = AedatReader::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.version(), AedatVersion::V4);
+        assert_eq!(reader.chip(), Some(AedatChip::Davis346));
+        assert_eq!(reader.size(), Some((346, 260)));
+        let events: Vec<_> = (0..2)
+            .map(|_| format!("{:?}", reader.next_event().unwrap()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "DvsEvent { t: 0, x: 7, y: 3, p: 1 }",
+                "DvsEvent { t: 20, x: 8, y: 259, p: 0 }"
+            ]
+        );
+        assert!(reader.next_event().is_err());
+    }
+
+    // A corrupt packet header can't make the reader allocate without bound
+    let mut bytes = file(0, &|data| data);
+    let first_packet = bytes.len() - packets.iter().map(|(_, p)| p.len() + 12).sum::<usize>();
+    bytes[first_packet + 4..first_packet + 8].copy_from_slice(&i32::MAX.to_le_bytes());
+    let mut reader = AedatReader::new(std::io::Cursor::new(bytes)).unwrap();
+    let error = reader.next_event().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[cfg(feature = "rosbag")]
+#[test]
+fn test_rosbag() {
+    use adder_codec_rs::transcoder::source::rosbag::RosbagReader;
+    use adder_codec_rs::transcoder::source::video::Source;
+
+    // Little-endian CDR, padding each field to its own alignment
+    fn field(data: &mut Vec<u8>, bytes: &[u8]) {
+        while (data.len() - 4) % bytes.len().min(4) != 0 {
+            data.push(0);
+        }
+        data.extend(bytes);
+    }
+    fn message(fields: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut data = vec![0, 1, 0, 0];
+        // Header: stamp and frame_id
+        field(&mut data, &1_u32.to_le_bytes());
+        field(&mut data, &0_u32.to_le_bytes());
+        field(&mut data, &4_u32.to_le_bytes());
+        data.extend(b"cam\0");
+        fields(&mut data);
+        data
+    }
+    let event_array = |events: &[(u16, u16, u32, u8)]| {
+        message(|data| {
+            field(data, &3_u32.to_le_bytes());
+            field(data, &4_u32.to_le_bytes());
+            field(data, &(events.len() as u32).to_le_bytes());
+            for &(x, y, nanosec, p) in events {
+                field(data, &x.to_le_bytes());
+                field(data, &y.to_le_bytes());
+                field(data, &1_u32.to_le_bytes());
+                field(data, &nanosec.to_le_bytes());
+                field(data, &[p]);
+            }
+        })
+    };
+    let image = message(|data| {
+        field(data, &3_u32.to_le_bytes());
+        field(data, &4_u32.to_le_bytes());
+        field(data, &7_u32.to_le_bytes());
+        data.extend(b"mono8\0");
+        field(data, &[0]);
+        field(data, &4_u32.to_le_bytes());
+        field(data, &12_u32.to_le_bytes());
+        data.extend((0..12).map(|i| i * 20));
+    });
+
+    let path = "./tests/samples/TEST_rosbag.db3";
+    let _ = fs::remove_file(path);
+    {
+        let connection = rusqlite::Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE topics(id INTEGER PRIMARY KEY, name TEXT NOT NULL, \
+                 type TEXT NOT NULL, serialization_format TEXT NOT NULL, \
+                 offered_qos_profiles TEXT NOT NULL);
+                 CREATE TABLE messages(id INTEGER PRIMARY KEY, topic_id INTEGER NOT NULL, \
+                 timestamp INTEGER NOT NULL, data BLOB NOT NULL);
+                 INSERT INTO topics VALUES (1, '/image', 'sensor_msgs/msg/Image', 'cdr', '');
+                 INSERT INTO topics VALUES (2, '/events', 'dvs_msgs/msg/EventArray', 'cdr', '');",
+            )
+            .unwrap();
+        for (topic_id, data) in [
+            (1, image),
+            (
+                2,
+                event_array(&[(1, 2, 0, 1), (4, 0, 100, 1), (3, 0, 500_000, 0)]),
+            ),
+            (2, event_array(&[(0, 0, 1_000_000, 1)])),
+        ] {
+            connection
+                .execute(
+                    "INSERT INTO messages(topic_id, timestamp, data) VALUES (?1, 0, ?2)",
+                    (topic_id, data),
+                )
+                .unwrap();
+        }
+    }
+
+    let mut reader = RosbagReader::open(Path::new(path), None).unwrap();
+    assert_eq!(reader.size(), (4, 3));
+    let events: Vec<_> = (0..3)
+        .map(|_| format!("{:?}", reader.next_event().unwrap()))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "DvsEvent { t: 0, x: 1, y: 2, p: 1 }",
+            "DvsEvent { t: 500, x: 3, y: 0, p: 0 }",
+            "DvsEvent { t: 1000, x: 0, y: 0, p: 1 }"
+        ]
+    );
+    assert!(reader.next_event().is_err());
+
+    let source: Prophesee<BufWriter<File>> =
+        Prophesee::from_rosbag(1, Path::new(path), Some("/events"), Some("/image")).unwrap();
+    let intensities = &source.get_video_ref().state.running_intensities;
+    assert_eq!(intensities.dim(), (3, 4, 1));
+    assert_eq!(intensities[[2, 1, 0]], 180);
+    assert!(RosbagReader::open(Path::new(path), Some("/image")).is_err());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "metavision")]
+#[test]
+fn test_metavision_bias_file() {
+    use adder_codec_rs::transcoder::source::metavision::read_bias_file;
+
+    let path = "./tests/samples/TEST_biases.bias";
+    fs::write(
+        path,
+        "% IMX636 biases\n299 % bias_diff\n 25 % bias_diff_off \n\n0 % bias_hpf\n",
+    )
+    .unwrap();
+    let biases = read_bias_file(Path::new(path)).unwrap();
+    assert_eq!(
+        biases,
+        [
+            ("bias_diff".to_string(), 299),
+            ("bias_diff_off".to_string(), 25),
+            ("bias_hpf".to_string(), 0)
+        ]
+    );
+
+    fs::write(path, "bias_diff = 299\n").unwrap();
+    assert!(read_bias_file(Path::new(path)).is_err());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "gpu")]
+fn test_gpu_integration() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    // Without a GPU, the transcode falls back to the CPU, so the events match trivially
+    let transcode = |gpu| {
+        let mut source =
+            Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), true, 1.0)
+                .unwrap()
+                .auto_time_parameters(255, 255 * 30, None)
+                .unwrap()
+                .gpu_integration(gpu)
+                .unwrap();
+        let mut events = Vec::new();
+        for _ in 0..30 {
+            events.extend(source.consume().unwrap().into_iter().flatten());
+        }
+        (
+            events,
+            source.get_video_ref().state.running_intensities.clone(),
+        )
+    };
+    let (cpu_events, cpu_intensities) = transcode(false);
+    let (gpu_events, gpu_intensities) = transcode(true);
+    assert!(!cpu_events.is_empty());
+    assert_eq!(cpu_events, gpu_events);
+    assert_eq!(cpu_intensities, gpu_intensities);
+}
+
+#[test]
+fn test_noise_model() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::noise::NoiseModel;
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    assert!(NoiseModel::new(-1.0, 0.0, 2.0).is_err());
+    assert!(NoiseModel::new(1.0, f32::NAN, 2.0).is_err());
+
+    // The noise grows with the intensity, and so does its floor
+    let model = NoiseModel::new(2.0, 0.5, 3.0).unwrap();
+    assert_eq!(model.std_dev(0.0), 2.0);
+    assert_eq!(model.std_dev(24.0), 4.0);
+    let floor = model.noise_floor(1.0);
+    assert_eq!(floor[0], 6);
+    assert_eq!(floor[24], 12);
+    assert!(floor.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    // Fluctuations within the noise don't fire events
+    let count_events = |model| {
+        let mut source =
+            Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+                .unwrap()
+                .auto_time_parameters(255, 255 * 30, None)
+                .unwrap()
+                .noise_model(model);
+        (0..20)
+            .map(|_| {
+                source
+                    .consume()
+                    .unwrap()
+                    .iter()
+                    .map(Vec::len)
+                    .sum::<usize>()
+            })
+            .sum::<usize>()
+    };
+    let noisy = NoiseModel::new(4.0, 1.0, 3.0).unwrap();
+    assert!(count_events(Some(noisy)) < count_events(None));
+}
+
+#[test]
+fn test_temporal_filter() {
+    use adder_codec_rs::transcoder::source::denoise::TemporalFilter;
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::video::VideoBuilder;
+
+    let new_source = |filter| {
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap()
+            .temporal_filter(filter)
+    };
+    for (strength, edge) in [(1.0, 8.0), (-0.1, 8.0), (0.5, 0.0), (0.5, f32::NAN)] {
+        assert!(new_source(Some(TemporalFilter { strength, edge })).is_err());
+    }
+
+    // Smoothing out the small fluctuations between frames fires fewer events
+    let count_events = |filter| {
+        let mut source = new_source(filter).unwrap();
+        (0..20)
+            .map(|_| {
+                source
+                    .consume()
+                    .unwrap()
+                    .iter()
+                    .map(Vec::len)
+                    .sum::<usize>()
+            })
+            .sum::<usize>()
+    };
+    let filter = TemporalFilter {
+        strength: 0.9,
+        edge: 16.0,
+    };
+    assert!(count_events(Some(filter)) < count_events(None));
+}
+
+#[test]
+fn test_quality_target() {
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::quality::{psnr_from_mse, QualityTarget};
+    use adder_codec_rs::transcoder::source::video::{FramedViewMode, VideoBuilder};
+
+    assert!(QualityTarget::new(-1.0, 0.5).is_err());
+    assert!(QualityTarget::new(40.0, f64::NAN).is_err());
+    assert!(QualityTarget::from_mse(0.0, 0.5).is_err());
+    assert!(psnr_from_mse(0.0).is_finite());
+    let target = QualityTarget::from_mse(1.0, 0.5).unwrap();
+    assert!((target.psnr - 20.0 * 255.0_f64.log10()).abs() < 1e-9);
+
+    // The CRF moves toward the level which holds the target, measured the same way whatever
+    // the view mode
+    let final_crf = |psnr, view_mode| {
+        let mut source =
+            Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+                .unwrap()
+                .crf(5)
+                .auto_time_parameters(255, 255 * 30, None)
+                .unwrap()
+                .quality_target(Some(QualityTarget::new(psnr, 0.5).unwrap()));
+        source.get_video_mut().instantaneous_view_mode = view_mode;
+        for _ in 0..20 {
+            source.consume().unwrap();
+        }
+        let video = source.get_video_ref();
+        (
+            video.get_encoder_options().crf.get_quality().unwrap(),
+            video.measured_psnr().unwrap(),
+        )
+    };
+    for psnr in [80.0, 5.0] {
+        let (crf, measured) = final_crf(psnr, FramedViewMode::Intensity);
+        assert_eq!(crf < 5, psnr > 40.0);
+        assert_eq!(crf > 5, psnr < 40.0);
+        let (d_crf, d_measured) = final_crf(psnr, FramedViewMode::D);
+        assert_eq!(d_crf, crf);
+        assert!((d_measured - measured).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_renditions() {
+    use adder_codec_core::codec::rate_controller::DEFAULT_CRF_QUALITY;
+    use adder_codec_core::codec::EncoderType;
+    use adder_codec_core::PixelMultiMode;
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::video::{ContrastMask, VideoBuilder};
+    use adder_codec_rs::utils::viz::ShowFeatureMode;
+
+    let dir = std::env::temp_dir().join(format!("adder_renditions_{}", rand::random::<u32>()));
+    fs::create_dir(&dir).unwrap();
+    let writer = |name: &str| BufWriter::new(File::create(dir.join(name)).unwrap());
+
+    let mut source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap();
+    let plane = source.get_video_ref().state.plane;
+
+    // A rendition needs the main output first
+    assert!(source
+        .get_video_mut()
+        .add_rendition(DEFAULT_CRF_QUALITY, writer("early.adder"))
+        .is_err());
+
+    let write_out = |source: Framed<BufWriter<File>>, name| {
+        *source
+            .write_out(
+                FramedU8,
+                TimeMode::AbsoluteT,
+                PixelMultiMode::Collapse,
+                None,
+                EncoderType::Raw,
+                EncoderOptions::default(plane),
+                writer(name),
+            )
+            .unwrap()
+    };
+    let mut source = write_out(source, "main.adder");
+    let video = source.get_video_mut();
+    // The thresholds lowered around features apply to the renditions too
+    video.update_detect_features(true, ShowFeatureMode::Off, true, false);
+    assert!(video.add_rendition(10, writer("bad.adder")).is_err());
+    let crf = video
+        .get_encoder_options()
+        .crf
+        .get_quality()
+        .unwrap_or(DEFAULT_CRF_QUALITY);
+    assert_eq!(video.add_rendition(crf, writer("same.adder")).unwrap(), 0);
+    assert_eq!(video.add_rendition(9, writer("low.adder")).unwrap(), 1);
+    assert_eq!(video.rendition_count(), 2);
+
+    for _ in 0..20 {
+        source.consume().unwrap();
+    }
+    let writers = source.get_video_mut().end_rendition_streams().unwrap();
+    assert_eq!(writers.len(), 2);
+    for writer in writers {
+        writer.unwrap().flush().unwrap();
+    }
+    source.get_video_mut().end_write_stream().unwrap();
+
+    // A rendition at the same CRF is the same stream, and a lower quality one is smaller
+    let main = fs::read(dir.join("main.adder")).unwrap();
+    assert_eq!(fs::read(dir.join("same.adder")).unwrap(), main);
+    assert!(fs::read(dir.join("low.adder")).unwrap().len() < main.len());
+
+    // A contrast mask applies to the renditions as well, even one set after they were added, and
+    // its regions keep their thresholds at any CRF. With every pixel in a region, a lower
+    // quality rendition is the same stream as the main output.
+    let source =
+        Framed::<BufWriter<File>>::new("./tests/samples/bunny_crop4.mp4".into(), false, 1.0)
+            .unwrap()
+            .auto_time_parameters(255, 255 * 30, None)
+            .unwrap();
+    let mut source = write_out(source, "masked_main.adder");
+    let video = source.get_video_mut();
+    assert_eq!(
+        video.add_rendition(9, writer("masked_low.adder")).unwrap(),
+        0
+    );
+    let labels = ndarray::Array2::zeros((plane.h_usize(), plane.w_usize()));
+    video
+        .update_contrast_mask(Some(ContrastMask::new(labels).region(0, 5, 5)))
+        .unwrap();
+    for _ in 0..20 {
+        source.consume().unwrap();
+    }
+    source
+        .get_video_mut()
+        .end_write_stream()
+        .unwrap()
+        .unwrap()
+        .flush()
+        .unwrap();
+    assert_eq!(
+        fs::read(dir.join("masked_low.adder")).unwrap(),
+        fs::read(dir.join("masked_main.adder")).unwrap()
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_high_bit_depth_video() {
+    use adder_codec_core::SourceCamera::FramedU16;
+    use adder_codec_rs::transcoder::source::framed::Framed;
+    use adder_codec_rs::transcoder::source::high_bit_depth::HighBitDepthDecoder;
+    use ffmpeg_next::format::Pixel;
+    use ffmpeg_next::util::frame::video::Video as VideoFrame;
+    use ffmpeg_next::{codec, encoder, format, Packet, Rational};
+
+    const WIDTH: u32 = 32;
+    const HEIGHT: u32 = 24;
+    const FRAMES: i64 = 10;
+
+    // Write a short, flat, 10-bit video losslessly with FFV1
+    ffmpeg_next::init().unwrap();
+    let path = std::env::temp_dir().join(format!("adder_10bit_{}.mkv", rand::random::<u32>()));
+    let time_base = Rational(1, 30);
+    let mut output = format::output(&path).unwrap();
+    let ffv1 = encoder::find(codec::Id::FFV1).unwrap();
+    let stream_index = output.add_stream(ffv1).unwrap().index();
+    let mut video_encoder = codec::context::Context::new_with_codec(ffv1)
+        .encoder()
+        .video()
+        .unwrap();
+    video_encoder.set_width(WIDTH);
+    video_encoder.set_height(HEIGHT);
+    video_encoder.set_format(Pixel::YUV420P10LE);
+    video_encoder.set_time_base(time_base);
+    if output
+        .format()
+        .flags()
+        .contains(format::flag::Flags::GLOBAL_HEADER)
+    {
+        video_encoder.set_flags(codec::flag::Flags::GLOBAL_HEADER);
+    }
+    let mut video_encoder = video_encoder.open_as(ffv1).unwrap();
+    output
+        .stream_mut(stream_index)
+        .unwrap()
+        .set_parameters(&video_encoder);
+    output.write_header().unwrap();
+
+    let write_packets = |video_encoder: &mut encoder::video::Encoder,
+                         output: &mut format::context::Output| {
+        let mut packet = Packet::empty();
+        while video_encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(stream_index);
+            packet.rescale_ts(time_base, output.stream(stream_index).unwrap().time_base());
+            packet.write_interleaved(output).unwrap();
+        }
+    };
+    for pts in 0..FRAMES {
+        let mut frame = VideoFrame::new(Pixel::YUV420P10LE, WIDTH, HEIGHT);
+        // A flat gray, with neutral chroma
+        for (plane, sample, rows) in [
+            (0, 600u16, HEIGHT),
+            (1, 512, HEIGHT / 2),
+            (2, 512, HEIGHT / 2),
+        ] {
+            let stride = frame.stride(plane);
+            let data = frame.data_mut(plane);
+            for row in data.chunks_mut(stride).take(rows as usize) {
+                for bytes in row.chunks_exact_mut(2) {
+                    bytes.copy_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+        frame.set_pts(Some(pts));
+        video_encoder.send_frame(&frame).unwrap();
+        write_packets(&mut video_encoder, &mut output);
+    }
+    video_encoder.send_eof().unwrap();
+    write_packets(&mut video_encoder, &mut output);
+    output.write_trailer().unwrap();
+    drop(output);
+
+    // It's decoded at full precision
+    let mut decoder = HighBitDepthDecoder::new(&path, 1.0).unwrap();
+    assert_eq!(decoder.bit_depth(), 10);
+    assert_eq!(decoder.size_out(), (WIDTH, HEIGHT));
+    let mut decoded = 0;
+    while let Some(intensities) = decoder.decode().unwrap() {
+        assert_eq!(intensities.dim(), (HEIGHT as usize, WIDTH as usize, 3));
+        let first = intensities[[0, 0, 0]];
+        assert!(first > f32::from(u8::MAX) && first < f32::from(u16::MAX));
+        assert!(intensities
+            .iter()
+            .all(|&intensity| (intensity - first).abs() < 256.0));
+        decoded += 1;
+    }
+    assert_eq!(decoded, FRAMES);
+    if let Some(frame_count) = decoder.frame_count() {
+        assert_eq!(frame_count, FRAMES as u64);
+    }
+
+    // The framed source takes the high bit depth path, and transcodes from it
+    let mut source = Framed::<BufWriter<File>>::new(path.clone(), false, 1.0)
+        .unwrap()
+        .auto_time_parameters(255, 255 * 30, None)
+        .unwrap();
+    assert_eq!(source.source_camera(), FramedU16);
+    assert!(source.consume().is_ok());
+    fs::remove_file(path).unwrap();
+}
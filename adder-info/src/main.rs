@@ -48,9 +48,21 @@ fn adder_info(args: MyArgs, out: impl Write) -> Result<(), Box<dyn error::Error>
     writeln!(handle, "\tWidth: {}", meta.plane.w())?;
     writeln!(handle, "\tHeight: {}", meta.plane.h())?;
     writeln!(handle, "\tColor channels: {}", meta.plane.c())?;
+    if meta.wide_coordinates {
+        writeln!(handle, "\tWide (32-bit) pixel addresses")?;
+    }
     writeln!(handle, "Source camera: {:?}", meta.source_camera)?;
+    if meta.intensity_peak != 1.0 {
+        writeln!(handle, "\tHDR intensity peak: {}", meta.intensity_peak)?;
+    }
+    if meta.color_space != ColorSpace::Bgr {
+        writeln!(handle, "\tColor space: {:?}", meta.color_space)?;
+    }
     writeln!(handle, "ADΔER transcoder parameters")?;
     writeln!(handle, "\tCodec version: {}", meta.codec_version)?;
+    if meta.motion_compensation {
+        writeln!(handle, "\tMotion-compensated Adus")?;
+    }
     writeln!(handle, "\tTime mode: {:?}", meta.time_mode)?;
     writeln!(handle, "\tTicks per second: {}", meta.tps)?;
     writeln!(
@@ -58,7 +70,33 @@ fn adder_info(args: MyArgs, out: impl Write) -> Result<(), Box<dyn error::Error>
         "\tReference ticks per source interval: {}",
         meta.ref_interval
     )?;
+    let ref_interval_changes = stream.read_ref_interval_changes(&mut bitreader)?.len();
+    if ref_interval_changes > 0 {
+        writeln!(
+            handle,
+            "\tReference interval changes (adaptive): {ref_interval_changes}"
+        )?;
+    }
     writeln!(handle, "\tΔt_max: {}", meta.delta_t_max)?;
+    let stabilization_transforms = stream.read_stabilization_transforms(&mut bitreader)?.len();
+    if stabilization_transforms > 0 {
+        writeln!(
+            handle,
+            "\tStabilization transforms: {stabilization_transforms}"
+        )?;
+    }
+    if let Some(epoch) = meta.epoch {
+        writeln!(handle, "\tEpoch (UTC ns): {epoch}")?;
+    }
+    if meta.enhancement_layers > 0 {
+        writeln!(handle, "\tEnhancement layers: {}", meta.enhancement_layers)?;
+    }
+    if !stream.user_metadata().is_empty() {
+        writeln!(handle, "User metadata")?;
+        for (key, value) in stream.user_metadata() {
+            writeln!(handle, "\t{key}: {value}")?;
+        }
+    }
     writeln!(handle, "File metadata")?;
     writeln!(handle, "\tFile size: {file_size}")?;
     writeln!(handle, "\tHeader size: {0}", meta.header_size)?;
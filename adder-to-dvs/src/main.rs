@@ -11,7 +11,7 @@ use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::option::Option;
 use std::path::PathBuf;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{error, io};
 use video_rs::{Encoder, EncoderSettings, Options, PixelFormat};
 
@@ -55,6 +55,14 @@ pub struct MyArgs {
 
     #[clap(short, long, action)]
     pub reorder: bool,
+
+    /// Seconds into the ADΔER video to start converting from
+    #[clap(long, default_value_t = 0.0)]
+    pub start_time: f64,
+
+    /// Seconds of the ADΔER video to convert from the start (0 = no limit)
+    #[clap(long, default_value_t = 0.0)]
+    pub duration: f64,
 }
 
 struct DvsPixel {
@@ -114,6 +122,11 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     let mut handle = io::BufWriter::new(stdout.lock());
 
     stream.set_input_stream_position(&mut bitreader, first_event_position)?;
+    stream.set_time_window(
+        &mut bitreader,
+        Duration::from_secs_f64(args.start_time.max(0.0)),
+        (args.duration > 0.0).then(|| Duration::from_secs_f64(args.duration)),
+    )?;
 
     let mut ordered_event_queue: Option<VecDeque<DvsEvent>> = if args.reorder {
         Some(VecDeque::new())
@@ -179,18 +192,18 @@ fn main() -> Result<(), Box<dyn error::Error>> {
 
         Array3::from_shape_vec(
             (
-                meta.plane.h().into(),
-                meta.plane.w().into(),
-                meta.plane.c().into(),
+                meta.plane.h_usize(),
+                meta.plane.w_usize(),
+                meta.plane.c_usize(),
             ),
             data,
         )?
     };
 
     let mut event_counts: Array3<u16> = Array3::zeros((
-        meta.plane.h().into(),
-        meta.plane.w().into(),
-        meta.plane.c().into(),
+        meta.plane.h_usize(),
+        meta.plane.w_usize(),
+        meta.plane.c_usize(),
     ));
 
     let mut instantaneous_frame_deque = VecDeque::from([create_blank_dvs_frame(&meta)?]);
@@ -438,11 +451,11 @@ fn set_instant_dvs_pixel(
     }
 
     if frame_idx >= frame_count {
-        frames[frame_idx - frame_count][[event.coord.y.into(), event.coord.x.into(), 0]] =
+        frames[frame_idx - frame_count][[event.coord.y_usize(), event.coord.x_usize(), 0]] =
             value as u8;
-        frames[frame_idx - frame_count][[event.coord.y.into(), event.coord.x.into(), 1]] =
+        frames[frame_idx - frame_count][[event.coord.y_usize(), event.coord.x_usize(), 1]] =
             value as u8;
-        frames[frame_idx - frame_count][[event.coord.y.into(), event.coord.x.into(), 2]] =
+        frames[frame_idx - frame_count][[event.coord.y_usize(), event.coord.x_usize(), 2]] =
             value as u8;
     }
     Ok(())
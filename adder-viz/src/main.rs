@@ -69,7 +69,8 @@ impl eframe::App for App {
             Tabs::Player => self.player_ui.update(ctx),
         }
 
-        ctx.request_repaint();
+        // No unconditional repaint here. The transcoder and player threads wake the UI when they
+        // have new images or messages, so the app stays idle otherwise.
     }
 }
 
@@ -2,7 +2,7 @@ use crate::player::adder::AdderPlayerError::Uninitialized;
 use crate::player::adder::AdderPlayerError::{InvalidFileType, NoFileSelected};
 use crate::player::ui::PlayerState;
 use crate::player::ui::{PlayerInfoMsg, PlayerStateMsg};
-use crate::utils::prep_epaint_image;
+use crate::utils::{prep_epaint_image, time_window};
 use adder_codec_rs::adder_codec_core::bitstream_io::{BigEndian, BitReader};
 use adder_codec_rs::adder_codec_core::codec::decoder::Decoder;
 use adder_codec_rs::adder_codec_core::codec::{CodecError, EncoderType};
@@ -17,7 +17,7 @@ use std::io::BufReader;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
 use tokio::sync::mpsc::{Receiver, Sender};
 use video_rs_adder_dep::Frame;
 
@@ -58,6 +58,9 @@ pub struct AdderPlayer {
     running_frame: Frame,
     pub image_tx: Sender<ColorImage>,
     framer_builder: FramerBuilder,
+
+    /// Handle for waking the UI thread whenever there is something new to draw
+    egui_ctx: egui::Context,
 }
 
 impl AdderPlayer {
@@ -65,6 +68,7 @@ impl AdderPlayer {
         rx: Receiver<PlayerStateMsg>,
         msg_tx: mpsc::Sender<PlayerInfoMsg>,
         image_tx: Sender<ColorImage>,
+        egui_ctx: egui::Context,
     ) -> Self {
         let threaded_rt = tokio::runtime::Runtime::new().unwrap();
 
@@ -80,12 +84,20 @@ impl AdderPlayer {
             input_stream: None,
             running_frame: Frame::zeros((0, 0, 0)),
             framer_builder: FramerBuilder::new(PlaneSize::default(), 0),
+            egui_ctx,
         }
     }
 
     pub(crate) async fn run(&mut self) {
         loop {
-            match self.rx.try_recv() {
+            // Sleep until the UI sends a new state if there is nothing to decode
+            let received = if self.framer.is_some() {
+                self.rx.try_recv()
+            } else {
+                self.rx.recv().await.ok_or(TryRecvError::Disconnected)
+            };
+
+            match received {
                 Ok(msg) => match msg {
                     PlayerStateMsg::Terminate => {
                         eprintln!("Resetting video");
@@ -102,7 +114,11 @@ impl AdderPlayer {
                         self.handle_error(result);
                     }
                 },
-                Err(_) => {
+                Err(TryRecvError::Disconnected) => {
+                    // The UI has been closed
+                    return;
+                }
+                Err(TryRecvError::Empty) => {
                     // Received no data, so consume the transcoder source if it exists
                     if self.framer.is_some() {
                         let result = self.consume().await;
@@ -149,6 +165,7 @@ impl AdderPlayer {
                     }
                     _ => {}
                 };
+                self.egui_ctx.request_repaint();
             }
         }
     }
@@ -184,6 +201,7 @@ impl AdderPlayer {
                         panic!("todo");
                     }
                 };
+                self.egui_ctx.request_repaint();
 
                 // Send a message with the plane size of the video
                 // let plane = self
@@ -258,7 +276,12 @@ impl AdderPlayer {
                         // adder video
                         let input_path =
                             input_path_buf.to_str().expect("Invalid string").to_string();
-                        let (stream, bitreader) = open_file_decoder(&input_path)?;
+                        let (mut stream, mut bitreader) = open_file_decoder(&input_path)?;
+                        let (start_time, duration) = time_window(
+                            player_state.core_params.start_time,
+                            player_state.core_params.duration,
+                        );
+                        stream.set_time_window(&mut bitreader, start_time, duration)?;
 
                         let meta = *stream.meta();
 
@@ -282,7 +305,9 @@ impl AdderPlayer {
                             .mode(INSTANTANEOUS)
                             .buffer_limit(player_state.adaptive_params.buffer_limit)
                             .detect_features(player_state.adaptive_params.detect_features)
-                            .source(stream.get_source_type(), meta.source_camera);
+                            .source(stream.get_source_type(), meta.source_camera)
+                            .chroma_subsampling(meta.chroma_subsampling)
+                            .color_space(meta.color_space);
 
                         let mut frame_sequence: FrameSequence<u8> = framer_builder.clone().finish();
                         self.framer = Some(frame_sequence);
@@ -342,7 +367,10 @@ impl AdderPlayer {
             let mut idx = 0;
             unsafe {
                 let db = self.running_frame.as_slice_mut().unwrap();
-                let new_frame = frame_sequence.pop_next_frame().unwrap();
+                let mut new_frame = frame_sequence.pop_next_frame().unwrap();
+                for chunk in new_frame.iter_mut() {
+                    frame_sequence.convert_to_bgr(chunk);
+                }
                 // Flatten the frame
                 for chunk in 0..new_frame.len() {
                     for y in 0..new_frame[chunk].shape()[0] {
@@ -402,6 +430,7 @@ impl AdderPlayer {
             // Set the image to the handle, so that the UI can display it
             // TODO: Actually send the images on a channel, so they can be displayed separately from the decompression thread
             self.image_tx.send(image).await.unwrap();
+            self.egui_ctx.request_repaint();
             // self.player_state.last_frame_display_time = Some(Instant::now());
 
             // return Ok(());
@@ -447,4 +476,3 @@ pub struct InputStream {
     pub(crate) decoder: Decoder<BufReader<File>>,
     pub(crate) bitreader: BitReader<BufReader<File>, BigEndian>,
 }
-unsafe impl Send for InputStream {}
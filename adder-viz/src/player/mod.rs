@@ -12,6 +12,8 @@ pub mod ui;
 pub(crate) struct CoreParams {
     pub input_path_buf_0: Option<PathBuf>,
     pub playback_speed: f32,
+    pub start_time: f64,
+    pub duration: Option<f64>,
 }
 
 impl Default for CoreParams {
@@ -19,6 +21,8 @@ impl Default for CoreParams {
         Self {
             input_path_buf_0: None,
             playback_speed: 1.0,
+            start_time: 0.0,
+            duration: None,
         }
     }
 }
@@ -1,7 +1,7 @@
 use crate::player::adder::AdderPlayer;
 use crate::player::{AdaptiveParams, CoreParams};
 use crate::transcoder::InfoParams;
-use crate::utils::{add_checkbox_row, add_slider_row, slider_pm};
+use crate::utils::{add_checkbox_row, add_slider_row, add_time_window_rows, slider_pm};
 use crate::{TabState, VizUi};
 use adder_codec_rs::adder_codec_core::PlaneSize;
 use adder_codec_rs::transcoder::source::video::FramedViewMode;
@@ -95,7 +95,7 @@ impl PlayerUi {
             paused: Arc::new(false.into()),
         };
 
-        player_ui.spawn_tab_runner(rx, msg_tx, image_tx);
+        player_ui.spawn_tab_runner(rx, msg_tx, image_tx, cc.egui_ctx.clone());
         player_ui
     }
 
@@ -104,6 +104,7 @@ impl PlayerUi {
         rx: mpsc::Receiver<PlayerStateMsg>,
         msg_tx: mpsc::Sender<PlayerInfoMsg>,
         image_tx: Sender<ColorImage>,
+        egui_ctx: egui::Context,
     ) {
         let adder_image_handle = self.adder_image_handle.clone();
         let rt = tokio::runtime::Runtime::new().expect("Unable to create Runtime");
@@ -113,7 +114,7 @@ impl PlayerUi {
         // Execute the runtime in its own thread.
         std::thread::spawn(move || {
             rt.block_on(async {
-                let mut transcoder = AdderPlayer::new(rx, msg_tx, image_tx);
+                let mut transcoder = AdderPlayer::new(rx, msg_tx, image_tx, egui_ctx);
                 transcoder.run().await;
             })
         });
@@ -256,6 +257,9 @@ impl VizUi for PlayerUi {
                 Ok(image) => {
                     self.adder_image_handle.set(image, Default::default());
                     self.last_frame_display_time = Some(Instant::now());
+
+                    // Wake up again when the next buffered frame is due
+                    ui.ctx().request_repaint_after(self.frame_length);
                 }
                 Err(_) => {
                     // If we don't have a new image to display, sleep this thread (buffered pause)
@@ -265,14 +269,20 @@ impl VizUi for PlayerUi {
 
                         // Spawn a thread to mark the player as unpaused after 3 seconds
                         let paused = self.paused.clone();
+                        let ctx = ui.ctx().clone();
                         std::thread::spawn(move || {
                             dbg!("Sleeping 3 seconds...");
                             std::thread::sleep(Duration::from_secs(3));
                             paused.store(false, Ordering::Relaxed);
+                            ctx.request_repaint();
                         });
                     }
                 }
             }
+        } else if !self.paused.load(Ordering::Relaxed) {
+            // An intermediate repaint (e.g., from user input) may have consumed the scheduled one
+            ui.ctx()
+                .request_repaint_after(self.frame_length - time_since_last_displayed);
         }
     }
 
@@ -297,6 +307,12 @@ impl VizUi for PlayerUi {
         if playback_speed != core_params.playback_speed {
             while self.image_rx.try_recv().is_ok() {} // Drain the image channel
         }
+        add_time_window_rows(
+            true,
+            ui,
+            &mut core_params.start_time,
+            &mut core_params.duration,
+        );
 
         ui.add_enabled(true, egui::Label::new("Playback controls:"));
         ui.horizontal(|ui| {
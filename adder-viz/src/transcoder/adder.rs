@@ -10,7 +10,6 @@ use std::fmt;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 #[cfg(feature = "open-cv")]
@@ -22,18 +21,24 @@ use adder_codec_rs::davis_edi_rs::util::reconstructor::Reconstructor;
 use crate::transcoder::adder::AdderTranscoderError::{
     InvalidFileType, NoFileSelected, Uninitialized,
 };
+use crate::transcoder::schedule::{ParamSchedule, ScheduleError};
 use crate::transcoder::ui::{TranscoderInfoMsg, TranscoderState, TranscoderStateMsg};
 use crate::transcoder::{EventRateMsg, InfoUiState};
-use crate::utils::prep_epaint_image;
+use crate::utils::{prep_epaint_image, time_window};
 use crate::Images;
+use adder_codec_rs::adder_codec_core::codec::encoder::Encoder;
 use adder_codec_rs::adder_codec_core::codec::rate_controller::DEFAULT_CRF_QUALITY;
-use adder_codec_rs::adder_codec_core::SourceCamera::{DavisU8, Dvs, FramedU8};
+use adder_codec_rs::adder_codec_core::codec::raw::stream::RawOutput;
+use adder_codec_rs::adder_codec_core::codec::sink::Backpressure;
+use adder_codec_rs::adder_codec_core::SourceCamera::{DavisU8, Dvs};
 use adder_codec_rs::adder_codec_core::{Event, PlaneError};
 #[cfg(feature = "open-cv")]
 use adder_codec_rs::davis_edi_rs::util::reconstructor::ReconstructorError;
+#[cfg(feature = "metavision")]
+use adder_codec_rs::transcoder::source::metavision::read_bias_file;
 use adder_codec_rs::transcoder::source::prophesee::Prophesee;
-use adder_codec_rs::transcoder::source::video::SourceError::{NoData, VideoError};
-use adder_codec_rs::transcoder::source::video::{Source, SourceError, VideoBuilder};
+use adder_codec_rs::transcoder::source::video::SourceError::{BufferEmpty, NoData, VideoError};
+use adder_codec_rs::transcoder::source::video::{EventStats, Source, SourceError, VideoBuilder};
 use adder_codec_rs::transcoder::source::AdderSource;
 use adder_codec_rs::utils::cv::{calculate_quality_metrics, QualityMetrics};
 #[cfg(feature = "open-cv")]
@@ -44,6 +49,10 @@ use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
 use tokio::sync::mpsc::Receiver;
 use video_rs_adder_dep::Frame;
 
+/// The number of batches of events which may be queued for the raw copy before the transcode
+/// waits for it to catch up
+const RAW_COPY_CAPACITY: usize = 64;
+
 pub struct AdderTranscoder {
     pool: tokio::runtime::Runtime,
     transcoder_state: TranscoderState,
@@ -54,6 +63,12 @@ pub struct AdderTranscoder {
     pub(crate) adder_image_handle: egui::TextureHandle,
     total_events: u64,
     last_consume_time: std::time::Instant,
+
+    /// Drives the adaptive parameters over the course of the transcode, if one is loaded
+    schedule: Option<ParamSchedule>,
+
+    /// Handle for waking the UI thread whenever there is something new to draw
+    egui_ctx: egui::Context,
 }
 
 #[derive(Error, Debug)]
@@ -82,6 +97,10 @@ pub enum AdderTranscoderError {
     #[error(transparent)]
     ReconstructorError(#[from] ReconstructorError),
 
+    /// Parameter schedule error
+    #[error(transparent)]
+    ScheduleError(#[from] ScheduleError),
+
     /// Uninitialized error
     #[error("Uninitialized")]
     Uninitialized,
@@ -93,6 +112,7 @@ impl AdderTranscoder {
         msg_tx: mpsc::Sender<TranscoderInfoMsg>,
         input_image_handle: egui::TextureHandle,
         adder_image_handle: egui::TextureHandle,
+        egui_ctx: egui::Context,
     ) -> Self {
         let threaded_rt = tokio::runtime::Builder::new_multi_thread().build().unwrap();
 
@@ -106,13 +126,22 @@ impl AdderTranscoder {
             adder_image_handle,
             total_events: 0,
             last_consume_time: std::time::Instant::now(),
+            schedule: None,
+            egui_ctx,
         }
     }
 
-    /// The unbounded loop. Continually processes messages or consumes the source
+    /// The unbounded loop. Continually processes messages or consumes the source. While there is
+    /// no source, the loop sleeps until the UI sends a new state.
     pub(crate) async fn run(&mut self) {
         loop {
-            match self.rx.try_recv() {
+            let received = if self.source.is_some() {
+                self.rx.try_recv()
+            } else {
+                self.rx.recv().await.ok_or(TryRecvError::Disconnected)
+            };
+
+            match received {
                 Ok(msg) => match msg {
                     TranscoderStateMsg::Terminate => {
                         eprintln!("Resetting video");
@@ -126,12 +155,14 @@ impl AdderTranscoder {
 
                         self.transcoder_state.core_params.input_path_buf_0 = None;
                         self.transcoder_state.core_params.output_path = None;
+                        self.transcoder_state.core_params.raw_copy_path = None;
 
                         // Clear the images
                         self.adder_image_handle
                             .set(ColorImage::default(), Default::default());
                         self.input_image_handle
                             .set(ColorImage::default(), Default::default());
+                        self.egui_ctx.request_repaint();
                     }
 
                     TranscoderStateMsg::Set { transcoder_state } => {
@@ -140,7 +171,11 @@ impl AdderTranscoder {
                         self.handle_error(result).await;
                     }
                 },
-                Err(_) => {
+                Err(TryRecvError::Disconnected) => {
+                    // The UI has been closed
+                    return;
+                }
+                Err(TryRecvError::Empty) => {
                     // Received no data, so consume the transcoder source if it exists
                     if self.source.is_some() {
                         let result = self.consume();
@@ -161,7 +196,8 @@ impl AdderTranscoder {
                     AdderTranscoderError::SourceError(VideoError(
                         video_rs_adder_dep::Error::ReadExhausted,
                     ))
-                    | AdderTranscoderError::SourceError(NoData) => {
+                    | AdderTranscoderError::SourceError(NoData)
+                    | AdderTranscoderError::SourceError(BufferEmpty) => {
                         let mut state = self.transcoder_state.clone();
                         self.source
                             .as_mut()
@@ -170,6 +206,7 @@ impl AdderTranscoder {
                             .state
                             .in_interval_count = 0;
                         state.core_params.output_path = None;
+                        state.core_params.raw_copy_path = None;
                         self.state_update(state, true)
                             .await
                             .expect("Error creating new transcoder");
@@ -192,6 +229,7 @@ impl AdderTranscoder {
                     }
                     _ => {}
                 };
+                self.egui_ctx.request_repaint();
             }
         }
     }
@@ -214,6 +252,7 @@ impl AdderTranscoder {
             msg.events_ppc_per_sec =
                 msg.events_per_sec / (source.get_video_ref().state.plane.volume() as f64);
             msg.total_events = self.total_events;
+            let source_t = source.get_video_ref().state.in_interval_count as f64 / source_fps;
 
             msg.transcoded_fps = 1.0
                 / Instant::now()
@@ -232,6 +271,7 @@ impl AdderTranscoder {
                     // return Err(Box::new(e)); // TODO
                 }
             };
+            self.apply_schedule(source_t)?;
         }
         self.show_input_frame();
 
@@ -242,6 +282,32 @@ impl AdderTranscoder {
 
         self.last_consume_time = std::time::Instant::now();
 
+        // The images and metrics are updated, so wake the UI thread to draw them
+        self.egui_ctx.request_repaint();
+
+        Ok(())
+    }
+
+    /// Apply the steps of the parameter schedule which are due by `source_t` seconds into the
+    /// source, and send the changed parameters to the UI
+    fn apply_schedule(&mut self, source_t: f64) -> Result<(), AdderTranscoderError> {
+        let Some(schedule) = &mut self.schedule else {
+            return Ok(());
+        };
+        if !schedule.apply_due(source_t, &mut self.transcoder_state.adaptive_params) {
+            return Ok(());
+        }
+        self.adaptive_state_update()?;
+
+        match self.msg_tx.try_send(TranscoderInfoMsg::AdaptiveParams(
+            self.transcoder_state.adaptive_params.clone(),
+        )) {
+            Ok(_) => {}
+            Err(TrySendError::Full(..)) => {
+                eprintln!("Msg channel full");
+            }
+            Err(_) => {}
+        };
         Ok(())
     }
 
@@ -284,7 +350,37 @@ impl AdderTranscoder {
         if force_new || transcoder_state.core_params != self.transcoder_state.core_params {
             // eprintln!("Create new transcoder");
             let res = self.core_state_update(transcoder_state).await;
+
+            // Every new transcode starts from the beginning of the schedule
+            let res = res.and_then(|()| {
+                self.schedule = match &self.transcoder_state.core_params.schedule_path {
+                    Some(path) => Some(ParamSchedule::load(path)?),
+                    None => None,
+                };
+                Ok(())
+            });
             if res.is_ok() {
+                // Push the distribution of the emitted events to the UI's histograms as the
+                // source produces them. If the channel is full, the stats keep accumulating
+                // until the next batch.
+                let msg_tx = self.msg_tx.clone();
+                let egui_ctx = self.egui_ctx.clone();
+                let mut pending = EventStats::default();
+                self.source
+                    .as_mut()
+                    .unwrap()
+                    .get_video_mut()
+                    .event_stats_callback(Some(Box::new(move |stats| {
+                        pending.merge(stats);
+                        if msg_tx
+                            .try_send(TranscoderInfoMsg::EventStats(pending.clone()))
+                            .is_ok()
+                        {
+                            pending = EventStats::default();
+                            egui_ctx.request_repaint();
+                        }
+                    })));
+
                 // Send a message with the plane size of the video
                 let plane = self
                     .source
@@ -306,6 +402,7 @@ impl AdderTranscoder {
                         panic!("todo");
                     }
                 };
+                self.egui_ctx.request_repaint();
             }
             return res;
         } else if transcoder_state.adaptive_params != self.transcoder_state.adaptive_params {
@@ -358,6 +455,22 @@ impl AdderTranscoder {
         Ok(())
     }
 
+    /// Write a raw copy of the source's events, if a path for one is selected, as an additional
+    /// sink alongside the main output
+    fn attach_raw_copy(&mut self) -> Result<(), AdderTranscoderError> {
+        let Some(path) = &self.transcoder_state.core_params.raw_copy_path else {
+            return Ok(());
+        };
+        let writer = BufWriter::new(File::create(path)?);
+        let video = self.source.as_mut().ok_or(Uninitialized)?.get_video_mut();
+
+        let mut meta = *video.encoder.meta();
+        meta.header_size = 0;
+        let encoder = Encoder::new_raw(RawOutput::new(meta, writer), video.encoder.options.clone());
+        video.add_sink(Box::new(encoder), RAW_COPY_CAPACITY, Backpressure::Block);
+        Ok(())
+    }
+
     /// Called both when creating a new transcoder source and when an adaptive parameter has
     /// changed. Sets the adaptive parameters for the source. Sets all the parameters (instead of
     /// only the changed ones) because it's much easier to read and it's still fast.
@@ -382,7 +495,10 @@ impl AdderTranscoder {
         );
         source
             .get_video_mut()
-            .update_encoder_options(params.encoder_options);
+            .update_encoder_options(params.encoder_options.clone());
+        source
+            .get_video_mut()
+            .update_temporal_filter(params.denoise.then_some(params.temporal_filter))?;
 
         Ok(())
     }
@@ -407,8 +523,18 @@ impl AdderTranscoder {
                         let ext = ext.to_os_string();
                         self.create_davis(transcoder_state, ext).await
                     }
-                    "dat" => {
-                        // Prophesee video
+                    #[cfg(not(feature = "open-cv"))]
+                    "aedat4" => {
+                        // DVS events only, without OpenCV
+                        self.create_prophesee(transcoder_state).await
+                    }
+                    "dat" | "aedat" => {
+                        // Prophesee or legacy AEDAT 2.0/3.x video
+                        self.create_prophesee(transcoder_state).await
+                    }
+                    #[cfg(feature = "metavision")]
+                    "bias" => {
+                        // Live Prophesee camera, configured with the bias file
                         self.create_prophesee(transcoder_state).await
                     }
                     _ => Err(InvalidFileType),
@@ -430,8 +556,12 @@ impl AdderTranscoder {
         if let Some(AdderSource::Framed(source)) = &mut self.source {
             if transcoder_state.core_params.input_path_buf_0
                 == self.transcoder_state.core_params.input_path_buf_0
+                && transcoder_state.core_params.start_time
+                    == self.transcoder_state.core_params.start_time
                 && transcoder_state.core_params.output_path.is_none()
                 && self.transcoder_state.core_params.output_path.is_none()
+                && transcoder_state.core_params.raw_copy_path.is_none()
+                && self.transcoder_state.core_params.raw_copy_path.is_none()
             {
                 current_frame =
                     source.get_video_ref().state.in_interval_count + source.frame_idx_start;
@@ -443,6 +573,7 @@ impl AdderTranscoder {
 
         let core_params = &self.transcoder_state.core_params;
         let adaptive_params = &self.transcoder_state.adaptive_params;
+        let (start_time, duration) = time_window(core_params.start_time, core_params.duration);
 
         let mut framed = Framed::new(
             core_params.input_path_buf_0.clone().unwrap(),
@@ -456,13 +587,17 @@ impl AdderTranscoder {
                 .get_quality()
                 .unwrap_or(DEFAULT_CRF_QUALITY),
         )
-        .frame_start(current_frame)?
+        .start_time(start_time)?
+        .duration(duration)
         .chunk_rows(1)
         .auto_time_parameters(
             core_params.delta_t_ref as u32,
             core_params.delta_t_max_mult * core_params.delta_t_ref as u32,
             Some(core_params.time_mode),
         )?;
+        if current_frame > 0 {
+            framed = framed.frame_start(current_frame)?;
+        }
 
         // TODO: Change the builder to take in a pathbuf directly, not a string,
         // and to handle the error checking in the associated function
@@ -472,19 +607,21 @@ impl AdderTranscoder {
                 let out_path = output_path.to_str().unwrap();
                 let writer = BufWriter::new(File::create(out_path)?);
 
+                let source_camera = framed.source_camera();
                 framed = *framed.write_out(
-                    FramedU8,
+                    source_camera,
                     core_params.time_mode,
                     core_params.integration_mode_radio_state,
                     Some(core_params.adu_interval as usize),
                     core_params.encoder_type,
-                    adaptive_params.encoder_options,
+                    adaptive_params.encoder_options.clone(),
                     writer,
                 )?;
             }
         };
 
         self.source = Some(AdderSource::Framed(framed));
+        self.attach_raw_copy()?;
 
         self.adaptive_state_update()?;
         self.last_consume_time = std::time::Instant::now();
@@ -600,6 +737,12 @@ impl AdderTranscoder {
                     Some(core_params.time_mode),
                 )?;
 
+        // A live camera can't be seeked, so only apply the time window to a recording
+        if mode == "file" {
+            let (start_time, duration) = time_window(core_params.start_time, core_params.duration);
+            davis_source = davis_source.start_time(start_time)?.duration(duration);
+        }
+
         // Override time parameters if we're in framed mode
         if core_params.davis_mode_radio_state == TranscoderMode::Framed {
             davis_source = davis_source.time_parameters(
@@ -610,6 +753,16 @@ impl AdderTranscoder {
             )?;
         }
 
+        // A live camera starts at tick 0 now, so record the wall-clock time in the stream header,
+        // and keep the stream aligned with it as the camera's clock drifts
+        if mode == "socket" {
+            let epoch_ns = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .and_then(|elapsed| u64::try_from(elapsed.as_nanos()).ok());
+            davis_source = davis_source.epoch(epoch_ns).drift_correction(true);
+        }
+
         if let Some(output_string) = output_string {
             let writer = BufWriter::new(File::create(output_string)?);
             davis_source = *davis_source.write_out(
@@ -618,12 +771,13 @@ impl AdderTranscoder {
                 core_params.integration_mode_radio_state,
                 Some(core_params.delta_t_max_mult as usize),
                 core_params.encoder_type,
-                adaptive_params.encoder_options,
+                adaptive_params.encoder_options.clone(),
                 writer,
             )?;
         }
 
         self.source = Some(AdderSource::Davis(davis_source));
+        self.attach_raw_copy()?;
 
         self.adaptive_state_update()?;
         self.last_consume_time = std::time::Instant::now();
@@ -645,15 +799,9 @@ impl AdderTranscoder {
             .clone()
             .map(|output_path| output_path.to_str().expect("Bad path").to_string());
 
-        let mut prophesee_source: Prophesee<BufWriter<File>> = Prophesee::new(
+        let mut prophesee_source = open_prophesee(
             core_params.delta_t_ref as u32,
-            core_params
-                .input_path_buf_0
-                .clone()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string(),
+            core_params.input_path_buf_0.as_ref().unwrap(),
         )?
         .crf(
             adaptive_params
@@ -662,6 +810,8 @@ impl AdderTranscoder {
                 .get_quality()
                 .unwrap_or(DEFAULT_CRF_QUALITY),
         );
+        let (start_time, duration) = time_window(core_params.start_time, core_params.duration);
+        prophesee_source = prophesee_source.start_time(start_time)?.duration(duration);
         let adu_interval = (prophesee_source.get_video_ref().state.tps as f32
             / core_params.delta_t_ref as f32) as usize;
 
@@ -673,7 +823,7 @@ impl AdderTranscoder {
                 core_params.integration_mode_radio_state,
                 Some(adu_interval),
                 core_params.encoder_type,
-                adaptive_params.encoder_options,
+                adaptive_params.encoder_options.clone(),
                 writer,
             )?;
         }
@@ -682,6 +832,7 @@ impl AdderTranscoder {
             / prophesee_source.get_video_ref().state.params.ref_time as u32;
 
         self.source = Some(AdderSource::Prophesee(prophesee_source));
+        self.attach_raw_copy()?;
 
         self.adaptive_state_update()?;
         self.last_consume_time = std::time::Instant::now();
@@ -691,3 +842,16 @@ impl AdderTranscoder {
         Ok(())
     }
 }
+
+/// Open a Prophesee source from a `.dat`, `.aedat`, or `.aedat4` file, or, given a Metavision
+/// bias file, from a live camera started with those biases
+fn open_prophesee(
+    ref_time: u32,
+    path: &Path,
+) -> Result<Prophesee<BufWriter<File>>, Box<dyn Error>> {
+    #[cfg(feature = "metavision")]
+    if path.extension().is_some_and(|ext| ext == "bias") {
+        return Prophesee::from_camera(ref_time, None, &read_bias_file(path)?);
+    }
+    Prophesee::new(ref_time, path.to_str().unwrap().to_string())
+}
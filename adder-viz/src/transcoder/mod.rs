@@ -4,18 +4,23 @@ use adder_codec_rs::adder_codec_core::codec::{EncoderOptions, EncoderType};
 use adder_codec_rs::adder_codec_core::{PixelMultiMode, TimeMode};
 #[cfg(feature = "open-cv")]
 use adder_codec_rs::transcoder::source::davis::TranscoderMode;
-use adder_codec_rs::transcoder::source::video::FramedViewMode;
+use adder_codec_rs::transcoder::source::denoise::TemporalFilter;
+use adder_codec_rs::transcoder::source::video::{EventStats, FramedViewMode};
 use adder_codec_rs::utils::viz::ShowFeatureMode;
 use std::collections::VecDeque;
 use std::path::PathBuf;
 use tokio::sync::Mutex;
 
 pub mod adder;
+pub mod schedule;
 pub mod ui;
 
+/// The number of recent batches of events whose D and Δt distributions are plotted
+pub(crate) const EVENT_STATS_WINDOW: usize = 30;
+
 /// UI-driven parameters which do not require a total reset of the transcoder. These
 /// parameters can be adaptively changed during a transcoder operation.
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct AdaptiveParams {
     pub auto_quality: bool,
     pub crf_number: u8,
@@ -29,6 +34,8 @@ pub(crate) struct AdaptiveParams {
     pub feature_cluster: bool,
     optimize_c: bool,
     optimize_c_frequency: u32,
+    pub denoise: bool,
+    pub temporal_filter: TemporalFilter,
 }
 
 /// Core parameters which require a total reset of the transcoder. These parameters
@@ -38,12 +45,19 @@ pub(crate) struct CoreParams {
     pub delta_t_ref: u32,
     pub color: bool,
     pub scale: f64,
+    pub start_time: f64,
+    pub duration: Option<f64>,
     pub delta_t_max_mult: u32,
     pub adu_interval: u32,
     pub time_mode: TimeMode,
     pub encoder_type: EncoderType,
     pub input_path_buf_0: Option<PathBuf>,
     pub output_path: Option<PathBuf>,
+    /// A raw stream to write alongside the main output, e.g., to keep a lossless copy of a lossy
+    /// compressed transcode
+    pub raw_copy_path: Option<PathBuf>,
+    /// A [`schedule::ParamSchedule`] file to drive the adaptive parameters during the transcode
+    pub schedule_path: Option<PathBuf>,
     pub(crate) integration_mode_radio_state: PixelMultiMode,
     #[cfg(feature = "open-cv")]
     davis_mode_radio_state: TranscoderMode,
@@ -79,7 +93,27 @@ impl Default for AdaptiveParams {
             encoder_options: EncoderOptions {
                 event_drop: Default::default(),
                 event_order: Default::default(),
+                event_validation: Default::default(),
+                time_index: false,
+                adu_checksum: false,
+                adu_sync_markers: false,
+                enhancement_layers: 0,
                 crf: Crf::new(None, Default::default()),
+                quality_map: None,
+                channel_c_thresh_max: None,
+                target_kbps: None,
+                user_metadata: None,
+                raw_flush_events: None,
+                adu_interval: None,
+                delta_t_coding: Default::default(),
+                lz_dictionary: None,
+                motion_compensation: false,
+                block_size: None,
+                round_trip_check: Default::default(),
+                max_adu_events: None,
+                context_priors: None,
+                intra_refresh: Default::default(),
+                quantization_table: None,
             },
             thread_count: 1,
             show_original: false,
@@ -90,6 +124,8 @@ impl Default for AdaptiveParams {
             feature_cluster: false,
             optimize_c: false,
             optimize_c_frequency: 10,
+            denoise: false,
+            temporal_filter: Default::default(),
         }
     }
 }
@@ -100,6 +136,8 @@ impl Default for CoreParams {
             delta_t_ref: 255,
             color: false,
             scale: 0.25,
+            start_time: 0.0,
+            duration: None,
             delta_t_max_mult: 30,
             adu_interval: 30,
             time_mode: Default::default(),
@@ -109,6 +147,8 @@ impl Default for CoreParams {
             davis_mode_radio_state: TranscoderMode::RawDavis,
             input_path_buf_0: None,
             output_path: None,
+            raw_copy_path: None,
+            schedule_path: None,
             davis_output_fps: 100.0,
             input_path_buf_1: None,
         }
@@ -143,6 +183,7 @@ pub struct InfoUiState {
     events_ppc_total: f64,
     events_ppc_per_sec: f64,
     transcoded_fps: f64,
+    event_stats_history: VecDeque<EventStats>,
     //     plot_points_latency_y: PlotY,
     //     pub view_mode_radio_state: FramedViewMode, // TODO: Move to different struct
 }
@@ -172,6 +213,7 @@ impl Default for InfoUiState {
             events_ppc_total: 0.0,
             events_ppc_per_sec: 0.0,
             transcoded_fps: 0.0,
+            event_stats_history: VecDeque::with_capacity(EVENT_STATS_WINDOW),
         }
     }
 }
@@ -0,0 +1,241 @@
+use crate::transcoder::AdaptiveParams;
+use adder_codec_rs::adder_codec_core::codec::rate_controller::CRF;
+use adder_codec_rs::utils::viz::ShowFeatureMode;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A schedule of adaptive parameter changes to apply at given times during a transcode, so that
+/// an experiment with dynamic parameters can be reproduced exactly.
+///
+/// Each line of a schedule file holds a time in seconds of source video since the transcode
+/// started (or restarted, e.g., after a core parameter changed), followed by one or more
+/// `name=value` assignments. The words `at`, `set`, and `and`, a `t=` before the time, and an `s`
+/// after it are optional. Everything after a `#` is a comment. For example:
+///
+/// ```text
+/// # Lower the quality, and then look for features
+/// at t=5s set crf=3
+/// 10 detect_features=true show_features=hold
+/// ```
+///
+/// The supported parameters are `crf` (which also turns on auto mode), `detect_features`,
+/// `show_features` (`off`, `instant`, or `hold`), `feature_rate_adjustment`, and
+/// `feature_cluster`. Values aren't interpolated: each one takes effect at the first interval at
+/// or after its time, and holds until the next change of the same parameter.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct ParamSchedule {
+    /// The steps, in time order
+    steps: Vec<ScheduleStep>,
+
+    /// The index of the first step which hasn't been applied yet
+    next: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ScheduleStep {
+    /// The time of the step, in seconds of source video since the transcode started
+    t: f64,
+    change: ParamChange,
+}
+
+/// A single change to the adaptive parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParamChange {
+    Crf(u8),
+    DetectFeatures(bool),
+    ShowFeatures(ShowFeatureMode),
+    FeatureRateAdjustment(bool),
+    FeatureCluster(bool),
+}
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    /// The schedule file couldn't be read
+    #[error("Couldn't read the schedule: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// A line of the schedule couldn't be parsed
+    #[error("Schedule line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+impl ParamChange {
+    fn parse(name: &str, value: &str) -> Result<Self, String> {
+        let parse_bool = |value: &str| {
+            bool::from_str(value).map_err(|_| format!("expected true or false for {name}"))
+        };
+        Ok(match name {
+            "crf" => {
+                let crf = u8::from_str(value)
+                    .ok()
+                    .filter(|crf| (*crf as usize) < CRF.len())
+                    .ok_or_else(|| format!("expected a CRF from 0 to {}", CRF.len() - 1))?;
+                ParamChange::Crf(crf)
+            }
+            "detect_features" => ParamChange::DetectFeatures(parse_bool(value)?),
+            "show_features" => ParamChange::ShowFeatures(match value {
+                "off" => ShowFeatureMode::Off,
+                "instant" => ShowFeatureMode::Instant,
+                "hold" => ShowFeatureMode::Hold,
+                _ => return Err("expected off, instant, or hold for show_features".to_string()),
+            }),
+            "feature_rate_adjustment" => ParamChange::FeatureRateAdjustment(parse_bool(value)?),
+            "feature_cluster" => ParamChange::FeatureCluster(parse_bool(value)?),
+            _ => return Err(format!("unknown parameter {name}")),
+        })
+    }
+
+    fn apply(self, params: &mut AdaptiveParams) {
+        match self {
+            ParamChange::Crf(crf) => {
+                params.auto_quality = true;
+                params.crf_number = crf;
+                params.encoder_options.crf.update_quality(crf);
+            }
+            ParamChange::DetectFeatures(detect) => params.detect_features = detect,
+            ParamChange::ShowFeatures(mode) => params.show_features = mode,
+            ParamChange::FeatureRateAdjustment(adjust) => params.feature_rate_adjustment = adjust,
+            ParamChange::FeatureCluster(cluster) => params.feature_cluster = cluster,
+        }
+    }
+}
+
+impl FromStr for ParamSchedule {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for (idx, line) in s.lines().enumerate() {
+            let parse_error = |message: String| ScheduleError::Parse {
+                line: idx + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line
+                .split_whitespace()
+                .filter(|word| !matches!(*word, "at" | "set" | "and"));
+            let Some(time) = words.next() else {
+                continue;
+            };
+            let time = time.strip_prefix("t=").unwrap_or(time);
+            let time = time.strip_suffix('s').unwrap_or(time);
+            let t = f64::from_str(time)
+                .ok()
+                .filter(|t| *t >= 0.0)
+                .ok_or_else(|| parse_error(format!("invalid time {time}")))?;
+
+            let mut any_change = false;
+            for assignment in words {
+                let (name, value) = assignment
+                    .split_once('=')
+                    .ok_or_else(|| parse_error(format!("expected name=value, got {assignment}")))?;
+                let change =
+                    ParamChange::parse(&name.to_ascii_lowercase(), value).map_err(parse_error)?;
+                steps.push(ScheduleStep { t, change });
+                any_change = true;
+            }
+            if !any_change {
+                return Err(parse_error("no parameters to set".to_string()));
+            }
+        }
+
+        // The sort is stable, so simultaneous changes are applied in the order they're written
+        steps.sort_by(|a, b| a.t.total_cmp(&b.t));
+        Ok(ParamSchedule { steps, next: 0 })
+    }
+}
+
+impl ParamSchedule {
+    /// Read a schedule from a file
+    pub fn load(path: &Path) -> Result<Self, ScheduleError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Apply every step which is due by time `t` (in seconds of source video) and hasn't been
+    /// applied yet. Returns true if any parameter was changed.
+    pub fn apply_due(&mut self, t: f64, params: &mut AdaptiveParams) -> bool {
+        let start = self.next;
+        while let Some(step) = self.steps.get(self.next) {
+            if step.t > t {
+                break;
+            }
+            step.change.apply(params);
+            self.next += 1;
+        }
+        self.next > start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParamSchedule, ScheduleError};
+    use crate::transcoder::AdaptiveParams;
+    use adder_codec_rs::utils::viz::ShowFeatureMode;
+
+    #[test]
+    fn parse() {
+        let schedule: ParamSchedule = "# Comment\n\
+                                       at t=5s set crf=3 and detect_features=true\n\
+                                       \n\
+                                       2.5 show_features=hold # Earlier, so it's sorted first\n"
+            .parse()
+            .unwrap();
+        let times: Vec<f64> = schedule.steps.iter().map(|step| step.t).collect();
+        assert_eq!(times, [2.5, 5.0, 5.0]);
+
+        for (line, bad) in [
+            (1, "5 crf=10"),
+            (1, "t=-1 crf=3"),
+            (2, "1 crf=3\n2"),
+            (1, "1 crf"),
+            (1, "1 unknown=3"),
+        ] {
+            match bad.parse::<ParamSchedule>() {
+                Err(ScheduleError::Parse { line: found, .. }) => assert_eq!(found, line, "{bad}"),
+                other => panic!("{bad} parsed as {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn apply_due() {
+        let mut schedule: ParamSchedule = "at t=5s set crf=3\n\
+                                           10 detect_features=true crf=6\n\
+                                           10 crf=7"
+            .parse()
+            .unwrap();
+        let mut params = AdaptiveParams {
+            auto_quality: false,
+            ..Default::default()
+        };
+        let initial = params.clone();
+
+        // Nothing changes before the first step
+        assert!(!schedule.apply_due(4.99, &mut params));
+        assert_eq!(params, initial);
+
+        // A step takes effect at its time exactly
+        assert!(schedule.apply_due(5.0, &mut params));
+        assert!(params.auto_quality);
+        assert_eq!(params.crf_number, 3);
+        assert!(!params.detect_features);
+
+        // Between steps, the values hold rather than being interpolated
+        let held = params.clone();
+        assert!(!schedule.apply_due(7.5, &mut params));
+        assert!(!schedule.apply_due(9.99, &mut params));
+        assert_eq!(params, held);
+
+        // Simultaneous changes apply in the order they're written, and each applies once, even
+        // when an interval skips past several steps
+        assert!(schedule.apply_due(30.0, &mut params));
+        assert!(params.detect_features);
+        assert_eq!(params.crf_number, 7);
+        params.crf_number = 1;
+        assert!(!schedule.apply_due(40.0, &mut params));
+        assert_eq!(params.crf_number, 1);
+        assert_eq!(params.show_features, ShowFeatureMode::Off);
+    }
+}
@@ -3,14 +3,14 @@ use adder_codec_rs::adder_codec_core::codec::{EncoderOptions, EncoderType, Event
 use adder_codec_rs::adder_codec_core::{PixelMultiMode, PlaneSize, TimeMode};
 #[cfg(feature = "open-cv")]
 use adder_codec_rs::transcoder::source::davis::TranscoderMode;
-use adder_codec_rs::transcoder::source::video::FramedViewMode;
+use adder_codec_rs::transcoder::source::video::{EventStats, FramedViewMode};
 use adder_codec_rs::utils::cv::QualityMetrics;
 use adder_codec_rs::utils::viz::ShowFeatureMode;
 use eframe::epaint::{ColorImage, ImageDelta};
 use egui::epaint::TextureManager;
 use egui::{ImageSource, TextureOptions, Vec2b};
 use egui_plot::Corner::LeftTop;
-use egui_plot::{Legend, Plot};
+use egui_plot::{Bar, BarChart, Legend, Plot};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
@@ -19,7 +19,9 @@ use tokio::sync::mpsc::Sender;
 // use crate::transcoder::adder::{replace_adder_transcoder, AdderTranscoder};
 // use crate::utils::prep_bevy_image;
 use crate::transcoder::adder::AdderTranscoder;
-use crate::transcoder::{AdaptiveParams, CoreParams, EventRateMsg, InfoParams, InfoUiState};
+use crate::transcoder::{
+    AdaptiveParams, CoreParams, EventRateMsg, InfoParams, InfoUiState, EVENT_STATS_WINDOW,
+};
 use crate::{App, Images, TabState, Tabs};
 // #[cfg(feature = "open-cv")]
 // use adder_codec_rs::transcoder::source::davis::TranscoderMode;
@@ -28,7 +30,7 @@ use crate::{App, Images, TabState, Tabs};
 // use std::collections::VecDeque;
 // use std::error::Error;
 //
-use crate::utils::{slider_pm, PlotY};
+use crate::utils::{add_time_window_rows, slider_pm, PlotY};
 // use adder_codec_rs::adder_codec_core::codec::rate_controller::{Crf, CRF, DEFAULT_CRF_QUALITY};
 // use adder_codec_rs::adder_codec_core::codec::{EncoderOptions, EncoderType, EventDrop, EventOrder};
 // use adder_codec_rs::adder_codec_core::TimeMode;
@@ -102,8 +104,6 @@ use crate::utils::{slider_pm, PlotY};
 //     }
 // }
 //
-// unsafe impl Sync for InfoUiState {}
-//
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct TranscoderState {
     pub adaptive_params: AdaptiveParams,
@@ -122,6 +122,7 @@ impl TabState for TranscoderState {
     fn reset_video(&mut self) {
         self.core_params.input_path_buf_0 = None;
         self.core_params.output_path = None;
+        self.core_params.raw_copy_path = None;
     }
 }
 
@@ -134,8 +135,10 @@ pub enum TranscoderStateMsg {
 #[derive(Debug, Clone)]
 pub enum TranscoderInfoMsg {
     Plane((PlaneSize, bool)),
+    AdaptiveParams(AdaptiveParams),
     QualityMetrics(QualityMetrics),
     EventRateMsg(EventRateMsg),
+    EventStats(EventStats),
     Error(String),
 }
 
@@ -175,7 +178,7 @@ impl TranscoderUi {
             last_frame_time: std::time::Instant::now(),
             slider_button_down: false,
         };
-        transcoder_ui.spawn_transcoder(rx, msg_tx);
+        transcoder_ui.spawn_transcoder(rx, msg_tx, cc.egui_ctx.clone());
         transcoder_ui
     }
 
@@ -183,6 +186,7 @@ impl TranscoderUi {
         &mut self,
         rx: mpsc::Receiver<TranscoderStateMsg>,
         msg_tx: mpsc::Sender<TranscoderInfoMsg>,
+        egui_ctx: egui::Context,
     ) {
         let adder_image_handle = self.adder_image_handle.clone();
         let input_image_handle = self.input_image_handle.clone();
@@ -193,8 +197,13 @@ impl TranscoderUi {
         // Execute the runtime in its own thread.
         std::thread::spawn(move || {
             rt.block_on(async {
-                let mut transcoder =
-                    AdderTranscoder::new(rx, msg_tx, input_image_handle, adder_image_handle);
+                let mut transcoder = AdderTranscoder::new(
+                    rx,
+                    msg_tx,
+                    input_image_handle,
+                    adder_image_handle,
+                    egui_ctx,
+                );
                 transcoder.run().await;
             })
         });
@@ -226,6 +235,19 @@ impl TranscoderUi {
             match self.msg_rx.try_recv() {
                 Ok(message) => match message {
                     TranscoderInfoMsg::QualityMetrics(metrics) => self.handle_metrics(metrics),
+                    TranscoderInfoMsg::EventStats(stats) => {
+                        let history = &mut self.info_ui_state.event_stats_history;
+                        if history.len() == EVENT_STATS_WINDOW {
+                            history.pop_front();
+                        }
+                        history.push_back(stats);
+                    }
+                    TranscoderInfoMsg::AdaptiveParams(params) => {
+                        // The transcoder's schedule changed the parameters, so show them without
+                        // sending them back
+                        self.transcoder_state.adaptive_params = params.clone();
+                        self.transcoder_state_last_sent.adaptive_params = params;
+                    }
                     TranscoderInfoMsg::Error(error_string) => {
                         self.info_ui_state.error_string = Some(error_string);
                     }
@@ -257,6 +279,7 @@ impl TranscoderUi {
                         // Received when we have created a new video
                         if finish {
                             self.transcoder_state.core_params.output_path = None;
+                            self.transcoder_state.core_params.raw_copy_path = None;
                         }
                         self.transcoder_state
                             .adaptive_params
@@ -279,6 +302,49 @@ impl TranscoderUi {
         }
     }
 
+    /// Plot the distributions of the D and Δt values of the recently emitted events
+    fn event_stats_ui(&self, ui: &mut egui::Ui) {
+        let mut stats = EventStats::default();
+        for batch in &self.info_ui_state.event_stats_history {
+            stats.merge(batch);
+        }
+        let total = stats.total().max(1) as f64;
+
+        // Empty events carry no intensity, so they're excluded from the D histogram
+        let d_bars: Vec<Bar> = stats.d_counts[..stats.d_counts.len() - 1]
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(d, count)| Bar::new(d as f64, *count as f64 / total).width(0.9))
+            .collect();
+        let delta_t_bars: Vec<Bar> = stats
+            .delta_t_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bin, count)| Bar::new(bin as f64, *count as f64 / total).width(0.9))
+            .collect();
+
+        ui.columns(2, |columns| {
+            Plot::new("d_histogram")
+                .height(100.0)
+                .allow_drag(false)
+                .auto_bounds(Vec2b { x: true, y: true })
+                .legend(Legend::default().position(LeftTop))
+                .show(&mut columns[0], |plot_ui| {
+                    plot_ui.bar_chart(BarChart::new(d_bars).name("D"));
+                });
+            Plot::new("delta_t_histogram")
+                .height(100.0)
+                .allow_drag(false)
+                .auto_bounds(Vec2b { x: true, y: true })
+                .legend(Legend::default().position(LeftTop))
+                .show(&mut columns[1], |plot_ui| {
+                    plot_ui.bar_chart(BarChart::new(delta_t_bars).name("log2 Δt"));
+                });
+        });
+    }
+
     fn handle_metrics(&mut self, metrics: QualityMetrics) {
         self.info_ui_state.plot_points_psnr_y.update(metrics.psnr);
         self.info_ui_state.plot_points_mse_y.update(metrics.mse);
@@ -371,6 +437,7 @@ impl TranscoderUi {
                     .add_filter("framed video", &["mp4", "mkv", "avi", "mov"])
                     .add_filter("DVS/DAVIS video", &["aedat4"])
                     .add_filter("Prophesee video", &["dat"])
+                    .add_filter("Legacy DVS video", &["aedat"])
                     .pick_file()
                 {
                     eprintln!("Updating input path: {:?}", path);
@@ -385,7 +452,7 @@ impl TranscoderUi {
                     ui.style().visuals.text_color()
                 },
                 label_opt.as_ref().map_or(
-                    "OR drag and drop your source file here (.mp4, .aedat4, .dat)",
+                    "OR drag and drop your source file here (.mp4, .aedat4, .dat, .aedat)",
                     |p| p.to_str().unwrap(),
                 ),
             );
@@ -410,6 +477,16 @@ impl TranscoderUi {
                     self.transcoder_state.core_params.input_path_buf_1 = Some(path.clone());
                 }
             }
+            #[cfg(feature = "metavision")]
+            if ui.button("Open Prophesee camera").clicked() {
+                // The camera is started with the biases of the chosen file
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Metavision biases", &["bias"])
+                    .pick_file()
+                {
+                    self.transcoder_state.core_params.input_path_buf_0 = Some(path.clone());
+                }
+            }
             // if ui.button("Go!").clicked()
             //     && self.transcoder_state.core_params.input_path_buf_0.is_some()
             //     && self.transcoder_state.core_params.input_path_buf_1.is_some()
@@ -458,6 +535,60 @@ impl TranscoderUi {
             );
         });
 
+        ui.horizontal(|ui| {
+            if ui.button("Save raw copy").clicked() {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("adder video", &["adder"])
+                    .save_file()
+                {
+                    self.transcoder_state.core_params.raw_copy_path =
+                        Some(path.with_extension("adder"));
+                }
+            }
+            if self.transcoder_state.core_params.raw_copy_path.is_some()
+                && ui.button("Clear").clicked()
+            {
+                self.transcoder_state.core_params.raw_copy_path = None;
+            }
+
+            let label_opt = &self.transcoder_state.core_params.raw_copy_path;
+            ui.colored_label(
+                if label_opt.is_some() {
+                    egui::Color32::GREEN
+                } else {
+                    ui.style().visuals.text_color()
+                },
+                label_opt
+                    .as_ref()
+                    .map_or("No raw copy", |p| p.to_str().unwrap()),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Load schedule").clicked() {
+                if let Some(path) = rfd::FileDialog::new().pick_file() {
+                    self.transcoder_state.core_params.schedule_path = Some(path);
+                }
+            }
+            if self.transcoder_state.core_params.schedule_path.is_some()
+                && ui.button("Clear schedule").clicked()
+            {
+                self.transcoder_state.core_params.schedule_path = None;
+            }
+
+            let label_opt = &self.transcoder_state.core_params.schedule_path;
+            ui.colored_label(
+                if label_opt.is_some() {
+                    egui::Color32::GREEN
+                } else {
+                    ui.style().visuals.text_color()
+                },
+                label_opt
+                    .as_ref()
+                    .map_or("No parameter schedule", |p| p.to_str().unwrap()),
+            );
+        });
+
         Plot::new("quality_plot")
             .height(100.0)
             .allow_drag(true)
@@ -501,6 +632,8 @@ impl TranscoderUi {
                 }
             });
 
+        self.event_stats_ui(ui);
+
         ui.label(format!(
             "{:.2} transcoded FPS\t\
                 {:.2} events per source sec\t\
@@ -718,6 +851,12 @@ impl TranscoderUi {
             0.1,
         );
         ui.end_row();
+        slider_button_down |= add_time_window_rows(
+            enabled,
+            ui,
+            &mut core_params.start_time,
+            &mut core_params.duration,
+        );
         ui.label("Channels:");
         ui.add_enabled(
             enabled,
@@ -806,6 +945,7 @@ impl TranscoderUi {
                         EncoderType::Compressed,
                         "Compressed",
                     );
+                    ui.radio_value(&mut core_params.encoder_type, EncoderType::Lz, "Lz (zstd)");
                 });
             });
         });
@@ -928,10 +1068,41 @@ impl TranscoderUi {
                         "Manual",
                     );
                 }
+                if let EventDrop::PerFrameBudget { max_events } =
+                    adaptive_params.encoder_options.event_drop
+                {
+                    ui.radio_value(
+                        &mut adaptive_params.encoder_options.event_drop,
+                        EventDrop::PerFrameBudget { max_events },
+                        "Per frame",
+                    );
+                } else {
+                    ui.radio_value(
+                        &mut adaptive_params.encoder_options.event_drop,
+                        EventDrop::PerFrameBudget { max_events: 10_000 },
+                        "Per frame",
+                    );
+                }
             });
         });
         ui.end_row();
 
+        if let EventDrop::PerFrameBudget { max_events } =
+            &mut adaptive_params.encoder_options.event_drop
+        {
+            ui.label("Events per frame:");
+            slider_button_down |= slider_pm(
+                true,
+                true,
+                ui,
+                max_events,
+                100..=1_000_000,
+                vec![1_000, 10_000, 100_000],
+                100,
+            );
+            ui.end_row();
+        }
+
         if let EventDrop::Manual {
             target_event_rate,
             alpha,
@@ -969,6 +1140,37 @@ impl TranscoderUi {
             ui.end_row();
         }
 
+        ui.label("Temporal denoise:");
+        ui.add_enabled(
+            true,
+            egui::Checkbox::new(&mut adaptive_params.denoise, "Denoise input?"),
+        );
+        ui.end_row();
+
+        ui.label("Denoise strength:");
+        slider_button_down |= slider_pm(
+            adaptive_params.denoise,
+            false,
+            ui,
+            &mut adaptive_params.temporal_filter.strength,
+            0.0..=0.95,
+            vec![0.3, 0.6, 0.9],
+            0.05,
+        );
+        ui.end_row();
+
+        ui.label("Denoise edge:");
+        slider_button_down |= slider_pm(
+            adaptive_params.denoise,
+            false,
+            ui,
+            &mut adaptive_params.temporal_filter.edge,
+            1.0..=64.0,
+            vec![4.0, 8.0, 16.0, 32.0],
+            1.0,
+        );
+        ui.end_row();
+
         ui.label("Processing:");
         ui.vertical(|ui| {
             ui.add_enabled(
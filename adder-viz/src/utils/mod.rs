@@ -7,6 +7,7 @@ use egui_plot::{Line, PlotPoints};
 use std::collections::VecDeque;
 use std::error::Error;
 use std::ops::RangeInclusive;
+use std::time::Duration;
 use video_rs_adder_dep::Frame;
 
 pub(crate) mod slider;
@@ -81,6 +82,57 @@ pub fn add_checkbox_row(
     ret
 }
 
+/// Add rows for choosing the window of the input to use: its start time and, optionally, its
+/// duration, in seconds. Returns true if a slider is being dragged.
+pub(crate) fn add_time_window_rows(
+    enabled: bool,
+    ui: &mut Ui,
+    start_time: &mut f64,
+    duration: &mut Option<f64>,
+) -> bool {
+    let mut slider_button_down = add_slider_row(
+        enabled,
+        false,
+        "Start time (s):",
+        ui,
+        start_time,
+        0.0..=600.0,
+        vec![0.0, 10.0, 60.0, 300.0],
+        1.0,
+    );
+
+    let mut limit_duration = duration.is_some();
+    add_checkbox_row(
+        enabled,
+        "Duration:",
+        "Limit duration?",
+        ui,
+        &mut limit_duration,
+    );
+    let mut duration_value = duration.unwrap_or(10.0);
+    slider_button_down |= add_slider_row(
+        enabled && limit_duration,
+        true,
+        "Duration (s):",
+        ui,
+        &mut duration_value,
+        0.1..=3600.0,
+        vec![1.0, 10.0, 60.0, 600.0],
+        1.0,
+    );
+    *duration = limit_duration.then_some(duration_value);
+    slider_button_down
+}
+
+/// Convert a window of the input chosen with [`add_time_window_rows`] to the start time and
+/// duration of a source or decoder
+pub(crate) fn time_window(start_time: f64, duration: Option<f64>) -> (Duration, Option<Duration>) {
+    (
+        Duration::from_secs_f64(start_time.max(0.0)),
+        duration.map(|duration| Duration::from_secs_f64(duration.max(0.0))),
+    )
+}
+
 pub fn add_slider_row<Num: emath::Numeric + Pm>(
     enabled: bool,
     logarithmic: bool,